@@ -37,7 +37,7 @@ fn impact_or(impact: CubeRayIntersection, or: f32) -> f32{
     return or;
 }
 
-//crate::spatial::math::hash_region
+//crate::spatial::math::Octant::from_offset
 fn hash_region(offset: vec3f, size: f32) -> u32 {
     let midpoint = vec3f(size / 2., size / 2., size / 2.);
     return u32(offset.x >= midpoint.x)
@@ -45,7 +45,7 @@ fn hash_region(offset: vec3f, size: f32) -> u32 {
         + u32(offset.y >= midpoint.y) * 4u;
 }
 
-//crate::spatial::math::offset_region
+//crate::spatial::math::Octant::offset
 fn offset_region(octant: u32) -> vec3f {
     switch(octant){
         case 0u { return vec3f(0., 0., 0.); }
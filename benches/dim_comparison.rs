@@ -0,0 +1,133 @@
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, BenchmarkId, Criterion,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use shocovox_rs::octree::{Octree, V3c};
+
+#[cfg(feature = "raytracing")]
+use criterion::Throughput;
+
+#[cfg(feature = "raytracing")]
+use shocovox_rs::octree::raytracing::Ray;
+
+/// The size of the scene built with every DIM, a multiple of the largest one
+const SCENE_SIZE: u32 = 32;
+
+/// The voxels of the scene compared between the DIM values:
+/// a solid floor, some hills on it and noise scattered in the air
+fn scene() -> Vec<(V3c<u32>, u32)> {
+    let mut rng = StdRng::seed_from_u64(shocovox_rs::testing::seed("dim_comparison"));
+    let mut voxels = Vec::new();
+    for x in 0..SCENE_SIZE {
+        for z in 0..SCENE_SIZE {
+            let height = 2 + ((x as f32 / 5.).sin() * (z as f32 / 7.).cos() * 4.).max(0.) as u32;
+            for y in 0..height {
+                voxels.push((V3c::new(x, y, z), 1 + y));
+            }
+        }
+    }
+    for _ in 0..(SCENE_SIZE * SCENE_SIZE) {
+        voxels.push((
+            V3c::new(
+                rng.gen_range(0..SCENE_SIZE),
+                rng.gen_range(SCENE_SIZE / 2..SCENE_SIZE),
+                rng.gen_range(0..SCENE_SIZE),
+            ),
+            rng.gen_range(1..500),
+        ));
+    }
+    voxels
+}
+
+fn build<const DIM: usize>(voxels: &[(V3c<u32>, u32)]) -> Octree<u32, DIM> {
+    let mut tree = Octree::<u32, DIM>::new(SCENE_SIZE).ok().unwrap();
+    for (position, data) in voxels {
+        tree.insert(position, *data).ok().unwrap();
+    }
+    tree
+}
+
+/// The rays of a viewport looking at the scene from above one of its corners
+#[cfg(feature = "raytracing")]
+fn viewport_rays() -> Vec<Ray> {
+    let size = SCENE_SIZE as f32;
+    let origin = V3c::new(size * 1.5, size, size * 1.5);
+    let target = V3c::new(size / 2., 0., size / 2.);
+    let direction = (target - origin).normalized();
+    let right = direction.cross(V3c::new(0., 1., 0.)).normalized();
+    let up = right.cross(direction);
+    let resolution = 64;
+    let mut rays = Vec::new();
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let offset_x = (x as f32 / resolution as f32 - 0.5) * 2.;
+            let offset_y = (y as f32 / resolution as f32 - 0.5) * 2.;
+            rays.push(Ray {
+                origin,
+                direction: (direction * 3. + right * offset_x + up * offset_y).normalized(),
+            });
+        }
+    }
+    rays
+}
+
+fn bench_build<const DIM: usize>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    voxels: &[(V3c<u32>, u32)],
+) {
+    let tree = build::<DIM>(voxels);
+    println!(
+        "DIM {}: {} bytes, {} bytes unused in pools",
+        DIM,
+        tree.memory_usage(),
+        tree.memory_report().total.free_slots
+    );
+    group.bench_with_input(BenchmarkId::from_parameter(DIM), voxels, |b, voxels| {
+        b.iter(|| build::<DIM>(voxels));
+    });
+}
+
+#[cfg(feature = "raytracing")]
+fn bench_rays<const DIM: usize>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    voxels: &[(V3c<u32>, u32)],
+    rays: &[Ray],
+) {
+    let tree = build::<DIM>(voxels);
+    group.bench_with_input(BenchmarkId::from_parameter(DIM), rays, |b, rays| {
+        b.iter(|| {
+            for ray in rays {
+                tree.get_by_ray(ray);
+            }
+        });
+    });
+}
+
+/// Builds the same scene with each DIM, reporting the time to build it, the memory it uses,
+/// and with the raytracing feature the number of rays cast through it per second
+fn dim_comparison(c: &mut Criterion) {
+    let voxels = scene();
+    let mut group = c.benchmark_group("dim comparison build");
+    bench_build::<1>(&mut group, &voxels);
+    bench_build::<2>(&mut group, &voxels);
+    bench_build::<4>(&mut group, &voxels);
+    bench_build::<8>(&mut group, &voxels);
+    bench_build::<16>(&mut group, &voxels);
+    group.finish();
+
+    #[cfg(feature = "raytracing")]
+    {
+        let rays = viewport_rays();
+        let mut group = c.benchmark_group("dim comparison get_by_ray");
+        group.throughput(Throughput::Elements(rays.len() as u64));
+        bench_rays::<1>(&mut group, &voxels, &rays);
+        bench_rays::<2>(&mut group, &voxels, &rays);
+        bench_rays::<4>(&mut group, &voxels, &rays);
+        bench_rays::<8>(&mut group, &voxels, &rays);
+        bench_rays::<16>(&mut group, &voxels, &rays);
+        group.finish();
+    }
+}
+
+criterion_group!(benches, dim_comparison);
+criterion_main!(benches);
@@ -1,12 +1,12 @@
 use criterion::{criterion_group, criterion_main};
-use rand::Rng;
-use shocovox_rs::{octree::Octree, octree::V3c};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use shocovox_rs::{octree::Cube, octree::Octant, octree::Octree, octree::V3c};
 
 #[cfg(feature = "raytracing")]
-use shocovox_rs::octree::raytracing::Ray;
+use shocovox_rs::octree::raytracing::{PrefetchPolicy, Ray, RayOptions};
 
 fn criterion_benchmark(c: &mut criterion::Criterion) {
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(shocovox_rs::testing::seed("performance"));
 
     #[cfg(feature = "raytracing")]
     {
@@ -74,6 +74,170 @@ fn criterion_benchmark(c: &mut criterion::Criterion) {
         });
     }
 
+    // A large sparse tree, with far more nodes than what fits into the cache
+    #[cfg(feature = "raytracing")]
+    {
+        let large_tree_size = 512;
+        let mut large_tree = Octree::<u32, 4>::new(large_tree_size).ok().unwrap();
+        for _ in 0..100_000 {
+            large_tree
+                .insert(
+                    &V3c::new(
+                        rng.gen_range(0..large_tree_size),
+                        rng.gen_range(0..large_tree_size),
+                        rng.gen_range(0..large_tree_size),
+                    ),
+                    rng.gen_range(1..500),
+                )
+                .ok()
+                .unwrap();
+        }
+        let rays = (0..1024)
+            .map(|_| Ray {
+                origin: V3c::new(
+                    rng.gen_range(0.0..512.0),
+                    rng.gen_range(0.0..512.0),
+                    rng.gen_range(0.0..512.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+            })
+            .collect::<Vec<_>>();
+        for (name, prefetch) in [
+            ("cpu get_by_ray large tree", PrefetchPolicy::LookAhead),
+            (
+                "cpu get_by_ray large tree without prefetch",
+                PrefetchPolicy::Disabled,
+            ),
+        ] {
+            let options = RayOptions {
+                prefetch,
+                ..Default::default()
+            };
+            c.bench_function(name, |b| {
+                b.iter(|| {
+                    for ray in rays.iter() {
+                        criterion::black_box(large_tree.get_by_ray_with_options(ray, &options));
+                    }
+                })
+            });
+        }
+    }
+
+    // A terrain like tree: solid below a bumpy surface
+    let occupancy_tree_size = 64;
+    let mut occupancy_tree = Octree::<u32, 4>::new(occupancy_tree_size).ok().unwrap();
+    for x in 0..occupancy_tree_size {
+        for z in 0..occupancy_tree_size {
+            for y in 0..rng.gen_range(8..24) {
+                occupancy_tree
+                    .insert(&V3c::new(x, y, z), rng.gen_range(1..500))
+                    .ok()
+                    .unwrap();
+            }
+        }
+    }
+    let occupancy = occupancy_tree.to_occupancy();
+
+    #[cfg(feature = "raytracing")]
+    {
+        let rays = (0..1024)
+            .map(|_| Ray {
+                origin: V3c::new(
+                    rng.gen_range(0.0..64.0),
+                    rng.gen_range(24.0..64.0),
+                    rng.gen_range(0.0..64.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..-0.1),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+            })
+            .collect::<Vec<_>>();
+
+        c.bench_function("occlusion by get_by_ray", |b| {
+            b.iter(|| {
+                for ray in rays.iter() {
+                    criterion::black_box(occupancy_tree.get_by_ray(ray).is_some_and(
+                        |(_, impact_point, _)| (impact_point - ray.origin).length() <= 32.,
+                    ));
+                }
+            })
+        });
+
+        c.bench_function("occlusion by bit octree", |b| {
+            b.iter(|| {
+                for ray in rays.iter() {
+                    criterion::black_box(occupancy.is_occluded(ray, 32.));
+                }
+            })
+        });
+    }
+
+    let region = Cube::new(V3c::new(16, 0, 16), 32);
+    c.bench_function("count occupied by get", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for x in 16..48 {
+                for y in 0..32 {
+                    for z in 16..48 {
+                        if occupancy_tree.get(&V3c::new(x, y, z)).is_some() {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            criterion::black_box(count)
+        })
+    });
+
+    c.bench_function("count occupied by bit octree", |b| {
+        b.iter(|| criterion::black_box(occupancy.count_occupied(criterion::black_box(&region))))
+    });
+
+    c.bench_function("octant from float offset", |b| {
+        let offsets = (0..1024)
+            .map(|_| {
+                V3c::new(
+                    rng.gen_range(0..1024) as f32,
+                    rng.gen_range(0..1024) as f32,
+                    rng.gen_range(0..1024) as f32,
+                )
+            })
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            for offset in offsets.iter() {
+                criterion::black_box(Octant::from_offset(criterion::black_box(offset), 1024.));
+            }
+        });
+    });
+
+    c.bench_function("octant from voxel offset", |b| {
+        let offsets = (0..1024)
+            .map(|_| {
+                V3c::new(
+                    rng.gen_range(0..1024),
+                    rng.gen_range(0..1024),
+                    rng.gen_range(0..1024),
+                )
+            })
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            for offset in offsets.iter() {
+                criterion::black_box(Octant::from_voxel_offset(
+                    criterion::black_box(offset),
+                    1024,
+                ));
+            }
+        });
+    });
+
     let tree_size = 64;
     let mut tree = shocovox_rs::octree::Octree::<u32>::new(tree_size)
         .ok()
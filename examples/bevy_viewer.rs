@@ -0,0 +1,122 @@
+//! Renders a MagicaVoxel model with the WGSL traversal shader
+//! usage: cargo run --example bevy_viewer --features bevy_wgpu,vox -- path/to/model.vox
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+use bevy::prelude::*;
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+use shocovox_rs::octree::{
+    raytracing::{OctreeViewMaterial, Viewport},
+    Octree,
+};
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+const MATRIX_DIMENSION: usize = 8;
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+#[derive(Resource)]
+struct ModelSize(f32);
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+#[derive(Component)]
+struct Orbit {
+    yaw: f32,
+    distance: f32,
+}
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        println!("usage: bevy_viewer <path to .vox file>");
+        return;
+    };
+    let tree = Octree::<u32, MATRIX_DIMENSION>::load_vox(&path, |_, [r, g, b, a]| {
+        Some(r as u32 | (g as u32) << 8 | (b as u32) << 16 | (a as u32) << 24)
+    })
+    .unwrap_or_else(|error| panic!("Unable to read {path}: {error}"));
+
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            MaterialPlugin::<OctreeViewMaterial>::default(),
+        ))
+        .insert_resource(ModelSize(tree.size() as f32))
+        .insert_non_send_resource(tree)
+        .add_systems(Startup, setup)
+        .add_systems(Update, orbit_camera)
+        .run();
+}
+
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<OctreeViewMaterial>>,
+    tree: NonSend<Octree<u32, MATRIX_DIMENSION>>,
+    model_size: Res<ModelSize>,
+) {
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0.0, 0.0, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..Default::default()
+    });
+    commands.spawn(Orbit {
+        yaw: 0.,
+        distance: model_size.0 * 2.,
+    });
+
+    // The buffers of the tree are uploaded once, only the viewport changes afterwards
+    let material_handle = materials.add(tree.create_bevy_material_view(&Viewport {
+        origin: Vec3::new(0., model_size.0 / 2., model_size.0 * 2.),
+        direction: Vec3::new(0., 0., -1.),
+        size: Vec2::new(10., 10.),
+        fov: 3.,
+    }));
+    commands.spawn(MaterialMeshBundle {
+        mesh: meshes.add(Mesh::from(Rectangle {
+            half_size: Vec2::new(5., 5.) / 2.,
+        })),
+        material: material_handle,
+        ..Default::default()
+    });
+}
+
+/// Rotates the viewport around the model with the left and right arrows, zooms with the up and down arrows
+#[cfg(all(feature = "bevy_wgpu", feature = "vox"))]
+fn orbit_camera(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    model_size: Res<ModelSize>,
+    mut orbit_query: Query<&mut Orbit>,
+    mut mats: ResMut<Assets<OctreeViewMaterial>>,
+) {
+    let mut orbit = orbit_query.single_mut();
+    let delta = time.delta_seconds();
+    if keys.pressed(KeyCode::ArrowLeft) {
+        orbit.yaw -= delta;
+    }
+    if keys.pressed(KeyCode::ArrowRight) {
+        orbit.yaw += delta;
+    }
+    if keys.pressed(KeyCode::ArrowUp) {
+        orbit.distance = (orbit.distance - model_size.0 * delta).max(1.);
+    }
+    if keys.pressed(KeyCode::ArrowDown) {
+        orbit.distance += model_size.0 * delta;
+    }
+
+    let center = Vec3::splat(model_size.0 / 2.);
+    for (_mat_handle, mat) in mats.as_mut().iter_mut() {
+        mat.viewport.origin = center
+            + Vec3::new(
+                orbit.yaw.sin() * orbit.distance,
+                model_size.0 / 4.,
+                orbit.yaw.cos() * orbit.distance,
+            );
+        mat.viewport.direction = (center - mat.viewport.origin).normalize();
+    }
+}
+
+#[cfg(not(all(feature = "bevy_wgpu", feature = "vox")))]
+fn main() {
+    //nothing to do when the feature is not enabled
+    println!("You probably forgot to enable the features \"bevy_wgpu\" and \"vox\"")
+}
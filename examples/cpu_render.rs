@@ -1,4 +1,4 @@
-#[cfg(feature = "raytracing")]
+#[cfg(feature = "viewer")]
 #[derive(Default, Clone, Debug, PartialEq)]
 struct RGB {
     r: u8,
@@ -7,14 +7,14 @@ struct RGB {
     a: u8,
 }
 
-#[cfg(feature = "raytracing")]
+#[cfg(feature = "viewer")]
 impl RGB {
     pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         RGB { r, g, b, a }
     }
 }
 
-#[cfg(feature = "raytracing")]
+#[cfg(feature = "viewer")]
 impl shocovox_rs::octree::VoxelData for RGB {
     fn new(r: u8, g: u8, b: u8, a: u8, _user_data: u32) -> Self {
         Self { r, g, b, a }
@@ -33,38 +33,73 @@ impl shocovox_rs::octree::VoxelData for RGB {
     }
 }
 
-#[cfg(feature = "raytracing")]
-use rand::Rng;
+#[cfg(feature = "viewer")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-#[cfg(feature = "raytracing")]
-use shocovox_rs::octree::{raytracing::Ray, V3c};
+#[cfg(feature = "viewer")]
+use shocovox_rs::octree::{
+    raytracing::{Camera, FrameCoherenceCache},
+    Octree, V3c,
+};
 
-#[cfg(feature = "raytracing")]
+#[cfg(feature = "viewer")]
+use show_image::event::{ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+
+#[cfg(feature = "viewer")]
+const VIEWPORT_SIZE: u32 = 128;
+
+#[cfg(feature = "viewer")]
+const WINDOW_SIZE: u32 = 512;
+
+/// The render resolution is divided by this while the camera moves, then refined step by step while idle
+#[cfg(feature = "viewer")]
+const MAX_RENDER_DIVISOR: u32 = 4;
+
+/// A free flying camera: moved with WASD (Q/E for down/up), rotated by dragging with the right mouse button
+#[cfg(feature = "viewer")]
+struct FlyCamera {
+    position: V3c<f32>,
+    yaw: f32,
+    pitch: f32,
+}
+
+#[cfg(feature = "viewer")]
+impl FlyCamera {
+    fn direction(&self) -> V3c<f32> {
+        V3c::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    fn camera(&self) -> Camera {
+        Camera {
+            origin: self.position,
+            direction: self.direction(),
+            size: (4., 4.),
+            fov: 3.,
+        }
+    }
+}
+
+#[cfg(feature = "viewer")]
 #[show_image::main]
 fn main() {
     // fill octree with data
     let tree_size = 16;
     const MATRIX_DIMENSION: usize = 4;
-    const ARRAY_DIMENSION: u32 = 16;
-    let viewport_size_width = 128;
-    let viewport_size_height = 128;
-    let mut tree = shocovox_rs::octree::Octree::<RGB, MATRIX_DIMENSION>::new(tree_size)
+    let mut tree = Octree::<RGB, MATRIX_DIMENSION>::new(tree_size)
         .ok()
         .unwrap();
 
-    tree.insert(&V3c::new(1, 3, 3), RGB::new(100, 80, 151, 255))
-        .ok()
-        .unwrap();
+    let mut rng = StdRng::seed_from_u64(shocovox_rs::testing::seed("cpu_render"));
     for x in 0..tree_size {
         for y in 0..tree_size {
             for z in 0..tree_size {
-                if ((x < (ARRAY_DIMENSION / 4)
-                    || y < (ARRAY_DIMENSION / 4)
-                    || z < (ARRAY_DIMENSION / 4))
+                if ((x < (tree_size / 4) || y < (tree_size / 4) || z < (tree_size / 4))
                     && (0 == x % 2 && 0 == y % 4 && 0 == z % 2))
-                    || ((ARRAY_DIMENSION / 2) <= x
-                        && (ARRAY_DIMENSION / 2) <= y
-                        && (ARRAY_DIMENSION / 2) <= z)
+                    || ((tree_size / 2) <= x && (tree_size / 2) <= y && (tree_size / 2) <= z)
                 {
                     tree.insert(
                         &V3c::new(x, y, z),
@@ -82,111 +117,164 @@ fn main() {
         }
     }
 
-    use show_image::create_window;
-    let window = create_window("image", Default::default()).ok().unwrap();
+    let window = show_image::create_window(
+        "shocovox - WASD/QE: move, right drag: look, click: place, shift+click: remove, H: heatmap",
+        show_image::WindowOptions::new()
+            .set_size([WINDOW_SIZE, WINDOW_SIZE])
+            .set_resizable(false),
+    )
+    .ok()
+    .unwrap();
+    let events = window.event_channel().ok().unwrap();
 
-    let radius = 2. * tree_size as f32;
-    let mut rng = rand::thread_rng();
-    let mut angle = 40.;
-    let mut velos = V3c::new(-0.05, 0., 0.);
+    let mut fly_camera = FlyCamera {
+        position: V3c::new(-8., 24., -8.),
+        yaw: std::f32::consts::FRAC_PI_4,
+        pitch: -0.6,
+    };
+    let mut held_keys = std::collections::HashSet::new();
+    let mut frame_cache = FrameCoherenceCache::new(VIEWPORT_SIZE, VIEWPORT_SIZE);
+    let mut render_divisor = MAX_RENDER_DIVISOR;
+    let mut refined = false;
+    let mut show_heatmap = false;
+    let mut frames = 0;
+    let mut fps_timer = std::time::Instant::now();
+    let mut last_frame = std::time::Instant::now();
 
-    // Close app on window exit
-    window
-        .add_event_handler(|_, event, _| {
+    loop {
+        let delta = last_frame.elapsed().as_secs_f32();
+        last_frame = std::time::Instant::now();
+        let mut camera_moved = false;
+        let mut tree_changed = false;
+        let mut view_changed = false;
+
+        for event in events.try_iter() {
             match event {
-                show_image::event::WindowEvent::Destroyed(_) => {
-                    std::process::exit(0);
+                WindowEvent::Destroyed(_) => std::process::exit(0),
+                WindowEvent::KeyboardInput(event) => {
+                    if let Some(key) = event.input.key_code {
+                        match event.input.state {
+                            ElementState::Pressed => {
+                                // H switches between the voxels and the cost of tracing each pixel
+                                if held_keys.insert(key) && VirtualKeyCode::H == key {
+                                    show_heatmap = !show_heatmap;
+                                    view_changed = true;
+                                }
+                            }
+                            ElementState::Released => {
+                                held_keys.remove(&key);
+                            }
+                        }
+                    }
+                }
+                WindowEvent::MouseMove(event) => {
+                    if event.buttons.is_pressed(MouseButton::Right) {
+                        let movement = event.position - event.prev_position;
+                        fly_camera.yaw -= movement.x * 0.005;
+                        fly_camera.pitch = (fly_camera.pitch - movement.y * 0.005).clamp(-1.5, 1.5);
+                        camera_moved = true;
+                    }
+                }
+                WindowEvent::MouseButton(event)
+                    if matches!(event.button, MouseButton::Left)
+                        && matches!(event.state, ElementState::Pressed) =>
+                {
+                    // Pick the voxel under the cursor, with the viewport stretched over the whole window
+                    let pixel = (
+                        (event.position.x.max(0.) as u32 * VIEWPORT_SIZE / WINDOW_SIZE)
+                            .min(VIEWPORT_SIZE - 1),
+                        (event.position.y.max(0.) as u32 * VIEWPORT_SIZE / WINDOW_SIZE)
+                            .min(VIEWPORT_SIZE - 1),
+                    );
+                    let ray =
+                        fly_camera
+                            .camera()
+                            .ray_for(pixel.0, pixel.1, VIEWPORT_SIZE, VIEWPORT_SIZE);
+                    if let Some(hit) = tree.get_by_ray_owned(&ray) {
+                        if event.modifiers.shift() {
+                            tree.clear(&hit.voxel).ok().unwrap();
+                        } else {
+                            // Place the new voxel next to the face of the hit voxel
+                            let target = V3c::<f32>::from(hit.voxel) + hit.normal;
+                            if (0. ..tree_size as f32).contains(&target.x)
+                                && (0. ..tree_size as f32).contains(&target.y)
+                                && (0. ..tree_size as f32).contains(&target.z)
+                            {
+                                let color = RGB::new(
+                                    rng.gen_range(64..=255),
+                                    rng.gen_range(64..=255),
+                                    rng.gen_range(64..=255),
+                                    255,
+                                );
+                                tree.insert(&V3c::<u32>::from(target), color).ok().unwrap();
+                            }
+                        }
+                        tree_changed = true;
+                    }
                 }
                 _ => {}
+            }
+        }
+
+        let speed = 8. * delta;
+        let forward = fly_camera.direction();
+        let right = forward.cross(V3c::new(0., 1., 0.)).normalized();
+        for key in held_keys.iter() {
+            let movement = match key {
+                VirtualKeyCode::W => forward * speed,
+                VirtualKeyCode::S => forward * -speed,
+                VirtualKeyCode::D => right * speed,
+                VirtualKeyCode::A => right * -speed,
+                VirtualKeyCode::E => V3c::new(0., speed, 0.),
+                VirtualKeyCode::Q => V3c::new(0., -speed, 0.),
+                _ => continue,
             };
-        })
-        .ok()
-        .unwrap();
+            fly_camera.position = fly_camera.position + movement;
+            camera_moved = true;
+        }
 
-    loop {
-        //generate a random number to add to velos
-        velos = velos
-            + V3c::new(
-                (-5 + rng.gen_range(0..10)) as f32 / 2000.,
-                (-5 + rng.gen_range(0..10)) as f32 / 2000.,
-                (-5 + rng.gen_range(0..10)) as f32 / 2000.,
-            );
-        angle = angle + velos.x / 10.;
-
-        // Set the viewport
-        let origin = V3c::new(angle.sin() * radius, radius, angle.cos() * radius);
-        let viewport_ray = Ray {
-            direction: (V3c::unit(0.) - origin).normalized(),
-            origin,
+        // Render in low resolution while anything changes, then refine the image while idle
+        if tree_changed {
+            frame_cache.invalidate();
+        }
+        if camera_moved || tree_changed || view_changed {
+            render_divisor = MAX_RENDER_DIVISOR;
+            refined = false;
+        } else if refined {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            continue;
+        }
+        let resolution = VIEWPORT_SIZE / render_divisor;
+        let binding = if show_heatmap {
+            // Black where the rays do no work, through blue, red and yellow to white for the most expensive pixel
+            tree.render_traversal_heatmap(&fly_camera.camera(), resolution, resolution)
+                .to_rgba(None)
+        } else {
+            tree.render_viewport(
+                &fly_camera.camera(),
+                resolution,
+                resolution,
+                Some(&mut frame_cache),
+            )
         };
-        let viewport_up_direction = V3c::new(0., 1., 0.);
-        let viewport_right_direction = viewport_up_direction
-            .cross(viewport_ray.direction)
-            .normalized();
-        let viewport_width = 4.;
-        let viewport_height = 4.;
-        let viewport_fov = 3.;
-        let pixel_width = viewport_width as f32 / viewport_size_width as f32;
-        let pixel_height = viewport_height as f32 / viewport_size_height as f32;
-        let viewport_bottom_left = viewport_ray.origin + (viewport_ray.direction * viewport_fov)
-            - (viewport_up_direction * (viewport_height / 2.))
-            - (viewport_right_direction * (viewport_width / 2.));
-
-        // define light
-        let diffuse_light_normal = V3c::new(0., -1., 1.).normalized();
-
-        use image::ImageBuffer;
-        use image::Rgb;
-        let mut img = ImageBuffer::new(viewport_size_width, viewport_size_height);
-
-        // cast each ray for a hit
-        for y in 0..viewport_size_width {
-            for x in 0..viewport_size_height {
-                let actual_y_in_image = viewport_size_height - y - 1;
-                //from the origin of the camera to the current point of the viewport
-                let glass_point = viewport_bottom_left
-                    + viewport_right_direction * x as f32 * pixel_width
-                    + viewport_up_direction * y as f32 * pixel_height;
-                let ray = Ray {
-                    origin: viewport_ray.origin,
-                    direction: (glass_point - viewport_ray.origin).normalized(),
-                };
-
-                use std::io::Write;
-                std::io::stdout().flush().ok().unwrap();
-
-                if let Some(hit) = tree.get_by_ray(&ray) {
-                    let (data, _, normal) = hit;
-                    //Because both vector should be normalized, the dot product should be 1*1*cos(angle)
-                    //That means it is in range -1, +1, which should be accounted for
-                    let diffuse_light_strength =
-                        1. - (normal.dot(&diffuse_light_normal) / 2. + 0.5);
-                    img.put_pixel(
-                        x,
-                        actual_y_in_image,
-                        Rgb([
-                            (data.r as f32 * diffuse_light_strength) as u8,
-                            (data.g as f32 * diffuse_light_strength) as u8,
-                            (data.b as f32 * diffuse_light_strength) as u8,
-                        ]),
-                    );
-                } else {
-                    img.put_pixel(x, actual_y_in_image, Rgb([128, 128, 128]));
-                }
-            }
+        if 1 < render_divisor {
+            render_divisor /= 2;
+        } else {
+            refined = true;
         }
 
         use show_image::{ImageInfo, ImageView};
-        let binding = img.into_raw();
-        let image = ImageView::new(
-            ImageInfo::rgb8(viewport_size_width, viewport_size_height),
-            &binding,
-        );
-
-        // Create a window with default options and display the image.
+        let image = ImageView::new(ImageInfo::rgba8(resolution, resolution), &binding);
         window.set_image("image-001", image).ok().unwrap();
+
+        frames += 1;
+        if 1. <= fps_timer.elapsed().as_secs_f32() {
+            println!("FPS: {frames}");
+            frames = 0;
+            fps_timer = std::time::Instant::now();
+        }
     }
 }
 
-#[cfg(not(feature = "raytracing"))]
+#[cfg(not(feature = "viewer"))]
 fn main() {} //nothing to do when the feature is not enabled
@@ -0,0 +1,302 @@
+//! Projectiles carve spherical craters into a structure; parts losing their connection to the ground
+//! are extracted into separate trees as debris, which then fall down.
+//! usage: cargo run --example destruction --features viewer
+
+#[cfg(feature = "viewer")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[cfg(feature = "viewer")]
+use shocovox_rs::octree::{
+    raytracing::{Camera, Ray, ToneMapping},
+    Octree, Sphere, V3c, VoxelData,
+};
+
+#[cfg(feature = "viewer")]
+#[derive(Default, Clone, Debug, PartialEq)]
+struct RGB {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+#[cfg(feature = "viewer")]
+impl VoxelData for RGB {
+    fn new(r: u8, g: u8, b: u8, a: u8, _user_data: u32) -> Self {
+        Self { r, g, b, a }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+    fn user_data(&self) -> u32 {
+        0
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "viewer")]
+const MATRIX_DIMENSION: usize = 2;
+
+#[cfg(feature = "viewer")]
+const WORLD_SIZE: u32 = 32;
+
+#[cfg(feature = "viewer")]
+const VIEWPORT_SIZE: u32 = 128;
+
+#[cfg(feature = "viewer")]
+const CRATER_RADIUS: f32 = 3.;
+
+#[cfg(feature = "viewer")]
+const GRAVITY: f32 = 20.;
+
+/// A part of the structure detached from the ground, falling freely
+#[cfg(feature = "viewer")]
+struct Debris {
+    tree: Octree<RGB, MATRIX_DIMENSION>,
+    offset: V3c<f32>,
+    velocity: f32,
+}
+
+/// Builds a ground plate with two towers connected by a bridge
+#[cfg(feature = "viewer")]
+fn build_structure() -> Octree<RGB, MATRIX_DIMENSION> {
+    let mut tree = Octree::new(WORLD_SIZE).ok().unwrap();
+    for x in 0..WORLD_SIZE {
+        for z in 0..WORLD_SIZE {
+            for y in 0..WORLD_SIZE {
+                let ground = y < 2;
+                let tower = (4..10).contains(&z)
+                    && ((4..10).contains(&x) || (22..28).contains(&x))
+                    && y < 24;
+                let bridge = (4..10).contains(&z) && (10..22).contains(&x) && (18..21).contains(&y);
+                let color = if ground {
+                    RGB::new(70, 120, 60, 255, 0)
+                } else if tower {
+                    RGB::new(150 + (y * 3) as u8, 130, 110, 255, 0)
+                } else if bridge {
+                    RGB::new(110, 80, 60, 255, 0)
+                } else {
+                    continue;
+                };
+                tree.insert(&V3c::new(x, y, z), color).ok().unwrap();
+            }
+        }
+    }
+    tree
+}
+
+/// Clears every voxel of the tree inside the given sphere
+#[cfg(feature = "viewer")]
+fn carve(tree: &mut Octree<RGB, MATRIX_DIMENSION>, sphere: &Sphere) {
+    let min = |c: f32| (c - sphere.radius).max(0.) as u32;
+    let max = |c: f32| ((c + sphere.radius).ceil() as u32).min(WORLD_SIZE);
+    for x in min(sphere.center.x)..max(sphere.center.x) {
+        for y in min(sphere.center.y)..max(sphere.center.y) {
+            for z in min(sphere.center.z)..max(sphere.center.z) {
+                let voxel_center = V3c::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5);
+                if sphere.contains(&voxel_center) {
+                    tree.clear(&V3c::new(x, y, z)).ok().unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// The voxels connected to the given starting voxels through their faces
+#[cfg(feature = "viewer")]
+fn flood_fill(
+    tree: &Octree<RGB, MATRIX_DIMENSION>,
+    start: Vec<(u32, u32, u32)>,
+    visited: &mut std::collections::HashSet<(u32, u32, u32)>,
+) -> Vec<(u32, u32, u32)> {
+    let mut component = Vec::new();
+    let mut stack = start;
+    while let Some((x, y, z)) = stack.pop() {
+        if !visited.insert((x, y, z)) {
+            continue;
+        }
+        component.push((x, y, z));
+        let neighbours = [
+            (x.wrapping_sub(1), y, z),
+            (x + 1, y, z),
+            (x, y.wrapping_sub(1), z),
+            (x, y + 1, z),
+            (x, y, z.wrapping_sub(1)),
+            (x, y, z + 1),
+        ];
+        for (nx, ny, nz) in neighbours {
+            if nx < WORLD_SIZE
+                && ny < WORLD_SIZE
+                && nz < WORLD_SIZE
+                && !visited.contains(&(nx, ny, nz))
+                && tree.get(&V3c::new(nx, ny, nz)).is_some()
+            {
+                stack.push((nx, ny, nz));
+            }
+        }
+    }
+    component
+}
+
+/// Moves every group of voxels not connected to the ground into a separate tree
+#[cfg(feature = "viewer")]
+fn detach_floating_parts(tree: &mut Octree<RGB, MATRIX_DIMENSION>) -> Vec<Debris> {
+    let mut visited = std::collections::HashSet::new();
+    let mut ground = Vec::new();
+    for x in 0..WORLD_SIZE {
+        for z in 0..WORLD_SIZE {
+            if tree.get(&V3c::new(x, 0, z)).is_some() {
+                ground.push((x, 0, z));
+            }
+        }
+    }
+    flood_fill(tree, ground, &mut visited);
+
+    let mut debris = Vec::new();
+    for x in 0..WORLD_SIZE {
+        for y in 1..WORLD_SIZE {
+            for z in 0..WORLD_SIZE {
+                if visited.contains(&(x, y, z)) || tree.get(&V3c::new(x, y, z)).is_none() {
+                    continue;
+                }
+                let component = flood_fill(tree, vec![(x, y, z)], &mut visited);
+                let min = component
+                    .iter()
+                    .fold((u32::MAX, u32::MAX, u32::MAX), |m, v| {
+                        (m.0.min(v.0), m.1.min(v.1), m.2.min(v.2))
+                    });
+                let extent = component
+                    .iter()
+                    .map(|v| (v.0 - min.0).max(v.1 - min.1).max(v.2 - min.2) + 1)
+                    .max()
+                    .unwrap();
+                let mut size = MATRIX_DIMENSION as u32;
+                while size < extent {
+                    size *= 2;
+                }
+                let mut part = Octree::new(size).ok().unwrap();
+                for (vx, vy, vz) in component {
+                    let position = V3c::new(vx, vy, vz);
+                    let data = tree.get(&position).unwrap().clone();
+                    part.insert(&V3c::new(vx - min.0, vy - min.1, vz - min.2), data)
+                        .ok()
+                        .unwrap();
+                    tree.clear(&position).ok().unwrap();
+                }
+                debris.push(Debris {
+                    tree: part,
+                    offset: V3c::new(min.0 as f32, min.1 as f32, min.2 as f32),
+                    velocity: 0.,
+                });
+            }
+        }
+    }
+    debris
+}
+
+/// Renders the world and the debris together, taking the closest hit of every pixel
+#[cfg(feature = "viewer")]
+fn render(world: &Octree<RGB, MATRIX_DIMENSION>, debris: &[Debris], camera: &Camera) -> Vec<u8> {
+    let light = V3c::new(0.4, 1., 0.3).normalized();
+    let tone_mapping = ToneMapping::default();
+    let mut image = Vec::with_capacity((VIEWPORT_SIZE * VIEWPORT_SIZE * 4) as usize);
+    for y in 0..VIEWPORT_SIZE {
+        for x in 0..VIEWPORT_SIZE {
+            let ray = camera.ray_for(x, y, VIEWPORT_SIZE, VIEWPORT_SIZE);
+            let mut closest = world.get_by_ray_owned(&ray);
+            for part in debris {
+                let local_ray = Ray {
+                    origin: ray.origin - part.offset,
+                    direction: ray.direction,
+                };
+                if let Some(hit) = part.tree.get_by_ray_owned(&local_ray) {
+                    if closest.as_ref().map_or(true, |c| hit.distance < c.distance) {
+                        closest = Some(hit);
+                    }
+                }
+            }
+            match closest {
+                Some(hit) => {
+                    let shade = 0.4 + 0.6 * hit.normal.dot(&light).max(0.);
+                    let [r, g, b, a] = hit.data.albedo();
+                    let [r, g, b] = tone_mapping.apply_rgb([
+                        r as f32 / 255. * shade,
+                        g as f32 / 255. * shade,
+                        b as f32 / 255. * shade,
+                    ]);
+                    image.extend_from_slice(&[r, g, b, a]);
+                }
+                None => image.extend_from_slice(&[150, 180, 220, 255]),
+            }
+        }
+    }
+    image
+}
+
+#[cfg(feature = "viewer")]
+#[show_image::main]
+fn main() {
+    let mut rng = StdRng::seed_from_u64(shocovox_rs::testing::seed("destruction"));
+    let window = show_image::create_window("destruction", Default::default())
+        .ok()
+        .unwrap();
+    let events = window.event_channel().ok().unwrap();
+
+    let mut world = build_structure();
+    let mut debris: Vec<Debris> = Vec::new();
+    let origin = V3c::new(
+        WORLD_SIZE as f32 * 0.5,
+        WORLD_SIZE as f32 * 0.8,
+        WORLD_SIZE as f32 * 1.2,
+    );
+    let camera = Camera {
+        origin,
+        direction: (V3c::new(WORLD_SIZE as f32 / 2., 10., 6.) - origin).normalized(),
+        size: (4., 4.),
+        fov: 3.,
+    };
+    let mut last_frame = std::time::Instant::now();
+    let mut next_shot = 0.;
+
+    loop {
+        for event in events.try_iter() {
+            if let show_image::event::WindowEvent::Destroyed(_) = event {
+                std::process::exit(0);
+            }
+        }
+        let delta = last_frame.elapsed().as_secs_f32().min(0.1);
+        last_frame = std::time::Instant::now();
+
+        // Fire a projectile at a random point of the structure from time to time
+        next_shot -= delta;
+        if next_shot <= 0. {
+            next_shot = 0.5;
+            let target = V3c::new(rng.gen_range(4.0..28.0), rng.gen_range(4.0..24.0), 7.);
+            let projectile = Ray {
+                origin,
+                direction: (target - origin).normalized(),
+            };
+            if let Some(hit) = world.get_by_ray_owned(&projectile) {
+                carve(&mut world, &Sphere::new(hit.point, CRATER_RADIUS));
+                debris.extend(detach_floating_parts(&mut world));
+            }
+        }
+
+        // Integrate the falling debris, dropping the parts fallen out of sight
+        for part in debris.iter_mut() {
+            part.velocity -= GRAVITY * delta;
+            part.offset.y += part.velocity * delta;
+        }
+        debris.retain(|part| -(WORLD_SIZE as f32) < part.offset.y);
+
+        let binding = render(&world, &debris, &camera);
+        use show_image::{ImageInfo, ImageView};
+        let image = ImageView::new(ImageInfo::rgba8(VIEWPORT_SIZE, VIEWPORT_SIZE), &binding);
+        window.set_image("destruction", image).ok().unwrap();
+    }
+}
+
+#[cfg(not(feature = "viewer"))]
+fn main() {} //nothing to do when the feature is not enabled
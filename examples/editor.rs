@@ -0,0 +1,230 @@
+//! A minimal voxel editor: places voxels on the picked voxel faces, erases the picked voxels
+//! and saves the scene to a file.
+//! Controls:
+//! - Arrows: rotate and zoom the camera
+//! - Click: place a voxel, Shift+Click: erase a voxel
+//! - C: next color
+//! - F5: save, F9: load
+//!
+//! usage: cargo run --example editor --features viewer
+
+#[cfg(feature = "viewer")]
+use shocovox_rs::octree::{
+    raytracing::{Camera, FrameCoherenceCache},
+    DefaultVoxelDataCodec, Octree, OctreeLoader, V3c, VoxelData,
+};
+
+#[cfg(feature = "viewer")]
+use show_image::event::{ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+
+#[cfg(feature = "viewer")]
+#[derive(Default, Clone, Debug, PartialEq)]
+struct RGB {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+#[cfg(feature = "viewer")]
+impl VoxelData for RGB {
+    fn new(r: u8, g: u8, b: u8, a: u8, _user_data: u32) -> Self {
+        Self { r, g, b, a }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+    fn user_data(&self) -> u32 {
+        0
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "viewer")]
+const MATRIX_DIMENSION: usize = 2;
+
+#[cfg(feature = "viewer")]
+type Scene = Octree<RGB, MATRIX_DIMENSION>;
+
+#[cfg(feature = "viewer")]
+const SCENE_SIZE: u32 = 32;
+
+#[cfg(feature = "viewer")]
+const VIEWPORT_SIZE: u32 = 128;
+
+#[cfg(feature = "viewer")]
+const WINDOW_SIZE: u32 = 512;
+
+#[cfg(feature = "viewer")]
+const PALETTE: [[u8; 3]; 5] = [
+    [200, 60, 50],
+    [60, 170, 80],
+    [50, 90, 200],
+    [230, 200, 70],
+    [240, 240, 240],
+];
+
+#[cfg(feature = "viewer")]
+fn camera_for(yaw: f32, distance: f32) -> Camera {
+    let center = V3c::unit(SCENE_SIZE as f32 / 2.);
+    let origin = center + V3c::new(yaw.sin() * distance, distance * 0.6, yaw.cos() * distance);
+    Camera {
+        direction: (center - origin).normalized(),
+        origin,
+        size: (4., 4.),
+        fov: 3.,
+    }
+}
+
+#[cfg(feature = "viewer")]
+#[show_image::main]
+fn main() {
+    let save_path = std::env::temp_dir().join("shocovox_editor_scene.bin");
+    let save_path = save_path.to_str().unwrap();
+    let window = show_image::create_window(
+        "editor",
+        show_image::WindowOptions::new()
+            .set_size([WINDOW_SIZE, WINDOW_SIZE])
+            .set_resizable(false),
+    )
+    .ok()
+    .unwrap();
+    let events = window.event_channel().ok().unwrap();
+
+    // Start with a floor to paint on
+    let mut scene = Scene::new(SCENE_SIZE).ok().unwrap();
+    for x in 0..SCENE_SIZE {
+        for z in 0..SCENE_SIZE {
+            scene
+                .insert(&V3c::new(x, 0, z), RGB::new(120, 120, 120, 255, 0))
+                .ok()
+                .unwrap();
+        }
+    }
+    let mut frame_cache = FrameCoherenceCache::new(VIEWPORT_SIZE, VIEWPORT_SIZE);
+    let mut held_keys = std::collections::HashSet::new();
+    let (mut yaw, mut distance) = (0.6_f32, SCENE_SIZE as f32 * 1.5);
+    let mut color = 0;
+    let mut redraw = true;
+
+    loop {
+        for event in events.try_iter() {
+            match event {
+                WindowEvent::Destroyed(_) => std::process::exit(0),
+                WindowEvent::KeyboardInput(event) => {
+                    let Some(key) = event.input.key_code else {
+                        continue;
+                    };
+                    if matches!(event.input.state, ElementState::Released) {
+                        held_keys.remove(&key);
+                        continue;
+                    }
+                    held_keys.insert(key);
+                    let changed = match key {
+                        VirtualKeyCode::C => {
+                            color = (color + 1) % PALETTE.len();
+                            println!("color: {:?}", PALETTE[color]);
+                            false
+                        }
+                        VirtualKeyCode::F5 => {
+                            match scene.save(save_path) {
+                                Ok(()) => println!("saved to {save_path}"),
+                                Err(error) => println!("unable to save: {error}"),
+                            }
+                            false
+                        }
+                        VirtualKeyCode::F9 => {
+                            match OctreeLoader::new(DefaultVoxelDataCodec)
+                                .load::<MATRIX_DIMENSION>(save_path)
+                            {
+                                Ok(loaded) => {
+                                    scene = loaded;
+                                    true
+                                }
+                                Err(error) => {
+                                    println!("unable to load: {error}");
+                                    false
+                                }
+                            }
+                        }
+                        _ => false,
+                    };
+                    if changed {
+                        frame_cache.invalidate();
+                        redraw = true;
+                    }
+                }
+                WindowEvent::MouseButton(event)
+                    if matches!(event.button, MouseButton::Left)
+                        && matches!(event.state, ElementState::Pressed) =>
+                {
+                    let pixel = (
+                        (event.position.x.max(0.) as u32 * VIEWPORT_SIZE / WINDOW_SIZE)
+                            .min(VIEWPORT_SIZE - 1),
+                        (event.position.y.max(0.) as u32 * VIEWPORT_SIZE / WINDOW_SIZE)
+                            .min(VIEWPORT_SIZE - 1),
+                    );
+                    let ray = camera_for(yaw, distance).ray_for(
+                        pixel.0,
+                        pixel.1,
+                        VIEWPORT_SIZE,
+                        VIEWPORT_SIZE,
+                    );
+                    let Some(hit) = scene.get_by_ray_owned(&ray) else {
+                        continue;
+                    };
+                    let edit = if event.modifiers.shift() {
+                        scene.clear(&hit.voxel)
+                    } else {
+                        // Place on top of the face which was hit
+                        let target = V3c::<f32>::from(hit.voxel) + hit.normal;
+                        if target.x < 0. || target.y < 0. || target.z < 0. {
+                            continue;
+                        }
+                        let [r, g, b] = PALETTE[color];
+                        scene.insert(&target.into(), RGB::new(r, g, b, 255, 0))
+                    };
+                    if let Err(error) = edit {
+                        // e.g. placing a voxel outside of the scene
+                        println!("unable to edit: {error:?}");
+                        continue;
+                    }
+                    frame_cache.invalidate();
+                    redraw = true;
+                }
+                _ => {}
+            }
+        }
+
+        for key in held_keys.iter() {
+            match key {
+                VirtualKeyCode::Left => yaw -= 0.03,
+                VirtualKeyCode::Right => yaw += 0.03,
+                VirtualKeyCode::Up => distance = (distance - 0.5).max(SCENE_SIZE as f32 * 0.5),
+                VirtualKeyCode::Down => distance += 0.5,
+                _ => continue,
+            }
+            redraw = true;
+        }
+
+        if !redraw {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            continue;
+        }
+        redraw = false;
+        let binding = scene.render_viewport(
+            &camera_for(yaw, distance),
+            VIEWPORT_SIZE,
+            VIEWPORT_SIZE,
+            Some(&mut frame_cache),
+        );
+        use show_image::{ImageInfo, ImageView};
+        let image = ImageView::new(ImageInfo::rgba8(VIEWPORT_SIZE, VIEWPORT_SIZE), &binding);
+        window.set_image("editor", image).ok().unwrap();
+    }
+}
+
+#[cfg(not(feature = "viewer"))]
+fn main() {} //nothing to do when the feature is not enabled
@@ -0,0 +1,211 @@
+//! Renders a small scene with a mirror wall and a pool of water on the CPU, following the rays
+//! reflected and refracted by these voxels with `get_by_ray`, along with shadow rays towards the sun.
+//! usage: cargo run --example secondary_rays --features image
+//! The result is saved into secondary_rays.png
+
+#[cfg(feature = "image")]
+use shocovox_rs::octree::{
+    raytracing::{reflect, refract, Camera, Ray, ToneMapping},
+    Octree, V3c, VoxelData,
+};
+
+/// Materials of the voxels, stored in their user data
+#[cfg(feature = "image")]
+const DIFFUSE: u32 = 0;
+#[cfg(feature = "image")]
+const MIRROR: u32 = 1;
+#[cfg(feature = "image")]
+const WATER: u32 = 2;
+
+/// The index of refraction of water surrounded by air
+#[cfg(feature = "image")]
+const WATER_IOR: f32 = 1.33;
+
+/// The number of times a ray may bounce off of mirrors or get refracted by water
+#[cfg(feature = "image")]
+const MAX_DEPTH: u32 = 4;
+
+/// Secondary rays start this far away from the surface they are leaving, so they don't hit it again
+#[cfg(feature = "image")]
+const SURFACE_OFFSET: f32 = 0.01;
+
+#[cfg(feature = "image")]
+const TREE_SIZE: u32 = 32;
+
+#[cfg(feature = "image")]
+const IMAGE_SIZE: u32 = 256;
+
+#[cfg(feature = "image")]
+#[derive(Default, Clone, Debug, PartialEq)]
+struct Voxel {
+    color: [u8; 4],
+    material: u32,
+}
+
+#[cfg(feature = "image")]
+impl VoxelData for Voxel {
+    fn new(r: u8, g: u8, b: u8, a: u8, user_data: u32) -> Self {
+        Self {
+            color: [r, g, b, a],
+            material: user_data,
+        }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        self.color
+    }
+    fn user_data(&self) -> u32 {
+        self.material
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "image")]
+fn build_scene() -> Octree<Voxel, 2> {
+    let mut tree = Octree::<Voxel, 2>::new(TREE_SIZE).ok().unwrap();
+    for x in 0..TREE_SIZE {
+        for z in 0..TREE_SIZE {
+            let in_pool = (6..18).contains(&x) && (12..26).contains(&z);
+            if in_pool {
+                // The bottom of the pool, and the surface of the water two voxels above it
+                tree.insert(&V3c::new(x, 0, z), Voxel::new(200, 180, 120, 255, DIFFUSE))
+                    .ok()
+                    .unwrap();
+                tree.insert(&V3c::new(x, 3, z), Voxel::new(40, 110, 160, 255, WATER))
+                    .ok()
+                    .unwrap();
+            } else {
+                let shade = if 0 == (x / 4 + z / 4) % 2 { 220 } else { 90 };
+                for y in 0..4 {
+                    tree.insert(
+                        &V3c::new(x, y, z),
+                        Voxel::new(shade, shade, shade, 255, DIFFUSE),
+                    )
+                    .ok()
+                    .unwrap();
+                }
+            }
+        }
+    }
+    // A mirror wall at the far side of the scene, framed by red voxels
+    for x in 2..30 {
+        for y in 4..20 {
+            let frame = 2 == x || 29 == x || 19 == y;
+            let voxel = if frame {
+                Voxel::new(180, 40, 40, 255, DIFFUSE)
+            } else {
+                Voxel::new(230, 230, 240, 255, MIRROR)
+            };
+            tree.insert(&V3c::new(x, y, 30), voxel).ok().unwrap();
+        }
+    }
+    // A green pillar in front of the mirror, casting a shadow onto the floor
+    for y in 4..14 {
+        for x in 22..25 {
+            for z in 14..17 {
+                tree.insert(&V3c::new(x, y, z), Voxel::new(60, 170, 70, 255, DIFFUSE))
+                    .ok()
+                    .unwrap();
+            }
+        }
+    }
+    tree
+}
+
+#[cfg(feature = "image")]
+fn linear(color: [u8; 4]) -> V3c<f32> {
+    V3c::new(color[0] as f32, color[1] as f32, color[2] as f32) * (1. / 255.)
+}
+
+/// The color of the sky in the given direction
+#[cfg(feature = "image")]
+fn sky(direction: V3c<f32>) -> V3c<f32> {
+    let height = direction.y.max(0.);
+    V3c::new(0.6, 0.75, 0.95) * (1. - height) + V3c::new(0.25, 0.45, 0.85) * height
+}
+
+/// Follows the given ray through the scene, spawning secondary rays at the voxels it hits
+#[cfg(feature = "image")]
+fn trace(tree: &Octree<Voxel, 2>, ray: &Ray, depth: u32) -> V3c<f32> {
+    let Some((voxel, point, normal)) = tree.get_by_ray(ray) else {
+        return sky(ray.direction);
+    };
+    if 0 == depth {
+        return linear(voxel.color) * 0.2;
+    }
+    match voxel.material {
+        MIRROR => {
+            let reflected = Ray {
+                origin: point + normal * SURFACE_OFFSET,
+                direction: reflect(ray.direction, normal).normalized(),
+            };
+            trace(tree, &reflected, depth - 1) * 0.9
+        }
+        WATER => {
+            // The water surface is a single layer of voxels: the pool beneath it is treated as
+            // filled with water, so refracted rays continue below the layer without bending again
+            let reflected = Ray {
+                origin: point + normal * SURFACE_OFFSET,
+                direction: reflect(ray.direction, normal).normalized(),
+            };
+            let reflection = trace(tree, &reflected, depth - 1);
+            let Some(direction) = refract(ray.direction, normal, WATER_IOR) else {
+                return reflection;
+            };
+            let refracted = Ray {
+                origin: point - normal * (1. + SURFACE_OFFSET),
+                direction,
+            };
+            // Schlick's approximation of the amount of light reflected by the surface
+            let cosine = -ray.direction.dot(&normal);
+            let base = ((WATER_IOR - 1.) / (WATER_IOR + 1.)).powi(2);
+            let reflectance = base + (1. - base) * (1. - cosine).powi(5);
+            reflection * reflectance
+                + trace(tree, &refracted, depth - 1) * linear(voxel.color) * (1. - reflectance)
+        }
+        _ => {
+            let sun = V3c::new(-0.4, 0.8, -0.45).normalized();
+            let shadow_ray = Ray {
+                origin: point + normal * SURFACE_OFFSET,
+                direction: sun,
+            };
+            // Sunlight passes through the water, so only other voxels cast shadows
+            let lit = !matches!(
+                tree.get_by_ray(&shadow_ray),
+                Some((blocker, _, _)) if WATER != blocker.material
+            );
+            let diffuse = if lit { normal.dot(&sun).max(0.) } else { 0. };
+            linear(voxel.color) * (0.25 + 0.75 * diffuse)
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+fn main() {
+    let tree = build_scene();
+    let camera = Camera {
+        origin: V3c::new(16., 18., -14.),
+        direction: V3c::new(0., -0.45, 1.).normalized(),
+        size: (1.5, 1.5),
+        fov: 1.,
+    };
+    let tone_mapping = ToneMapping::default();
+    let mut image = image::RgbImage::new(IMAGE_SIZE, IMAGE_SIZE);
+    for y in 0..IMAGE_SIZE {
+        for x in 0..IMAGE_SIZE {
+            let ray = camera.ray_for(x, y, IMAGE_SIZE, IMAGE_SIZE);
+            let color = trace(&tree, &ray, MAX_DEPTH);
+            image.put_pixel(
+                x,
+                y,
+                image::Rgb(tone_mapping.apply_rgb([color.x, color.y, color.z])),
+            );
+        }
+    }
+    image.save("secondary_rays.png").ok().unwrap();
+    println!("Saved the rendered scene into secondary_rays.png");
+}
+
+#[cfg(not(feature = "image"))]
+fn main() {} //nothing to do when the feature is not enabled
@@ -0,0 +1,236 @@
+//! Streams a procedurally generated terrain around a camera flying over it:
+//! chunks of a world are generated from a noise function as the camera nears them and evicted once it leaves them,
+//! with a coarse version of every chunk traced in the distance.
+//! usage: cargo run --example terrain --features viewer
+
+#[cfg(feature = "viewer")]
+use shocovox_rs::octree::{
+    raytracing::{Camera, LodFade, WorldRayHit},
+    DistanceStreamingPolicy, Octree, V3c, VoxelData, VoxelWorld,
+};
+
+#[cfg(feature = "viewer")]
+#[derive(Default, Clone, Debug, PartialEq)]
+struct RGB {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+#[cfg(feature = "viewer")]
+impl VoxelData for RGB {
+    fn new(r: u8, g: u8, b: u8, a: u8, _user_data: u32) -> Self {
+        Self { r, g, b, a }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+    fn user_data(&self) -> u32 {
+        0
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "viewer")]
+const MATRIX_DIMENSION: usize = 4;
+
+/// The size of the chunks of the world
+#[cfg(feature = "viewer")]
+const CHUNK_SIZE: u32 = 32;
+
+/// The size of the coarse version of the chunks, each of its voxels covers 4*4*4 voxels of the chunk
+#[cfg(feature = "viewer")]
+const COARSE_CHUNK_SIZE: u32 = 8;
+
+/// The terrain is below this height everywhere
+#[cfg(feature = "viewer")]
+const MAX_HEIGHT: i32 = 72;
+
+#[cfg(feature = "viewer")]
+const VIEWPORT_SIZE: u32 = 128;
+
+/// A pseudo random value between 0 and 1 for the given lattice point
+#[cfg(feature = "viewer")]
+fn lattice_value(x: i64, z: i64, seed: u64) -> f32 {
+    let mut hash = (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (z as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ seed;
+    hash ^= hash >> 31;
+    hash = hash.wrapping_mul(0xBF58476D1CE4E5B9);
+    hash ^= hash >> 29;
+    (hash & 0xFFFF) as f32 / 0xFFFF as f32
+}
+
+/// Smoothly interpolated value noise between 0 and 1 with the given period
+#[cfg(feature = "viewer")]
+fn value_noise(x: f32, z: f32, period: f32, seed: u64) -> f32 {
+    let (x, z) = (x / period, z / period);
+    let (cell_x, cell_z) = (x.floor() as i64, z.floor() as i64);
+    let smooth = |t: f32| t * t * (3. - 2. * t);
+    let (tx, tz) = (smooth(x - x.floor()), smooth(z - z.floor()));
+    let top = lattice_value(cell_x, cell_z, seed) * (1. - tx)
+        + lattice_value(cell_x + 1, cell_z, seed) * tx;
+    let bottom = lattice_value(cell_x, cell_z + 1, seed) * (1. - tx)
+        + lattice_value(cell_x + 1, cell_z + 1, seed) * tx;
+    top * (1. - tz) + bottom * tz
+}
+
+/// The height of the terrain at the given world position
+#[cfg(feature = "viewer")]
+fn terrain_height(x: i32, z: i32, seed: u64) -> i32 {
+    let (x, z) = (x as f32, z as f32);
+    let height = value_noise(x, z, 64., seed) * 0.65
+        + value_noise(x, z, 16., seed + 1) * 0.25
+        + value_noise(x, z, 4., seed + 2) * 0.1;
+    4 + (height * (MAX_HEIGHT - 8) as f32) as i32
+}
+
+/// The voxel of the terrain at the given world position, if any
+#[cfg(feature = "viewer")]
+fn terrain_voxel(position: &V3c<i32>, seed: u64) -> Option<RGB> {
+    if position.y < 0 || terrain_height(position.x, position.z, seed) <= position.y {
+        return None;
+    }
+    let (r, g, b) = match position.y {
+        0..=14 => (40, 90, 200),
+        15..=34 => (60, 160 - position.y as u8 * 2, 50),
+        35..=50 => (120, 110, 100),
+        _ => (240, 240, 245),
+    };
+    Some(RGB { r, g, b, a: 255 })
+}
+
+/// Generates the chunk at the given coordinates and its coarse version into the world,
+/// chunks outside the height of the terrain are marked as empty
+#[cfg(feature = "viewer")]
+fn generate_chunk(world: &mut VoxelWorld<RGB, MATRIX_DIMENSION>, chunk_coord: V3c<i32>, seed: u64) {
+    let chunk_min = world.world_position_of(&chunk_coord, &V3c::unit(0));
+    if chunk_min.y < 0 || MAX_HEIGHT <= chunk_min.y {
+        world.insert_empty_chunk(chunk_coord);
+        return;
+    }
+    let at = |position: &V3c<u32>, scale: u32| {
+        let offset = (*position * scale) + V3c::unit(scale / 2);
+        chunk_min + V3c::<i32>::from(offset)
+    };
+    let chunk = Octree::generate(CHUNK_SIZE, |position| terrain_voxel(&at(position, 1), seed))
+        .ok()
+        .unwrap();
+    let scale = CHUNK_SIZE / COARSE_CHUNK_SIZE;
+    let coarse = Octree::generate(COARSE_CHUNK_SIZE, |position| {
+        terrain_voxel(&at(position, scale), seed)
+    })
+    .ok()
+    .unwrap();
+    world.insert_chunk(chunk_coord, chunk).ok().unwrap();
+    world.insert_coarse_chunk(chunk_coord, coarse).ok().unwrap();
+}
+
+/// The color of the result of a ray cast through the world
+#[cfg(feature = "viewer")]
+fn shade(result: &WorldRayHit<RGB>) -> [f32; 3] {
+    match result {
+        WorldRayHit::Hit { hit, .. } => {
+            let light = 0.6 + 0.4 * hit.normal.y.max(0.);
+            [
+                hit.data.r as f32 * light,
+                hit.data.g as f32 * light,
+                hit.data.b as f32 * light,
+            ]
+        }
+        // Chunks not generated yet are shown as fog
+        WorldRayHit::Unknown { .. } => [200., 200., 210.],
+        WorldRayHit::Miss => [130., 180., 240.],
+    }
+}
+
+#[cfg(feature = "viewer")]
+#[show_image::main]
+fn main() {
+    let seed = shocovox_rs::testing::seed("terrain");
+    let window = show_image::create_window("terrain", Default::default())
+        .ok()
+        .unwrap();
+    let events = window.event_channel().ok().unwrap();
+
+    let mut world = VoxelWorld::<RGB, MATRIX_DIMENSION>::new(CHUNK_SIZE)
+        .ok()
+        .unwrap();
+    let policy = DistanceStreamingPolicy {
+        load_radius: 3,
+        keep_radius: 4,
+        loads_per_tick: 2,
+    };
+    let fade = LodFade {
+        start: 1.5 * CHUNK_SIZE as f32,
+        end: 2.5 * CHUNK_SIZE as f32,
+    };
+    let max_distance = (policy.load_radius * CHUNK_SIZE) as f32;
+
+    let mut camera_position = V3c::new(0., MAX_HEIGHT as f32, 0.);
+    let mut time = 0_f32;
+    let mut frames = 0;
+    let mut fps_timer = std::time::Instant::now();
+    let mut image = vec![0_u8; (VIEWPORT_SIZE * VIEWPORT_SIZE * 4) as usize];
+
+    loop {
+        for event in events.try_iter() {
+            if let show_image::event::WindowEvent::Destroyed(_) = event {
+                std::process::exit(0);
+            }
+        }
+
+        time += 0.016;
+        let direction = V3c::new((time * 0.3).sin() * 0.5, -0.9, 1.).normalized();
+        camera_position = camera_position + V3c::new(direction.x, 0., direction.z) * 0.5;
+
+        let plan = world.plan_streaming(&V3c::from(camera_position), &policy);
+        for chunk_coord in plan.evict.iter() {
+            world.remove_chunk(chunk_coord);
+            world.remove_coarse_chunk(chunk_coord);
+        }
+        for chunk_coord in plan.load {
+            generate_chunk(&mut world, chunk_coord, seed);
+        }
+
+        let camera = Camera {
+            origin: camera_position,
+            direction,
+            size: (4., 4.),
+            fov: 3.,
+        };
+        for y in 0..VIEWPORT_SIZE {
+            for x in 0..VIEWPORT_SIZE {
+                let ray = camera.ray_for(x, y, VIEWPORT_SIZE, VIEWPORT_SIZE);
+                let result = world.get_by_ray_lod(&ray, max_distance, true, &fade);
+                let mut color = shade(&result.hit);
+                if let Some((other, weight)) = &result.blend {
+                    let other = shade(other);
+                    for (channel, other) in color.iter_mut().zip(other) {
+                        *channel += (other - *channel) * weight;
+                    }
+                }
+                let pixel = ((y * VIEWPORT_SIZE + x) * 4) as usize;
+                let [r, g, b] = color.map(|channel| channel as u8);
+                image[pixel..pixel + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+
+        use show_image::{ImageInfo, ImageView};
+        let view = ImageView::new(ImageInfo::rgba8(VIEWPORT_SIZE, VIEWPORT_SIZE), &image);
+        window.set_image("terrain", view).ok().unwrap();
+
+        frames += 1;
+        if 1. <= fps_timer.elapsed().as_secs_f32() {
+            println!("FPS: {frames}, loaded chunks: {}", world.chunks().count());
+            frames = 0;
+            fps_timer = std::time::Instant::now();
+        }
+    }
+}
+
+#[cfg(not(feature = "viewer"))]
+fn main() {} //nothing to do when the feature is not enabled
@@ -1,4 +1,12 @@
-mod object_pool;
+//! Sparse Voxel Octree with ray marching.
+//! The public API is the `octree` module: `octree::Octree` and the types re-exported next to it.
+//! Nodes are addressed by `object_pool::PoolKey` keys internally, exposed as `octree::NodeKey`
+//! through `octree::NodeHandle`; there is no other octree implementation in the crate.
+
 mod spatial;
 
+pub mod object_pool;
 pub mod octree;
+// Helpers for the tests, examples and benchmarks of the crate, not part of the public API
+#[doc(hidden)]
+pub mod testing;
@@ -1,3 +1,5 @@
+use std::collections::TryReserveError;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::vec::Vec;
 
 #[cfg(feature = "serialization")]
@@ -11,12 +13,37 @@ struct ReusableItem<T: Clone> {
     item: T,
 }
 
-pub fn key_none_value() -> u32 {
-    u32::MAX
+/// The type of the keys of the items in a pool, u32 by default for compact and cache friendly nodes.
+/// With the `u64_keys` feature it is u64, for scenes needing more than 2^32 nodes
+#[cfg(not(feature = "u64_keys"))]
+pub type PoolKey = u32;
+#[cfg(feature = "u64_keys")]
+pub type PoolKey = u64;
+
+/// The key value used to mark the absence of an item
+pub fn key_none_value() -> PoolKey {
+    PoolKey::MAX
+}
+
+/// False if the key is the one marking the absence of an item
+pub fn key_might_be_valid(key: PoolKey) -> bool {
+    key < PoolKey::MAX
+}
+
+/// Source of the identifiers every new pool gets, so keys can be traced back to the pool they came from
+static NEXT_POOL_ID: AtomicU32 = AtomicU32::new(0);
+
+fn next_pool_id() -> u32 {
+    NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed)
 }
 
-pub fn key_might_be_valid(key: u32) -> bool {
-    key < u32::MAX
+/// A key bound to the pool which provided it. Keys stored outside of the pool should be branded,
+/// so using them with a different pool ( e.g. the pool of another tree ) fails loudly instead of
+/// silently pointing to unrelated data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BrandedKey {
+    pool_id: u32,
+    key: PoolKey,
 }
 
 use bendy::encoding::{Error as BencodeError, SingleItemEncoder, ToBencode};
@@ -33,7 +60,17 @@ where
     }
 }
 
-use bendy::decoding::{FromBencode, Object};
+use bendy::decoding::{FromBencode, ListDecoder, Object};
+
+/// The next item of the given list, or an error naming the expected field should the list end before it
+pub(crate) fn next_list_item<'item, 'obj, 'ser>(
+    list: &'item mut ListDecoder<'obj, 'ser>,
+    field: &str,
+) -> Result<Object<'item, 'ser>, bendy::decoding::Error> {
+    list.next_object()?
+        .ok_or_else(|| bendy::decoding::Error::unexpected_token(field, "the end of the list"))
+}
+
 impl<T> FromBencode for ReusableItem<T>
 where
     T: Clone + FromBencode,
@@ -41,7 +78,7 @@ where
     fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
         match data {
             Object::List(mut list) => {
-                let reserved = match list.next_object()?.unwrap() {
+                let reserved = match next_list_item(&mut list, "boolean field reserved")? {
                     Object::Integer("0") => Ok(false),
                     Object::Integer("1") => Ok(true),
                     Object::Integer(i) => Err(bendy::decoding::Error::unexpected_token(
@@ -53,7 +90,7 @@ where
                         "Something else",
                     )),
                 }?;
-                let item = T::decode_bencode_object(list.next_object()?.unwrap())?;
+                let item = T::decode_bencode_object(next_list_item(&mut list, "pooled item")?)?;
                 Ok(Self { item, reserved })
             }
             _ => Err(bendy::decoding::Error::unexpected_token(
@@ -67,14 +104,41 @@ where
 ///####################################################################################
 /// ObjectPool
 ///####################################################################################
-
 /// Stores re-usable objects to eliminate data allocation overhead when inserting and removing Nodes
 /// It keeps track of different buffers for different levels in the graph, allocating more space initially to lower levels
-#[derive(Default, Clone)]
+/// Items are addressed by their keys, which are the indices of the slots they are stored in:
+/// structures mirroring the pool can use the same keys to index their own buffers.
 #[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
-pub(crate) struct ObjectPool<T: Clone> {
+pub struct ObjectPool<T: Clone> {
     buffer: Vec<ReusableItem<T>>, // Pool of objects to be reused
     first_available: usize,       // the index of the first available item
+    reserved_count: usize,        // the number of items in use
+
+    // unique identifier of the pool, not persisted: loaded or cloned pools are treated as different pools
+    #[cfg_attr(feature = "serialization", serde(skip, default = "next_pool_id"))]
+    id: u32,
+}
+
+impl<T: Clone> Default for ObjectPool<T> {
+    fn default() -> Self {
+        Self {
+            buffer: Vec::new(),
+            first_available: 0,
+            reserved_count: 0,
+            id: next_pool_id(),
+        }
+    }
+}
+
+impl<T: Clone> Clone for ObjectPool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            first_available: self.first_available,
+            reserved_count: self.reserved_count,
+            id: next_pool_id(),
+        }
+    }
 }
 
 impl<
@@ -98,17 +162,17 @@ where
     fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
         match data {
             Object::List(mut list) => {
-                let first_available = match list.next_object()?.unwrap() {
-                    Object::Integer(i) => Ok(i.parse::<usize>().ok().unwrap()),
-                    _ => Err(bendy::decoding::Error::unexpected_token(
-                        "int field first_available",
-                        "Something else",
-                    )),
-                }?;
-                let buffer = Vec::decode_bencode_object(list.next_object()?.unwrap())?;
+                let first_available = usize::decode_bencode_object(next_list_item(
+                    &mut list,
+                    "int field first_available",
+                )?)?;
+                let buffer: Vec<ReusableItem<T>> =
+                    Vec::decode_bencode_object(next_list_item(&mut list, "list of pooled items")?)?;
                 Ok(Self {
                     first_available,
+                    reserved_count: buffer.iter().filter(|item| item.reserved).count(),
                     buffer,
+                    ..Default::default()
                 })
             }
             _ => Err(bendy::decoding::Error::unexpected_token(
@@ -119,12 +183,12 @@ where
     }
 }
 
-#[allow(dead_code)] // Object implemented for universal usage
 impl<T> ObjectPool<T>
 where
     T: Default + Clone,
 {
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
+    /// Creates an empty pool with space for at least the given number of items
+    pub fn with_capacity(capacity: usize) -> Self {
         ObjectPool {
             buffer: Vec::with_capacity(capacity),
             ..Default::default()
@@ -148,17 +212,56 @@ where
         }
     }
 
-    pub(crate) fn len(&self) -> usize {
+    /// The number of slots in the pool, including the ones not currently in use;
+    /// Every valid key is smaller, than this number
+    pub fn len(&self) -> usize {
         self.buffer.len()
     }
 
-    pub(crate) fn push(&mut self, item: T) -> usize {
+    /// True if the pool has no slots
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// The number of items currently in use
+    pub fn count(&self) -> usize {
+        self.reserved_count
+    }
+
+    /// The number of slots the pool can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Reserves space for at least the given number of additional slots
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
+
+    /// Reserves space for at least the given number of additional slots,
+    /// failing instead of aborting should the allocation fail
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.buffer.try_reserve(additional)
+    }
+
+    /// Removes every item from the pool, keeping the space allocated for them
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.first_available = 0;
+        self.reserved_count = 0;
+    }
+
+    /// Stores the given item in the pool
+    /// returns with the key of the item
+    pub fn push(&mut self, item: T) -> usize {
         let key = self.allocate();
         *self.get_mut(key) = item;
         key
     }
 
-    pub(crate) fn allocate(&mut self) -> usize {
+    /// Reserves a slot holding the default value of the item
+    /// returns with the key of the slot
+    pub fn allocate(&mut self) -> usize {
         let key = if self.check_first_available() {
             self.buffer[self.first_available].reserved = true;
             self.first_available
@@ -166,9 +269,11 @@ where
             // reserve place for additional items
             let x = self.buffer.len().max(10) as f32;
 
-            // reserve less additional items the more the size of the buffer
-            self.buffer
-                .reserve(((100. * x.log10().powf(2.)) / x) as usize);
+            // reserve less additional items the more the size of the buffer;
+            // the space reserved up front by `try_reserve` is used should that not be possible
+            let _ = self
+                .buffer
+                .try_reserve(((100. * x.log10().powf(2.)) / x) as usize);
 
             // mark Node as reserved and return with the key
             self.buffer.push(ReusableItem {
@@ -181,38 +286,154 @@ where
         if self.is_next_available() {
             self.first_available += 1;
         }
+        self.reserved_count += 1;
         key
     }
 
-    pub(crate) fn pop(&mut self, key: usize) -> Option<T> {
+    /// Takes the item out of the pool, freeing up its slot
+    /// returns with the item, should the key be valid
+    pub fn pop(&mut self, key: usize) -> Option<T> {
         if key < self.buffer.len() && self.buffer[key].reserved {
             self.buffer[key].reserved = false;
             self.first_available = self.first_available.min(key);
+            self.reserved_count -= 1;
             Some(std::mem::take(&mut self.buffer[key].item))
         } else {
             None
         }
     }
 
-    pub(crate) fn free(&mut self, key: usize) -> bool {
+    /// Frees up the slot of the given key, keeping its contents until the slot is reused
+    /// returns true if the key was valid
+    pub fn free(&mut self, key: usize) -> bool {
         if key < self.buffer.len() && self.buffer[key].reserved {
             self.buffer[key].reserved = false;
             self.first_available = self.first_available.min(key);
+            self.reserved_count -= 1;
             true
         } else {
             false
         }
     }
 
-    pub(crate) fn get(&self, key: usize) -> &T {
+    /// True if the given key points to an item in use
+    pub fn key_is_valid(&self, key: usize) -> bool {
+        key < self.buffer.len() && self.buffer[key].reserved
+    }
+
+    /// Binds the given key to this pool, so it can be safely stored outside of it
+    pub fn brand(&self, key: usize) -> BrandedKey {
+        BrandedKey {
+            pool_id: self.id,
+            key: key as PoolKey,
+        }
+    }
+
+    /// Provides the key inside this pool for the given branded key, if it was branded by this pool.
+    /// Keys coming from a different pool trigger a debug assertion
+    pub fn unbrand(&self, key: &BrandedKey) -> Option<usize> {
+        debug_assert!(
+            key.pool_id == self.id,
+            "Key from object pool {} used with object pool {}",
+            key.pool_id,
+            self.id
+        );
+        if key.pool_id == self.id {
+            Some(key.key as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Provides a reference to the item under the given key
+    /// The key is expected to be valid, which is checked only in debug builds
+    pub fn get(&self, key: usize) -> &T {
         debug_assert!(key < self.buffer.len() && self.buffer[key].reserved);
         &self.buffer[key].item
     }
 
-    pub(crate) fn get_mut(&mut self, key: usize) -> &mut T {
+    /// Provides a mutable reference to the item under the given key
+    /// The key is expected to be valid, which is checked only in debug builds
+    pub fn get_mut(&mut self, key: usize) -> &mut T {
         debug_assert!(key < self.buffer.len() && self.buffer[key].reserved);
         &mut self.buffer[key].item
     }
+
+    /// Iterates over the items in use, along with their keys, in the order of the keys
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.buffer
+            .iter()
+            .enumerate()
+            .filter(|(_, reusable)| reusable.reserved)
+            .map(|(key, reusable)| (key, &reusable.item))
+    }
+
+    /// Creates a pool with the same keys, converting every item in use with the given function;
+    /// free slots are filled with default values
+    /// * `convert` - Called with every item in use
+    pub fn map<U>(&self, mut convert: impl FnMut(&T) -> U) -> ObjectPool<U>
+    where
+        U: Default + Clone,
+    {
+        match self.try_map(|item| Ok::<U, std::convert::Infallible>(convert(item))) {
+            Ok(pool) => pool,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Creates a pool with the same keys, converting every item in use with the given function;
+    /// free slots are filled with default values. Stops at the first error of the conversion.
+    /// * `convert` - Called with every item in use
+    pub fn try_map<U, E>(
+        &self,
+        mut convert: impl FnMut(&T) -> Result<U, E>,
+    ) -> Result<ObjectPool<U>, E>
+    where
+        U: Default + Clone,
+    {
+        let mut buffer = Vec::with_capacity(self.buffer.len());
+        for reusable in self.buffer.iter() {
+            buffer.push(ReusableItem {
+                reserved: reusable.reserved,
+                item: if reusable.reserved {
+                    convert(&reusable.item)?
+                } else {
+                    U::default()
+                },
+            });
+        }
+        Ok(ObjectPool {
+            buffer,
+            first_available: self.first_available,
+            reserved_count: self.reserved_count,
+            id: next_pool_id(),
+        })
+    }
+
+    /// Moves every slot of the given pool behind the slots of this pool, keeping the free slots free.
+    /// returns with the offset the keys of the given pool are shifted by: the item under `key`
+    /// in the given pool is under `key + offset` in this pool afterwards
+    pub fn append(&mut self, other: ObjectPool<T>) -> usize {
+        let offset = self.buffer.len();
+        if self.first_available >= offset {
+            self.first_available = offset + other.first_available;
+        }
+        self.reserved_count += other.reserved_count;
+        self.buffer.extend(other.buffer);
+        offset
+    }
+
+    /// Frees up the slot of every item the given function returns false for
+    /// * `keep` - Called with the key and the item for every item in use
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &mut T) -> bool) {
+        for (key, reusable) in self.buffer.iter_mut().enumerate() {
+            if reusable.reserved && !keep(key, &mut reusable.item) {
+                reusable.reserved = false;
+                self.first_available = self.first_available.min(key);
+                self.reserved_count -= 1;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -256,4 +477,77 @@ mod object_pool_tests {
         pool.push(test_value * 3.);
         debug_assert!(*pool.get(key_1) == test_value * 3.); // the original key is reused to hold the latest value
     }
+
+    #[test]
+    fn test_reserve_capacity() {
+        let mut pool = ObjectPool::<f32>::with_capacity(3);
+        assert!(pool.is_empty());
+        pool.reserve(20);
+        assert!(pool.capacity() >= 20);
+        pool.push(5.);
+        assert!(pool.len() == 1);
+    }
+
+    #[test]
+    fn test_iter_retain() {
+        let mut pool = ObjectPool::<f32>::with_capacity(3);
+        let key_1 = pool.push(1.);
+        let key_2 = pool.push(2.);
+        let key_3 = pool.push(3.);
+        pool.free(key_2);
+        assert!(pool.iter().collect::<Vec<_>>() == vec![(key_1, &1.), (key_3, &3.)]);
+
+        let mut freed_keys = Vec::new();
+        pool.retain(|key, item| {
+            if *item < 2. {
+                *item *= 10.;
+                true
+            } else {
+                freed_keys.push(key);
+                false
+            }
+        });
+        assert!(freed_keys == vec![key_3]);
+        assert!(pool.count() == 1);
+        assert!(pool.iter().collect::<Vec<_>>() == vec![(key_1, &10.)]);
+        assert!(!pool.key_is_valid(key_3));
+        assert!(pool.push(4.) == key_2); // freed slots are reused
+    }
+
+    #[test]
+    fn test_append() {
+        let mut pool = ObjectPool::<f32>::with_capacity(3);
+        pool.push(1.);
+        let mut other_pool = ObjectPool::<f32>::with_capacity(3);
+        let other_key_1 = other_pool.push(2.);
+        let other_key_2 = other_pool.push(3.);
+        other_pool.free(other_key_1);
+
+        let offset = pool.append(other_pool);
+        assert!(1 == offset);
+        assert!(2 == pool.count());
+        assert!(*pool.get(other_key_2 + offset) == 3.);
+        assert!(!pool.key_is_valid(other_key_1 + offset));
+        assert!(pool.push(4.) == other_key_1 + offset); // free slots of the appended pool are reused
+    }
+
+    #[test]
+    fn test_branded_key_roundtrip() {
+        let mut pool = ObjectPool::<f32>::with_capacity(3);
+        let key = pool.push(5.);
+        let branded_key = pool.brand(key);
+        assert!(pool.unbrand(&branded_key) == Some(key));
+        assert!(pool.clone().id != pool.id);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "used with object pool")]
+    fn test_branded_key_from_other_pool() {
+        let mut pool = ObjectPool::<f32>::with_capacity(3);
+        let other_pool = ObjectPool::<f32>::with_capacity(3);
+        let key = pool.push(5.);
+        let branded_key = pool.brand(key);
+        other_pool.unbrand(&branded_key);
+    }
 }
@@ -0,0 +1,269 @@
+use crate::object_pool::{key_might_be_valid, PoolKey};
+use crate::octree::{types::NodeContent, Cube, LightLevel, Octant, Octree, VoxelData};
+use std::{any::Any, collections::HashMap};
+
+/// A summary of the voxels inside a node, e.g. the number of voxels or their dominant material.
+/// A tree keeps the summary selected by `Octree::set_aggregate` up to date for each of its nodes,
+/// so renderers and simulations can decide based on it without visiting the voxels of the node.
+/// Empty voxels are not summarized: an empty node has the default summary. Merging the summaries
+/// of the parts of a node is expected to give the same summary regardless of how the node is split,
+/// as the tree merges and splits nodes without recalculating the summaries of the voxels.
+pub trait Aggregate<T>: Clone + Default + Send + Sync + 'static {
+    /// The summary of the given number of voxels, each equal to the given one
+    fn of_voxels(voxel: &T, count: u64) -> Self;
+
+    /// Adds the summary of another part of the node to this one
+    fn merge(&mut self, other: &Self);
+}
+
+/// The number of voxels which are not empty
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OccupancyCount(pub u64);
+
+impl<T: VoxelData> Aggregate<T> for OccupancyCount {
+    fn of_voxels(_voxel: &T, count: u64) -> Self {
+        OccupancyCount(count)
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.0 += other.0;
+    }
+}
+
+/// The number of voxels of each material, the material being the user data of the voxels
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DominantMaterial {
+    counts: HashMap<u32, u64>,
+}
+
+impl DominantMaterial {
+    /// The material of the most voxels, the smallest one of them on a tie; None for empty nodes
+    pub fn material(&self) -> Option<u32> {
+        self.counts
+            .iter()
+            .max_by_key(|(material, count)| (**count, std::cmp::Reverse(**material)))
+            .map(|(material, _)| *material)
+    }
+
+    /// The number of voxels of the given material
+    pub fn count_of(&self, material: u32) -> u64 {
+        self.counts.get(&material).copied().unwrap_or(0)
+    }
+}
+
+impl<T: VoxelData> Aggregate<T> for DominantMaterial {
+    fn of_voxels(voxel: &T, count: u64) -> Self {
+        DominantMaterial {
+            counts: HashMap::from([(voxel.user_data(), count)]),
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (material, count) in other.counts.iter() {
+            *self.counts.entry(*material).or_default() += count;
+        }
+    }
+}
+
+/// The smallest and the largest density of the voxels, the density being the alpha of their albedo;
+/// None for empty nodes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DensityRange(pub Option<(u8, u8)>);
+
+impl<T: VoxelData> Aggregate<T> for DensityRange {
+    fn of_voxels(voxel: &T, _count: u64) -> Self {
+        let density = voxel.albedo()[3];
+        DensityRange(Some((density, density)))
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.0 = match (self.0, other.0) {
+            (Some((min, max)), Some((other_min, other_max))) => {
+                Some((min.min(other_min), max.max(other_max)))
+            }
+            (range, None) | (None, range) => range,
+        };
+    }
+}
+
+/// The sum of the light levels of the voxels, for trees of light levels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LightSum {
+    pub sky: u64,
+    pub block: u64,
+}
+
+impl Aggregate<LightLevel> for LightSum {
+    fn of_voxels(voxel: &LightLevel, count: u64) -> Self {
+        LightSum {
+            sky: voxel.sky as u64 * count,
+            block: voxel.block as u64 * count,
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.sky += other.sky;
+        self.block += other.block;
+    }
+}
+
+/// The summaries of the nodes of a tree, regardless of the type of the summary
+pub(in crate::octree) trait AggregateStore<T: Default + Clone + VoxelData, const DIM: usize>:
+    Send + Sync
+{
+    fn as_any(&self) -> &dyn Any;
+
+    /// Recalculates the summary of the given node from its content and the summaries of its children
+    fn update_node(&mut self, tree: &Octree<T, DIM>, node: usize, bounds: &Cube);
+}
+
+/// The summaries of the nodes of a tree, indexed by the keys of the nodes
+struct AggregateLayer<A> {
+    summaries: Vec<A>,
+}
+
+impl<A> AggregateLayer<A> {
+    fn of_uniform<T: VoxelData>(data: &T, bounds: &Cube) -> A
+    where
+        A: Aggregate<T>,
+    {
+        if data.is_empty() {
+            A::default()
+        } else {
+            A::of_voxels(data, (bounds.size as u64).pow(3))
+        }
+    }
+}
+
+impl<T, A, const DIM: usize> AggregateStore<T, DIM> for AggregateLayer<A>
+where
+    T: Default + Clone + VoxelData,
+    A: Aggregate<T>,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn update_node(&mut self, tree: &Octree<T, DIM>, node: usize, bounds: &Cube) {
+        if self.summaries.len() < tree.nodes.len() {
+            self.summaries.resize(tree.nodes.len(), A::default());
+        }
+        let summary = match tree.nodes.get(node) {
+            NodeContent::Nothing => A::default(),
+            NodeContent::UniformLeaf(data) => Self::of_uniform(data, bounds),
+            NodeContent::Leaf(brick) => {
+                let cell_voxels = ((bounds.size / DIM as u32).max(1) as u64).pow(3);
+                let mut summary = A::default();
+                for voxel in tree
+                    .bricks
+                    .get(*brick as usize)
+                    .0
+                    .iter()
+                    .flatten()
+                    .flatten()
+                {
+                    if !voxel.is_empty() {
+                        summary.merge(&A::of_voxels(voxel, cell_voxels));
+                    }
+                }
+                summary
+            }
+            NodeContent::Internal(_) => {
+                let mut summary = A::default();
+                for octant in Octant::iter() {
+                    let child = tree.node_children[node][octant];
+                    if !key_might_be_valid(child) || !tree.nodes.key_is_valid(child as usize) {
+                        continue;
+                    }
+                    match tree.nodes.get(child as usize) {
+                        NodeContent::Nothing => continue,
+                        // Splitting a leaf creates uniform children beside the edited one,
+                        // so the summaries of those are not kept up to date by the edits
+                        NodeContent::UniformLeaf(data) => {
+                            self.summaries[child as usize] =
+                                Self::of_uniform(data, &bounds.child_bounds_for(octant));
+                        }
+                        _ => {}
+                    }
+                    summary.merge(&self.summaries[child as usize]);
+                }
+                summary
+            }
+        };
+        self.summaries[node] = summary;
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Selects the summary kept for every node of the tree, replacing the one selected before.
+    /// It is calculated for the whole tree, then kept up to date as the tree is edited;
+    /// changes made through `get_mut` are not tracked, see `refresh_aggregate`
+    pub fn set_aggregate<A: Aggregate<T>>(&mut self) {
+        self.aggregates = Some(Box::new(AggregateLayer::<A> {
+            summaries: Vec::new(),
+        }));
+        self.refresh_aggregate();
+    }
+
+    /// Stops keeping the summary selected by `set_aggregate`
+    pub fn remove_aggregate(&mut self) {
+        self.aggregates = None;
+    }
+
+    /// Recalculates the summary of every node, e.g. after changing voxels through `get_mut`
+    pub fn refresh_aggregate(&mut self) {
+        self.update_aggregates_below(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            Cube::root_bounds(self.octree_size),
+        );
+    }
+
+    /// The summary of the whole tree, None unless `A` is the summary selected by `set_aggregate`
+    pub fn aggregate<A: Aggregate<T>>(&self) -> Option<&A> {
+        self.aggregate_of(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
+    }
+
+    /// The summary of the given node, None unless `A` is the summary selected by `set_aggregate`
+    pub(in crate::octree) fn aggregate_of<A: Aggregate<T>>(&self, node: usize) -> Option<&A> {
+        self.aggregates
+            .as_ref()?
+            .as_any()
+            .downcast_ref::<AggregateLayer<A>>()?
+            .summaries
+            .get(node)
+    }
+
+    /// Recalculates the summary of the given node, should the tree keep one, expecting
+    /// the summaries of its children to be up to date
+    pub(in crate::octree) fn update_aggregate(&mut self, node: PoolKey, bounds: &Cube) {
+        if let Some(mut aggregates) = self.aggregates.take() {
+            aggregates.update_node(self, node as usize, bounds);
+            self.aggregates = Some(aggregates);
+        }
+    }
+
+    /// Recalculates the summaries of the given node and all of its descendants
+    pub(in crate::octree) fn update_aggregates_below(&mut self, node: PoolKey, bounds: Cube) {
+        let Some(mut aggregates) = self.aggregates.take() else {
+            return;
+        };
+        // Nodes in depth-first order, so children are updated before their parents in reverse
+        let mut order = Vec::new();
+        let mut node_stack = vec![(node, bounds)];
+        while let Some((node_key, node_bounds)) = node_stack.pop() {
+            order.push((node_key, node_bounds));
+            if let NodeContent::Internal(_) = self.nodes.get(node_key as usize) {
+                for octant in Octant::iter() {
+                    let child = self.node_children[node_key as usize][octant];
+                    if key_might_be_valid(child) {
+                        node_stack.push((child, node_bounds.child_bounds_for(octant)));
+                    }
+                }
+            }
+        }
+        for (node_key, node_bounds) in order.into_iter().rev() {
+            aggregates.update_node(self, node_key as usize, &node_bounds);
+        }
+        self.aggregates = Some(aggregates);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::octree::{
+    types::{OctreeEdit, OctreeError},
+    Octree, VoxelData,
+};
+
+/// An animated voxel scene, e.g. imported from MagicaVoxel or recorded for a replay:
+/// every keyframe is stored as the edits turning the base tree into it, see `Octree::delta_to`,
+/// so any of them can be reconstructed on its own without playing the ones before it.
+pub struct VoxelAnimation<T: Default + Clone + VoxelData, const DIM: usize = 1> {
+    pub(in crate::octree) base: Octree<T, DIM>,
+    pub(in crate::octree) keyframes: Vec<Vec<OctreeEdit<T>>>,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelAnimation<T, DIM> {
+    /// Creates an animation without any keyframes, the given tree being the base of each of them
+    pub fn new(base: Octree<T, DIM>) -> Self {
+        Self {
+            base,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// The tree every keyframe is stored relative to
+    pub fn base(&self) -> &Octree<T, DIM> {
+        &self.base
+    }
+
+    /// The number of keyframes in the animation
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// True if the animation has no keyframes
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Adds the given tree as the next keyframe of the animation, storing only its differences from the base
+    pub fn push_keyframe(&mut self, frame: &Octree<T, DIM>) -> Result<(), OctreeError> {
+        self.keyframes.push(self.base.delta_to(frame)?);
+        Ok(())
+    }
+
+    /// The edits turning the base tree into the keyframe with the given index, if there is such a keyframe
+    pub fn keyframe_delta(&self, index: usize) -> Option<&[OctreeEdit<T>]> {
+        self.keyframes.get(index).map(|edits| edits.as_slice())
+    }
+
+    /// Reconstructs the keyframe with the given index
+    pub fn frame(&self, index: usize) -> Result<Octree<T, DIM>, OctreeError> {
+        let edits = self
+            .keyframes
+            .get(index)
+            .ok_or(OctreeError::InvalidKeyframe(index))?;
+        let mut frame = Octree::new(self.base.octree_size)?.with_voxel_size(self.base.voxel_size);
+        frame.auto_simplify = self.base.auto_simplify;
+        frame.nodes = self.base.nodes.clone();
+        frame.node_children = self.base.node_children.clone();
+        frame.bricks = self.base.bricks.clone();
+        for edit in edits {
+            frame.apply_edit(edit.clone())?;
+        }
+        Ok(frame)
+    }
+}
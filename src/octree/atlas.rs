@@ -0,0 +1,241 @@
+use crate::object_pool::{key_might_be_valid, PoolKey};
+use crate::octree::{
+    detail::{bound_contains, child_octant_for},
+    types::{NodeContent, OctreeError},
+    Cube, Octree, V3c, VoxelData,
+};
+use std::collections::HashMap;
+
+/// How the color of a voxel is stored in a texel of the atlas
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AtlasTexelFormat {
+    /// The albedo of the voxel in RGBA8
+    #[default]
+    Rgba8,
+    /// The linear HDR color of the voxel in RGBA16F, with little endian half precision channels
+    #[cfg(feature = "half")]
+    Rgba16Float,
+}
+
+impl AtlasTexelFormat {
+    /// The number of bytes a voxel takes up in the atlas
+    pub fn bytes_per_texel(&self) -> usize {
+        match self {
+            AtlasTexelFormat::Rgba8 => 4,
+            #[cfg(feature = "half")]
+            AtlasTexelFormat::Rgba16Float => 8,
+        }
+    }
+
+    /// Writes the color of the given voxel into the given texel bytes
+    fn write_texel<T: VoxelData>(&self, texel: &mut [u8], voxel: &T) {
+        match self {
+            AtlasTexelFormat::Rgba8 => texel.copy_from_slice(&voxel.albedo()),
+            #[cfg(feature = "half")]
+            AtlasTexelFormat::Rgba16Float => {
+                for (channel_bytes, channel) in
+                    texel.chunks_exact_mut(2).zip(voxel.albedo_hdr().iter())
+                {
+                    channel_bytes.copy_from_slice(&half::f16::from_f32(*channel).to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Describes where the bricks of an octree are stored inside a 3D texture atlas.
+/// The atlas is made up of tiles of DIM*DIM*DIM texels, each storing the colors of one brick in the texel format;
+/// texels are laid out x first, then y, then z, as 3D textures are uploaded.
+/// Uniform leaves have no brick, so they have no tile in the atlas either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atlas3dLayout {
+    /// The number of tiles along each axis of the atlas
+    pub atlas_dims: V3c<u32>,
+    /// The number of texels along each axis of a tile
+    pub tile_size: u32,
+    /// How the colors of the voxels are stored in the texels
+    pub texel_format: AtlasTexelFormat,
+    pub(in crate::octree) tiles: HashMap<PoolKey, V3c<u32>>,
+    pub(in crate::octree) free_tiles: Vec<V3c<u32>>,
+}
+
+impl Atlas3dLayout {
+    /// The size of the atlas in texels
+    pub fn texel_dims(&self) -> V3c<u32> {
+        self.atlas_dims * self.tile_size
+    }
+
+    /// The tile coordinates of the given brick inside the atlas, if it is packed
+    pub fn tile_of(&self, brick: PoolKey) -> Option<V3c<u32>> {
+        self.tiles.get(&brick).copied()
+    }
+
+    /// The number of bricks packed into the atlas
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Writes the voxels of the given brick into the given tile of the atlas bytes
+    fn write_tile<T: VoxelData, const DIM: usize>(
+        &self,
+        atlas: &mut [u8],
+        tile: &V3c<u32>,
+        voxels: &[[[T; DIM]; DIM]; DIM],
+    ) {
+        let texel_dims = self.texel_dims();
+        let bytes_per_texel = self.texel_format.bytes_per_texel();
+        let tile_start = *tile * self.tile_size;
+        for (x, plane) in voxels.iter().enumerate() {
+            for (y, row) in plane.iter().enumerate() {
+                for (z, voxel) in row.iter().enumerate() {
+                    let texel = tile_start + V3c::new(x as u32, y as u32, z as u32);
+                    let index = ((texel.z * texel_dims.y + texel.y) * texel_dims.x + texel.x)
+                        as usize
+                        * bytes_per_texel;
+                    self.texel_format
+                        .write_texel(&mut atlas[index..index + bytes_per_texel], voxel);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The key of the brick storing the voxel at the given position, if it is stored in a brick
+    pub fn brick_at(&self, position: &V3c<u32>) -> Option<PoolKey> {
+        let mut current_bounds = Cube::root_bounds(self.octree_size);
+        let mut current_node_key = Octree::<T, DIM>::ROOT_NODE_KEY as usize;
+        if !bound_contains(&current_bounds, position) {
+            return None;
+        }
+
+        loop {
+            match self.nodes.get(current_node_key) {
+                NodeContent::Leaf(brick) => return Some(*brick),
+                NodeContent::Internal(_) => {
+                    let child_octant_at_position = child_octant_for(&current_bounds, position);
+                    let child_at_position =
+                        self.node_children[current_node_key][child_octant_at_position];
+                    if !key_might_be_valid(child_at_position) {
+                        return None;
+                    }
+                    current_node_key = child_at_position as usize;
+                    current_bounds =
+                        Cube::child_bounds_for(&current_bounds, child_octant_at_position);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Packs every brick of the tree into a 3D texture atlas storing the albedo of the voxels in RGBA8,
+    /// returning the layout of the atlas along with its bytes. Afterwards the atlas can be kept up to date
+    /// with `repack_dirty_bricks`.
+    /// * `atlas_dims` - The number of tiles along each axis of the atlas
+    pub fn pack_bricks_into_atlas(
+        &mut self,
+        atlas_dims: V3c<u32>,
+    ) -> Result<(Atlas3dLayout, Vec<u8>), OctreeError> {
+        self.pack_bricks_into_atlas_with_format(atlas_dims, AtlasTexelFormat::Rgba8)
+    }
+
+    /// Packs every brick of the tree into a 3D texture atlas storing the colors of the voxels
+    /// in the given texel format, see `pack_bricks_into_atlas`
+    pub fn pack_bricks_into_atlas_with_format(
+        &mut self,
+        atlas_dims: V3c<u32>,
+        texel_format: AtlasTexelFormat,
+    ) -> Result<(Atlas3dLayout, Vec<u8>), OctreeError> {
+        let tile_count = (atlas_dims.x * atlas_dims.y * atlas_dims.z) as usize;
+        if tile_count < self.bricks.count() {
+            return Err(OctreeError::AtlasFull {
+                tiles: tile_count,
+                required: self.bricks.count(),
+            });
+        }
+
+        let mut layout = Atlas3dLayout {
+            atlas_dims,
+            tile_size: DIM as u32,
+            texel_format,
+            tiles: HashMap::new(),
+            // Stored in reverse, so tiles are taken from the start of the atlas first
+            free_tiles: (0..tile_count as u32)
+                .rev()
+                .map(|i| {
+                    V3c::new(
+                        i % atlas_dims.x,
+                        i / atlas_dims.x % atlas_dims.y,
+                        i / (atlas_dims.x * atlas_dims.y),
+                    )
+                })
+                .collect(),
+        };
+        let mut atlas = vec![0; tile_count * DIM.pow(3) * texel_format.bytes_per_texel()];
+        for (key, brick) in self.bricks.iter() {
+            let tile = layout.free_tiles.pop().unwrap();
+            layout.write_tile(&mut atlas, &tile, &brick.0);
+            layout.tiles.insert(key as PoolKey, tile);
+        }
+        self.dirty_bricks.clear();
+        Ok((layout, atlas))
+    }
+
+    /// Updates the atlas with the bricks changed since it was last packed: tiles of freed bricks
+    /// are released, new and modified bricks are (re)written. Returns the tiles which were written,
+    /// so only those need to be uploaded again. The tree tracks a single set of changed bricks,
+    /// so it is to be used with one atlas at a time.
+    /// * `layout` - The layout of the atlas, as returned by `pack_bricks_into_atlas`
+    /// * `atlas` - The bytes of the atlas to update
+    pub fn repack_dirty_bricks(
+        &mut self,
+        layout: &mut Atlas3dLayout,
+        atlas: &mut [u8],
+    ) -> Result<Vec<V3c<u32>>, OctreeError> {
+        let freed = layout
+            .tiles
+            .keys()
+            .filter(|brick| !self.bricks.key_is_valid(**brick as usize))
+            .copied()
+            .collect::<Vec<_>>();
+        for brick in freed {
+            layout.free_tiles.push(layout.tiles.remove(&brick).unwrap());
+        }
+
+        let required = self
+            .dirty_bricks
+            .iter()
+            .filter(|brick| {
+                self.bricks.key_is_valid(**brick as usize) && !layout.tiles.contains_key(brick)
+            })
+            .count();
+        if layout.free_tiles.len() < required {
+            return Err(OctreeError::AtlasFull {
+                tiles: layout.tiles.len() + layout.free_tiles.len(),
+                required: layout.tiles.len() + required,
+            });
+        }
+
+        let mut written = Vec::new();
+        for brick in self.dirty_bricks.drain() {
+            if !self.bricks.key_is_valid(brick as usize) {
+                continue;
+            }
+            let tile = match layout.tiles.get(&brick) {
+                Some(tile) => *tile,
+                None => {
+                    let tile = layout.free_tiles.pop().unwrap();
+                    layout.tiles.insert(brick, tile);
+                    tile
+                }
+            };
+            layout.write_tile(atlas, &tile, &self.bricks.get(brick as usize).0);
+            written.push(tile);
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::octree::{detail::bound_contains, types::OctreeError, Cube, Octree, V3c, VoxelData};
+
+/// Per-voxel data kept beside the voxels of a tree, so it doesn't need to be part of every voxel,
+/// e.g. motion vectors for temporal reprojection, or identifiers of gameplay objects
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct VoxelAux {
+    /// The movement of the voxel since the previous frame, in voxels
+    pub motion: V3c<f32>,
+    /// An identifier of the voxel chosen by the user, 0 if not set
+    pub id: u32,
+}
+
+/// The auxiliary data is stored in a separate tree with the bricks of the same size, so regions
+/// without it or with equal values in them are kept in uniform nodes
+impl VoxelData for VoxelAux {
+    fn new(_r: u8, _g: u8, _b: u8, _a: u8, user_data: u32) -> Self {
+        Self {
+            motion: V3c::unit(0.),
+            id: user_data,
+        }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        [0; 4]
+    }
+    fn user_data(&self) -> u32 {
+        self.id
+    }
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The auxiliary data stored for the given voxel, None if it was not set
+    pub fn auxiliary_at(&self, position: &V3c<u32>) -> Option<&VoxelAux> {
+        self.auxiliary
+            .as_ref()?
+            .get(position)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Stores the given auxiliary data for the voxel at the given position; it is kept separate
+    /// from the data of the voxel, so it stays until it is overwritten or cleared
+    pub fn set_auxiliary(
+        &mut self,
+        position: &V3c<u32>,
+        value: VoxelAux,
+    ) -> Result<(), OctreeError> {
+        if !bound_contains(&Cube::root_bounds(self.octree_size), position) {
+            return Err(OctreeError::InvalidPosition {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            });
+        }
+        if value.is_empty() {
+            return self.clear_auxiliary(position);
+        }
+        if self.auxiliary.is_none() {
+            self.auxiliary = Some(Box::new(Octree::new(self.octree_size)?));
+        }
+        self.auxiliary.as_mut().unwrap().insert(position, value)
+    }
+
+    /// Removes the auxiliary data stored for the voxel at the given position
+    pub fn clear_auxiliary(&mut self, position: &V3c<u32>) -> Result<(), OctreeError> {
+        match self.auxiliary.as_mut() {
+            Some(auxiliary) => auxiliary.clear(position),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the auxiliary data of every voxel, freeing the memory used by it
+    pub fn drop_auxiliary(&mut self) {
+        self.auxiliary = None;
+    }
+}
@@ -0,0 +1,203 @@
+use crate::octree::{types::OctreeError, Aabb, Cube, Octree, V3c, VoxelData};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A spatial hash of the boxes of entities, e.g. the characters and projectiles of a game, along with the regions
+/// of a tree holding voxels, for the broad phase of collisions between them without a separate spatial index:
+/// entities near a region of the tree, and regions with voxels near an entity are found through the cells
+/// of the hash, which are on the grid of the tree. The regions holding voxels are kept up to date with the edits
+/// of the tree through `update_voxels`. The boxes of the entities are in world units, see `Octree::with_voxel_size`.
+pub struct VoxelBroadPhase<E> {
+    cell_size: u32,
+    tree_size: u32,
+    voxel_size: f32,
+    /// The boxes of the registered entities, in world units
+    entities: HashMap<E, Aabb>,
+    /// The entities with boxes overlapping each cell; cells may be outside of the tree
+    entity_cells: HashMap<V3c<i32>, Vec<E>>,
+    /// The cells of the tree with any voxels inside them, by their min position
+    voxel_cells: HashSet<V3c<u32>>,
+    /// The number of the first change of the tree not taken over yet, see `update_voxels`
+    changes_seen: u64,
+}
+
+impl<E: Copy + Eq + Hash> VoxelBroadPhase<E> {
+    /// Creates a broad phase without entities for the given tree, with the regions holding voxels derived from it.
+    /// It can be kept up to date with the changes of the tree through `update_voxels`;
+    /// the changes of the tree are only tracked from the first broad phase created for it on.
+    /// Fails with `OctreeError::InvalidNodeSize` should the cells not match the size of a node of the tree
+    /// * `cell_size` - The size of the cells of the hash in voxels, must be `DIM * (2^x)`, not larger than the tree
+    pub fn new<T, const DIM: usize>(
+        tree: &Octree<T, DIM>,
+        cell_size: u32,
+    ) -> Result<Self, OctreeError>
+    where
+        T: Default + PartialEq + Clone + VoxelData,
+    {
+        if Octree::<T, DIM>::is_size_inadequate(cell_size) || cell_size > tree.octree_size {
+            return Err(OctreeError::InvalidNodeSize(cell_size));
+        }
+        tree.changed_regions.start_recording();
+        let mut broad_phase = Self {
+            cell_size,
+            tree_size: tree.octree_size,
+            voxel_size: tree.voxel_size,
+            entities: HashMap::new(),
+            entity_cells: HashMap::new(),
+            voxel_cells: HashSet::new(),
+            changes_seen: tree.changed_regions.cursor(),
+        };
+        broad_phase.update_region(tree, &Cube::root_bounds(tree.octree_size));
+        Ok(broad_phase)
+    }
+
+    /// The size of the cells of the hash in voxels
+    pub fn cell_size(&self) -> u32 {
+        self.cell_size
+    }
+
+    /// Registers the given entity with the given box, or moves it to the box should it be registered already
+    pub fn insert(&mut self, entity: E, aabb: Aabb) {
+        self.remove(&entity);
+        for cell in self.cells_of(&aabb) {
+            self.entity_cells.entry(cell).or_default().push(entity);
+        }
+        self.entities.insert(entity, aabb);
+    }
+
+    /// Unregisters the given entity, providing its box, should it be registered
+    pub fn remove(&mut self, entity: &E) -> Option<Aabb> {
+        let aabb = self.entities.remove(entity)?;
+        for cell in self.cells_of(&aabb) {
+            if let Some(entities) = self.entity_cells.get_mut(&cell) {
+                entities.retain(|registered| registered != entity);
+                if entities.is_empty() {
+                    self.entity_cells.remove(&cell);
+                }
+            }
+        }
+        Some(aabb)
+    }
+
+    /// The box of the given entity, should it be registered
+    pub fn get(&self, entity: &E) -> Option<&Aabb> {
+        self.entities.get(entity)
+    }
+
+    /// The entities with their boxes overlapping the given region of the tree, each listed once
+    pub fn entities_near(&self, region: &Cube) -> Vec<E> {
+        let region_aabb = Aabb::new(
+            V3c::<f32>::from(region.min_position) * self.voxel_size,
+            V3c::<f32>::from(region.max_position()) * self.voxel_size,
+        );
+        let mut listed = HashSet::new();
+        self.cells_of(&region_aabb)
+            .filter_map(|cell| self.entity_cells.get(&cell))
+            .flatten()
+            .filter(|entity| self.entities[*entity].intersects(&region_aabb))
+            .filter(|entity| listed.insert(**entity))
+            .copied()
+            .collect()
+    }
+
+    /// The cells of the tree holding voxels overlapped by the box of the given entity,
+    /// e.g. to collect the colliders of the voxels near the entity with `extract_colliders`.
+    /// Empty should the entity not be registered
+    pub fn voxel_regions_near(&self, entity: &E) -> Vec<Cube> {
+        let Some(aabb) = self.entities.get(entity) else {
+            return Vec::new();
+        };
+        self.cells_of(aabb)
+            .filter(|cell| {
+                let cells = (self.tree_size / self.cell_size) as i32;
+                (0..cells).contains(&cell.x)
+                    && (0..cells).contains(&cell.y)
+                    && (0..cells).contains(&cell.z)
+            })
+            .map(|cell| {
+                let min_position =
+                    V3c::new(cell.x as u32, cell.y as u32, cell.z as u32) * self.cell_size;
+                Cube::new(min_position, self.cell_size)
+            })
+            .filter(|region| {
+                self.voxel_cells.contains(&region.min_position)
+                    && aabb.intersects(&Aabb::new(
+                        V3c::<f32>::from(region.min_position) * self.voxel_size,
+                        V3c::<f32>::from(region.max_position()) * self.voxel_size,
+                    ))
+            })
+            .collect()
+    }
+
+    /// Updates the regions holding voxels with the regions of the given tree changed since the broad phase
+    /// was last updated. The tree shares its changed regions with its other broad phases and occupancy trees,
+    /// each taking over the changes it has not seen yet. Changes made through `get_mut` are not tracked.
+    /// Should the broad phase fall too far behind the changes of the tree, every region is derived again.
+    /// Should the size of the tree or of its voxels differ from the ones the broad phase was created with,
+    /// the entities are registered again into the cells too
+    pub fn update_voxels<T, const DIM: usize>(&mut self, tree: &Octree<T, DIM>)
+    where
+        T: Default + PartialEq + Clone + VoxelData,
+    {
+        let changed_regions = tree.changed_regions.since(self.changes_seen);
+        self.changes_seen = tree.changed_regions.cursor();
+        if self.tree_size != tree.octree_size || self.voxel_size != tree.voxel_size {
+            // Cells which were valid in the previous tree may be too small for the new one
+            self.cell_size = self.cell_size.clamp(DIM as u32, tree.octree_size);
+            self.tree_size = tree.octree_size;
+            self.voxel_size = tree.voxel_size;
+            self.voxel_cells.clear();
+            self.update_region(tree, &Cube::root_bounds(tree.octree_size));
+            self.entity_cells.clear();
+            for (entity, aabb) in std::mem::take(&mut self.entities) {
+                self.insert(entity, aabb);
+            }
+            return;
+        }
+        match changed_regions {
+            Some(regions) => {
+                for (min_position, size) in regions {
+                    self.update_region(tree, &Cube::new(min_position, size));
+                }
+            }
+            None => self.update_region(tree, &Cube::root_bounds(tree.octree_size)),
+        }
+    }
+
+    /// Derives whether the cells overlapping the given region of the tree hold any voxels
+    fn update_region<T, const DIM: usize>(&mut self, tree: &Octree<T, DIM>, region: &Cube)
+    where
+        T: Default + PartialEq + Clone + VoxelData,
+    {
+        let first_cell = region.min_position / self.cell_size;
+        let cell_count = region.size.div_ceil(self.cell_size);
+        for x in first_cell.x..(first_cell.x + cell_count) {
+            for y in first_cell.y..(first_cell.y + cell_count) {
+                for z in first_cell.z..(first_cell.z + cell_count) {
+                    let cell = Cube::new(V3c::new(x, y, z) * self.cell_size, self.cell_size);
+                    if tree.contains_any_in(&cell) {
+                        self.voxel_cells.insert(cell.min_position);
+                    } else {
+                        self.voxel_cells.remove(&cell.min_position);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The coordinates of the cells the given box in world units overlaps, boundaries included
+    fn cells_of(&self, aabb: &Aabb) -> impl Iterator<Item = V3c<i32>> {
+        let cell_world_size = self.cell_size as f32 * self.voxel_size;
+        let cell_at = |point: V3c<f32>| {
+            V3c::new(
+                (point.x / cell_world_size).floor() as i32,
+                (point.y / cell_world_size).floor() as i32,
+                (point.z / cell_world_size).floor() as i32,
+            )
+        };
+        let (min, max) = (cell_at(aabb.min), cell_at(aabb.max));
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| V3c::new(x, y, z)))
+        })
+    }
+}
@@ -1,61 +1,84 @@
-use crate::object_pool::ObjectPool;
-use crate::octree::types::{NodeChildren, NodeChildrenArray, NodeContent, Octree, VoxelData};
+use crate::object_pool::{key_might_be_valid, next_list_item, ObjectPool, PoolKey};
+#[cfg(feature = "world_dir")]
+use crate::octree::world_dir::{SavedChunk, WorldManifest, WORLD_MANIFEST_VERSION};
+use crate::octree::{
+    recorder::{EditRecorder, RecordedCall},
+    types::{
+        Brick, DefaultVoxelDataCodec, NodeChildren, NodeChildrenArray, NodeContent, Octree,
+        OctreeEdit, OctreeLoader, VoxelData, VoxelDataCodec, VoxelDataMigration,
+    },
+    SeamHandling, V3c, VoxelWorld,
+};
 use bendy::{
-    decoding::ListDecoder,
-    encoding::{Encoder, Error as BencodeError, SingleItemEncoder, ToBencode},
+    decoding::{FromBencode, Object},
+    encoding::{Error as BencodeError, SingleItemEncoder, ToBencode},
 };
+use std::collections::{HashMap, HashSet};
+
+/// Marks encoded trees with a versioned layout, trees saved before start with their `auto_simplify` flag instead
+const OCTREE_FORMAT_TAG: &str = "shocovox_octree";
 
-impl<'obj, 'ser, T: Clone + VoxelData, const DIM: usize> NodeContent<T, DIM> {
-    fn encode_single(data: &T, encoder: &mut Encoder) -> Result<(), BencodeError> {
-        let color = data.albedo();
-        encoder.emit(color[0])?;
-        encoder.emit(color[1])?;
-        encoder.emit(color[2])?;
-        encoder.emit(color[3])?;
-        encoder.emit(data.user_data())
+/// The version of the layout of encoded trees, to be increased whenever it changes.
+/// Trees saved without a version are read as version 0, which only differs in the missing tag and version
+const OCTREE_FORMAT_VERSION: u32 = 1;
+
+///####################################################################################
+/// Voxel data
+///####################################################################################
+impl<T: VoxelData> VoxelDataCodec<T> for DefaultVoxelDataCodec {
+    fn encode(&self, data: &T) -> Vec<u8> {
+        let mut bytes = data.albedo().to_vec();
+        bytes.extend_from_slice(&data.user_data().to_le_bytes());
+        bytes
     }
 
-    fn decode_single(list: &mut ListDecoder<'obj, 'ser>) -> Result<T, bendy::decoding::Error> {
-        let r = match list.next_object()?.unwrap() {
-            Object::Integer(i) => Ok(i.parse::<u8>().ok().unwrap()),
-            _ => Err(bendy::decoding::Error::unexpected_token(
-                "int field red color component",
-                "Something else",
-            )),
-        }?;
-        let g = match list.next_object()?.unwrap() {
-            Object::Integer(i) => Ok(i.parse::<u8>().ok().unwrap()),
-            _ => Err(bendy::decoding::Error::unexpected_token(
-                "int field green color component",
-                "Something else",
-            )),
-        }?;
-        let b = match list.next_object()?.unwrap() {
-            Object::Integer(i) => Ok(i.parse::<u8>().ok().unwrap()),
-            _ => Err(bendy::decoding::Error::unexpected_token(
-                "int field blue color component",
-                "Something else",
-            )),
-        }?;
-        let a = match list.next_object()?.unwrap() {
-            Object::Integer(i) => Ok(i.parse::<u8>().ok().unwrap()),
+    fn decode(&self, bytes: &[u8]) -> Option<T> {
+        if 8 != bytes.len() {
+            return None;
+        }
+        let user_data = u32::from_le_bytes(bytes[4..8].try_into().ok()?);
+        Some(T::new(bytes[0], bytes[1], bytes[2], bytes[3], user_data))
+    }
+}
+
+/// The bytes of a single voxel, as provided by a `VoxelDataCodec`
+#[derive(Default, Clone)]
+pub(in crate::octree) struct VoxelBytes(Vec<u8>);
+
+impl VoxelBytes {
+    fn decode<T>(
+        &self,
+        decode_voxel: &impl Fn(&[u8]) -> Option<T>,
+    ) -> Result<T, bendy::decoding::Error> {
+        decode_voxel(&self.0).ok_or_else(|| {
+            bendy::decoding::Error::malformed_content("voxel data rejected by the codec")
+        })
+    }
+}
+
+impl ToBencode for VoxelBytes {
+    const MAX_DEPTH: usize = 0;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        encoder.emit_bytes(&self.0)
+    }
+}
+
+impl FromBencode for VoxelBytes {
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        match data {
+            Object::Bytes(b) => Ok(VoxelBytes(b.to_vec())),
             _ => Err(bendy::decoding::Error::unexpected_token(
-                "int field alpha color component",
+                "The bytes of a voxel, which is a ByteString",
                 "Something else",
             )),
-        }?;
-        let user_data = match list.next_object()?.unwrap() {
-            Object::Integer(i) => i.parse::<u32>().ok().unwrap(),
-            _ => 0,
-        };
-        Ok(VoxelData::new(r, g, b, a, user_data))
+        }
     }
 }
 
-impl<T, const DIM: usize> ToBencode for NodeContent<T, DIM>
-where
-    T: Default + Clone + VoxelData,
-{
+///####################################################################################
+/// NodeContent
+///####################################################################################
+impl ToBencode for NodeContent<VoxelBytes> {
     const MAX_DEPTH: usize = 8;
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
         match self {
@@ -64,80 +87,61 @@ where
                 e.emit_str("##")?;
                 e.emit_int(*count)
             }),
-            NodeContent::Leaf(data) => encoder.emit_list(|e| {
+            NodeContent::Leaf(brick) => encoder.emit_list(|e| {
                 e.emit_str("###")?;
-                for x in data.iter().take(DIM) {
-                    for y in x.iter().take(DIM) {
-                        for z in y.iter().take(DIM) {
-                            NodeContent::<T, DIM>::encode_single(z, e)?;
-                        }
-                    }
-                }
-                Ok(())
+                e.emit_int(*brick)
+            }),
+            NodeContent::UniformLeaf(data) => encoder.emit_list(|e| {
+                e.emit_str("####")?;
+                e.emit(data)
             }),
         }
     }
 }
 
-use bendy::decoding::{FromBencode, Object};
-impl<T, const DIM: usize> FromBencode for NodeContent<T, DIM>
-where
-    T: PartialEq + Default + Clone + VoxelData,
-{
+impl FromBencode for NodeContent<VoxelBytes> {
     fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
         match data {
             Object::List(mut list) => {
-                let is_leaf = match list.next_object()?.unwrap() {
-                    Object::Bytes(b) => {
-                        match String::from_utf8(b.to_vec())
-                            .unwrap_or("".to_string())
-                            .as_str()
-                        {
-                            "##" => {
-                                // The content is an internal Node
-                                Ok(false)
-                            }
-                            "###" => {
-                                // The content is a leaf
-                                Ok(true)
-                            }
-                            misc => Err(bendy::decoding::Error::unexpected_token(
-                                "A NodeContent Identifier string, which is either # or ##",
-                                "The string ".to_owned() + misc,
-                            )),
-                        }
-                    }
+                let identifier = match next_list_item(&mut list, "A NodeContent Identifier")? {
+                    Object::Bytes(b) => Ok(String::from_utf8(b.to_vec()).unwrap_or("".to_string())),
                     _ => Err(bendy::decoding::Error::unexpected_token(
                         "A NodeContent Identifier, which is a string",
                         "Something else",
                     )),
                 }?;
-                if !is_leaf {
-                    let count;
-                    match list.next_object()?.unwrap() {
-                        Object::Integer(i) => count = i.parse::<u32>().ok().unwrap(),
-                        _ => {
-                            return Err(bendy::decoding::Error::unexpected_token(
-                                "int field for Internal Node count",
-                                "Something else",
-                            ))
-                        }
-                    };
-                    Ok(NodeContent::Internal(count))
-                } else {
-                    Ok(NodeContent::<T, DIM>::Leaf(array_init::array_init(|_| {
-                        array_init::array_init(|_| {
-                            array_init::array_init(|_| {
-                                NodeContent::<T, DIM>::decode_single(&mut list).unwrap()
-                            })
-                        })
-                    })))
+                if "####" == identifier {
+                    // The content is a leaf without a brick
+                    return Ok(NodeContent::UniformLeaf(VoxelBytes::decode_bencode_object(
+                        next_list_item(&mut list, "The data of a uniform leaf")?,
+                    )?));
+                }
+                let key = u64::decode_bencode_object(next_list_item(
+                    &mut list,
+                    "int field for Internal Node count or Leaf brick key",
+                )?)?;
+                if list.next_object()?.is_some() {
+                    // Trees saved before bricks were pooled store every voxel of a leaf inside the node
+                    return Err(bendy::decoding::Error::malformed_content(
+                        "Leaf voxels stored inside the node, saved before leaves had bricks, are not supported",
+                    ));
+                }
+                match identifier.as_str() {
+                    // The content is an internal Node
+                    "##" => Ok(NodeContent::Internal(key as u32)),
+                    // The content is a leaf
+                    "###" => Ok(NodeContent::Leaf(key as PoolKey)),
+                    misc => Err(bendy::decoding::Error::unexpected_token(
+                        "A NodeContent Identifier string, which is one of #, ##, ### or ####",
+                        "The string ".to_owned() + misc,
+                    )),
                 }
             }
-            Object::Bytes(b) => {
-                assert!(String::from_utf8(b.to_vec()).unwrap_or("".to_string()) == "#");
-                Ok(NodeContent::Nothing)
-            }
+            Object::Bytes(b"#") => Ok(NodeContent::Nothing),
+            Object::Bytes(b) => Err(bendy::decoding::Error::unexpected_token(
+                "The NodeContent Identifier string #",
+                "The string ".to_owned() + &String::from_utf8_lossy(b),
+            )),
             _ => Err(bendy::decoding::Error::unexpected_token(
                 "A NodeContent Object, either a List or a ByteString",
                 "Something else",
@@ -146,10 +150,13 @@ where
     }
 }
 
+///####################################################################################
+/// NodeChildren
+///####################################################################################
 // using generic arguments means the default key needs to be serialzied along with the data, which means a lot of wasted space..
 // so serialization for the current ObjectPool key is adequate; The engineering hour cost of implementing new serialization logic
 // every time the ObjectPool::Itemkey type changes is acepted.
-impl ToBencode for NodeChildren<u32> {
+impl ToBencode for NodeChildren<PoolKey> {
     const MAX_DEPTH: usize = 2;
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
         match &self.content {
@@ -161,29 +168,43 @@ impl ToBencode for NodeChildren<u32> {
                 e.emit(c[4])?;
                 e.emit(c[5])?;
                 e.emit(c[6])?;
-                e.emit(c[7])
+                e.emit(c[7])?;
+                e.emit(self.occupied_bits)
             }),
             NodeChildrenArray::NoChildren => encoder.emit_str("##x##"),
         }
     }
 }
 
-impl FromBencode for NodeChildren<u32> {
+impl FromBencode for NodeChildren<PoolKey> {
     fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
         use crate::object_pool::key_none_value;
         match data {
             Object::List(mut list) => {
                 let mut c = Vec::new();
                 for _ in 0..8 {
-                    c.push(
-                        u32::decode_bencode_object(list.next_object()?.unwrap())
-                            .ok()
-                            .unwrap(),
-                    );
+                    // Keys are read as 64 bit ones to load trees saved with either key width
+                    let key = u64::decode_bencode_object(next_list_item(&mut list, "Node key")?)?;
+                    if u64::MAX == key {
+                        c.push(key_none_value());
+                    } else if u64::BITS - key.leading_zeros() <= PoolKey::BITS {
+                        c.push(key as PoolKey);
+                    } else {
+                        return Err(bendy::decoding::Error::malformed_content(format!(
+                            "Node key {} does not fit into {} bits, see the u64_keys feature",
+                            key,
+                            PoolKey::BITS
+                        )));
+                    }
                 }
+                let occupied_bits = u8::decode_bencode_object(next_list_item(
+                    &mut list,
+                    "The occupied bits of the children",
+                )?)?;
                 Ok(NodeChildren::from(
                     key_none_value(),
                     c.try_into().ok().unwrap(),
+                    occupied_bits,
                 ))
             }
             Object::Bytes(_b) =>
@@ -202,60 +223,642 @@ impl FromBencode for NodeChildren<u32> {
 ///####################################################################################
 /// Octree
 ///####################################################################################
-impl<T, const DIM: usize> ToBencode for Octree<T, DIM>
-where
-    T: Default + Clone + VoxelData,
-{
+/// The contents of an octree with the voxels converted to bytes, as it is stored
+pub(in crate::octree) struct EncodedOctree {
+    auto_simplify: bool,
+    octree_size: u32,
+    nodes: ObjectPool<NodeContent<VoxelBytes>>,
+    node_children: Vec<NodeChildren<PoolKey>>,
+    bricks: ObjectPool<Vec<VoxelBytes>>,
+    data_version: u32, // version of the codec the voxels were encoded with
+    voxel_size: f32,
+}
+
+impl EncodedOctree {
+    /// Converts the voxels of the given tree to bytes with the given codec
+    pub(in crate::octree) fn new<T, C, const DIM: usize>(tree: &Octree<T, DIM>, codec: &C) -> Self
+    where
+        T: Default + Clone + VoxelData,
+        C: VoxelDataCodec<T>,
+    {
+        let encode = |data: &T| VoxelBytes(codec.encode(data));
+        Self {
+            auto_simplify: tree.auto_simplify,
+            octree_size: tree.octree_size,
+            nodes: tree.nodes.map(|node| match node {
+                NodeContent::Nothing => NodeContent::Nothing,
+                NodeContent::Internal(count) => NodeContent::Internal(*count),
+                NodeContent::Leaf(brick) => NodeContent::Leaf(*brick),
+                NodeContent::UniformLeaf(data) => NodeContent::UniformLeaf(encode(data)),
+            }),
+            node_children: tree.node_children.clone(),
+            bricks: tree
+                .bricks
+                .map(|brick| brick.0.iter().flatten().flatten().map(encode).collect()),
+            data_version: codec.version(),
+            voxel_size: tree.voxel_size,
+        }
+    }
+
+    /// Parses a tree from the given bytes, decoding its voxels as in `decode`.
+    /// Malformed or unsupported data is reported as `InvalidData`
+    pub(in crate::octree) fn decode_bytes<T, C, const DIM: usize>(
+        bytes: &[u8],
+        codec: &C,
+        migrations: &HashMap<u32, VoxelDataMigration<T>>,
+    ) -> Result<Octree<T, DIM>, std::io::Error>
+    where
+        T: Default + Clone + VoxelData,
+        C: VoxelDataCodec<T>,
+    {
+        let invalid_data = |error: bendy::decoding::Error| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error.to_string())
+        };
+        Self::from_bencode(bytes)
+            .map_err(invalid_data)?
+            .decode(codec, migrations)
+            .map_err(invalid_data)
+    }
+
+    /// Checks that the nodes refer only to existing nodes and bricks, so the decoded tree can be used safely
+    fn validate<T, const DIM: usize>(&self) -> Result<(), bendy::decoding::Error>
+    where
+        T: Default + Clone + VoxelData,
+    {
+        if Octree::<T, DIM>::is_size_inadequate(self.octree_size) {
+            return Err(bendy::decoding::Error::malformed_content(format!(
+                "Tree size {} is not DIM * (2^x) for DIM {}",
+                self.octree_size, DIM
+            )));
+        }
+        if !self
+            .nodes
+            .key_is_valid(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
+        {
+            return Err(bendy::decoding::Error::malformed_content(
+                "The root node is missing",
+            ));
+        }
+        for (node_key, node) in self.nodes.iter() {
+            let children_valid =
+                self.node_children
+                    .get(node_key)
+                    .is_some_and(|children| match &children.content {
+                        NodeChildrenArray::Children(keys) => keys.iter().all(|key| {
+                            !key_might_be_valid(*key) || self.nodes.key_is_valid(*key as usize)
+                        }),
+                        NodeChildrenArray::NoChildren => true,
+                    });
+            let brick_valid = match node {
+                NodeContent::Leaf(brick) => self.bricks.key_is_valid(*brick as usize),
+                _ => true,
+            };
+            if !children_valid || !brick_valid {
+                return Err(bendy::decoding::Error::malformed_content(format!(
+                    "Node {} refers to a missing node or brick",
+                    node_key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the stored bytes back to voxels with the given codec, or with the migration
+    /// registered for the version of the stored voxels, should it differ from the version of the codec
+    pub(in crate::octree) fn decode<T, C, const DIM: usize>(
+        self,
+        codec: &C,
+        migrations: &HashMap<u32, VoxelDataMigration<T>>,
+    ) -> Result<Octree<T, DIM>, bendy::decoding::Error>
+    where
+        T: Default + Clone + VoxelData,
+        C: VoxelDataCodec<T>,
+    {
+        self.validate::<T, DIM>()?;
+        if self.data_version != codec.version() && !migrations.contains_key(&self.data_version) {
+            return Err(bendy::decoding::Error::malformed_content(format!(
+                "No migration for voxel data version {} to version {}",
+                self.data_version,
+                codec.version()
+            )));
+        }
+        let decode_voxel = |bytes: &[u8]| -> Option<T> {
+            if self.data_version == codec.version() {
+                codec.decode(bytes)
+            } else {
+                migrations
+                    .get(&self.data_version)
+                    .map(|migration| migration(bytes))
+            }
+        };
+        let nodes = self.nodes.try_map(|node| {
+            Ok(match node {
+                NodeContent::Nothing => NodeContent::Nothing,
+                NodeContent::Internal(count) => NodeContent::Internal(*count),
+                NodeContent::Leaf(brick) => NodeContent::Leaf(*brick),
+                NodeContent::UniformLeaf(data) => {
+                    NodeContent::UniformLeaf(data.decode(&decode_voxel)?)
+                }
+            })
+        })?;
+        let bricks = self.bricks.try_map(|voxels| {
+            if DIM.pow(3) != voxels.len() {
+                return Err(bendy::decoding::Error::malformed_content(format!(
+                    "A brick of {} voxels instead of {}",
+                    voxels.len(),
+                    DIM.pow(3)
+                )));
+            }
+            let mut voxels = voxels.iter();
+            let mut brick = Brick::<T, DIM>::default();
+            for voxel in brick.0.iter_mut().flatten().flatten() {
+                *voxel = voxels.next().unwrap().decode(&decode_voxel)?;
+            }
+            Ok(brick)
+        })?;
+        Ok(Octree {
+            auto_simplify: self.auto_simplify,
+            octree_size: self.octree_size,
+            nodes,
+            node_children: self.node_children,
+            bricks,
+            voxel_size: self.voxel_size,
+            memory_budget: None,
+            eviction_callback: None,
+            capacity_policy: Default::default(),
+            simplify_queue: Default::default(),
+            simplify_queued: Default::default(),
+            dirty_bricks: Default::default(),
+            edit_count: 0,
+            changed_regions: Default::default(),
+            light: None,
+            auxiliary: None,
+            fluids: Default::default(),
+            recorder: None,
+            aggregates: None,
+        })
+    }
+}
+
+impl ToBencode for EncodedOctree {
     const MAX_DEPTH: usize = 10;
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
         encoder.emit_list(|e| {
+            e.emit_str(OCTREE_FORMAT_TAG)?;
+            e.emit_int(OCTREE_FORMAT_VERSION)?;
             e.emit_int(self.auto_simplify as u8)?;
             e.emit_int(self.octree_size)?;
             e.emit(&self.nodes)?;
-            e.emit(&self.node_children)
+            e.emit(&self.node_children)?;
+            e.emit(&self.bricks)?;
+            e.emit_int(self.data_version)?;
+            e.emit_int(self.voxel_size.to_bits())
         })
     }
 }
 
-impl<T, const DIM: usize> FromBencode for Octree<T, DIM>
-where
-    T: PartialEq + Default + Clone + VoxelData,
-{
+impl FromBencode for EncodedOctree {
     fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
         match data {
             Object::List(mut list) => {
-                let auto_simplify = match list.next_object()?.unwrap() {
-                    Object::Integer("0") => Ok(false),
-                    Object::Integer("1") => Ok(true),
-                    Object::Integer(i) => Err(bendy::decoding::Error::unexpected_token(
-                        "boolean field auto_simplify",
-                        format!("the number: {}", i),
-                    )),
-                    _ => Err(bendy::decoding::Error::unexpected_token(
-                        "boolean field auto_simplify",
-                        "Something else",
-                    )),
-                }?;
+                // Trees saved before the layout was versioned start with auto_simplify right away
+                let unversioned_auto_simplify =
+                    match next_list_item(&mut list, "The format tag or auto_simplify")? {
+                        Object::Bytes(tag) if OCTREE_FORMAT_TAG.as_bytes() == tag => None,
+                        object => Some(decode_auto_simplify(object)?),
+                    };
+                let auto_simplify = match unversioned_auto_simplify {
+                    Some(auto_simplify) => auto_simplify,
+                    None => {
+                        let format_version = u32::decode_bencode_object(next_list_item(
+                            &mut list,
+                            "int field format version",
+                        )?)?;
+                        if OCTREE_FORMAT_VERSION < format_version {
+                            return Err(bendy::decoding::Error::malformed_content(format!(
+                                "Tree saved in format version {}, newer than the supported version {}",
+                                format_version, OCTREE_FORMAT_VERSION
+                            )));
+                        }
+                        decode_auto_simplify(next_list_item(
+                            &mut list,
+                            "boolean field auto_simplify",
+                        )?)?
+                    }
+                };
 
-                let root_size = match list.next_object()?.unwrap() {
-                    Object::Integer(i) => Ok(i.parse::<u32>().ok().unwrap()),
-                    _ => Err(bendy::decoding::Error::unexpected_token(
-                        "int field root_size",
-                        "Something else",
-                    )),
+                let octree_size =
+                    u32::decode_bencode_object(next_list_item(&mut list, "int field root_size")?)?;
+                let nodes = ObjectPool::decode_bencode_object(next_list_item(&mut list, "nodes")?)?;
+                let node_children: Vec<NodeChildren<PoolKey>> =
+                    Vec::decode_bencode_object(next_list_item(&mut list, "node_children")?)?;
+                // Trees saved with 32 bit keys mark missing children with the largest 32 bit key,
+                // which is only a valid key in pools with more nodes than that
+                #[cfg(feature = "u64_keys")]
+                let mut node_children = node_children;
+                #[cfg(feature = "u64_keys")]
+                if nodes.len() <= u32::MAX as usize {
+                    for children in node_children.iter_mut() {
+                        if let NodeChildrenArray::Children(keys) = &mut children.content {
+                            for key in keys.iter_mut().filter(|key| u32::MAX as PoolKey == **key) {
+                                *key = crate::object_pool::key_none_value();
+                            }
+                        }
+                    }
+                }
+                let bricks =
+                    ObjectPool::decode_bencode_object(next_list_item(&mut list, "bricks")?)?;
+                // Missing from data saved before it was added, which is the first version of the codec
+                let data_version = match list.next_object()? {
+                    None => Ok(0),
+                    Some(object) => u32::decode_bencode_object(object),
+                }?;
+                // Stored as the bits of the float; missing from data saved before it was added
+                let voxel_size = match list.next_object()? {
+                    None => Ok(1.),
+                    Some(object) => Ok(f32::from_bits(u32::decode_bencode_object(object)?)),
                 }?;
-                let nodes = ObjectPool::<NodeContent<T, DIM>>::decode_bencode_object(
-                    list.next_object()?.unwrap(),
-                )?;
-                let node_children = Vec::decode_bencode_object(list.next_object()?.unwrap())?;
                 Ok(Self {
                     auto_simplify,
-                    octree_size: root_size,
+                    octree_size,
                     nodes,
                     node_children,
+                    bricks,
+                    data_version,
+                    voxel_size,
                 })
             }
             _ => Err(bendy::decoding::Error::unexpected_token("List", "not List")),
         }
     }
 }
+
+/// The `auto_simplify` flag of an encoded tree
+fn decode_auto_simplify(object: Object) -> Result<bool, bendy::decoding::Error> {
+    match object {
+        Object::Integer("0") => Ok(false),
+        Object::Integer("1") => Ok(true),
+        Object::Integer(i) => Err(bendy::decoding::Error::unexpected_token(
+            "boolean field auto_simplify",
+            format!("the number: {}", i),
+        )),
+        _ => Err(bendy::decoding::Error::unexpected_token(
+            "boolean field auto_simplify",
+            "Something else",
+        )),
+    }
+}
+
+impl<T, const DIM: usize> ToBencode for Octree<T, DIM>
+where
+    T: Default + Clone + VoxelData,
+{
+    const MAX_DEPTH: usize = 10;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        EncodedOctree::new(self, &DefaultVoxelDataCodec).encode(encoder)
+    }
+}
+
+impl<T, const DIM: usize> FromBencode for Octree<T, DIM>
+where
+    T: PartialEq + Default + Clone + VoxelData,
+{
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        EncodedOctree::decode_bencode_object(data)?.decode(&DefaultVoxelDataCodec, &HashMap::new())
+    }
+}
+
+///####################################################################################
+/// VoxelWorld
+///####################################################################################
+impl<T, const DIM: usize> ToBencode for VoxelWorld<T, DIM>
+where
+    T: Default + Clone + VoxelData,
+{
+    const MAX_DEPTH: usize = 12;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        encoder.emit_list(|e| {
+            e.emit_int(self.chunk_size)?;
+            e.emit_list(|e| {
+                for (chunk_coord, chunk) in self.chunks.iter() {
+                    e.emit_list(|e| {
+                        e.emit_int(chunk_coord.x)?;
+                        e.emit_int(chunk_coord.y)?;
+                        e.emit_int(chunk_coord.z)?;
+                        e.emit(chunk)
+                    })?;
+                }
+                Ok(())
+            })?;
+            e.emit_list(|e| {
+                for chunk_coord in self.empty_chunks.iter() {
+                    e.emit_list(|e| {
+                        e.emit_int(chunk_coord.x)?;
+                        e.emit_int(chunk_coord.y)?;
+                        e.emit_int(chunk_coord.z)
+                    })?;
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+impl<T, const DIM: usize> FromBencode for VoxelWorld<T, DIM>
+where
+    T: PartialEq + Default + Clone + VoxelData,
+{
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        let Object::List(mut list) = data else {
+            return Err(bendy::decoding::Error::unexpected_token("List", "not List"));
+        };
+        let chunk_size = u32::decode_bencode_object(next_list_item(&mut list, "chunk_size")?)?;
+        let chunks = {
+            let Some(Object::List(mut chunk_list)) = list.next_object()? else {
+                return Err(bendy::decoding::Error::unexpected_token(
+                    "List of chunks",
+                    "Something else",
+                ));
+            };
+            let mut chunks = HashMap::new();
+            while let Some(chunk) = chunk_list.next_object()? {
+                let Object::List(mut chunk) = chunk else {
+                    return Err(bendy::decoding::Error::unexpected_token("List", "not List"));
+                };
+                let x = i32::decode_bencode_object(next_list_item(&mut chunk, "x")?)?;
+                let y = i32::decode_bencode_object(next_list_item(&mut chunk, "y")?)?;
+                let z = i32::decode_bencode_object(next_list_item(&mut chunk, "z")?)?;
+                let tree =
+                    Octree::decode_bencode_object(next_list_item(&mut chunk, "chunk tree")?)?;
+                chunks.insert(V3c::new(x, y, z), tree);
+            }
+            chunks
+        };
+
+        // Worlds saved before empty chunks were tracked have no list of them
+        let mut empty_chunks = HashSet::new();
+        if let Some(Object::List(mut empty_list)) = list.next_object()? {
+            while let Some(Object::List(mut chunk_coord)) = empty_list.next_object()? {
+                let x = i32::decode_bencode_object(next_list_item(&mut chunk_coord, "x")?)?;
+                let y = i32::decode_bencode_object(next_list_item(&mut chunk_coord, "y")?)?;
+                let z = i32::decode_bencode_object(next_list_item(&mut chunk_coord, "z")?)?;
+                empty_chunks.insert(V3c::new(x, y, z));
+            }
+        }
+        // Every decoded chunk is to be saved by `VoxelWorld::save_dir`
+        let dirty_chunks = chunks.keys().chain(empty_chunks.iter()).copied().collect();
+        Ok(Self {
+            chunk_size,
+            chunks,
+            empty_chunks,
+            dirty_chunks,
+            #[cfg(feature = "world_dir")]
+            clean_dir: None,
+            coarse_chunks: HashMap::new(),
+            seam_handling: SeamHandling::default(),
+        })
+    }
+}
+
+/// The coordinates of a chunk in a list of chunks
+#[cfg(feature = "world_dir")]
+fn decode_chunk_coord(
+    list: &mut bendy::decoding::ListDecoder,
+) -> Result<V3c<i32>, bendy::decoding::Error> {
+    let x = i32::decode_bencode_object(next_list_item(list, "x")?)?;
+    let y = i32::decode_bencode_object(next_list_item(list, "y")?)?;
+    let z = i32::decode_bencode_object(next_list_item(list, "z")?)?;
+    Ok(V3c::new(x, y, z))
+}
+
+#[cfg(feature = "world_dir")]
+impl ToBencode for WorldManifest {
+    const MAX_DEPTH: usize = 3;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        encoder.emit_list(|e| {
+            e.emit_int(WORLD_MANIFEST_VERSION)?;
+            e.emit_int(self.chunk_size)?;
+            e.emit_list(|e| {
+                for (chunk_coord, saved) in self.chunks.iter() {
+                    e.emit_list(|e| {
+                        e.emit_int(chunk_coord.x)?;
+                        e.emit_int(chunk_coord.y)?;
+                        e.emit_int(chunk_coord.z)?;
+                        e.emit_int(saved.hash)?;
+                        e.emit_int(saved.version)
+                    })?;
+                }
+                Ok(())
+            })?;
+            e.emit_list(|e| {
+                for chunk_coord in self.empty_chunks.iter() {
+                    e.emit_list(|e| {
+                        e.emit_int(chunk_coord.x)?;
+                        e.emit_int(chunk_coord.y)?;
+                        e.emit_int(chunk_coord.z)
+                    })?;
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+#[cfg(feature = "world_dir")]
+impl FromBencode for WorldManifest {
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        let Object::List(mut list) = data else {
+            return Err(bendy::decoding::Error::unexpected_token("List", "not List"));
+        };
+        let version = u32::decode_bencode_object(next_list_item(&mut list, "version")?)?;
+        if WORLD_MANIFEST_VERSION != version {
+            return Err(bendy::decoding::Error::malformed_content(
+                "unsupported world manifest version",
+            ));
+        }
+        let mut manifest = WorldManifest {
+            chunk_size: u32::decode_bencode_object(next_list_item(&mut list, "chunk_size")?)?,
+            ..Default::default()
+        };
+        {
+            let Some(Object::List(mut chunk_list)) = list.next_object()? else {
+                return Err(bendy::decoding::Error::unexpected_token(
+                    "List of chunks",
+                    "Something else",
+                ));
+            };
+            while let Some(Object::List(mut chunk)) = chunk_list.next_object()? {
+                let chunk_coord = decode_chunk_coord(&mut chunk)?;
+                let hash = u64::decode_bencode_object(next_list_item(&mut chunk, "hash")?)?;
+                let version = u32::decode_bencode_object(next_list_item(&mut chunk, "version")?)?;
+                manifest
+                    .chunks
+                    .insert(chunk_coord, SavedChunk { hash, version });
+            }
+        }
+        let Some(Object::List(mut empty_list)) = list.next_object()? else {
+            return Err(bendy::decoding::Error::unexpected_token(
+                "List of empty chunks",
+                "Something else",
+            ));
+        };
+        while let Some(Object::List(mut chunk_coord)) = empty_list.next_object()? {
+            manifest
+                .empty_chunks
+                .insert(decode_chunk_coord(&mut chunk_coord)?);
+        }
+        Ok(manifest)
+    }
+}
+
+///####################################################################################
+/// EditRecorder
+///####################################################################################
+impl<T: VoxelData> ToBencode for RecordedCall<T> {
+    const MAX_DEPTH: usize = 1;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        encoder.emit_list(|e| match self {
+            RecordedCall::Edit(OctreeEdit::Insert {
+                position,
+                size,
+                data,
+            }) => {
+                e.emit_int(0)?;
+                e.emit_int(position.x)?;
+                e.emit_int(position.y)?;
+                e.emit_int(position.z)?;
+                e.emit_int(*size)?;
+                e.emit(VoxelBytes(DefaultVoxelDataCodec.encode(data)))
+            }
+            RecordedCall::Edit(OctreeEdit::Clear { position, size }) => {
+                e.emit_int(1)?;
+                e.emit_int(position.x)?;
+                e.emit_int(position.y)?;
+                e.emit_int(position.z)?;
+                e.emit_int(*size)
+            }
+            RecordedCall::SetAutoSimplify(auto_simplify) => {
+                e.emit_int(2)?;
+                e.emit_int(*auto_simplify as u8)
+            }
+            RecordedCall::SimplifyIncremental { node_budget } => {
+                e.emit_int(3)?;
+                e.emit_int(*node_budget)
+            }
+        })
+    }
+}
+
+impl<T: VoxelData> FromBencode for RecordedCall<T> {
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        let Object::List(mut list) = data else {
+            return Err(bendy::decoding::Error::unexpected_token("List", "not List"));
+        };
+        let kind = u8::decode_bencode_object(next_list_item(&mut list, "kind")?)?;
+        if 2 == kind {
+            let auto_simplify =
+                u8::decode_bencode_object(next_list_item(&mut list, "auto_simplify")?)?;
+            return Ok(RecordedCall::SetAutoSimplify(1 == auto_simplify));
+        }
+        if 3 == kind {
+            let node_budget =
+                usize::decode_bencode_object(next_list_item(&mut list, "node_budget")?)?;
+            return Ok(RecordedCall::SimplifyIncremental { node_budget });
+        }
+        let x = u32::decode_bencode_object(next_list_item(&mut list, "x")?)?;
+        let y = u32::decode_bencode_object(next_list_item(&mut list, "y")?)?;
+        let z = u32::decode_bencode_object(next_list_item(&mut list, "z")?)?;
+        let position = V3c::new(x, y, z);
+        let size = u32::decode_bencode_object(next_list_item(&mut list, "size")?)?;
+        match kind {
+            0 => {
+                let data = VoxelBytes::decode_bencode_object(next_list_item(&mut list, "data")?)?
+                    .decode(&|bytes| DefaultVoxelDataCodec.decode(bytes))?;
+                Ok(RecordedCall::Edit(OctreeEdit::Insert {
+                    position,
+                    size,
+                    data,
+                }))
+            }
+            1 => Ok(RecordedCall::Edit(OctreeEdit::Clear { position, size })),
+            _ => Err(bendy::decoding::Error::unexpected_token(
+                "The kind of a recorded call",
+                format!("the number: {}", kind),
+            )),
+        }
+    }
+}
+
+impl<T: VoxelData> ToBencode for EditRecorder<T> {
+    const MAX_DEPTH: usize = 3;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), BencodeError> {
+        encoder.emit_list(|e| {
+            e.emit_int(self.octree_size)?;
+            e.emit_int(self.auto_simplify as u8)?;
+            e.emit(&self.calls)
+        })
+    }
+}
+
+impl<T: VoxelData> FromBencode for EditRecorder<T> {
+    fn decode_bencode_object(data: Object) -> Result<Self, bendy::decoding::Error> {
+        let Object::List(mut list) = data else {
+            return Err(bendy::decoding::Error::unexpected_token("List", "not List"));
+        };
+        let octree_size = u32::decode_bencode_object(next_list_item(&mut list, "octree_size")?)?;
+        let auto_simplify = u8::decode_bencode_object(next_list_item(&mut list, "auto_simplify")?)?;
+        let calls = Vec::decode_bencode_object(next_list_item(&mut list, "calls")?)?;
+        Ok(Self {
+            octree_size,
+            auto_simplify: 1 == auto_simplify,
+            calls,
+        })
+    }
+}
+
+///####################################################################################
+/// OctreeLoader
+///####################################################################################
+impl<T, C> OctreeLoader<T, C>
+where
+    T: Default + PartialEq + Clone + VoxelData,
+    C: VoxelDataCodec<T>,
+{
+    /// Creates a loader decoding voxels with the given codec, without any migrations
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            migrations: HashMap::new(),
+        }
+    }
+
+    /// Registers the function to convert voxels saved with the given version of the codec
+    /// * `version` - The version of the codec the voxels were saved with
+    /// * `migration` - Creates the voxel from the bytes saved by the older version of the codec
+    pub fn with_migration(
+        mut self,
+        version: u32,
+        migration: impl Fn(&[u8]) -> T + 'static,
+    ) -> Self {
+        self.migrations.insert(version, Box::new(migration));
+        self
+    }
+
+    /// parses an octree from a byte string, migrating its voxels if needed
+    pub fn from_bytes<const DIM: usize>(
+        &self,
+        bytes: Vec<u8>,
+    ) -> Result<Octree<T, DIM>, std::io::Error> {
+        EncodedOctree::decode_bytes(&bytes, &self.codec, &self.migrations)
+    }
+
+    /// loads an octree from the given file path, migrating its voxels if needed
+    pub fn load<const DIM: usize>(&self, path: &str) -> Result<Octree<T, DIM>, std::io::Error> {
+        use std::fs::File;
+        use std::io::Read;
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        self.from_bytes(bytes)
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+/// A limited set of RGB colors, e.g. for exporting into formats with a color palette, see `quantize`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// The colors of the palette
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// The index of the color of the palette closest to the given color, None if the palette is empty
+    pub fn nearest(&self, color: &[u8; 3]) -> Option<usize> {
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, palette_color)| {
+                palette_color
+                    .iter()
+                    .zip(color.iter())
+                    .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+/// A set of distinct colors with the number of times each occurs
+struct ColorBox {
+    colors: Vec<([u8; 3], usize)>,
+}
+
+impl ColorBox {
+    /// The channel the colors are spread the most along, and the size of the spread
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let (min, max) = self
+                    .colors
+                    .iter()
+                    .fold((u8::MAX, 0), |(min, max), (color, _)| {
+                        (min.min(color[channel]), max.max(color[channel]))
+                    });
+                (channel, max - min)
+            })
+            .max_by_key(|(_, spread)| *spread)
+            .unwrap()
+    }
+
+    /// Splits the box at the median of its widest channel, weighted by the occurrences of the colors
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(color, _)| color[channel]);
+        let total = self.colors.iter().map(|(_, count)| count).sum::<usize>();
+        let mut below = 0;
+        let median = self
+            .colors
+            .iter()
+            .position(|(_, count)| {
+                below += count;
+                below * 2 >= total
+            })
+            .unwrap();
+        // Both halves keep at least one color
+        let upper = self
+            .colors
+            .split_off((median + 1).min(self.colors.len() - 1));
+        (self, ColorBox { colors: upper })
+    }
+
+    /// The average of the colors, weighted by their occurrences
+    fn average(&self) -> [u8; 3] {
+        let total = self.colors.iter().map(|(_, count)| count).sum::<usize>() as f64;
+        std::array::from_fn(|channel| {
+            let sum = self
+                .colors
+                .iter()
+                .map(|(color, count)| color[channel] as f64 * *count as f64)
+                .sum::<f64>();
+            (sum / total).round() as u8
+        })
+    }
+}
+
+/// A palette of at most the given number of colors representing the given colors,
+/// built by median cut: the colors are split into boxes along their widest channel,
+/// and each box is represented by the average of its colors.
+/// If there are not more distinct colors than the maximum, each of them is kept exactly.
+/// The palette is the same for the same colors regardless of their order
+pub fn quantize(colors: impl Iterator<Item = [u8; 3]>, max: usize) -> Palette {
+    let mut counts = HashMap::<[u8; 3], usize>::new();
+    for color in colors {
+        *counts.entry(color).or_default() += 1;
+    }
+    let mut distinct = counts.into_iter().collect::<Vec<_>>();
+    distinct.sort();
+    if distinct.len() <= max {
+        return Palette {
+            colors: distinct.into_iter().map(|(color, _)| color).collect(),
+        };
+    }
+    if 0 == max {
+        return Palette::default();
+    }
+
+    let mut boxes = vec![ColorBox { colors: distinct }];
+    while boxes.len() < max {
+        // The box with the widest spread is split next
+        let Some((widest, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| 1 < color_box.colors.len())
+            .max_by_key(|(_, color_box)| color_box.widest_channel().1)
+        else {
+            break;
+        };
+        let (lower, upper) = boxes.swap_remove(widest).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    let mut colors = boxes.iter().map(ColorBox::average).collect::<Vec<_>>();
+    colors.sort();
+    colors.dedup();
+    Palette { colors }
+}
@@ -0,0 +1,231 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    detail::child_octant_for,
+    types::{NodeContent, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+use std::ops::Range;
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The height and data of the topmost voxel in the vertical column at the given position,
+    /// e.g. to spawn or snap objects onto the ground. None if the column is empty or outside the tree.
+    /// Only the nodes overlapping the column are visited, the upper ones first.
+    pub fn top_voxel_at(&self, x: u32, z: u32) -> Option<(u32, &T)> {
+        self.top_voxel_in(x, z, 0..self.octree_size)
+    }
+
+    /// The height and data of the topmost voxel in the given part of the vertical column at the given position
+    /// * `heights` - The range of y coordinates to look for voxels in
+    pub(in crate::octree) fn top_voxel_in(
+        &self,
+        x: u32,
+        z: u32,
+        heights: Range<u32>,
+    ) -> Option<(u32, &T)> {
+        if x >= self.octree_size || z >= self.octree_size {
+            return None;
+        }
+        let overlaps = |bounds: &Cube| {
+            bounds.min_position.y < heights.end
+                && heights.start < bounds.min_position.y + bounds.size
+        };
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(self.octree_size),
+        )];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            if !overlaps(&bounds) {
+                continue;
+            }
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        let top = (bounds.min_position.y + bounds.size).min(heights.end);
+                        return Some((top - 1, data));
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    let top = (bounds.min_position.y + bounds.size).min(heights.end);
+                    let mat_index = Self::mat_index(&bounds, &V3c::new(x, top - 1, z));
+                    let column = &self.bricks.get(*brick as usize).0[mat_index.x];
+                    for y in (0..=mat_index.y).rev() {
+                        let cell_bottom = bounds.min_position.y + y as u32 * cell_size;
+                        if cell_bottom + cell_size <= heights.start {
+                            break;
+                        }
+                        let data = &column[y][mat_index.z];
+                        if !data.is_empty() {
+                            return Some(((cell_bottom + cell_size).min(top) - 1, data));
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    // The lower octant is pushed first, so the upper one is visited first
+                    let lower = V3c::new(x, bounds.min_position.y, z);
+                    let upper = lower + V3c::new(0, bounds.size / 2, 0);
+                    for position in [lower, upper] {
+                        let octant = child_octant_for(&bounds, &position);
+                        let child_key = self.node_children[node_key][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key as usize, bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Builds a tree of the given size from run-length encoded vertical columns, the interchange format
+    /// of many terrain generators. Each column is a list of (data, run length) pairs from the bottom up,
+    /// with the columns laid out x first: the column at (x, z) is at `z * size + x`.
+    /// Empty data marks air, and voxels above the last run of a column are empty.
+    /// Runs are inserted as the largest aligned cubes with the same run in every column, instead of voxel by voxel
+    pub fn from_rle_columns(size: u32, columns: &[Vec<(T, u32)>]) -> Result<Self, OctreeError> {
+        let mut tree = Self::new(size)?;
+        let expected = (size as usize).pow(2);
+        if columns.len() != expected {
+            return Err(OctreeError::InvalidDenseData {
+                expected,
+                actual: columns.len(),
+            });
+        }
+
+        // The height each run ends at in every column, to look up the run at a height by binary search
+        let mut run_ends = Vec::with_capacity(expected);
+        for (index, column) in columns.iter().enumerate() {
+            let mut end = 0;
+            let ends = column
+                .iter()
+                .map(|(_, run_length)| {
+                    end += run_length;
+                    end
+                })
+                .collect::<Vec<_>>();
+            if end > size {
+                return Err(OctreeError::InvalidPosition {
+                    x: index as u32 % size,
+                    y: end - 1,
+                    z: index as u32 / size,
+                });
+            }
+            run_ends.push(ends);
+        }
+
+        // The data of the run at the given height in the column at (x, z) and the height it ends at,
+        // None as data for air
+        let run_at = |x: u32, y: u32, z: u32| {
+            let index = (z * size + x) as usize;
+            let run = run_ends[index].partition_point(|end| *end <= y);
+            match run_ends[index].get(run) {
+                Some(end) => (
+                    Some(&columns[index][run].0).filter(|data| !data.is_empty()),
+                    *end,
+                ),
+                None => (None, size),
+            }
+        };
+
+        let auto_simplify = tree.auto_simplify;
+        tree.auto_simplify = false;
+        let mut cube_stack = vec![Cube::root_bounds(size)];
+        while let Some(bounds) = cube_stack.pop() {
+            let min = bounds.min_position;
+            let top = min.y + bounds.size;
+            let (data, _) = run_at(min.x, min.y, min.z);
+            let uniform = (min.x..(min.x + bounds.size)).all(|x| {
+                (min.z..(min.z + bounds.size)).all(|z| {
+                    let (run_data, run_end) = run_at(x, min.y, z);
+                    run_end >= top && run_data == data
+                })
+            });
+            if uniform {
+                if let Some(data) = data {
+                    tree.insert_at_lod(&min, bounds.size, data.clone())?;
+                }
+            } else {
+                cube_stack.extend(Octant::iter().map(|octant| bounds.child_bounds_for(octant)));
+            }
+        }
+        while !tree.simplify_incremental(usize::MAX) {}
+        tree.auto_simplify = auto_simplify;
+        Ok(tree)
+    }
+
+    /// The content of the tree as run-length encoded vertical columns, in the layout of `from_rle_columns`.
+    /// Air runs hold the default data, neighbouring runs of the same data are merged,
+    /// and the air above the topmost voxel of a column is left out, so empty columns have no runs
+    pub fn to_rle_columns(&self) -> Vec<Vec<(T, u32)>> {
+        let size = self.octree_size;
+        // The non-empty cubes of the tree as (min position, size, data), collected in a single walk of the tree
+        let mut cubes = Vec::new();
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(size),
+        )];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        cubes.push((bounds.min_position, bounds.size, data));
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    for (x, plane) in self.bricks.get(*brick as usize).0.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                let cell = V3c::new(x as u32, y as u32, z as u32) * cell_size;
+                                if !data.is_empty() {
+                                    cubes.push((bounds.min_position + cell, cell_size, data));
+                                }
+                            }
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key as usize, bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // The (bottom, height, data) segments of each column
+        let mut segments = vec![Vec::new(); (size as usize).pow(2)];
+        for (min, cube_size, data) in cubes {
+            for z in min.z..(min.z + cube_size) {
+                for x in min.x..(min.x + cube_size) {
+                    segments[(z * size + x) as usize].push((min.y, cube_size, data));
+                }
+            }
+        }
+        segments
+            .into_iter()
+            .map(|mut column_segments| {
+                column_segments.sort_by_key(|(bottom, _, _)| *bottom);
+                let mut runs = Vec::<(T, u32)>::new();
+                let mut end = 0;
+                for (bottom, height, data) in column_segments {
+                    if bottom > end {
+                        runs.push((T::default(), bottom - end));
+                    }
+                    match runs.last_mut() {
+                        Some((run_data, run_length)) if bottom == end && run_data == data => {
+                            *run_length += height;
+                        }
+                        _ => runs.push((data.clone(), height)),
+                    }
+                    end = bottom + height;
+                }
+                runs
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,170 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    parallel::*,
+    progress::{CancellationToken, ProgressSink},
+    types::{NodeContent, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+impl<T, const DIM: usize> Octree<T, DIM>
+where
+    T: Default + PartialEq + Clone + VoxelData + Send,
+{
+    /// Creates a tree of the given size with the voxels provided by the given function.
+    /// The eight octants of the root are built in parallel with the `parallel` feature, then grafted under the root.
+    /// * `size` - The size of the tree, see `Octree::new`
+    /// * `generator` - Provides the voxel at each position of the tree, None for empty positions
+    pub fn generate(
+        size: u32,
+        generator: impl Fn(&V3c<u32>) -> Option<T> + Sync,
+    ) -> Result<Self, OctreeError> {
+        Self::generate_with_progress(size, generator, &(), &CancellationToken::new())
+    }
+
+    /// Creates a tree like `generate`, reporting the number of generated planes of voxels
+    /// along the x axis to the given sink, from the threads generating them.
+    /// Fails with `OctreeError::Cancelled` if the given token is cancelled before the tree is complete
+    pub fn generate_with_progress(
+        size: u32,
+        generator: impl Fn(&V3c<u32>) -> Option<T> + Sync,
+        progress: &impl ProgressSink,
+        cancellation: &CancellationToken,
+    ) -> Result<Self, OctreeError> {
+        let mut tree = Self::new(size)?;
+        let done_planes = AtomicUsize::new(0);
+        let plane_done = |total: usize| {
+            progress.report(done_planes.fetch_add(1, Ordering::Relaxed) + 1, total);
+            !cancellation.is_cancelled()
+        };
+        if Self::is_size_inadequate(size / 2) {
+            // The root is the smallest node possible, it has no octants to build separately
+            tree.fill_with(&V3c::unit(0), &generator, &|| plane_done(size as usize))?;
+            return Ok(tree);
+        }
+
+        // Each of the eight octants has half as many planes as the tree
+        let total_planes = 4 * size as usize;
+        let octants = Octant::ALL
+            .into_par_iter()
+            .map(|octant| {
+                let mut subtree = Self::new(size / 2)?;
+                subtree.fill_with(&(octant.offset() * (size / 2)), &generator, &|| {
+                    plane_done(total_planes)
+                })?;
+                Ok((octant, subtree))
+            })
+            .collect::<Result<Vec<_>, OctreeError>>()?;
+        for (octant, subtree) in octants {
+            tree.graft(octant.index(), subtree)?;
+        }
+        Ok(tree)
+    }
+
+    /// Creates a tree from a dense array of voxels, laid out x first, then y, then z.
+    /// Empty voxels are not inserted.
+    /// * `size` - The size of the tree, see `Octree::new`
+    /// * `voxels` - The voxels of the tree, expected to contain size*size*size items
+    pub fn from_dense(size: u32, voxels: &[T]) -> Result<Self, OctreeError>
+    where
+        T: Sync,
+    {
+        let expected = (size as usize).pow(3);
+        if voxels.len() != expected {
+            return Err(OctreeError::InvalidDenseData {
+                expected,
+                actual: voxels.len(),
+            });
+        }
+        Self::generate(size, |position| {
+            let index = ((position.z * size + position.y) * size + position.x) as usize;
+            Some(voxels[index].clone()).filter(|voxel| !voxel.is_empty())
+        })
+    }
+
+    /// Inserts the voxels provided by the given generator for every position of the tree,
+    /// offset by the given position, then simplifies the tree in one pass
+    /// * `plane_done` - Called after each plane of voxels along the x axis, returns false to abort
+    fn fill_with(
+        &mut self,
+        offset: &V3c<u32>,
+        generator: &impl Fn(&V3c<u32>) -> Option<T>,
+        plane_done: &impl Fn() -> bool,
+    ) -> Result<(), OctreeError> {
+        let auto_simplify = self.auto_simplify;
+        self.auto_simplify = false;
+        for x in 0..self.octree_size {
+            if 0 < x && !plane_done() {
+                return Err(OctreeError::Cancelled);
+            }
+            for y in 0..self.octree_size {
+                for z in 0..self.octree_size {
+                    let position = V3c::new(x, y, z);
+                    if let Some(data) = generator(&(*offset + position)) {
+                        self.insert(&position, data)?;
+                    }
+                }
+            }
+        }
+        if !plane_done() {
+            return Err(OctreeError::Cancelled);
+        }
+        while !self.simplify_incremental(usize::MAX) {}
+        self.auto_simplify = auto_simplify;
+        Ok(())
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Converts the tree to one with a different brick size, e.g. to render a tree imported
+    /// with a small DIM on the GPU with larger bricks. Uniform nodes are converted in one step,
+    /// bricks are converted cell by cell, skipping empty cells, then the new tree is simplified in one pass.
+    /// The size of the tree must be valid with the new brick size, see `Octree::new`
+    pub fn rebrick<const NEW_DIM: usize>(&self) -> Result<Octree<T, NEW_DIM>, OctreeError> {
+        let mut tree =
+            Octree::<T, NEW_DIM>::new(self.octree_size)?.with_voxel_size(self.voxel_size);
+        tree.auto_simplify = false;
+        let mut node_stack = vec![(
+            Self::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(self.octree_size),
+        )];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        tree.insert_at_lod(&bounds.min_position, bounds.size, data.clone())?;
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    for (x, plane) in self.bricks.get(*brick as usize).0.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                if !data.is_empty() {
+                                    tree.insert_at_lod(
+                                        &(bounds.min_position
+                                            + V3c::new(x as u32, y as u32, z as u32) * cell_size),
+                                        cell_size,
+                                        data.clone(),
+                                    )?;
+                                }
+                            }
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key as usize, bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+            }
+        }
+        while !tree.simplify_incremental(usize::MAX) {}
+        tree.auto_simplify = self.auto_simplify;
+        Ok(tree)
+    }
+}
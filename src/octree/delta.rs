@@ -0,0 +1,156 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    types::{NodeContent, OctreeEdit, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+
+/// The content of a region of the source tree while comparing it to the target tree
+enum Region<'a, T> {
+    Empty,
+    Uniform(&'a T),
+    /// An internal node or a leaf with a brick
+    Node(usize),
+}
+
+// Derived implementations would require T to be Copy as well
+impl<T> Clone for Region<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Region<'_, T> {}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The edits turning this tree into the given one, e.g. to store keyframes of an animation
+    /// or to send changes over the network instead of the whole tree.
+    /// Regions matching in both trees are skipped, uniform regions are set in one edit each.
+    /// Applying the edits in order with `apply_edit` makes this tree contain the same voxels as the other one.
+    pub fn delta_to(&self, other: &Self) -> Result<Vec<OctreeEdit<T>>, OctreeError> {
+        if self.octree_size != other.octree_size {
+            return Err(OctreeError::InvalidNodeSize(other.octree_size));
+        }
+        let mut edits = Vec::new();
+        self.push_delta_in(
+            self.region_of(Self::ROOT_NODE_KEY as usize),
+            other,
+            Self::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(self.octree_size),
+            &mut edits,
+        );
+        Ok(edits)
+    }
+
+    /// The content of the region of the given node
+    fn region_of(&self, node_key: usize) -> Region<'_, T> {
+        match self.nodes.get(node_key) {
+            NodeContent::Nothing => Region::Empty,
+            NodeContent::UniformLeaf(data) if data.is_empty() => Region::Empty,
+            NodeContent::UniformLeaf(data) => Region::Uniform(data),
+            NodeContent::Internal(_) | NodeContent::Leaf(_) => Region::Node(node_key),
+        }
+    }
+
+    /// Collects the edits needed to turn the given region of this tree into the matching node of the other tree
+    fn push_delta_in(
+        &self,
+        source: Region<'_, T>,
+        other: &Self,
+        other_key: usize,
+        bounds: Cube,
+        edits: &mut Vec<OctreeEdit<T>>,
+    ) {
+        match (other.region_of(other_key), other.nodes.get(other_key)) {
+            (Region::Empty, _) => {
+                if !matches!(source, Region::Empty) {
+                    edits.push(OctreeEdit::Clear {
+                        position: bounds.min_position,
+                        size: bounds.size,
+                    });
+                }
+            }
+            (Region::Uniform(data), _) => {
+                if !matches!(source, Region::Uniform(source_data) if source_data == data) {
+                    edits.push(OctreeEdit::Insert {
+                        position: bounds.min_position,
+                        size: bounds.size,
+                        data: data.clone(),
+                    });
+                }
+            }
+            (Region::Node(_), NodeContent::Internal(_)) => {
+                for octant in Octant::iter() {
+                    let child_bounds = bounds.child_bounds_for(octant);
+                    let child_source = match source {
+                        Region::Node(node_key) => match self.nodes.get(node_key) {
+                            NodeContent::Internal(_) => {
+                                let child_key = self.node_children[node_key][octant];
+                                if key_might_be_valid(child_key) {
+                                    self.region_of(child_key as usize)
+                                } else {
+                                    Region::Empty
+                                }
+                            }
+                            // The children of a leaf with a brick are not nodes of the tree
+                            _ => {
+                                self.push_voxel_delta_in(other, &child_bounds, edits);
+                                continue;
+                            }
+                        },
+                        _ => source,
+                    };
+                    let other_child_key = other.node_children[other_key][octant];
+                    if key_might_be_valid(other_child_key) {
+                        self.push_delta_in(
+                            child_source,
+                            other,
+                            other_child_key as usize,
+                            child_bounds,
+                            edits,
+                        );
+                    } else if !matches!(child_source, Region::Empty) {
+                        edits.push(OctreeEdit::Clear {
+                            position: child_bounds.min_position,
+                            size: child_bounds.size,
+                        });
+                    }
+                }
+            }
+            (Region::Node(_), NodeContent::Leaf(other_brick)) => {
+                if let Region::Node(node_key) = source {
+                    if let NodeContent::Leaf(brick) = self.nodes.get(node_key) {
+                        if self.bricks.get(*brick as usize).0
+                            == other.bricks.get(*other_brick as usize).0
+                        {
+                            return;
+                        }
+                    }
+                }
+                self.push_voxel_delta_in(other, &bounds, edits);
+            }
+            (Region::Node(_), _) => unreachable!(),
+        }
+    }
+
+    /// Collects the edits of every voxel inside the given bounds which differs in the other tree
+    fn push_voxel_delta_in(&self, other: &Self, bounds: &Cube, edits: &mut Vec<OctreeEdit<T>>) {
+        let min = bounds.min_position;
+        for x in min.x..(min.x + bounds.size) {
+            for y in min.y..(min.y + bounds.size) {
+                for z in min.z..(min.z + bounds.size) {
+                    let position = V3c::new(x, y, z);
+                    match (self.get(&position), other.get(&position)) {
+                        (Some(data), Some(other_data)) if data == other_data => {}
+                        (None, None) => {}
+                        (_, Some(other_data)) => edits.push(OctreeEdit::Insert {
+                            position,
+                            size: 1,
+                            data: other_data.clone(),
+                        }),
+                        (Some(_), None) => edits.push(OctreeEdit::Clear { position, size: 1 }),
+                    }
+                }
+            }
+        }
+    }
+}
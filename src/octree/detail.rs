@@ -1,11 +1,13 @@
-use crate::object_pool::key_none_value;
-use crate::octree::types::{NodeChildren, NodeChildrenArray, NodeContent, Octree, VoxelData};
-use crate::octree::{hash_region, Cube, V3c};
+use crate::object_pool::{key_might_be_valid, key_none_value, PoolKey};
+use crate::octree::types::{
+    Brick, CapacityPolicy, NodeChildren, NodeChildrenArray, NodeContent, Octree, OctreeError,
+    VoxelData,
+};
+use crate::octree::{Cube, Octant, V3c};
 
 ///####################################################################################
 /// Utility functions
 ///####################################################################################
-
 /// Returns whether the given bound contains the given position.
 pub(in crate::octree) fn bound_contains(bounds: &Cube, position: &V3c<u32>) -> bool {
     position.x >= bounds.min_position.x
@@ -16,13 +18,10 @@ pub(in crate::octree) fn bound_contains(bounds: &Cube, position: &V3c<u32>) -> b
         && position.z < bounds.min_position.z + bounds.size
 }
 
-/// Returns with the octant value(i.e. index) of the child for the given position
-pub(in crate::octree) fn child_octant_for(bounds: &Cube, position: &V3c<u32>) -> u32 {
+/// Returns with the octant of the child for the given position
+pub(in crate::octree) fn child_octant_for(bounds: &Cube, position: &V3c<u32>) -> Octant {
     debug_assert!(bound_contains(bounds, position));
-    hash_region(
-        &(*position - bounds.min_position).into(),
-        bounds.size as f32,
-    )
+    Octant::from_voxel_offset(&(*position - bounds.min_position), bounds.size)
 }
 
 ///####################################################################################
@@ -40,17 +39,23 @@ where
         Self {
             default_key,
             content: NodeChildrenArray::default(),
+            occupied_bits: 0,
         }
     }
 
-    pub(in crate::octree) fn from(default_key: T, children: [T; 8]) -> Self {
+    pub(in crate::octree) fn from(default_key: T, children: [T; 8], occupied_bits: u8) -> Self {
         Self {
             default_key,
             content: NodeChildrenArray::Children(children),
+            occupied_bits,
         }
     }
 
-    pub(in crate::octree) fn iter(&self) -> Option<std::slice::Iter<T>> {
+    pub(in crate::octree) fn is_occupied(&self, octant: Octant) -> bool {
+        0 != (self.occupied_bits & (1 << octant.index()))
+    }
+
+    pub(in crate::octree) fn iter(&self) -> Option<std::slice::Iter<'_, T>> {
         match &self.content {
             NodeChildrenArray::Children(c) => Some(c.iter()),
             _ => None,
@@ -61,6 +66,7 @@ where
         self.content = NodeChildrenArray::Children(children)
     }
 
+    #[cfg(feature = "bevy_wgpu")]
     pub(in crate::octree) fn get_full(&self) -> [T; 8] {
         match &self.content {
             NodeChildrenArray::Children(c) => c.clone(),
@@ -78,100 +84,98 @@ where
     }
 }
 
+impl NodeChildren<PoolKey> {
+    /// Shifts every valid child key by the given offset, e.g. after the nodes were moved to another pool
+    pub(in crate::octree) fn offset_keys(&mut self, offset: PoolKey) {
+        if let NodeChildrenArray::Children(children) = &mut self.content {
+            for child in children.iter_mut() {
+                if crate::object_pool::key_might_be_valid(*child) {
+                    *child += offset;
+                }
+            }
+        }
+    }
+}
+
 use std::{
     matches,
     ops::{Index, IndexMut},
 };
-impl<T> Index<u32> for NodeChildren<T>
+impl<T> Index<Octant> for NodeChildren<T>
 where
     T: Default + Copy + Clone,
 {
     type Output = T;
-    fn index(&self, index: u32) -> &T {
+    fn index(&self, octant: Octant) -> &T {
         match &self.content {
-            NodeChildrenArray::Children(c) => &c[index as usize],
+            NodeChildrenArray::Children(c) => &c[octant as usize],
             _ => &self.default_key,
         }
     }
 }
 
-impl<T> IndexMut<u32> for NodeChildren<T>
+impl<T> IndexMut<Octant> for NodeChildren<T>
 where
     T: Default + Copy + Clone,
 {
-    fn index_mut(&mut self, index: u32) -> &mut T {
+    fn index_mut(&mut self, octant: Octant) -> &mut T {
         if let NodeChildrenArray::NoChildren = &mut self.content {
             self.content = NodeChildrenArray::Children([self.default_key; 8]);
         }
         match &mut self.content {
-            NodeChildrenArray::Children(c) => &mut c[index as usize],
+            NodeChildrenArray::Children(c) => &mut c[octant as usize],
             _ => unreachable!(),
         }
     }
 }
 
 ///####################################################################################
-/// NodeContent
+/// NodeContent + Brick
 ///####################################################################################
-impl<T, const DIM: usize> NodeContent<T, DIM>
-where
-    T: PartialEq + Clone + Default,
-{
+impl<T: Clone> NodeContent<T> {
     pub fn is_leaf(&self) -> bool {
-        matches!(self, NodeContent::Leaf(_))
+        matches!(self, NodeContent::Leaf(_) | NodeContent::UniformLeaf(_))
     }
 
-    pub fn is_all(&self, data: &T) -> bool {
+    /// True if the node might contain voxels; Leaves with bricks are not inspected
+    pub fn is_occupied(&self) -> bool
+    where
+        T: VoxelData,
+    {
         match self {
-            NodeContent::Leaf(d) => {
-                for x in d.iter() {
-                    for y in x.iter() {
-                        for item in y.iter() {
-                            if *item != *data {
-                                return false;
-                            }
-                        }
-                    }
-                }
-                true
-            }
-            _ => false,
-        }
-    }
-
-    pub fn leaf_data(&self) -> &[[[T; DIM]; DIM]; DIM] {
-        match self {
-            NodeContent::Leaf(t) => t,
-            _ => panic!("leaf_data was called for NodeContent<T> where there is no content!"),
-        }
-    }
-
-    pub fn mut_leaf_data(&mut self) -> &mut [[[T; DIM]; DIM]; DIM] {
-        match self {
-            NodeContent::Leaf(t) => t,
-            _ => panic!("leaf_data was called for NodeContent<T> where there is no content!"),
-        }
-    }
-
-    pub fn as_leaf_ref(&self) -> Option<&[[[T; DIM]; DIM]; DIM]> {
-        match self {
-            NodeContent::Leaf(t) => Some(t),
-            _ => None,
+            NodeContent::Nothing => false,
+            NodeContent::Internal(count) => 0 < *count,
+            NodeContent::Leaf(_) => true,
+            NodeContent::UniformLeaf(data) => !data.is_empty(),
         }
     }
+}
 
-    pub fn as_mut_leaf_ref(&mut self) -> Option<&mut [[[T; DIM]; DIM]; DIM]> {
-        match self {
-            NodeContent::Leaf(t) => Some(t),
-            _ => None,
-        }
+impl<T, const DIM: usize> Default for Brick<T, DIM>
+where
+    T: Default + Clone,
+{
+    fn default() -> Self {
+        Self::filled_with(T::default())
     }
+}
 
-    pub fn leaf_from(data: T) -> Self {
-        NodeContent::Leaf(array_init::array_init(|_| {
+impl<T, const DIM: usize> Brick<T, DIM>
+where
+    T: Clone,
+{
+    pub(in crate::octree) fn filled_with(data: T) -> Self {
+        Brick(array_init::array_init(|_| {
             array_init::array_init(|_| array_init::array_init(|_| data.clone()))
         }))
     }
+
+    pub(in crate::octree) fn is_all(&self, data: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.0.iter().flatten().flatten().all(|item| *item == *data)
+    }
 }
 
 ///####################################################################################
@@ -182,10 +186,10 @@ where
     T: Default + Clone + VoxelData,
 {
     /// The root node is always the first item
-    pub(crate) const ROOT_NODE_KEY: u32 = 0;
+    pub(crate) const ROOT_NODE_KEY: PoolKey = 0;
 
     pub(crate) fn is_size_inadequate(size: u32) -> bool {
-        0 == size || (size as f32 / DIM as f32).log(2.0).fract() != 0.0
+        size < DIM as u32 || (size as f32 / DIM as f32).log(2.0).fract() != 0.0
     }
 }
 
@@ -205,26 +209,181 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
         mat_index
     }
 
-    pub(in crate::octree) fn make_uniform_children(
-        &mut self,
-        content: [[[T; DIM]; DIM]; DIM],
-    ) -> [u32; 8] {
-        let children = [
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content.clone())) as u32,
-            self.nodes.push(NodeContent::Leaf(content)) as u32,
-        ];
+    /// Calls the eviction callback of the tree, if any
+    fn evict(&mut self) {
+        // The callback is taken out for the duration of the call, as it needs to modify the tree
+        if let Some(mut eviction_callback) = self.eviction_callback.take() {
+            eviction_callback(self);
+            self.eviction_callback = Some(eviction_callback);
+        }
+    }
+
+    /// Makes sure the memory usage of the tree is within its budget before it grows further,
+    /// calling the eviction callback once should it be above
+    pub(in crate::octree) fn ensure_memory_budget(&mut self) -> Result<(), OctreeError> {
+        let Some(budget) = self.memory_budget else {
+            return Ok(());
+        };
+        if self.memory_usage() > budget {
+            self.evict();
+        }
+        let used = self.memory_usage();
+        if used > budget {
+            return Err(OctreeError::OutOfBudget { used, budget });
+        }
+        Ok(())
+    }
+
+    /// Returns whether inserting the given data at the given position and size would create new nodes or bricks,
+    /// following the same path as `insert_at_lod` without changing anything
+    pub(in crate::octree) fn insert_allocates(
+        &self,
+        position: &V3c<u32>,
+        insert_size: u32,
+        data: &T,
+    ) -> bool {
+        let mut node_key = Octree::<T, DIM>::ROOT_NODE_KEY as usize;
+        let mut bounds = Cube::root_bounds(self.octree_size);
+        while bounds.size > insert_size.max(DIM as u32) {
+            let octant = child_octant_for(&bounds, position);
+            let child_key = self.node_children[node_key][octant];
+            if !key_might_be_valid(child_key) {
+                // Either new children are created, or the leaf already contains the data
+                return !(self.nodes.get(node_key).is_leaf() && self.is_all(node_key, data));
+            }
+            node_key = child_key as usize;
+            bounds = bounds.child_bounds_for(octant);
+        }
+        match self.nodes.get(node_key) {
+            NodeContent::UniformLeaf(leaf_data) if leaf_data == data => false,
+            NodeContent::Leaf(_) if insert_size < DIM as u32 => false,
+            NodeContent::UniformLeaf(_) if insert_size < DIM as u32 => true,
+            _ => insert_size != DIM as u32 && insert_size < bounds.size,
+        }
+    }
+
+    /// Makes sure the pools of the tree have space for the nodes a single insert might create,
+    /// within the limit of the capacity policy of the tree
+    pub(in crate::octree) fn ensure_capacity(&mut self) -> Result<(), OctreeError> {
+        let max_nodes = match self.capacity_policy {
+            CapacityPolicy::Grow => None,
+            CapacityPolicy::Fail { max_nodes } => Some(max_nodes),
+            CapacityPolicy::Evict { max_nodes } => {
+                if self.nodes.count() >= max_nodes {
+                    self.evict();
+                }
+                Some(max_nodes)
+            }
+        };
+        if max_nodes.is_some_and(|max_nodes| self.nodes.count() >= max_nodes) {
+            return Err(OctreeError::OutOfMemory);
+        }
+
+        // An insert creates at most the children of each node along the path to the inserted position
+        let new_nodes = 8 * self.depth() as usize;
+        self.nodes
+            .try_reserve(new_nodes)
+            .and(self.node_children.try_reserve(new_nodes))
+            .and(self.bricks.try_reserve(new_nodes))
+            .map_err(|_| OctreeError::OutOfMemory)
+    }
+
+    /// Provides the voxels of the given leaf node mutably, panics if the node is not a leaf
+    /// A uniform leaf is given a brick first, as the voxels inside it are about to differ
+    pub(in crate::octree) fn mut_leaf_data(&mut self, node: usize) -> &mut [[[T; DIM]; DIM]; DIM] {
+        if let NodeContent::UniformLeaf(data) = self.nodes.get(node) {
+            let brick_key = self.bricks.push(Brick::filled_with(data.clone())) as PoolKey;
+            *self.nodes.get_mut(node) = NodeContent::Leaf(brick_key);
+        }
+        match self.nodes.get(node) {
+            NodeContent::Leaf(brick) => {
+                self.dirty_bricks.insert(*brick);
+                &mut self.bricks.get_mut(*brick as usize).0
+            }
+            _ => panic!("mut_leaf_data was called for a Node which is not a leaf!"),
+        }
+    }
+
+    /// True if the given leaf contents hold the same voxels
+    fn leaves_match(&self, a: &NodeContent<T>, b: &NodeContent<T>) -> bool {
+        match (a, b) {
+            (NodeContent::UniformLeaf(a), NodeContent::UniformLeaf(b)) => a == b,
+            (NodeContent::Leaf(a), NodeContent::Leaf(b)) => {
+                a == b || self.bricks.get(*a as usize).0 == self.bricks.get(*b as usize).0
+            }
+            (NodeContent::UniformLeaf(data), NodeContent::Leaf(brick))
+            | (NodeContent::Leaf(brick), NodeContent::UniformLeaf(data)) => {
+                self.bricks.get(*brick as usize).is_all(data)
+            }
+            _ => false,
+        }
+    }
+
+    /// True if the given node is a leaf with every voxel equal to the given data
+    pub(in crate::octree) fn is_all(&self, node: usize, data: &T) -> bool {
+        match self.nodes.get(node) {
+            NodeContent::Leaf(brick) => self.bricks.get(*brick as usize).is_all(data),
+            NodeContent::UniformLeaf(leaf_data) => leaf_data == data,
+            _ => false,
+        }
+    }
+
+    /// Updates the content of the given node, freeing up its brick should it stop being a leaf
+    pub(in crate::octree) fn set_node_content(&mut self, node: usize, content: NodeContent<T>) {
+        if let NodeContent::Leaf(brick) = *self.nodes.get(node) {
+            if !matches!(content, NodeContent::Leaf(new_brick) if new_brick == brick) {
+                self.bricks.free(brick as usize);
+            }
+        }
+        *self.nodes.get_mut(node) = content;
+    }
+
+    /// Makes the given node a leaf with the given voxels, re-using its brick if it is already a leaf
+    pub(in crate::octree) fn make_leaf(&mut self, node: usize, brick: Brick<T, DIM>) {
+        match self.nodes.get(node) {
+            NodeContent::Leaf(brick_key) => {
+                self.dirty_bricks.insert(*brick_key);
+                *self.bricks.get_mut(*brick_key as usize) = brick;
+            }
+            _ => {
+                let brick_key = self.bricks.push(brick) as PoolKey;
+                self.dirty_bricks.insert(brick_key);
+                *self.nodes.get_mut(node) = NodeContent::Leaf(brick_key);
+            }
+        }
+    }
+
+    /// Frees up the given node along with its brick
+    pub(in crate::octree) fn free_node(&mut self, node: usize) {
+        if let NodeContent::Leaf(brick) = *self.nodes.get(node) {
+            self.bricks.free(brick as usize);
+        }
+        self.nodes.free(node);
+    }
+
+    /// Creates 8 leaf nodes with the same content as the given leaf node
+    /// Children of uniform leaves are uniform leaves themselves, so no bricks are allocated for them
+    pub(in crate::octree) fn make_uniform_children(&mut self, node: usize) -> [PoolKey; 8] {
+        let content = self.nodes.get(node).clone();
+        debug_assert!(content.is_leaf());
+        let children = array_init::array_init(|_| {
+            let child_content = match &content {
+                NodeContent::Leaf(brick) => {
+                    let brick = self.bricks.get(*brick as usize).clone();
+                    let brick_key = self.bricks.push(brick) as PoolKey;
+                    self.dirty_bricks.insert(brick_key);
+                    NodeContent::Leaf(brick_key)
+                }
+                content => content.clone(),
+            };
+            self.nodes.push(child_content) as PoolKey
+        });
         self.node_children
             .resize(self.nodes.len(), NodeChildren::new(key_none_value()));
         children
     }
 
-    pub(in crate::octree) fn deallocate_children_of(&mut self, node: u32) {
+    pub(in crate::octree) fn deallocate_children_of(&mut self, node: PoolKey) {
         let mut to_deallocate = Vec::new();
         if let Some(children) = self.node_children[node as usize].iter() {
             for child in children {
@@ -234,33 +393,83 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
             }
             for child in to_deallocate {
                 self.deallocate_children_of(child); // Recursion should be fine as depth is not expceted to be more, than 32
-                self.nodes.free(child as usize);
+                self.free_node(child as usize);
             }
         }
         self.node_children[node as usize].content = NodeChildrenArray::NoChildren;
+        self.node_children[node as usize].occupied_bits = 0;
+    }
+
+    /// True if there are any voxels inside the given node: internal nodes are decided by their occupancy bits,
+    /// and the bricks of leaves are inspected, so the occupancy bits of their parents are exact
+    pub(in crate::octree) fn node_has_content(&self, node: usize) -> bool {
+        match self.nodes.get(node) {
+            NodeContent::Nothing => false,
+            NodeContent::Internal(_) => 0 != self.node_children[node].occupied_bits,
+            NodeContent::Leaf(brick) => self
+                .bricks
+                .get(*brick as usize)
+                .0
+                .iter()
+                .flatten()
+                .flatten()
+                .any(|data| !data.is_empty()),
+            NodeContent::UniformLeaf(data) => !data.is_empty(),
+        }
+    }
+
+    /// Updates the occupancy bits of the given node based on the content of its children
+    pub(in crate::octree) fn update_occupied_bits(&mut self, node: PoolKey) {
+        let mut occupied_bits = 0;
+        for octant in Octant::iter() {
+            let child_key = self.node_children[node as usize][octant];
+            if crate::object_pool::key_might_be_valid(child_key)
+                && self.node_has_content(child_key as usize)
+            {
+                occupied_bits |= 1 << octant.index();
+            }
+        }
+        self.node_children[node as usize].occupied_bits = occupied_bits;
+    }
+
+    /// True if there are any voxels inside the children of the given node
+    pub(in crate::octree) fn has_occupied_child(&self, node: PoolKey) -> bool {
+        Octant::iter().any(|octant| {
+            let child_key = self.node_children[node as usize][octant];
+            crate::object_pool::key_might_be_valid(child_key)
+                && self.node_has_content(child_key as usize)
+        })
     }
 
     /// Updates the given node recursively to collapse nodes with uniform children into a leaf
-    pub(in crate::octree) fn simplify(&mut self, node: u32) -> bool {
-        let mut data = NodeContent::Nothing;
+    pub(in crate::octree) fn simplify(&mut self, node: PoolKey) -> bool {
+        let mut data: Option<&NodeContent<T>> = None;
         if crate::object_pool::key_might_be_valid(node) {
-            for i in 0..8 {
-                let child_key = self.node_children[node as usize][i];
+            for octant in Octant::iter() {
+                let child_key = self.node_children[node as usize][octant];
                 if crate::object_pool::key_might_be_valid(child_key) {
-                    if let Some(leaf_data) = self.nodes.get(child_key as usize).as_leaf_ref() {
-                        if !data.is_leaf() {
-                            data = NodeContent::Leaf(leaf_data.clone());
-                        } else if data.leaf_data() != leaf_data {
-                            return false;
-                        }
-                    } else {
+                    let leaf_content = self.nodes.get(child_key as usize);
+                    if !leaf_content.is_leaf() {
                         return false;
                     }
+                    match data {
+                        None => data = Some(leaf_content),
+                        Some(d) if !self.leaves_match(d, leaf_content) => return false,
+                        // Uniform leaves are preferred, as they need no brick
+                        Some(NodeContent::Leaf(_)) => data = Some(leaf_content),
+                        _ => {}
+                    }
                 } else {
                     return false;
                 }
             }
-            *self.nodes.get_mut(node as usize) = data;
+            match data.unwrap().clone() {
+                NodeContent::Leaf(brick) => {
+                    let brick = self.bricks.get(brick as usize).clone();
+                    self.make_leaf(node as usize, brick);
+                }
+                content => self.set_node_content(node as usize, content),
+            }
             self.deallocate_children_of(node); // no need to use this as all the children are leaves, but it's more understanfdable this way
             true
         } else {
@@ -268,23 +477,102 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
         }
     }
 
-    /// Count the number of children a Node has according to the stored cache of the children
-    pub(in crate::octree) fn count_cached_children(&self, node: u32) -> u32 {
+    /// Replaces the child of the given node with the given bounds in the given octant with the root of the given tree,
+    /// moving its nodes and bricks into the pools of this tree; an empty tree leaves the octant empty.
+    /// The caller is responsible for the size of the subtree matching the size of the octant,
+    /// and for updating the voxel counts and occupancy of the ancestors of the given node.
+    pub(in crate::octree) fn graft_subtree(
+        &mut self,
+        node: PoolKey,
+        bounds: &Cube,
+        octant: Octant,
+        subtree: Self,
+    ) {
+        // The given node needs to have children for the subtree to be placed under it
+        if self.nodes.get(node as usize).is_leaf() {
+            let children = self.make_uniform_children(node as usize);
+            self.set_node_content(node as usize, NodeContent::Internal(0));
+            self.node_children[node as usize].set(children);
+        }
+        let previous_child = self.node_children[node as usize][octant];
+        if crate::object_pool::key_might_be_valid(previous_child) {
+            self.deallocate_children_of(previous_child);
+            self.free_node(previous_child as usize);
+            self.node_children[node as usize][octant] = key_none_value();
+        }
+
+        if subtree
+            .nodes
+            .get(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
+            .is_occupied()
+        {
+            let node_offset = self.nodes.append(subtree.nodes);
+            let brick_offset = self.bricks.append(subtree.bricks) as PoolKey;
+            debug_assert!(self.node_children.len() == node_offset);
+            self.node_children
+                .extend(subtree.node_children.into_iter().map(|mut children| {
+                    children.offset_keys(node_offset as PoolKey);
+                    children
+                }));
+            for key in node_offset..self.nodes.len() {
+                if !self.nodes.key_is_valid(key) {
+                    continue;
+                }
+                if let NodeContent::Leaf(brick) = self.nodes.get_mut(key) {
+                    *brick += brick_offset;
+                    let brick = *brick;
+                    self.dirty_bricks.insert(brick);
+                }
+            }
+
+            // The root node of the subtree is the first item of its pool
+            self.node_children[node as usize][octant] =
+                node_offset as PoolKey + Octree::<T, DIM>::ROOT_NODE_KEY;
+        }
+
+        let count = self.count_cached_children(node, bounds);
+        if 0 < count {
+            *self.nodes.get_mut(node as usize) = NodeContent::Internal(count);
+        } else {
+            self.deallocate_children_of(node);
+            *self.nodes.get_mut(node as usize) = NodeContent::Nothing;
+        }
+        self.update_occupied_bits(node);
+    }
+
+    /// Count the number of voxels inside the children of the given node with the given bounds,
+    /// according to the counts stored in the internal children and the voxels of the leaf children
+    pub(in crate::octree) fn count_cached_children(&self, node: PoolKey, bounds: &Cube) -> u32 {
         let mut actual_count = 0;
-        for i in 0..8 {
-            let child_key = self.node_children[node as usize][i];
+        for octant in Octant::iter() {
+            let child_key = self.node_children[node as usize][octant];
             if crate::object_pool::key_might_be_valid(child_key) {
-                match self.nodes.get(child_key as usize) {
-                    NodeContent::Leaf(_) => {
-                        actual_count += (DIM as u32).pow(3);
-                    }
-                    NodeContent::Internal(c) => {
-                        actual_count += c;
-                    }
-                    _ => {}
-                }
+                actual_count += self.voxel_count(child_key, &bounds.child_bounds_for(octant));
             }
         }
         actual_count
     }
+
+    /// The number of voxels inside the given node with the given bounds: the stored count of internal nodes,
+    /// or the voxels of leaves; each cell of a leaf larger than its brick stands for multiple voxels
+    pub(in crate::octree) fn voxel_count(&self, node: PoolKey, bounds: &Cube) -> u32 {
+        match self.nodes.get(node as usize) {
+            NodeContent::Nothing => 0,
+            NodeContent::Internal(count) => *count,
+            NodeContent::UniformLeaf(data) if data.is_empty() => 0,
+            NodeContent::UniformLeaf(_) => bounds.size.pow(3),
+            NodeContent::Leaf(brick) => {
+                let occupied_cells = self
+                    .bricks
+                    .get(*brick as usize)
+                    .0
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|data| !data.is_empty())
+                    .count() as u32;
+                occupied_cells * (bounds.size / DIM as u32).pow(3)
+            }
+        }
+    }
 }
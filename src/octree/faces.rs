@@ -0,0 +1,256 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    fields::boundary_voxels, types::NodeContent, Cube, Octant, Octree, V3c, VoxelData, VoxelWorld,
+};
+use crate::spatial::raytracing::BoxFace;
+
+/// How the faces on the boundary between chunks shown in different resolutions are handled,
+/// see `VoxelWorld::visible_faces_of_chunk_lod`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SeamHandling {
+    /// Neighbouring chunks are sampled in the resolution of the chunk itself,
+    /// which leaves cracks wherever the two resolutions differ
+    Ignore,
+    /// Every face on the boundary to a chunk shown in another resolution is kept like a skirt,
+    /// covering the cracks at the cost of some overdraw
+    Skirts,
+    /// Faces on the boundary are culled against the neighbouring chunk in the resolution it is shown in.
+    /// Faces of coarse voxels are only culled when every voxel of the neighbour behind them is filled
+    #[default]
+    Stitched,
+}
+
+impl BoxFace {
+    /// The offset to the neighbouring voxel on the side of the face
+    fn neighbour_offset(&self) -> V3c<i32> {
+        V3c::<i32>::from(self.normal())
+    }
+
+    /// True if the given position is on the side of the face inside the given region
+    fn is_on_side_of(&self, region: &Cube, position: &V3c<u32>) -> bool {
+        let min = region.min_position;
+        let max = min + V3c::unit(region.size - 1);
+        match self {
+            BoxFace::NegativeX => position.x == min.x,
+            BoxFace::PositiveX => position.x == max.x,
+            BoxFace::NegativeY => position.y == min.y,
+            BoxFace::PositiveY => position.y == max.y,
+            BoxFace::NegativeZ => position.z == min.z,
+            BoxFace::PositiveZ => position.z == max.z,
+        }
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The faces of the voxels inside the given bounds which are not covered by a neighbouring voxel,
+    /// e.g. to build a mesh from. Faces on the boundary of the tree are visible.
+    /// Calculated for each leaf at once: neighbours inside the leaf are looked up from its brick
+    /// directly instead of through the tree, and the inside of uniform regions is skipped.
+    pub fn visible_faces_in(&self, bounds: &Cube) -> impl Iterator<Item = (V3c<u32>, BoxFace)> {
+        self.visible_faces_in_with(bounds, |_| false)
+    }
+
+    /// Same as `visible_faces_in`, with the voxels outside the tree provided by the given sampler,
+    /// e.g. from the neighbouring chunks of a world, so faces on the boundary of the tree are only
+    /// visible where the neighbouring voxel is empty.
+    /// * `filled_outside` - True if there is data at the given position outside the tree,
+    ///   given relative to the tree
+    pub fn visible_faces_in_with(
+        &self,
+        bounds: &Cube,
+        filled_outside: impl Fn(&V3c<i32>) -> bool,
+    ) -> impl Iterator<Item = (V3c<u32>, BoxFace)> {
+        let mut faces = Vec::new();
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            Cube::root_bounds(self.octree_size),
+        )];
+        while let Some((node_key, node_bounds)) = node_stack.pop() {
+            if !node_bounds.intersects(bounds) {
+                continue;
+            }
+            match self.nodes.get(node_key as usize) {
+                NodeContent::Nothing => {}
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key as usize][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key, node_bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        self.push_visible_faces(
+                            &node_bounds,
+                            &node_bounds,
+                            &|_| true,
+                            &filled_outside,
+                            bounds,
+                            &mut faces,
+                        );
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (node_bounds.size / DIM as u32).max(1);
+                    let matrix = &self.bricks.get(*brick as usize).0;
+                    // The neighbours inside the brick are looked up from it directly
+                    let filled_at = |position: &V3c<u32>| {
+                        let index = (*position - node_bounds.min_position) / cell_size;
+                        !matrix[index.x as usize][index.y as usize][index.z as usize].is_empty()
+                    };
+                    for (x, plane) in matrix.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                if data.is_empty() {
+                                    continue;
+                                }
+                                let cell = Cube::new(
+                                    node_bounds.min_position
+                                        + V3c::new(x as u32, y as u32, z as u32) * cell_size,
+                                    cell_size,
+                                );
+                                self.push_visible_faces(
+                                    &cell,
+                                    &node_bounds,
+                                    &filled_at,
+                                    &filled_outside,
+                                    bounds,
+                                    &mut faces,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        faces.into_iter()
+    }
+
+    /// Collects the visible faces on the boundary of the given region of voxels with data
+    /// * `region` - The region of equal voxels inside a leaf
+    /// * `leaf_bounds` - The bounds of the leaf containing the region
+    /// * `leaf_filled_at` - True if there is data at the given position inside the leaf
+    /// * `filled_outside` - True if there is data at the given position outside the tree
+    /// * `bounds` - The bounds to collect the faces inside of
+    fn push_visible_faces(
+        &self,
+        region: &Cube,
+        leaf_bounds: &Cube,
+        leaf_filled_at: &impl Fn(&V3c<u32>) -> bool,
+        filled_outside: &impl Fn(&V3c<i32>) -> bool,
+        bounds: &Cube,
+        faces: &mut Vec<(V3c<u32>, BoxFace)>,
+    ) {
+        let size = self.octree_size as i32;
+        for position in boundary_voxels(region) {
+            if !bounds.contains(&position) {
+                continue;
+            }
+            for face in BoxFace::ALL {
+                if !face.is_on_side_of(region, &position) {
+                    continue;
+                }
+                let neighbour = V3c::<i32>::from(position) + face.neighbour_offset();
+                let filled = if neighbour.x < 0
+                    || neighbour.y < 0
+                    || neighbour.z < 0
+                    || neighbour.x >= size
+                    || neighbour.y >= size
+                    || neighbour.z >= size
+                {
+                    filled_outside(&neighbour)
+                } else if leaf_bounds.contains(&V3c::<u32>::from(neighbour)) {
+                    leaf_filled_at(&V3c::<u32>::from(neighbour))
+                } else {
+                    self.get(&V3c::<u32>::from(neighbour)).is_some()
+                };
+                if !filled {
+                    faces.push((position, face));
+                }
+            }
+        }
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelWorld<T, DIM> {
+    /// The visible faces of the voxels in the given chunk, with the neighbouring chunks taken
+    /// into account on its boundary: faces between two chunks are only visible on the side of
+    /// the chunk with the voxel in it, and not at all between two voxels. Missing neighbouring
+    /// chunks count as empty. Positions are given inside the chunk, see `Octree::visible_faces_in`.
+    pub fn visible_faces_of_chunk(
+        &self,
+        chunk_coord: &V3c<i32>,
+    ) -> impl Iterator<Item = (V3c<u32>, BoxFace)> {
+        let faces = self.chunks.get(chunk_coord).map(|chunk| {
+            chunk
+                .visible_faces_in_with(&Cube::root_bounds(self.chunk_size), |position| {
+                    let world_position = *chunk_coord * self.chunk_size as i32 + *position;
+                    self.get(&world_position).is_some()
+                })
+                .collect::<Vec<_>>()
+        });
+        faces.unwrap_or_default().into_iter()
+    }
+
+    /// Same as `visible_faces_of_chunk`, with chunks shown in the resolution decided by the given function:
+    /// the coarse version of the chunks it selects is used where present, see `insert_coarse_chunk`.
+    /// Faces on the boundary to a chunk shown in another resolution are handled by `seam_handling`.
+    /// Positions are given inside the version of the chunk it is shown in.
+    /// * `shown_coarse` - True if the chunk at the given coordinates is shown in its coarse version
+    pub fn visible_faces_of_chunk_lod(
+        &self,
+        chunk_coord: &V3c<i32>,
+        shown_coarse: impl Fn(&V3c<i32>) -> bool,
+    ) -> impl Iterator<Item = (V3c<u32>, BoxFace)> {
+        let faces = self
+            .chunk_version(chunk_coord, shown_coarse(chunk_coord))
+            .map(|chunk| {
+                let scale = self.chunk_size / chunk.octree_size;
+                chunk
+                    .visible_faces_in_with(&Cube::root_bounds(chunk.octree_size), |position| {
+                        let world_position =
+                            *chunk_coord * self.chunk_size as i32 + *position * scale as i32;
+                        self.is_shown_filled(&world_position, scale, &shown_coarse)
+                    })
+                    .collect::<Vec<_>>()
+            });
+        faces.unwrap_or_default().into_iter()
+    }
+
+    /// The version of the chunk at the given coordinates to show: its coarse version if asked for and present
+    fn chunk_version(&self, chunk_coord: &V3c<i32>, coarse: bool) -> Option<&Octree<T, DIM>> {
+        coarse
+            .then(|| self.coarse_chunks.get(chunk_coord))
+            .flatten()
+            .or_else(|| self.chunks.get(chunk_coord))
+    }
+
+    /// True if the region of the given size at the given world position is filled in the chunk containing it,
+    /// as it is shown next to a chunk with voxels of the given size, see `SeamHandling`
+    fn is_shown_filled(
+        &self,
+        position: &V3c<i32>,
+        size: u32,
+        shown_coarse: &impl Fn(&V3c<i32>) -> bool,
+    ) -> bool {
+        let (chunk_coord, local) = self.chunk_coord_of(position);
+        let coarse = match self.seam_handling {
+            SeamHandling::Ignore => 1 < size,
+            SeamHandling::Skirts | SeamHandling::Stitched => shown_coarse(&chunk_coord),
+        };
+        let Some(chunk) = self.chunk_version(&chunk_coord, coarse) else {
+            return false;
+        };
+        let scale = self.chunk_size / chunk.octree_size;
+        if SeamHandling::Skirts == self.seam_handling && scale != size {
+            return false;
+        }
+        let from = local / scale;
+        let to = (local + V3c::unit(size - 1)) / scale;
+        (from.x..=to.x).all(|x| {
+            (from.y..=to.y)
+                .all(|y| (from.z..=to.z).all(|z| chunk.get(&V3c::new(x, y, z)).is_some()))
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    types::{NodeContent, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+
+/// A scalar quantity stored inside the voxels of a tree, e.g. temperature or gas concentration
+pub trait ScalarChannel<T> {
+    /// The value of the quantity inside the given voxel
+    fn value(&self, voxel: &T) -> f32;
+    /// Updates the value of the quantity inside the given voxel
+    fn set_value(&self, voxel: &mut T, value: f32);
+}
+
+/// The positions on the surface of the given region; the inside of uniform regions has equal
+/// neighbours everywhere, so only the voxels on their boundary differ from their neighbours
+pub(in crate::octree) fn boundary_voxels(region: &Cube) -> Vec<V3c<u32>> {
+    let min = region.min_position;
+    let max = min + V3c::unit(region.size - 1);
+    let mut positions = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            let on_side = x == min.x || x == max.x || y == min.y || y == max.y;
+            let mut z = min.z;
+            while z <= max.z {
+                positions.push(V3c::new(x, y, z));
+                z = if on_side || z == max.z { z + 1 } else { max.z };
+            }
+        }
+    }
+    positions
+}
+
+/// Runs one explicit step of diffusion on the given channel of the voxels in the tree:
+/// each voxel exchanges `dt * rate * difference` with each of its 6 neighbours.
+/// Empty voxels do not take part, so the quantity is kept inside the voxels with data,
+/// and its total is conserved. The step is stable while `dt * rate` is at most 1/6.
+/// Only the boundaries of uniform regions are visited, as nothing changes inside them.
+/// * `channel` - The quantity to diffuse
+/// * `dt` - The length of the time step
+/// * `rate` - The diffusion coefficient of the quantity
+pub fn diffuse<T, C, const DIM: usize>(
+    tree: &mut Octree<T, DIM>,
+    channel: &C,
+    dt: f32,
+    rate: f32,
+) -> Result<(), OctreeError>
+where
+    T: Default + PartialEq + Clone + VoxelData,
+    C: ScalarChannel<T>,
+{
+    // Regions with the same data inside them
+    let mut regions = Vec::new();
+    let mut node_stack = vec![(
+        Octree::<T, DIM>::ROOT_NODE_KEY,
+        Cube::root_bounds(tree.octree_size),
+    )];
+    while let Some((node_key, bounds)) = node_stack.pop() {
+        match tree.nodes.get(node_key as usize) {
+            NodeContent::Nothing => {}
+            NodeContent::Internal(_) => {
+                for octant in Octant::iter() {
+                    let child_key = tree.node_children[node_key as usize][octant];
+                    if key_might_be_valid(child_key) {
+                        node_stack.push((child_key, bounds.child_bounds_for(octant)));
+                    }
+                }
+            }
+            NodeContent::UniformLeaf(data) => {
+                if !data.is_empty() {
+                    regions.push(bounds);
+                }
+            }
+            NodeContent::Leaf(brick) => {
+                let cell_size = (bounds.size / DIM as u32).max(1);
+                for (x, plane) in tree.bricks.get(*brick as usize).0.iter().enumerate() {
+                    for (y, row) in plane.iter().enumerate() {
+                        for (z, data) in row.iter().enumerate() {
+                            if !data.is_empty() {
+                                regions.push(Cube::new(
+                                    bounds.min_position
+                                        + V3c::new(x as u32, y as u32, z as u32) * cell_size,
+                                    cell_size,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Changes are calculated from the current state, before any of them are applied
+    let mut updates = Vec::new();
+    for region in regions {
+        for position in boundary_voxels(&region) {
+            let data = tree.get(&position).unwrap();
+            let value = channel.value(data);
+            let mut flux = 0.;
+            for (dx, dy, dz) in [
+                (-1, 0, 0),
+                (1, 0, 0),
+                (0, -1, 0),
+                (0, 1, 0),
+                (0, 0, -1),
+                (0, 0, 1),
+            ] {
+                let (Some(x), Some(y), Some(z)) = (
+                    position.x.checked_add_signed(dx),
+                    position.y.checked_add_signed(dy),
+                    position.z.checked_add_signed(dz),
+                ) else {
+                    continue;
+                };
+                if let Some(neighbour) = tree.get(&V3c::new(x, y, z)) {
+                    flux += channel.value(neighbour) - value;
+                }
+            }
+            if 0. != flux {
+                let mut data = data.clone();
+                channel.set_value(&mut data, value + dt * rate * flux);
+                updates.push((position, data));
+            }
+        }
+    }
+
+    for (position, data) in updates {
+        tree.insert(&position, data)?;
+    }
+    Ok(())
+}
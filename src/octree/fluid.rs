@@ -0,0 +1,276 @@
+use crate::object_pool::{key_might_be_valid, key_none_value, ObjectPool, PoolKey};
+use crate::octree::{
+    detail::{bound_contains, child_octant_for},
+    types::OctreeError,
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+
+/// The fluid level of a completely filled voxel
+pub const MAX_FLUID_LEVEL: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub(in crate::octree) enum FluidNode<const DIM: usize> {
+    /// The total fluid volume inside the node, and the keys of the wet children
+    Internal { volume: u32, children: [PoolKey; 8] },
+    /// The total fluid volume inside the node, and the fluid level of each voxel in it
+    Leaf {
+        volume: u32,
+        levels: [[[u8; DIM]; DIM]; DIM],
+    },
+}
+
+impl<const DIM: usize> Default for FluidNode<DIM> {
+    fn default() -> Self {
+        FluidNode::Internal {
+            volume: 0,
+            children: [key_none_value(); 8],
+        }
+    }
+}
+
+impl<const DIM: usize> FluidNode<DIM> {
+    fn volume(&self) -> u32 {
+        match self {
+            FluidNode::Internal { volume, .. } | FluidNode::Leaf { volume, .. } => *volume,
+        }
+    }
+}
+
+/// Fluid levels stored in a sparse tree matching the bounds of the octree nodes:
+/// only wet nodes are allocated, so dry regions cost nothing to store or to simulate.
+/// Leaves span DIM voxels along each axis.
+#[derive(Clone)]
+pub(in crate::octree) struct FluidLayer<const DIM: usize> {
+    pub(in crate::octree) root: PoolKey,
+    pub(in crate::octree) nodes: ObjectPool<FluidNode<DIM>>,
+}
+
+impl<const DIM: usize> Default for FluidLayer<DIM> {
+    fn default() -> Self {
+        Self {
+            root: key_none_value(),
+            nodes: ObjectPool::default(),
+        }
+    }
+}
+
+impl<const DIM: usize> FluidLayer<DIM> {
+    /// The total fluid volume stored in the layer
+    pub(in crate::octree) fn volume(&self) -> u32 {
+        if key_might_be_valid(self.root) {
+            self.nodes.get(self.root as usize).volume()
+        } else {
+            0
+        }
+    }
+
+    /// The fluid level at the given position
+    /// * `size` - The size of the tree the layer belongs to
+    pub(in crate::octree) fn level_at(&self, size: u32, position: &V3c<u32>) -> u8 {
+        let mut current_bounds = Cube::root_bounds(size);
+        let mut current_key = self.root;
+        while key_might_be_valid(current_key) {
+            match self.nodes.get(current_key as usize) {
+                FluidNode::Leaf { levels, .. } => {
+                    let index = *position - current_bounds.min_position;
+                    return levels[index.x as usize][index.y as usize][index.z as usize];
+                }
+                FluidNode::Internal { children, .. } => {
+                    let octant = child_octant_for(&current_bounds, position);
+                    current_key = children[octant as usize];
+                    current_bounds = current_bounds.child_bounds_for(octant);
+                }
+            }
+        }
+        0
+    }
+
+    /// Changes the fluid level at the given position by the given amount, updating the volume
+    /// of every node containing it. Nodes are allocated as they get wet and freed once dry.
+    /// * `size` - The size of the tree the layer belongs to
+    pub(in crate::octree) fn change_level(&mut self, size: u32, position: &V3c<u32>, delta: i32) {
+        self.root = self.change_level_in(self.root, Cube::root_bounds(size), position, delta);
+    }
+
+    /// Changes the fluid level inside the given node, returns the key of the node afterwards
+    fn change_level_in(
+        &mut self,
+        node_key: PoolKey,
+        bounds: Cube,
+        position: &V3c<u32>,
+        delta: i32,
+    ) -> PoolKey {
+        let node_key = if key_might_be_valid(node_key) {
+            node_key
+        } else if bounds.size <= DIM as u32 {
+            self.nodes.push(FluidNode::Leaf {
+                volume: 0,
+                levels: [[[0; DIM]; DIM]; DIM],
+            }) as PoolKey
+        } else {
+            self.nodes.push(FluidNode::default()) as PoolKey
+        };
+
+        let child = match self.nodes.get(node_key as usize) {
+            FluidNode::Internal { children, .. } => {
+                let octant = child_octant_for(&bounds, position);
+                Some((octant, children[octant as usize]))
+            }
+            FluidNode::Leaf { .. } => None,
+        };
+        let updated_child = child.map(|(octant, child_key)| {
+            (
+                octant,
+                self.change_level_in(child_key, bounds.child_bounds_for(octant), position, delta),
+            )
+        });
+
+        let volume = match self.nodes.get_mut(node_key as usize) {
+            FluidNode::Internal { volume, children } => {
+                let (octant, child_key) = updated_child.unwrap();
+                children[octant as usize] = child_key;
+                *volume = volume.saturating_add_signed(delta);
+                *volume
+            }
+            FluidNode::Leaf { volume, levels } => {
+                let index = *position - bounds.min_position;
+                let level = &mut levels[index.x as usize][index.y as usize][index.z as usize];
+                *level = (*level as i32 + delta) as u8;
+                *volume = volume.saturating_add_signed(delta);
+                *volume
+            }
+        };
+
+        // Dry nodes are not kept; their children are already freed as they dried up too
+        if 0 == volume {
+            self.nodes.free(node_key as usize);
+            return key_none_value();
+        }
+        node_key
+    }
+
+    /// The positions of every voxel with fluid in it, dry subtrees are skipped
+    /// * `size` - The size of the tree the layer belongs to
+    pub(in crate::octree) fn wet_voxels(&self, size: u32) -> Vec<V3c<u32>> {
+        let mut wet = Vec::new();
+        let mut node_stack = vec![(self.root, Cube::root_bounds(size))];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            if !key_might_be_valid(node_key) {
+                continue;
+            }
+            match self.nodes.get(node_key as usize) {
+                FluidNode::Internal { children, .. } => {
+                    for (octant, child_key) in Octant::iter().zip(children.iter()) {
+                        node_stack.push((*child_key, bounds.child_bounds_for(octant)));
+                    }
+                }
+                FluidNode::Leaf { levels, .. } => {
+                    for (x, plane) in levels.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, level) in row.iter().enumerate() {
+                                if 0 < *level {
+                                    wet.push(
+                                        bounds.min_position
+                                            + V3c::new(x as u32, y as u32, z as u32),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        wet
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The fluid level at the given position, 0..=MAX_FLUID_LEVEL
+    pub fn fluid_at(&self, position: &V3c<u32>) -> u8 {
+        if !bound_contains(&Cube::root_bounds(self.octree_size), position) {
+            return 0;
+        }
+        self.fluids.level_at(self.octree_size, position)
+    }
+
+    /// The total fluid volume inside the tree, which is kept by `step_fluids`
+    pub fn fluid_volume(&self) -> u32 {
+        self.fluids.volume()
+    }
+
+    /// Pours fluid into the voxel at the given position, up until it is full.
+    /// Fluid can not be poured into voxels with data in them.
+    /// Returns the amount of fluid actually added.
+    pub fn add_fluid(&mut self, position: &V3c<u32>, amount: u8) -> Result<u8, OctreeError> {
+        if !bound_contains(&Cube::root_bounds(self.octree_size), position) {
+            return Err(OctreeError::InvalidPosition {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            });
+        }
+        if self.get(position).is_some() {
+            return Ok(0);
+        }
+        let added = amount.min(MAX_FLUID_LEVEL - self.fluid_at(position));
+        if 0 < added {
+            self.fluids
+                .change_level(self.octree_size, position, added as i32);
+        }
+        Ok(added)
+    }
+
+    /// Advances the fluid simulation by one step: fluid falls into the voxel below it if it can,
+    /// and what remains spreads one level at a time towards lower neighbouring voxels on the sides.
+    /// The total volume of fluid is conserved; only wet regions of the tree are visited.
+    /// Voxels with data in them block fluids, fluid already inside them is not displaced.
+    pub fn step_fluids(&mut self) {
+        let size = self.octree_size;
+        let mut wet = self.fluids.wet_voxels(size);
+        // Processed bottom up, so fluid falls at most one voxel per step
+        wet.sort_by_key(|position| position.y);
+        for position in wet {
+            let mut level = self.fluid_at(&position);
+            if 0 == level {
+                continue;
+            }
+
+            if 0 < position.y {
+                let below = V3c::new(position.x, position.y - 1, position.z);
+                if self.get(&below).is_none() {
+                    let flow = level.min(MAX_FLUID_LEVEL - self.fluid_at(&below));
+                    self.move_fluid(&position, &below, flow);
+                    level -= flow;
+                }
+            }
+
+            for (dx, dz) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (Some(x), Some(z)) = (
+                    position.x.checked_add_signed(dx),
+                    position.z.checked_add_signed(dz),
+                ) else {
+                    continue;
+                };
+                let side = V3c::new(x, position.y, z);
+                if x < size
+                    && z < size
+                    && self.get(&side).is_none()
+                    && self.fluid_at(&side) + 1 < level
+                {
+                    self.move_fluid(&position, &side, 1);
+                    level -= 1;
+                }
+            }
+        }
+    }
+
+    /// Moves the given amount of fluid between the given voxels
+    fn move_fluid(&mut self, from: &V3c<u32>, to: &V3c<u32>, amount: u8) {
+        if 0 < amount {
+            self.fluids
+                .change_level(self.octree_size, to, amount as i32);
+            self.fluids
+                .change_level(self.octree_size, from, -(amount as i32));
+        }
+    }
+}
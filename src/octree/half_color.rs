@@ -0,0 +1,76 @@
+use crate::octree::{VoxelData, VoxelDataCodec};
+use half::f16;
+
+/// A voxel with a linear HDR color stored in half precision floats, taking half the memory
+/// of the same color in f32 while keeping values above 1 e.g. for emissive voxels or baked lighting.
+/// Channels are limited to the range of f16: values beyond 65504 are stored as infinity.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct HalfColorVoxel {
+    /// The linear RGBA color of the voxel
+    pub color: [f16; 4],
+    /// User defined data
+    pub user_data: u32,
+}
+
+impl HalfColorVoxel {
+    /// Creates a voxel from the given linear RGBA color, rounded to half precision
+    pub fn from_hdr(color: [f32; 4], user_data: u32) -> Self {
+        Self {
+            color: color.map(f16::from_f32),
+            user_data,
+        }
+    }
+}
+
+impl VoxelData for HalfColorVoxel {
+    fn new(r: u8, g: u8, b: u8, a: u8, user_data: u32) -> Self {
+        Self::from_hdr([r, g, b, a].map(|channel| channel as f32 / 255.), user_data)
+    }
+    fn albedo(&self) -> [u8; 4] {
+        self.color
+            .map(|channel| (channel.to_f32().clamp(0., 1.) * 255.).round() as u8)
+    }
+    fn albedo_hdr(&self) -> [f32; 4] {
+        self.color.map(f16::to_f32)
+    }
+    fn user_data(&self) -> u32 {
+        self.user_data
+    }
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Stores the color of each voxel in half precision along with its user data, in 12 bytes.
+/// Unlike `DefaultVoxelDataCodec`, colors outside of 0-1 are kept as they are
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HalfColorCodec;
+
+impl VoxelDataCodec<HalfColorVoxel> for HalfColorCodec {
+    fn encode(&self, data: &HalfColorVoxel) -> Vec<u8> {
+        let mut bytes = data
+            .color
+            .iter()
+            .flat_map(|channel| channel.to_le_bytes())
+            .collect::<Vec<u8>>();
+        bytes.extend_from_slice(&data.user_data.to_le_bytes());
+        bytes
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<HalfColorVoxel> {
+        if 12 != bytes.len() {
+            return None;
+        }
+        let mut color = [f16::ZERO; 4];
+        for (channel, channel_bytes) in color.iter_mut().zip(bytes[0..8].chunks_exact(2)) {
+            *channel = f16::from_le_bytes(channel_bytes.try_into().ok()?);
+        }
+        Some(HalfColorVoxel {
+            color,
+            user_data: u32::from_le_bytes(bytes[8..12].try_into().ok()?),
+        })
+    }
+}
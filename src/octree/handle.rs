@@ -0,0 +1,116 @@
+use crate::object_pool::{key_might_be_valid, PoolKey};
+use crate::octree::{types::NodeContent, Aggregate, Cube, Octant, Octree, VoxelData};
+
+/// An opaque identifier of a node inside a tree, e.g. to keep track of the visited nodes
+/// in custom algorithms. It stays the same until the tree is edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeKey(PoolKey);
+
+/// The kind of content stored in a node, see `NodeHandle::kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The node has no content
+    Empty,
+    /// The node has children, see `NodeHandle::children`
+    Internal,
+    /// The node stores its voxels in a brick, see `NodeHandle::leaf_brick`
+    Leaf,
+    /// Every voxel of the node is the same, see `NodeHandle::uniform_data`
+    UniformLeaf,
+}
+
+/// A read-only view of a node of a tree, so custom algorithms like tracers or exporters
+/// can walk the structure of the tree directly. It borrows the tree, so it can't outlive edits of it.
+pub struct NodeHandle<'a, T: Default + Clone + VoxelData, const DIM: usize> {
+    tree: &'a Octree<T, DIM>,
+    key: PoolKey,
+    bounds: Cube,
+}
+
+impl<T: Default + Clone + VoxelData, const DIM: usize> Clone for NodeHandle<'_, T, DIM> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Default + Clone + VoxelData, const DIM: usize> Copy for NodeHandle<'_, T, DIM> {}
+
+impl<'a, T: Default + PartialEq + Clone + VoxelData, const DIM: usize> NodeHandle<'a, T, DIM> {
+    /// The identifier of the node inside its tree
+    pub fn key(&self) -> NodeKey {
+        NodeKey(self.key)
+    }
+
+    /// The region of the tree the node covers
+    pub fn bounds(&self) -> Cube {
+        self.bounds
+    }
+
+    /// The kind of content the node stores
+    pub fn kind(&self) -> NodeKind {
+        match self.tree.nodes.get(self.key as usize) {
+            NodeContent::Nothing => NodeKind::Empty,
+            NodeContent::Internal(_) => NodeKind::Internal,
+            NodeContent::Leaf(_) => NodeKind::Leaf,
+            NodeContent::UniformLeaf(_) => NodeKind::UniformLeaf,
+        }
+    }
+
+    /// The children of the node indexed by octant, None where there is no child.
+    /// Only internal nodes have children
+    pub fn children(&self) -> [Option<NodeHandle<'a, T, DIM>>; 8] {
+        let is_internal = NodeKind::Internal == self.kind();
+        Octant::ALL.map(|octant| {
+            let child_key = self.tree.node_children[self.key as usize][octant];
+            (is_internal && key_might_be_valid(child_key)).then(|| NodeHandle {
+                tree: self.tree,
+                key: child_key,
+                bounds: self.bounds.child_bounds_for(octant),
+            })
+        })
+    }
+
+    /// The voxels of a leaf node indexed by [x][y][z], each covering `bounds().size / DIM` voxels
+    /// of the tree along every axis, or at least one; None if the node is not a leaf with a brick
+    pub fn leaf_brick(&self) -> Option<&'a [[[T; DIM]; DIM]; DIM]> {
+        match self.tree.nodes.get(self.key as usize) {
+            NodeContent::Leaf(brick) => Some(&self.tree.bricks.get(*brick as usize).0),
+            _ => None,
+        }
+    }
+
+    /// The summary of the voxels of the node, None unless `A` is the summary selected by `Octree::set_aggregate`
+    pub fn aggregate<A: Aggregate<T>>(&self) -> Option<&'a A> {
+        self.tree.aggregate_of(self.key as usize)
+    }
+
+    /// The data of every voxel of a uniform leaf node, None if the node is not a uniform leaf
+    pub fn uniform_data(&self) -> Option<&'a T> {
+        match self.tree.nodes.get(self.key as usize) {
+            NodeContent::UniformLeaf(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The root node of the tree, to walk its structure from, see `NodeHandle`
+    pub fn root_node(&self) -> NodeHandle<'_, T, DIM> {
+        NodeHandle {
+            tree: self,
+            key: Self::ROOT_NODE_KEY,
+            bounds: Cube::root_bounds(self.octree_size),
+        }
+    }
+
+    /// Every node of the tree in depth-first order, parents before their children
+    /// and children in the order of their octants
+    pub fn nodes(&self) -> impl Iterator<Item = NodeHandle<'_, T, DIM>> {
+        let mut node_stack = vec![self.root_node()];
+        std::iter::from_fn(move || {
+            let node = node_stack.pop()?;
+            node_stack.extend(node.children().into_iter().rev().flatten());
+            Some(node)
+        })
+    }
+}
@@ -0,0 +1,549 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    detail::{bound_contains, child_octant_for},
+    progress::{CancellationToken, ProgressSink},
+    types::{NodeContent, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+use std::collections::{HashMap, VecDeque};
+
+#[cfg(feature = "raytracing")]
+use crate::octree::{raytracing::RayContext, BoxFace};
+#[cfg(feature = "raytracing")]
+use crate::spatial::raytracing::Ray;
+
+/// The highest light level: the level of direct sunlight and of the brightest light sources
+pub const MAX_LIGHT_LEVEL: u8 = 15;
+
+/// The light reaching a voxel, Minecraft style: light levels decrease by one with every step
+/// from their source, except for sunlight which reaches down without loss until blocked
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightLevel {
+    /// Light coming from the sky, 0..=MAX_LIGHT_LEVEL
+    pub sky: u8,
+    /// Light coming from emissive voxels, 0..=MAX_LIGHT_LEVEL
+    pub block: u8,
+}
+
+/// The light levels are stored in a separate tree, so dark and fully lit regions are kept
+/// in uniform nodes; the levels are presented as the red and green channels
+impl VoxelData for LightLevel {
+    fn new(r: u8, g: u8, _b: u8, _a: u8, _user_data: u32) -> Self {
+        Self { sky: r, block: g }
+    }
+    fn albedo(&self) -> [u8; 4] {
+        [self.sky, self.block, 0, 0]
+    }
+    fn user_data(&self) -> u32 {
+        0
+    }
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Light levels of the voxels reached by light from a BFS flood fill
+type LightMap = HashMap<(u32, u32, u32), u8>;
+
+/// The positions next to the given position inside a tree of the given size
+fn neighbours(position: (u32, u32, u32), size: u32) -> impl Iterator<Item = (u32, u32, u32)> {
+    let (x, y, z) = position;
+    [
+        (x.wrapping_sub(1), y, z),
+        (x + 1, y, z),
+        (x, y.wrapping_sub(1), z),
+        (x, y + 1, z),
+        (x, y, z.wrapping_sub(1)),
+        (x, y, z + 1),
+    ]
+    .into_iter()
+    .filter(move |(x, y, z)| *x < size && *y < size && *z < size)
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The light level at the given position, calculated by the last call to `propagate_light`.
+    /// None if the light was not yet propagated, or the position is outside the tree.
+    pub fn light_at(&self, position: &V3c<u32>) -> Option<LightLevel> {
+        let light = self.light.as_ref()?;
+        if !bound_contains(&Cube::root_bounds(self.octree_size), position) {
+            return None;
+        }
+        Some(light.get(position).copied().unwrap_or_default())
+    }
+
+    /// Calculates the light level of every voxel in the tree: sunlight shines down from the top
+    /// of the tree, voxels emit light based on `VoxelData::light_emission`, and both spread to
+    /// the neighbouring voxels not blocking light, see `VoxelData::is_opaque`.
+    /// The levels are not updated by edits of the tree, this is to be called again after them.
+    pub fn propagate_light(&mut self) -> Result<(), OctreeError> {
+        self.propagate_light_with_progress(&(), &CancellationToken::new())
+    }
+
+    /// Calculates the light levels like `propagate_light`, reporting each of its stages to the given sink when done.
+    /// Fails with `OctreeError::Cancelled` if the given token is cancelled before it is done;
+    /// the light levels calculated before are kept then
+    pub fn propagate_light_with_progress(
+        &mut self,
+        progress: &impl ProgressSink,
+        cancellation: &CancellationToken,
+    ) -> Result<(), OctreeError> {
+        const STAGES: usize = 4;
+        let stage_done = |stage: usize| {
+            progress.report(stage, STAGES);
+            if cancellation.is_cancelled() {
+                return Err(OctreeError::Cancelled);
+            }
+            Ok(())
+        };
+        let size = self.octree_size;
+
+        // Sunlight reaches down in each column until the first opaque voxel
+        let mut sky_floor = vec![0; (size * size) as usize];
+        for x in 0..size {
+            for z in 0..size {
+                sky_floor[(x * size + z) as usize] = self.sky_floor_at(x, z);
+            }
+        }
+        let floor_at = |x: u32, z: u32| sky_floor[(x * size + z) as usize];
+        stage_done(1)?;
+
+        // Sunlight spreads sideways from the lit columns into the shadows below their neighbours
+        let mut sky = LightMap::new();
+        let mut sky_queue = VecDeque::new();
+        for x in 0..size {
+            for z in 0..size {
+                for (nx, _, nz) in neighbours((x, 0, z), size) {
+                    for y in floor_at(x, z)..floor_at(nx, nz) {
+                        self.spread_light(
+                            (nx, y, nz),
+                            MAX_LIGHT_LEVEL - 1,
+                            &mut sky,
+                            &mut sky_queue,
+                        );
+                    }
+                }
+            }
+        }
+        self.flood_light(&mut sky, sky_queue, |position| {
+            position.1 >= floor_at(position.0, position.2)
+        });
+        stage_done(2)?;
+
+        let mut block = LightMap::new();
+        let mut block_queue = VecDeque::new();
+        for (position, emission) in self.light_emitters() {
+            if block.get(&position).is_none_or(|level| *level < emission) {
+                block.insert(position, emission);
+                block_queue.push_back(position);
+            }
+        }
+        self.flood_light(&mut block, block_queue, |_| false);
+        stage_done(3)?;
+
+        let generator = |position: &V3c<u32>| {
+            let key = (position.x, position.y, position.z);
+            let light = LightLevel {
+                sky: if position.y >= floor_at(position.x, position.z) {
+                    MAX_LIGHT_LEVEL
+                } else {
+                    sky.get(&key).copied().unwrap_or(0)
+                },
+                block: block.get(&key).copied().unwrap_or(0),
+            };
+            Some(light).filter(|light| !light.is_empty())
+        };
+        let light =
+            Octree::<LightLevel, DIM>::generate_with_progress(size, generator, &(), cancellation)?;
+        self.light = Some(Box::new(light));
+        progress.report(STAGES, STAGES);
+        Ok(())
+    }
+
+    /// The height from which sunlight reaches down in the given column:
+    /// one above the topmost voxel blocking light, or 0 if nothing blocks it.
+    /// Uniform regions are skipped in one step.
+    fn sky_floor_at(&self, x: u32, z: u32) -> u32 {
+        let mut y = self.octree_size;
+        while 0 < y {
+            let (data, region) = self.uniform_region_at(&V3c::new(x, y - 1, z));
+            if data.is_some_and(|data| data.is_opaque()) {
+                return y;
+            }
+            y = region.min_position.y;
+        }
+        0
+    }
+
+    /// Sets the given light level at the given position, if it is brighter and light can pass through it
+    fn spread_light(
+        &self,
+        position: (u32, u32, u32),
+        level: u8,
+        light: &mut LightMap,
+        queue: &mut VecDeque<(u32, u32, u32)>,
+    ) {
+        if 0 == level
+            || light
+                .get(&position)
+                .is_some_and(|current| *current >= level)
+        {
+            return;
+        }
+        if self
+            .get(&V3c::new(position.0, position.1, position.2))
+            .is_some_and(|data| data.is_opaque())
+        {
+            return;
+        }
+        light.insert(position, level);
+        queue.push_back(position);
+    }
+
+    /// Spreads the light from the queued positions through the voxels not blocking light
+    /// * `is_fully_lit` - True for positions which are already at the maximum light level
+    fn flood_light(
+        &self,
+        light: &mut LightMap,
+        mut queue: VecDeque<(u32, u32, u32)>,
+        is_fully_lit: impl Fn((u32, u32, u32)) -> bool,
+    ) {
+        while let Some(position) = queue.pop_front() {
+            let level = light[&position];
+            for neighbour in neighbours(position, self.octree_size) {
+                if !is_fully_lit(neighbour) {
+                    self.spread_light(neighbour, level - 1, light, &mut queue);
+                }
+            }
+        }
+    }
+
+    /// The positions and light levels of the voxels emitting light
+    fn light_emitters(&self) -> Vec<((u32, u32, u32), u8)> {
+        let mut emitters = Vec::new();
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            Cube::root_bounds(self.octree_size),
+        )];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            match self.nodes.get(node_key as usize) {
+                NodeContent::Nothing => {}
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key as usize][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key, bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+                NodeContent::UniformLeaf(data) => {
+                    if 0 < data.light_emission() {
+                        Self::push_emitters(&mut emitters, &bounds, data.light_emission());
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    for (x, plane) in self.bricks.get(*brick as usize).0.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                if 0 < data.light_emission() {
+                                    let cell = Cube::new(
+                                        bounds.min_position
+                                            + V3c::new(x as u32, y as u32, z as u32) * cell_size,
+                                        cell_size,
+                                    );
+                                    Self::push_emitters(
+                                        &mut emitters,
+                                        &cell,
+                                        data.light_emission(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        emitters
+    }
+
+    fn push_emitters(emitters: &mut Vec<((u32, u32, u32), u8)>, region: &Cube, emission: u8) {
+        let min = region.min_position;
+        for x in min.x..min.x + region.size {
+            for y in min.y..min.y + region.size {
+                for z in min.z..min.z + region.size {
+                    emitters.push(((x, y, z), emission.min(MAX_LIGHT_LEVEL)));
+                }
+            }
+        }
+    }
+
+    /// The data of the largest uniform region containing the given position, and the bounds of it.
+    /// Inside bricks the region is the cell of the brick containing the position.
+    pub(in crate::octree) fn uniform_region_at(&self, position: &V3c<u32>) -> (Option<&T>, Cube) {
+        let mut current_bounds = Cube::root_bounds(self.octree_size);
+        let mut current_node_key = Octree::<T, DIM>::ROOT_NODE_KEY as usize;
+        loop {
+            match self.nodes.get(current_node_key) {
+                NodeContent::Nothing => return (None, current_bounds),
+                NodeContent::UniformLeaf(data) => {
+                    return (Some(data).filter(|data| !data.is_empty()), current_bounds)
+                }
+                NodeContent::Leaf(brick) => {
+                    let mat_index = Self::mat_index(&current_bounds, position);
+                    let cell_size = (current_bounds.size / DIM as u32).max(1);
+                    let data =
+                        &self.bricks.get(*brick as usize).0[mat_index.x][mat_index.y][mat_index.z];
+                    return (
+                        Some(data).filter(|data| !data.is_empty()),
+                        Cube::new(
+                            current_bounds.min_position + V3c::<u32>::from(mat_index) * cell_size,
+                            cell_size,
+                        ),
+                    );
+                }
+                NodeContent::Internal(_) => {
+                    let child_octant = child_octant_for(&current_bounds, position);
+                    let child_key = self.node_children[current_node_key][child_octant];
+                    let child_bounds = current_bounds.child_bounds_for(child_octant);
+                    if !key_might_be_valid(child_key) {
+                        return (None, child_bounds);
+                    }
+                    current_node_key = child_key as usize;
+                    current_bounds = child_bounds;
+                }
+            }
+        }
+    }
+}
+
+/// The number of directions the light arriving at a probe is sampled from, see `bake_probe_grid`
+#[cfg(feature = "raytracing")]
+const PROBE_SAMPLE_COUNT: u32 = 64;
+
+/// The linear radiance of the sky, arriving along the rays leaving the tree without a hit
+#[cfg(feature = "raytracing")]
+const SKY_RADIANCE: [f32; 3] = [1., 1., 1.];
+
+/// The light arriving at a point from the six axis directions, in the order of `BoxFace::ALL`;
+/// every face stores the linear RGB light a surface facing in its direction receives
+#[cfg(feature = "raytracing")]
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct AmbientCube {
+    pub faces: [[f32; 3]; 6],
+}
+
+#[cfg(feature = "raytracing")]
+impl AmbientCube {
+    /// The light received by a surface facing in the direction of the given face
+    pub fn face(&self, face: BoxFace) -> [f32; 3] {
+        self.faces[face as usize]
+    }
+
+    /// The light received by a surface with the given normal: the faces it looks towards blended
+    /// by the squares of the components of the normal
+    pub fn irradiance(&self, normal: &V3c<f32>) -> [f32; 3] {
+        let normal = normal.normalized();
+        let weighted_faces = [
+            (normal.x, BoxFace::NegativeX, BoxFace::PositiveX),
+            (normal.y, BoxFace::NegativeY, BoxFace::PositiveY),
+            (normal.z, BoxFace::NegativeZ, BoxFace::PositiveZ),
+        ];
+        let mut irradiance = [0.; 3];
+        for (component, negative, positive) in weighted_faces {
+            let face = self.face(if component < 0. { negative } else { positive });
+            for channel in 0..3 {
+                irradiance[channel] += face[channel] * component * component;
+            }
+        }
+        irradiance
+    }
+}
+
+/// Light probes placed on a regular grid over a tree, baked by `bake_probe_grid`, so engines rasterizing
+/// the voxels can light them without tracing rays. Probes are at the centers of the voxels in the middle of
+/// the cells of the grid, with positions given in the coordinates of the voxels of the tree
+#[cfg(feature = "raytracing")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeGrid {
+    spacing: u32,
+    probe_count: u32,
+    // The probes row by row along x, then y and z; None for probes inside solid voxels
+    probes: Vec<Option<AmbientCube>>,
+}
+
+#[cfg(feature = "raytracing")]
+impl ProbeGrid {
+    /// The distance between neighbouring probes in voxels
+    pub fn spacing(&self) -> u32 {
+        self.spacing
+    }
+
+    /// The number of probes along each axis
+    pub fn probe_count(&self) -> u32 {
+        self.probe_count
+    }
+
+    /// The position of the probe with the given index along each axis
+    pub fn probe_position(&self, index: &V3c<u32>) -> V3c<f32> {
+        V3c::from(*index * self.spacing + V3c::unit(self.spacing / 2)) + V3c::unit(0.5)
+    }
+
+    /// The light stored in the probe with the given index along each axis,
+    /// or None if the index is out of the grid or the probe is inside a solid voxel
+    pub fn probe(&self, index: &V3c<u32>) -> Option<&AmbientCube> {
+        if index.x >= self.probe_count || index.y >= self.probe_count || index.z >= self.probe_count
+        {
+            return None;
+        }
+        self.probes[((index.z * self.probe_count + index.y) * self.probe_count + index.x) as usize]
+            .as_ref()
+    }
+
+    /// The light at the given point, interpolated from the surrounding probes; probes inside
+    /// solid voxels are left out, so light doesn't leak from them into the open space next to the voxels
+    pub fn sample(&self, point: &V3c<f32>) -> AmbientCube {
+        let last_index = (self.probe_count - 1) as f32;
+        let first_position = self.probe_position(&V3c::unit(0));
+        let grid_position =
+            |value: f32, first: f32| ((value - first) / self.spacing as f32).clamp(0., last_index);
+        let grid_position = V3c::new(
+            grid_position(point.x, first_position.x),
+            grid_position(point.y, first_position.y),
+            grid_position(point.z, first_position.z),
+        );
+        // The probes of the cell the point is in; on the last probes the cell before them is used
+        let last_base = self.probe_count.saturating_sub(2) as f32;
+        let base = V3c::new(
+            grid_position.x.floor().min(last_base),
+            grid_position.y.floor().min(last_base),
+            grid_position.z.floor().min(last_base),
+        );
+        let fraction = grid_position - base;
+        let weight = |offset: u32, fraction: f32| {
+            if 0 == offset {
+                1. - fraction
+            } else {
+                fraction
+            }
+        };
+        let probes = Octant::iter()
+            .filter_map(|octant| {
+                let offset = octant.offset();
+                let probe = self.probe(&(V3c::<u32>::from(base) + offset))?;
+                Some((
+                    probe,
+                    weight(offset.x, fraction.x)
+                        * weight(offset.y, fraction.y)
+                        * weight(offset.z, fraction.z),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        // Points at probes inside solid voxels take the average of the probes around them
+        let total_weight = probes.iter().map(|(_, weight)| weight).sum::<f32>();
+        let mut result = AmbientCube::default();
+        for (probe, weight) in probes.iter() {
+            let weight = if 0. < total_weight {
+                weight / total_weight
+            } else {
+                1. / probes.len() as f32
+            };
+            for (face, probe_face) in result.faces.iter_mut().zip(probe.faces.iter()) {
+                for channel in 0..3 {
+                    face[channel] += probe_face[channel] * weight;
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The directions the light arriving at a probe is sampled from, evenly spread on a Fibonacci sphere
+#[cfg(feature = "raytracing")]
+fn probe_sample_directions() -> Vec<V3c<f32>> {
+    let golden_angle = std::f32::consts::PI * (3. - 5f32.sqrt());
+    (0..PROBE_SAMPLE_COUNT)
+        .map(|i| {
+            let y = 1. - (i as f32 + 0.5) / PROBE_SAMPLE_COUNT as f32 * 2.;
+            let radius = (1. - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            V3c::new(angle.cos() * radius, y, angle.sin() * radius)
+        })
+        .collect()
+}
+
+/// Bakes the light arriving at probes placed with the given spacing over the given tree, tracing rays in
+/// every direction from each of them. Rays leaving the tree bring the light of the sky, rays hitting
+/// voxels bring the light they emit: their color scaled by their emission relative to `MAX_LIGHT_LEVEL`.
+/// Every face of the probes stores the cosine weighted average of the light arriving on its side
+#[cfg(feature = "raytracing")]
+pub fn bake_probe_grid<
+    T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData,
+    const DIM: usize,
+>(
+    tree: &Octree<T, DIM>,
+    spacing: u32,
+) -> ProbeGrid {
+    let spacing = spacing.max(1);
+    let probe_count = tree.octree_size.div_ceil(spacing);
+    let directions = probe_sample_directions();
+    let mut context = RayContext::new();
+    let mut grid = ProbeGrid {
+        spacing,
+        probe_count,
+        probes: Vec::with_capacity(probe_count.pow(3) as usize),
+    };
+    for z in 0..probe_count {
+        for y in 0..probe_count {
+            for x in 0..probe_count {
+                let voxel = V3c::new(x, y, z) * spacing + V3c::unit(spacing / 2);
+                if tree.get(&voxel).is_some() {
+                    grid.probes.push(None);
+                    continue;
+                }
+                let origin = grid.probe_position(&V3c::new(x, y, z));
+                let mut probe = AmbientCube::default();
+                let mut face_weights = [0.; 6];
+                for direction in directions.iter() {
+                    let radiance = match tree.get_by_ray_with(
+                        &mut context,
+                        &Ray {
+                            origin,
+                            direction: *direction,
+                        },
+                    ) {
+                        Some((data, _, _)) => {
+                            let albedo = data.albedo_hdr();
+                            let emission = data.light_emission() as f32 / MAX_LIGHT_LEVEL as f32;
+                            [
+                                albedo[0] * emission,
+                                albedo[1] * emission,
+                                albedo[2] * emission,
+                            ]
+                        }
+                        None => SKY_RADIANCE,
+                    };
+                    for face in BoxFace::ALL {
+                        let weight = direction.dot(&face.normal());
+                        if weight <= 0. {
+                            continue;
+                        }
+                        for (channel, radiance) in
+                            probe.faces[face as usize].iter_mut().zip(radiance.iter())
+                        {
+                            *channel += radiance * weight;
+                        }
+                        face_weights[face as usize] += weight;
+                    }
+                }
+                for (face, weight) in probe.faces.iter_mut().zip(face_weights.iter()) {
+                    for channel in face.iter_mut() {
+                        *channel /= weight;
+                    }
+                }
+                grid.probes.push(Some(probe));
+            }
+        }
+    }
+    grid
+}
@@ -0,0 +1,48 @@
+use crate::octree::{Cube, Octree, VoxelData};
+
+/// The color of the pixels above columns without any voxels
+const EMPTY_COLUMN_COLOR: [u8; 4] = [0, 0, 0, 0];
+
+/// The brightness of the voxels at the bottom of the projected bounds, rising linearly to 1 at the top
+const MIN_HEIGHT_BRIGHTNESS: f32 = 0.4;
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Renders an orthographic view of the given bounds from above into an RGBA8 image buffer,
+    /// e.g. for minimaps or overviews of a world. Each pixel shows the topmost voxel of the column
+    /// at its center, darkened the lower it is inside the bounds; pixels above empty columns are transparent.
+    /// The columns are walked downwards through the tree, see `top_voxel_at`.
+    /// * `bounds` - The region to project, only voxels inside it are shown
+    /// * `resolution` - The size of the image in pixels: (width, height);
+    ///   x grows to the right and z grows downwards in the image
+    pub fn project_topdown(&self, bounds: &Cube, resolution: (u32, u32)) -> Vec<u8> {
+        let (width, height) = resolution;
+        let mut image = Vec::with_capacity((width * height * 4) as usize);
+        for pixel_y in 0..height {
+            for pixel_x in 0..width {
+                let x = bounds.min_position.x + ((2 * pixel_x + 1) * bounds.size) / (2 * width);
+                let z = bounds.min_position.z + ((2 * pixel_y + 1) * bounds.size) / (2 * height);
+                image.extend_from_slice(&match self.top_voxel_in(
+                    x,
+                    z,
+                    bounds.min_position.y..bounds.min_position.y + bounds.size,
+                ) {
+                    Some((y, data)) => {
+                        let relative_height =
+                            (y - bounds.min_position.y + 1) as f32 / bounds.size as f32;
+                        let brightness =
+                            MIN_HEIGHT_BRIGHTNESS + (1. - MIN_HEIGHT_BRIGHTNESS) * relative_height;
+                        let [r, g, b, _] = data.albedo();
+                        [
+                            (r as f32 * brightness) as u8,
+                            (g as f32 * brightness) as u8,
+                            (b as f32 * brightness) as u8,
+                            255,
+                        ]
+                    }
+                    None => EMPTY_COLUMN_COLOR,
+                });
+            }
+        }
+        image
+    }
+}
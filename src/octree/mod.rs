@@ -1,51 +1,157 @@
+pub mod aggregate;
+pub mod anim;
+pub mod atlas;
+pub mod auxiliary;
+pub mod broadphase;
 pub mod bytecode;
+pub mod color;
+pub mod column;
+pub mod construct;
+pub mod delta;
 pub mod detail;
+pub mod faces;
+pub mod fields;
+pub mod fluid;
+#[cfg(feature = "half")]
+pub mod half_color;
+pub mod handle;
+pub mod lighting;
+pub mod minimap;
+pub mod occupancy;
+pub mod overlay;
+mod parallel;
+pub mod physics;
+pub mod progress;
+pub mod recorder;
+#[cfg(feature = "schematic")]
+pub mod schematic;
+pub mod slice;
+pub mod stats;
+pub mod streaming;
 pub mod tests;
 pub mod types;
 pub mod update;
+#[cfg(feature = "debug-verify")]
+mod verify;
+#[cfg(feature = "vox")]
+pub mod vox;
+pub mod world;
+#[cfg(feature = "archive")]
+pub mod world_archive;
+#[cfg(feature = "world_dir")]
+pub mod world_dir;
 
 #[cfg(feature = "raytracing")]
 pub mod raytracing;
 
-pub use crate::spatial::math::vector::V3c;
-pub use types::{Octree, VoxelData};
+pub use crate::spatial::{
+    math::{vector::V3c, Octant},
+    primitives::{Axis, Capsule, Facing, Plane, Sphere, VoxelShape},
+    raytracing::BoxFace,
+    Aabb, Cube,
+};
+pub use aggregate::{Aggregate, DensityRange, DominantMaterial, LightSum, OccupancyCount};
+pub use anim::VoxelAnimation;
+pub use atlas::{Atlas3dLayout, AtlasTexelFormat};
+pub use auxiliary::VoxelAux;
+pub use broadphase::VoxelBroadPhase;
+pub use faces::SeamHandling;
+pub use fluid::MAX_FLUID_LEVEL;
+#[cfg(feature = "half")]
+pub use half_color::{HalfColorCodec, HalfColorVoxel};
+pub use handle::{NodeHandle, NodeKey, NodeKind};
+#[cfg(feature = "raytracing")]
+pub use lighting::{AmbientCube, ProbeGrid};
+pub use lighting::{LightLevel, MAX_LIGHT_LEVEL};
+pub use occupancy::BitOctree;
+pub use overlay::OverlayOctree;
+pub use progress::{CancellationToken, ProgressSink};
+pub use recorder::{EditRecorder, RecordedCall};
+pub use stats::{MemoryBreakdown, MemoryReport};
+pub use streaming::{DistanceStreamingPolicy, StreamingPlan, StreamingPolicy};
+pub use types::{
+    CapacityPolicy, DefaultVoxelDataCodec, Octree, OctreeEdit, OctreeLoader, OctreeWriteQueue,
+    VoxelData, VoxelDataCodec, VoxelDataMigration,
+};
+pub use world::{ChunkMut, LayeredWorld, VoxelWorld, WorldGet};
+#[cfg(feature = "archive")]
+pub use world_archive::WorldArchive;
+#[cfg(feature = "world_dir")]
+pub use world_dir::SavedChunk;
 
-use crate::object_pool::{key_none_value, ObjectPool};
+use crate::object_pool::{key_none_value, ObjectPool, PoolKey};
 use crate::octree::{
+    bytecode::EncodedOctree,
     detail::{bound_contains, child_octant_for},
-    types::{NodeChildren, NodeContent, OctreeError},
+    types::{Brick, EvictionCallback, NodeChildren, NodeContent, OctreeError},
 };
-use crate::spatial::{math::hash_region, Cube};
 use bendy::{decoding::FromBencode, encoding::ToBencode};
+use std::collections::HashMap;
+
+/// The number of levels of a new octree its pools have space for up front
+const PREALLOCATED_LEVELS: u32 = 3;
 
 impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
     /// converts the data structure to a byte representation
     pub fn to_bytes(&self) -> Vec<u8> {
-        self.to_bencode().ok().unwrap()
+        self.to_bytes_with(&DefaultVoxelDataCodec)
+    }
+
+    /// converts the data structure to a byte representation, the voxels encoded by the given codec
+    pub fn to_bytes_with(&self, codec: &impl VoxelDataCodec<T>) -> Vec<u8> {
+        EncodedOctree::new(self, codec).to_bencode().ok().unwrap()
     }
 
     /// parses the data structure from a byte string
+    ///
+    /// # Panics
+    /// Should the bytes not contain a tree; `load` reports that as an error instead
     pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        Self::from_bencode(&bytes).ok().unwrap()
+        Self::from_bytes_with(bytes, &DefaultVoxelDataCodec)
+    }
+
+    /// parses the data structure from a byte string, the voxels decoded by the given codec
+    ///
+    /// # Panics
+    /// Should the bytes not contain a tree; `load_with` reports that as an error instead
+    pub fn from_bytes_with(bytes: Vec<u8>, codec: &impl VoxelDataCodec<T>) -> Self {
+        EncodedOctree::decode_bytes(&bytes, codec, &HashMap::new())
+            .ok()
+            .unwrap()
     }
 
     /// saves the data structure to the given file path
     pub fn save(&mut self, path: &str) -> Result<(), std::io::Error> {
+        self.save_with(path, &DefaultVoxelDataCodec)
+    }
+
+    /// saves the data structure to the given file path, the voxels encoded by the given codec
+    pub fn save_with(
+        &mut self,
+        path: &str,
+        codec: &impl VoxelDataCodec<T>,
+    ) -> Result<(), std::io::Error> {
         use std::fs::File;
         use std::io::Write;
         let mut file = File::create(path)?;
-        file.write_all(&self.to_bytes())?;
+        file.write_all(&self.to_bytes_with(codec))?;
         Ok(())
     }
 
     /// loads the data structure from the given file path
     pub fn load(path: &str) -> Result<Self, std::io::Error> {
+        Self::load_with(path, &DefaultVoxelDataCodec)
+    }
+
+    /// loads the data structure from the given file path, the voxels decoded by the given codec.
+    /// Files not containing a tree, e.g. corrupted ones or ones saved in an unsupported layout, fail with `InvalidData`
+    pub fn load_with(path: &str, codec: &impl VoxelDataCodec<T>) -> Result<Self, std::io::Error> {
         use std::fs::File;
         use std::io::Read;
         let mut file = File::open(path)?;
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes)?;
-        Ok(Self::from_bytes(bytes))
+        EncodedOctree::decode_bytes(&bytes, codec, &HashMap::new())
     }
 
     /// creates an octree with overall size nodes_dimension * DIM
@@ -54,8 +160,15 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
         if Self::is_size_inadequate(size) {
             return Err(OctreeError::InvalidNodeSize(size));
         }
-        let mut nodes = ObjectPool::<NodeContent<T, DIM>>::with_capacity(size.pow(3) as usize);
-        let mut node_children = Vec::with_capacity(size.pow(3) as usize);
+        // Trees are expected to be sparse, so only the nodes of the first levels are preallocated,
+        // and the pools grow with the content. Bricks are only stored on the lowest of those levels
+        let levels = Self::depth_for(size);
+        let node_capacity = (0..levels.min(PREALLOCATED_LEVELS))
+            .map(|level| 8_usize.pow(level))
+            .sum();
+        let brick_capacity = 8_usize.pow(levels.min(PREALLOCATED_LEVELS) - 1);
+        let mut nodes = ObjectPool::<NodeContent<T>>::with_capacity(node_capacity);
+        let mut node_children = Vec::with_capacity(node_capacity);
         node_children.push(NodeChildren::new(key_none_value()));
         let root_node_key = nodes.push(NodeContent::Nothing); // The first element is the root Node
         assert!(root_node_key == 0);
@@ -64,9 +177,172 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
             octree_size: size,
             nodes,
             node_children,
+            bricks: ObjectPool::with_capacity(brick_capacity),
+            voxel_size: 1.,
+            memory_budget: None,
+            eviction_callback: None,
+            capacity_policy: Default::default(),
+            simplify_queue: Default::default(),
+            simplify_queued: Default::default(),
+            dirty_bricks: Default::default(),
+            edit_count: 0,
+            changed_regions: Default::default(),
+            light: None,
+            auxiliary: None,
+            fluids: Default::default(),
+            recorder: None,
+            aggregates: None,
         })
     }
 
+    /// Reserves space for the given number of nodes up front, e.g. when the size of the content is known,
+    /// so the pools of the tree don't need to grow while it is filled. Space for bricks is reserved
+    /// for the leaves among them: 7 in 8 nodes in a full tree
+    /// * `node_count` - The number of nodes the tree is expected to have
+    pub fn with_capacity_hint(mut self, node_count: usize) -> Self {
+        self.nodes
+            .reserve(node_count.saturating_sub(self.nodes.len()));
+        self.node_children
+            .reserve(node_count.saturating_sub(self.node_children.len()));
+        self.bricks
+            .reserve((node_count * 7 / 8).saturating_sub(self.bricks.len()));
+        self
+    }
+
+    /// The size of the edge of the tree in voxels
+    pub fn size(&self) -> u32 {
+        self.octree_size
+    }
+
+    /// The size of the edge of the bricks stored in the leaves of the tree in voxels
+    pub fn dim(&self) -> usize {
+        DIM
+    }
+
+    /// The number of levels of nodes in the tree, the root and the level of nodes sized `DIM` included,
+    /// e.g. a tree of size 32 with DIM 4 has 4 levels, with the node sizes 32, 16, 8 and 4
+    pub fn depth(&self) -> u32 {
+        Self::depth_for(self.octree_size)
+    }
+
+    fn depth_for(size: u32) -> u32 {
+        Cube::level_of(size) - Cube::level_of(DIM as u32) + 1
+    }
+
+    /// Sets the physical size of the edge of a voxel, e.g. in meters; it is saved with the tree.
+    /// World space positions are converted with it by `world_to_voxel` and `voxel_to_world`,
+    /// and by the ray queries accepting world space rays.
+    /// * `meters_per_voxel` - must be positive, 1 by default
+    pub fn with_voxel_size(mut self, meters_per_voxel: f32) -> Self {
+        debug_assert!(0. < meters_per_voxel);
+        self.voxel_size = meters_per_voxel;
+        self
+    }
+
+    /// The physical size of the edge of a voxel, see `with_voxel_size`
+    pub fn voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    /// Converts the given world space position into the coordinates of the tree
+    pub fn world_to_voxel(&self, point: V3c<f32>) -> V3c<f32> {
+        point / self.voxel_size
+    }
+
+    /// Converts the given position in the coordinates of the tree into world space
+    pub fn voxel_to_world(&self, point: V3c<f32>) -> V3c<f32> {
+        point * self.voxel_size
+    }
+
+    /// The estimated memory used by the nodes of the octree in bytes, see `memory_report` for a breakdown
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.count()
+            * (std::mem::size_of::<NodeContent<T>>() + std::mem::size_of::<NodeChildren<PoolKey>>())
+            + self.bricks.count() * std::mem::size_of::<Brick<T, DIM>>()
+    }
+
+    /// Limits the memory the nodes of the octree can use. When the usage is above the budget,
+    /// inserts call the eviction callback first, and fail with `OctreeError::OutOfBudget`
+    /// should the usage remain above the budget. As the usage is checked before each insert
+    /// which would create nodes or bricks, it might go above the budget by the ones created by a single insert.
+    /// * `budget` - The limit in bytes, or None for unbounded growth
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+    }
+
+    /// Limits the number of nodes in the octree, see `CapacityPolicy`.
+    /// Before each insert the pools also reserve space for the nodes it might create,
+    /// so failing allocations are reported as `OctreeError::OutOfMemory` instead of aborting.
+    /// As the number of nodes is checked before each insert which would create nodes or bricks,
+    /// it might go above the limit by the ones created by a single insert.
+    pub fn set_capacity_policy(&mut self, policy: CapacityPolicy) {
+        self.capacity_policy = policy;
+    }
+
+    /// Sets the function to call when an insert would exceed the memory budget or the node capacity
+    pub fn set_eviction_callback(&mut self, callback: Option<EvictionCallback<T, DIM>>) {
+        self.eviction_callback = callback;
+    }
+
+    /// True if there are no voxels in the tree, answered from the occupancy of the root node
+    /// without visiting the rest of the tree.
+    /// Voxels emptied through the reference given by `get_mut` still count until the next edit around them.
+    pub fn is_empty(&self) -> bool {
+        !self.node_has_content(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
+    }
+
+    /// True if there are any voxels inside the given bounds, e.g. for broad-phase culling.
+    /// Nodes completely inside the bounds are answered from their occupancy without visiting their children,
+    /// so only the nodes on the boundary of the bounds are descended into.
+    /// Voxels emptied through the reference given by `get_mut` still count until the next edit around them.
+    pub fn contains_any_in(&self, bounds: &Cube) -> bool {
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(self.octree_size),
+        )];
+        while let Some((node_key, node_bounds)) = node_stack.pop() {
+            if !node_bounds.intersects(bounds) || !self.node_has_content(node_key) {
+                continue;
+            }
+            if bounds.contains_cube(&node_bounds) {
+                return true;
+            }
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(_) => return true,
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (node_bounds.size / DIM as u32).max(1);
+                    for (x, plane) in self.bricks.get(*brick as usize).0.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                let cell = V3c::new(x as u32, y as u32, z as u32) * cell_size;
+                                if !data.is_empty()
+                                    && cell.x < node_bounds.size
+                                    && cell.y < node_bounds.size
+                                    && cell.z < node_bounds.size
+                                    && Cube::new(node_bounds.min_position + cell, cell_size)
+                                        .intersects(bounds)
+                                {
+                                    return true;
+                                }
+                            }
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key][octant];
+                        if self.node_children[node_key].is_occupied(octant) {
+                            node_stack
+                                .push((child_key as usize, node_bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
     /// Provides immutable reference to the data, if there is any at the given position
     pub fn get(&self, position: &V3c<u32>) -> Option<&T> {
         let mut current_bounds = Cube::root_bounds(self.octree_size);
@@ -80,13 +356,20 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                 NodeContent::Nothing => {
                     return None;
                 }
-                NodeContent::Leaf(mat) => {
+                NodeContent::Leaf(brick) => {
+                    let mat = &self.bricks.get(*brick as usize).0;
                     let mat_index = Self::mat_index(&current_bounds, position);
                     if !mat[mat_index.x][mat_index.y][mat_index.z].is_empty() {
                         return Some(&mat[mat_index.x][mat_index.y][mat_index.z]);
                     }
                     return None;
                 }
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        return Some(data);
+                    }
+                    return None;
+                }
                 _ => {
                     let child_octant_at_position = child_octant_for(&current_bounds, position);
                     let child_at_position =
@@ -116,16 +399,16 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                 NodeContent::Nothing => {
                     return None;
                 }
-                NodeContent::Leaf(mat) => {
+                NodeContent::UniformLeaf(data) if data.is_empty() => {
+                    return None;
+                }
+                NodeContent::Leaf(_) | NodeContent::UniformLeaf(_) => {
+                    self.edit_count = self.edit_count.wrapping_add(1);
+                    // A uniform leaf gets its own brick here, as the returned reference must only change one voxel
                     let mat_index = Self::mat_index(&current_bounds, position);
+                    let mat = self.mut_leaf_data(current_node_key);
                     if !mat[mat_index.x][mat_index.y][mat_index.z].is_empty() {
-                        return Some(
-                            &mut self
-                                .nodes
-                                .get_mut(current_node_key)
-                                .as_mut_leaf_ref()
-                                .unwrap()[mat_index.x][mat_index.y][mat_index.z],
-                        );
+                        return Some(&mut mat[mat_index.x][mat_index.y][mat_index.z]);
                     }
                     return None;
                 }
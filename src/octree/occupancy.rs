@@ -0,0 +1,853 @@
+use crate::object_pool::{key_might_be_valid, ObjectPool, PoolKey};
+use crate::octree::{
+    detail::child_octant_for,
+    types::{ChangedRegions, NodeContent, OctreeError},
+    Cube, Octant, Octree, V3c, VoxelData,
+};
+use std::sync::atomic::Ordering;
+
+#[cfg(feature = "raytracing")]
+use crate::spatial::raytracing::Ray;
+
+/// The content of a node of a `BitOctree`
+#[derive(Debug, Default, Clone, Copy)]
+enum BitNode {
+    #[default]
+    Empty,
+    Full,
+    /// The keys of the children of the node, in the order of their octants
+    Internal([PoolKey; 8]),
+    /// The index of the brick storing the occupancy of the voxels of the node
+    Leaf(PoolKey),
+}
+
+/// The part of the source tree a node of a `BitOctree` is derived from
+#[derive(Clone, Copy)]
+enum OccupancySource {
+    /// An internal node of the source tree with the same bounds
+    Node(usize),
+    /// A region of the source tree with every voxel occupied or every voxel empty
+    Uniform(bool),
+    /// A brick of the source tree stored in a node with the given bounds, covering the region
+    Brick(usize, Cube),
+}
+
+/// The operations combining the occupancy of two trees, see `BitOctree::union`
+#[derive(Debug, Clone, Copy)]
+enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    /// Combines the given bits of the voxels of the two trees
+    fn apply(self, a: u64, b: u64) -> u64 {
+        match self {
+            CsgOperation::Union => a | b,
+            CsgOperation::Intersection => a & b,
+            CsgOperation::Difference => a & !b,
+        }
+    }
+
+    /// The result of the operation for the given nodes, should it not depend on the voxels inside them
+    fn decided_by(self, a: BitNode, b: BitNode) -> Option<BitNode> {
+        match (self, a, b) {
+            (CsgOperation::Union, BitNode::Full, _) | (CsgOperation::Union, _, BitNode::Full) => {
+                Some(BitNode::Full)
+            }
+            (CsgOperation::Intersection, BitNode::Empty, _)
+            | (CsgOperation::Intersection, _, BitNode::Empty)
+            | (CsgOperation::Difference, BitNode::Empty, _)
+            | (CsgOperation::Difference, _, BitNode::Full) => Some(BitNode::Empty),
+            (_, BitNode::Empty | BitNode::Full, BitNode::Empty | BitNode::Full) => {
+                let bits = |node| match node {
+                    BitNode::Full => u64::MAX,
+                    _ => 0,
+                };
+                Some(match self.apply(bits(a), bits(b)) {
+                    0 => BitNode::Empty,
+                    _ => BitNode::Full,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Which voxels of an octree are occupied, without their data, see `Octree::to_occupancy`;
+/// e.g. for physics or audio servers which don't need to know the materials of the voxels.
+/// Uniform regions are stored in single nodes, while the nodes of DIM*DIM*DIM voxels
+/// are packed into bricks of one bit for each voxel: with the default DIM of 4 a brick is a single u64,
+/// so counting voxels and combining trees works on whole bricks at once.
+#[derive(Clone)]
+pub struct BitOctree<const DIM: usize = 4> {
+    size: u32,
+    nodes: ObjectPool<BitNode>,
+    bricks: Vec<u64>,
+    free_bricks: Vec<PoolKey>,
+    /// The number of the first change of the tree it was derived from not taken over yet, see `Octree::update_occupancy`
+    changes_seen: u64,
+}
+
+impl<const DIM: usize> BitOctree<DIM> {
+    const ROOT_NODE_KEY: usize = 0;
+
+    /// The number of 64 bit words a brick is stored in
+    const BRICK_WORDS: usize = (DIM * DIM * DIM).div_ceil(64);
+
+    /// Creates a tree without any occupied voxels
+    /// * `size` - must be `DIM * (2^x)`, see `Octree::new`
+    pub fn new(size: u32) -> Result<Self, OctreeError> {
+        if size < DIM as u32 || !(size / DIM as u32).is_power_of_two() || 0 != size % DIM as u32 {
+            return Err(OctreeError::InvalidNodeSize(size));
+        }
+        let mut nodes = ObjectPool::default();
+        let root_key = nodes.push(BitNode::Empty);
+        debug_assert!(Self::ROOT_NODE_KEY == root_key);
+        Ok(Self {
+            size,
+            nodes,
+            bricks: Vec::new(),
+            free_bricks: Vec::new(),
+            changes_seen: 0,
+        })
+    }
+
+    /// The size of the tree, equal to the size of the tree it was derived from
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// True if the voxel at the given position is occupied; positions outside the tree are empty
+    pub fn get(&self, position: &V3c<u32>) -> bool {
+        let mut bounds = Cube::root_bounds(self.size);
+        if !bounds.contains(position) {
+            return false;
+        }
+        let mut node_key = Self::ROOT_NODE_KEY;
+        loop {
+            match *self.nodes.get(node_key) {
+                BitNode::Empty => return false,
+                BitNode::Full => return true,
+                BitNode::Leaf(brick) => {
+                    let offset = *position - bounds.min_position;
+                    let bit =
+                        Self::bit_index(offset.x as usize, offset.y as usize, offset.z as usize);
+                    return 0 != self.brick_words(brick)[bit / 64] & (1 << (bit % 64));
+                }
+                BitNode::Internal(children) => {
+                    let octant = child_octant_for(&bounds, position);
+                    node_key = children[octant as usize] as usize;
+                    bounds = bounds.child_bounds_for(octant);
+                }
+            }
+        }
+    }
+
+    /// Sets whether the voxel at the given position is occupied
+    pub fn set(&mut self, position: &V3c<u32>, occupied: bool) -> Result<(), OctreeError> {
+        let mut bounds = Cube::root_bounds(self.size);
+        if !bounds.contains(position) {
+            return Err(OctreeError::InvalidPosition {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            });
+        }
+        let mut node_key = Self::ROOT_NODE_KEY;
+        let mut parents = Vec::new();
+        while bounds.size as usize > DIM {
+            match *self.nodes.get(node_key) {
+                BitNode::Full if occupied => return Ok(()),
+                BitNode::Empty if !occupied => return Ok(()),
+                _ => {}
+            }
+            let children = self.split(node_key);
+            parents.push(node_key);
+            let octant = child_octant_for(&bounds, position);
+            node_key = children[octant as usize] as usize;
+            bounds = bounds.child_bounds_for(octant);
+        }
+
+        let mut words = self.words_of(*self.nodes.get(node_key));
+        let offset = *position - bounds.min_position;
+        let bit = Self::bit_index(offset.x as usize, offset.y as usize, offset.z as usize);
+        if occupied {
+            words[bit / 64] |= 1 << (bit % 64);
+        } else {
+            words[bit / 64] &= !(1 << (bit % 64));
+        }
+        self.free_content(node_key);
+        *self.nodes.get_mut(node_key) = self.leaf_from_words(&words);
+
+        // Parents left with only empty or only full children are merged
+        for parent_key in parents.into_iter().rev() {
+            let BitNode::Internal(children) = *self.nodes.get(parent_key) else {
+                break;
+            };
+            match Self::merged(&children.map(|child| *self.nodes.get(child as usize))) {
+                Some(node) => {
+                    self.free_content(parent_key);
+                    *self.nodes.get_mut(parent_key) = node;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of occupied voxels inside the given region; bricks inside the region are counted
+    /// by the population count of their bits, uniform nodes by their volume
+    pub fn count_occupied(&self, region: &Cube) -> u64 {
+        self.count_occupied_in(Self::ROOT_NODE_KEY, Cube::root_bounds(self.size), region)
+    }
+
+    /// The ratio of the occupied voxels inside the given region, between 0 and 1
+    pub fn density(&self, region: &Cube) -> f32 {
+        (self.count_occupied(region) as f64 / (region.size as f64).powi(3)) as f32
+    }
+
+    /// The tree of the voxels occupied in either of the trees
+    pub fn union(&self, other: &Self) -> Result<Self, OctreeError> {
+        self.combine(other, CsgOperation::Union)
+    }
+
+    /// The tree of the voxels occupied in both of the trees
+    pub fn intersection(&self, other: &Self) -> Result<Self, OctreeError> {
+        self.combine(other, CsgOperation::Intersection)
+    }
+
+    /// The tree of the voxels occupied in this tree, but not in the other one
+    pub fn difference(&self, other: &Self) -> Result<Self, OctreeError> {
+        self.combine(other, CsgOperation::Difference)
+    }
+
+    /// The number of bytes used by the nodes and the bricks of the tree
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.count() * std::mem::size_of::<BitNode>()
+            + (self.bricks.len() - self.free_bricks.len() * Self::BRICK_WORDS)
+                * std::mem::size_of::<u64>()
+    }
+
+    /// Derives the occupancy of the given region from the given tree, keeping the rest of the tree as it is
+    pub fn update_region<T: Default + PartialEq + Clone + VoxelData>(
+        &mut self,
+        source: &Octree<T, DIM>,
+        region: &Cube,
+    ) {
+        self.refresh(
+            source,
+            Self::ROOT_NODE_KEY,
+            Cube::root_bounds(self.size),
+            region,
+        );
+    }
+
+    /// The number of occupied voxels of the given node inside the given region
+    fn count_occupied_in(&self, node_key: usize, bounds: Cube, region: &Cube) -> u64 {
+        if !bounds.intersects(region) {
+            return 0;
+        }
+        match *self.nodes.get(node_key) {
+            BitNode::Empty => 0,
+            BitNode::Full => {
+                let (min, max) = Self::overlap(&bounds, region);
+                (max.x - min.x) as u64 * (max.y - min.y) as u64 * (max.z - min.z) as u64
+            }
+            BitNode::Internal(children) => Octant::iter()
+                .map(|octant| {
+                    self.count_occupied_in(
+                        children[octant as usize] as usize,
+                        bounds.child_bounds_for(octant),
+                        region,
+                    )
+                })
+                .sum(),
+            BitNode::Leaf(brick) if region.contains_cube(&bounds) => self
+                .brick_words(brick)
+                .iter()
+                .map(|word| word.count_ones() as u64)
+                .sum(),
+            BitNode::Leaf(brick) => {
+                // Only the bits of the voxels inside the region are counted
+                let (min, max) = Self::overlap(&bounds, region);
+                let mut mask = vec![0u64; Self::BRICK_WORDS];
+                for z in min.z..max.z {
+                    for y in min.y..max.y {
+                        for x in min.x..max.x {
+                            let offset = V3c::new(x, y, z) - bounds.min_position;
+                            let bit = Self::bit_index(
+                                offset.x as usize,
+                                offset.y as usize,
+                                offset.z as usize,
+                            );
+                            mask[bit / 64] |= 1 << (bit % 64);
+                        }
+                    }
+                }
+                self.brick_words(brick)
+                    .iter()
+                    .zip(mask.iter())
+                    .map(|(word, mask)| (word & mask).count_ones() as u64)
+                    .sum()
+            }
+        }
+    }
+
+    /// The min and max corners of the part of the given cubes which is inside both
+    fn overlap(a: &Cube, b: &Cube) -> (V3c<u32>, V3c<u32>) {
+        let (a_max, b_max) = (a.max_position(), b.max_position());
+        (
+            V3c::new(
+                a.min_position.x.max(b.min_position.x),
+                a.min_position.y.max(b.min_position.y),
+                a.min_position.z.max(b.min_position.z),
+            ),
+            V3c::new(
+                a_max.x.min(b_max.x),
+                a_max.y.min(b_max.y),
+                a_max.z.min(b_max.z),
+            ),
+        )
+    }
+
+    /// Combines the voxels of the two trees with the given operation into a new tree
+    fn combine(&self, other: &Self, operation: CsgOperation) -> Result<Self, OctreeError> {
+        if self.size != other.size {
+            return Err(OctreeError::InvalidNodeSize(other.size));
+        }
+        let mut result = Self::new(self.size)?;
+        let root = result.combine_nodes(
+            (self, *self.nodes.get(Self::ROOT_NODE_KEY)),
+            (other, *other.nodes.get(Self::ROOT_NODE_KEY)),
+            self.size,
+            operation,
+        );
+        *result.nodes.get_mut(Self::ROOT_NODE_KEY) = root;
+        Ok(result)
+    }
+
+    /// Creates the node combining the given nodes of the given size of two trees with the given operation.
+    /// Bricks are combined word by word, uniform nodes act as if they had uniform children
+    fn combine_nodes(
+        &mut self,
+        a: (&Self, BitNode),
+        b: (&Self, BitNode),
+        size: u32,
+        operation: CsgOperation,
+    ) -> BitNode {
+        if let Some(node) = operation.decided_by(a.1, b.1) {
+            return node;
+        }
+        if size as usize <= DIM {
+            let words =
+                a.0.words_of(a.1)
+                    .iter()
+                    .zip(b.0.words_of(b.1).iter())
+                    .map(|(a, b)| operation.apply(*a, *b))
+                    .collect::<Vec<_>>();
+            return self.leaf_from_words(&words);
+        }
+
+        let children = Octant::ALL.map(|octant| {
+            self.combine_nodes(
+                Self::child_in(a, octant),
+                Self::child_in(b, octant),
+                size / 2,
+                operation,
+            )
+        });
+        match Self::merged(&children) {
+            Some(node) => node,
+            None => BitNode::Internal(children.map(|child| self.nodes.push(child) as PoolKey)),
+        }
+    }
+
+    /// The child of the given node of the given tree in the given octant; uniform nodes are their own children
+    fn child_in((tree, node): (&Self, BitNode), octant: Octant) -> (&Self, BitNode) {
+        match node {
+            BitNode::Internal(children) => {
+                (tree, *tree.nodes.get(children[octant as usize] as usize))
+            }
+            node => (tree, node),
+        }
+    }
+
+    /// The position of the bit of the voxel at the given offset inside its brick, x first, then y, then z
+    fn bit_index(x: usize, y: usize, z: usize) -> usize {
+        x + DIM * (y + DIM * z)
+    }
+
+    /// Derives the node of the given key with the given bounds from the source tree, should it overlap the region
+    fn refresh<T: Default + PartialEq + Clone + VoxelData>(
+        &mut self,
+        source: &Octree<T, DIM>,
+        node_key: usize,
+        bounds: Cube,
+        region: &Cube,
+    ) {
+        if !bounds.intersects(region) {
+            return;
+        }
+        if region.contains_cube(&bounds) || bounds.size as usize <= DIM {
+            let node = self.build(source, source.occupancy_source(&bounds), &bounds);
+            self.free_content(node_key);
+            *self.nodes.get_mut(node_key) = node;
+            return;
+        }
+
+        // Uniform nodes are split, so only the children overlapping the region are derived again
+        let children = self.split(node_key);
+        for octant in Octant::iter() {
+            self.refresh(
+                source,
+                children[octant as usize] as usize,
+                bounds.child_bounds_for(octant),
+                region,
+            );
+        }
+        if let Some(node) = Self::merged(&children.map(|child| *self.nodes.get(child as usize))) {
+            self.free_content(node_key);
+            *self.nodes.get_mut(node_key) = node;
+        }
+    }
+
+    /// The children of the given node above brick size, uniform nodes are split into equal children first
+    fn split(&mut self, node_key: usize) -> [PoolKey; 8] {
+        match *self.nodes.get(node_key) {
+            BitNode::Internal(children) => children,
+            node => {
+                debug_assert!(!matches!(node, BitNode::Leaf(_)));
+                let children = [0; 8].map(|_| self.nodes.push(node) as PoolKey);
+                *self.nodes.get_mut(node_key) = BitNode::Internal(children);
+                children
+            }
+        }
+    }
+
+    /// The node replacing the given children, should they be all empty or all full
+    fn merged(children: &[BitNode; 8]) -> Option<BitNode> {
+        match children[0] {
+            BitNode::Empty if children.iter().all(|child| matches!(child, BitNode::Empty)) => {
+                Some(BitNode::Empty)
+            }
+            BitNode::Full if children.iter().all(|child| matches!(child, BitNode::Full)) => {
+                Some(BitNode::Full)
+            }
+            _ => None,
+        }
+    }
+
+    /// Creates the node with the given bounds from the given part of the source tree
+    fn build<T: Default + PartialEq + Clone + VoxelData>(
+        &mut self,
+        source: &Octree<T, DIM>,
+        view: OccupancySource,
+        bounds: &Cube,
+    ) -> BitNode {
+        match view {
+            OccupancySource::Uniform(false) => BitNode::Empty,
+            OccupancySource::Uniform(true) => BitNode::Full,
+            _ if bounds.size as usize <= DIM => self.build_leaf(source, view, bounds),
+            _ => {
+                let children = Octant::ALL.map(|octant| {
+                    let child_bounds = bounds.child_bounds_for(octant);
+                    let child_view = match view {
+                        OccupancySource::Node(node_key) => {
+                            source.occupancy_source_of(node_key, octant, &child_bounds)
+                        }
+                        view => view,
+                    };
+                    self.build(source, child_view, &child_bounds)
+                });
+                if let Some(node) = Self::merged(&children) {
+                    return node;
+                }
+                BitNode::Internal(children.map(|child| self.nodes.push(child) as PoolKey))
+            }
+        }
+    }
+
+    /// Packs the occupancy of the voxels inside the given bounds of DIM size into a brick
+    fn build_leaf<T: Default + PartialEq + Clone + VoxelData>(
+        &mut self,
+        source: &Octree<T, DIM>,
+        view: OccupancySource,
+        bounds: &Cube,
+    ) -> BitNode {
+        let mut words = vec![0u64; Self::BRICK_WORDS];
+        for z in 0..DIM {
+            for y in 0..DIM {
+                for x in 0..DIM {
+                    let position = bounds.min_position + V3c::new(x as u32, y as u32, z as u32);
+                    let occupied = match view {
+                        OccupancySource::Brick(brick, brick_bounds) => {
+                            let mat_index = Octree::<T, DIM>::mat_index(&brick_bounds, &position);
+                            !source.bricks.get(brick).0[mat_index.x][mat_index.y][mat_index.z]
+                                .is_empty()
+                        }
+                        _ => source.get(&position).is_some(),
+                    };
+                    if occupied {
+                        let bit = Self::bit_index(x, y, z);
+                        words[bit / 64] |= 1 << (bit % 64);
+                    }
+                }
+            }
+        }
+        self.leaf_from_words(&words)
+    }
+
+    /// The node of DIM size with the given bits of its voxels; only partially occupied nodes are stored in a brick
+    fn leaf_from_words(&mut self, words: &[u64]) -> BitNode {
+        let occupied_count = words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        if 0 == occupied_count {
+            return BitNode::Empty;
+        }
+        if DIM.pow(3) == occupied_count {
+            return BitNode::Full;
+        }
+        let brick = match self.free_bricks.pop() {
+            Some(brick) => brick,
+            None => {
+                self.bricks.extend_from_slice(words);
+                return BitNode::Leaf((self.bricks.len() / Self::BRICK_WORDS - 1) as PoolKey);
+            }
+        };
+        let start = brick as usize * Self::BRICK_WORDS;
+        self.bricks[start..start + Self::BRICK_WORDS].copy_from_slice(words);
+        BitNode::Leaf(brick)
+    }
+
+    /// The words of the given brick
+    fn brick_words(&self, brick: PoolKey) -> &[u64] {
+        let start = brick as usize * Self::BRICK_WORDS;
+        &self.bricks[start..start + Self::BRICK_WORDS]
+    }
+
+    /// The bits of the voxels of the given node of DIM size
+    fn words_of(&self, node: BitNode) -> Vec<u64> {
+        match node {
+            BitNode::Leaf(brick) => self.brick_words(brick).to_vec(),
+            BitNode::Full => (0..Self::BRICK_WORDS)
+                .map(|word| match DIM.pow(3) - word * 64 {
+                    bits if bits >= 64 => u64::MAX,
+                    bits => (1 << bits) - 1,
+                })
+                .collect(),
+            _ => vec![0; Self::BRICK_WORDS],
+        }
+    }
+
+    /// Frees the children and the brick of the node of the given key, leaving the node itself in place
+    fn free_content(&mut self, node_key: usize) {
+        match *self.nodes.get(node_key) {
+            BitNode::Internal(children) => {
+                for child in children {
+                    self.free_content(child as usize);
+                    self.nodes.free(child as usize);
+                }
+            }
+            BitNode::Leaf(brick) => self.free_bricks.push(brick),
+            _ => {}
+        }
+    }
+}
+
+/// A ray prepared for the many box tests of an occlusion query
+#[cfg(feature = "raytracing")]
+struct OcclusionRay {
+    origin: [f32; 3],
+    direction: [f32; 3],
+    inverse_direction: [f32; 3],
+    max_distance: f32,
+    /// The octants of a node are visited front to back in the order of their index mirrored by this mask
+    octant_mask: usize,
+}
+
+#[cfg(feature = "raytracing")]
+impl OcclusionRay {
+    fn new(ray: &Ray, max_distance: f32) -> Self {
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        Self {
+            origin: [ray.origin.x, ray.origin.y, ray.origin.z],
+            direction,
+            inverse_direction: direction.map(|component| 1. / component),
+            max_distance,
+            octant_mask: (direction[0] < 0.) as usize
+                | ((direction[2] < 0.) as usize) << 1
+                | ((direction[1] < 0.) as usize) << 2,
+        }
+    }
+
+    /// The distance along the ray where it enters the given cube, should it be not farther than the max distance.
+    /// Rays parallel to an axis only hit the cube if their origin is between its faces on that axis
+    fn entry_distance(&self, cube: &Cube) -> Option<f32> {
+        let min = [
+            cube.min_position.x,
+            cube.min_position.y,
+            cube.min_position.z,
+        ];
+        let mut entry = 0_f32;
+        let mut exit = self.max_distance;
+        for (axis, low) in min.iter().enumerate() {
+            let low = *low as f32;
+            let high = low + cube.size as f32;
+            if 0. == self.direction[axis] {
+                if self.origin[axis] < low || self.origin[axis] > high {
+                    return None;
+                }
+                continue;
+            }
+            let near = (low - self.origin[axis]) * self.inverse_direction[axis];
+            let far = (high - self.origin[axis]) * self.inverse_direction[axis];
+            entry = entry.max(near.min(far));
+            exit = exit.min(near.max(far));
+        }
+        (entry <= exit).then_some(entry)
+    }
+}
+
+#[cfg(feature = "raytracing")]
+impl<const DIM: usize> BitOctree<DIM> {
+    /// True if the ray hits an occupied voxel not farther than the given distance, e.g. for shadow or visibility rays.
+    /// Only the existence of a hit is decided, so the traversal ends at the first occupied voxel along the ray
+    pub fn is_occluded(&self, ray: &Ray, max_distance: f32) -> bool {
+        let ray = OcclusionRay::new(ray, max_distance);
+        let bounds = Cube::root_bounds(self.size);
+        match ray.entry_distance(&bounds) {
+            Some(distance) => self.is_occluded_in(Self::ROOT_NODE_KEY, &bounds, distance, &ray),
+            None => false,
+        }
+    }
+
+    /// True if the ray entering the given node at the given distance hits an occupied voxel inside it
+    fn is_occluded_in(
+        &self,
+        node_key: usize,
+        bounds: &Cube,
+        entry_distance: f32,
+        ray: &OcclusionRay,
+    ) -> bool {
+        match *self.nodes.get(node_key) {
+            BitNode::Empty => false,
+            BitNode::Full => true,
+            BitNode::Internal(children) => (0..8).any(|index| {
+                let octant = Octant::ALL[index ^ ray.octant_mask];
+                let child_key = children[octant as usize] as usize;
+                if matches!(self.nodes.get(child_key), BitNode::Empty) {
+                    return false;
+                }
+                let child_bounds = bounds.child_bounds_for(octant);
+                ray.entry_distance(&child_bounds).is_some_and(|distance| {
+                    self.is_occluded_in(child_key, &child_bounds, distance, ray)
+                })
+            }),
+            BitNode::Leaf(brick) => self.is_occluded_in_brick(brick, bounds, entry_distance, ray),
+        }
+    }
+
+    /// Steps through the voxels of the given brick along the ray from where it enters the brick,
+    /// until it finds an occupied one or leaves the brick
+    fn is_occluded_in_brick(
+        &self,
+        brick: PoolKey,
+        bounds: &Cube,
+        entry_distance: f32,
+        ray: &OcclusionRay,
+    ) -> bool {
+        let words = self.brick_words(brick);
+        let min = [
+            bounds.min_position.x,
+            bounds.min_position.y,
+            bounds.min_position.z,
+        ];
+        let start: [f32; 3] = std::array::from_fn(|axis| {
+            ray.origin[axis] + ray.direction[axis] * entry_distance - min[axis] as f32
+        });
+        let mut cell = start.map(|coordinate| (coordinate.floor() as i32).clamp(0, DIM as i32 - 1));
+        let step = ray
+            .direction
+            .map(|component| if component < 0. { -1 } else { 1 });
+
+        // The distances along the ray to the next cell boundary on each axis
+        let mut next_boundary = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if 0. != ray.direction[axis] {
+                let boundary = (cell[axis] + (step[axis] + 1) / 2) as f32;
+                next_boundary[axis] =
+                    entry_distance + (boundary - start[axis]) * ray.inverse_direction[axis];
+            }
+        }
+
+        let mut distance = entry_distance;
+        while distance <= ray.max_distance {
+            let bit = Self::bit_index(cell[0] as usize, cell[1] as usize, cell[2] as usize);
+            if 0 != words[bit / 64] & (1 << (bit % 64)) {
+                return true;
+            }
+            let axis = if next_boundary[0] < next_boundary[1] {
+                if next_boundary[0] < next_boundary[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next_boundary[1] < next_boundary[2] {
+                1
+            } else {
+                2
+            };
+            distance = next_boundary[axis];
+            cell[axis] += step[axis];
+            if !(0..DIM as i32).contains(&cell[axis]) {
+                return false;
+            }
+            next_boundary[axis] += ray.inverse_direction[axis].abs();
+        }
+        false
+    }
+}
+
+/// The number of changed regions kept before the older half of them is forgotten
+const MAX_CHANGED_REGIONS: usize = 1024;
+
+impl ChangedRegions {
+    /// Records the regions changed from now on, see `insert`
+    pub(in crate::octree) fn start_recording(&self) {
+        self.recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Notes the given region as changed, should the regions be recorded; the whole tree of the given size
+    /// replaces every other region. Once too many regions are noted, the older half of them is forgotten
+    pub(in crate::octree) fn insert(&mut self, min_position: V3c<u32>, size: u32, tree_size: u32) {
+        if !self.recording.load(Ordering::Relaxed) {
+            return;
+        }
+        if (V3c::unit(0), tree_size) == (min_position, size) {
+            // Every region is inside the whole tree, which changed later
+            self.regions.clear();
+        }
+        self.regions.insert((min_position, size), self.next_change);
+        self.next_change += 1;
+        if MAX_CHANGED_REGIONS < self.regions.len() {
+            let mut changes = self.regions.values().copied().collect::<Vec<_>>();
+            let median = *changes.select_nth_unstable(MAX_CHANGED_REGIONS / 2).1;
+            self.regions.retain(|_, change| median <= *change);
+            self.forgotten_before = median;
+        }
+    }
+
+    /// The number of the next change, to update structures derived from the tree as it is now from
+    pub(in crate::octree) fn cursor(&self) -> u64 {
+        self.next_change
+    }
+
+    /// The regions changed since the change of the given number, by their min position and size;
+    /// None should some of them be forgotten already, in which case the whole tree is to be taken as changed
+    pub(in crate::octree) fn since(&self, cursor: u64) -> Option<Vec<(V3c<u32>, u32)>> {
+        if cursor < self.forgotten_before {
+            return None;
+        }
+        Some(
+            self.regions
+                .iter()
+                .filter(|(_, change)| cursor <= **change)
+                .map(|(region, _)| *region)
+                .collect(),
+        )
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Creates a tree of the same size storing only which voxels are occupied, in one bit for each voxel.
+    /// It can be kept up to date with the changes of this tree through `update_occupancy`;
+    /// the changes of this tree are only tracked from the first call on
+    pub fn to_occupancy(&self) -> BitOctree<DIM> {
+        self.changed_regions.start_recording();
+        let mut occupancy = BitOctree::new(self.octree_size).ok().unwrap();
+        occupancy.update_region(self, &Cube::root_bounds(self.octree_size));
+        occupancy.changes_seen = self.changed_regions.cursor();
+        occupancy
+    }
+
+    /// Updates the given occupancy tree with the regions of this tree changed since it was last updated.
+    /// Any number of occupancy trees can be kept up to date this way, each taking over the changes it has not seen yet.
+    /// Changes made through `get_mut` are not tracked. Should the sizes of the trees differ,
+    /// e.g. after `reset_to`, or the occupancy tree fall too far behind, it is derived again for the whole tree.
+    pub fn update_occupancy(&self, occupancy: &mut BitOctree<DIM>) {
+        if occupancy.size == self.octree_size {
+            if let Some(regions) = self.changed_regions.since(occupancy.changes_seen) {
+                for (min_position, size) in regions {
+                    occupancy.update_region(self, &Cube::new(min_position, size));
+                }
+                occupancy.changes_seen = self.changed_regions.cursor();
+                return;
+            }
+        }
+        *occupancy = self.to_occupancy();
+    }
+
+    /// Notes the region affected by an edit of the given size at the given position, and counts the edit,
+    /// see `update_occupancy`, `VoxelBroadPhase::update_voxels` and `VoxelWorld::chunk_mut`
+    pub(in crate::octree) fn mark_changed(&mut self, position: &V3c<u32>, size: u32) {
+        self.edit_count = self.edit_count.wrapping_add(1);
+        let mut region_size = self.octree_size;
+        while region_size > size.max(DIM as u32) && region_size > DIM as u32 {
+            region_size /= 2;
+        }
+        let min_position = V3c::new(
+            position.x - position.x % region_size,
+            position.y - position.y % region_size,
+            position.z - position.z % region_size,
+        );
+        self.changed_regions
+            .insert(min_position, region_size, self.octree_size);
+    }
+
+    /// The part of the tree the occupancy of the given bounds is derived from
+    fn occupancy_source(&self, bounds: &Cube) -> OccupancySource {
+        let mut node_key = Octree::<T, DIM>::ROOT_NODE_KEY as usize;
+        let mut node_bounds = Cube::root_bounds(self.octree_size);
+        loop {
+            let view = self.occupancy_source_at(node_key, &node_bounds);
+            if !matches!(view, OccupancySource::Node(_)) || node_bounds.size <= bounds.size {
+                return view;
+            }
+            let octant = child_octant_for(&node_bounds, &bounds.min_position);
+            let child_key = self.node_children[node_key][octant];
+            if !key_might_be_valid(child_key) {
+                return OccupancySource::Uniform(false);
+            }
+            node_key = child_key as usize;
+            node_bounds = node_bounds.child_bounds_for(octant);
+        }
+    }
+
+    /// The part of the tree the child of the given node in the given octant is
+    fn occupancy_source_of(
+        &self,
+        node_key: usize,
+        octant: Octant,
+        bounds: &Cube,
+    ) -> OccupancySource {
+        let child_key = self.node_children[node_key][octant];
+        if !key_might_be_valid(child_key) {
+            return OccupancySource::Uniform(false);
+        }
+        self.occupancy_source_at(child_key as usize, bounds)
+    }
+
+    /// The content of the given node with the given bounds, as the source of an occupancy tree
+    fn occupancy_source_at(&self, node_key: usize, bounds: &Cube) -> OccupancySource {
+        match self.nodes.get(node_key) {
+            NodeContent::Nothing => OccupancySource::Uniform(false),
+            NodeContent::UniformLeaf(data) => OccupancySource::Uniform(!data.is_empty()),
+            NodeContent::Leaf(brick) => OccupancySource::Brick(*brick as usize, *bounds),
+            NodeContent::Internal(_) => OccupancySource::Node(node_key),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::octree::{detail::bound_contains, types::OctreeError, Cube, Octree, V3c, VoxelData};
+
+/// A small tree placed over a region of a bigger one, e.g. for previews, selection highlights
+/// or cursor ghosts: ray queries and rendering through `Octree::get_by_ray_with_overlay` and
+/// `Octree::render_viewport_with_overlay` show the voxels of the overlay wherever it has any,
+/// without the main tree being changed. Positions are given in the space of the main tree.
+pub struct OverlayOctree<T: Default + Clone + VoxelData, const DIM: usize = 1> {
+    pub(in crate::octree) tree: Octree<T, DIM>,
+    pub(in crate::octree) offset: V3c<u32>,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> OverlayOctree<T, DIM> {
+    /// Creates an empty overlay
+    /// * `size` - The size of the overlay, see `Octree::new`
+    /// * `offset` - The position of the minimum corner of the overlay inside the main tree
+    pub fn new(size: u32, offset: V3c<u32>) -> Result<Self, OctreeError> {
+        Ok(Self {
+            tree: Octree::new(size)?,
+            offset,
+        })
+    }
+
+    /// The position of the minimum corner of the overlay inside the main tree
+    pub fn offset(&self) -> V3c<u32> {
+        self.offset
+    }
+
+    /// Moves the overlay with its contents to the given position, e.g. to follow a cursor
+    pub fn set_offset(&mut self, offset: V3c<u32>) {
+        self.offset = offset;
+    }
+
+    /// The region of the main tree covered by the overlay
+    pub fn bounds(&self) -> Cube {
+        Cube::new(self.offset, self.tree.octree_size)
+    }
+
+    /// The tree storing the contents of the overlay, positioned relative to its offset
+    pub fn tree(&self) -> &Octree<T, DIM> {
+        &self.tree
+    }
+
+    /// The tree storing the contents of the overlay, positioned relative to its offset
+    pub fn tree_mut(&mut self) -> &mut Octree<T, DIM> {
+        &mut self.tree
+    }
+
+    /// Provides immutable reference to the data in the overlay, if there is any at the given position
+    pub fn get(&self, position: &V3c<u32>) -> Option<&T> {
+        if !bound_contains(&self.bounds(), position) {
+            return None;
+        }
+        self.tree.get(&(*position - self.offset))
+    }
+
+    /// Sets the given data in the overlay at the given position, which must be inside the overlay
+    pub fn insert(&mut self, position: &V3c<u32>, data: T) -> Result<(), OctreeError> {
+        self.tree.insert(&self.local_position(position)?, data)
+    }
+
+    /// Clears the data in the overlay at the given position, which must be inside the overlay
+    pub fn clear(&mut self, position: &V3c<u32>) -> Result<(), OctreeError> {
+        self.tree.clear(&self.local_position(position)?)
+    }
+
+    /// The position inside the overlay tree of the given position of the main tree
+    fn local_position(&self, position: &V3c<u32>) -> Result<V3c<u32>, OctreeError> {
+        if !bound_contains(&self.bounds(), position) {
+            return Err(OctreeError::InvalidPosition {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            });
+        }
+        Ok(*position - self.offset)
+    }
+}
@@ -0,0 +1,67 @@
+//! The iterators the crate builds trees and renders with: rayon's parallel iterators with the `parallel` feature,
+//! or serial iterators with the same methods without it
+
+// Which of the methods are used depends on the enabled features
+#[cfg(feature = "parallel")]
+#[allow(unused_imports)]
+pub(crate) use rayon::{current_num_threads, prelude::*};
+
+#[cfg(not(feature = "parallel"))]
+#[allow(unused_imports)]
+pub(crate) use serial::*;
+
+#[cfg(not(feature = "parallel"))]
+#[allow(dead_code)]
+mod serial {
+    /// The number of threads work is spread on
+    pub(crate) fn current_num_threads() -> usize {
+        1
+    }
+
+    pub(crate) trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    pub(crate) trait ParallelSlice<T> {
+        fn par_iter(&self) -> std::slice::Iter<'_, T>;
+        fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_iter(&self) -> std::slice::Iter<'_, T> {
+            self.iter()
+        }
+
+        fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+            self.iter_mut()
+        }
+    }
+
+    /// The methods of rayon's parallel iterators missing from `Iterator`;
+    /// the state created by `init` is shared by every item
+    pub(crate) trait ParallelIterator: Iterator + Sized {
+        fn map_init<S, R>(
+            self,
+            init: impl FnOnce() -> S,
+            mut map: impl FnMut(&mut S, Self::Item) -> R,
+        ) -> impl Iterator<Item = R> {
+            let mut state = init();
+            self.map(move |item| map(&mut state, item))
+        }
+
+        fn for_each_init<S>(
+            self,
+            init: impl FnOnce() -> S,
+            mut op: impl FnMut(&mut S, Self::Item),
+        ) {
+            let mut state = init();
+            self.for_each(|item| op(&mut state, item));
+        }
+    }
+
+    impl<I: Iterator> ParallelIterator for I {}
+}
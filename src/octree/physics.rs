@@ -0,0 +1,236 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{types::NodeContent, Aabb, Cube, Octant, Octree, V3c, VoxelData};
+use std::collections::HashSet;
+
+/// Provides a small set of boxes covering the voxels with data inside the given bounds,
+/// e.g. to be used as colliders in a physics engine. Uniform regions of the tree are kept as one
+/// box each, the voxels stored in bricks are merged greedily into boxes along x, then y, then z.
+/// The boxes are in world units, see `Octree::with_voxel_size`.
+pub fn extract_colliders<T, const DIM: usize>(tree: &Octree<T, DIM>, bounds: &Cube) -> Vec<Aabb>
+where
+    T: Default + PartialEq + Clone + VoxelData,
+{
+    let mut boxes = Vec::new();
+    let mut voxels = HashSet::new();
+    let mut node_stack = vec![(
+        Octree::<T, DIM>::ROOT_NODE_KEY,
+        Cube::root_bounds(tree.octree_size),
+    )];
+    while let Some((node_key, node_bounds)) = node_stack.pop() {
+        if !node_bounds.intersects(bounds) {
+            continue;
+        }
+        match tree.nodes.get(node_key as usize) {
+            NodeContent::Nothing => {}
+            NodeContent::Internal(_) => {
+                for octant in Octant::iter() {
+                    let child_key = tree.node_children[node_key as usize][octant];
+                    if key_might_be_valid(child_key) {
+                        node_stack.push((child_key, node_bounds.child_bounds_for(octant)));
+                    }
+                }
+            }
+            NodeContent::UniformLeaf(data) => {
+                if !data.is_empty() {
+                    let (min, max) = clipped(&node_bounds, bounds);
+                    boxes.push((min, max));
+                }
+            }
+            NodeContent::Leaf(brick) => {
+                let cell_size = (node_bounds.size / DIM as u32).max(1);
+                for (x, plane) in tree.bricks.get(*brick as usize).0.iter().enumerate() {
+                    for (y, row) in plane.iter().enumerate() {
+                        for (z, data) in row.iter().enumerate() {
+                            if data.is_empty() {
+                                continue;
+                            }
+                            let cell = Cube::new(
+                                node_bounds.min_position
+                                    + V3c::new(x as u32, y as u32, z as u32) * cell_size,
+                                cell_size,
+                            );
+                            if !cell.intersects(bounds) {
+                                continue;
+                            }
+                            let (min, max) = clipped(&cell, bounds);
+                            for x in min.x..max.x {
+                                for y in min.y..max.y {
+                                    for z in min.z..max.z {
+                                        voxels.insert((x, y, z));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    boxes.extend(merge_voxels(voxels));
+    boxes
+        .into_iter()
+        .map(|(min, max)| {
+            Aabb::new(
+                tree.voxel_to_world(min.into()),
+                tree.voxel_to_world(max.into()),
+            )
+        })
+        .collect()
+}
+
+/// The minimum and maximum corners of the part of the region inside the bounds
+fn clipped(region: &Cube, bounds: &Cube) -> (V3c<u32>, V3c<u32>) {
+    let region_max = region.max_position();
+    let bounds_max = bounds.max_position();
+    (
+        V3c::new(
+            region.min_position.x.max(bounds.min_position.x),
+            region.min_position.y.max(bounds.min_position.y),
+            region.min_position.z.max(bounds.min_position.z),
+        ),
+        V3c::new(
+            region_max.x.min(bounds_max.x),
+            region_max.y.min(bounds_max.y),
+            region_max.z.min(bounds_max.z),
+        ),
+    )
+}
+
+/// Merges the given voxels into boxes: starting from the lowest voxel not yet covered,
+/// each box is grown as far as it can along x, then y, then z
+fn merge_voxels(mut voxels: HashSet<(u32, u32, u32)>) -> Vec<(V3c<u32>, V3c<u32>)> {
+    let mut order = voxels.iter().copied().collect::<Vec<_>>();
+    order.sort_by_key(|(x, y, z)| (*z, *y, *x));
+    let mut boxes = Vec::new();
+    for (x, y, z) in order {
+        if !voxels.contains(&(x, y, z)) {
+            continue;
+        }
+        let mut max_x = x + 1;
+        while voxels.contains(&(max_x, y, z)) {
+            max_x += 1;
+        }
+        let mut max_y = y + 1;
+        while (x..max_x).all(|x| voxels.contains(&(x, max_y, z))) {
+            max_y += 1;
+        }
+        let mut max_z = z + 1;
+        while (x..max_x).all(|x| (y..max_y).all(|y| voxels.contains(&(x, y, max_z)))) {
+            max_z += 1;
+        }
+        for x in x..max_x {
+            for y in y..max_y {
+                for z in z..max_z {
+                    voxels.remove(&(x, y, z));
+                }
+            }
+        }
+        boxes.push((V3c::new(x, y, z), V3c::new(max_x, max_y, max_z)));
+    }
+    boxes
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// True if any of the given boxes overlaps a voxel with data, e.g. the boxes of a fast moving object
+    /// sampled along its motion within a step, where a single box swept over the whole motion is too coarse.
+    /// The boxes are in world units, see `with_voxel_size`; boxes only touching a voxel do not overlap it.
+    /// The tree is descended once for all the boxes: every node is only checked against the boxes
+    /// overlapping its parent, so boxes close to each other share the nodes visited on the way down
+    /// * `aabb_path` - The boxes of the object at the sampled moments of its motion
+    pub fn continuous_overlaps(&self, aabb_path: &[Aabb]) -> bool {
+        let boxes = aabb_path
+            .iter()
+            .map(|aabb| Aabb::new(self.world_to_voxel(aabb.min), self.world_to_voxel(aabb.max)))
+            .collect::<Vec<_>>();
+        let root_bounds = Cube::root_bounds(self.octree_size);
+        let root_aabb = Aabb::from(root_bounds);
+
+        // The indices of the boxes overlapping each node on the stack are kept in a single buffer,
+        // every node owning a range of it. The ranges of the nodes pushed after a node are behind its own range,
+        // and they are all popped before it, so the buffer can be cut back to the end of each range popped
+        let mut candidates = (0..boxes.len())
+            .filter(|index| boxes[*index].intersects(&root_aabb))
+            .collect::<Vec<_>>();
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY as usize,
+            root_bounds,
+            0,
+            candidates.len(),
+        )];
+        while let Some((node_key, node_bounds, start, end)) = node_stack.pop() {
+            candidates.truncate(end);
+            if start == end {
+                continue;
+            }
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(data) => {
+                    if !data.is_empty() {
+                        return true;
+                    }
+                }
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (node_bounds.size / DIM as u32).max(1);
+                    for (x, plane) in self.bricks.get(*brick as usize).0.iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                let cell = V3c::new(x as u32, y as u32, z as u32) * cell_size;
+                                if data.is_empty()
+                                    || cell.x >= node_bounds.size
+                                    || cell.y >= node_bounds.size
+                                    || cell.z >= node_bounds.size
+                                {
+                                    continue;
+                                }
+                                let cell_aabb = Aabb::from(Cube::new(
+                                    node_bounds.min_position + cell,
+                                    cell_size,
+                                ));
+                                if candidates[start..end]
+                                    .iter()
+                                    .any(|index| boxes[*index].intersects(&cell_aabb))
+                                {
+                                    return true;
+                                }
+                            }
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    let children = &self.node_children[node_key];
+                    if 0 == children.occupied_bits {
+                        continue;
+                    }
+                    // Nodes completely inside any of the boxes are answered from their occupancy
+                    let node_aabb = Aabb::from(node_bounds);
+                    if candidates[start..end]
+                        .iter()
+                        .any(|index| boxes[*index].contains_aabb(&node_aabb))
+                    {
+                        return true;
+                    }
+                    for octant in Octant::iter() {
+                        if !children.is_occupied(octant) {
+                            continue;
+                        }
+                        let child_bounds = node_bounds.child_bounds_for(octant);
+                        let child_aabb = Aabb::from(child_bounds);
+                        let child_start = candidates.len();
+                        for candidate in start..end {
+                            if boxes[candidates[candidate]].intersects(&child_aabb) {
+                                candidates.push(candidates[candidate]);
+                            }
+                        }
+                        node_stack.push((
+                            children[octant] as usize,
+                            child_bounds,
+                            child_start,
+                            candidates.len(),
+                        ));
+                    }
+                }
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Receives the progress of long operations, e.g. to show a progress bar.
+/// Implemented for closures taking the amount of work done and the total amount of work,
+/// and for `()` to ignore the progress. Parallel operations may report from multiple threads.
+pub trait ProgressSink: Sync {
+    /// Called whenever a part of the operation is done, `done` is at most `total`
+    fn report(&self, done: usize, total: usize);
+}
+
+impl<F: Fn(usize, usize) + Sync> ProgressSink for F {
+    fn report(&self, done: usize, total: usize) {
+        self(done, total)
+    }
+}
+
+impl ProgressSink for () {
+    fn report(&self, _done: usize, _total: usize) {}
+}
+
+/// A flag to abort long operations with, e.g. from a UI thread while the operation runs on another one.
+/// Clones of the token share the flag. Operations check it between their steps and return early
+/// when it is set, leaving their target in a valid state, as documented for each of them.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests every operation using the token, or any clone of it, to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
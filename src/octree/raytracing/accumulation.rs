@@ -0,0 +1,119 @@
+use crate::octree::{
+    raytracing::{color::ToneMapping, render_on_cpu::BACKGROUND_COLOR},
+    V3c,
+};
+
+/// The samples rendered into an image over multiple frames, see `Octree::accumulate_viewport`.
+/// Every pixel keeps the running average of the linear color, the albedo and the normal of its samples,
+/// so denoisers can be guided by them; samples showing the background have zero albedo and normal
+#[derive(Debug, Clone)]
+pub struct AccumulationBuffer {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) color: Vec<[f32; 3]>,
+    pub(crate) albedo: Vec<[f32; 3]>,
+    pub(crate) normal: Vec<V3c<f32>>,
+    pub(crate) sample_count: Vec<u32>,
+}
+
+/// A filter applied to the accumulated samples before they are displayed, e.g. a binding to an external denoiser
+pub trait Denoiser {
+    /// Provides the filtered linear colors of the given buffer row by row, one for every pixel
+    fn denoise(&mut self, buffer: &AccumulationBuffer) -> Vec<[f32; 3]>;
+}
+
+impl AccumulationBuffer {
+    /// Creates a buffer without any samples for images of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            width,
+            height,
+            color: vec![[0.; 3]; pixel_count],
+            albedo: vec![[0.; 3]; pixel_count],
+            normal: vec![V3c::unit(0.); pixel_count],
+            sample_count: vec![0; pixel_count],
+        }
+    }
+
+    /// Drops every sample, e.g. after the camera moved or the contents of the tree changed
+    pub fn reset(&mut self) {
+        *self = Self::new(self.width, self.height);
+    }
+
+    /// The width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The average linear color of the samples of every pixel, row by row
+    pub fn color(&self) -> &[[f32; 3]] {
+        &self.color
+    }
+
+    /// The average linear albedo of the voxels hit by the samples of every pixel, row by row
+    pub fn albedo(&self) -> &[[f32; 3]] {
+        &self.albedo
+    }
+
+    /// The average normal of the faces hit by the samples of every pixel, row by row
+    pub fn normal(&self) -> &[V3c<f32>] {
+        &self.normal
+    }
+
+    /// The number of samples accumulated for every pixel, row by row
+    pub fn sample_count(&self) -> &[u32] {
+        &self.sample_count
+    }
+
+    /// Adds a sample to the pixel at the given index, updating its running averages
+    pub(crate) fn add_sample(
+        &mut self,
+        index: usize,
+        color: [f32; 3],
+        albedo: [f32; 3],
+        normal: V3c<f32>,
+    ) {
+        self.sample_count[index] += 1;
+        let weight = 1. / self.sample_count[index] as f32;
+        for channel in 0..3 {
+            self.color[index][channel] += (color[channel] - self.color[index][channel]) * weight;
+            self.albedo[index][channel] += (albedo[channel] - self.albedo[index][channel]) * weight;
+        }
+        self.normal[index] = self.normal[index] + (normal - self.normal[index]) * weight;
+    }
+
+    /// Converts the accumulated colors into an RGBA8 image through the given tone mapping,
+    /// filtering them with the given denoiser first. Pixels without samples show the background
+    pub fn resolve(
+        &self,
+        denoiser: Option<&mut dyn Denoiser>,
+        tone_mapping: &ToneMapping,
+    ) -> Vec<u8> {
+        let denoised;
+        let colors = match denoiser {
+            Some(denoiser) => {
+                denoised = denoiser.denoise(self);
+                debug_assert_eq!(denoised.len(), self.color.len());
+                &denoised
+            }
+            None => &self.color,
+        };
+        colors
+            .iter()
+            .zip(self.sample_count.iter())
+            .flat_map(|(color, &sample_count)| {
+                if 0 == sample_count {
+                    return BACKGROUND_COLOR;
+                }
+                let [r, g, b] = tone_mapping.apply_rgb(*color);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
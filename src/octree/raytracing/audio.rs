@@ -0,0 +1,67 @@
+use crate::octree::{raytracing::RayHitCompact, Octree, V3c, VoxelData};
+
+/// The offsets of the rays cast around the listener in `occlusion_between`, relative to the
+/// size of a voxel; sounds passing around the edge of an obstacle are only partially occluded
+const OCCLUSION_PROBE_OFFSETS: [(f32, f32, f32); 7] = [
+    (0., 0., 0.),
+    (0.5, 0., 0.),
+    (-0.5, 0., 0.),
+    (0., 0.5, 0.),
+    (0., -0.5, 0.),
+    (0., 0., 0.5),
+    (0., 0., -0.5),
+];
+
+/// The angle between consecutive directions of a Fibonacci spiral, spreading them evenly
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + Sync + VoxelData, const DIM: usize>
+    Octree<T, DIM>
+{
+    /// How much of the sound travelling between the given points is blocked by voxels:
+    /// 0. for a clear line of sight, 1. if every probe ray around it is obstructed.
+    /// * `a` - The position of the source of the sound
+    /// * `b` - The position of the listener
+    pub fn occlusion_between(&self, a: V3c<f32>, b: V3c<f32>) -> f32 {
+        let targets = OCCLUSION_PROBE_OFFSETS
+            .iter()
+            .map(|(x, y, z)| b + V3c::new(*x, *y, *z))
+            .collect::<Vec<_>>();
+        let dirs = targets.iter().map(|target| *target - a).collect::<Vec<_>>();
+        if dirs.iter().any(|dir| 0. == dir.length()) {
+            return 0.;
+        }
+        let origins = vec![a; targets.len()];
+        let mut hits = vec![RayHitCompact::default(); targets.len()];
+        self.raycast_batch(&origins, &dirs, &mut hits);
+        let blocked = hits
+            .iter()
+            .zip(dirs.iter())
+            .filter(|(hit, dir)| hit.hit && hit.distance < dir.length())
+            .count();
+        blocked as f32 / targets.len() as f32
+    }
+
+    /// The fraction of rays cast from the given point into the upper hemisphere which are not
+    /// obstructed by any voxel, e.g. to estimate how enclosed a space is for reverb.
+    /// 1. in the open, near 0. inside closed rooms.
+    /// * `point` - The position to probe from
+    /// * `samples` - The number of rays to cast, spread evenly over the hemisphere
+    pub fn openness_at(&self, point: V3c<f32>, samples: u32) -> f32 {
+        if 0 == samples {
+            return 1.;
+        }
+        let dirs = (0..samples)
+            .map(|i| {
+                let y = 1. - (i as f32 + 0.5) / samples as f32;
+                let radius = (1. - y * y).sqrt();
+                let angle = i as f32 * GOLDEN_ANGLE;
+                V3c::new(radius * angle.cos(), y, radius * angle.sin())
+            })
+            .collect::<Vec<_>>();
+        let origins = vec![point; dirs.len()];
+        let mut hits = vec![RayHitCompact::default(); dirs.len()];
+        self.raycast_batch(&origins, &dirs, &mut hits);
+        hits.iter().filter(|hit| !hit.hit).count() as f32 / samples as f32
+    }
+}
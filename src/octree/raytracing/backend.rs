@@ -0,0 +1,20 @@
+use crate::octree::{raytracing::types::Camera, Octree, VoxelData};
+
+/// A renderer the contents of an octree can be uploaded to and displayed with.
+/// The node structure and the voxel data are uploaded separately, so backends keeping them in
+/// different buffers only need to update what changed; after structural changes both are to be uploaded.
+pub trait VoxelRenderBackend<T: Default + Clone + VoxelData, const DIM: usize> {
+    /// The result of rendering a frame, e.g. the bytes of an image or a handle to GPU resources
+    type Frame<'a>
+    where
+        Self: 'a;
+
+    /// Uploads the nodes of the given tree, describing its structure
+    fn upload_nodes(&mut self, tree: &Octree<T, DIM>);
+
+    /// Uploads the voxels stored in the leaves of the given tree
+    fn upload_bricks(&mut self, tree: &Octree<T, DIM>);
+
+    /// Renders the uploaded contents through the given camera
+    fn render(&mut self, camera: &Camera) -> Self::Frame<'_>;
+}
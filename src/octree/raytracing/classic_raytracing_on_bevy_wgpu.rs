@@ -1,4 +1,4 @@
-use crate::object_pool::key_none_value;
+use crate::object_pool::{key_might_be_valid, PoolKey};
 use crate::octree::{
     raytracing::types::{OctreeMetaData, OctreeViewMaterial, SizedNode, Viewport, Voxelement},
     NodeContent,
@@ -10,6 +10,22 @@ use bevy::{
     render::{color::Color, render_resource::ShaderRef},
 };
 
+/// The shaders address their buffers with 32 bit keys, with `u32::MAX` marking the absence of a node.
+/// With the `u64_keys` feature the tree can only be uploaded while its keys fit into 32 bits
+fn gpu_keys(keys: [PoolKey; 8]) -> [u32; 8] {
+    keys.map(|key| {
+        if key_might_be_valid(key) {
+            assert!(
+                key < u32::MAX as PoolKey,
+                "Node key {key} does not fit into the 32 bit keys of the GPU buffers"
+            );
+            key as u32
+        } else {
+            u32::MAX
+        }
+    })
+}
+
 impl Material for OctreeViewMaterial {
     fn fragment_shader() -> ShaderRef {
         "shaders/viewport_render.wgsl".into()
@@ -33,24 +49,24 @@ impl<T: Default + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
         let mut voxels = Vec::new();
         for i in 0..self.nodes.len() {
             match self.nodes.get(i) {
-                NodeContent::Leaf(data) => {
+                NodeContent::Leaf(_) | NodeContent::UniformLeaf(_) => {
+                    let voxel_at = |x: usize, y: usize, z: usize| match self.nodes.get(i) {
+                        NodeContent::Leaf(brick) => &self.bricks.get(*brick as usize).0[x][y][z],
+                        NodeContent::UniformLeaf(data) => data,
+                        _ => unreachable!(),
+                    };
                     nodes.push(SizedNode {
                         contains_nodes: 1,
-                        children: self.node_children[i].get_full(),
+                        children: gpu_keys(self.node_children[i].get_full()),
                         voxels_start_at: voxels.len() as u32,
                     });
                     for x in 0..DIM {
                         for y in 0..DIM {
                             for z in 0..DIM {
-                                let albedo = data[x][y][z].albedo();
-                                let content = data[x][y][z].user_data();
+                                let [r, g, b, a] = voxel_at(x, y, z).albedo_hdr();
+                                let content = voxel_at(x, y, z).user_data();
                                 voxels.push(Voxelement {
-                                    albedo: Color::rgba(
-                                        albedo[0] as f32 / 255.,
-                                        albedo[1] as f32 / 255.,
-                                        albedo[2] as f32 / 255.,
-                                        albedo[3] as f32 / 255.,
-                                    ),
+                                    albedo: Color::rgba_linear(r, g, b, a),
                                     content,
                                 })
                             }
@@ -60,15 +76,15 @@ impl<T: Default + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
                 NodeContent::Internal(count) => {
                     nodes.push(SizedNode {
                         contains_nodes: *count,
-                        children: self.node_children[i].get_full(),
-                        voxels_start_at: key_none_value(),
+                        children: gpu_keys(self.node_children[i].get_full()),
+                        voxels_start_at: u32::MAX,
                     });
                 }
                 NodeContent::Nothing => {
                     nodes.push(SizedNode {
                         contains_nodes: 0,
-                        children: self.node_children[i].get_full(),
-                        voxels_start_at: key_none_value(),
+                        children: gpu_keys(self.node_children[i].get_full()),
+                        voxels_start_at: u32::MAX,
                     });
                 }
             }
@@ -0,0 +1,63 @@
+/// Converts a linear color channel in range 0..1 to the sRGB transfer curve displays expect
+pub fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0., 1.);
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Converts a color channel encoded with the sRGB transfer curve in range 0..1 to linear
+pub fn srgb_to_linear(value: f32) -> f32 {
+    let value = value.clamp(0., 1.);
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Compresses unbounded linear intensity into range 0..1, keeping dark values close to unchanged
+pub fn reinhard(value: f32) -> f32 {
+    let value = value.max(0.);
+    value / (1. + value)
+}
+
+/// Describes how the linear radiance computed by the raytracer is turned into displayable 8 bit colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    /// Multiplier applied to the linear radiance before tone mapping
+    pub exposure: f32,
+    /// Apply the Reinhard operator before encoding, otherwise values above 1 are clipped
+    pub reinhard: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 1.,
+            reinhard: false,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Converts the given linear color channel into an 8 bit sRGB encoded value
+    pub fn apply(&self, linear: f32) -> u8 {
+        let mut value = linear * self.exposure;
+        if self.reinhard {
+            value = reinhard(value);
+        }
+        (linear_to_srgb(value) * 255. + 0.5) as u8
+    }
+
+    /// Converts the given linear RGB color into 8 bit sRGB encoded values
+    pub fn apply_rgb(&self, linear: [f32; 3]) -> [u8; 3] {
+        [
+            self.apply(linear[0]),
+            self.apply(linear[1]),
+            self.apply(linear[2]),
+        ]
+    }
+}
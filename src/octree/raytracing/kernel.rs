@@ -0,0 +1,45 @@
+use crate::octree::{Cube, V3c, VoxelData, VoxelShape};
+use crate::spatial::raytracing::Ray;
+
+/// Where a ray hits a cell accepted by a `TraversalKernel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellHit {
+    /// The ray hits the cell where it enters it
+    Entry,
+    /// The ray hits something inside the cell at the given distance along the ray, on a surface with the given normal
+    At { distance: f32, normal: V3c<f32> },
+}
+
+/// Decides how rays interact with the cells of the leaf nodes they pass through, see `Octree::get_by_ray_with_kernel`.
+/// The nodes of the tree are traversed the same way for every kernel, only the cells inside the leaves are
+/// resolved by it, so custom kernels can e.g. sphere trace signed distance fields inside bricks, or sample densities.
+/// Cells are visited in front-to-back order: the cells of bricks one by one, while uniform leaves of cube shaped voxels
+/// are given as a single cell
+pub trait TraversalKernel<T> {
+    /// True if the ray is to look for a hit inside a cell with the given data; called for every cell the ray passes
+    fn accepts(&mut self, data: &T) -> bool;
+
+    /// Provides the hit of the ray inside the given cell with the given data, should it hit anything in it.
+    /// Only called for the cells accepted by `accepts`
+    fn hit_cell(&mut self, ray: &Ray, data: &T, cell: &Cube) -> Option<CellHit>;
+}
+
+/// The kernel of the raycasts of the tree: every non-empty voxel is hit, cubes where the ray enters their cell,
+/// other shapes where the ray hits their surface
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultKernel;
+
+impl<T: VoxelData> TraversalKernel<T> for DefaultKernel {
+    fn accepts(&mut self, data: &T) -> bool {
+        !data.is_empty()
+    }
+
+    fn hit_cell(&mut self, ray: &Ray, data: &T, cell: &Cube) -> Option<CellHit> {
+        match data.shape() {
+            VoxelShape::Cube => Some(CellHit::Entry),
+            shape => shape
+                .intersect_ray(ray, cell)
+                .map(|(distance, normal)| CellHit::At { distance, normal }),
+        }
+    }
+}
@@ -1,11 +1,59 @@
+#[cfg(feature = "cpu_render")]
+pub mod accumulation;
+
+#[cfg(feature = "raytracing")]
+pub mod audio;
+
+#[cfg(feature = "raytracing")]
+pub mod backend;
+
+#[cfg(feature = "cpu_render")]
+pub mod color;
+
+#[cfg(feature = "raytracing")]
+pub mod kernel;
+
 #[cfg(feature = "raytracing")]
 pub mod raytracing_on_cpu;
 
+#[cfg(feature = "cpu_render")]
+pub mod render_on_cpu;
+
+#[cfg(feature = "raytracing")]
+pub mod world_on_cpu;
+
 #[cfg(feature = "bevy_wgpu")]
 pub mod classic_raytracing_on_bevy_wgpu;
 
 #[cfg(feature = "raytracing")]
-pub use crate::spatial::raytracing::Ray;
+pub use crate::spatial::raytracing::{
+    intersect_aabb, reflect, refract, BoxFace, CubeRayIntersection, Ray,
+};
+
+#[cfg(feature = "raytracing")]
+pub use backend::VoxelRenderBackend;
+
+#[cfg(feature = "raytracing")]
+pub use kernel::{CellHit, DefaultKernel, TraversalKernel};
+
+#[cfg(feature = "cpu_render")]
+pub use render_on_cpu::CpuRenderBackend;
+
+#[cfg(feature = "cpu_render")]
+pub use accumulation::{AccumulationBuffer, Denoiser};
+
+#[cfg(feature = "cpu_render")]
+pub use color::ToneMapping;
+
+#[cfg(feature = "raytracing")]
+pub use types::{
+    Camera, HitOrBudgetExceeded, InsideVoxelPolicy, LodFade, OwnedRayHit, PrefetchPolicy,
+    RayContext, RayFootprint, RayHitCompact, RayOptions, TraversalCounters, WorldLodRayHit,
+    WorldRayHit,
+};
+
+#[cfg(feature = "cpu_render")]
+pub use types::{AdaptiveRendering, FrameCoherenceCache, ImageTile, TileOrder, TraversalHeatmap};
 
 #[cfg(feature = "bevy_wgpu")]
 pub use types::{OctreeViewMaterial, Viewport};
@@ -1,45 +1,266 @@
-use crate::octree::{raytracing::types::NodeStackItem, NodeContent};
-use crate::octree::{Cube, Octree, V3c, VoxelData};
+use crate::object_pool::{key_might_be_valid, PoolKey};
+use crate::octree::{
+    detail::child_octant_for,
+    parallel::*,
+    raytracing::kernel::{CellHit, DefaultKernel, TraversalKernel},
+    raytracing::types::{
+        HitOrBudgetExceeded, InsideVoxelPolicy, NodeStack, NodeStackItem, OwnedRayHit,
+        PrefetchPolicy, RayContext, RayFootprint, RayHit, RayHitCompact, RayOptions,
+        TraversalCounters, MAX_NODE_STACK_DEPTH,
+    },
+    types::{OctreeEdit, OctreeWriteQueue},
+    NodeContent,
+};
+use crate::octree::{Cube, Octree, OverlayOctree, V3c, VoxelData, VoxelShape};
 
 use crate::spatial::{
-    math::{hash_region, offset_region},
+    math::{Octant, RAY_NEXT_MIRRORED_OCTANT},
     raytracing::{CubeRayIntersection, Ray},
     FLOAT_ERROR_TOLERANCE,
 };
 
+/// The index of the cell hit inside a matrix, with the distance and normal of the hit
+/// should it have been refined against the shape of the voxel
+type MatrixHit = (V3c<usize>, Option<(f32, V3c<f32>)>);
+
+/// Hints the CPU to load the memory of the given item into the cache, without waiting for it.
+/// Only a hint: no memory is read, and it does nothing on architectures without support for it
+#[inline(always)]
+fn prefetch<I>(item: &I) {
+    // SAFETY: SSE is available on every x86_64 CPU, and prefetching never faults, even on invalid addresses
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch::<{ std::arch::x86_64::_MM_HINT_T0 }>(
+            item as *const I as *const i8,
+        );
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = item;
+}
+
 impl NodeStackItem {
+    /// Creates the stack item for the root node, should the ray intersect it
+    pub(crate) fn for_root(bounds: Cube, node: PoolKey, ray: &Ray) -> Option<Self> {
+        let min_position = V3c::<f32>::from(bounds.min_position);
+        let max_position = min_position + V3c::unit(bounds.size as f32);
+        let min_plane_distances = V3c::new(
+            (min_position.x - ray.origin.x) / ray.direction.x,
+            (min_position.y - ray.origin.y) / ray.direction.y,
+            (min_position.z - ray.origin.z) / ray.direction.z,
+        );
+        let max_plane_distances = V3c::new(
+            (max_position.x - ray.origin.x) / ray.direction.x,
+            (max_position.y - ray.origin.y) / ray.direction.y,
+            (max_position.z - ray.origin.z) / ray.direction.z,
+        );
+        let item = Self::new(
+            bounds,
+            node,
+            V3c::new(
+                min_plane_distances.x.min(max_plane_distances.x),
+                min_plane_distances.y.min(max_plane_distances.y),
+                min_plane_distances.z.min(max_plane_distances.z),
+            ),
+            V3c::new(
+                min_plane_distances.x.max(max_plane_distances.x),
+                min_plane_distances.y.max(max_plane_distances.y),
+                min_plane_distances.z.max(max_plane_distances.z),
+            ),
+        );
+        if 0. <= item.exit_distance() && item.entry_distance() <= item.exit_distance() {
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a stack item with the first child to visit set from the given plane distances
     pub(crate) fn new(
         bounds: Cube,
-        bounds_intersection: CubeRayIntersection,
-        node: u32,
-        target_octant: u32,
+        node: PoolKey,
+        entry_plane_distances: V3c<f32>,
+        exit_plane_distances: V3c<f32>,
     ) -> Self {
-        let child_center = Into::<V3c<f32>>::into(bounds.min_position)
-            + V3c::unit(bounds.size as f32 / 4.)
-            + Into::<V3c<f32>>::into(offset_region(target_octant)) * (bounds.size as f32 / 2.);
-        Self {
-            bounds_intersection,
+        let mut item = Self {
             bounds,
             node,
-            target_octant,
-            child_center,
+            next_child: 0,
+            entry_plane_distances,
+            exit_plane_distances,
+        };
+        item.next_child = item.first_mirrored_child();
+        item
+    }
+
+    /// The distance along the ray where it enters the bounds of the item
+    fn entry_distance(&self) -> f32 {
+        self.entry_plane_distances
+            .x
+            .max(self.entry_plane_distances.y)
+            .max(self.entry_plane_distances.z)
+    }
+
+    /// The distance along the ray where it exits the bounds of the item
+    fn exit_distance(&self) -> f32 {
+        self.exit_plane_distances
+            .x
+            .min(self.exit_plane_distances.y)
+            .min(self.exit_plane_distances.z)
+    }
+
+    /// The distances along the ray to the planes halving the bounds of the item
+    fn mid_plane_distances(&self) -> V3c<f32> {
+        (self.entry_plane_distances + self.exit_plane_distances) / 2.
+    }
+
+    /// The mirrored octant the ray enters first: it is in the upper half on every axis
+    /// where the ray is already past the halving plane at the entry point of the item
+    fn first_mirrored_child(&self) -> u8 {
+        let entry_distance = self.entry_distance();
+        let mid_plane_distances = self.mid_plane_distances();
+        (mid_plane_distances.x < entry_distance) as u8
+            + (mid_plane_distances.z < entry_distance) as u8 * 2
+            + (mid_plane_distances.y < entry_distance) as u8 * 4
+    }
+
+    /// Advances `next_child` to the sibling the ray steps into after leaving the child in the given mirrored octant.
+    /// The plane distances of the children are either the same as the parents or half-way between them,
+    /// so no additional divisions are needed.
+    /// * `mirrored_octant` - The octant of the child, mirrored by the sign bits of the ray direction
+    ///
+    /// output: the entry and exit plane distances of the child
+    pub(crate) fn step_over_child(&mut self, mirrored_octant: u8) -> (V3c<f32>, V3c<f32>) {
+        let mid_plane_distances = self.mid_plane_distances();
+        let pick = |bit: u8, lower: f32, upper: f32| -> f32 {
+            if 0 == (mirrored_octant & bit) {
+                lower
+            } else {
+                upper
+            }
+        };
+        let child_entry_plane_distances = V3c::new(
+            pick(1, self.entry_plane_distances.x, mid_plane_distances.x),
+            pick(4, self.entry_plane_distances.y, mid_plane_distances.y),
+            pick(2, self.entry_plane_distances.z, mid_plane_distances.z),
+        );
+        let child_exit_plane_distances = V3c::new(
+            pick(1, mid_plane_distances.x, self.exit_plane_distances.x),
+            pick(4, mid_plane_distances.y, self.exit_plane_distances.y),
+            pick(2, mid_plane_distances.z, self.exit_plane_distances.z),
+        );
+
+        // The ray leaves the child through the closest exit plane; When it leaves through an edge or a corner,
+        // the step which still has a sibling to go to is taken, so grazing hits are not missed
+        let exit_distance = child_exit_plane_distances
+            .x
+            .min(child_exit_plane_distances.y)
+            .min(child_exit_plane_distances.z)
+            + FLOAT_ERROR_TOLERANCE;
+        let next_siblings = &RAY_NEXT_MIRRORED_OCTANT[mirrored_octant as usize];
+        let mut next_child = 8;
+        if child_exit_plane_distances.x <= exit_distance {
+            next_child = next_child.min(next_siblings[0]);
+        }
+        if child_exit_plane_distances.y <= exit_distance {
+            next_child = next_child.min(next_siblings[1]);
+        }
+        if child_exit_plane_distances.z <= exit_distance {
+            next_child = next_child.min(next_siblings[2]);
         }
+        self.next_child = next_child;
+        (child_entry_plane_distances, child_exit_plane_distances)
     }
+}
 
-    pub(crate) fn add_point(&mut self, p: V3c<f32>) {
-        self.child_center = self.child_center + p;
-        self.target_octant = hash_region(
-            &(self.child_center - self.bounds.min_position.into()),
-            self.bounds.size as f32,
+impl NodeStack {
+    pub(crate) fn new() -> Self {
+        Self {
+            items: [NodeStackItem::default(); MAX_NODE_STACK_DEPTH],
+            len: 0,
+        }
+    }
+
+    /// Empties the stack, then pushes the given item as its only one
+    pub(crate) fn reset_to(&mut self, root_item: NodeStackItem) {
+        self.len = 0;
+        self.push(root_item);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn push(&mut self, item: NodeStackItem) {
+        debug_assert!(
+            self.len < MAX_NODE_STACK_DEPTH,
+            "Node stack is deeper, than any tree"
         );
+        self.items[self.len] = item;
+        self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.len = self.len.saturating_sub(1);
     }
 
-    pub(crate) fn target_bounds(&self) -> Cube {
-        self.bounds.child_bounds_for(self.target_octant)
+    pub(crate) fn last_mut(&mut self) -> Option<&mut NodeStackItem> {
+        self.len.checked_sub(1).map(|top| &mut self.items[top])
     }
+}
 
-    pub(crate) fn contains_target_center(&self) -> bool {
-        self.bounds.contains_point(&self.child_center)
+impl RayFootprint {
+    /// The width of the area the ray stands for at the given distance along it
+    pub fn width_at(&self, distance: f32) -> f32 {
+        self.width + self.spread * distance.max(0.)
+    }
+}
+
+impl RayContext {
+    pub fn new() -> Self {
+        Self {
+            node_stack: NodeStack::new(),
+            counters: TraversalCounters::default(),
+        }
+    }
+
+    /// The work done by the rays cast with the context since its creation, or the last `reset_counters`
+    pub fn counters(&self) -> TraversalCounters {
+        self.counters
+    }
+
+    /// Sets every counter of the context to zero, e.g. before casting the ray to be measured
+    pub fn reset_counters(&mut self) {
+        self.counters = TraversalCounters::default();
+    }
+}
+
+impl TraversalCounters {
+    /// The number of nodes and cells visited together
+    pub fn steps(&self) -> u64 {
+        self.node_visits + self.cell_steps
+    }
+}
+
+/// Counts the cells the ray steps through in the leaves for the wrapped kernel, see `TraversalCounters`
+struct CountingKernel<'a, K> {
+    kernel: &'a mut K,
+    cell_steps: &'a mut u64,
+}
+
+impl<T, K: TraversalKernel<T>> TraversalKernel<T> for CountingKernel<'_, K> {
+    fn accepts(&mut self, data: &T) -> bool {
+        *self.cell_steps += 1;
+        self.kernel.accepts(data)
+    }
+
+    fn hit_cell(&mut self, ray: &Ray, data: &T, cell: &Cube) -> Option<CellHit> {
+        self.kernel.hit_cell(ray, data, cell)
+    }
+}
+
+impl Default for RayContext {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -69,6 +290,7 @@ impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: us
     /// * `ray_current_distance` - The distance the ray iteration is currently at
     /// * `current_bounds` - The cell which boundaries the current ray iteration intersects
     /// * `ray_scale_factors` - Pre-computed dda values for the ray
+    ///
     /// inputs: current distances of the 3 components of the ray, unit size, Ray, scale factors of each xyz components
     /// output: the step to the next sibling
     pub(in crate::octree) fn dda_step_to_next_sibling(
@@ -111,47 +333,64 @@ impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: us
         )
     }
 
-    /// Iterates on the given ray and matrix to find a potential intersection in 3D space
-    fn traverse_matrix(
+    /// Iterates on the given ray and matrix to find a potential intersection in 3D space.
+    /// Cells are hit as the given kernel decides, should the hit be inside the cell
+    /// instead of where the ray enters it, the distance and normal of the hit is also provided.
+    /// * `resolution` - The number of cells along each axis of the bounds
+    /// * `cell_at` - Provides the data of the cell at the given index
+    /// * `kernel` - Decides which cells are hit, and where
+    #[allow(clippy::too_many_arguments)]
+    fn traverse_matrix<'a>(
         ray: &Ray,
         ray_current_distance: &mut f32,
         ray_scale_factors: &V3c<f32>,
-        matrix: &[[[T; DIM]; DIM]; DIM],
+        resolution: usize,
+        cell_at: impl Fn(V3c<usize>) -> &'a T,
         bounds: &Cube,
         intersection: &CubeRayIntersection,
-    ) -> Option<V3c<usize>> {
+        kernel: &mut impl TraversalKernel<T>,
+    ) -> Option<MatrixHit>
+    where
+        T: 'a,
+    {
         let mut current_index = {
             let pos = ray.point_at(
                 intersection
                     .impact_distance
                     .unwrap_or(*ray_current_distance),
             ) - V3c::<f32>::from(bounds.min_position);
+            let matrix_unit = (bounds.size / resolution as u32) as f32;
             V3c::new(
-                (pos.x as i32).clamp(0, (DIM - 1) as i32),
-                (pos.y as i32).clamp(0, (DIM - 1) as i32),
-                (pos.z as i32).clamp(0, (DIM - 1) as i32),
+                ((pos.x / matrix_unit) as i32).clamp(0, (resolution - 1) as i32),
+                ((pos.y / matrix_unit) as i32).clamp(0, (resolution - 1) as i32),
+                ((pos.z / matrix_unit) as i32).clamp(0, (resolution - 1) as i32),
             )
         };
-        let matrix_unit = bounds.size / DIM as u32;
+        let matrix_unit = bounds.size / resolution as u32;
         let mut current_bounds = Cube {
             min_position: bounds.min_position + V3c::<u32>::from(current_index) * matrix_unit,
             size: matrix_unit,
         };
         loop {
             if current_index.x < 0
-                || current_index.x >= DIM as i32
+                || current_index.x >= resolution as i32
                 || current_index.y < 0
-                || current_index.y >= DIM as i32
+                || current_index.y >= resolution as i32
                 || current_index.z < 0
-                || current_index.z >= DIM as i32
+                || current_index.z >= resolution as i32
             {
                 return None;
             }
 
-            if !matrix[current_index.x as usize][current_index.y as usize][current_index.z as usize]
-                .is_empty()
-            {
-                return Some(V3c::<usize>::from(current_index));
+            let cell = cell_at(V3c::<usize>::from(current_index));
+            if kernel.accepts(cell) {
+                match kernel.hit_cell(ray, cell, &current_bounds) {
+                    Some(CellHit::Entry) => return Some((V3c::<usize>::from(current_index), None)),
+                    Some(CellHit::At { distance, normal }) => {
+                        return Some((V3c::<usize>::from(current_index), Some((distance, normal))))
+                    }
+                    None => {}
+                }
             }
 
             let step = Self::dda_step_to_next_sibling(
@@ -181,10 +420,10 @@ impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: us
         }
     }
 
-    /// provides the collision point of the ray with the contained voxel field
-    /// return reference of the data, collision point and normal at impact, should there be any
-    pub fn get_by_ray(&self, ray: &Ray) -> Option<(&T, V3c<f32>, V3c<f32>)> {
-        let ray = Ray {
+    /// Replaces the zero components of the given rays direction with a minimal value,
+    /// so the DDA calculations used in the traversal do not end up in divisions by zero
+    pub(in crate::octree) fn sanitized_ray(ray: &Ray) -> Ray {
+        Ray {
             origin: ray.origin,
             direction: V3c::new(
                 if 0. != ray.direction.x {
@@ -203,175 +442,895 @@ impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: us
                     FLOAT_ERROR_TOLERANCE
                 },
             ),
+        }
+    }
+
+    /// Iterates the ray through the matrix of the given leaf node to find the voxel it hits first
+    /// * `ray` - The ray to find the hit for, direction is expected to be sanitized
+    /// * `ray_current_distance` - The distance the ray iteration is currently at
+    /// * `ray_scale_factors` - Pre-computed dda values for the ray
+    /// * `node_key` - The key of the leaf node to traverse
+    /// * `bounds` - The bounds of the leaf node
+    /// * `bounds_intersection` - The intersection of the ray with the bounds of the leaf node
+    /// * `kernel` - Decides which cells of the leaf are hit, and where
+    #[allow(clippy::too_many_arguments)]
+    pub(in crate::octree) fn probe_leaf(
+        &self,
+        ray: &Ray,
+        ray_current_distance: &mut f32,
+        ray_scale_factors: &V3c<f32>,
+        node_key: PoolKey,
+        bounds: &Cube,
+        bounds_intersection: &CubeRayIntersection,
+        kernel: &mut impl TraversalKernel<T>,
+    ) -> Option<RayHit<'_, T>> {
+        let (leaf_data, leaf_matrix_hit) = match self.nodes.get(node_key as usize) {
+            NodeContent::Leaf(brick) => {
+                let leaf_data = &self.bricks.get(*brick as usize).0;
+                let leaf_matrix_hit = Self::traverse_matrix(
+                    ray,
+                    ray_current_distance,
+                    ray_scale_factors,
+                    DIM,
+                    |index| &leaf_data[index.x][index.y][index.z],
+                    bounds,
+                    bounds_intersection,
+                    kernel,
+                )?;
+                (
+                    &leaf_data[leaf_matrix_hit.0.x][leaf_matrix_hit.0.y][leaf_matrix_hit.0.z],
+                    leaf_matrix_hit,
+                )
+            }
+            // Every voxel of a uniform leaf is the same, so the leaf is resolved as a single cell
+            NodeContent::UniformLeaf(data) if VoxelShape::Cube == data.shape() => {
+                if !kernel.accepts(data) {
+                    return None;
+                }
+                let (distance, normal) = match kernel.hit_cell(ray, data, bounds)? {
+                    CellHit::Entry => (
+                        bounds_intersection
+                            .impact_distance
+                            .unwrap_or(*ray_current_distance),
+                        bounds_intersection.impact_normal,
+                    ),
+                    CellHit::At { distance, normal } => (distance, normal),
+                };
+                return Some(RayHit {
+                    data,
+                    point: ray.point_at(distance),
+                    normal,
+                    distance,
+                    node: node_key,
+                    bounds: *bounds,
+                    cell: *bounds,
+                });
+            }
+            // Shaped voxels are refined one by one
+            NodeContent::UniformLeaf(data) => {
+                let leaf_matrix_hit = Self::traverse_matrix(
+                    ray,
+                    ray_current_distance,
+                    ray_scale_factors,
+                    bounds.size as usize,
+                    |_| data,
+                    bounds,
+                    bounds_intersection,
+                    kernel,
+                )?;
+                (data, leaf_matrix_hit)
+            }
+            _ => panic!("probe_leaf was called for a Node which is not a leaf!"),
+        };
+        // Bricks are made of DIM cells along each axis, shaped uniform leaves of single voxels
+        let matrix_unit = match self.nodes.get(node_key as usize) {
+            NodeContent::Leaf(_) => (bounds.size / DIM as u32).max(1),
+            _ => 1,
+        };
+        let cell = Cube {
+            min_position: bounds.min_position
+                + V3c::<u32>::from(leaf_matrix_hit.0 * matrix_unit as usize),
+            size: matrix_unit,
+        };
+        let (distance, normal) = match leaf_matrix_hit {
+            (_, Some(shape_hit)) => shape_hit,
+            (_, None) => {
+                let result_raycast = cell.intersect_ray(ray).unwrap_or(*bounds_intersection);
+                (
+                    result_raycast
+                        .impact_distance
+                        .unwrap_or(*ray_current_distance),
+                    result_raycast.impact_normal,
+                )
+            }
         };
+        Some(RayHit {
+            data: leaf_data,
+            point: ray.point_at(distance),
+            normal,
+            distance,
+            node: node_key,
+            bounds: *bounds,
+            cell,
+        })
+    }
 
-        use crate::object_pool::key_might_be_valid;
-        let root_bounds = Cube::root_bounds(self.octree_size);
-        let mut current_d = 0.0; // No need to initialize, but it will shut the compiler
-        let mut node_stack = Vec::new();
-        let ray_scale_factors = Self::get_dda_scale_factors(&ray);
-        if let Some(root_hit) = root_bounds.intersect_ray(&ray) {
-            current_d = root_hit.impact_distance.unwrap_or(0.);
-            if self
-                .nodes
-                .get(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
-                .is_leaf()
-            {
-                if let Some(root_matrix_hit) = Self::traverse_matrix(
-                    &ray,
-                    &mut current_d,
-                    &ray_scale_factors,
-                    self.nodes
-                        .get(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
-                        .leaf_data(),
-                    &root_bounds,
-                    &root_hit,
-                ) {
-                    let matrix_unit = root_bounds.size / DIM as u32;
-                    let result_raycast = Cube {
-                        min_position: root_bounds.min_position
-                            + V3c::<u32>::from(root_matrix_hit * matrix_unit as usize),
-                        size: matrix_unit,
+    /// provides the collision point of the ray with the contained voxel field
+    /// return reference of the data, collision point and normal at impact, should there be any
+    pub fn get_by_ray(&self, ray: &Ray) -> Option<(&T, V3c<f32>, V3c<f32>)> {
+        self.get_by_ray_detailed(&Self::sanitized_ray(ray))
+            .map(|hit| (hit.data, hit.point, hit.normal))
+    }
+
+    /// Same as `get_by_ray`, reusing the memory in the given context instead of preparing it for the ray,
+    /// e.g. when casting many rays on the same thread
+    pub fn get_by_ray_with(
+        &self,
+        context: &mut RayContext,
+        ray: &Ray,
+    ) -> Option<(&T, V3c<f32>, V3c<f32>)> {
+        match self.get_by_ray_in_context(context, &Self::sanitized_ray(ray), &RayOptions::default())
+        {
+            HitOrBudgetExceeded::Hit(hit) => Some((hit.data, hit.point, hit.normal)),
+            _ => None,
+        }
+    }
+
+    /// Same as `get_by_ray`, with the voxels inside the leaf nodes hit as the given kernel decides,
+    /// e.g. to trace signed distance fields stored in the voxels, or to accumulate densities along the ray
+    pub fn get_by_ray_with_kernel(
+        &self,
+        ray: &Ray,
+        kernel: &mut impl TraversalKernel<T>,
+    ) -> Option<(&T, V3c<f32>, V3c<f32>)> {
+        match self.traverse_ray_with_kernel(
+            &mut RayContext::new(),
+            &Self::sanitized_ray(ray),
+            &RayOptions::default(),
+            f32::INFINITY,
+            kernel,
+        ) {
+            HitOrBudgetExceeded::Hit(hit) => Some((hit.data, hit.point, hit.normal)),
+            _ => None,
+        }
+    }
+
+    /// provides the collision point of the ray with the contained voxel field, within the given limits
+    /// return reference of the data, collision point and normal at impact, should there be any;
+    /// or `BudgetExceeded` should the ray reach its limits first
+    pub fn get_by_ray_with_options(
+        &self,
+        ray: &Ray,
+        options: &RayOptions,
+    ) -> HitOrBudgetExceeded<(&T, V3c<f32>, V3c<f32>)> {
+        match self.get_by_ray_limited(&Self::sanitized_ray(ray), options) {
+            HitOrBudgetExceeded::Hit(hit) => {
+                HitOrBudgetExceeded::Hit((hit.data, hit.point, hit.normal))
+            }
+            HitOrBudgetExceeded::Miss => HitOrBudgetExceeded::Miss,
+            HitOrBudgetExceeded::BudgetExceeded => HitOrBudgetExceeded::BudgetExceeded,
+            HitOrBudgetExceeded::OriginInside => HitOrBudgetExceeded::OriginInside,
+        }
+    }
+
+    /// provides a copy of the data hit by the given ray along with the position of the hit voxel,
+    /// so the result can be kept while the tree is edited; the position can be used to access the voxel again
+    pub fn get_by_ray_owned(&self, ray: &Ray) -> Option<OwnedRayHit<T>> {
+        let ray = Self::sanitized_ray(ray);
+        self.get_by_ray_detailed(&ray).map(|hit| {
+            let voxel = Self::voxel_position_of(&ray, &hit);
+            OwnedRayHit {
+                data: hit.data.clone(),
+                point: hit.point,
+                normal: hit.normal,
+                distance: hit.distance,
+                voxel,
+                auxiliary: self.auxiliary_at(&voxel).copied(),
+            }
+        })
+    }
+
+    /// Same as `get_by_ray_owned`, with the ray and the hit in world space, see `Octree::with_voxel_size`:
+    /// the hit point and distance are scaled by the size of the voxels, the normal and voxel position are unchanged
+    pub fn get_by_world_ray(&self, ray: &Ray) -> Option<OwnedRayHit<T>> {
+        let ray = Ray {
+            origin: self.world_to_voxel(ray.origin),
+            direction: ray.direction,
+        };
+        self.get_by_ray_owned(&ray).map(|hit| OwnedRayHit {
+            point: self.voxel_to_world(hit.point),
+            distance: hit.distance * self.voxel_size,
+            ..hit
+        })
+    }
+
+    /// Same as `get_by_ray_owned`, with the edits waiting in the given queue taken into account as if they were applied,
+    /// so placements can be previewed before the queue is applied to the tree.
+    /// Voxels set or cleared by queued edits are treated as full cubes.
+    pub fn get_by_ray_with_pending(
+        &self,
+        ray: &Ray,
+        queue: &OctreeWriteQueue<T>,
+    ) -> Option<OwnedRayHit<T>> {
+        let ray = Self::sanitized_ray(ray);
+        let tree_hit = self.get_by_ray_owned(&ray);
+
+        // Nothing is filled along the ray before the tree hit or the first queued insert it enters,
+        // so voxels only need to be visited one by one from there
+        let insert_distance = queue
+            .edits
+            .iter()
+            .filter(|edit| matches!(edit, OctreeEdit::Insert { data, .. } if !data.is_empty()))
+            .filter_map(|edit| edit.region::<DIM>(self.octree_size)?.intersect_ray(&ray))
+            .map(|intersection| intersection.impact_distance.unwrap_or(0.))
+            .min_by(|a, b| a.total_cmp(b));
+        let start = match (&tree_hit, insert_distance) {
+            (Some(hit), Some(distance)) if distance < hit.distance => distance,
+            (Some(hit), _) => {
+                if queue.pending_at(self, &hit.voxel).is_none() {
+                    return tree_hit;
+                }
+                hit.distance
+            }
+            (None, Some(distance)) => distance,
+            (None, None) => return None,
+        };
+
+        let bounds = Cube::root_bounds(self.octree_size);
+        let mut voxel = match &tree_hit {
+            Some(hit) if hit.distance == start => hit.voxel,
+            _ => {
+                let inside = ray.point_at(start + FLOAT_ERROR_TOLERANCE);
+                let max_position = bounds.max_position() - V3c::unit(1);
+                V3c::new(
+                    (inside.x.max(0.) as u32).min(max_position.x),
+                    (inside.y.max(0.) as u32).min(max_position.y),
+                    (inside.z.max(0.) as u32).min(max_position.z),
+                )
+            }
+        };
+        let mut distance = start;
+        let mut normal = match &tree_hit {
+            Some(hit) if hit.distance == start => hit.normal,
+            _ => Cube::new(voxel, 1)
+                .intersect_ray(&ray)
+                .map_or(ray.direction * -1., |intersection| {
+                    intersection.impact_normal
+                }),
+        };
+
+        // Step through the voxels along the ray until one is found with data in it
+        loop {
+            let data = queue
+                .pending_at(self, &voxel)
+                .unwrap_or_else(|| self.get(&voxel));
+            if let Some(data) = data {
+                return Some(OwnedRayHit {
+                    data: data.clone(),
+                    point: ray.point_at(distance),
+                    normal,
+                    distance,
+                    voxel,
+                    auxiliary: self.auxiliary_at(&voxel).copied(),
+                });
+            }
+            let (next, next_normal, exit_distance) =
+                self.step_out_of_cell(&ray, &Cube::new(voxel, 1))?;
+            (voxel, normal, distance) = (next?, next_normal, exit_distance);
+        }
+    }
+
+    /// Provides the intervals along the given ray where it goes through voxels, in front-to-back order:
+    /// the distances along the ray where it enters and leaves them, with their data. Neighbouring voxels
+    /// with the same data are merged into one interval, so the intervals can be used e.g. to integrate
+    /// absorption and emission through volumes, or to composite multiple trees along the same ray.
+    /// Should the ray start inside a voxel, the first interval starts at 0
+    pub fn ray_intervals(&self, ray: &Ray) -> Vec<(f32, f32, &T)> {
+        let ray = Self::sanitized_ray(ray);
+        let mut intervals = Vec::new();
+        let Some(entry) = Cube::root_bounds(self.octree_size).intersect_ray(&ray) else {
+            return intervals;
+        };
+        let mut distance = entry.impact_distance.unwrap_or(0.).max(0.);
+        let entry_point = ray.point_at(distance);
+        let max_position = (self.octree_size - 1) as f32;
+        let mut voxel = V3c::new(
+            entry_point.x.clamp(0., max_position) as u32,
+            entry_point.y.clamp(0., max_position) as u32,
+            entry_point.z.clamp(0., max_position) as u32,
+        );
+
+        // Step through the empty and uniform parts of the tree along the ray, splitting the intervals where the data changes
+        let mut open_interval: Option<(f32, &T)> = None;
+        loop {
+            let (cell, data, _) = self.uniform_cell_at(&voxel);
+            if open_interval.map(|(_, open_data)| open_data) != data {
+                if let Some((enter, open_data)) = open_interval {
+                    intervals.push((enter, distance, open_data));
+                }
+                open_interval = data.map(|data| (distance, data));
+            }
+            let Some((next, _, exit)) = self.step_out_of_cell(&ray, &cell) else {
+                break;
+            };
+            distance = exit;
+            match next {
+                Some(next) => voxel = next,
+                None => break,
+            }
+        }
+        if let Some((enter, open_data)) = open_interval {
+            intervals.push((enter, distance, open_data));
+        }
+        intervals
+    }
+
+    /// Provides the part of the tree around the given position with the same content along the ray:
+    /// a whole empty node or uniform leaf, or a single cell of the brick of a leaf; along with its data,
+    /// should it not be empty, and the key and bounds of the leaf it is inside of, should it be inside one
+    fn uniform_cell_at(&self, position: &V3c<u32>) -> (Cube, Option<&T>, Option<(PoolKey, Cube)>) {
+        let mut bounds = Cube::root_bounds(self.octree_size);
+        let mut node_key = Octree::<T, DIM>::ROOT_NODE_KEY;
+        loop {
+            match self.nodes.get(node_key as usize) {
+                NodeContent::Nothing => return (bounds, None, None),
+                NodeContent::UniformLeaf(data) => {
+                    return (
+                        bounds,
+                        Some(data).filter(|data| !data.is_empty()),
+                        Some((node_key, bounds)),
+                    )
+                }
+                NodeContent::Leaf(brick) => {
+                    let mat_index = Self::mat_index(&bounds, position);
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    let cell = Cube::new(
+                        bounds.min_position + V3c::<u32>::from(mat_index) * cell_size,
+                        cell_size,
+                    );
+                    let data =
+                        &self.bricks.get(*brick as usize).0[mat_index.x][mat_index.y][mat_index.z];
+                    return (
+                        cell,
+                        Some(data).filter(|data| !data.is_empty()),
+                        Some((node_key, bounds)),
+                    );
+                }
+                NodeContent::Internal(_) => {
+                    let octant = child_octant_for(&bounds, position);
+                    let child_key = self.node_children[node_key as usize][octant];
+                    bounds = bounds.child_bounds_for(octant);
+                    if !key_might_be_valid(child_key) {
+                        return (bounds, None, None);
                     }
-                    .intersect_ray(&ray)
-                    .unwrap_or(root_hit);
-                    return Some((
-                        &self
-                            .nodes
-                            .get(Octree::<T, DIM>::ROOT_NODE_KEY as usize)
-                            .leaf_data()[root_matrix_hit.x][root_matrix_hit.y][root_matrix_hit.z],
-                        ray.point_at(result_raycast.impact_distance.unwrap_or(current_d)),
-                        result_raycast.impact_normal,
-                    ));
+                    node_key = child_key;
+                }
+            }
+        }
+    }
+
+    /// Provides the voxel the given ray steps into after leaving the given cell, None should it leave the tree;
+    /// along with the normal of the face it leaves the cell through, pointing back along the step,
+    /// and the distance along the ray where it crosses that face
+    fn step_out_of_cell(
+        &self,
+        ray: &Ray,
+        cell: &Cube,
+    ) -> Option<(Option<V3c<u32>>, V3c<f32>, f32)> {
+        let step = |direction: f32| if 0. < direction { 1 } else { -1 };
+        let steps = [
+            step(ray.direction.x),
+            step(ray.direction.y),
+            step(ray.direction.z),
+        ];
+        let exit = cell.intersect_ray(ray)?;
+        let exit_point = ray.point_at(exit.exit_distance);
+        let origins = [
+            cell.min_position.x,
+            cell.min_position.y,
+            cell.min_position.z,
+        ];
+        let points = [exit_point.x, exit_point.y, exit_point.z];
+        let face = |axis: usize| {
+            origins[axis] as f32
+                + if 0 < steps[axis] {
+                    cell.size as f32
                 } else {
-                    // If the root if a leaf already and there's no hit in it, then there is no hit at all.
-                    return None;
+                    0.
+                }
+        };
+        let axis = (0..3)
+            .min_by(|a, b| {
+                let gap = |axis: usize| (points[axis] - face(axis)).abs();
+                gap(*a).total_cmp(&gap(*b))
+            })
+            .unwrap();
+        let back = -steps[axis] as f32;
+        let normal = match axis {
+            0 => V3c::new(back, 0., 0.),
+            1 => V3c::new(0., back, 0.),
+            _ => V3c::new(0., 0., back),
+        };
+
+        // The next voxel is across the exit face, at the exit point along the other axes
+        let mut next = [0; 3];
+        for (other_axis, coordinate) in next.iter_mut().enumerate() {
+            *coordinate = if other_axis == axis {
+                if 0 < steps[axis] {
+                    origins[axis] + cell.size
+                } else {
+                    match origins[axis].checked_sub(1) {
+                        Some(coordinate) => coordinate,
+                        None => return Some((None, normal, exit.exit_distance)),
+                    }
+                }
+            } else {
+                (points[other_axis].max(0.) as u32)
+                    .clamp(origins[other_axis], origins[other_axis] + cell.size - 1)
+            };
+        }
+        let next = (next[axis] < self.octree_size).then(|| V3c::new(next[0], next[1], next[2]));
+        Some((next, normal, exit.exit_distance))
+    }
+
+    /// Provides the hit where the given ray leaves the solid voxels around the voxel containing its origin,
+    /// with the normal of the face it leaves through flipped to face the ray, pointing into the solid voxels
+    fn get_back_face_hit(&self, ray: &Ray, origin_voxel: &V3c<u32>) -> Option<RayHit<'_, T>> {
+        let (mut cell, data, mut leaf) = self.uniform_cell_at(origin_voxel);
+        let mut data = data?;
+        loop {
+            let (next, normal, distance) = self.step_out_of_cell(ray, &cell)?;
+            match next.map(|next| self.uniform_cell_at(&next)) {
+                Some((next_cell, Some(next_data), next_leaf)) => {
+                    (cell, data, leaf) = (next_cell, next_data, next_leaf)
+                }
+                _ => {
+                    let (node, bounds) = leaf?;
+                    return Some(RayHit {
+                        data,
+                        point: ray.point_at(distance),
+                        normal,
+                        distance,
+                        node,
+                        bounds,
+                        cell,
+                    });
                 }
             }
-            let target_octant = hash_region(
-                &(ray.point_at(current_d) - root_bounds.min_position.into()),
-                root_bounds.size as f32,
-            );
-            node_stack.push(NodeStackItem::new(
-                root_bounds,
-                root_hit,
-                Octree::<T, DIM>::ROOT_NODE_KEY,
-                target_octant,
-            ));
         }
+    }
 
-        while !node_stack.is_empty() {
-            let current_bounds = node_stack.last().unwrap().bounds;
-            let current_bounds_ray_intersection = node_stack.last().unwrap().bounds_intersection;
-            if !node_stack.last().unwrap().contains_target_center() // If current target is OOB
-                // No need to go into the Node if it's empty
-                || match self.nodes.get(node_stack.last().unwrap().node as usize) {
-                    NodeContent::Nothing => true,
-                    NodeContent::Internal(count) => 0 == *count,
-                    _ => false,
+    /// Same as `get_by_ray_owned`, with the given overlay composited over the tree:
+    /// where the overlay has voxels in front of the ones in the tree or at the same distance, they are hit instead
+    pub fn get_by_ray_with_overlay(
+        &self,
+        ray: &Ray,
+        overlay: &OverlayOctree<T, DIM>,
+    ) -> Option<OwnedRayHit<T>> {
+        let offset = V3c::<f32>::from(overlay.offset);
+        let overlay_hit = overlay
+            .tree
+            .get_by_ray_owned(&Ray {
+                origin: ray.origin - offset,
+                direction: ray.direction,
+            })
+            .map(|hit| OwnedRayHit {
+                point: hit.point + offset,
+                voxel: hit.voxel + overlay.offset,
+                ..hit
+            });
+        match (overlay_hit, self.get_by_ray_owned(ray)) {
+            (Some(overlay_hit), Some(hit)) if hit.distance < overlay_hit.distance => Some(hit),
+            (Some(overlay_hit), _) => Some(overlay_hit),
+            (None, hit) => hit,
+        }
+    }
+
+    /// Provides the position of the voxel the given hit is inside of
+    pub(in crate::octree) fn voxel_position_of(ray: &Ray, hit: &RayHit<'_, T>) -> V3c<u32> {
+        // The hit point is on the surface of the voxel, so it is moved a bit inside, against the normal of the surface:
+        // moving along the ray instead could leave the voxel through a nearby edge, should the ray graze it
+        let inside = if 0. < hit.normal.length() {
+            hit.point - hit.normal * 0.01
+        } else {
+            ray.point_at(hit.distance + 0.01)
+        };
+        // The point is also kept inside the hit cell, as rounding errors might place it just outside of it
+        let max_position = hit.cell.min_position + V3c::unit(hit.cell.size - 1);
+        V3c::new(
+            (inside.x.max(0.) as u32).clamp(hit.cell.min_position.x, max_position.x),
+            (inside.y.max(0.) as u32).clamp(hit.cell.min_position.y, max_position.y),
+            (inside.z.max(0.) as u32).clamp(hit.cell.min_position.z, max_position.z),
+        )
+    }
+
+    /// provides the details of the first voxel hit by the given ray, including the leaf node it is inside
+    /// * `ray` - The ray to cast, direction is expected to be sanitized
+    pub(in crate::octree) fn get_by_ray_detailed(&self, ray: &Ray) -> Option<RayHit<'_, T>> {
+        match self.get_by_ray_limited(ray, &RayOptions::default()) {
+            HitOrBudgetExceeded::Hit(hit) => Some(hit),
+            _ => None,
+        }
+    }
+
+    /// Same as `get_by_ray_detailed`, reusing the memory in the given context
+    pub(in crate::octree) fn get_by_ray_detailed_with<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+    ) -> Option<RayHit<'a, T>> {
+        match self.get_by_ray_in_context(context, ray, &RayOptions::default()) {
+            HitOrBudgetExceeded::Hit(hit) => Some(hit),
+            _ => None,
+        }
+    }
+
+    /// provides the details of the first voxel hit by the given ray, should it be found within the given limits
+    /// * `ray` - The ray to cast, direction is expected to be sanitized
+    /// * `options` - The limits of the traversal
+    pub(in crate::octree) fn get_by_ray_limited(
+        &self,
+        ray: &Ray,
+        options: &RayOptions,
+    ) -> HitOrBudgetExceeded<RayHit<'_, T>> {
+        self.get_by_ray_in_context(&mut RayContext::new(), ray, options)
+    }
+
+    /// provides the details of the first voxel hit by the given ray, should it be found within the given limits,
+    /// handling rays starting inside a voxel as set in the options
+    /// * `context` - The memory to use during the traversal
+    /// * `ray` - The ray to cast, direction is expected to be sanitized
+    /// * `options` - The limits of the traversal
+    pub(in crate::octree) fn get_by_ray_in_context<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+        options: &RayOptions,
+    ) -> HitOrBudgetExceeded<RayHit<'a, T>> {
+        // The traversal starts in the voxel containing the origin, so it is hit there without checking for it
+        if InsideVoxelPolicy::HitAtOrigin == options.inside_voxel {
+            return self.traverse_ray(context, ray, options);
+        }
+        let Some(origin_voxel) = self.occupied_voxel_at(&ray.origin) else {
+            return self.traverse_ray(context, ray, options);
+        };
+        match options.inside_voxel {
+            InsideVoxelPolicy::HitAtOrigin => self.traverse_ray(context, ray, options),
+            InsideVoxelPolicy::Reject => HitOrBudgetExceeded::OriginInside,
+            InsideVoxelPolicy::HitBackFace => match self.get_back_face_hit(ray, &origin_voxel) {
+                Some(hit) => HitOrBudgetExceeded::Hit(hit),
+                None => HitOrBudgetExceeded::Miss,
+            },
+            InsideVoxelPolicy::SkipContaining => {
+                // The ray is cast again from where it leaves the voxel, a bit inside the next one
+                let Some(exit) = Cube::new(origin_voxel, 1).intersect_ray(ray) else {
+                    return self.traverse_ray(context, ray, options);
+                };
+                let skipped = exit.exit_distance + FLOAT_ERROR_TOLERANCE;
+                let skipped_ray = Ray {
+                    origin: ray.point_at(skipped),
+                    direction: ray.direction,
+                };
+                match self.traverse_ray(context, &skipped_ray, options) {
+                    HitOrBudgetExceeded::Hit(hit) => HitOrBudgetExceeded::Hit(RayHit {
+                        distance: hit.distance + skipped,
+                        ..hit
+                    }),
+                    result => result,
                 }
-            {
-                // POP
-                let popped_target = node_stack.pop().unwrap();
-                if let Some(parent) = node_stack.last_mut() {
-                    let step_vec = Self::dda_step_to_next_sibling(
-                        &ray,
-                        &mut current_d,
-                        &popped_target.bounds,
-                        &ray_scale_factors,
-                    );
-                    parent.add_point(step_vec);
+            }
+        }
+    }
+
+    /// Provides the hit of the given ray on the bounds of the given node as a whole, for rays wider, than the node.
+    /// The voxel of the node nearest to where the ray enters it stands for its content
+    fn get_footprint_hit(
+        &self,
+        ray: &Ray,
+        item: &NodeStackItem,
+        sign_mask: usize,
+    ) -> Option<RayHit<'_, T>> {
+        let node_content = self.nodes.get(item.node as usize);
+        // Uniform leaves are hit exactly without further traversal anyway
+        if matches!(node_content, NodeContent::UniformLeaf(_)) || !node_content.is_occupied() {
+            return None;
+        }
+        let distance = item.entry_distance().max(0.);
+        let point = ray.point_at(distance);
+
+        // Descend towards the ray: into the first occupied child in front-to-back order
+        let mut node = item.node;
+        let mut bounds = item.bounds;
+        let data = loop {
+            match self.nodes.get(node as usize) {
+                NodeContent::Nothing => return None,
+                NodeContent::UniformLeaf(data) => break data,
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1) as f32;
+                    let mut nearest: Option<(f32, &T)> = None;
+                    for x in 0..DIM {
+                        for y in 0..DIM {
+                            for z in 0..DIM {
+                                let voxel = &self.bricks.get(*brick as usize).0[x][y][z];
+                                if voxel.is_empty() {
+                                    continue;
+                                }
+                                let center = V3c::<f32>::from(bounds.min_position)
+                                    + (V3c::new(x as f32, y as f32, z as f32) + V3c::unit(0.5))
+                                        * cell_size;
+                                let gap = (center - point).length();
+                                if nearest.is_none_or(|(nearest_gap, _)| gap < nearest_gap) {
+                                    nearest = Some((gap, voxel));
+                                }
+                            }
+                        }
+                    }
+                    break nearest?.1;
+                }
+                NodeContent::Internal(_) => {
+                    let children = &self.node_children[node as usize];
+                    let octant = Octant::iter()
+                        .map(|mirrored| mirrored.mirror_by_mask(sign_mask))
+                        .find(|octant| children.is_occupied(*octant))?;
+                    node = children[octant];
+                    bounds = bounds.child_bounds_for(octant);
                 }
-                current_d = current_bounds_ray_intersection.exit_distance;
-                continue; // Re-calculate current_bounds and ray intersection
             }
+        };
+        if data.is_empty() {
+            return None;
+        }
+        Some(RayHit {
+            data,
+            point,
+            normal: item
+                .bounds
+                .intersect_ray(ray)
+                .map_or(V3c::unit(0.), |intersection| intersection.impact_normal),
+            distance,
+            node,
+            bounds,
+            cell: item.bounds,
+        })
+    }
 
-            let current_node = node_stack.last().unwrap().node as usize;
-            debug_assert!(key_might_be_valid(current_node as u32));
-
-            if self.nodes.get(current_node).is_leaf() {
-                if let Some(leaf_matrix_hit) = Self::traverse_matrix(
-                    &ray,
-                    &mut current_d,
-                    &ray_scale_factors,
-                    self.nodes.get(current_node).leaf_data(),
-                    &current_bounds,
-                    &current_bounds_ray_intersection,
-                ) {
-                    let matrix_unit = current_bounds.size / DIM as u32;
-                    let result_raycast = Cube {
-                        min_position: current_bounds.min_position
-                            + V3c::<u32>::from(leaf_matrix_hit * matrix_unit as usize),
-                        size: matrix_unit,
+    /// The position of the voxel containing the given point, should it not be empty
+    fn occupied_voxel_at(&self, point: &V3c<f32>) -> Option<V3c<u32>> {
+        let size = self.octree_size as f32;
+        if !(0. ..size).contains(&point.x)
+            || !(0. ..size).contains(&point.y)
+            || !(0. ..size).contains(&point.z)
+        {
+            return None;
+        }
+        let voxel = V3c::new(point.x as u32, point.y as u32, point.z as u32);
+        self.get(&voxel)
+            .is_some_and(|data| !data.is_empty())
+            .then_some(voxel)
+    }
+
+    /// provides the details of the first voxel hit by the given ray, should it be found within the given limits
+    /// Only the children of internal nodes intersected by the ray are visited, in front-to-back order,
+    /// stepping between them with the precomputed tables based on the direction of the ray
+    /// * `context` - The memory to use during the traversal
+    /// * `ray` - The ray to cast, direction is expected to be sanitized
+    /// * `options` - The limits of the traversal
+    fn traverse_ray<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+        options: &RayOptions,
+    ) -> HitOrBudgetExceeded<RayHit<'a, T>> {
+        self.traverse_ray_with_kernel(context, ray, options, f32::INFINITY, &mut DefaultKernel)
+    }
+
+    /// Same as `traverse_ray`, with only the voxels hit before the given distance along the ray found,
+    /// e.g. to check whether anything is in front of a known hit
+    pub(in crate::octree) fn traverse_ray_until<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+        max_distance: f32,
+    ) -> HitOrBudgetExceeded<RayHit<'a, T>> {
+        self.traverse_ray_with_kernel(
+            context,
+            ray,
+            &RayOptions::default(),
+            max_distance,
+            &mut DefaultKernel,
+        )
+    }
+
+    /// Requests the content and the children of the given node into the cache, see `PrefetchPolicy`
+    #[inline(always)]
+    fn prefetch_node(&self, node_key: PoolKey) {
+        prefetch(self.nodes.get(node_key as usize));
+        prefetch(&self.node_children[node_key as usize]);
+    }
+
+    /// Same as `traverse_ray`, with the cells of the leaf nodes resolved by the given kernel,
+    /// and nodes the ray enters at or beyond the given distance skipped
+    fn traverse_ray_with_kernel<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+        options: &RayOptions,
+        max_distance: f32,
+        kernel: &mut impl TraversalKernel<T>,
+    ) -> HitOrBudgetExceeded<RayHit<'a, T>> {
+        let Some(root_item) = NodeStackItem::for_root(
+            Cube::root_bounds(self.octree_size),
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            ray,
+        )
+        .filter(|root_item| root_item.entry_distance() < max_distance) else {
+            return HitOrBudgetExceeded::Miss;
+        };
+        let max_node_visits = options.max_node_visits.unwrap_or(u32::MAX);
+        let max_depth = options.max_depth.unwrap_or(u32::MAX) as usize;
+        if 0 == max_node_visits {
+            return HitOrBudgetExceeded::BudgetExceeded;
+        }
+        let mut node_visits = 1;
+        context.counters.node_visits += 1;
+        let mut kernel = CountingKernel {
+            kernel,
+            cell_steps: &mut context.counters.cell_steps,
+        };
+
+        // The stack never grows deeper, than the tree, so it fits inline without allocating
+        let node_stack = &mut context.node_stack;
+        node_stack.reset_to(root_item);
+        let ray_scale_factors = Self::get_dda_scale_factors(ray);
+        let sign_mask = ray.direction_sign_mask();
+
+        while let Some(current) = node_stack.last_mut() {
+            if let Some(footprint) = &options.footprint {
+                if current.bounds.size as f32 <= footprint.width_at(current.entry_distance()) {
+                    if let Some(hit) = self.get_footprint_hit(ray, current, sign_mask) {
+                        return HitOrBudgetExceeded::Hit(hit);
                     }
-                    .intersect_ray(&ray)
-                    .unwrap_or(current_bounds_ray_intersection);
-                    return Some((
-                        &self.nodes.get(current_node).leaf_data()[leaf_matrix_hit.x]
-                            [leaf_matrix_hit.y][leaf_matrix_hit.z],
-                        ray.point_at(result_raycast.impact_distance.unwrap_or(current_d)),
-                        result_raycast.impact_normal,
-                    ));
-                } else {
-                    // POP
-                    let popped_target = node_stack.pop().unwrap();
-                    if let Some(parent) = node_stack.last_mut() {
-                        let step_vec = Self::dda_step_to_next_sibling(
-                            &ray,
+                }
+            }
+            match self.nodes.get(current.node as usize) {
+                NodeContent::Leaf(_) | NodeContent::UniformLeaf(_) => {
+                    let leaf_hit = current.bounds.intersect_ray(ray).and_then(|intersection| {
+                        let mut current_d = intersection.impact_distance.unwrap_or(0.);
+                        self.probe_leaf(
+                            ray,
                             &mut current_d,
-                            &popped_target.bounds,
                             &ray_scale_factors,
-                        );
-                        parent.add_point(step_vec);
+                            current.node,
+                            &current.bounds,
+                            &intersection,
+                            &mut kernel,
+                        )
+                    });
+                    if let Some(leaf_hit) = leaf_hit {
+                        // Nodes are visited front-to-back, so no other hit is before the limit either
+                        if max_distance <= leaf_hit.distance {
+                            return HitOrBudgetExceeded::Miss;
+                        }
+                        return HitOrBudgetExceeded::Hit(leaf_hit);
                     }
-                    current_d = current_bounds_ray_intersection.exit_distance;
-                    continue; // Re-calculate current_bounds and ray intersection
+                    node_stack.pop();
                 }
-            }
-            current_d = current_bounds_ray_intersection
-                .impact_distance
-                .unwrap_or(current_d);
-
-            let target_octant = node_stack.last().unwrap().target_octant;
-            let target_child = self.node_children[current_node][target_octant];
-            let target_bounds = current_bounds.child_bounds_for(target_octant);
-            let target_is_empty = !key_might_be_valid(target_child)
-                || match self.nodes.get(target_child as usize) {
-                    NodeContent::Internal(count) => 0 == *count,
-                    NodeContent::Leaf(_) => false,
-                    _ => true,
-                };
-            let target_hit = target_bounds.intersect_ray(&ray);
-            if !target_is_empty && target_hit.is_some() {
-                // PUSH
-                current_d = target_hit.unwrap().impact_distance.unwrap_or(current_d);
-                let child_target_octant = hash_region(
-                    &(ray.point_at(current_d) - target_bounds.min_position.into()),
-                    target_bounds.size as f32,
-                );
-                node_stack.push(NodeStackItem::new(
-                    target_bounds,
-                    target_hit.unwrap(),
-                    target_child,
-                    child_target_octant,
-                ));
-            } else {
-                // ADVANCE
-                // target child is invalid, or it does not intersect with the ray
-                // Advance iteration to the next sibling
-                let current_target_bounds = node_stack.last().unwrap().target_bounds();
-                let step_vec = Self::dda_step_to_next_sibling(
-                    &ray,
-                    &mut current_d,
-                    &current_target_bounds,
-                    &ray_scale_factors,
-                );
-                node_stack.last_mut().unwrap().add_point(step_vec);
-                if let Some(hit) = target_hit {
-                    current_d = hit.exit_distance;
+                // No need to go into the Node if it's empty
+                NodeContent::Nothing | NodeContent::Internal(0) => {
+                    node_stack.pop();
+                }
+                NodeContent::Internal(_) => {
+                    let mut target_item = None;
+                    while target_item.is_none() && current.next_child < 8 {
+                        let mirrored_octant = current.next_child;
+                        let (entry_plane_distances, exit_plane_distances) =
+                            current.step_over_child(mirrored_octant);
+
+                        // Children behind the origin of the ray, or beyond the limit can be skipped
+                        if exit_plane_distances
+                            .x
+                            .min(exit_plane_distances.y)
+                            .min(exit_plane_distances.z)
+                            < 0.
+                            || max_distance
+                                <= entry_plane_distances
+                                    .x
+                                    .max(entry_plane_distances.y)
+                                    .max(entry_plane_distances.z)
+                        {
+                            continue;
+                        }
+                        let target_octant =
+                            Octant::ALL[mirrored_octant as usize].mirror_by_mask(sign_mask);
+                        let children = &self.node_children[current.node as usize];
+                        if children.is_occupied(target_octant) {
+                            let target_child = children[target_octant];
+                            if PrefetchPolicy::LookAhead == options.prefetch {
+                                // The sibling after the target is visited once the ray leaves it,
+                                // which is right away should the target turn out to be missed
+                                if current.next_child < 8 {
+                                    let next_octant = Octant::ALL[current.next_child as usize]
+                                        .mirror_by_mask(sign_mask);
+                                    if children.is_occupied(next_octant) {
+                                        self.prefetch_node(children[next_octant]);
+                                    }
+                                }
+                                self.prefetch_node(target_child);
+                            }
+                            target_item = Some(NodeStackItem::new(
+                                current.bounds.child_bounds_for(target_octant),
+                                target_child,
+                                entry_plane_distances,
+                                exit_plane_distances,
+                            ));
+                        }
+                    }
+                    match target_item {
+                        Some(target_item) => {
+                            // The depth of the target is the current length of the stack
+                            if node_visits >= max_node_visits || node_stack.len() > max_depth {
+                                return HitOrBudgetExceeded::BudgetExceeded;
+                            }
+                            node_visits += 1;
+                            context.counters.node_visits += 1;
+                            node_stack.push(target_item);
+                        }
+                        // every child intersecting the ray is visited
+                        None => {
+                            node_stack.pop();
+                        }
+                    }
                 }
             }
         }
-        None
+        HitOrBudgetExceeded::Miss
+    }
+}
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + Sync + VoxelData, const DIM: usize>
+    Octree<T, DIM>
+{
+    /// Casts many rays at once, given as separate arrays of origins and directions, writing the
+    /// result of each ray into the same index of the output; the rays are processed in parallel
+    /// with the `parallel` feature.
+    /// Directions need not be normalized, rays with a zero direction miss.
+    ///
+    /// # Panics
+    /// If the given slices are not of the same length
+    pub fn raycast_batch(
+        &self,
+        origins: &[V3c<f32>],
+        dirs: &[V3c<f32>],
+        out: &mut [RayHitCompact],
+    ) {
+        assert!(
+            origins.len() == dirs.len() && origins.len() == out.len(),
+            "raycast_batch: {} origins, {} directions and {} outputs given",
+            origins.len(),
+            dirs.len(),
+            out.len()
+        );
+        out.par_iter_mut()
+            .zip(origins.par_iter().zip(dirs.par_iter()))
+            .for_each_init(RayContext::new, |context, (result, (origin, direction))| {
+                // A zero direction can not be normalized, and points nowhere to hit anything
+                if 0. == direction.length() {
+                    *result = RayHitCompact::default();
+                    return;
+                }
+                let ray = Self::sanitized_ray(&Ray {
+                    origin: *origin,
+                    direction: direction.normalized(),
+                });
+                *result = match self.get_by_ray_detailed_with(context, &ray) {
+                    Some(hit) => RayHitCompact {
+                        hit: true,
+                        distance: hit.distance,
+                        normal: hit.normal,
+                        voxel: Self::voxel_position_of(&ray, &hit),
+                    },
+                    None => RayHitCompact::default(),
+                };
+            });
     }
 }
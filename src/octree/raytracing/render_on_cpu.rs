@@ -0,0 +1,893 @@
+use crate::object_pool::PoolKey;
+use crate::octree::parallel::*;
+use crate::octree::{
+    raytracing::{
+        accumulation::AccumulationBuffer,
+        backend::VoxelRenderBackend,
+        color::{srgb_to_linear, ToneMapping},
+        kernel::DefaultKernel,
+        types::{
+            AdaptiveRendering, Camera, CoherenceEntry, FrameCoherenceCache, HitOrBudgetExceeded,
+            ImageTile, RayContext, RayFootprint, RayHit, TileOrder, TraversalCounters,
+            TraversalHeatmap,
+        },
+    },
+    Cube, NodeContent, Octant, Octree, OverlayOctree, V3c, VoxelData,
+};
+use crate::spatial::raytracing::Ray;
+use std::collections::HashSet;
+
+/// The color of the pixels where no voxel is hit
+pub(in crate::octree) const BACKGROUND_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+/// The depth of the nodes the rays of whole tiles are checked against before tracing them pixel by pixel
+const BACKGROUND_CHECK_DEPTH: u32 = 2;
+
+/// The size of the tiles `Octree::render_viewport_with` checks for showing only the background
+const BACKGROUND_CHECK_TILE_SIZE: u32 = 8;
+
+/// The offsets inside the pixels the samples of `Octree::accumulate_viewport` go through,
+/// along the R2 low discrepancy sequence; the first sample goes through the center of the pixel
+fn sample_offset(sample_index: u32) -> (f32, f32) {
+    const PLASTIC_NUMBER_INVERSE: (f32, f32) = (0.754_877_7, 0.569_840_3);
+    (
+        (0.5 + sample_index as f32 * PLASTIC_NUMBER_INVERSE.0).fract(),
+        (0.5 + sample_index as f32 * PLASTIC_NUMBER_INVERSE.1).fract(),
+    )
+}
+
+/// The linear color of a voxel with the given data hit on a face with the given normal, lit by a fixed diffuse light
+fn shaded_linear<T: VoxelData>(data: &T, normal: &V3c<f32>) -> [f32; 3] {
+    let diffuse_light_normal = V3c::new(0., -1., 1.).normalized();
+    //Because both vector should be normalized, the dot product should be 1*1*cos(angle)
+    //That means it is in range -1, +1, which should be accounted for
+    let diffuse_light_strength = 1. - (normal.dot(&diffuse_light_normal) / 2. + 0.5);
+    let albedo = data.albedo_hdr();
+    [
+        albedo[0] * diffuse_light_strength,
+        albedo[1] * diffuse_light_strength,
+        albedo[2] * diffuse_light_strength,
+    ]
+}
+
+/// The color of a voxel with the given data hit on a face with the given normal, lit by a fixed diffuse light
+fn shaded_color<T: VoxelData>(data: &T, normal: &V3c<f32>, tone_mapping: &ToneMapping) -> [u8; 4] {
+    let [r, g, b] = tone_mapping.apply_rgb(shaded_linear(data, normal));
+    [r, g, b, 255]
+}
+
+impl Camera {
+    /// Provides the ray going through the given pixel of the viewport
+    /// * `x`, `y` - The pixel coordinates, where (0,0) is the top-left corner of the image
+    /// * `width`, `height` - The size of the rendered image in pixels
+    pub fn ray_for(&self, x: u32, y: u32, width: u32, height: u32) -> Ray {
+        self.ray_through(x as f32 + 0.5, y as f32 + 0.5, width, height)
+    }
+
+    /// Provides the ray going through the given point of the viewport, given in pixels
+    /// from the top-left corner of the image; e.g. the corners of the pixels
+    fn ray_through(&self, x: f32, y: f32, width: u32, height: u32) -> Ray {
+        let right = self.direction.cross(V3c::new(0., 1., 0.)).normalized();
+        let up = right.cross(self.direction);
+        let pixel_width = self.size.0 / width as f32;
+        let pixel_height = self.size.1 / height as f32;
+        let bottom_left = self.origin + (self.direction * self.fov)
+            - (up * (self.size.1 / 2.))
+            - (right * (self.size.0 / 2.));
+        let glass_point =
+            bottom_left + right * x * pixel_width + up * (height as f32 - y) * pixel_height;
+        Ray {
+            origin: self.origin,
+            direction: (glass_point - self.origin).normalized(),
+        }
+    }
+
+    /// True if none of the rays going through the given rectangle of the viewport hit any of the given bounds.
+    /// The bounds are outside of the pyramid of the rays, should all their corners be behind one of its sides
+    /// or behind the camera; Bounds passing by its edges might not be behind any single side,
+    /// so some rectangles without hits might not be found
+    /// * `rect` - The area of the viewport in pixels: (x, y, width, height), where (0,0) is the top-left corner
+    /// * `resolution` - The size of the whole viewport in pixels: (width, height)
+    pub(in crate::octree) fn misses_all(
+        &self,
+        rect: (u32, u32, u32, u32),
+        resolution: (u32, u32),
+        bounds: &[Cube],
+    ) -> bool {
+        let (width, height) = resolution;
+        let (x, y) = (rect.0 as f32, rect.1 as f32);
+        let (right, bottom) = ((rect.0 + rect.2) as f32, (rect.1 + rect.3) as f32);
+        let corners = [(x, y), (right, y), (right, bottom), (x, bottom)]
+            .map(|(x, y)| self.ray_through(x, y, width, height).direction);
+        let center = self
+            .ray_through((x + right) / 2., (y + bottom) / 2., width, height)
+            .direction;
+        // The normals of the sides of the pyramid pointing inside it, and the direction of the camera,
+        // as every ray goes forward from the camera
+        let normals: [V3c<f32>; 5] = std::array::from_fn(|side| {
+            if 4 == side {
+                return self.direction;
+            }
+            let normal = corners[side].cross(corners[(side + 1) % 4]);
+            if normal.dot(&center) < 0. {
+                normal * -1.
+            } else {
+                normal
+            }
+        });
+        bounds.iter().all(|bounds| {
+            normals.iter().any(|normal| {
+                bounds
+                    .corners()
+                    .iter()
+                    .all(|corner| (V3c::<f32>::from(*corner) - self.origin).dot(normal) < 0.)
+            })
+        })
+    }
+
+    /// Provides the footprint of the rays going through the pixels of the viewport, see `RayOptions::footprint`;
+    /// the rays widen by the size of a pixel on the viewport glass for every `fov` distance
+    /// * `width`, `height` - The size of the rendered image in pixels
+    pub fn footprint_for(&self, width: u32, height: u32) -> RayFootprint {
+        let pixel_size = (self.size.0 / width as f32).max(self.size.1 / height as f32);
+        RayFootprint {
+            width: 0.,
+            spread: pixel_size / self.fov,
+        }
+    }
+}
+
+impl FrameCoherenceCache {
+    /// Creates an empty cache for frames of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            entries: vec![None; (width * height) as usize],
+            max_age: 8,
+        }
+    }
+
+    /// Drops every cached hit, e.g. after the contents of the tree changed
+    pub fn invalidate(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+/// The position of the given tile along the Z-order curve: the bits of the coordinates interleaved
+fn morton_code(column: u32, row: u32) -> u64 {
+    (0..32).fold(0, |code, bit| {
+        code | ((column as u64 >> bit) & 1) << (2 * bit)
+            | ((row as u64 >> bit) & 1) << (2 * bit + 1)
+    })
+}
+
+impl TileOrder {
+    /// The tiles of an image of the given size in this order, as (x, y, width, height) in pixels.
+    /// Tiles on the right and bottom edges are smaller if the image size is not a multiple of the tile size
+    pub fn tiles(&self, width: u32, height: u32, tile_size: u32) -> Vec<(u32, u32, u32, u32)> {
+        let tile_size = tile_size.max(1);
+        let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+        let mut tiles = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect::<Vec<_>>();
+        match self {
+            TileOrder::Scanline => {}
+            TileOrder::Morton => tiles.sort_by_key(|(column, row)| morton_code(*column, *row)),
+            TileOrder::Spiral => {
+                // Tiles are ordered by the ring around the center they are in, then by their angle around the center
+                let center = ((columns as f32 - 1.) / 2., (rows as f32 - 1.) / 2.);
+                let spiral_key = |(column, row): &(u32, u32)| {
+                    let (dx, dy) = (*column as f32 - center.0, *row as f32 - center.1);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                tiles.sort_by(|a, b| {
+                    let (a, b) = (spiral_key(a), spiral_key(b));
+                    a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+                });
+            }
+        }
+        tiles
+            .into_iter()
+            .map(|(column, row)| {
+                let (x, y) = (column * tile_size, row * tile_size);
+                (x, y, tile_size.min(width - x), tile_size.min(height - y))
+            })
+            .collect()
+    }
+}
+
+/// The colors of `TraversalHeatmap::to_rgba`, evenly spread from no steps to the number of steps shown as the most
+const HEATMAP_COLORS: [[f32; 3]; 5] = [
+    [0., 0., 0.],
+    [0., 0., 1.],
+    [1., 0., 0.],
+    [1., 1., 0.],
+    [1., 1., 1.],
+];
+
+/// The color of the heatmap at the given ratio of the steps shown as the most, clamped into [0, 1]
+fn heatmap_color(ratio: f32) -> [u8; 4] {
+    let position = ratio.clamp(0., 1.) * (HEATMAP_COLORS.len() - 1) as f32;
+    let index = (position as usize).min(HEATMAP_COLORS.len() - 2);
+    let blend = position - index as f32;
+    let [r, g, b] = std::array::from_fn(|channel| {
+        let (from, to) = (
+            HEATMAP_COLORS[index][channel],
+            HEATMAP_COLORS[index + 1][channel],
+        );
+        ((from + (to - from) * blend) * 255.).round() as u8
+    });
+    [r, g, b, 255]
+}
+
+impl TraversalHeatmap {
+    /// The highest number of steps of any pixel, see `TraversalCounters::steps`
+    pub fn max_steps(&self) -> u64 {
+        self.counters
+            .iter()
+            .map(TraversalCounters::steps)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The number of steps of every pixel together, e.g. to compare the cost of whole frames
+    pub fn total_steps(&self) -> u64 {
+        self.counters.iter().map(TraversalCounters::steps).sum()
+    }
+
+    /// Converts the heatmap into an RGBA8 image: pixels without any steps are black,
+    /// more steps shown through blue, red and yellow, up to white at the given number of steps
+    /// * `max_steps` - The number of steps shown as white, pixels with more steps are white too;
+    ///   None for the highest number of steps of the heatmap. Heatmaps to be compared need the same value
+    pub fn to_rgba(&self, max_steps: Option<u64>) -> Vec<u8> {
+        let max_steps = max_steps.unwrap_or_else(|| self.max_steps()).max(1) as f32;
+        self.counters
+            .iter()
+            .flat_map(|counters| heatmap_color(counters.steps() as f32 / max_steps))
+            .collect()
+    }
+
+    /// Saves the heatmap as a PNG image, see `to_rgba`
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: &str, max_steps: Option<u64>) -> Result<(), std::io::Error> {
+        image::RgbaImage::from_raw(self.width, self.height, self.to_rgba(max_steps))
+            .unwrap()
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|error| match error {
+                image::ImageError::IoError(error) => error,
+                error => std::io::Error::other(error.to_string()),
+            })
+    }
+}
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: usize>
+    Octree<T, DIM>
+{
+    /// Provides the bounds of the occupied nodes at the given depth, and of the occupied leaves above it,
+    /// the root being at depth 0; every voxel of the tree is inside one of them
+    pub(in crate::octree) fn occupied_bounds_down_to(&self, depth: u32) -> Vec<Cube> {
+        let mut occupied = Vec::new();
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            Cube::root_bounds(self.octree_size),
+            0,
+        )];
+        while let Some((node_key, bounds, node_depth)) = node_stack.pop() {
+            match self.nodes.get(node_key as usize) {
+                NodeContent::Internal(_) if node_depth < depth => {
+                    let children = &self.node_children[node_key as usize];
+                    for octant in Octant::iter() {
+                        if children.is_occupied(octant) {
+                            node_stack.push((
+                                children[octant],
+                                bounds.child_bounds_for(octant),
+                                node_depth + 1,
+                            ));
+                        }
+                    }
+                }
+                content if content.is_occupied() => occupied.push(bounds),
+                _ => {}
+            }
+        }
+        occupied
+    }
+
+    /// Tries to find the hit of the given ray inside the leaf node stored in the cache entry.
+    /// The cached leaf is only probed should the ray go through nothing before entering it,
+    /// as the camera or the contents of the tree might have moved other voxels in front of it
+    /// * `context` - The memory to use for checking the ray before the cached leaf
+    /// * `ray` - The ray to cast, direction is expected to be sanitized
+    fn get_by_ray_from_cache<'a>(
+        &'a self,
+        context: &mut RayContext,
+        ray: &Ray,
+        entry: &CoherenceEntry,
+    ) -> Option<RayHit<'a, T>> {
+        let node_key = self.nodes.unbrand(&entry.node)?;
+        if !self.nodes.key_is_valid(node_key) || !self.nodes.get(node_key).is_leaf() {
+            return None;
+        }
+        let intersection = entry.bounds.intersect_ray(ray)?;
+        let mut current_d = intersection.impact_distance.unwrap_or(0.);
+        if 0. < current_d
+            && !matches!(
+                self.traverse_ray_until(context, ray, current_d),
+                HitOrBudgetExceeded::Miss
+            )
+        {
+            return None;
+        }
+        self.probe_leaf(
+            ray,
+            &mut current_d,
+            &Self::get_dda_scale_factors(ray),
+            node_key as PoolKey,
+            &entry.bounds,
+            &intersection,
+            &mut DefaultKernel,
+        )
+    }
+
+    /// Traces the ray through the given pixel, providing its shaded color and the distance of the hit, if any
+    fn trace_pixel(
+        &self,
+        context: &mut RayContext,
+        camera: &Camera,
+        pixel: (u32, u32),
+        resolution: (u32, u32),
+        tone_mapping: &ToneMapping,
+    ) -> ([u8; 4], Option<f32>) {
+        let ray =
+            Self::sanitized_ray(&camera.ray_for(pixel.0, pixel.1, resolution.0, resolution.1));
+        match self.get_by_ray_detailed_with(context, &ray) {
+            Some(hit) => (
+                shaded_color(hit.data, &hit.normal, tone_mapping),
+                Some(hit.distance),
+            ),
+            None => (BACKGROUND_COLOR, None),
+        }
+    }
+
+    /// Renders the contents of the octree through the given camera into an RGBA8 image buffer
+    /// * `camera` - The camera to render through
+    /// * `width`, `height` - The size of the rendered image in pixels
+    /// * `cache` - Optional cache of the previous frames' hits to start traversal from
+    pub fn render_viewport(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        cache: Option<&mut FrameCoherenceCache>,
+    ) -> Vec<u8> {
+        self.render_viewport_with(camera, width, height, cache, &ToneMapping::default())
+    }
+
+    /// Renders the contents of the octree like `render_viewport`, converting the shaded
+    /// linear colors to the sRGB encoded output through the given tone mapping
+    pub fn render_viewport_with(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        mut cache: Option<&mut FrameCoherenceCache>,
+        tone_mapping: &ToneMapping,
+    ) -> Vec<u8> {
+        if let Some(cache) = cache.as_mut() {
+            if cache.width != width || cache.height != height {
+                **cache = FrameCoherenceCache {
+                    max_age: cache.max_age,
+                    ..FrameCoherenceCache::new(width, height)
+                };
+            }
+        }
+
+        // Tiles of the image where no rays can hit anything are not traced pixel by pixel
+        let occupied = self.occupied_bounds_down_to(BACKGROUND_CHECK_DEPTH);
+        let tile_columns = width.div_ceil(BACKGROUND_CHECK_TILE_SIZE);
+        let background_tiles = TileOrder::Scanline
+            .tiles(width, height, BACKGROUND_CHECK_TILE_SIZE)
+            .into_iter()
+            .map(|tile| camera.misses_all(tile, (width, height), &occupied))
+            .collect::<Vec<_>>();
+
+        let mut context = RayContext::new();
+        let mut image = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel_index = (y * width + x) as usize;
+                let tile_index = (y / BACKGROUND_CHECK_TILE_SIZE) * tile_columns
+                    + x / BACKGROUND_CHECK_TILE_SIZE;
+                if background_tiles[tile_index as usize] {
+                    if let Some(cache) = cache.as_mut() {
+                        cache.entries[pixel_index] = None;
+                    }
+                    image.extend_from_slice(&BACKGROUND_COLOR);
+                    continue;
+                }
+                let ray = Self::sanitized_ray(&camera.ray_for(x, y, width, height));
+                let hit = match cache.as_mut() {
+                    Some(cache) => {
+                        let cached_hit = cache.entries[pixel_index]
+                            .filter(|entry| entry.age < cache.max_age)
+                            .and_then(|entry| {
+                                self.get_by_ray_from_cache(&mut context, &ray, &entry)
+                            });
+                        if let Some(hit) = cached_hit {
+                            if let Some(entry) = cache.entries[pixel_index].as_mut() {
+                                entry.age += 1;
+                            }
+                            Some(hit)
+                        } else {
+                            let hit = self.get_by_ray_detailed_with(&mut context, &ray);
+                            // Stagger the age of the new entries, so forced refreshes
+                            // are distributed evenly between frames
+                            cache.entries[pixel_index] = hit.as_ref().map(|hit| CoherenceEntry {
+                                node: self.nodes.brand(hit.node as usize),
+                                bounds: hit.bounds,
+                                age: pixel_index as u32 % cache.max_age.max(1),
+                            });
+                            hit
+                        }
+                    }
+                    None => self.get_by_ray_detailed_with(&mut context, &ray),
+                };
+
+                image.extend_from_slice(&match hit {
+                    Some(hit) => shaded_color(hit.data, &hit.normal, tone_mapping),
+                    None => BACKGROUND_COLOR,
+                });
+            }
+        }
+        image
+    }
+
+    /// Renders the contents of the octree like `render_viewport_with`, with the given overlay
+    /// composited over it, see `get_by_ray_with_overlay`
+    pub fn render_viewport_with_overlay(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        overlay: &OverlayOctree<T, DIM>,
+        tone_mapping: &ToneMapping,
+    ) -> Vec<u8> {
+        let mut image = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let ray = Self::sanitized_ray(&camera.ray_for(x, y, width, height));
+                image.extend_from_slice(&match self.get_by_ray_with_overlay(&ray, overlay) {
+                    Some(hit) => shaded_color(&hit.data, &hit.normal, tone_mapping),
+                    None => BACKGROUND_COLOR,
+                });
+            }
+        }
+        image
+    }
+
+    /// Renders the contents of the octree like `render_viewport` and saves it as a PNG image,
+    /// e.g. to produce thumbnails of saved worlds on headless servers
+    /// * `path` - The file to save the image to
+    /// * `camera` - The camera to render through
+    /// * `resolution` - The size of the rendered image in pixels: (width, height)
+    #[cfg(feature = "image")]
+    pub fn render_to_png(
+        &self,
+        path: &str,
+        camera: &Camera,
+        resolution: (u32, u32),
+    ) -> Result<(), std::io::Error> {
+        let (width, height) = resolution;
+        image::RgbaImage::from_raw(
+            width,
+            height,
+            self.render_viewport(camera, width, height, None),
+        )
+        .unwrap()
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|error| match error {
+            image::ImageError::IoError(error) => error,
+            error => std::io::Error::other(error.to_string()),
+        })
+    }
+
+    /// Provides the front-most voxels visible through the given rectangle of the viewport,
+    /// ordered by their distance from the camera, each voxel listed once
+    /// * `camera` - The camera to look through
+    /// * `rect` - The area of the viewport in pixels: (x, y, width, height), where (0,0) is the top-left corner
+    /// * `resolution` - The size of the whole viewport in pixels: (width, height)
+    pub fn voxels_in_screen_rect(
+        &self,
+        camera: &Camera,
+        rect: (u32, u32, u32, u32),
+        resolution: (u32, u32),
+    ) -> Vec<V3c<u32>> {
+        let (width, height) = resolution;
+        let mut hits = Vec::new();
+        for y in rect.1..(rect.1 + rect.3).min(height) {
+            for x in rect.0..(rect.0 + rect.2).min(width) {
+                let ray = Self::sanitized_ray(&camera.ray_for(x, y, width, height));
+                if let Some(hit) = self.get_by_ray_detailed(&ray) {
+                    hits.push((hit.distance, Self::voxel_position_of(&ray, &hit)));
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut listed = HashSet::new();
+        hits.into_iter()
+            .filter_map(|(_, p)| listed.insert((p.x, p.y, p.z)).then_some(p))
+            .collect()
+    }
+}
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + Sync + VoxelData, const DIM: usize>
+    Octree<T, DIM>
+{
+    /// Renders the contents of the octree like `render_viewport_with` on multiple threads
+    /// with the `parallel` feature, tile by tile.
+    /// Batches of tiles are rendered in parallel, then handed to the given callback one by one in the given order,
+    /// e.g. to show a progressive preview. Every pixel is traced on its own, so both the image
+    /// and the order of the callbacks are the same regardless of thread scheduling
+    /// * `resolution` - The size of the rendered image in pixels: (width, height)
+    /// * `tile_size` - The width and height of the tiles in pixels
+    /// * `on_tile` - Called with every tile once it is rendered
+    pub fn render_viewport_tiled(
+        &self,
+        camera: &Camera,
+        resolution: (u32, u32),
+        tile_size: u32,
+        order: TileOrder,
+        tone_mapping: &ToneMapping,
+        mut on_tile: impl FnMut(&ImageTile),
+    ) -> Vec<u8> {
+        let (width, height) = resolution;
+        let mut image = vec![0; (width * height * 4) as usize];
+        let tiles = order.tiles(width, height, tile_size);
+        let occupied = self.occupied_bounds_down_to(BACKGROUND_CHECK_DEPTH);
+        for batch in tiles.chunks(current_num_threads().max(1)) {
+            let rendered = batch
+                .par_iter()
+                .map_init(
+                    RayContext::new,
+                    |context, &(x, y, tile_width, tile_height)| {
+                        // Tiles where no rays can hit anything are filled without tracing them
+                        let tile = (x, y, tile_width, tile_height);
+                        if camera.misses_all(tile, (width, height), &occupied) {
+                            return ImageTile {
+                                x,
+                                y,
+                                width: tile_width,
+                                height: tile_height,
+                                pixels: BACKGROUND_COLOR
+                                    .repeat((tile_width * tile_height) as usize),
+                            };
+                        }
+                        let mut pixels =
+                            Vec::with_capacity((tile_width * tile_height * 4) as usize);
+                        for pixel_y in y..(y + tile_height) {
+                            for pixel_x in x..(x + tile_width) {
+                                pixels.extend_from_slice(
+                                    &self
+                                        .trace_pixel(
+                                            context,
+                                            camera,
+                                            (pixel_x, pixel_y),
+                                            resolution,
+                                            tone_mapping,
+                                        )
+                                        .0,
+                                );
+                            }
+                        }
+                        ImageTile {
+                            x,
+                            y,
+                            width: tile_width,
+                            height: tile_height,
+                            pixels,
+                        }
+                    },
+                )
+                .collect::<Vec<_>>();
+            for tile in rendered.iter() {
+                for (row, tile_row) in tile.pixels.chunks((tile.width * 4) as usize).enumerate() {
+                    let start = (((tile.y + row as u32) * width + tile.x) * 4) as usize;
+                    image[start..(start + tile_row.len())].copy_from_slice(tile_row);
+                }
+                on_tile(tile);
+            }
+        }
+        image
+    }
+
+    /// Traces one more sample for every pixel of the given buffer and adds it to the accumulated averages.
+    /// The samples of a pixel go through different points inside it, so the image converges to an anti-aliased one;
+    /// the buffer is to be reset whenever the camera or the contents of the tree change.
+    /// The result can be displayed through `AccumulationBuffer::resolve`, optionally with a denoiser
+    pub fn accumulate_viewport(&self, camera: &Camera, buffer: &mut AccumulationBuffer) {
+        let (width, height) = (buffer.width, buffer.height);
+        let background = BACKGROUND_COLOR.map(|channel| srgb_to_linear(channel as f32 / 255.));
+        let samples = buffer
+            .sample_count
+            .par_iter()
+            .enumerate()
+            .map_init(RayContext::new, |context, (index, &sample_count)| {
+                let (offset_x, offset_y) = sample_offset(sample_count);
+                let ray = Self::sanitized_ray(&camera.ray_through(
+                    (index as u32 % width) as f32 + offset_x,
+                    (index as u32 / width) as f32 + offset_y,
+                    width,
+                    height,
+                ));
+                match self.get_by_ray_detailed_with(context, &ray) {
+                    Some(hit) => {
+                        let albedo = hit.data.albedo_hdr();
+                        (
+                            shaded_linear(hit.data, &hit.normal),
+                            [albedo[0], albedo[1], albedo[2]],
+                            hit.normal,
+                        )
+                    }
+                    None => (
+                        [background[0], background[1], background[2]],
+                        [0.; 3],
+                        V3c::unit(0.),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        for (index, (color, albedo, normal)) in samples.into_iter().enumerate() {
+            buffer.add_sample(index, color, albedo, normal);
+        }
+    }
+
+    /// Renders the contents of the octree like `render_viewport_tiled`, starting at quarter resolution:
+    /// one ray is traced for every 2x2 block of pixels. Only the tiles whose samples vary in color or depth
+    /// more than the given settings allow, or which show both voxels and the background are traced again
+    /// pixel by pixel; the rest of the image is upscaled from the samples
+    /// * `resolution` - The size of the rendered image in pixels: (width, height)
+    /// * `tile_size` - The width and height of the tiles in pixels, rounded up to an even number
+    /// * `adaptive` - Decides which tiles are refined, and how many of them in one frame
+    pub fn render_viewport_adaptive(
+        &self,
+        camera: &Camera,
+        resolution: (u32, u32),
+        tile_size: u32,
+        adaptive: &AdaptiveRendering,
+        tone_mapping: &ToneMapping,
+    ) -> Vec<u8> {
+        let (width, height) = resolution;
+        let mut image = vec![0; (width * height * 4) as usize];
+        let tiles = TileOrder::Scanline.tiles(width, height, tile_size.max(1).next_multiple_of(2));
+        let occupied = self.occupied_bounds_down_to(BACKGROUND_CHECK_DEPTH);
+
+        // Sample the top-left pixel of every 2x2 block; tiles showing only the background are not sampled
+        let samples = tiles
+            .par_iter()
+            .map_init(
+                RayContext::new,
+                |context, &(x, y, tile_width, tile_height)| {
+                    if camera.misses_all((x, y, tile_width, tile_height), resolution, &occupied) {
+                        return None;
+                    }
+                    let mut samples = Vec::new();
+                    for pixel_y in (y..(y + tile_height)).step_by(2) {
+                        for pixel_x in (x..(x + tile_width)).step_by(2) {
+                            samples.push(self.trace_pixel(
+                                context,
+                                camera,
+                                (pixel_x, pixel_y),
+                                resolution,
+                                tone_mapping,
+                            ));
+                        }
+                    }
+                    Some(samples)
+                },
+            )
+            .collect::<Vec<_>>();
+
+        for (&(x, y, tile_width, tile_height), tile_samples) in tiles.iter().zip(samples.iter()) {
+            for pixel_y in y..(y + tile_height) {
+                for pixel_x in x..(x + tile_width) {
+                    let color = match tile_samples {
+                        Some(tile_samples) => {
+                            let sample_index =
+                                ((pixel_y - y) / 2) * tile_width.div_ceil(2) + (pixel_x - x) / 2;
+                            tile_samples[sample_index as usize].0
+                        }
+                        None => BACKGROUND_COLOR,
+                    };
+                    let start = ((pixel_y * width + pixel_x) * 4) as usize;
+                    image[start..(start + 4)].copy_from_slice(&color);
+                }
+            }
+        }
+
+        // Refine the tiles varying the most, within the budget of the frame
+        let mut refined = samples
+            .iter()
+            .enumerate()
+            .filter_map(|(index, tile_samples)| {
+                Some((index, sample_variation(tile_samples.as_ref()?, adaptive)?))
+            })
+            .collect::<Vec<_>>();
+        refined.sort_by(|a, b| b.1.total_cmp(&a.1));
+        refined.truncate(adaptive.max_refined_tiles.unwrap_or(usize::MAX));
+        let rendered = refined
+            .par_iter()
+            .map_init(RayContext::new, |context, &(index, _)| {
+                let (x, y, tile_width, tile_height) = tiles[index];
+                let mut pixels = Vec::with_capacity((tile_width * tile_height * 4) as usize);
+                for pixel_y in y..(y + tile_height) {
+                    for pixel_x in x..(x + tile_width) {
+                        pixels.extend_from_slice(
+                            &self
+                                .trace_pixel(
+                                    context,
+                                    camera,
+                                    (pixel_x, pixel_y),
+                                    resolution,
+                                    tone_mapping,
+                                )
+                                .0,
+                        );
+                    }
+                }
+                (index, pixels)
+            })
+            .collect::<Vec<_>>();
+        for (index, pixels) in rendered {
+            let (x, y, tile_width, _) = tiles[index];
+            for (row, tile_row) in pixels.chunks((tile_width * 4) as usize).enumerate() {
+                let start = (((y + row as u32) * width + x) * 4) as usize;
+                image[start..(start + tile_row.len())].copy_from_slice(tile_row);
+            }
+        }
+        image
+    }
+
+    /// Traces the ray through every pixel like `render_viewport`, recording the work done for each of them
+    /// instead of the color of the voxels hit, to see where the tracer spends its time, e.g. at grazing angles
+    /// or in the deep regions of the tree; see `TraversalHeatmap::to_rgba`. Unlike `render_viewport_with`,
+    /// tiles missing every node are traced too, so every ray is measured. Rows are traced in parallel with the `parallel` feature
+    /// * `camera` - The camera to render through
+    /// * `width`, `height` - The size of the rendered image in pixels
+    pub fn render_traversal_heatmap(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+    ) -> TraversalHeatmap {
+        let counters = (0..height)
+            .into_par_iter()
+            .map_init(RayContext::new, |context, y| {
+                (0..width)
+                    .map(|x| {
+                        let ray = Self::sanitized_ray(&camera.ray_for(x, y, width, height));
+                        context.reset_counters();
+                        self.get_by_ray_detailed_with(context, &ray);
+                        context.counters()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .flatten()
+            .collect();
+        TraversalHeatmap {
+            width,
+            height,
+            counters,
+        }
+    }
+}
+
+/// How much the given samples of a tile vary compared to the allowed variance in the given settings,
+/// or `None` if the tile doesn't need to be refined.
+/// Tiles with both hits and misses vary the most, as they show the silhouette of the voxels
+fn sample_variation(
+    samples: &[([u8; 4], Option<f32>)],
+    adaptive: &AdaptiveRendering,
+) -> Option<f32> {
+    let count = samples.len() as f32;
+    let hit_count = samples
+        .iter()
+        .filter(|(_, distance)| distance.is_some())
+        .count();
+    if 0 < hit_count && hit_count < samples.len() {
+        return Some(f32::INFINITY);
+    }
+
+    let mut color_variance = 0.;
+    for channel in 0..3 {
+        let values = samples
+            .iter()
+            .map(|(color, _)| color[channel] as f32 / 255.);
+        let mean = values.clone().sum::<f32>() / count;
+        color_variance += values.map(|value| (value - mean).powi(2)).sum::<f32>() / count;
+    }
+    color_variance /= 3.;
+
+    let distances = samples.iter().filter_map(|(_, distance)| *distance);
+    let mean_distance = distances.clone().sum::<f32>() / count;
+    let depth_variance = if 0. < mean_distance {
+        distances
+            .map(|distance| (distance - mean_distance).powi(2))
+            .sum::<f32>()
+            / count
+            / mean_distance.powi(2)
+    } else {
+        0.
+    };
+
+    (adaptive.color_variance < color_variance || adaptive.depth_variance < depth_variance).then(
+        || {
+            (color_variance / adaptive.color_variance.max(f32::EPSILON))
+                .max(depth_variance / adaptive.depth_variance.max(f32::EPSILON))
+        },
+    )
+}
+
+/// Renders on the CPU into RGBA8 image buffers of a fixed size, see `Octree::render_viewport`
+pub struct CpuRenderBackend<T: Default + Clone + VoxelData, const DIM: usize> {
+    pub width: u32,
+    pub height: u32,
+    pub tone_mapping: ToneMapping,
+    pub(in crate::octree) tree: Option<Octree<T, DIM>>,
+    pub(in crate::octree) cache: FrameCoherenceCache,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> CpuRenderBackend<T, DIM> {
+    /// Creates a backend rendering images of the given size in pixels
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tone_mapping: ToneMapping::default(),
+            tree: None,
+            cache: FrameCoherenceCache::new(width, height),
+        }
+    }
+
+    /// The copy of the tree to render from, created on the first upload
+    fn uploaded_tree(&mut self, tree: &Octree<T, DIM>) -> &mut Octree<T, DIM> {
+        // Cached hits refer to the previously uploaded nodes
+        self.cache.invalidate();
+        self.tree
+            .get_or_insert_with(|| Octree::new(tree.octree_size).ok().unwrap())
+    }
+}
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: usize>
+    VoxelRenderBackend<T, DIM> for CpuRenderBackend<T, DIM>
+{
+    type Frame<'a>
+        = Vec<u8>
+    where
+        Self: 'a;
+
+    fn upload_nodes(&mut self, tree: &Octree<T, DIM>) {
+        let uploaded = self.uploaded_tree(tree);
+        uploaded.octree_size = tree.octree_size;
+        uploaded.nodes = tree.nodes.clone();
+        uploaded.node_children = tree.node_children.clone();
+    }
+
+    fn upload_bricks(&mut self, tree: &Octree<T, DIM>) {
+        self.uploaded_tree(tree).bricks = tree.bricks.clone();
+    }
+
+    fn render(&mut self, camera: &Camera) -> Vec<u8> {
+        match self.tree.as_ref() {
+            Some(tree) => tree.render_viewport_with(
+                camera,
+                self.width,
+                self.height,
+                Some(&mut self.cache),
+                &self.tone_mapping,
+            ),
+            None => BACKGROUND_COLOR.repeat((self.width * self.height) as usize),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod wgpu_tests {
     #[test]
+    // 64 bit keys are converted to the 32 bit keys of the shader on upload
+    #[cfg(not(feature = "u64_keys"))]
     fn test_special_key_values() {
         // assumptions in shader needs to be compared to factual values
         assert!(crate::object_pool::key_none_value() == 4294967295u32);
@@ -9,16 +11,28 @@ mod wgpu_tests {
 
 #[cfg(test)]
 mod octree_raytracing_tests {
-    use crate::octree::{Cube, Octree, V3c};
+    use crate::octree::raytracing::{
+        CellHit, DefaultKernel, HitOrBudgetExceeded, InsideVoxelPolicy, LodFade, PrefetchPolicy,
+        RayContext, RayFootprint, RayHitCompact, RayOptions, TraversalCounters, TraversalKernel,
+        WorldRayHit,
+    };
+    use crate::octree::{
+        lighting::bake_probe_grid, BoxFace, Cube, Facing, Octree, OctreeWriteQueue, V3c, VoxelAux,
+        VoxelData, VoxelShape, VoxelWorld,
+    };
     use crate::spatial::raytracing::Ray;
-    use crate::spatial::{math::plane_line_intersection, FLOAT_ERROR_TOLERANCE};
+    use crate::spatial::{
+        primitives::{Plane, Sphere},
+        FLOAT_ERROR_TOLERANCE,
+    };
 
-    use rand::{rngs::ThreadRng, Rng};
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     /// Reference implementation to decide step to sibling boundary
     fn get_step_to_next_sibling(current: &Cube, ray: &Ray) -> V3c<f32> {
         //Find the point furthest from the ray
-        let midpoint = V3c::unit((current.size / 2) as f32) + current.min_position.into();
+        let midpoint = V3c::unit(current.size as f32 / 2.) + current.min_position.into();
         let ref_point = midpoint
             + V3c::new(
                 (current.size as f32 / 2.).copysign(ray.direction.x),
@@ -27,27 +41,15 @@ mod octree_raytracing_tests {
             );
 
         // Find the min of the 3 plane intersections
-        let x_plane_distance = plane_line_intersection(
-            &ref_point,
-            &V3c::new(1., 0., 0.),
-            &ray.origin,
-            &ray.direction,
-        )
-        .unwrap_or(f32::MAX);
-        let y_plane_distance = plane_line_intersection(
-            &ref_point,
-            &V3c::new(0., 1., 0.),
-            &ray.origin,
-            &ray.direction,
-        )
-        .unwrap_or(f32::MAX);
-        let z_plane_distance = plane_line_intersection(
-            &ref_point,
-            &V3c::new(0., 0., 1.),
-            &ray.origin,
-            &ray.direction,
-        )
-        .unwrap_or(f32::MAX);
+        let x_plane_distance = Plane::new(ref_point, V3c::new(1., 0., 0.))
+            .intersect_line(&ray.origin, &ray.direction)
+            .unwrap_or(f32::MAX);
+        let y_plane_distance = Plane::new(ref_point, V3c::new(0., 1., 0.))
+            .intersect_line(&ray.origin, &ray.direction)
+            .unwrap_or(f32::MAX);
+        let z_plane_distance = Plane::new(ref_point, V3c::new(0., 0., 1.))
+            .intersect_line(&ray.origin, &ray.direction)
+            .unwrap_or(f32::MAX);
         let min_d = x_plane_distance.min(y_plane_distance).min(z_plane_distance);
 
         // Step along the axes with the minimum distances
@@ -72,10 +74,7 @@ mod octree_raytracing_tests {
 
     #[test]
     fn compare_sibling_step_functions() {
-        // Sometimes this test fails because the optimized implementation
-        // does not consider points at the exact edges of the cube to be part of it
-        // and axis aligned ray directions at the cube boundaries also behave differently
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed("compare_sibling_step_functions"));
         for _ in 0..100 {
             let cube = Cube {
                 min_position: V3c::new(
@@ -110,7 +109,7 @@ mod octree_raytracing_tests {
         }
     }
 
-    fn make_ray_point_to(target: &V3c<f32>, rng: &mut ThreadRng) -> Ray {
+    fn make_ray_point_to(target: &V3c<f32>, rng: &mut impl Rng) -> Ray {
         let origin = V3c {
             x: rng.gen_range(8..16) as f32,
             y: rng.gen_range(8..16) as f32,
@@ -124,7 +123,7 @@ mod octree_raytracing_tests {
 
     #[test]
     fn test_get_by_ray_from_outside() {
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_from_outside"));
         let mut tree = Octree::<u32>::new(4).ok().unwrap();
         let mut filled = Vec::new();
         for x in 1..4 {
@@ -144,9 +143,430 @@ mod octree_raytracing_tests {
         }
     }
 
+    #[test]
+    fn test_raycast_batch() {
+        let mut rng = StdRng::seed_from_u64(seed("test_raycast_batch"));
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        let mut targets = Vec::new();
+        for x in 1..7 {
+            for y in 1..7 {
+                if 10 > rng.gen_range(0..20) {
+                    let position = V3c::new(x, y, 1);
+                    tree.insert(&position, 5 | 0xFF000000).ok().unwrap();
+                    targets.push(V3c::new(x as f32, y as f32, 1.));
+                }
+            }
+        }
+        let rays = targets
+            .iter()
+            .map(|target| make_ray_point_to(target, &mut rng))
+            .chain(std::iter::once(Ray {
+                origin: V3c::new(-1., -1., -1.),
+                direction: V3c::new(-1., 0., 0.),
+            }))
+            .collect::<Vec<_>>();
+        let origins = rays.iter().map(|ray| ray.origin).collect::<Vec<_>>();
+        // Directions are normalized by the batch
+        let dirs = rays
+            .iter()
+            .map(|ray| ray.direction * 3.)
+            .collect::<Vec<_>>();
+        let mut out = vec![RayHitCompact::default(); rays.len()];
+        tree.raycast_batch(&origins, &dirs, &mut out);
+
+        // The rays target the corners of voxels, so they are compared with the same direction the batch used
+        for ((origin, direction), result) in origins.iter().zip(dirs.iter()).zip(out.iter()) {
+            let ray = Ray {
+                origin: *origin,
+                direction: direction.normalized(),
+            };
+            match tree.get_by_ray_owned(&ray) {
+                Some(hit) => {
+                    assert!(result.hit);
+                    assert!(result.voxel == hit.voxel);
+                    assert!((result.distance - hit.distance).abs() < 0.001);
+                }
+                None => assert!(!result.hit),
+            }
+        }
+        assert!(out[..targets.len()].iter().all(|result| result.hit));
+        assert!(!out[targets.len()].hit);
+    }
+
+    #[test]
+    fn test_raycast_batch_with_zero_direction() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(2, 2, 2), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let origins = [V3c::new(2.5, 2.5, 0.5), V3c::new(2.5, 2.5, 2.5)];
+        let dirs = [V3c::unit(0.), V3c::unit(0.)];
+        let mut out = [RayHitCompact {
+            hit: true,
+            ..Default::default()
+        }; 2];
+        tree.raycast_batch(&origins, &dirs, &mut out);
+
+        // Rays with a zero direction miss, even when they start inside a voxel
+        assert!(out.iter().all(|result| *result == RayHitCompact::default()));
+    }
+
+    #[test]
+    fn test_occlusion_between() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        // A wall at z = 8, covering x < 8
+        for x in 0..8 {
+            for y in 0..16 {
+                tree.insert(&V3c::new(x, y, 8), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let behind_wall = tree.occlusion_between(V3c::new(3.5, 8.5, 2.5), V3c::new(3.5, 8.5, 13.5));
+        let clear = tree.occlusion_between(V3c::new(12.5, 8.5, 2.5), V3c::new(12.5, 8.5, 13.5));
+        let at_edge = tree.occlusion_between(V3c::new(8., 8.5, 2.5), V3c::new(8., 8.5, 13.5));
+        assert!(1. == behind_wall);
+        assert!(0. == clear);
+        assert!(0. < at_edge && at_edge < 1.);
+    }
+
+    #[test]
+    fn test_openness_at() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        assert!(1. == tree.openness_at(V3c::new(8., 1., 8.), 32));
+
+        // A roof above the half of the tree with x < 8
+        for x in 0..8 {
+            for z in 0..16 {
+                tree.insert(&V3c::new(x, 10, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let under_roof = tree.openness_at(V3c::new(2., 1., 8.), 64);
+        let next_to_roof = tree.openness_at(V3c::new(12., 1., 8.), 64);
+        assert!(under_roof < next_to_roof);
+        assert!(next_to_roof < 1.);
+    }
+
+    #[test]
+    fn test_get_by_world_ray() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap().with_voxel_size(0.5);
+        tree.insert(&V3c::new(4, 1, 1), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        // The voxel spans 2..2.5 along x in world space
+        let ray = Ray {
+            origin: V3c::new(0., 0.75, 0.75),
+            direction: V3c::new(1., 0., 0.),
+        };
+        let hit = tree.get_by_world_ray(&ray).unwrap();
+        assert!(hit.voxel == V3c::new(4, 1, 1));
+        assert!((hit.distance - 2.).abs() < 0.001);
+        assert!((hit.point - V3c::new(2., 0.75, 0.75)).length() < 0.001);
+        assert!(hit.normal == V3c::new(-1., 0., 0.));
+    }
+
+    /// Reference implementation of a world ray: the closest of the voxels the ray intersects
+    /// The closest of the given voxels the given ray enters, with the distances it enters and exits it at.
+    /// Voxels the ray misses by less than the given margin are included
+    fn closest_voxel_hit(
+        voxels: &[V3c<i32>],
+        ray: &Ray,
+        margin: f32,
+    ) -> Option<(V3c<i32>, f32, f32)> {
+        voxels
+            .iter()
+            .filter_map(|voxel| {
+                let min = V3c::<f32>::from(*voxel);
+                let (mut enter, mut exit) = (0., f32::MAX);
+                for (origin, direction, min) in [
+                    (ray.origin.x, ray.direction.x, min.x),
+                    (ray.origin.y, ray.direction.y, min.y),
+                    (ray.origin.z, ray.direction.z, min.z),
+                ] {
+                    let near = (min - origin) / direction;
+                    let far = (min + 1. - origin) / direction;
+                    enter = near.min(far).max(enter);
+                    exit = near.max(far).min(exit);
+                }
+                (enter <= exit + margin).then_some((*voxel, enter, exit))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    #[test]
+    fn test_world_ray_across_chunks() {
+        let mut rng = StdRng::seed_from_u64(seed("test_world_ray_across_chunks"));
+        // Far from the world origin, so the precision of the traversal is tested too
+        for offset in [V3c::new(0, 0, 0), V3c::new(-40000, 20000, 40000)] {
+            // The reference points far from the world origin are only precise up to a few ulps
+            let tolerance = 0.01 + 4. * f32::EPSILON * V3c::<f32>::from(offset).length();
+            let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+            let mut voxels = Vec::new();
+            for _ in 0..30 {
+                let voxel = offset
+                    + V3c::new(
+                        rng.gen_range(-10..10),
+                        rng.gen_range(-10..10),
+                        rng.gen_range(-10..10),
+                    );
+                world.insert(&voxel, 5).ok().unwrap();
+                voxels.push(voxel);
+            }
+            for _ in 0..200 {
+                let origin = V3c::<f32>::from(offset)
+                    + V3c::new(
+                        rng.gen_range(-16.0..16.0),
+                        rng.gen_range(-16.0..16.0),
+                        rng.gen_range(-16.0..16.0),
+                    );
+                let target =
+                    V3c::<f32>::from(voxels[rng.gen_range(0..voxels.len())]) + V3c::unit(0.5);
+                let ray = Ray {
+                    origin,
+                    direction: (target - origin).normalized(),
+                };
+                // Rays starting inside a voxel are not compared
+                let origin_voxel = V3c::new(origin.x.floor(), origin.y.floor(), origin.z.floor());
+                if world.get(&V3c::<i32>::from(origin_voxel)).is_some() {
+                    continue;
+                }
+                // Rays grazing an edge or a corner of the first voxel might hit or miss it within the precision
+                let reference = closest_voxel_hit(&voxels, &ray, tolerance);
+                if reference.is_some_and(|(_, enter, exit)| exit - enter < tolerance) {
+                    continue;
+                }
+                match (world.get_by_ray(&ray, 100., false), reference) {
+                    (WorldRayHit::Hit { chunk_coord, hit }, Some((_, distance, _))) => {
+                        let voxel = world.world_position_of(&chunk_coord, &hit.voxel);
+                        assert!(voxels.contains(&voxel));
+                        assert!((hit.distance - distance).abs() < 0.01);
+                        assert!(
+                            (hit.point - (ray.origin + ray.direction * distance)).length()
+                                < tolerance
+                        );
+                    }
+                    (_, reference) => panic!("The ray should hit {:?}", reference),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_world_ray_through_unknown_chunks() {
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        world.insert(&V3c::new(-2, 1, 1), 5).ok().unwrap();
+        world.insert(&V3c::new(9, 1, 1), 6).ok().unwrap();
+        world.insert_empty_chunk(V3c::new(1, 0, 0));
+        let ray = Ray {
+            origin: V3c::new(-3.5, 1.5, 1.5),
+            direction: V3c::new(2., 0., 0.),
+        };
+        match world.get_by_ray(&ray, 20., false) {
+            WorldRayHit::Hit { chunk_coord, hit } => {
+                assert!(chunk_coord == V3c::new(-1, 0, 0));
+                assert!(hit.data == 5);
+                assert!(hit.voxel == V3c::new(2, 1, 1));
+                assert!((hit.distance - 1.5).abs() < 0.001);
+                assert!((hit.point - V3c::new(-2., 1.5, 1.5)).length() < 0.001);
+            }
+            _ => panic!("The ray should hit the first voxel"),
+        }
+
+        // Missing chunks are passed through as air, unless the ray stops at them
+        let ray = Ray {
+            origin: V3c::new(-0.5, 1.5, 1.5),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(matches!(
+            world.get_by_ray(&ray, 20., false),
+            WorldRayHit::Hit { chunk_coord, hit }
+                if chunk_coord == V3c::new(2, 0, 0) && hit.data == 6 && (hit.distance - 9.5).abs() < 0.001
+        ));
+        assert!(world.get_by_ray(&ray, 9., false) == WorldRayHit::Miss);
+        assert!(matches!(
+            world.get_by_ray(&ray, 20., true),
+            WorldRayHit::Unknown { chunk_coord, distance }
+                if chunk_coord == V3c::new(0, 0, 0) && (distance - 0.5).abs() < 0.001
+        ));
+
+        world.insert_empty_chunk(V3c::new(0, 0, 0));
+        assert!(matches!(
+            world.get_by_ray(&ray, 20., true),
+            WorldRayHit::Hit { chunk_coord, .. } if chunk_coord == V3c::new(2, 0, 0)
+        ));
+        assert!(world.get_by_ray(&ray, 3., true) == WorldRayHit::Miss);
+    }
+
+    #[test]
+    fn test_world_ray_lod_fade() {
+        let mut world = VoxelWorld::<u32>::new(4).ok().unwrap();
+        world.insert(&V3c::new(9, 1, 1), 6).ok().unwrap();
+        let mut coarse = Octree::<u32>::new(2).ok().unwrap();
+        coarse.insert(&V3c::new(0, 0, 0), 7).ok().unwrap();
+        assert!(world
+            .insert_coarse_chunk(V3c::new(2, 0, 0), Octree::<u32>::new(4).ok().unwrap())
+            .is_err());
+        assert!(world
+            .insert_coarse_chunk(V3c::new(2, 0, 0), coarse)
+            .is_ok_and(|replaced| replaced.is_none()));
+
+        // The chunk with its center about 10.5 away is traced in its full resolution up close
+        let ray = Ray {
+            origin: V3c::new(-0.5, 1.5, 1.5),
+            direction: V3c::new(1., 0., 0.),
+        };
+        let near = LodFade {
+            start: 20.,
+            end: 30.,
+        };
+        let result = world.get_by_ray_lod(&ray, 20., false, &near);
+        assert!(result.blend.is_none());
+        assert!(matches!(
+            result.hit,
+            WorldRayHit::Hit { hit, .. } if hit.data == 6 && (hit.distance - 9.5).abs() < 0.001
+        ));
+
+        // ..and in its coarse version in the distance, scaled to the size of the chunk
+        let far = LodFade { start: 0., end: 5. };
+        let result = world.get_by_ray_lod(&ray, 20., false, &far);
+        assert!(result.blend.is_none());
+        match result.hit {
+            WorldRayHit::Hit { chunk_coord, hit } => {
+                assert!(chunk_coord == V3c::new(2, 0, 0));
+                assert!(hit.data == 7);
+                assert!(hit.voxel == V3c::new(0, 0, 0));
+                assert!((hit.distance - 8.5).abs() < 0.001);
+                assert!((hit.point - V3c::new(8., 1.5, 1.5)).length() < 0.001);
+            }
+            _ => panic!("The ray should hit the coarse voxel"),
+        }
+
+        // Within the transition band the other resolution is provided to be blended in
+        let band = LodFade {
+            start: 5.,
+            end: 15.,
+        };
+        let result = world.get_by_ray_lod(&ray, 20., false, &band);
+        assert!(matches!(result.hit, WorldRayHit::Hit { hit, .. } if hit.data == 7));
+        match result.blend {
+            Some((WorldRayHit::Hit { hit, .. }, weight)) => {
+                assert!(hit.data == 6);
+                assert!(0.4 < weight && weight < 0.5);
+            }
+            _ => panic!("The full resolution should be blended in"),
+        }
+
+        // Chunks with only their coarse version present are traced in that one
+        world.remove_chunk(&V3c::new(2, 0, 0));
+        let result = world.get_by_ray_lod(&ray, 20., true, &near);
+        assert!(matches!(result.hit, WorldRayHit::Unknown { .. }));
+        for x in -1..2 {
+            world.insert_empty_chunk(V3c::new(x, 0, 0));
+        }
+        let result = world.get_by_ray_lod(&ray, 20., true, &near);
+        assert!(matches!(result.hit, WorldRayHit::Hit { hit, .. } if hit.data == 7));
+        assert!(matches!(
+            world.get_by_ray(&ray, 20., true),
+            WorldRayHit::Unknown { .. }
+        ));
+    }
+
+    #[test]
+    fn test_get_by_ray_with_pending() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(5, 1, 1), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let ray = Ray {
+            origin: V3c::new(0., 1.5, 1.5),
+            direction: V3c::new(1., 0., 0.),
+        };
+
+        // Without any queued edits the tree is hit as is
+        let mut queue = OctreeWriteQueue::new();
+        let hit = tree.get_by_ray_with_pending(&ray, &queue).unwrap();
+        assert!(hit.voxel == V3c::new(5, 1, 1));
+
+        // A queued insert in front of the voxel in the tree is hit first
+        queue.insert(&V3c::new(3, 1, 1), 7 | 0xFF000000);
+        let hit = tree.get_by_ray_with_pending(&ray, &queue).unwrap();
+        assert!(hit.voxel == V3c::new(3, 1, 1));
+        assert!(hit.data == 7 | 0xFF000000);
+        assert!((hit.distance - 3.).abs() < 0.001);
+        assert!(hit.normal == V3c::new(-1., 0., 0.));
+
+        // Clearing both voxels lets the ray pass through them
+        queue.clear(&V3c::new(3, 1, 1));
+        queue.clear(&V3c::new(5, 1, 1));
+        assert!(tree.get_by_ray_with_pending(&ray, &queue).is_none());
+
+        // The voxel behind a queued clear is found, even if it is only queued as well
+        queue.insert(&V3c::new(6, 1, 1), 9 | 0xFF000000);
+        let hit = tree.get_by_ray_with_pending(&ray, &queue).unwrap();
+        assert!(hit.voxel == V3c::new(6, 1, 1));
+        assert!(hit.data == 9 | 0xFF000000);
+
+        // The tree itself is not changed by the preview
+        assert!(tree.get(&V3c::new(3, 1, 1)).is_none());
+        assert!(*tree.get(&V3c::new(5, 1, 1)).unwrap() == 5 | 0xFF000000);
+    }
+
+    #[test]
+    fn test_get_by_ray_refined_by_voxel_shape() {
+        #[derive(Default, Clone, Debug, PartialEq)]
+        struct Ramp(bool);
+        impl VoxelData for Ramp {
+            fn new(_r: u8, _g: u8, _b: u8, _a: u8, _user_data: u32) -> Self {
+                Self(true)
+            }
+            fn albedo(&self) -> [u8; 4] {
+                [if self.0 { 255 } else { 0 }; 4]
+            }
+            fn user_data(&self) -> u32 {
+                0
+            }
+            fn clear(&mut self) {
+                *self = Self::default();
+            }
+            fn shape(&self) -> VoxelShape {
+                VoxelShape::Slope(Facing::PositiveX)
+            }
+        }
+
+        let mut tree = Octree::<Ramp, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(2, 0, 2), Ramp(true)).ok().unwrap();
+        let down_at = |x: f32, z: f32| Ray {
+            origin: V3c::new(x, 7.5, z),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let hit = tree.get_by_ray_owned(&down_at(2.75, 2.5)).unwrap();
+        assert!(hit.voxel == V3c::new(2, 0, 2));
+        assert!((hit.point.y - 0.75).abs() < 0.001);
+        assert!((hit.normal - V3c::new(-1., 1., 0.).normalized()).length() < 0.001);
+        let hit = tree.get_by_ray_owned(&down_at(2.25, 2.5)).unwrap();
+        assert!((hit.point.y - 0.25).abs() < 0.001);
+
+        // Rays passing over the low end of the slope miss it
+        let ray = Ray {
+            origin: V3c::new(2.25, 0.5, 7.5),
+            direction: V3c::new(0., 0., -1.),
+        };
+        assert!(tree.get_by_ray(&ray).is_none());
+
+        // Uniform leaves of shaped voxels are refined voxel by voxel
+        tree.insert_at_lod(&V3c::new(4, 0, 4), 4, Ramp(true))
+            .ok()
+            .unwrap();
+        let hit = tree.get_by_ray_owned(&down_at(5.5, 5.5)).unwrap();
+        assert!(hit.voxel.x == 5);
+        assert!((hit.point.y - 3.5).abs() < 0.001);
+    }
+
     #[test]
     fn test_get_by_ray_from_outside_where_dim_is_2() {
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_from_outside_where_dim_is_2"));
         let mut tree = Octree::<u32, 2>::new(4).ok().unwrap();
         let mut filled = Vec::new();
         for x in 1..4 {
@@ -166,7 +586,7 @@ mod octree_raytracing_tests {
         }
     }
 
-    fn make_edge_ray_point_to(target: &V3c<f32>, rng: &mut ThreadRng) -> Ray {
+    fn make_edge_ray_point_to(target: &V3c<f32>, rng: &mut impl Rng) -> Ray {
         let origin = V3c {
             x: rng.gen_range(0..8) as f32,
             y: rng.gen_range(0..8) as f32,
@@ -180,7 +600,7 @@ mod octree_raytracing_tests {
 
     #[test]
     fn test_get_by_ray_from_edge() {
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_from_edge"));
         let mut tree = Octree::<u32>::new(8).ok().unwrap();
         let mut filled = Vec::new();
         for x in 1..4 {
@@ -208,7 +628,7 @@ mod octree_raytracing_tests {
 
     #[test]
     fn test_get_by_ray_from_inside() {
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_from_inside"));
         let mut tree = Octree::<u32>::new(16).ok().unwrap();
         let mut filled = Vec::new();
         for x in 1..4 {
@@ -634,4 +1054,1487 @@ mod octree_raytracing_tests {
             *v.0 == 0xFF000000 && (v.2 - V3c::<f32>::new(0., 0., 0.)).length() < 1.1
         }));
     }
+
+    #[test]
+    fn test_get_by_ray_owned() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(3, 2, 1), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let ray = Ray {
+            origin: V3c::new(3.5, 2.5, 9.),
+            direction: V3c::new(0., 0., -1.),
+        };
+        let hit = tree.get_by_ray_owned(&ray).unwrap();
+        assert!(hit.data == 5 | 0xFF000000);
+        assert!(hit.voxel == V3c::new(3, 2, 1));
+        assert!((hit.distance - 7.).abs() < 0.01);
+
+        // The hit can be used to edit the tree it was taken from
+        tree.clear(&hit.voxel).ok().unwrap();
+        assert!(tree.get(&hit.voxel).is_none());
+        assert!(tree.get_by_ray_owned(&ray).is_none());
+    }
+
+    #[test]
+    fn test_get_by_ray_with_options() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        // The hit voxel is a leaf at depth 3, reached by visiting 4 nodes
+        let ray = Ray {
+            origin: V3c::new(0.5, 0.5, 9.),
+            direction: V3c::new(0., 0., -1.),
+        };
+        assert!(matches!(
+            tree.get_by_ray_with_options(&ray, &RayOptions::default()),
+            HitOrBudgetExceeded::Hit((data, _, _)) if *data == 5 | 0xFF000000
+        ));
+        let limited = |ray: &Ray, max_node_visits, max_depth| {
+            tree.get_by_ray_with_options(
+                ray,
+                &RayOptions {
+                    max_node_visits,
+                    max_depth,
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(matches!(
+            limited(&ray, Some(4), Some(3)),
+            HitOrBudgetExceeded::Hit(_)
+        ));
+        assert!(limited(&ray, Some(3), None) == HitOrBudgetExceeded::BudgetExceeded);
+        assert!(limited(&ray, None, Some(2)) == HitOrBudgetExceeded::BudgetExceeded);
+
+        let ray = Ray {
+            origin: V3c::new(20., 20., 20.),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(limited(&ray, Some(1), Some(0)) == HitOrBudgetExceeded::Miss);
+        assert!(
+            tree.get_by_ray_with_options(&ray, &RayOptions::default()) == HitOrBudgetExceeded::Miss
+        );
+    }
+    #[test]
+    fn test_ray_context_counters() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(5, 1, 1), 0xFF0000FF).ok().unwrap();
+        let mut context = RayContext::new();
+        let ray = Ray {
+            origin: V3c::new(-1., 1.5, 1.5),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(tree.get_by_ray_with(&mut context, &ray).is_some());
+        let counters = context.counters();
+        assert!(1 < counters.node_visits);
+        assert!(0 < counters.cell_steps);
+        assert_eq!(counters.steps(), counters.node_visits + counters.cell_steps);
+
+        // The counters add up until they are reset
+        tree.get_by_ray_with(&mut context, &ray);
+        assert_eq!(context.counters().node_visits, 2 * counters.node_visits);
+        assert_eq!(context.counters().cell_steps, 2 * counters.cell_steps);
+        context.reset_counters();
+        assert_eq!(context.counters(), TraversalCounters::default());
+
+        // Rays missing the tree do not visit any nodes
+        let ray = Ray {
+            origin: V3c::new(-1., 1.5, 1.5),
+            direction: V3c::new(-1., 0., 0.),
+        };
+        assert!(tree.get_by_ray_with(&mut context, &ray).is_none());
+        assert_eq!(context.counters(), TraversalCounters::default());
+    }
+
+    #[test]
+    fn test_get_by_ray_prefetch_keeps_hits() {
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_prefetch_keeps_hits"));
+        let mut tree = Octree::<u32, 2>::new(32).ok().unwrap();
+        for _ in 0..500 {
+            tree.insert(
+                &V3c::new(
+                    rng.gen_range(0..32),
+                    rng.gen_range(0..32),
+                    rng.gen_range(0..32),
+                ),
+                rng.gen_range(1..500) | 0xFF000000,
+            )
+            .ok()
+            .unwrap();
+        }
+        let cast = |ray: &Ray, prefetch| {
+            tree.get_by_ray_with_options(
+                ray,
+                &RayOptions {
+                    prefetch,
+                    max_node_visits: Some(64),
+                    ..Default::default()
+                },
+            )
+        };
+        for _ in 0..500 {
+            let ray = Ray {
+                origin: V3c::new(
+                    rng.gen_range(-8.0..40.0),
+                    rng.gen_range(-8.0..40.0),
+                    rng.gen_range(-8.0..40.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+            };
+            assert!(cast(&ray, PrefetchPolicy::LookAhead) == cast(&ray, PrefetchPolicy::Disabled));
+        }
+    }
+
+    #[test]
+    fn test_get_by_ray_from_inside_with_policy() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(2, 2, 2), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(2, 2, 5), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let cast = |origin: V3c<f32>, inside_voxel| {
+            tree.get_by_ray_with_options(
+                &Ray {
+                    origin,
+                    direction: V3c::new(0., 0., 1.),
+                },
+                &RayOptions {
+                    inside_voxel,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The voxel containing the origin is hit immediately, skipped or rejected
+        let inside = V3c::new(2.5, 2.5, 2.5);
+        assert!(matches!(
+            cast(inside, InsideVoxelPolicy::HitAtOrigin),
+            HitOrBudgetExceeded::Hit((data, point, _))
+                if *data == 5 | 0xFF000000 && (point - inside).length() < FLOAT_ERROR_TOLERANCE
+        ));
+        assert!(matches!(
+            cast(inside, InsideVoxelPolicy::SkipContaining),
+            HitOrBudgetExceeded::Hit((data, point, _))
+                if *data == 6 | 0xFF000000 && (point.z - 5.).abs() < FLOAT_ERROR_TOLERANCE
+        ));
+        assert!(cast(inside, InsideVoxelPolicy::Reject) == HitOrBudgetExceeded::OriginInside);
+
+        // Rays starting in empty space are not affected
+        let outside = V3c::new(2.5, 2.5, 0.5);
+        for policy in [
+            InsideVoxelPolicy::HitAtOrigin,
+            InsideVoxelPolicy::SkipContaining,
+            InsideVoxelPolicy::Reject,
+        ] {
+            assert!(matches!(
+                cast(outside, policy),
+                HitOrBudgetExceeded::Hit((data, point, _))
+                    if *data == 5 | 0xFF000000 && (point.z - 2.).abs() < FLOAT_ERROR_TOLERANCE
+            ));
+        }
+
+        // Skipping the containing voxel still finds the voxel right after it
+        tree.insert(&V3c::new(2, 2, 3), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+        assert!(matches!(
+            tree.get_by_ray_with_options(
+                &Ray {
+                    origin: inside,
+                    direction: V3c::new(0., 0., 1.),
+                },
+                &RayOptions {
+                    inside_voxel: InsideVoxelPolicy::SkipContaining,
+                    ..Default::default()
+                },
+            ),
+            HitOrBudgetExceeded::Hit((data, _, _)) if *data == 7 | 0xFF000000
+        ));
+    }
+
+    #[test]
+    fn test_get_by_ray_hitting_back_faces() {
+        // A block of glass from x: 2 to x: 5, with a different voxel in the middle of it
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 2..5 {
+            tree.insert(&V3c::new(x, 1, 1), 5 | 0xFF000000)
+                .ok()
+                .unwrap();
+        }
+        tree.insert(&V3c::new(3, 1, 1), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(7, 1, 1), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let back_face_hit = |origin: V3c<f32>, direction: V3c<f32>| {
+            let options = RayOptions {
+                inside_voxel: InsideVoxelPolicy::HitBackFace,
+                ..Default::default()
+            };
+            match tree.get_by_ray_with_options(&Ray { origin, direction }, &options) {
+                HitOrBudgetExceeded::Hit((data, point, normal)) => Some((*data, point, normal)),
+                _ => None,
+            }
+        };
+
+        // Rays leave the block at its far side, the normal pointing back into it
+        let (data, point, normal) =
+            back_face_hit(V3c::new(2.1, 1.5, 1.5), V3c::new(1., 0., 0.)).unwrap();
+        assert!(data == 5 | 0xFF000000);
+        assert!((point - V3c::new(5., 1.5, 1.5)).length() < 0.01);
+        assert!((normal - V3c::new(-1., 0., 0.)).length() < FLOAT_ERROR_TOLERANCE);
+
+        let (data, point, normal) =
+            back_face_hit(V3c::new(3.5, 1.5, 1.5), V3c::new(-1., 0., 0.)).unwrap();
+        assert!(data == 5 | 0xFF000000);
+        assert!((point - V3c::new(2., 1.5, 1.5)).length() < 0.01);
+        assert!((normal - V3c::new(1., 0., 0.)).length() < FLOAT_ERROR_TOLERANCE);
+
+        // Sideways the ray leaves the block through the voxel it starts in
+        let (data, point, normal) =
+            back_face_hit(V3c::new(3.5, 1.5, 1.5), V3c::new(0., 1., 0.)).unwrap();
+        assert!(data == 6 | 0xFF000000);
+        assert!((point - V3c::new(3.5, 2., 1.5)).length() < 0.01);
+        assert!((normal - V3c::new(0., -1., 0.)).length() < FLOAT_ERROR_TOLERANCE);
+
+        // Rays from outside of the block still hit its front face
+        let (data, point, normal) =
+            back_face_hit(V3c::new(0.5, 1.5, 1.5), V3c::new(1., 0., 0.)).unwrap();
+        assert!(data == 5 | 0xFF000000);
+        assert!((point - V3c::new(2., 1.5, 1.5)).length() < 0.01);
+        assert!((normal - V3c::new(-1., 0., 0.)).length() < FLOAT_ERROR_TOLERANCE);
+
+        // The back face is also hit where the voxels end at the boundary of the tree
+        let (data, point, _) =
+            back_face_hit(V3c::new(7.5, 1.5, 1.5), V3c::new(1., 0., 0.)).unwrap();
+        assert!(data == 7 | 0xFF000000);
+        assert!((point - V3c::new(8., 1.5, 1.5)).length() < 0.01);
+    }
+
+    #[test]
+    fn test_get_by_ray_with_context() {
+        let mut rng = StdRng::seed_from_u64(seed("test_get_by_ray_with_context"));
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        let mut other_tree = Octree::<u32>::new(4).ok().unwrap();
+        for _ in 0..40 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, rng.gen_range(1..5) | 0xFF000000)
+                .ok()
+                .unwrap();
+        }
+        other_tree
+            .insert(&V3c::new(1, 1, 1), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        // The same context can be used for any number of rays and trees
+        let mut context = RayContext::new();
+        for _ in 0..100 {
+            let ray = Ray {
+                origin: V3c::new(
+                    rng.gen_range(-8.0..24.0),
+                    rng.gen_range(-8.0..24.0),
+                    rng.gen_range(-8.0..24.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+            };
+            assert!(tree.get_by_ray_with(&mut context, &ray) == tree.get_by_ray(&ray));
+            assert!(other_tree.get_by_ray_with(&mut context, &ray) == other_tree.get_by_ray(&ray));
+        }
+    }
+
+    #[test]
+    fn test_get_by_ray_with_footprint() {
+        let mut tree = Octree::<u32, 1>::new(16).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(9, 9, 9), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let cast = |origin: V3c<f32>, footprint| {
+            tree.get_by_ray_with_options(
+                &Ray {
+                    origin,
+                    direction: V3c::new(0., 0., 1.),
+                },
+                &RayOptions {
+                    footprint,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // A thin ray passes by the voxel, a wide one hits the smallest node around it it is wider than
+        let passing = V3c::new(1.5, 1.5, -4.);
+        assert!(cast(passing, None) == HitOrBudgetExceeded::Miss);
+        let wide = RayFootprint {
+            width: 2.,
+            spread: 0.,
+        };
+        assert!(matches!(
+            cast(passing, Some(wide)),
+            HitOrBudgetExceeded::Hit((data, point, normal))
+                if *data == 5 | 0xFF000000
+                    && (point - V3c::new(1.5, 1.5, 0.)).length() < 0.01
+                    && (normal - V3c::new(0., 0., -1.)).length() < 0.01
+        ));
+
+        // The footprint widens along the ray, so only distant nodes are hit as a whole
+        let widening = RayFootprint {
+            width: 0.,
+            spread: 0.5,
+        };
+        assert!(matches!(
+            cast(passing, Some(widening)),
+            HitOrBudgetExceeded::Hit((data, _, _)) if *data == 5 | 0xFF000000
+        ));
+        assert!(cast(V3c::new(1.5, 1.5, -1.), Some(widening)) == HitOrBudgetExceeded::Miss);
+
+        // Rays hitting voxels before getting wider, than them hit them the same regardless of the footprint
+        let through = V3c::new(9.5, 9.5, 8.2);
+        assert!(cast(through, Some(widening)) == cast(through, None));
+
+        // Empty space is not hit even by the widest rays
+        assert!(
+            cast(
+                V3c::new(4.5, 12.5, 0.5),
+                Some(RayFootprint {
+                    width: 4.,
+                    spread: 0.
+                })
+            ) == HitOrBudgetExceeded::Miss
+        );
+    }
+
+    #[test]
+    fn test_get_by_ray_in_deep_tree() {
+        // The traversal keeps the nodes the ray is inside of in a fixed size stack, without allocating
+        let size = 1 << 10;
+        let mut tree = Octree::<u32, 1>::new(size).ok().unwrap();
+        let voxel = V3c::new(size - 2, 1, size - 3);
+        tree.insert(&voxel, 5 | 0xFF000000).ok().unwrap();
+        let ray = Ray {
+            origin: V3c::new(voxel.x as f32 + 0.5, size as f32 + 4., voxel.z as f32 + 0.5),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let hit = tree.get_by_ray_owned(&ray).unwrap();
+        assert!(hit.data == 5 | 0xFF000000);
+        assert!(hit.voxel == voxel);
+
+        // The voxel is a leaf at depth 10, below every level of the tree
+        let limited = |max_depth| {
+            tree.get_by_ray_with_options(
+                &ray,
+                &RayOptions {
+                    max_node_visits: None,
+                    max_depth: Some(max_depth),
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(matches!(limited(10), HitOrBudgetExceeded::Hit(_)));
+        assert!(limited(9) == HitOrBudgetExceeded::BudgetExceeded);
+    }
+
+    #[test]
+    fn test_get_by_ray_with_auxiliary_data() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(3, 3, 3), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let ray = Ray {
+            origin: V3c::new(3.5, 3.5, -1.),
+            direction: V3c::new(0., 0., 1.),
+        };
+        assert!(tree.get_by_ray_owned(&ray).unwrap().auxiliary.is_none());
+
+        let value = VoxelAux {
+            motion: V3c::new(1., 0., 0.),
+            id: 7,
+        };
+        tree.set_auxiliary(&V3c::new(3, 3, 3), value).ok().unwrap();
+        assert!(tree.get_by_ray_owned(&ray).unwrap().auxiliary == Some(value));
+        assert!(tree.get_by_world_ray(&ray).unwrap().auxiliary == Some(value));
+    }
+
+    #[test]
+    fn test_get_by_ray_with_kernel() {
+        let (glass, stone) = (1 | 0x80000000, 2 | 0xFF000000);
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(2, 3, 3), glass).ok().unwrap();
+        tree.insert(&V3c::new(5, 3, 3), stone).ok().unwrap();
+        let ray = Ray {
+            origin: V3c::new(-1., 3.5, 3.5),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(tree.get_by_ray_with_kernel(&ray, &mut DefaultKernel) == tree.get_by_ray(&ray));
+        assert!(*tree.get_by_ray(&ray).unwrap().0 == glass);
+
+        // Rays can look through the voxels the kernel doesn't accept
+        struct SeeThrough(u32);
+        impl TraversalKernel<u32> for SeeThrough {
+            fn accepts(&mut self, data: &u32) -> bool {
+                !data.is_empty() && *data != self.0
+            }
+            fn hit_cell(&mut self, _ray: &Ray, _data: &u32, _cell: &Cube) -> Option<CellHit> {
+                Some(CellHit::Entry)
+            }
+        }
+        let (data, point, normal) = tree
+            .get_by_ray_with_kernel(&ray, &mut SeeThrough(glass))
+            .unwrap();
+        assert!(*data == stone);
+        assert!((point - V3c::new(5., 3.5, 3.5)).length() < 0.001);
+        assert!(normal == V3c::new(-1., 0., 0.));
+
+        // Voxels can be traced as the spheres inscribed in their cells
+        struct Spheres;
+        impl TraversalKernel<u32> for Spheres {
+            fn accepts(&mut self, data: &u32) -> bool {
+                !data.is_empty()
+            }
+            fn hit_cell(&mut self, ray: &Ray, _data: &u32, cell: &Cube) -> Option<CellHit> {
+                let sphere = Sphere {
+                    center: V3c::from(cell.min_position) + V3c::unit(cell.size as f32 / 2.),
+                    radius: cell.size as f32 / 2.,
+                };
+                let distance = sphere.intersect_ray(ray)?;
+                Some(CellHit::At {
+                    distance,
+                    normal: (ray.point_at(distance) - sphere.center).normalized(),
+                })
+            }
+        }
+        let (_, point, _) = tree.get_by_ray_with_kernel(&ray, &mut Spheres).unwrap();
+        assert!((point - V3c::new(2., 3.5, 3.5)).length() < 0.001);
+        let grazing_ray = Ray {
+            origin: V3c::new(-1., 3.05, 3.05),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(tree.get_by_ray(&grazing_ray).is_some());
+        assert!(tree
+            .get_by_ray_with_kernel(&grazing_ray, &mut Spheres)
+            .is_none());
+
+        // Kernels may gather what the ray passes through without stopping it
+        struct Density(u32);
+        impl TraversalKernel<u32> for Density {
+            fn accepts(&mut self, data: &u32) -> bool {
+                self.0 += (!data.is_empty()) as u32;
+                false
+            }
+            fn hit_cell(&mut self, _ray: &Ray, _data: &u32, _cell: &Cube) -> Option<CellHit> {
+                None
+            }
+        }
+        let mut density = Density(0);
+        assert!(tree.get_by_ray_with_kernel(&ray, &mut density).is_none());
+        assert!(density.0 == 2);
+    }
+
+    #[test]
+    fn test_ray_intervals() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        let (a, b) = (5 | 0xFF000000, 6 | 0xFF000000);
+        for (x, data) in [(1, a), (2, a), (3, b), (5, a), (6, a)] {
+            tree.insert(&V3c::new(x, 1, 1), data).ok().unwrap();
+        }
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, b).ok().unwrap();
+        let intervals = |origin: V3c<f32>, direction: V3c<f32>| {
+            tree.ray_intervals(&Ray { origin, direction })
+                .into_iter()
+                .map(|(enter, exit, data)| (enter, exit, *data))
+                .collect::<Vec<_>>()
+        };
+        let matches = |result: Vec<(f32, f32, u32)>, expected: &[(f32, f32, u32)]| {
+            result.len() == expected.len()
+                && result.iter().zip(expected.iter()).all(|(r, e)| {
+                    (r.0 - e.0).abs() < 0.001 && (r.1 - e.1).abs() < 0.001 && r.2 == e.2
+                })
+        };
+
+        // Neighbouring voxels with the same data are merged, different data splits the interval
+        assert!(matches(
+            intervals(V3c::new(-1., 1.5, 1.5), V3c::new(1., 0., 0.)),
+            &[(2., 4., a), (4., 5., b), (6., 8., a)]
+        ));
+        assert!(matches(
+            intervals(V3c::new(9., 1.5, 1.5), V3c::new(-1., 0., 0.)),
+            &[(2., 4., a), (5., 6., b), (6., 8., a)]
+        ));
+
+        // Rays starting inside a voxel start with an interval from their origin
+        assert!(matches(
+            intervals(V3c::new(2.5, 1.5, 1.5), V3c::new(1., 0., 0.)),
+            &[(0., 0.5, a), (0.5, 1.5, b), (2.5, 4.5, a)]
+        ));
+        assert!(intervals(V3c::new(-1., 4.5, 1.5), V3c::new(1., 0., 0.)).is_empty());
+
+        // Uniform regions are one interval, from either direction
+        assert!(matches(
+            intervals(V3c::new(-1., 5.5, 5.5), V3c::new(1., 0., 0.)),
+            &[(5., 9., b)]
+        ));
+        assert!(matches(
+            intervals(V3c::new(6.5, 6.5, 6.5), V3c::new(0., -1., 0.)),
+            &[(0., 2.5, b)]
+        ));
+
+        // The first interval starts at the hit of the ray, and the intervals follow each other along the ray
+        let mut rng = StdRng::seed_from_u64(seed("test_ray_intervals"));
+        for _ in 0..50 {
+            tree.insert(
+                &V3c::new(
+                    rng.gen_range(0..8),
+                    rng.gen_range(0..8),
+                    rng.gen_range(0..8),
+                ),
+                rng.gen_range(1..4) | 0xFF000000,
+            )
+            .ok()
+            .unwrap();
+        }
+        for _ in 0..100 {
+            let ray = Ray {
+                origin: V3c::new(rng.gen_range(-4.0..12.0), rng.gen_range(-4.0..12.0), -4.),
+                direction: V3c::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 1.)
+                    .normalized(),
+            };
+            let intervals = tree.ray_intervals(&ray);
+            match tree.get_by_ray_owned(&ray) {
+                Some(hit) => {
+                    assert!((intervals[0].0 - hit.distance).abs() < 0.001);
+                    assert!(*intervals[0].2 == hit.data);
+                }
+                None => assert!(intervals.is_empty()),
+            }
+            for (interval, next) in intervals.iter().zip(intervals.iter().skip(1)) {
+                assert!(interval.0 < interval.1);
+                assert!(interval.1 <= next.0 + 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bake_probe_grid() {
+        // Without voxels every probe sees only the sky
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        let grid = bake_probe_grid(&tree, 4);
+        assert!(grid.spacing() == 4);
+        assert!(grid.probe_count() == 2);
+        assert!(grid.probe_position(&V3c::new(1, 0, 1)) == V3c::new(6.5, 2.5, 6.5));
+        for face in grid.sample(&V3c::new(3., 5., 1.)).faces {
+            assert!(face.iter().all(|channel| (channel - 1.).abs() < 0.001));
+        }
+
+        // The floor blocks the sky from below
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.insert(&V3c::new(6, 6, 6), 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let grid = bake_probe_grid(&tree, 4);
+        let probe = grid.probe(&V3c::new(0, 0, 0)).unwrap();
+        assert!(probe.face(BoxFace::NegativeY)[0] < probe.face(BoxFace::PositiveY)[0]);
+        assert!((probe.face(BoxFace::PositiveY)[0] - 1.).abs() < 0.001);
+        assert!(probe.irradiance(&V3c::new(0., 1., 0.)) == probe.face(BoxFace::PositiveY));
+        assert!(probe.irradiance(&V3c::new(0., -1., 0.)) == probe.face(BoxFace::NegativeY));
+
+        // Probes inside solid voxels are left out from sampling
+        assert!(grid.probe(&V3c::new(1, 1, 1)).is_none());
+        assert!(grid.probe(&V3c::new(2, 0, 0)).is_none());
+        let sampled = grid.sample(&V3c::new(6.5, 6.5, 6.5));
+        assert!(sampled.faces.iter().flatten().all(|channel| 0. < *channel));
+        assert!(grid.sample(&V3c::new(2.5, 2.5, 2.5)) == *probe);
+    }
+
+    #[test]
+    fn test_bit_octree_is_occluded() {
+        let mut rng = StdRng::seed_from_u64(seed("test_bit_octree_is_occluded"));
+        let mut tree = Octree::<u32, 4>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(8, 0, 8), 8, 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+        for _ in 0..100 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, 5 | 0xFF000000).ok().unwrap();
+        }
+        let occupancy = tree.to_occupancy();
+
+        // The occluded rays agree with the hits of the tree
+        for _ in 0..100 {
+            let ray = Ray {
+                origin: V3c::new(
+                    rng.gen_range(-8.0..24.0),
+                    rng.gen_range(-8.0..24.0),
+                    rng.gen_range(-8.0..24.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+            };
+            match tree.get_by_ray(&ray) {
+                Some((_, impact_point, _)) => {
+                    let distance = (impact_point - ray.origin).length();
+                    assert!(occupancy.is_occluded(&ray, distance + 0.01));
+                    assert!(distance < 0.01 || !occupancy.is_occluded(&ray, distance - 0.01));
+                }
+                None => assert!(!occupancy.is_occluded(&ray, f32::INFINITY)),
+            }
+        }
+
+        // Rays starting inside an occupied voxel are occluded right away
+        let ray = Ray {
+            origin: V3c::new(12.5, 1.5, 12.5),
+            direction: V3c::new(0., 1., 0.),
+        };
+        assert!(occupancy.is_occluded(&ray, 0.));
+    }
+}
+
+#[cfg(all(test, feature = "cpu_render"))]
+mod cpu_render_tests {
+    use crate::octree::raytracing::{
+        AccumulationBuffer, AdaptiveRendering, Camera, CpuRenderBackend, Denoiser,
+        FrameCoherenceCache, RayContext, RayFootprint, TileOrder, ToneMapping, VoxelRenderBackend,
+    };
+    use crate::octree::{Cube, Octree, OverlayOctree, V3c};
+    use crate::spatial::raytracing::Ray;
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_traversal_heatmap() {
+        let mut rng = StdRng::seed_from_u64(seed("test_traversal_heatmap"));
+        let mut tree = Octree::<u32, 2>::new(32).ok().unwrap();
+        for _ in 0..200 {
+            let position = V3c::new(
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+            );
+            tree.insert(&position, 5 | 0xFF000000).ok().unwrap();
+        }
+        // The tree covers the middle of the view, the rays at its edges miss it
+        let camera = Camera {
+            origin: V3c::new(16., 16., -100.),
+            direction: V3c::new(0., 0., 1.),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let (width, height) = (24, 24);
+        let heatmap = tree.render_traversal_heatmap(&camera, width, height);
+        assert_eq!(heatmap.counters.len(), (width * height) as usize);
+        assert_eq!(heatmap.counters[0].steps(), 0);
+        assert!(0 < heatmap.counters[(12 * width + 12) as usize].steps());
+
+        // Every pixel costs as much as tracing its ray on its own
+        let mut context = RayContext::new();
+        for (index, counters) in heatmap.counters.iter().enumerate() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            context.reset_counters();
+            tree.get_by_ray_with(&mut context, &camera.ray_for(x, y, width, height));
+            assert_eq!(context.counters(), *counters);
+        }
+
+        // Pixels without any steps are black, the most expensive ones white
+        let image = heatmap.to_rgba(None);
+        assert_eq!(image.len(), (width * height * 4) as usize);
+        assert_eq!(image[0..4], [0, 0, 0, 255]);
+        let max_steps = heatmap.max_steps();
+        let most_expensive = heatmap
+            .counters
+            .iter()
+            .position(|counters| counters.steps() == max_steps)
+            .unwrap();
+        assert_eq!(
+            image[(most_expensive * 4)..(most_expensive * 4 + 4)],
+            [255, 255, 255, 255]
+        );
+        assert_eq!(
+            heatmap.total_steps(),
+            heatmap
+                .counters
+                .iter()
+                .map(|counters| counters.steps())
+                .sum::<u64>()
+        );
+
+        // With a shared scale, the more expensive frame is brighter
+        let empty_heatmap = Octree::<u32, 2>::new(32)
+            .ok()
+            .unwrap()
+            .render_traversal_heatmap(&camera, width, height);
+        assert!(empty_heatmap.total_steps() < heatmap.total_steps());
+        let brightness = |image: Vec<u8>| image.iter().map(|channel| *channel as u64).sum::<u64>();
+        assert!(
+            brightness(empty_heatmap.to_rgba(Some(max_steps)))
+                < brightness(heatmap.to_rgba(Some(max_steps)))
+        );
+    }
+
+    #[test]
+    fn test_background_tiles() {
+        let mut rng = StdRng::seed_from_u64(seed("test_background_tiles"));
+        let mut tree = Octree::<u32, 2>::new(32).ok().unwrap();
+        for _ in 0..20 {
+            let position = V3c::new(
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+            );
+            tree.insert(&position, 5 | 0xFF000000).ok().unwrap();
+        }
+        let occupied = tree.occupied_bounds_down_to(2);
+        let resolution = (32, 24);
+        let mut background_tiles = 0;
+        for _ in 0..20 {
+            let camera = Camera {
+                origin: V3c::new(
+                    rng.gen_range(-32.0..64.0),
+                    rng.gen_range(-32.0..64.0),
+                    rng.gen_range(-32.0..64.0),
+                ),
+                direction: V3c::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalized(),
+                size: (4., 3.),
+                fov: 3.,
+            };
+            // No ray through a tile showing only the background hits anything
+            for tile in TileOrder::Scanline.tiles(resolution.0, resolution.1, 8) {
+                if !camera.misses_all(tile, resolution, &occupied) {
+                    continue;
+                }
+                background_tiles += 1;
+                for y in tile.1..(tile.1 + tile.3) {
+                    for x in tile.0..(tile.0 + tile.2) {
+                        let ray = camera.ray_for(x, y, resolution.0, resolution.1);
+                        assert!(tree.get_by_ray(&ray).is_none());
+                    }
+                }
+            }
+            // Rendering the image tile by tile gives the same result
+            assert!(
+                tree.render_viewport_tiled(
+                    &camera,
+                    resolution,
+                    8,
+                    TileOrder::Scanline,
+                    &ToneMapping::default(),
+                    |_| {}
+                ) == tree.render_viewport(&camera, resolution.0, resolution.1, None)
+            );
+        }
+        assert!(0 < background_tiles);
+
+        // Looking away from the tree, every tile shows the background
+        let camera = Camera {
+            origin: V3c::new(16., 16., -4.),
+            direction: V3c::new(0., 0., -1.),
+            size: (4., 3.),
+            fov: 3.,
+        };
+        assert!(TileOrder::Scanline
+            .tiles(resolution.0, resolution.1, 8)
+            .into_iter()
+            .all(|tile| camera.misses_all(tile, resolution, &occupied)));
+
+        // Looking at the tree, the tiles in front of it do not
+        let camera = Camera {
+            direction: V3c::new(0., 0., 1.),
+            ..camera
+        };
+        let root = [Cube::new(V3c::new(0, 0, 0), 32)];
+        assert!(!camera.misses_all((12, 8, 8, 8), resolution, &root));
+        assert!(!camera.misses_all((0, 0, 32, 24), resolution, &root));
+    }
+
+    #[test]
+    fn test_camera_footprint() {
+        let camera = Camera {
+            origin: V3c::new(0., 0., 0.),
+            direction: V3c::new(0., 0., 1.),
+            size: (4., 2.),
+            fov: 2.,
+        };
+        // A pixel of the 8x8 image is 0.5 wide on the glass, 2 away from the origin
+        let footprint = camera.footprint_for(8, 8);
+        assert!(footprint.width == 0.);
+        assert!((footprint.spread - 0.25).abs() < 0.0001);
+        assert!((footprint.width_at(8.) - 2.).abs() < 0.0001);
+
+        // Neighbouring rays are as far apart as the footprint is wide
+        let distance = 10.;
+        let gap = (camera.ray_for(3, 4, 8, 8).point_at(distance)
+            - camera.ray_for(4, 4, 8, 8).point_at(distance))
+        .length();
+        assert!((gap - footprint.width_at(distance)).abs() < 0.1);
+        assert!(
+            RayFootprint {
+                width: 1.,
+                spread: 1.
+            }
+            .width_at(-1.)
+                == 1.
+        );
+    }
+
+    #[test]
+    fn test_render_viewport_with_frame_coherence_cache() {
+        let tree_size = 8;
+        let mut tree = Octree::<u32, 2>::new(tree_size).ok().unwrap();
+        for x in 0..tree_size {
+            for z in 0..tree_size {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.insert(&V3c::new(3, 1, 3), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let (width, height) = (32, 32);
+        let reference = tree.render_viewport(&camera, width, height, None);
+        let mut cache = FrameCoherenceCache::new(width, height);
+        for _ in 0..3 {
+            assert!(reference == tree.render_viewport(&camera, width, height, Some(&mut cache)));
+        }
+        assert!(cache.entries.iter().any(|entry| entry.is_some()));
+    }
+
+    #[test]
+    fn test_frame_coherence_cache_with_moving_camera() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        for y in 1..6 {
+            tree.insert(&V3c::new(4, y, 4), 6 | 0xFF000000)
+                .ok()
+                .unwrap();
+        }
+
+        // The column moves in front of floor voxels cached in earlier frames as the camera orbits around it
+        let (width, height) = (32, 32);
+        let mut cache = FrameCoherenceCache::new(width, height);
+        let camera_at = |angle: f32| {
+            let origin = V3c::new(4. + 10. * angle.cos(), 6., 4. + 10. * angle.sin());
+            Camera {
+                origin,
+                direction: (V3c::new(4., 1., 4.) - origin).normalized(),
+                size: (4., 4.),
+                fov: 3.,
+            }
+        };
+        for frame in 0..24 {
+            let camera = camera_at(frame as f32 * 0.15);
+            assert!(
+                tree.render_viewport(&camera, width, height, None)
+                    == tree.render_viewport(&camera, width, height, Some(&mut cache))
+            );
+        }
+
+        // Voxels added in front of the cached hits are not missed either
+        let camera = camera_at(0.);
+        tree.render_viewport(&camera, width, height, Some(&mut cache));
+        for y in 1..4 {
+            tree.insert(&V3c::new(6, y, 4), 7 | 0xFF000000)
+                .ok()
+                .unwrap();
+        }
+        assert!(
+            tree.render_viewport(&camera, width, height, None)
+                == tree.render_viewport(&camera, width, height, Some(&mut cache))
+        );
+    }
+
+    #[test]
+    fn test_overlay_composited_over_tree() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let mut overlay = OverlayOctree::<u32, 2>::new(2, V3c::new(2, 0, 2))
+            .ok()
+            .unwrap();
+        overlay
+            .insert(&V3c::new(3, 1, 3), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+        assert!(overlay.insert(&V3c::new(5, 1, 3), 6).is_err());
+        assert!(overlay
+            .get(&V3c::new(3, 1, 3))
+            .is_some_and(|v| *v == 6 | 0xFF000000));
+
+        // The overlay is hit in front of the tree, the tree is hit where the overlay is empty
+        let ray = Ray {
+            origin: V3c::new(3.5, 5., 3.5),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let hit = tree.get_by_ray_with_overlay(&ray, &overlay).unwrap();
+        assert!(hit.data == 6 | 0xFF000000);
+        assert!(hit.voxel == V3c::new(3, 1, 3));
+        assert!((hit.point - V3c::new(3.5, 2., 3.5)).length() < 0.001);
+        let ray = Ray {
+            origin: V3c::new(2.5, 5., 2.5),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let hit = tree.get_by_ray_with_overlay(&ray, &overlay).unwrap();
+        assert!(hit.data == 5 | 0xFF000000);
+        assert!(hit.voxel == V3c::new(2, 0, 2));
+
+        // Moving the overlay moves its contents with it
+        overlay.set_offset(V3c::new(4, 0, 4));
+        let hit = tree.get_by_ray_with_overlay(&ray, &overlay).unwrap();
+        assert!(hit.data == 5 | 0xFF000000);
+
+        // The tree is not changed by the overlay, it renders only when composited
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let tone_mapping = ToneMapping::default();
+        let reference = tree.render_viewport_with(&camera, 16, 16, None, &tone_mapping);
+        let empty_overlay = OverlayOctree::<u32, 2>::new(2, V3c::new(4, 0, 4))
+            .ok()
+            .unwrap();
+        assert!(
+            reference
+                == tree.render_viewport_with_overlay(
+                    &camera,
+                    16,
+                    16,
+                    &empty_overlay,
+                    &tone_mapping
+                )
+        );
+        assert!(
+            reference
+                != tree.render_viewport_with_overlay(&camera, 16, 16, &overlay, &tone_mapping)
+        );
+        assert!(tree.get(&V3c::new(5, 1, 5)).is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "used with object pool")]
+    fn test_frame_coherence_cache_used_with_other_tree() {
+        let mut tree = Octree::<u32>::new(4).ok().unwrap();
+        let mut other_tree = Octree::<u32>::new(4).ok().unwrap();
+        for x in 0..4 {
+            for z in 0..4 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+                other_tree
+                    .insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+
+        let origin = V3c::new(6., 6., 6.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(2.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let mut cache = FrameCoherenceCache::new(8, 8);
+        tree.render_viewport(&camera, 8, 8, Some(&mut cache));
+        other_tree.render_viewport(&camera, 8, 8, Some(&mut cache));
+    }
+
+    #[test]
+    fn test_cpu_render_backend() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let mut backend = CpuRenderBackend::new(16, 16);
+        assert!(backend
+            .render(&camera)
+            .chunks_exact(4)
+            .all(|p| p == [128, 128, 128, 255]));
+
+        backend.upload_nodes(&tree);
+        backend.upload_bricks(&tree);
+        assert!(backend.render(&camera) == tree.render_viewport(&camera, 16, 16, None));
+
+        // Changes of the tree are only visible after they are uploaded
+        let before = backend.render(&camera);
+        tree.insert(&V3c::new(4, 4, 4), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+        assert!(before == backend.render(&camera));
+        backend.upload_nodes(&tree);
+        backend.upload_bricks(&tree);
+        assert!(before != backend.render(&camera));
+        assert!(backend.render(&camera) == tree.render_viewport(&camera, 16, 16, None));
+    }
+
+    #[test]
+    fn test_render_viewport_tiled() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), (x * 30 + z * 8) | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.insert(&V3c::new(4, 4, 4), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let tone_mapping = ToneMapping::default();
+        let (width, height) = (37, 29);
+        let reference = tree.render_viewport_with(&camera, width, height, None, &tone_mapping);
+        for order in [TileOrder::Scanline, TileOrder::Morton, TileOrder::Spiral] {
+            let mut tiles = Vec::new();
+            let image = tree.render_viewport_tiled(
+                &camera,
+                (width, height),
+                8,
+                order,
+                &tone_mapping,
+                |tile| tiles.push(tile.clone()),
+            );
+            assert!(image == reference);
+
+            // Every pixel is covered by exactly one tile, and tiles are handed over in the given order
+            let expected_order = order.tiles(width, height, 8);
+            assert!(
+                tiles
+                    .iter()
+                    .map(|t| (t.x, t.y, t.width, t.height))
+                    .collect::<Vec<_>>()
+                    == expected_order
+            );
+            assert!(tiles.iter().map(|t| t.width * t.height).sum::<u32>() == width * height);
+            for tile in tiles.iter() {
+                assert!(tile.pixels.len() == (tile.width * tile.height * 4) as usize);
+                let start = ((tile.y * width + tile.x) * 4) as usize;
+                assert!(tile.pixels[..4] == reference[start..(start + 4)]);
+            }
+        }
+
+        // Spiral order starts from the center of the image
+        let (x, y, tile_width, tile_height) = TileOrder::Spiral.tiles(width, height, 8)[0];
+        assert!((x..(x + tile_width)).contains(&(width / 2)));
+        assert!((y..(y + tile_height)).contains(&(height / 2)));
+        assert!(
+            TileOrder::Morton.tiles(16, 16, 8)[..3] == [(0, 0, 8, 8), (8, 0, 8, 8), (0, 8, 8, 8)]
+        );
+    }
+
+    #[test]
+    fn test_render_viewport_adaptive() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), (x * 30 + z * 8) | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.insert(&V3c::new(4, 4, 4), 7 | 0xFF000000)
+            .ok()
+            .unwrap();
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let tone_mapping = ToneMapping::default();
+        let (width, height) = (37, 29);
+        let reference = tree.render_viewport_with(&camera, width, height, None, &tone_mapping);
+        let pixel = |image: &[u8], x: u32, y: u32| {
+            let start = ((y * width + x) * 4) as usize;
+            image[start..(start + 4)].to_vec()
+        };
+
+        // Refining every tile gives the full resolution image
+        let refine_all = AdaptiveRendering {
+            color_variance: -1.,
+            depth_variance: -1.,
+            max_refined_tiles: None,
+        };
+        let image =
+            tree.render_viewport_adaptive(&camera, (width, height), 8, &refine_all, &tone_mapping);
+        assert!(image == reference);
+
+        // Without refining, every 2x2 block takes the color of its top-left pixel
+        let coarse = tree.render_viewport_adaptive(
+            &camera,
+            (width, height),
+            8,
+            &AdaptiveRendering {
+                max_refined_tiles: Some(0),
+                ..refine_all
+            },
+            &tone_mapping,
+        );
+        for y in 0..height {
+            for x in 0..width {
+                assert!(pixel(&coarse, x, y) == pixel(&reference, x - x % 2, y - y % 2));
+            }
+        }
+        assert!(coarse != reference);
+
+        // Refined tiles match the full resolution image, the others stay coarse
+        let image = tree.render_viewport_adaptive(
+            &camera,
+            (width, height),
+            8,
+            &AdaptiveRendering::default(),
+            &tone_mapping,
+        );
+        let mut refined_tiles = 0;
+        for (x, y, tile_width, tile_height) in TileOrder::Scanline.tiles(width, height, 8) {
+            let tile_pixels = |image: &[u8]| {
+                (y..(y + tile_height))
+                    .flat_map(|py| (x..(x + tile_width)).map(move |px| (px, py)))
+                    .map(|(px, py)| pixel(image, px, py))
+                    .collect::<Vec<_>>()
+            };
+            let tile = tile_pixels(&image);
+            assert!(tile == tile_pixels(&reference) || tile == tile_pixels(&coarse));
+            if tile != tile_pixels(&coarse) {
+                refined_tiles += 1;
+            }
+        }
+        assert!(0 < refined_tiles);
+
+        // The budget limits the number of refined tiles
+        let image = tree.render_viewport_adaptive(
+            &camera,
+            (width, height),
+            8,
+            &AdaptiveRendering {
+                max_refined_tiles: Some(1),
+                ..refine_all
+            },
+            &tone_mapping,
+        );
+        let differing_tiles = TileOrder::Scanline
+            .tiles(width, height, 8)
+            .into_iter()
+            .filter(|&(x, y, tile_width, tile_height)| {
+                (y..(y + tile_height)).any(|py| {
+                    (x..(x + tile_width)).any(|px| pixel(&image, px, py) != pixel(&coarse, px, py))
+                })
+            })
+            .count();
+        assert!(differing_tiles <= 1);
+    }
+
+    #[test]
+    fn test_accumulate_viewport() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), (x * 30 + z * 8) | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let tone_mapping = ToneMapping::default();
+        let (width, height) = (21, 17);
+        let mut buffer = AccumulationBuffer::new(width, height);
+        assert!(buffer.resolve(None, &tone_mapping) == [128, 128, 128, 255].repeat(21 * 17));
+
+        // The first sample goes through the center of the pixels, just like in the rendered image
+        tree.accumulate_viewport(&camera, &mut buffer);
+        assert!(
+            buffer.resolve(None, &tone_mapping)
+                == tree.render_viewport_with(&camera, width, height, None, &tone_mapping)
+        );
+
+        // Further samples update the averages
+        for _ in 0..3 {
+            tree.accumulate_viewport(&camera, &mut buffer);
+        }
+        assert!(buffer.sample_count().iter().all(|&count| count == 4));
+        assert!(buffer.color().len() == (width * height) as usize);
+        for (albedo, normal) in buffer.albedo().iter().zip(buffer.normal().iter()) {
+            assert!(albedo.iter().all(|channel| (0. ..=1.).contains(channel)));
+            assert!(normal.length() <= 1.001);
+        }
+        assert!(buffer.albedo().iter().any(|albedo| albedo[0] > 0.));
+        assert!(buffer
+            .normal()
+            .iter()
+            .any(|normal| *normal == V3c::unit(0.)));
+
+        // The denoiser provides the colors to display
+        struct Flat {
+            calls: u32,
+        }
+        impl Denoiser for Flat {
+            fn denoise(&mut self, buffer: &AccumulationBuffer) -> Vec<[f32; 3]> {
+                self.calls += 1;
+                vec![[1., 0., 0.]; buffer.color().len()]
+            }
+        }
+        let mut denoiser = Flat { calls: 0 };
+        assert!(
+            buffer.resolve(Some(&mut denoiser), &tone_mapping) == [255, 0, 0, 255].repeat(21 * 17)
+        );
+        assert!(denoiser.calls == 1);
+
+        buffer.reset();
+        assert!(buffer.sample_count().iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_voxels_in_screen_rect() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), 5 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.insert(&V3c::new(4, 1, 4), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        // Looking down at the floor, every selected voxel is on top of the floor or the single voxel above it
+        let origin = V3c::new(4.5, 12., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::new(4.5, 0., 4.5) - origin).normalized(),
+            size: (4., 4.),
+            fov: 2.,
+        };
+        let whole = tree.voxels_in_screen_rect(&camera, (0, 0, 64, 64), (64, 64));
+        assert!(whole.contains(&V3c::new(4, 1, 4)));
+        assert!(whole.iter().all(|v| v.y == 0 || *v == V3c::new(4, 1, 4)));
+        assert!(!whole.contains(&V3c::new(4, 0, 4)));
+        for (i, v) in whole.iter().enumerate() {
+            assert!(!whole[i + 1..].contains(v));
+        }
+
+        let part = tree.voxels_in_screen_rect(&camera, (0, 0, 32, 32), (64, 64));
+        assert!(!part.is_empty() && part.len() < whole.len());
+        assert!(part.iter().all(|v| whole.contains(v)));
+        assert!(tree
+            .voxels_in_screen_rect(&camera, (64, 64, 10, 10), (64, 64))
+            .is_empty());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_render_to_png() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 0, z), (x * 30) | (z * 30) << 8 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        let origin = V3c::new(12., 10., 12.);
+        let camera = Camera {
+            origin,
+            direction: (V3c::unit(4.) - origin).normalized(),
+            size: (4., 4.),
+            fov: 3.,
+        };
+        let path = std::env::temp_dir().join("shocovox_test_render_to_png.png");
+        let path = path.to_str().unwrap();
+        tree.render_to_png(path, &camera, (24, 16)).ok().unwrap();
+
+        // The saved image matches the rendered viewport pixel by pixel
+        let saved = image::open(path).ok().unwrap().into_rgba8();
+        std::fs::remove_file(path).ok().unwrap();
+        assert!(saved.dimensions() == (24, 16));
+        assert!(saved.into_raw() == tree.render_viewport(&camera, 24, 16, None));
+
+        assert!(tree
+            .render_to_png("missing_directory/thumbnail.png", &camera, (4, 4))
+            .is_err());
+    }
+}
+
+#[cfg(all(test, feature = "cpu_render"))]
+mod color_tests {
+    use crate::octree::raytracing::color::{linear_to_srgb, reinhard, srgb_to_linear, ToneMapping};
+    use crate::octree::raytracing::Camera;
+    use crate::octree::{Octree, V3c, VoxelData};
+
+    /// A voxel emitting the given multiple of white
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Emissive(u32);
+
+    impl VoxelData for Emissive {
+        fn new(_r: u8, _g: u8, _b: u8, a: u8, _user_data: u32) -> Self {
+            Self(a as u32)
+        }
+        fn albedo(&self) -> [u8; 4] {
+            [self.0.min(255) as u8; 4]
+        }
+        fn albedo_hdr(&self) -> [f32; 4] {
+            [self.0 as f32, self.0 as f32, self.0 as f32, 1.]
+        }
+        fn user_data(&self) -> u32 {
+            0
+        }
+        fn clear(&mut self) {
+            self.0 = 0;
+        }
+    }
+
+    #[test]
+    fn test_srgb_conversion_roundtrip() {
+        assert!(0. == linear_to_srgb(0.));
+        assert!((linear_to_srgb(1.) - 1.).abs() < 0.0001);
+        // Dark linear values are brightened by the encoding
+        assert!(linear_to_srgb(0.05) > 0.2);
+        for i in 0..=255 {
+            let value = i as f32 / 255.;
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        assert!(0.5 == reinhard(1.));
+        assert!(reinhard(1000.) < 1.);
+
+        let tone_mapping = ToneMapping::default();
+        assert!(0 == tone_mapping.apply(0.));
+        assert!(255 == tone_mapping.apply(1.));
+        assert!(255 == tone_mapping.apply(5.));
+        assert!([188, 0, 255] == tone_mapping.apply_rgb([0.5, 0., 1.]));
+
+        let tone_mapping = ToneMapping {
+            exposure: 2.,
+            reinhard: true,
+        };
+        assert!(tone_mapping.apply(5.) < 255);
+        assert!(tone_mapping.apply(0.25) == ToneMapping::default().apply(0.5 / 1.5));
+    }
+
+    #[test]
+    fn test_render_hdr_albedo() {
+        assert!([1., 0., 0.2, 1.] == (0xFF3300FF_u32).albedo_hdr());
+
+        let camera = Camera {
+            origin: V3c::new(1., 1., -10.),
+            direction: V3c::new(0., 0., 1.),
+            size: (1., 1.),
+            fov: 3.,
+        };
+        let tone_mapping = ToneMapping {
+            exposure: 1.,
+            reinhard: true,
+        };
+        let render = |brightness: u32| {
+            let mut tree = Octree::<Emissive, 2>::new(2).ok().unwrap();
+            tree.insert(&V3c::new(0, 0, 0), Emissive(brightness))
+                .ok()
+                .unwrap();
+            tree.insert(&V3c::new(1, 1, 0), Emissive(brightness))
+                .ok()
+                .unwrap();
+            tree.render_viewport_with(&camera, 4, 4, None, &tone_mapping)
+        };
+
+        // Brighter voxels are not clipped at the brightness of white
+        let white = render(1);
+        let bright = render(8);
+        assert!(white.len() == bright.len());
+        let lit = white
+            .chunks_exact(4)
+            .zip(bright.chunks_exact(4))
+            .filter(|(w, _)| w[0] != 128)
+            .collect::<Vec<_>>();
+        assert!(!lit.is_empty());
+        assert!(lit.iter().all(|(w, b)| w[0] < b[0] && b[0] < 255));
+    }
 }
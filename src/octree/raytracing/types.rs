@@ -1,5 +1,8 @@
-use crate::octree::{Cube, V3c};
-use crate::spatial::raytracing::CubeRayIntersection;
+use crate::object_pool::PoolKey;
+use crate::octree::{Cube, V3c, VoxelAux};
+
+#[cfg(feature = "cpu_render")]
+use crate::object_pool::BrandedKey;
 
 #[cfg(feature = "bevy_wgpu")]
 use bevy::{
@@ -11,12 +14,284 @@ use bevy::{
     render::{color::Color, render_resource::ShaderType},
 };
 
+/// Details of a ray hit, including the leaf node containing the hit voxel
+pub(crate) struct RayHit<'a, T> {
+    pub(crate) data: &'a T,
+    pub(crate) point: V3c<f32>,
+    pub(crate) normal: V3c<f32>,
+    pub(crate) distance: f32,
+    // Only used to start the traversal of the next frame from, see `FrameCoherenceCache`
+    #[cfg_attr(not(feature = "cpu_render"), allow(dead_code))]
+    pub(crate) node: PoolKey,
+    pub(crate) bounds: Cube,
+    // The part of the leaf node the hit voxel is in: the hit cell of its brick, or the whole uniform leaf
+    pub(crate) cell: Cube,
+}
+
+/// A ray hit owning a copy of the hit data, so it can be kept across edits of the tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedRayHit<T> {
+    pub data: T,
+    pub point: V3c<f32>,
+    pub normal: V3c<f32>,
+    /// The distance of the hit point from the origin of the ray
+    pub distance: f32,
+    /// The position of the hit voxel, it stays valid after edits of the tree
+    pub voxel: V3c<u32>,
+    /// The auxiliary data stored for the hit voxel, see `Octree::set_auxiliary`
+    pub auxiliary: Option<VoxelAux>,
+}
+
+/// The result of a ray cast through the chunks of a world, see `VoxelWorld::get_by_ray`
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldRayHit<T> {
+    /// The ray hit a voxel of the given chunk; the hit point is in world space,
+    /// the position of the voxel is inside the chunk
+    Hit {
+        chunk_coord: V3c<i32>,
+        hit: OwnedRayHit<T>,
+    },
+    /// The ray reached a chunk which is not loaded at the given distance, before hitting anything
+    Unknown {
+        chunk_coord: V3c<i32>,
+        distance: f32,
+    },
+    /// The ray didn't hit anything within its range
+    Miss,
+}
+
+/// The distances from the origin of a ray, between which chunks fade from their full resolution
+/// to their coarse version, see `VoxelWorld::get_by_ray_lod`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodFade {
+    /// The distance of the center of a chunk, from which its coarse version is blended in
+    pub start: f32,
+    /// The distance of the center of a chunk, from which only its coarse version is used
+    pub end: f32,
+}
+
+/// The result of a ray cast through a world with coarse versions of its chunks, see `VoxelWorld::get_by_ray_lod`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldLodRayHit<T> {
+    /// The result of the ray in the resolution weighing more at the distance of the chunks it passed
+    pub hit: WorldRayHit<T>,
+    /// The result of the ray in the other resolution with its weight below 0.5, to be blended with `hit`,
+    /// if the ray ends in a chunk within the transition band and the two results differ
+    pub blend: Option<(WorldRayHit<T>, f32)>,
+}
+
+/// The result of a single ray in `Octree::raycast_batch`, without a reference or copy of the hit data
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RayHitCompact {
+    /// True if the ray hit anything, the other fields are only meaningful if it did
+    pub hit: bool,
+    /// The distance of the hit point from the origin of the ray
+    pub distance: f32,
+    pub normal: V3c<f32>,
+    /// The position of the hit voxel
+    pub voxel: V3c<u32>,
+}
+
+/// Limits on the work a single raycast may do, so the worst case cost of a ray can be bound
+/// e.g. with grazing rays travelling along planes of voxels
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RayOptions {
+    /// The maximum number of nodes the ray may visit, None for no limit
+    pub max_node_visits: Option<u32>,
+    /// The maximum depth of the nodes the ray may visit, the root being at depth 0; None for no limit
+    pub max_depth: Option<u32>,
+    /// What the ray does should it start inside a voxel
+    pub inside_voxel: InsideVoxelPolicy,
+    /// The area the ray stands for, None for an infinitely thin ray; see `RayFootprint`
+    pub footprint: Option<RayFootprint>,
+    /// Whether the nodes the ray is about to visit are requested into the cache ahead of time
+    pub prefetch: PrefetchPolicy,
+}
+
+/// Prefetching of the nodes during traversal, to hide the cost of cache misses in trees much larger than the cache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchPolicy {
+    /// The child the ray steps into is prefetched along with the sibling it enters after that child,
+    /// which is known from the direction of the ray. Without support on the target architecture, it is the same as `Disabled`
+    #[default]
+    LookAhead,
+    /// No prefetch hints are issued
+    Disabled,
+}
+
+/// The width of the area a ray stands for, e.g. the pixel it is cast through, widening along the ray.
+/// Details smaller, than the footprint can not be told apart, so nodes not larger, than the footprint
+/// where the ray reaches them are hit as a whole, the voxel inside them nearest to the ray standing for them.
+/// This gives smooth distant geometry at the cost of fewer nodes visited
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RayFootprint {
+    /// The width of the area at the origin of the ray
+    pub width: f32,
+    /// The increase of the width for every unit of distance along the ray
+    pub spread: f32,
+}
+
+/// What a ray starting inside a voxel does, e.g. a camera clipped into a wall.
+/// The ray is inside the voxel when its origin is inside the cube of the voxel, regardless of its shape
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InsideVoxelPolicy {
+    /// The voxel the ray starts in is hit at distance 0
+    #[default]
+    HitAtOrigin,
+    /// The voxel the ray starts in is ignored, the ray hits the first voxel after leaving it
+    SkipContaining,
+    /// The raycast fails with `HitOrBudgetExceeded::OriginInside`
+    Reject,
+    /// The ray hits where it leaves the voxels it starts inside of, i.e. their back face,
+    /// with the normal flipped to face the ray; e.g. for refraction through volumes of glass,
+    /// casting the refracted ray from just inside the voxel hit on its front face
+    HitBackFace,
+}
+
+/// Memory reused between raycasts, so casting many rays does not need to prepare it for each one.
+/// Meant to be created once for each thread casting rays, see `Octree::get_by_ray_with`
+pub struct RayContext {
+    pub(crate) node_stack: NodeStack,
+    pub(crate) counters: TraversalCounters,
+}
+
+/// The work done by the rays cast with a `RayContext`, to see where the tracer spends its time,
+/// e.g. with `Octree::render_traversal_heatmap`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TraversalCounters {
+    /// The number of nodes the rays entered, including the root nodes
+    pub node_visits: u64,
+    /// The number of cells of leaf nodes the rays stepped through
+    pub cell_steps: u64,
+}
+
+/// The result of a raycast limited by `RayOptions`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitOrBudgetExceeded<H> {
+    /// The ray hit something before reaching any of its limits
+    Hit(H),
+    /// The ray left the tree without hitting anything
+    Miss,
+    /// The ray reached one of its limits before finding a hit
+    BudgetExceeded,
+    /// The ray started inside a voxel, see `InsideVoxelPolicy::Reject`
+    OriginInside,
+}
+
+/// A pinhole camera to render the contents of an octree with
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub origin: V3c<f32>,
+    /// The direction the camera is looking at, expected to be normalized
+    pub direction: V3c<f32>,
+    /// The width and height of the viewport glass in world units
+    pub size: (f32, f32),
+    /// The distance of the viewport glass from the origin of the camera
+    pub fov: f32,
+}
+
+/// The last hit of a pixel stored in the `FrameCoherenceCache`
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CoherenceEntry {
+    pub(crate) node: BrandedKey,
+    pub(crate) bounds: Cube,
+    pub(crate) age: u32,
+}
+
+/// Stores the last hit leaf node for every pixel of a rendered frame, so the next frame can probe
+/// the previous result directly; the ray is still checked for anything in front of the cached leaf.
+/// The cache is valid only for the tree it was used with, and it needs to be invalidated
+/// whenever the structure of that tree changes, as cached nodes might be reused for other parts of it.
+/// Using the cache with another tree without invalidating it first fails a debug assertion.
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Clone)]
+pub struct FrameCoherenceCache {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) entries: Vec<Option<CoherenceEntry>>,
+    /// The number of frames a cached hit can be re-used before a full traversal is forced for the pixel
+    pub max_age: u32,
+}
+
+/// The cost of tracing the ray through every pixel of an image, rendered by `Octree::render_traversal_heatmap`
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraversalHeatmap {
+    pub width: u32,
+    pub height: u32,
+    /// The work done for each pixel, row by row from the top-left corner
+    pub counters: Vec<TraversalCounters>,
+}
+
+/// The order the tiles of an image are rendered in by `Octree::render_viewport_tiled`
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row from the top-left corner
+    #[default]
+    Scanline,
+    /// Along the Z-order curve, keeping consecutive tiles close to each other
+    Morton,
+    /// Around the center of the image outwards, so progressive previews show the center first
+    Spiral,
+}
+
+/// A rectangle of a rendered image, see `Octree::render_viewport_tiled`
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTile {
+    /// The position of the top-left pixel of the tile inside the image
+    pub x: u32,
+    pub y: u32,
+    /// The size of the tile in pixels
+    pub width: u32,
+    pub height: u32,
+    /// The RGBA8 pixels of the tile row by row
+    pub pixels: Vec<u8>,
+}
+
+/// Decides which tiles `Octree::render_viewport_adaptive` refines from quarter resolution to full resolution
+#[cfg(feature = "cpu_render")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveRendering {
+    /// Tiles are refined if the variance of the color channels of their samples, in range 0..1, is above this
+    pub color_variance: f32,
+    /// Tiles are refined if the variance of the hit distances of their samples,
+    /// relative to the square of their mean, is above this
+    pub depth_variance: f32,
+    /// The most tiles refined in a frame, the ones varying the most come first; no limit if `None`
+    pub max_refined_tiles: Option<usize>,
+}
+
+#[cfg(feature = "cpu_render")]
+impl Default for AdaptiveRendering {
+    fn default() -> Self {
+        Self {
+            color_variance: 0.001,
+            depth_variance: 0.01,
+            max_refined_tiles: None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
 pub(crate) struct NodeStackItem {
-    pub(crate) bounds_intersection: CubeRayIntersection,
     pub(crate) bounds: Cube,
-    pub(crate) node: u32,
-    pub(crate) target_octant: u32,
-    pub(crate) child_center: V3c<f32>,
+    pub(crate) node: PoolKey,
+    pub(crate) next_child: u8, // mirrored octant of the next child to visit, 8 if there are none left
+    pub(crate) entry_plane_distances: V3c<f32>, // distance along the ray to the planes it enters the bounds through
+    pub(crate) exit_plane_distances: V3c<f32>, // distance along the ray to the planes it exits the bounds through
+}
+
+/// The most nodes a ray can be inside of at once: a tree of any size has at most 32 levels
+pub(crate) const MAX_NODE_STACK_DEPTH: usize = u32::BITS as usize;
+
+/// The nodes a ray is inside of during traversal, the innermost on top.
+/// The items are stored inline, so casting a ray does not allocate memory
+pub(crate) struct NodeStack {
+    pub(crate) items: [NodeStackItem; MAX_NODE_STACK_DEPTH],
+    pub(crate) len: usize,
 }
 
 #[cfg(feature = "bevy_wgpu")]
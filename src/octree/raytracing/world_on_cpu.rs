@@ -0,0 +1,184 @@
+use crate::octree::{
+    raytracing::types::{LodFade, OwnedRayHit, WorldLodRayHit, WorldRayHit},
+    Octree, V3c, VoxelData, VoxelWorld,
+};
+use crate::spatial::raytracing::Ray;
+
+impl<T: Default + PartialEq + Clone + std::fmt::Debug + VoxelData, const DIM: usize>
+    VoxelWorld<T, DIM>
+{
+    /// Casts the given ray through the chunks of the world, visiting the chunks along it in order.
+    /// Each chunk is traversed by its own tree from the point where the ray enters it,
+    /// the distances of the hits are measured from the origin of the ray.
+    /// Chunks which are not loaded are passed through as if they were empty, unless `stop_at_unknown` is set:
+    /// then the first one the ray reaches is reported, so streamed renderers don't show unloaded chunks as air.
+    /// * `ray` - The ray to cast in world positions, its direction doesn't need to be normalized
+    /// * `max_distance` - The distance from the origin of the ray within which hits are looked for
+    /// * `stop_at_unknown` - Whether to stop at the first chunk which is not loaded, see `is_chunk_loaded`
+    pub fn get_by_ray(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        stop_at_unknown: bool,
+    ) -> WorldRayHit<T> {
+        self.cast_through_chunks(ray, max_distance, stop_at_unknown, |chunk_coord| {
+            self.chunks.get(chunk_coord)
+        })
+    }
+
+    /// Casts the given ray through the chunks of the world like `get_by_ray`, traversing the coarse version
+    /// of the chunks far from the origin of the ray instead of their full resolution, see `insert_coarse_chunk`.
+    /// The resolution is picked by the distance of the center of each chunk, so every ray of a camera
+    /// sees a chunk in the same resolution. Within the transition band both resolutions are traced,
+    /// so the result of the other one can be blended in by its weight instead of the chunk popping
+    /// from one resolution to the other. Chunks with only one of their versions present are traced in that one.
+    /// * `fade` - The distances between which chunks fade from their full resolution to their coarse version
+    pub fn get_by_ray_lod(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        stop_at_unknown: bool,
+        fade: &LodFade,
+    ) -> WorldLodRayHit<T> {
+        let coarse_weight = |chunk_coord: &V3c<i32>| {
+            let chunk_center =
+                (V3c::<f32>::from(*chunk_coord) + V3c::unit(0.5)) * self.chunk_size as f32;
+            let distance = (chunk_center - ray.origin).length();
+            if fade.end <= fade.start {
+                return (fade.end <= distance) as u8 as f32;
+            }
+            ((distance - fade.start) / (fade.end - fade.start)).clamp(0., 1.)
+        };
+        let version = |chunk_coord: &V3c<i32>, minor: bool| match (
+            self.chunks.get(chunk_coord),
+            self.coarse_chunks.get(chunk_coord),
+        ) {
+            (Some(fine), Some(coarse)) => {
+                let weight = coarse_weight(chunk_coord);
+                let use_coarse = if 0. < weight && weight < 1. {
+                    (0.5 <= weight) != minor
+                } else {
+                    1. <= weight
+                };
+                Some(if use_coarse { coarse } else { fine })
+            }
+            (fine, coarse) => fine.or(coarse),
+        };
+
+        let hit = self.cast_through_chunks(ray, max_distance, stop_at_unknown, |chunk_coord| {
+            version(chunk_coord, false)
+        });
+        let other = self.cast_through_chunks(ray, max_distance, stop_at_unknown, |chunk_coord| {
+            version(chunk_coord, true)
+        });
+        if hit == other {
+            return WorldLodRayHit { hit, blend: None };
+        }
+
+        // The two casts are the same until the first chunk in the transition band,
+        // so the one ending sooner ends in the chunk the results are blended by
+        let ending = |result: &WorldRayHit<T>| match result {
+            WorldRayHit::Hit { chunk_coord, hit } => Some((*chunk_coord, hit.distance)),
+            WorldRayHit::Unknown {
+                chunk_coord,
+                distance,
+            } => Some((*chunk_coord, *distance)),
+            WorldRayHit::Miss => None,
+        };
+        let blend_chunk = match (ending(&hit), ending(&other)) {
+            (Some((hit_chunk, hit_distance)), Some((other_chunk, other_distance))) => {
+                if hit_distance <= other_distance {
+                    hit_chunk
+                } else {
+                    other_chunk
+                }
+            }
+            (Some((chunk_coord, _)), None) | (None, Some((chunk_coord, _))) => chunk_coord,
+            (None, None) => return WorldLodRayHit { hit, blend: None },
+        };
+        let weight = coarse_weight(&blend_chunk);
+        WorldLodRayHit {
+            hit,
+            blend: Some((other, weight.min(1. - weight))),
+        }
+    }
+
+    /// Casts the given ray through the chunks of the world, traversing the version of each chunk
+    /// provided by the given function, see `get_by_ray`
+    fn cast_through_chunks<'a>(
+        &'a self,
+        ray: &Ray,
+        max_distance: f32,
+        stop_at_unknown: bool,
+        version: impl Fn(&V3c<i32>) -> Option<&'a Octree<T, DIM>>,
+    ) -> WorldRayHit<T> {
+        let direction = ray.direction.normalized();
+        let size = self.chunk_size as f32;
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction_components = [direction.x, direction.y, direction.z];
+
+        // Step through the grid of chunks along the ray, one chunk boundary at a time
+        let mut chunk = origin.map(|component| (component / size).floor() as i32);
+        let step = direction_components.map(|component| if component < 0. { -1 } else { 1 });
+        let mut next_boundary_distance = [f32::INFINITY; 3];
+        let mut boundary_distance_step = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if 0. != direction_components[axis] {
+                let boundary = (chunk[axis] + (0 < step[axis]) as i32) as f32 * size;
+                next_boundary_distance[axis] =
+                    (boundary - origin[axis]) / direction_components[axis];
+                boundary_distance_step[axis] = size / direction_components[axis].abs();
+            }
+        }
+
+        let mut distance = 0.;
+        while distance <= max_distance {
+            let chunk_coord = V3c::new(chunk[0], chunk[1], chunk[2]);
+            match version(&chunk_coord) {
+                Some(tree) => {
+                    // The chunk is traversed from the point the ray enters it, relative to the chunk,
+                    // so the precision of the traversal doesn't depend on the distance from the origin of the ray.
+                    // The origin of the chunk is subtracted first, so far from the world origin
+                    // the entry point is not rounded to the precision of world positions.
+                    // Coarse versions of the chunk are traversed scaled down to their size
+                    let scale = self.chunk_size / tree.octree_size;
+                    let chunk_origin = V3c::<f32>::from(chunk_coord * self.chunk_size as i32);
+                    let local_ray = Ray {
+                        origin: ((ray.origin - chunk_origin) + direction * distance) / scale as f32,
+                        direction,
+                    };
+                    if let Some(hit) = tree.get_by_ray_owned(&local_ray) {
+                        let hit_distance = distance + hit.distance * scale as f32;
+                        if hit_distance > max_distance {
+                            return WorldRayHit::Miss;
+                        }
+                        return WorldRayHit::Hit {
+                            chunk_coord,
+                            hit: OwnedRayHit {
+                                point: hit.point * scale as f32 + chunk_origin,
+                                distance: hit_distance,
+                                voxel: hit.voxel * scale,
+                                ..hit
+                            },
+                        };
+                    }
+                }
+                None if stop_at_unknown && !self.empty_chunks.contains(&chunk_coord) => {
+                    return WorldRayHit::Unknown {
+                        chunk_coord,
+                        distance,
+                    };
+                }
+                None => {}
+            }
+
+            let axis = (0..3)
+                .min_by(|a, b| next_boundary_distance[*a].total_cmp(&next_boundary_distance[*b]))
+                .unwrap();
+            distance = next_boundary_distance[axis];
+            chunk[axis] += step[axis];
+            next_boundary_distance[axis] += boundary_distance_step[axis];
+        }
+        WorldRayHit::Miss
+    }
+}
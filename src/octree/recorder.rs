@@ -0,0 +1,134 @@
+use crate::octree::{
+    types::{OctreeEdit, OctreeError},
+    Octree, VoxelData,
+};
+use bendy::{decoding::FromBencode, encoding::ToBencode};
+
+/// A call changing the contents or the structure of an octree, as captured by `EditRecorder`
+#[derive(Debug, Clone)]
+pub enum RecordedCall<T> {
+    /// A call of `Octree::insert_at_lod` or `Octree::clear_at_lod`, or the ones calling them
+    Edit(OctreeEdit<T>),
+    /// The value of `Octree::auto_simplify` changed before the next call
+    SetAutoSimplify(bool),
+    /// A call of `Octree::simplify_incremental`
+    SimplifyIncremental { node_budget: usize },
+}
+
+/// The sequence of successful calls changing an octree since `Octree::start_recording`,
+/// which can be replayed onto a fresh tree to reproduce the state of the recorded one,
+/// e.g. to attach to bug reports about the structure of a tree.
+/// The contents of the tree when the recording started are captured as its first edits.
+#[derive(Debug, Clone)]
+pub struct EditRecorder<T> {
+    pub(in crate::octree) octree_size: u32,
+    pub(in crate::octree) auto_simplify: bool,
+    pub(in crate::octree) calls: Vec<RecordedCall<T>>,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData> EditRecorder<T> {
+    /// The size of the recorded tree
+    pub fn octree_size(&self) -> u32 {
+        self.octree_size
+    }
+
+    /// The number of recorded calls
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// True if no calls were recorded
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// The recorded calls, in the order they were made
+    pub fn calls(&self) -> &[RecordedCall<T>] {
+        &self.calls
+    }
+
+    /// converts the recording to a compact byte representation, the voxels encoded by `DefaultVoxelDataCodec`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bencode().ok().unwrap()
+    }
+
+    /// parses the recording from a byte string
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        Self::from_bencode(&bytes).map_err(|error| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error.to_string())
+        })
+    }
+
+    /// Makes every recorded call on a new tree, in the order they were made.
+    /// Only calls which succeeded are recorded, but the tree replayed onto may still have other limits,
+    /// so calls failing during the replay don't stop it.
+    ///
+    /// output: the tree after the replay, and the index and error of every failed call
+    pub fn replay<const DIM: usize>(&self) -> (Octree<T, DIM>, Vec<(usize, OctreeError)>) {
+        let mut tree = Octree::new(self.octree_size).ok().unwrap();
+        let mut errors = Vec::new();
+        for (index, call) in self.calls.iter().enumerate() {
+            match call {
+                RecordedCall::Edit(edit) => {
+                    if let Err(error) = tree.apply_edit(edit.clone()) {
+                        errors.push((index, error));
+                    }
+                }
+                RecordedCall::SetAutoSimplify(auto_simplify) => {
+                    tree.auto_simplify = *auto_simplify;
+                }
+                RecordedCall::SimplifyIncremental { node_budget } => {
+                    tree.simplify_incremental(*node_budget);
+                }
+            }
+        }
+        (tree, errors)
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Starts capturing every call changing the tree, replacing any previous recording.
+    /// The current contents of the tree are captured too, so the recording can be replayed onto a new tree.
+    /// Changes made through `get_mut` are not captured.
+    pub fn start_recording(&mut self) {
+        let mut calls = vec![RecordedCall::SetAutoSimplify(self.auto_simplify)];
+        calls.extend(
+            Octree::<T, DIM>::new(self.octree_size)
+                .ok()
+                .unwrap()
+                .delta_to(self)
+                .ok()
+                .unwrap()
+                .into_iter()
+                .map(RecordedCall::Edit),
+        );
+        self.recorder = Some(EditRecorder {
+            octree_size: self.octree_size,
+            auto_simplify: self.auto_simplify,
+            calls,
+        });
+    }
+
+    /// The calls captured since `start_recording`, if the tree is being recorded
+    pub fn recording(&self) -> Option<&EditRecorder<T>> {
+        self.recorder.as_ref()
+    }
+
+    /// Stops capturing the calls changing the tree, returns the calls captured since `start_recording`
+    pub fn stop_recording(&mut self) -> Option<EditRecorder<T>> {
+        self.recorder.take()
+    }
+
+    /// Adds the given call to the recording, should the tree be recorded
+    pub(in crate::octree) fn record(&mut self, call: impl FnOnce() -> RecordedCall<T>) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.auto_simplify != self.auto_simplify {
+                recorder.auto_simplify = self.auto_simplify;
+                recorder
+                    .calls
+                    .push(RecordedCall::SetAutoSimplify(self.auto_simplify));
+            }
+            recorder.calls.push(call());
+        }
+    }
+}
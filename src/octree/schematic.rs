@@ -0,0 +1,423 @@
+use crate::octree::{Octree, V3c, VoxelData};
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read};
+
+fn invalid_data(message: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// A tag of the NBT format schematics are stored in
+enum NbtTag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float,
+    Double,
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<NbtTag>),
+    Compound(HashMap<String, NbtTag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl NbtTag {
+    fn get(&self, name: &str) -> Result<&NbtTag, Error> {
+        match self {
+            NbtTag::Compound(tags) => tags
+                .get(name)
+                .ok_or_else(|| invalid_data(format!("Missing schematic tag {}", name))),
+            _ => Err(invalid_data(format!(
+                "Schematic tag {} looked up in a non-compound tag",
+                name
+            ))),
+        }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        matches!(self, NbtTag::Compound(tags) if tags.contains_key(name))
+    }
+
+    /// The value of the tag as an integer, for any integral tag
+    fn as_int(&self) -> Result<i64, Error> {
+        match self {
+            NbtTag::Byte(value) => Ok(*value as i64),
+            NbtTag::Short(value) => Ok(*value as i64),
+            NbtTag::Int(value) => Ok(*value as i64),
+            NbtTag::Long(value) => Ok(*value),
+            _ => Err(invalid_data("Expected an integer schematic tag")),
+        }
+    }
+
+    /// The value of the tag as a size, which is stored as a signed short even above 32767
+    fn as_size(&self) -> Result<u32, Error> {
+        match self {
+            NbtTag::Short(value) => Ok(*value as u16 as u32),
+            _ => u32::try_from(self.as_int()?).map_err(|_| invalid_data("Negative schematic size")),
+        }
+    }
+
+    fn as_vector(&self) -> Result<V3c<i32>, Error> {
+        Ok(V3c::new(
+            self.get("x")?.as_int()? as i32,
+            self.get("y")?.as_int()? as i32,
+            self.get("z")?.as_int()? as i32,
+        ))
+    }
+}
+
+/// Reads big endian NBT tags from a byte slice
+struct NbtReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl NbtReader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], Error> {
+        if self.bytes.len() < count {
+            return Err(invalid_data("Unexpected end of schematic"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn length(&mut self) -> Result<usize, Error> {
+        usize::try_from(i32::from_be_bytes(self.array()?))
+            .map_err(|_| invalid_data("Negative length in schematic"))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    /// The root tag of the schematic: a named compound
+    fn root(&mut self) -> Result<NbtTag, Error> {
+        let tag_type = self.array::<1>()?[0];
+        self.string()?;
+        self.payload(tag_type)
+    }
+
+    fn payload(&mut self, tag_type: u8) -> Result<NbtTag, Error> {
+        Ok(match tag_type {
+            1 => NbtTag::Byte(i8::from_be_bytes(self.array()?)),
+            2 => NbtTag::Short(i16::from_be_bytes(self.array()?)),
+            3 => NbtTag::Int(i32::from_be_bytes(self.array()?)),
+            4 => NbtTag::Long(i64::from_be_bytes(self.array()?)),
+            5 => {
+                self.take(4)?;
+                NbtTag::Float
+            }
+            6 => {
+                self.take(8)?;
+                NbtTag::Double
+            }
+            7 => {
+                let length = self.length()?;
+                NbtTag::ByteArray(self.take(length)?.to_vec())
+            }
+            8 => NbtTag::String(self.string()?),
+            9 => {
+                let item_type = self.array::<1>()?[0];
+                let length = self.length()?;
+                NbtTag::List(
+                    (0..length)
+                        .map(|_| self.payload(item_type))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            10 => {
+                let mut tags = HashMap::new();
+                loop {
+                    let tag_type = self.array::<1>()?[0];
+                    if 0 == tag_type {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.insert(name, self.payload(tag_type)?);
+                }
+                NbtTag::Compound(tags)
+            }
+            11 => {
+                let length = self.length()?;
+                NbtTag::IntArray(
+                    (0..length)
+                        .map(|_| Ok(i32::from_be_bytes(self.array()?)))
+                        .collect::<Result<_, Error>>()?,
+                )
+            }
+            12 => {
+                let length = self.length()?;
+                NbtTag::LongArray(
+                    (0..length)
+                        .map(|_| Ok(i64::from_be_bytes(self.array()?)))
+                        .collect::<Result<_, Error>>()?,
+                )
+            }
+            _ => return Err(invalid_data(format!("Unknown NBT tag type {}", tag_type))),
+        })
+    }
+}
+
+/// A box of blocks inside a schematic: the whole schematic, or a region of a litematic
+struct BlockVolume {
+    min_position: V3c<i32>,
+    size: V3c<u32>,
+    /// The block states the blocks refer to, e.g. "minecraft:oak_log[axis=y]"
+    palette: Vec<String>,
+    /// The index of the palette entry of every block, laid out x first, then z, then y
+    blocks: Vec<usize>,
+}
+
+impl BlockVolume {
+    fn block_count(&self) -> usize {
+        self.size.x as usize * self.size.y as usize * self.size.z as usize
+    }
+}
+
+/// The volume of a Sponge schematic (.schem) of version 1 to 3
+fn sponge_volume(root: &NbtTag) -> Result<BlockVolume, Error> {
+    // Version 3 wraps the content into a compound, and the blocks into another one
+    let schematic = if root.has("Schematic") {
+        root.get("Schematic")?
+    } else {
+        root
+    };
+    let (palette_tag, data_tag) = if schematic.has("Blocks") {
+        let blocks = schematic.get("Blocks")?;
+        (blocks.get("Palette")?, blocks.get("Data")?)
+    } else {
+        (schematic.get("Palette")?, schematic.get("BlockData")?)
+    };
+    let NbtTag::Compound(palette_tags) = palette_tag else {
+        return Err(invalid_data("Schematic palette is not a compound"));
+    };
+    let mut palette = vec![String::new(); palette_tags.len()];
+    for (block_state, index) in palette_tags {
+        let index = index.as_int()? as usize;
+        *palette
+            .get_mut(index)
+            .ok_or_else(|| invalid_data("Schematic palette index out of range"))? =
+            block_state.clone();
+    }
+    let NbtTag::ByteArray(data) = data_tag else {
+        return Err(invalid_data("Schematic block data is not a byte array"));
+    };
+
+    // Palette indices are stored as variable length integers, 7 bits in each byte
+    let mut blocks = Vec::with_capacity(data.len());
+    let (mut value, mut shift) = (0_usize, 0);
+    for byte in data {
+        value |= ((byte & 0x7F) as usize) << shift;
+        if 0 == byte & 0x80 {
+            blocks.push(value);
+            (value, shift) = (0, 0);
+        } else {
+            shift += 7;
+        }
+    }
+    let offset = if schematic.has("Offset") {
+        match schematic.get("Offset")? {
+            NbtTag::IntArray(offset) if 3 == offset.len() => {
+                V3c::new(offset[0], offset[1], offset[2])
+            }
+            _ => return Err(invalid_data("Schematic offset is not 3 integers")),
+        }
+    } else {
+        V3c::unit(0)
+    };
+    Ok(BlockVolume {
+        min_position: offset,
+        size: V3c::new(
+            schematic.get("Width")?.as_size()?,
+            schematic.get("Height")?.as_size()?,
+            schematic.get("Length")?.as_size()?,
+        ),
+        palette,
+        blocks,
+    })
+}
+
+/// The volumes of the regions of a Litematica schematic (.litematic)
+fn litematic_volumes(root: &NbtTag) -> Result<Vec<BlockVolume>, Error> {
+    let NbtTag::Compound(regions) = root.get("Regions")? else {
+        return Err(invalid_data("Litematic regions are not a compound"));
+    };
+    let mut volumes = Vec::with_capacity(regions.len());
+    for region in regions.values() {
+        // A negative size extends the region from its position towards the negative direction
+        let position = region.get("Position")?.as_vector()?;
+        let signed_size = region.get("Size")?.as_vector()?;
+        let corner = |position: i32, size: i32| {
+            if size < 0 {
+                position + size + 1
+            } else {
+                position
+            }
+        };
+        let min_position = V3c::new(
+            corner(position.x, signed_size.x),
+            corner(position.y, signed_size.y),
+            corner(position.z, signed_size.z),
+        );
+        let size = V3c::new(
+            signed_size.x.unsigned_abs(),
+            signed_size.y.unsigned_abs(),
+            signed_size.z.unsigned_abs(),
+        );
+
+        let NbtTag::List(palette_tags) = region.get("BlockStatePalette")? else {
+            return Err(invalid_data("Litematic palette is not a list"));
+        };
+        let palette = palette_tags
+            .iter()
+            .map(|entry| {
+                let NbtTag::String(name) = entry.get("Name")? else {
+                    return Err(invalid_data("Litematic block name is not a string"));
+                };
+                if !entry.has("Properties") {
+                    return Ok(name.clone());
+                }
+                let NbtTag::Compound(properties) = entry.get("Properties")? else {
+                    return Err(invalid_data(
+                        "Litematic block properties are not a compound",
+                    ));
+                };
+                let mut properties = properties
+                    .iter()
+                    .filter_map(|(key, value)| match value {
+                        NbtTag::String(value) => Some(format!("{}={}", key, value)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                properties.sort();
+                Ok(format!("{}[{}]", name, properties.join(",")))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Palette indices are packed tightly with at least 2 bits each, possibly spanning two longs
+        let NbtTag::LongArray(states) = region.get("BlockStates")? else {
+            return Err(invalid_data("Litematic block states are not a long array"));
+        };
+        let bits = (usize::BITS - palette.len().saturating_sub(1).leading_zeros()).max(2) as usize;
+        let mask = (1_u64 << bits) - 1;
+        let block_count = size.x as usize * size.y as usize * size.z as usize;
+        if states.len() * 64 < block_count * bits {
+            return Err(invalid_data("Litematic block states are too short"));
+        }
+        let blocks = (0..block_count)
+            .map(|index| {
+                let start_bit = index * bits;
+                let (start_long, start_offset) = (start_bit / 64, start_bit % 64);
+                let end_long = (start_bit + bits - 1) / 64;
+                let mut value = states[start_long] as u64 >> start_offset;
+                if end_long != start_long {
+                    value |= (states[end_long] as u64) << (64 - start_offset);
+                }
+                (value & mask) as usize
+            })
+            .collect();
+        volumes.push(BlockVolume {
+            min_position,
+            size,
+            palette,
+            blocks,
+        });
+    }
+    Ok(volumes)
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Creates a tree from a Minecraft schematic: a Sponge schematic (.schem) or a Litematica schematic (.litematic),
+    /// gzip compressed or not. The tree is the smallest possible to contain every block,
+    /// with the lowest corner of the schematic at its origin.
+    /// * `bytes` - The content of the schematic file
+    /// * `palette` - Provides the voxel of each block state, given as the block id
+    ///   with its properties sorted by name, e.g. "minecraft:oak_log[axis=y]"; None for empty blocks like air
+    pub fn from_schematic(
+        bytes: &[u8],
+        mut palette: impl FnMut(&str) -> Option<T>,
+    ) -> Result<Self, Error> {
+        let mut decompressed = Vec::new();
+        let bytes = if bytes.starts_with(&[0x1F, 0x8B]) {
+            GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+            &decompressed
+        } else {
+            bytes
+        };
+        let root = NbtReader { bytes }.root()?;
+        let volumes = if root.has("Regions") {
+            litematic_volumes(&root)?
+        } else {
+            vec![sponge_volume(&root)?]
+        };
+        for volume in volumes.iter() {
+            if volume.blocks.len() < volume.block_count() {
+                return Err(invalid_data("Schematic has less blocks than its size"));
+            }
+        }
+
+        let min_position = volumes.iter().fold(V3c::unit(i32::MAX), |min, volume| {
+            V3c::new(
+                min.x.min(volume.min_position.x),
+                min.y.min(volume.min_position.y),
+                min.z.min(volume.min_position.z),
+            )
+        });
+        let extent = volumes.iter().fold(1, |extent: u32, volume| {
+            let offset = volume.min_position - min_position;
+            extent
+                .max(offset.x as u32 + volume.size.x)
+                .max(offset.y as u32 + volume.size.y)
+                .max(offset.z as u32 + volume.size.z)
+        });
+        let mut size = DIM as u32;
+        while size < extent {
+            size *= 2;
+        }
+
+        let mut tree = Self::new(size).map_err(|error| invalid_data(format!("{:?}", error)))?;
+        tree.auto_simplify = false;
+        for volume in volumes.iter() {
+            let voxels = volume
+                .palette
+                .iter()
+                .map(|block_state| palette(block_state))
+                .collect::<Vec<_>>();
+            let offset = V3c::<u32>::from(volume.min_position - min_position);
+            for (index, palette_index) in volume.blocks[..volume.block_count()].iter().enumerate() {
+                let Some(data) = voxels.get(*palette_index).ok_or_else(|| {
+                    invalid_data("Schematic block refers to a missing palette entry")
+                })?
+                else {
+                    continue;
+                };
+                let index = index as u32;
+                let position = V3c::new(
+                    index % volume.size.x,
+                    index / (volume.size.x * volume.size.z),
+                    index / volume.size.x % volume.size.z,
+                );
+                tree.insert(&(offset + position), data.clone())
+                    .map_err(|error| invalid_data(format!("{:?}", error)))?;
+            }
+        }
+        while !tree.simplify_incremental(usize::MAX) {}
+        tree.auto_simplify = true;
+        Ok(tree)
+    }
+
+    /// Loads a tree from the Minecraft schematic at the given file path, see `from_schematic`
+    pub fn load_schematic(
+        path: &str,
+        palette: impl FnMut(&str) -> Option<T>,
+    ) -> Result<Self, Error> {
+        Self::from_schematic(&std::fs::read(path)?, palette)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{
+    types::{NodeContent, OctreeError},
+    Axis, Cube, Octant, Octree, VoxelData,
+};
+
+/// The color of the pixels of empty voxels in slice images
+const EMPTY_VOXEL_COLOR: [u8; 4] = [0, 0, 0, 0];
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The voxels of the plane perpendicular to the given axis at the given coordinate,
+    /// e.g. for cross-section views in editors. Only the nodes intersecting the plane are visited,
+    /// uniform regions and the rows of bricks are copied at once.
+    /// * `axis` - The axis the plane is perpendicular to
+    /// * `coord` - The coordinate of the plane along the axis
+    ///
+    /// output: `octree_size * octree_size` voxels, indexed by `v * octree_size + u`,
+    /// where u and v are the coordinates along the other two axes in x, y, z order
+    pub fn slice(&self, axis: Axis, coord: u32) -> Result<Vec<Option<T>>, OctreeError> {
+        let size = self.octree_size;
+        if coord >= size {
+            let position = axis.vector_with(coord, (0, 0));
+            return Err(OctreeError::InvalidPosition {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            });
+        }
+
+        let mut slice = vec![None; (size * size) as usize];
+        let mut fill = |bounds: &Cube, data: &T| {
+            if data.is_empty() {
+                return;
+            }
+            let (min_u, min_v) = axis.plane_of(&bounds.min_position);
+            for v in min_v..(min_v + bounds.size) {
+                let row = (v * size) as usize;
+                slice[row + min_u as usize..row + (min_u + bounds.size) as usize]
+                    .fill(Some(data.clone()));
+            }
+        };
+        let mut node_stack = vec![(
+            Octree::<T, DIM>::ROOT_NODE_KEY as usize,
+            Cube::root_bounds(size),
+        )];
+        while let Some((node_key, bounds)) = node_stack.pop() {
+            let min = axis.component_of(&bounds.min_position);
+            if coord < min || min + bounds.size <= coord {
+                continue;
+            }
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => {}
+                NodeContent::UniformLeaf(data) => fill(&bounds, data),
+                NodeContent::Leaf(brick) => {
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    let layer = ((coord - min) as usize * DIM) / bounds.size as usize;
+                    let brick = &self.bricks.get(*brick as usize).0;
+                    for a in 0..DIM {
+                        for b in 0..DIM {
+                            let mat_index = axis.vector_with(layer, (a, b));
+                            let cell_plane = axis.vector_with(0, (a as u32, b as u32));
+                            fill(
+                                &Cube::new(bounds.min_position + cell_plane * cell_size, cell_size),
+                                &brick[mat_index.x][mat_index.y][mat_index.z],
+                            );
+                        }
+                    }
+                }
+                NodeContent::Internal(_) => {
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key as usize, bounds.child_bounds_for(octant)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(slice)
+    }
+
+    /// The voxels of the plane perpendicular to the given axis like `slice`, as an RGBA8 image buffer
+    /// of their albedo colors, `octree_size` pixels wide and high; empty voxels are transparent
+    pub fn slice_image(&self, axis: Axis, coord: u32) -> Result<Vec<u8>, OctreeError> {
+        Ok(self
+            .slice(axis, coord)?
+            .iter()
+            .flat_map(|voxel| match voxel {
+                Some(data) => data.albedo(),
+                None => EMPTY_VOXEL_COLOR,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::object_pool::{key_might_be_valid, PoolKey};
+use crate::octree::{
+    types::{Brick, NodeChildren, NodeContent},
+    Octant, Octree, VoxelData,
+};
+
+/// Estimated bytes used by the nodes of an octree, by the kind of their content
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MemoryBreakdown {
+    /// Nodes with children
+    pub internal: usize,
+    /// Leaf nodes with a brick of voxels, the bytes of the brick included
+    pub leaf: usize,
+    /// Leaf nodes with the same data in all of their voxels
+    pub uniform_leaf: usize,
+    /// Nodes without any content
+    pub nothing: usize,
+    /// Slots of the pools not in use by any node or brick, kept to be reused
+    pub free_slots: usize,
+}
+
+impl MemoryBreakdown {
+    /// The sum of the bytes used by every kind
+    pub fn total(&self) -> usize {
+        self.internal + self.leaf + self.uniform_leaf + self.nothing + self.free_slots
+    }
+}
+
+/// Estimated memory usage of an octree, see `Octree::memory_report`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MemoryReport {
+    /// The bytes used by the nodes at each depth, the root being at depth 0; free slots have no depth
+    pub by_depth: Vec<MemoryBreakdown>,
+    /// The bytes used by every node and slot of the tree
+    pub total: MemoryBreakdown,
+}
+
+impl MemoryReport {
+    /// The report in the folded stack format, one `octree;depth_<n>;<kind> <bytes>` line for
+    /// each depth and kind using any memory, to be rendered as a flamegraph by e.g. inferno or flamegraph.pl
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        for (depth, breakdown) in self.by_depth.iter().enumerate() {
+            for (kind, bytes) in [
+                ("internal", breakdown.internal),
+                ("leaf", breakdown.leaf),
+                ("uniform_leaf", breakdown.uniform_leaf),
+                ("nothing", breakdown.nothing),
+            ] {
+                if 0 < bytes {
+                    folded += &format!("octree;depth_{};{} {}\n", depth, kind, bytes);
+                }
+            }
+        }
+        if 0 < self.total.free_slots {
+            folded += &format!("octree;free_slots {}\n", self.total.free_slots);
+        }
+        folded
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// The estimated memory used by the octree broken down by the depth and the kind of the nodes,
+    /// e.g. to tune DIM or the simplification of the tree. The bytes of the nodes in use add up to `memory_usage`.
+    pub fn memory_report(&self) -> MemoryReport {
+        let node_bytes =
+            std::mem::size_of::<NodeContent<T>>() + std::mem::size_of::<NodeChildren<PoolKey>>();
+        let brick_bytes = std::mem::size_of::<Brick<T, DIM>>();
+        let mut report = MemoryReport::default();
+        let mut node_stack = vec![(Self::ROOT_NODE_KEY as usize, 0)];
+        while let Some((node_key, depth)) = node_stack.pop() {
+            if report.by_depth.len() <= depth {
+                report
+                    .by_depth
+                    .resize(depth + 1, MemoryBreakdown::default());
+            }
+            let breakdown = &mut report.by_depth[depth];
+            match self.nodes.get(node_key) {
+                NodeContent::Nothing => breakdown.nothing += node_bytes,
+                NodeContent::Internal(_) => {
+                    breakdown.internal += node_bytes;
+                    for octant in Octant::iter() {
+                        let child_key = self.node_children[node_key][octant];
+                        if key_might_be_valid(child_key) {
+                            node_stack.push((child_key as usize, depth + 1));
+                        }
+                    }
+                }
+                NodeContent::Leaf(_) => breakdown.leaf += node_bytes + brick_bytes,
+                NodeContent::UniformLeaf(_) => breakdown.uniform_leaf += node_bytes,
+            }
+        }
+
+        for breakdown in report.by_depth.iter() {
+            report.total.internal += breakdown.internal;
+            report.total.leaf += breakdown.leaf;
+            report.total.uniform_leaf += breakdown.uniform_leaf;
+            report.total.nothing += breakdown.nothing;
+        }
+        report.total.free_slots = (self.nodes.len() - self.nodes.count()) * node_bytes
+            + (self.bricks.len() - self.bricks.count()) * brick_bytes;
+        report
+    }
+}
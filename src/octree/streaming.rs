@@ -0,0 +1,159 @@
+use crate::octree::{V3c, VoxelData, VoxelWorld};
+
+/// Decides which chunks of a streamed world are to be loaded or evicted around a focus,
+/// e.g. the chunk of the player, see `VoxelWorld::plan_streaming`
+pub trait StreamingPolicy {
+    /// The priority of loading the chunk at the given coordinates while the focus is in the given chunk;
+    /// chunks with higher priority are loaded first, and evicted last
+    fn priority(&self, chunk_coord: &V3c<i32>, focus: &V3c<i32>) -> f32;
+
+    /// The distance in chunks from the focus, within which loaded chunks are kept; chunks further away are evicted
+    fn keep_radius(&self) -> u32;
+
+    /// The chunks to load around the focus, in the order they are to be loaded.
+    /// By default the chunks within `keep_radius` of the focus, by descending priority
+    fn prefetch_order(&self, focus: &V3c<i32>) -> Vec<V3c<i32>> {
+        let mut chunks = chunks_within(focus, self.keep_radius());
+        chunks.sort_by(|a, b| self.priority(b, focus).total_cmp(&self.priority(a, focus)));
+        chunks
+    }
+
+    /// The maximum number of chunks to load in a single tick, so loading doesn't stall a frame
+    fn loads_per_tick(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// The distance of the given chunks in chunks
+fn chunk_distance(a: &V3c<i32>, b: &V3c<i32>) -> f32 {
+    V3c::<f32>::from(*a - *b).length()
+}
+
+/// The chunks within the given distance of the given chunk
+fn chunks_within(center: &V3c<i32>, radius: u32) -> Vec<V3c<i32>> {
+    let radius = radius as i32;
+    let mut chunks = Vec::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let chunk_coord = *center + V3c::new(x, y, z);
+                if chunk_distance(&chunk_coord, center) <= radius as f32 {
+                    chunks.push(chunk_coord);
+                }
+            }
+        }
+    }
+    chunks
+}
+
+/// Loads the chunks closest to the focus first. Chunks are loaded within `load_radius`
+/// and kept within `keep_radius`, so chunks on the border are not loaded and evicted repeatedly
+/// as the focus moves back and forth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceStreamingPolicy {
+    /// The distance in chunks from the focus, within which chunks are loaded
+    pub load_radius: u32,
+    /// The distance in chunks from the focus, within which loaded chunks are kept; expected to be at least `load_radius`
+    pub keep_radius: u32,
+    /// The maximum number of chunks to load in a single tick
+    pub loads_per_tick: usize,
+}
+
+impl Default for DistanceStreamingPolicy {
+    fn default() -> Self {
+        Self {
+            load_radius: 4,
+            keep_radius: 5,
+            loads_per_tick: 8,
+        }
+    }
+}
+
+impl StreamingPolicy for DistanceStreamingPolicy {
+    fn priority(&self, chunk_coord: &V3c<i32>, focus: &V3c<i32>) -> f32 {
+        -chunk_distance(chunk_coord, focus)
+    }
+
+    fn keep_radius(&self) -> u32 {
+        self.keep_radius
+    }
+
+    fn prefetch_order(&self, focus: &V3c<i32>) -> Vec<V3c<i32>> {
+        let mut chunks = chunks_within(focus, self.load_radius);
+        chunks.sort_by(|a, b| chunk_distance(a, focus).total_cmp(&chunk_distance(b, focus)));
+        chunks
+    }
+
+    fn loads_per_tick(&self) -> usize {
+        self.loads_per_tick
+    }
+}
+
+/// The chunks to load and to evict in a tick of streaming a world, see `VoxelWorld::plan_streaming`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StreamingPlan {
+    /// The chunks to load, in the order they are to be loaded
+    pub load: Vec<V3c<i32>>,
+    /// The loaded chunks to evict, in the order they are to be evicted
+    pub evict: Vec<V3c<i32>>,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelWorld<T, DIM> {
+    /// The chunks to load and evict in a tick of streaming the world around the given world position,
+    /// as decided by the given policy: the chunks not loaded yet in prefetch order up to the loads of a tick,
+    /// and the loaded chunks outside the keep radius of the policy, the ones with the lowest priority first
+    pub fn plan_streaming(&self, focus: &V3c<i32>, policy: &impl StreamingPolicy) -> StreamingPlan {
+        let (focus, _) = self.chunk_coord_of(focus);
+        let load = policy
+            .prefetch_order(&focus)
+            .into_iter()
+            .filter(|chunk_coord| !self.is_chunk_loaded(chunk_coord))
+            .take(policy.loads_per_tick())
+            .collect();
+        let keep_radius = policy.keep_radius() as f32;
+        let mut evict = self
+            .chunks
+            .keys()
+            .chain(self.empty_chunks.iter())
+            .filter(|chunk_coord| chunk_distance(chunk_coord, &focus) > keep_radius)
+            .copied()
+            .collect::<Vec<_>>();
+        evict.sort_by(|a, b| {
+            policy
+                .priority(a, &focus)
+                .total_cmp(&policy.priority(b, &focus))
+        });
+        StreamingPlan { load, evict }
+    }
+
+    /// Streams the world around the given world position from the given directory saved by `save_dir`,
+    /// for a tick as planned by `plan_streaming`: evicted chunks with changes are saved before they are removed,
+    /// then the planned chunks are loaded.
+    /// Returns the planned chunks the directory has no content for, e.g. to be generated by the caller
+    #[cfg(feature = "world_dir")]
+    pub fn stream_from_dir(
+        &mut self,
+        path: &str,
+        focus: &V3c<i32>,
+        policy: &impl StreamingPolicy,
+    ) -> Result<Vec<V3c<i32>>, std::io::Error> {
+        let plan = self.plan_streaming(focus, policy);
+        if plan
+            .evict
+            .iter()
+            .any(|chunk_coord| self.dirty_chunks.contains(chunk_coord))
+        {
+            self.save_dir(path)?;
+        }
+        for chunk_coord in plan.evict.iter() {
+            self.remove_chunk(chunk_coord);
+        }
+        let mut missing = Vec::new();
+        for chunk_coord in plan.load {
+            if !self.load_chunk_from_dir(path, &chunk_coord)? {
+                missing.push(chunk_coord);
+            }
+        }
+        Ok(missing)
+    }
+}
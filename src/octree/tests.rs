@@ -2,6 +2,225 @@
 mod octree_serialization_tests {
     use crate::octree::Octree;
     use crate::octree::V3c;
+    use crate::octree::{OctreeLoader, VoxelData, VoxelDataCodec};
+
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Material {
+        albedo: [u8; 4],
+        hardness: f32,
+    }
+
+    impl VoxelData for Material {
+        fn new(r: u8, g: u8, b: u8, a: u8, _user_data: u32) -> Self {
+            Material {
+                albedo: [r, g, b, a],
+                hardness: 0.,
+            }
+        }
+        fn albedo(&self) -> [u8; 4] {
+            self.albedo
+        }
+        fn user_data(&self) -> u32 {
+            0
+        }
+        fn clear(&mut self) {
+            *self = Material::default();
+        }
+    }
+
+    struct MaterialCodec;
+    impl VoxelDataCodec<Material> for MaterialCodec {
+        fn encode(&self, data: &Material) -> Vec<u8> {
+            let mut bytes = data.albedo.to_vec();
+            bytes.extend_from_slice(&data.hardness.to_le_bytes());
+            bytes
+        }
+        fn decode(&self, bytes: &[u8]) -> Option<Material> {
+            Some(Material {
+                albedo: bytes.get(0..4)?.try_into().ok()?,
+                hardness: f32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?),
+            })
+        }
+        fn version(&self) -> u32 {
+            1
+        }
+    }
+
+    /// The layout of the material before hardness was added to it
+    struct MaterialCodecV0;
+    impl VoxelDataCodec<Material> for MaterialCodecV0 {
+        fn encode(&self, data: &Material) -> Vec<u8> {
+            data.albedo.to_vec()
+        }
+        fn decode(&self, bytes: &[u8]) -> Option<Material> {
+            Some(Material {
+                albedo: bytes.try_into().ok()?,
+                hardness: 0.,
+            })
+        }
+    }
+
+    #[test]
+    fn test_octree_load_with_migration() {
+        let mut tree = Octree::<Material, 2>::new(4).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 2, Material::new(1, 2, 3, 255, 0))
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(3, 3, 3), Material::new(4, 5, 6, 255, 0))
+            .ok()
+            .unwrap();
+        let old_bytes = tree.to_bytes_with(&MaterialCodecV0);
+
+        let loader = OctreeLoader::new(MaterialCodec);
+        assert!(loader.from_bytes::<2>(old_bytes.clone()).is_err());
+
+        let loader = loader.with_migration(0, |old_bytes| Material {
+            albedo: old_bytes.try_into().unwrap(),
+            hardness: 0.5,
+        });
+        let migrated = loader.from_bytes::<2>(old_bytes).ok().unwrap();
+        assert!(migrated
+            .get(&V3c::new(1, 1, 1))
+            .is_some_and(|v| v.albedo == [1, 2, 3, 255] && 0.5 == v.hardness));
+        assert!(migrated
+            .get(&V3c::new(3, 3, 3))
+            .is_some_and(|v| v.albedo == [4, 5, 6, 255] && 0.5 == v.hardness));
+        assert!(migrated.get(&V3c::new(2, 2, 2)).is_none());
+
+        // Saves of the current version are loaded without migrations
+        let current = loader
+            .from_bytes::<2>(migrated.to_bytes_with(&MaterialCodec))
+            .ok()
+            .unwrap();
+        assert!(current
+            .get(&V3c::new(3, 3, 3))
+            .is_some_and(|v| 0.5 == v.hardness));
+    }
+
+    #[test]
+    fn test_octree_load_without_data_version() {
+        let mut tree = Octree::<Material, 2>::new(4).ok().unwrap();
+        tree.insert(&V3c::new(3, 3, 3), Material::new(4, 5, 6, 255, 0))
+            .ok()
+            .unwrap();
+
+        // Trees saved before the version of the voxel data was stored end with the bricks,
+        // without the versioned layout header, the data version or the voxel size
+        let bytes = tree.to_bytes_with(&MaterialCodecV0);
+        let header = b"15:shocovox_octreei1e";
+        let trailer = format!("i0ei{}ee", 1_f32.to_bits());
+        assert!(bytes.starts_with(b"l") && bytes[1..].starts_with(header));
+        assert!(bytes.ends_with(trailer.as_bytes()));
+        let mut old_bytes = b"l".to_vec();
+        old_bytes.extend_from_slice(&bytes[1 + header.len()..bytes.len() - trailer.len()]);
+        old_bytes.push(b'e');
+
+        // The voxels are read as the first version of the codec
+        let loader = OctreeLoader::new(MaterialCodec).with_migration(0, |old_bytes| Material {
+            albedo: old_bytes.try_into().unwrap(),
+            hardness: 0.5,
+        });
+        let migrated = loader.from_bytes::<2>(old_bytes).ok().unwrap();
+        assert!(migrated
+            .get(&V3c::new(3, 3, 3))
+            .is_some_and(|v| v.albedo == [4, 5, 6, 255] && 0.5 == v.hardness));
+        assert!(migrated.get(&V3c::new(1, 1, 1)).is_none());
+        assert!(1. == migrated.voxel_size());
+    }
+
+    #[test]
+    fn test_octree_serialize_with_codec() {
+        let stone = Material {
+            albedo: [128, 128, 128, 255],
+            hardness: 0.75,
+        };
+        let dirt = Material {
+            albedo: [96, 64, 0, 255],
+            hardness: 0.25,
+        };
+        let mut tree = Octree::<Material, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, stone.clone())
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(5, 5, 5), dirt.clone()).ok().unwrap();
+
+        let deserialized = Octree::<Material, 2>::from_bytes_with(
+            tree.to_bytes_with(&MaterialCodec),
+            &MaterialCodec,
+        );
+        assert!(deserialized.get(&V3c::new(1, 2, 3)) == Some(&stone));
+        assert!(deserialized.get(&V3c::new(5, 5, 5)) == Some(&dirt));
+        assert!(deserialized.get(&V3c::new(6, 6, 6)).is_none());
+
+        // The default codec only keeps what is available through VoxelData
+        let deserialized = Octree::<Material, 2>::from_bytes(tree.to_bytes());
+        assert!(deserialized
+            .get(&V3c::new(5, 5, 5))
+            .is_some_and(|v| v.albedo == dirt.albedo && 0. == v.hardness));
+    }
+
+    #[test]
+    fn test_octree_load_unsupported_layouts() {
+        let path = std::env::temp_dir().join("shocovox_test_octree_load_unsupported_layouts");
+        let path = path.to_str().unwrap();
+        let load = |bytes: &[u8]| {
+            std::fs::write(path, bytes).ok().unwrap();
+            Octree::<u32>::load(path)
+        };
+
+        // A tree of size 2 with a single voxel, saved before leaves had bricks: the voxels are inside the leaf node
+        let inline_leaves = "li0ei2el\
+            i2elli1el2:##i1eeeli1el3:###i5ei0ei0ei0ei5eeeee\
+            lli1ei4294967295ei4294967295ei4294967295ei4294967295ei4294967295ei4294967295ei4294967295ee5:##x##e\
+            e";
+        assert!(load(inline_leaves.as_bytes())
+            .is_err_and(|error| std::io::ErrorKind::InvalidData == error.kind()));
+
+        let mut tree = Octree::<u32>::new(4).ok().unwrap();
+        tree.insert(&V3c::new(1, 2, 3), 5).ok().unwrap();
+        let bytes = tree.to_bytes();
+        assert!(load(&bytes).is_ok_and(|loaded| loaded.get(&V3c::new(1, 2, 3)) == Some(&5)));
+        assert!(load(&bytes[..bytes.len() / 2]).is_err());
+        assert!(load(&bytes[..bytes.len() - 1]).is_err());
+
+        // Layouts newer than the supported one are rejected
+        let header = b"15:shocovox_octreei1e";
+        let header_at = bytes
+            .windows(header.len())
+            .position(|window| window == header)
+            .unwrap();
+        let mut newer = bytes.clone();
+        newer[header_at + header.len() - 2] = b'2';
+        assert!(load(&newer).is_err());
+
+        // Trees saved before the layout was versioned are still loaded
+        let mut unversioned = bytes;
+        unversioned.drain(header_at..header_at + header.len());
+        assert!(load(&unversioned).is_ok_and(|loaded| loaded.get(&V3c::new(1, 2, 3)) == Some(&5)));
+        std::fs::remove_file(path).ok().unwrap();
+    }
+
+    #[test]
+    fn test_voxel_size_serialization() {
+        let mut tree = Octree::<u32, 2>::new(4).ok().unwrap().with_voxel_size(0.25);
+        tree.insert(&V3c::new(1, 2, 3), 5).ok().unwrap();
+        let bytes = tree.to_bytes();
+        let deserialized = Octree::<u32, 2>::from_bytes(bytes.clone());
+        assert!(0.25 == deserialized.voxel_size());
+        assert!(deserialized.voxel_to_world(V3c::new(4., 0., 2.)) == V3c::new(1., 0., 0.5));
+        assert!(deserialized.world_to_voxel(V3c::new(1., 0., 0.5)) == V3c::new(4., 0., 2.));
+
+        // Data saved before the voxel size was stored defaults to voxels of size 1
+        let voxel_size_suffix = format!("i{}ee", 0.25_f32.to_bits());
+        let bytes = String::from_utf8_lossy(&bytes).into_owned();
+        assert!(bytes.ends_with(&voxel_size_suffix));
+        let legacy = bytes[..bytes.len() - voxel_size_suffix.len()].to_string() + "e";
+        let deserialized = Octree::<u32, 2>::from_bytes(legacy.into_bytes());
+        assert!(1. == deserialized.voxel_size());
+        assert!(deserialized
+            .get(&V3c::new(1, 2, 3))
+            .is_some_and(|v| *v == 5));
+    }
 
     #[test]
     fn test_octree_file_io() {
@@ -59,12 +278,44 @@ mod octree_serialization_tests {
             }
         }
     }
+
+    #[test]
+    fn test_node_key_width() {
+        use crate::object_pool::{key_none_value, PoolKey};
+        let key_bytes = if cfg!(feature = "u64_keys") { 8 } else { 4 };
+        assert!(key_bytes == std::mem::size_of::<PoolKey>());
+        assert!(PoolKey::MAX == key_none_value());
+
+        // Trees saved with either key width can be loaded regardless of the key width used
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(5, 6, 7), 3).ok().unwrap();
+        let bytes = String::from_utf8(tree.to_bytes()).ok().unwrap();
+        let none_32 = format!("i{}e", u32::MAX);
+        let none_64 = format!("i{}e", u64::MAX);
+        assert!(bytes.contains(&none_32) || bytes.contains(&none_64));
+        for saved in [
+            bytes.replace(&none_64, &none_32),
+            bytes.replace(&none_32, &none_64),
+        ] {
+            let deserialized = Octree::<u32, 2>::from_bytes(saved.into_bytes());
+            for x in 0..8 {
+                for y in 0..8 {
+                    for z in 0..8 {
+                        let position = V3c::new(x, y, z);
+                        assert!(deserialized.get(&position) == tree.get(&position));
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod octree_tests {
-    use crate::octree::types::{Octree, VoxelData};
-    use crate::spatial::math::vector::V3c;
+    use crate::octree::types::{NodeContent, Octree, VoxelData};
+    use crate::spatial::{math::vector::V3c, Cube};
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_simple_insert_and_get() {
@@ -80,6 +331,41 @@ mod octree_tests {
         assert!(tree.get(&V3c::new(1, 1, 1)).is_none());
     }
 
+    #[test]
+    fn test_upper_boundaries() {
+        use crate::octree::types::OctreeError;
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.auto_simplify = false;
+        // Positions on both sides of the boundaries between the octants and the nodes below them
+        for coordinate in [1, 2, 3, 4, 5, 7] {
+            let position = V3c::new(coordinate, 7 - coordinate, coordinate);
+            tree.insert(&position, coordinate + 1).ok().unwrap();
+        }
+        for coordinate in [1, 2, 3, 4, 5, 7] {
+            let position = V3c::new(coordinate, 7 - coordinate, coordinate);
+            assert!(tree.get(&position).is_some_and(|v| *v == coordinate + 1));
+        }
+        assert!(tree.get(&V3c::new(6, 1, 6)).is_none());
+        assert!(tree.get(&V3c::new(0, 7, 0)).is_none());
+
+        // The upper boundary of the tree is outside of it
+        for position in [V3c::new(8, 0, 0), V3c::new(0, 8, 0), V3c::new(0, 0, 8)] {
+            assert!(tree.get(&position).is_none());
+            assert!(matches!(
+                tree.insert(&position, 1),
+                Err(OctreeError::InvalidPosition { .. })
+            ));
+            assert!(matches!(
+                tree.clear(&position),
+                Err(OctreeError::InvalidPosition { .. })
+            ));
+        }
+
+        tree.clear(&V3c::new(4, 3, 4)).ok().unwrap();
+        assert!(tree.get(&V3c::new(4, 3, 4)).is_none());
+        assert!(tree.get(&V3c::new(3, 4, 3)).is_some_and(|v| *v == 4));
+    }
+
     #[test]
     fn test_simple_insert_and_get_where_dim_is_2() {
         let mut tree = Octree::<u32, 2>::new(4).ok().unwrap();
@@ -321,6 +607,113 @@ mod octree_tests {
         }
     }
 
+    #[test]
+    fn test_simplify_incremental() {
+        const SIZE: u32 = 4;
+        let mut tree = Octree::<u32>::new(SIZE).ok().unwrap();
+        tree.auto_simplify = false;
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    tree.insert(&V3c::new(x, y, z), 5).ok().unwrap();
+                }
+            }
+        }
+        let node_count = tree.nodes.count();
+
+        // A single node is simplified in each call, so multiple calls are needed
+        assert!(!tree.simplify_incremental(1));
+        let mut calls = 1;
+        while !tree.simplify_incremental(1) {
+            calls += 1;
+        }
+        assert!(1 < calls);
+        assert!(tree.nodes.count() < node_count);
+        assert!(tree.nodes.get(0).is_leaf());
+
+        // Integrity should be kept
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    assert!(tree.get(&V3c::new(x, y, z)).is_some_and(|v| *v == 5));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bricks_follow_leaf_nodes() {
+        const SIZE: u32 = 4;
+        let mut tree = Octree::<u32, 2>::new(SIZE).ok().unwrap();
+
+        // The whole tree is a single uniform leaf, which needs no brick
+        tree.insert_at_lod(&V3c::new(0, 0, 0), SIZE, 5)
+            .ok()
+            .unwrap();
+        assert!(tree.bricks.count() == 0);
+
+        // Breaking up the leaf only gives a brick to the child which is edited
+        tree.insert(&V3c::new(0, 0, 0), 4).ok().unwrap();
+        assert!(tree.bricks.count() == 1);
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 4));
+        assert!(tree.get(&V3c::new(1, 1, 1)).is_some_and(|v| *v == 5));
+        assert!(tree.get(&V3c::new(3, 3, 3)).is_some_and(|v| *v == 5));
+
+        // Clearing a whole leaf frees up its brick too
+        let mut tree = Octree::<u32, 2>::new(SIZE).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.insert(&V3c::new(2, 2, 2), 6).ok().unwrap();
+        assert!(tree.bricks.count() == 2);
+        tree.clear_at_lod(&V3c::new(2, 2, 2), 2).ok().unwrap();
+        assert!(tree.bricks.count() == 1);
+        assert!(tree.get(&V3c::new(2, 2, 2)).is_none());
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 5));
+    }
+
+    #[test]
+    fn test_uniform_leaf_materialized_on_edit() {
+        const SIZE: u32 = 8;
+        let mut tree = Octree::<u32, 2>::new(SIZE).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), SIZE, 5)
+            .ok()
+            .unwrap();
+
+        // Clearing a part of the uniform leaf keeps the rest of it without bricks
+        tree.clear_at_lod(&V3c::new(4, 4, 4), 4).ok().unwrap();
+        assert!(tree.bricks.count() == 0);
+        assert!(tree.get(&V3c::new(4, 4, 4)).is_none());
+        assert!(tree.get(&V3c::new(3, 3, 3)).is_some_and(|v| *v == 5));
+
+        // Mutable access to a single voxel needs a brick
+        *tree.get_mut(&V3c::new(0, 0, 0)).unwrap() = 6;
+        assert!(tree.bricks.count() == 1);
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 6));
+        assert!(tree.get(&V3c::new(2, 2, 2)).is_some_and(|v| *v == 5));
+
+        // Setting the edited voxel back lets the leaves collapse into a uniform one again
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 5));
+        assert!(tree.get(&V3c::new(1, 0, 0)).is_some_and(|v| *v == 5));
+    }
+
+    #[test]
+    fn test_occupied_bits_follow_insert_and_clear() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.insert(&V3c::new(7, 0, 0), 6).ok().unwrap();
+        assert!(tree.node_children[0].occupied_bits == 0b0000_0011);
+
+        tree.insert(&V3c::new(0, 7, 0), 7).ok().unwrap();
+        assert!(tree.node_children[0].occupied_bits == 0b0001_0011);
+
+        tree.clear(&V3c::new(7, 0, 0)).ok().unwrap();
+        tree.clear_at_lod(&V3c::new(4, 0, 0), 4).ok().unwrap();
+        assert!(tree.node_children[0].occupied_bits == 0b0001_0001);
+
+        tree.clear_at_lod(&V3c::new(0, 0, 0), 8).ok().unwrap();
+        assert!(tree.node_children[0].occupied_bits == 0);
+    }
+
     #[test]
     fn test_simplifyable_insert_and_get_where_dim_is_2() {
         const SIZE: u32 = 4;
@@ -618,4 +1011,3383 @@ mod octree_tests {
         // number of hits should be the number of nodes set minus the number of nodes cleared
         assert!(hits == (64 - 27));
     }
+
+    #[test]
+    fn test_clear_at_lod_keeps_neighbouring_voxels() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        tree.insert(&V3c::new(14, 5, 2), 1).ok().unwrap();
+        tree.insert(&V3c::new(8, 3, 5), 2).ok().unwrap();
+
+        // The cleared node holds a single voxel, its neighbour in the same parent is kept
+        tree.clear_at_lod(&V3c::new(8, 3, 5), 4).ok().unwrap();
+        assert!(tree.get(&V3c::new(8, 3, 5)).is_none());
+        assert!(tree.get(&V3c::new(14, 5, 2)) == Some(&1));
+    }
+
+    #[test]
+    fn test_voxel_counts_follow_edits() {
+        let mut rng = StdRng::seed_from_u64(seed("test_voxel_counts_follow_edits"));
+        let mut tree = Octree::<u32, 4>::new(16).ok().unwrap();
+
+        // Voxels already set are not counted again, even when inserted into the same brick repeatedly
+        for _ in 0..200 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            match rng.gen_range(0..4) {
+                0 => tree.clear(&position).ok().unwrap(),
+                1 => tree
+                    .insert_at_lod(&position, 2, rng.gen_range(1..3))
+                    .ok()
+                    .unwrap(),
+                _ => tree.insert(&position, rng.gen_range(1..3)).ok().unwrap(),
+            }
+        }
+        let mut voxels = 0;
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    voxels += tree.get(&V3c::new(x, y, z)).is_some() as u32;
+                }
+            }
+        }
+        assert!(matches!(
+            tree.nodes.get(Octree::<u32, 4>::ROOT_NODE_KEY as usize),
+            NodeContent::Internal(count) if *count == voxels
+        ));
+    }
+
+    #[test]
+    fn test_size_dim_and_depth() {
+        let tree = Octree::<u32, 4>::new(32).ok().unwrap();
+        assert_eq!((tree.size(), tree.dim(), tree.depth()), (32, 4, 4));
+        let tree = Octree::<u32, 3>::new(12).ok().unwrap();
+        assert_eq!((tree.size(), tree.dim(), tree.depth()), (12, 3, 3));
+        let tree = Octree::<u32, 1>::new(1).ok().unwrap();
+        assert_eq!((tree.size(), tree.dim(), tree.depth()), (1, 1, 1));
+
+        // A single voxel is stored in a leaf on the deepest level of nodes
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        tree.insert(&V3c::new(5, 9, 3), 1).ok().unwrap();
+        let mut node_key = Octree::<u32, 2>::ROOT_NODE_KEY;
+        let mut levels = 1;
+        while let NodeContent::Internal(_) = tree.nodes.get(node_key as usize) {
+            node_key = crate::spatial::math::Octant::iter()
+                .map(|octant| tree.node_children[node_key as usize][octant])
+                .find(|child| crate::object_pool::key_might_be_valid(*child))
+                .unwrap();
+            levels += 1;
+        }
+        assert_eq!(tree.depth(), levels);
+    }
+
+    #[test]
+    fn test_overlapping_region_edits_count_changed_voxels() {
+        let mut tree = Octree::<u32, 4>::new(16).ok().unwrap();
+        let root_count = |tree: &Octree<u32, 4>| match tree
+            .nodes
+            .get(Octree::<u32, 4>::ROOT_NODE_KEY as usize)
+        {
+            NodeContent::Internal(count) => *count,
+            _ => 0,
+        };
+
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 2, 1).ok().unwrap();
+        assert_eq!(root_count(&tree), 8);
+
+        // Only the voxels outside of the previous insert are added
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 2).ok().unwrap();
+        assert_eq!(root_count(&tree), 64);
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 2).ok().unwrap();
+        assert_eq!(root_count(&tree), 64);
+        tree.insert(&V3c::new(5, 5, 5), 3).ok().unwrap();
+        assert_eq!(root_count(&tree), 65);
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, 3).ok().unwrap();
+        assert_eq!(root_count(&tree), 128);
+
+        // Only the voxels present before the clear are removed
+        tree.clear_at_lod(&V3c::new(0, 0, 0), 2).ok().unwrap();
+        assert_eq!(root_count(&tree), 120);
+        tree.clear_at_lod(&V3c::new(0, 0, 0), 2).ok().unwrap();
+        assert_eq!(root_count(&tree), 120);
+        tree.clear(&V3c::new(1, 1, 1)).ok().unwrap();
+        assert_eq!(root_count(&tree), 120);
+        tree.clear_at_lod(&V3c::new(0, 0, 0), 4).ok().unwrap();
+        assert_eq!(root_count(&tree), 64);
+        tree.clear_at_lod(&V3c::new(4, 4, 4), 4).ok().unwrap();
+        assert!(tree.is_empty());
+    }
+
+    /// Compares the occupancy queries of a tree with randomly edited voxels to the voxels inside it
+    fn compare_occupancy<const DIM: usize>() {
+        let mut rng = StdRng::seed_from_u64(seed("test_occupancy_queries"));
+        let mut tree = Octree::<u32, DIM>::new(16).ok().unwrap();
+        assert!(tree.is_empty());
+        assert!(!tree.contains_any_in(&Cube::new(V3c::new(0, 0, 0), 16)));
+        let mut voxels = Vec::new();
+        for _ in 0..10 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, 5).ok().unwrap();
+            voxels.push(position);
+            assert!(!tree.is_empty());
+        }
+        tree.insert_at_lod(&V3c::new(8, 8, 8), 4, 6).ok().unwrap();
+        tree.clear_at_lod(&V3c::new(8, 8, 8), 2).ok().unwrap();
+
+        for _ in 0..200 {
+            let size = rng.gen_range(1..8);
+            let bounds = Cube::new(
+                V3c::new(
+                    rng.gen_range(0..16 - size),
+                    rng.gen_range(0..16 - size),
+                    rng.gen_range(0..16 - size),
+                ),
+                size,
+            );
+            let mut expected = false;
+            for x in bounds.min_position.x..bounds.min_position.x + size {
+                for y in bounds.min_position.y..bounds.min_position.y + size {
+                    for z in bounds.min_position.z..bounds.min_position.z + size {
+                        expected |= tree.get(&V3c::new(x, y, z)).is_some();
+                    }
+                }
+            }
+            assert!(tree.contains_any_in(&bounds) == expected);
+        }
+
+        // The tree is empty again once every voxel is cleared, even if its leaves remain
+        tree.clear_at_lod(&V3c::new(8, 8, 8), 4).ok().unwrap();
+        for position in voxels {
+            tree.clear(&position).ok().unwrap();
+        }
+        assert!(tree.is_empty());
+        assert!(!tree.contains_any_in(&Cube::new(V3c::new(0, 0, 0), 16)));
+    }
+
+    #[test]
+    fn test_occupancy_queries() {
+        compare_occupancy::<1>();
+        compare_occupancy::<2>();
+        compare_occupancy::<4>();
+    }
+}
+
+#[cfg(test)]
+mod octree_memory_budget_tests {
+    use crate::octree::types::{CapacityPolicy, Octree, OctreeError};
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_insert_out_of_budget() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.set_memory_budget(Some(tree.memory_usage() - 1));
+        assert!(matches!(
+            tree.insert(&V3c::new(7, 7, 7), 5),
+            Err(OctreeError::OutOfBudget { .. })
+        ));
+        assert!(tree.get(&V3c::new(7, 7, 7)).is_none());
+
+        tree.set_memory_budget(None);
+        assert!(tree.insert(&V3c::new(7, 7, 7), 5).is_ok());
+        assert!(*tree.get(&V3c::new(7, 7, 7)).unwrap() == 5);
+    }
+
+    #[test]
+    fn test_insert_without_allocation_at_budget() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, 6).ok().unwrap();
+        tree.set_memory_budget(Some(tree.memory_usage() - 1));
+
+        // Overwriting voxels in existing bricks or uniform leaves with the same data need no new nodes
+        assert!(tree.insert(&V3c::new(1, 0, 0), 7).is_ok());
+        assert!(tree.insert(&V3c::new(0, 0, 0), 8).is_ok());
+        assert!(tree.insert(&V3c::new(5, 5, 5), 6).is_ok());
+        assert!(*tree.get(&V3c::new(1, 0, 0)).unwrap() == 7);
+        assert!(*tree.get(&V3c::new(0, 0, 0)).unwrap() == 8);
+
+        // Splitting the uniform leaf does
+        assert!(matches!(
+            tree.insert(&V3c::new(5, 5, 5), 7),
+            Err(OctreeError::OutOfBudget { .. })
+        ));
+        assert!(*tree.get(&V3c::new(5, 5, 5)).unwrap() == 6);
+    }
+
+    #[test]
+    fn test_insert_with_eviction_callback() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        let usage_with_one_voxel = tree.memory_usage();
+        tree.insert(&V3c::new(7, 0, 0), 5).ok().unwrap();
+        assert!(tree.memory_usage() > usage_with_one_voxel);
+
+        // Evict the octant of the first voxel to make space for the new ones
+        tree.set_memory_budget(Some(usage_with_one_voxel));
+        tree.set_eviction_callback(Some(Box::new(|tree: &mut Octree<u32>| {
+            tree.clear_at_lod(&V3c::new(0, 0, 0), 4).ok().unwrap();
+        })));
+        tree.insert(&V3c::new(7, 7, 7), 5).ok().unwrap();
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_none());
+        assert!(*tree.get(&V3c::new(7, 0, 0)).unwrap() == 5);
+        assert!(*tree.get(&V3c::new(7, 7, 7)).unwrap() == 5);
+    }
+
+    #[test]
+    fn test_capacity_hint() {
+        // Only the first levels of large trees are preallocated
+        let tree = Octree::<u32, 4>::new(1024).ok().unwrap();
+        assert!(tree.nodes.capacity() < 1000);
+        assert!(tree.bricks.capacity() < 100);
+
+        let mut tree = Octree::<u32, 4>::new(1024)
+            .ok()
+            .unwrap()
+            .with_capacity_hint(10000);
+        assert!(tree.nodes.capacity() >= 10000);
+        assert!(tree.bricks.capacity() >= 8750);
+        tree.insert(&V3c::new(1000, 3, 500), 5).ok().unwrap();
+        assert!(*tree.get(&V3c::new(1000, 3, 500)).unwrap() == 5);
+    }
+
+    #[test]
+    fn test_clear_all_and_reset_to() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        tree.start_recording();
+        for x in 0..16 {
+            tree.insert(&V3c::new(x, x, 15 - x), x + 1).ok().unwrap();
+        }
+        let node_capacity = tree.nodes.capacity();
+        let brick_capacity = tree.bricks.capacity();
+
+        tree.clear_all();
+        assert!(tree.nodes.count() == 1);
+        assert!(tree.bricks.count() == 0);
+        assert!(tree.nodes.capacity() == node_capacity);
+        assert!(tree.bricks.capacity() == brick_capacity);
+        for x in 0..16 {
+            assert!(tree.get(&V3c::new(x, x, 15 - x)).is_none());
+        }
+        let (replayed, errors) = tree.recording().unwrap().replay::<2>();
+        assert!(errors.is_empty());
+        assert!(replayed.get(&V3c::new(3, 3, 12)).is_none());
+
+        tree.insert(&V3c::new(3, 3, 12), 7).ok().unwrap();
+        assert!(*tree.get(&V3c::new(3, 3, 12)).unwrap() == 7);
+
+        assert!(matches!(
+            tree.reset_to(12),
+            Err(OctreeError::InvalidNodeSize(12))
+        ));
+        tree.reset_to(32).ok().unwrap();
+        assert!(tree.get(&V3c::new(3, 3, 12)).is_none());
+        assert!(tree.nodes.capacity() == node_capacity);
+        tree.insert(&V3c::new(31, 0, 20), 9).ok().unwrap();
+        assert!(*tree.get(&V3c::new(31, 0, 20)).unwrap() == 9);
+        assert!(tree.recording().unwrap().octree_size() == 32);
+    }
+
+    #[test]
+    fn test_capacity_policy() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.set_capacity_policy(CapacityPolicy::Fail {
+            max_nodes: tree.nodes.count(),
+        });
+        assert!(matches!(
+            tree.insert(&V3c::new(7, 7, 7), 5),
+            Err(OctreeError::OutOfMemory)
+        ));
+        assert!(tree.get(&V3c::new(7, 7, 7)).is_none());
+
+        // Evicting the octant of the first voxel makes space for the new one
+        tree.set_capacity_policy(CapacityPolicy::Evict {
+            max_nodes: tree.nodes.count(),
+        });
+        assert!(matches!(
+            tree.insert(&V3c::new(7, 7, 7), 5),
+            Err(OctreeError::OutOfMemory)
+        ));
+        tree.set_eviction_callback(Some(Box::new(|tree: &mut Octree<u32>| {
+            tree.clear_at_lod(&V3c::new(0, 0, 0), 4).ok().unwrap();
+        })));
+        tree.insert(&V3c::new(7, 7, 7), 5).ok().unwrap();
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_none());
+        assert!(*tree.get(&V3c::new(7, 7, 7)).unwrap() == 5);
+
+        tree.set_capacity_policy(CapacityPolicy::Grow);
+        tree.insert(&V3c::new(3, 3, 3), 5).ok().unwrap();
+        assert!(*tree.get(&V3c::new(3, 3, 3)).unwrap() == 5);
+    }
+
+    #[test]
+    fn test_capacity_policy_overwrite_at_max_nodes() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, 6).ok().unwrap();
+        let evictions = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted_evictions = evictions.clone();
+        tree.set_eviction_callback(Some(Box::new(move |_: &mut Octree<u32, 2>| {
+            counted_evictions.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        })));
+
+        // Overwriting voxels in existing bricks or uniform leaves with the same data create no nodes
+        for policy in [
+            CapacityPolicy::Fail {
+                max_nodes: tree.nodes.count(),
+            },
+            CapacityPolicy::Evict {
+                max_nodes: tree.nodes.count(),
+            },
+        ] {
+            tree.set_capacity_policy(policy);
+            assert!(tree.insert(&V3c::new(1, 0, 0), 7).is_ok());
+            assert!(tree.insert(&V3c::new(0, 0, 0), 8).is_ok());
+            assert!(tree.insert(&V3c::new(5, 5, 5), 6).is_ok());
+            assert!(*tree.get(&V3c::new(1, 0, 0)).unwrap() == 7);
+            assert!(*tree.get(&V3c::new(0, 0, 0)).unwrap() == 8);
+            assert!(matches!(
+                tree.insert(&V3c::new(5, 5, 5), 7),
+                Err(OctreeError::OutOfMemory)
+            ));
+        }
+        assert!(1 == evictions.load(std::sync::atomic::Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod octree_write_queue_tests {
+    use crate::octree::types::{Octree, OctreeError, OctreeWriteQueue};
+    use crate::spatial::math::vector::V3c;
+    use std::time::Duration;
+
+    #[test]
+    fn test_apply_queued_edits() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        let mut queue = OctreeWriteQueue::new();
+        queue.insert_at_lod(&V3c::new(0, 0, 0), 4, 5);
+        queue.clear(&V3c::new(1, 1, 1));
+        queue.insert(&V3c::new(7, 7, 7), 6);
+        assert!(queue.len() == 3);
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_none());
+
+        queue.apply(&mut tree).ok().unwrap();
+        assert!(queue.is_empty());
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 5));
+        assert!(tree.get(&V3c::new(1, 1, 1)).is_none());
+        assert!(tree.get(&V3c::new(7, 7, 7)).is_some_and(|v| *v == 6));
+    }
+
+    #[test]
+    fn test_apply_queued_edits_for_duration() {
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        let mut queue = OctreeWriteQueue::new();
+        for x in 0..8 {
+            queue.insert(&V3c::new(x, 0, 0), x + 1);
+        }
+
+        // Every call progresses at least one edit, even without any time to spend
+        assert!(matches!(queue.apply_for(&mut tree, Duration::ZERO), Ok(7)));
+        assert!(tree.get(&V3c::new(0, 0, 0)).is_some_and(|v| *v == 1));
+        assert!(tree.get(&V3c::new(1, 0, 0)).is_none());
+
+        assert!(matches!(
+            queue.apply_for(&mut tree, Duration::from_secs(60)),
+            Ok(0)
+        ));
+        for x in 0..8 {
+            assert!(tree.get(&V3c::new(x, 0, 0)).is_some_and(|v| *v == x + 1));
+        }
+    }
+
+    #[test]
+    fn test_pending_edits_overlay() {
+        let tree = Octree::<u32>::new(8).ok().unwrap();
+        let mut queue = OctreeWriteQueue::new();
+        assert!(queue.pending_at(&tree, &V3c::new(1, 1, 1)).is_none());
+
+        queue.insert_at_lod(&V3c::new(5, 5, 5), 4, 5);
+        queue.clear(&V3c::new(6, 6, 6));
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(4, 7, 4)),
+            Some(Some(5))
+        ));
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(6, 6, 6)),
+            Some(None)
+        ));
+        assert!(queue.pending_at(&tree, &V3c::new(3, 4, 4)).is_none());
+
+        // The last edit covering a position wins
+        queue.insert(&V3c::new(6, 6, 6), 7);
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(6, 6, 6)),
+            Some(Some(7))
+        ));
+
+        // Edits outside the tree change nothing
+        queue.insert(&V3c::new(10, 0, 0), 8);
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(6, 6, 6)),
+            Some(Some(7))
+        ));
+    }
+
+    #[test]
+    fn test_pending_edits_overlay_smaller_than_brick() {
+        let mut tree = Octree::<u32, 4>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 16, 3).ok().unwrap();
+        let mut queue = OctreeWriteQueue::new();
+
+        // Edits smaller, than DIM start at their position, moved back inside the brick at its edge
+        queue.insert_at_lod(&V3c::new(1, 1, 1), 2, 5);
+        queue.clear_at_lod(&V3c::new(7, 7, 7), 2);
+        queue.insert_at_lod(&V3c::new(9, 2, 1), 3, 6);
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(2, 2, 2)),
+            Some(Some(5))
+        ));
+        assert!(queue.pending_at(&tree, &V3c::new(0, 1, 1)).is_none());
+        assert!(matches!(
+            queue.pending_at(&tree, &V3c::new(6, 6, 6)),
+            Some(None)
+        ));
+        assert!(queue.pending_at(&tree, &V3c::new(5, 6, 6)).is_none());
+
+        // Edits larger, than DIM cover the node they reach
+        queue.insert_at_lod(&V3c::new(13, 1, 1), 6, 7);
+
+        // The overlay matches the tree once the edits are applied
+        let mut applied = Octree::<u32, 4>::new(16).ok().unwrap();
+        applied
+            .insert_at_lod(&V3c::new(0, 0, 0), 16, 3)
+            .ok()
+            .unwrap();
+        queue.clone().apply(&mut applied).ok().unwrap();
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let position = V3c::new(x, y, z);
+                    let expected = queue
+                        .pending_at(&tree, &position)
+                        .unwrap_or_else(|| tree.get(&position));
+                    assert!(applied.get(&position) == expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_edit_is_removed_from_queue() {
+        let mut tree = Octree::<u32>::new(4).ok().unwrap();
+        let mut queue = OctreeWriteQueue::new();
+        queue.insert(&V3c::new(10, 0, 0), 5);
+        queue.insert(&V3c::new(1, 0, 0), 5);
+        assert!(matches!(
+            queue.apply(&mut tree),
+            Err(OctreeError::InvalidPosition { x: 10, y: 0, z: 0 })
+        ));
+        assert!(queue.len() == 1);
+        queue.apply(&mut tree).ok().unwrap();
+        assert!(tree.get(&V3c::new(1, 0, 0)).is_some_and(|v| *v == 5));
+    }
+}
+
+#[cfg(test)]
+mod octree_atlas_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::{Atlas3dLayout, VoxelData};
+    use crate::spatial::math::vector::V3c;
+
+    /// The albedo stored in the atlas for the voxel at the given position
+    fn atlas_albedo(
+        tree: &Octree<u32, 2>,
+        layout: &Atlas3dLayout,
+        atlas: &[u8],
+        position: &V3c<u32>,
+    ) -> [u8; 4] {
+        let tile = layout.tile_of(tree.brick_at(position).unwrap()).unwrap();
+        let texel =
+            tile * layout.tile_size + V3c::new(position.x % 2, position.y % 2, position.z % 2);
+        let dims = layout.texel_dims();
+        let index = (((texel.z * dims.y + texel.y) * dims.x + texel.x) * 4) as usize;
+        [
+            atlas[index],
+            atlas[index + 1],
+            atlas[index + 2],
+            atlas[index + 3],
+        ]
+    }
+
+    #[test]
+    fn test_pack_bricks_into_atlas() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 0x01020304).ok().unwrap();
+        tree.insert(&V3c::new(1, 0, 1), 0x05060708).ok().unwrap();
+        tree.insert(&V3c::new(7, 6, 5), 0x090A0B0C).ok().unwrap();
+        assert!(tree.brick_at(&V3c::new(4, 4, 4)).is_none());
+
+        let (layout, atlas) = tree.pack_bricks_into_atlas(V3c::new(2, 1, 1)).ok().unwrap();
+        assert!(2 == layout.len());
+        assert!(atlas.len() == (layout.texel_dims().x * 2 * 2 * 4) as usize);
+        assert!(tree.brick_at(&V3c::new(0, 0, 0)) == tree.brick_at(&V3c::new(1, 0, 1)));
+        for position in [V3c::new(0, 0, 0), V3c::new(1, 0, 1), V3c::new(7, 6, 5)] {
+            assert!(
+                atlas_albedo(&tree, &layout, &atlas, &position)
+                    == tree.get(&position).unwrap().albedo()
+            );
+        }
+        assert!([0; 4] == atlas_albedo(&tree, &layout, &atlas, &V3c::new(1, 1, 1)));
+
+        assert!(matches!(
+            tree.pack_bricks_into_atlas(V3c::new(1, 1, 1)),
+            Err(OctreeError::AtlasFull {
+                tiles: 1,
+                required: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_repack_dirty_bricks() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 0x01020304).ok().unwrap();
+        tree.insert(&V3c::new(7, 7, 7), 0x05060708).ok().unwrap();
+        let (mut layout, mut atlas) = tree.pack_bricks_into_atlas(V3c::new(2, 1, 1)).ok().unwrap();
+        assert!(tree
+            .repack_dirty_bricks(&mut layout, &mut atlas)
+            .ok()
+            .unwrap()
+            .is_empty());
+
+        // Modifying a brick rewrites only its tile
+        tree.insert(&V3c::new(1, 1, 1), 0x0A0B0C0D).ok().unwrap();
+        let written = tree
+            .repack_dirty_bricks(&mut layout, &mut atlas)
+            .ok()
+            .unwrap();
+        let tile = layout.tile_of(tree.brick_at(&V3c::new(1, 1, 1)).unwrap());
+        assert!(written == vec![tile.unwrap()]);
+        assert!(
+            [0x0D, 0x0C, 0x0B, 0x0A] == atlas_albedo(&tree, &layout, &atlas, &V3c::new(1, 1, 1))
+        );
+
+        // The tile of a freed brick is reused by new bricks
+        tree.clear_at_lod(&V3c::new(4, 4, 4), 4).ok().unwrap();
+        tree.insert(&V3c::new(4, 0, 0), 0x11121314).ok().unwrap();
+        tree.repack_dirty_bricks(&mut layout, &mut atlas)
+            .ok()
+            .unwrap();
+        assert!(2 == layout.len());
+        assert!(
+            [0x14, 0x13, 0x12, 0x11] == atlas_albedo(&tree, &layout, &atlas, &V3c::new(4, 0, 0))
+        );
+
+        // Bricks beyond the capacity of the atlas are reported
+        tree.insert(&V3c::new(0, 6, 0), 0x15161718).ok().unwrap();
+        assert!(matches!(
+            tree.repack_dirty_bricks(&mut layout, &mut atlas),
+            Err(OctreeError::AtlasFull { .. })
+        ));
+    }
+}
+
+#[cfg(test)]
+mod octree_construction_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::spatial::math::{vector::V3c, Octant};
+
+    /// A ball with a differently colored core
+    fn ball(position: &V3c<u32>) -> Option<u32> {
+        let distance = (V3c::<f32>::from(*position) - V3c::unit(7.5)).length();
+        if distance < 3. {
+            Some(0xFF0000FF)
+        } else if distance < 7. {
+            Some(0xFF00FF00)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        const SIZE: u32 = 16;
+        let tree = Octree::<u32, 2>::generate(SIZE, ball).ok().unwrap();
+        let mut reference = Octree::<u32, 2>::new(SIZE).ok().unwrap();
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    let position = V3c::new(x, y, z);
+                    assert!(tree.get(&position) == ball(&position).as_ref());
+                    if let Some(data) = ball(&position) {
+                        reference.insert(&position, data).ok().unwrap();
+                    }
+                }
+            }
+        }
+        assert!(tree.nodes.count() == reference.nodes.count());
+        assert!(tree.bricks.count() == reference.bricks.count());
+        assert!(tree.memory_usage() == reference.memory_usage());
+
+        // Uniform contents collapse into the root, the smallest trees are built in one piece
+        let tree = Octree::<u32, 2>::generate(SIZE, |_| Some(5)).ok().unwrap();
+        assert!(1 == tree.nodes.count());
+        assert!(Some(&5) == tree.get(&V3c::new(15, 3, 9)));
+        let tree = Octree::<u32, 2>::generate(2, |p| Some(p.x + 1))
+            .ok()
+            .unwrap();
+        assert!(Some(&2) == tree.get(&V3c::new(1, 0, 1)));
+        assert!(Octree::<u32, 2>::generate(SIZE, |_| None)
+            .ok()
+            .unwrap()
+            .get(&V3c::new(0, 0, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_graft() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(0, 0, 0), 1).ok().unwrap();
+        tree.insert(&V3c::new(5, 0, 0), 2).ok().unwrap();
+        let mut subtree = Octree::<u32, 2>::new(4).ok().unwrap();
+        subtree.insert(&V3c::new(1, 2, 3), 3).ok().unwrap();
+        subtree.insert(&V3c::new(3, 3, 3), 4).ok().unwrap();
+
+        // The octant at x: 4..8 is replaced, the other octants are kept
+        tree.graft(1, subtree).ok().unwrap();
+        assert!(Some(&1) == tree.get(&V3c::new(0, 0, 0)));
+        assert!(tree.get(&V3c::new(5, 0, 0)).is_none());
+        assert!(Some(&3) == tree.get(&V3c::new(5, 2, 3)));
+        assert!(Some(&4) == tree.get(&V3c::new(7, 3, 3)));
+
+        // Editing the grafted contents works like with any other node
+        tree.insert(&V3c::new(6, 1, 1), 5).ok().unwrap();
+        tree.clear(&V3c::new(5, 2, 3)).ok().unwrap();
+        assert!(Some(&5) == tree.get(&V3c::new(6, 1, 1)));
+        assert!(tree.get(&V3c::new(5, 2, 3)).is_none());
+
+        // Grafting an empty tree clears the octant, the size of the tree needs to match the octant
+        tree.graft(1, Octree::new(4).ok().unwrap()).ok().unwrap();
+        assert!(tree.get(&V3c::new(7, 3, 3)).is_none());
+        assert!(Some(&1) == tree.get(&V3c::new(0, 0, 0)));
+        assert!(matches!(
+            tree.graft(2, Octree::new(2).ok().unwrap()),
+            Err(OctreeError::InvalidNodeSize(2))
+        ));
+        assert!(matches!(
+            tree.graft(8, Octree::new(4).ok().unwrap()),
+            Err(OctreeError::InvalidOctant(8))
+        ));
+
+        // A leaf root is split up to make place for the grafted tree
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, 1).ok().unwrap();
+        let mut subtree = Octree::<u32, 2>::new(4).ok().unwrap();
+        subtree.insert(&V3c::new(0, 0, 0), 2).ok().unwrap();
+        tree.graft(7, subtree).ok().unwrap();
+        assert!(Some(&2) == tree.get(&V3c::new(4, 4, 4)));
+        assert!(tree.get(&V3c::new(5, 5, 5)).is_none());
+        assert!(Some(&1) == tree.get(&V3c::new(3, 3, 3)));
+        assert!(Some(&1) == tree.get(&V3c::new(7, 0, 0)));
+    }
+
+    #[test]
+    fn test_split_root() {
+        let tree = Octree::<u32, 2>::generate(16, |position| {
+            Some(position.x + position.y * 16 + position.z * 256 + 1)
+                .filter(|_| position.y < 8 || position.x < 8)
+        })
+        .ok()
+        .unwrap();
+        let subtrees = tree.split_root().ok().unwrap();
+        for (octant, subtree) in subtrees.iter().enumerate() {
+            let offset = Octant::ALL[octant].offset() * 8;
+            if 0 < offset.y && 0 < offset.x {
+                assert!(subtree.is_none());
+                continue;
+            }
+            let subtree = subtree.as_ref().unwrap();
+            for x in 0..8 {
+                for y in 0..8 {
+                    for z in 0..8 {
+                        let position = offset + V3c::new(x, y, z);
+                        assert!(
+                            Some(&(position.x + position.y * 16 + position.z * 256 + 1))
+                                == subtree.get(&V3c::new(x, y, z))
+                        );
+                    }
+                }
+            }
+        }
+
+        // Grafting the subtrees back restores the tree
+        let mut restored = Octree::<u32, 2>::new(16).ok().unwrap();
+        for (octant, subtree) in subtrees.into_iter().enumerate() {
+            if let Some(subtree) = subtree {
+                restored.graft(octant as u32, subtree).ok().unwrap();
+            }
+        }
+        assert!(Some(&(3 + 9 * 16 + 10 * 256 + 1)) == restored.get(&V3c::new(3, 9, 10)));
+        assert!(restored.get(&V3c::new(9, 9, 10)).is_none());
+
+        // Octants of a uniform tree are uniform as well, the smallest trees can not be split
+        let mut tree = Octree::<u32, 2>::new(4).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 5).ok().unwrap();
+        let subtrees = tree.split_root().ok().unwrap();
+        assert!(subtrees
+            .iter()
+            .all(|subtree| Some(&5) == subtree.as_ref().unwrap().get(&V3c::new(1, 1, 1))));
+        assert!(matches!(
+            Octree::<u32, 2>::new(2).ok().unwrap().split_root(),
+            Err(OctreeError::InvalidNodeSize(1))
+        ));
+    }
+
+    #[test]
+    fn test_from_dense() {
+        const SIZE: u32 = 8;
+        let mut voxels = vec![0; (SIZE * SIZE * SIZE) as usize];
+        voxels[((3 * SIZE + 2) * SIZE + 1) as usize] = 5;
+        voxels[((7 * SIZE + 7) * SIZE + 7) as usize] = 6;
+        let tree = Octree::<u32>::from_dense(SIZE, &voxels).ok().unwrap();
+        assert!(Some(&5) == tree.get(&V3c::new(1, 2, 3)));
+        assert!(Some(&6) == tree.get(&V3c::new(7, 7, 7)));
+        assert!(tree.get(&V3c::new(3, 2, 1)).is_none());
+
+        assert!(matches!(
+            Octree::<u32>::from_dense(SIZE, &voxels[1..]),
+            Err(OctreeError::InvalidDenseData {
+                expected: 512,
+                actual: 511
+            })
+        ));
+    }
+
+    /// Asserts that both trees contain the same voxels
+    fn assert_same_voxels<const DIM: usize, const OTHER_DIM: usize>(
+        tree: &Octree<u32, DIM>,
+        other: &Octree<u32, OTHER_DIM>,
+    ) {
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let position = V3c::new(x, y, z);
+                    assert!(tree.get(&position) == other.get(&position));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rebrick() {
+        let mut tree = Octree::<u32, 2>::generate(16, ball)
+            .ok()
+            .unwrap()
+            .with_voxel_size(0.5);
+        tree.insert_at_lod(&V3c::new(8, 8, 8), 8, 0xFF000005)
+            .ok()
+            .unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 0xFF000006)
+            .ok()
+            .unwrap();
+
+        let larger = tree.rebrick::<8>().ok().unwrap();
+        assert_same_voxels(&tree, &larger);
+        assert!(larger.voxel_size() == 0.5);
+        assert!(larger.auto_simplify);
+        assert!(larger.memory_report().by_depth.len() < tree.memory_report().by_depth.len());
+
+        let smaller = larger.rebrick::<1>().ok().unwrap();
+        assert_same_voxels(&tree, &smaller);
+        assert_same_voxels(&tree, &smaller.rebrick::<4>().ok().unwrap());
+
+        assert!(matches!(
+            Octree::<u32, 2>::new(2).ok().unwrap().rebrick::<4>(),
+            Err(OctreeError::InvalidNodeSize(2))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod octree_lighting_tests {
+    use crate::octree::types::Octree;
+    use crate::octree::{VoxelData, MAX_LIGHT_LEVEL};
+    use crate::spatial::math::vector::V3c;
+
+    /// A voxel emitting light at the level stored in it
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Lamp(u8);
+
+    impl VoxelData for Lamp {
+        fn new(r: u8, _g: u8, _b: u8, _a: u8, _user_data: u32) -> Self {
+            Self(r)
+        }
+        fn albedo(&self) -> [u8; 4] {
+            [self.0; 4]
+        }
+        fn user_data(&self) -> u32 {
+            0
+        }
+        fn clear(&mut self) {
+            *self = Self::default();
+        }
+        fn light_emission(&self) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_light_before_propagation() {
+        let tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        assert!(tree.light_at(&V3c::new(0, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_open_sky() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(3, 0, 3), 5).ok().unwrap();
+        tree.propagate_light().ok().unwrap();
+        assert!(tree.light_at(&V3c::new(0, 0, 0)).unwrap().sky == MAX_LIGHT_LEVEL);
+        assert!(tree.light_at(&V3c::new(3, 1, 3)).unwrap().sky == MAX_LIGHT_LEVEL);
+        assert!(tree.light_at(&V3c::new(3, 0, 3)).unwrap().sky == 0);
+        assert!(tree.light_at(&V3c::new(8, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_shadow_under_overhang() {
+        // A roof covering x in 0..4 at height 4, open to the sky beyond it
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..4 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 4, z), 5).ok().unwrap();
+            }
+        }
+        tree.propagate_light().ok().unwrap();
+        for x in 0..4 {
+            assert!(
+                tree.light_at(&V3c::new(x, 0, 2)).unwrap().sky == MAX_LIGHT_LEVEL - (4 - x) as u8
+            );
+        }
+        assert!(tree.light_at(&V3c::new(4, 0, 2)).unwrap().sky == MAX_LIGHT_LEVEL);
+        assert!(tree.light_at(&V3c::new(2, 5, 2)).unwrap().sky == MAX_LIGHT_LEVEL);
+    }
+
+    #[test]
+    fn test_block_light_falloff() {
+        let mut tree = Octree::<Lamp, 2>::new(16).ok().unwrap();
+        tree.insert(&V3c::new(2, 2, 2), Lamp(10)).ok().unwrap();
+        tree.propagate_light().ok().unwrap();
+        assert!(tree.light_at(&V3c::new(2, 2, 2)).unwrap().block == 10);
+        assert!(tree.light_at(&V3c::new(3, 2, 2)).unwrap().block == 9);
+        assert!(tree.light_at(&V3c::new(5, 3, 2)).unwrap().block == 6);
+        assert!(tree.light_at(&V3c::new(14, 2, 2)).unwrap().block == 0);
+    }
+}
+
+#[cfg(test)]
+mod octree_fluid_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::MAX_FLUID_LEVEL;
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_add_fluid() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        assert!(0 == tree.fluid_volume());
+        assert!(tree
+            .add_fluid(&V3c::new(3, 2, 1), 5)
+            .is_ok_and(|added| added == 5));
+        assert!(tree
+            .add_fluid(&V3c::new(3, 2, 1), 5)
+            .is_ok_and(|added| added == MAX_FLUID_LEVEL - 5));
+        assert!(tree
+            .add_fluid(&V3c::new(1, 1, 1), 5)
+            .is_ok_and(|added| added == 0));
+        assert!(matches!(
+            tree.add_fluid(&V3c::new(8, 0, 0), 5),
+            Err(OctreeError::InvalidPosition { x: 8, y: 0, z: 0 })
+        ));
+        assert!(MAX_FLUID_LEVEL == tree.fluid_at(&V3c::new(3, 2, 1)));
+        assert!(0 == tree.fluid_at(&V3c::new(1, 1, 1)));
+        assert!(MAX_FLUID_LEVEL as u32 == tree.fluid_volume());
+    }
+
+    #[test]
+    fn test_fluid_falls_and_spreads() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.add_fluid(&V3c::new(4, 6, 4), MAX_FLUID_LEVEL)
+            .ok()
+            .unwrap();
+        tree.add_fluid(&V3c::new(4, 7, 4), 3).ok().unwrap();
+        let volume = tree.fluid_volume();
+        for _ in 0..32 {
+            tree.step_fluids();
+            assert!(volume == tree.fluid_volume());
+        }
+        for x in 0..8 {
+            for y in 1..8 {
+                for z in 0..8 {
+                    assert!(0 == tree.fluid_at(&V3c::new(x, y, z)));
+                }
+            }
+        }
+        assert!(tree.fluid_at(&V3c::new(4, 0, 4)) < MAX_FLUID_LEVEL);
+        assert!(0 < tree.fluid_at(&V3c::new(3, 0, 4)));
+    }
+
+    #[test]
+    fn test_fluid_blocked_by_voxels() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(2, 1, 2), 5).ok().unwrap();
+        tree.add_fluid(&V3c::new(2, 2, 2), 1).ok().unwrap();
+        for _ in 0..8 {
+            tree.step_fluids();
+        }
+        assert!(1 == tree.fluid_at(&V3c::new(2, 2, 2)));
+        assert!(1 == tree.fluid_volume());
+    }
+}
+
+#[cfg(test)]
+mod octree_field_tests {
+    use crate::octree::fields::{diffuse, ScalarChannel};
+    use crate::octree::types::Octree;
+    use crate::octree::VoxelData;
+    use crate::spatial::math::vector::V3c;
+
+    #[derive(Default, Clone, Copy, Debug, PartialEq)]
+    struct Heat {
+        solid: bool,
+        temperature: f32,
+    }
+
+    impl VoxelData for Heat {
+        fn new(r: u8, _g: u8, _b: u8, _a: u8, _user_data: u32) -> Self {
+            Self {
+                solid: true,
+                temperature: r as f32,
+            }
+        }
+        fn albedo(&self) -> [u8; 4] {
+            [if self.solid { 255 } else { 0 }; 4]
+        }
+        fn user_data(&self) -> u32 {
+            0
+        }
+        fn clear(&mut self) {
+            *self = Self::default();
+        }
+    }
+
+    struct Temperature;
+
+    impl ScalarChannel<Heat> for Temperature {
+        fn value(&self, voxel: &Heat) -> f32 {
+            voxel.temperature
+        }
+        fn set_value(&self, voxel: &mut Heat, value: f32) {
+            voxel.temperature = value;
+        }
+    }
+
+    fn temperature_at(tree: &Octree<Heat, 2>, x: u32, y: u32, z: u32) -> f32 {
+        tree.get(&V3c::new(x, y, z)).unwrap().temperature
+    }
+
+    #[test]
+    fn test_uniform_field_is_untouched() {
+        let mut tree = Octree::<Heat, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, Heat::new(20, 0, 0, 0, 0))
+            .ok()
+            .unwrap();
+        let node_count = tree.nodes.count();
+        diffuse(&mut tree, &Temperature, 0.1, 1.).ok().unwrap();
+        assert!(node_count == tree.nodes.count());
+        assert!(20. == temperature_at(&tree, 4, 4, 4));
+    }
+
+    #[test]
+    fn test_heat_spreads_and_is_conserved() {
+        let mut tree = Octree::<Heat, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, Heat::new(10, 0, 0, 0, 0))
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(3, 3, 3), Heat::new(70, 0, 0, 0, 0))
+            .ok()
+            .unwrap();
+        diffuse(&mut tree, &Temperature, 0.1, 1.).ok().unwrap();
+        assert!((temperature_at(&tree, 3, 3, 3) - (70. - 6. * 6.)).abs() < 0.001);
+        assert!((temperature_at(&tree, 4, 3, 3) - 16.).abs() < 0.001);
+        assert!((temperature_at(&tree, 3, 2, 3) - 16.).abs() < 0.001);
+        assert!(10. == temperature_at(&tree, 5, 3, 3));
+
+        let mut total = 0.;
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    total += temperature_at(&tree, x, y, z);
+                }
+            }
+        }
+        assert!((total - (10. * 511. + 70.)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_empty_voxels_insulate() {
+        let mut tree = Octree::<Heat, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(1, 1, 1), Heat::new(50, 0, 0, 0, 0))
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(3, 1, 1), Heat::new(0, 0, 0, 0, 0))
+            .ok()
+            .unwrap();
+        diffuse(&mut tree, &Temperature, 0.1, 1.).ok().unwrap();
+        assert!(50. == temperature_at(&tree, 1, 1, 1));
+        assert!(0. == temperature_at(&tree, 3, 1, 1));
+    }
+}
+
+#[cfg(test)]
+mod octree_face_tests {
+    use crate::octree::types::Octree;
+    use crate::octree::{BoxFace, Cube};
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_visible_faces_of_single_voxel() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(3, 4, 5), 5).ok().unwrap();
+        let faces = tree
+            .visible_faces_in(&Cube::root_bounds(8))
+            .collect::<Vec<_>>();
+        assert!(6 == faces.len());
+        for face in BoxFace::ALL {
+            assert!(faces.contains(&(V3c::new(3, 4, 5), face)));
+        }
+    }
+
+    #[test]
+    fn test_visible_faces_between_bricks() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        // In the same brick, and in the neighbouring one
+        tree.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+        tree.insert(&V3c::new(1, 0, 0), 5).ok().unwrap();
+        tree.insert(&V3c::new(2, 0, 0), 5).ok().unwrap();
+        let faces = tree
+            .visible_faces_in(&Cube::root_bounds(8))
+            .collect::<Vec<_>>();
+        assert!(14 == faces.len());
+        assert!(!faces.contains(&(V3c::new(1, 0, 0), BoxFace::PositiveX)));
+        assert!(!faces.contains(&(V3c::new(2, 0, 0), BoxFace::NegativeX)));
+        assert!(faces.contains(&(V3c::new(2, 0, 0), BoxFace::PositiveX)));
+        assert!(faces.contains(&(V3c::new(0, 0, 0), BoxFace::NegativeX)));
+    }
+
+    #[test]
+    fn test_visible_faces_of_uniform_region() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, 5).ok().unwrap();
+        assert!(6 * 16 == tree.visible_faces_in(&Cube::root_bounds(8)).count());
+
+        // Touching the uniform region only hides the faces in contact
+        tree.insert(&V3c::new(3, 5, 5), 5).ok().unwrap();
+        let faces = tree
+            .visible_faces_in(&Cube::root_bounds(8))
+            .collect::<Vec<_>>();
+        assert!(6 * 16 - 1 + 5 == faces.len());
+        assert!(!faces.contains(&(V3c::new(4, 5, 5), BoxFace::NegativeX)));
+
+        // Only the faces of voxels inside the bounds
+        let faces = tree
+            .visible_faces_in(&Cube::new(V3c::new(0, 4, 4), 4))
+            .collect::<Vec<_>>();
+        assert!(5 == faces.len());
+        assert!(faces
+            .iter()
+            .all(|(position, _)| *position == V3c::new(3, 5, 5)));
+    }
+}
+
+#[cfg(test)]
+mod octree_world_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::{
+        BoxFace, DistanceStreamingPolicy, LayeredWorld, SeamHandling, StreamingPolicy, VoxelWorld,
+        WorldGet,
+    };
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_world_positions() {
+        let mut world = VoxelWorld::<u32, 2>::new(8).ok().unwrap();
+        assert!(matches!(
+            VoxelWorld::<u32, 2>::new(6),
+            Err(OctreeError::InvalidNodeSize(6))
+        ));
+        assert!(
+            world.chunk_coord_of(&V3c::new(-1, 8, 3)) == (V3c::new(-1, 1, 0), V3c::new(7, 0, 3))
+        );
+        assert!(
+            world.world_position_of(&V3c::new(-1, 1, 0), &V3c::new(7, 0, 3)) == V3c::new(-1, 8, 3)
+        );
+
+        world.insert(&V3c::new(-1, 8, 3), 5).ok().unwrap();
+        assert!(world.get(&V3c::new(-1, 8, 3)).is_some_and(|v| *v == 5));
+        assert!(world
+            .chunk(&V3c::new(-1, 1, 0))
+            .is_some_and(|chunk| chunk.get(&V3c::new(7, 0, 3)).is_some()));
+        assert!(world.get(&V3c::new(0, 8, 3)).is_none());
+        world.clear(&V3c::new(-1, 8, 3)).ok().unwrap();
+        assert!(world.get(&V3c::new(-1, 8, 3)).is_none());
+
+        assert!(world
+            .insert_chunk(V3c::new(0, 0, 0), Octree::new(4).ok().unwrap())
+            .is_err());
+        assert!(world
+            .insert_chunk(V3c::new(0, 0, 0), Octree::new(8).ok().unwrap())
+            .is_ok_and(|replaced| replaced.is_none()));
+        assert!(world.remove_chunk(&V3c::new(0, 0, 0)).is_some());
+    }
+
+    #[test]
+    fn test_visible_faces_across_chunks() {
+        let mut world = VoxelWorld::<u32, 2>::new(8).ok().unwrap();
+        // Touching voxels on both sides of the border of two chunks
+        world.insert(&V3c::new(7, 0, 0), 5).ok().unwrap();
+        world.insert(&V3c::new(8, 0, 0), 5).ok().unwrap();
+        // A voxel at the border of a chunk, with no chunk next to it
+        world.insert(&V3c::new(0, 0, 0), 5).ok().unwrap();
+
+        let faces = world
+            .visible_faces_of_chunk(&V3c::new(0, 0, 0))
+            .collect::<Vec<_>>();
+        assert!(12 - 1 == faces.len());
+        assert!(!faces.contains(&(V3c::new(7, 0, 0), BoxFace::PositiveX)));
+        assert!(faces.contains(&(V3c::new(0, 0, 0), BoxFace::NegativeX)));
+        let faces = world
+            .visible_faces_of_chunk(&V3c::new(1, 0, 0))
+            .collect::<Vec<_>>();
+        assert!(5 == faces.len());
+        assert!(!faces.contains(&(V3c::new(0, 0, 0), BoxFace::NegativeX)));
+
+        // Once the neighbour is removed, only the chunk with the voxel shows the face
+        world.clear(&V3c::new(8, 0, 0)).ok().unwrap();
+        assert!(world
+            .visible_faces_of_chunk(&V3c::new(0, 0, 0))
+            .any(|face| face == (V3c::new(7, 0, 0), BoxFace::PositiveX)));
+        assert!(0 == world.visible_faces_of_chunk(&V3c::new(1, 0, 0)).count());
+        assert!(0 == world.visible_faces_of_chunk(&V3c::new(5, 0, 0)).count());
+    }
+
+    #[test]
+    fn test_world_faces_across_lod_seams() {
+        let mut world = VoxelWorld::<u32>::new(4).ok().unwrap();
+        world.insert(&V3c::new(3, 0, 0), 5).ok().unwrap();
+        world.insert(&V3c::new(4, 0, 0), 5).ok().unwrap();
+        for y in 2..4 {
+            for z in 0..2 {
+                world.insert(&V3c::new(2, y, z), 5).ok().unwrap();
+                world.insert(&V3c::new(3, y, z), 5).ok().unwrap();
+            }
+        }
+        // The coarse version of the neighbour has a voxel next to the filled block, but not next to the single voxel
+        let mut coarse = Octree::<u32>::new(2).ok().unwrap();
+        coarse.insert(&V3c::new(0, 1, 0), 6).ok().unwrap();
+        world
+            .insert_coarse_chunk(V3c::new(1, 0, 0), coarse)
+            .ok()
+            .unwrap();
+        let shown_coarse = |chunk_coord: &V3c<i32>| 0 < chunk_coord.x;
+        let faces = |world: &VoxelWorld<u32>, chunk_coord: V3c<i32>| {
+            world
+                .visible_faces_of_chunk_lod(&chunk_coord, shown_coarse)
+                .collect::<Vec<_>>()
+        };
+        let single_face = (V3c::new(3, 0, 0), BoxFace::PositiveX);
+        let coarse_face = (V3c::new(0, 1, 0), BoxFace::NegativeX);
+
+        // Stitched seams show exactly the faces not covered by the neighbour as it is shown
+        assert!(SeamHandling::Stitched == world.seam_handling());
+        assert!(faces(&world, V3c::new(0, 0, 0)).contains(&single_face));
+        assert!(
+            !faces(&world, V3c::new(0, 0, 0)).contains(&(V3c::new(3, 2, 0), BoxFace::PositiveX))
+        );
+        assert!(!faces(&world, V3c::new(1, 0, 0)).contains(&coarse_face));
+        assert!(faces(&world, V3c::new(1, 0, 0)).len() == 5);
+
+        // Ignoring seams samples the neighbour in the wrong resolution, leaving a crack
+        world.set_seam_handling(SeamHandling::Ignore);
+        assert!(!faces(&world, V3c::new(0, 0, 0)).contains(&single_face));
+
+        // Skirts keep every face towards a neighbour in another resolution
+        world.set_seam_handling(SeamHandling::Skirts);
+        assert!(faces(&world, V3c::new(0, 0, 0)).contains(&single_face));
+        assert!(faces(&world, V3c::new(0, 0, 0)).contains(&(V3c::new(3, 2, 0), BoxFace::PositiveX)));
+        assert!(faces(&world, V3c::new(1, 0, 0)).contains(&coarse_face));
+
+        // Behind a partially filled neighbour the face of a coarse voxel is kept
+        world.set_seam_handling(SeamHandling::Stitched);
+        world.clear(&V3c::new(3, 3, 1)).ok().unwrap();
+        assert!(faces(&world, V3c::new(1, 0, 0)).contains(&coarse_face));
+    }
+
+    #[test]
+    fn test_layered_world() {
+        let mut world = LayeredWorld::<u32>::new(4).ok().unwrap();
+        world
+            .layer("terrain")
+            .insert(&V3c::new(1, 0, 1), 5)
+            .ok()
+            .unwrap();
+        world
+            .layer("terrain")
+            .insert(&V3c::new(2, 0, 1), 5)
+            .ok()
+            .unwrap();
+        world
+            .layer("props")
+            .insert(&V3c::new(1, 0, 1), 6)
+            .ok()
+            .unwrap();
+        assert!(world.layer_names().eq(["terrain", "props"]));
+
+        // Later layers are on top
+        assert!(world.get(&V3c::new(1, 0, 1)).is_some_and(|v| *v == 6));
+        assert!(world.get(&V3c::new(2, 0, 1)).is_some_and(|v| *v == 5));
+        assert!(world.move_layer("props", 0));
+        assert!(world.layer_names().eq(["props", "terrain"]));
+        assert!(world.get(&V3c::new(1, 0, 1)).is_some_and(|v| *v == 5));
+        assert!(!world.move_layer("markup", 0));
+
+        // Layers are stored separately
+        assert!(world
+            .get_layer("props")
+            .unwrap()
+            .get(&V3c::new(2, 0, 1))
+            .is_none());
+        assert!(world.remove_layer("terrain").is_some());
+        assert!(world.get(&V3c::new(2, 0, 1)).is_none());
+        assert!(world.get_layer("terrain").is_none());
+
+        assert!(matches!(
+            world.insert_layer("terrain", VoxelWorld::new(8).ok().unwrap()),
+            Err(OctreeError::InvalidNodeSize(8))
+        ));
+    }
+
+    #[test]
+    fn test_world_layer_serialization() {
+        let mut world = LayeredWorld::<u32>::new(4).ok().unwrap();
+        world
+            .layer("props")
+            .insert(&V3c::new(-3, 2, 7), 5)
+            .ok()
+            .unwrap();
+        world
+            .layer("props")
+            .insert(&V3c::new(1, 1, 1), 6)
+            .ok()
+            .unwrap();
+
+        let bytes = world.get_layer("props").unwrap().to_bytes();
+        let mut other = LayeredWorld::<u32>::new(4).ok().unwrap();
+        assert!(other
+            .insert_layer("props", VoxelWorld::from_bytes(bytes))
+            .is_ok_and(|replaced| replaced.is_none()));
+        assert!(other.get(&V3c::new(-3, 2, 7)).is_some_and(|v| *v == 5));
+        assert!(other.get(&V3c::new(1, 1, 1)).is_some_and(|v| *v == 6));
+        assert!(other.get_layer("props").unwrap().chunks().count() == 2);
+    }
+
+    #[test]
+    fn test_world_streaming_plan() {
+        let policy = DistanceStreamingPolicy {
+            load_radius: 1,
+            keep_radius: 2,
+            loads_per_tick: 3,
+        };
+        let world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+
+        // The closest chunks are loaded first, a few per tick
+        let plan = world.plan_streaming(&V3c::new(5, 5, 5), &policy);
+        assert!(plan.load.len() == 3);
+        assert!(plan.load[0] == V3c::new(1, 1, 1));
+        assert!(plan.evict.is_empty());
+        assert!(policy.prefetch_order(&V3c::new(1, 1, 1)).len() == 7);
+    }
+
+    #[test]
+    fn test_world_query_unknown_chunks() {
+        let mut world = VoxelWorld::<u32>::new(4).ok().unwrap();
+        world.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        world.insert_empty_chunk(V3c::new(1, 0, 0));
+        assert!(world.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(world.query(&V3c::new(2, 1, 1)) == WorldGet::Empty);
+        assert!(world.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(world.query(&V3c::new(-1, 1, 1)) == WorldGet::Unknown);
+        assert!(world.get(&V3c::new(-1, 1, 1)).is_none());
+        assert!(world.is_chunk_loaded(&V3c::new(1, 0, 0)));
+        assert!(!world.is_chunk_loaded(&V3c::new(-1, 0, 0)));
+
+        // The empty chunks are kept when saved
+        let loaded = VoxelWorld::<u32>::from_bytes(world.to_bytes());
+        assert!(loaded.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(loaded.query(&V3c::new(-1, 1, 1)) == WorldGet::Unknown);
+
+        // Unloaded chunks become unknown, empty chunks get their content when it is loaded
+        assert!(world.remove_chunk(&V3c::new(0, 0, 0)).is_some());
+        assert!(world.query(&V3c::new(1, 1, 1)) == WorldGet::Unknown);
+        world.insert(&V3c::new(5, 1, 1), 6).ok().unwrap();
+        assert!(world.query(&V3c::new(5, 1, 1)) == WorldGet::Voxel(&6));
+        assert!(world
+            .insert_empty_chunk(V3c::new(1, 0, 0))
+            .is_some_and(|chunk| chunk.get(&V3c::new(1, 1, 1)).is_some()));
+        assert!(world.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        world.remove_chunk(&V3c::new(1, 0, 0));
+        assert!(world.query(&V3c::new(5, 1, 1)) == WorldGet::Unknown);
+    }
+}
+
+#[cfg(test)]
+mod octree_physics_tests {
+    use crate::octree::physics::extract_colliders;
+    use crate::octree::types::Octree;
+    use crate::octree::{Aabb, Cube};
+    use crate::spatial::math::vector::V3c;
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn volume(boxes: &[Aabb]) -> f32 {
+        boxes
+            .iter()
+            .map(|b| b.size().x * b.size().y * b.size().z)
+            .sum()
+    }
+
+    #[test]
+    fn test_colliders_merge_voxels() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        // A row across multiple bricks
+        for x in 1..6 {
+            tree.insert(&V3c::new(x, 1, 1), 5).ok().unwrap();
+        }
+        let boxes = extract_colliders(&tree, &Cube::root_bounds(8));
+        assert!(1 == boxes.len());
+        assert!(boxes[0] == Aabb::new(V3c::new(1., 1., 1.), V3c::new(6., 2., 2.)));
+
+        // A plate on top of the row at its start
+        for x in 1..4 {
+            for z in 1..4 {
+                tree.insert(&V3c::new(x, 2, z), 5).ok().unwrap();
+            }
+        }
+        let boxes = extract_colliders(&tree, &Cube::root_bounds(8));
+        assert!(boxes.len() <= 3);
+        assert!(5. + 9. == volume(&boxes));
+    }
+
+    #[test]
+    fn test_colliders_of_uniform_regions() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap().with_voxel_size(0.5);
+        tree.insert_at_lod(&V3c::new(4, 4, 4), 4, 5).ok().unwrap();
+        let boxes = extract_colliders(&tree, &Cube::root_bounds(8));
+        assert!(1 == boxes.len());
+        assert!(boxes[0] == Aabb::new(V3c::new(2., 2., 2.), V3c::new(4., 4., 4.)));
+
+        // Only the part inside the bounds
+        let boxes = extract_colliders(&tree, &Cube::new(V3c::new(6, 6, 6), 2));
+        assert!(1 == boxes.len());
+        assert!(boxes[0] == Aabb::new(V3c::new(3., 3., 3.), V3c::new(4., 4., 4.)));
+        assert!(extract_colliders(&tree, &Cube::new(V3c::new(0, 0, 0), 4)).is_empty());
+    }
+
+    #[test]
+    fn test_continuous_overlaps_catch_thin_walls() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap().with_voxel_size(0.5);
+        // A wall one voxel thick, across the path of the object
+        for y in 0..16 {
+            for z in 0..16 {
+                tree.insert(&V3c::new(9, y, z), 5).ok().unwrap();
+            }
+        }
+        // An object of the size of a voxel moving from x = 1 to x = 7 in world units, sampled 8 times
+        let object_at = |x: f32| Aabb::new(V3c::new(x, 3., 3.), V3c::new(x + 0.5, 3.5, 3.5));
+        let path = (0..8)
+            .map(|sample| object_at(1. + sample as f32 * 6. / 7.))
+            .collect::<Vec<_>>();
+        // Neither the start nor the end overlaps the wall, only the samples between them
+        assert!(!tree.continuous_overlaps(&[path[0], path[7]]));
+        assert!(tree.continuous_overlaps(&path));
+
+        // Touching the wall is not overlapping it
+        assert!(!tree.continuous_overlaps(&[object_at(4.), object_at(5.)]));
+        assert!(tree.continuous_overlaps(&[object_at(4.1)]));
+        assert!(!tree.continuous_overlaps(&[]));
+
+        // Boxes outside of the tree do not overlap anything, the ones around the whole tree overlap every voxel
+        assert!(!tree.continuous_overlaps(&[object_at(-2.), object_at(9.)]));
+        assert!(tree.continuous_overlaps(&[Aabb::new(V3c::unit(-1.), V3c::unit(9.))]));
+    }
+
+    #[test]
+    fn test_continuous_overlaps_match_each_box() {
+        let mut rng = StdRng::seed_from_u64(seed("test_continuous_overlaps_match_each_box"));
+        let mut tree = Octree::<u32, 4>::new(32).ok().unwrap();
+        let mut voxels = Vec::new();
+        for _ in 0..40 {
+            let position = V3c::new(
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+            );
+            tree.insert(&position, 5).ok().unwrap();
+            voxels.push(Aabb::from(Cube::new(position, 1)));
+        }
+        tree.insert_at_lod(&V3c::new(16, 0, 16), 8, 6).ok().unwrap();
+        voxels.push(Aabb::from(Cube::new(V3c::new(16, 0, 16), 8)));
+
+        let mut overlapping_paths = 0;
+        for _ in 0..200 {
+            let start = V3c::new(
+                rng.gen_range(-4.0..36.0),
+                rng.gen_range(-4.0..36.0),
+                rng.gen_range(-4.0..36.0),
+            );
+            let motion = V3c::new(
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+            );
+            let size = V3c::unit(rng.gen_range(0.2..3.0));
+            let path = (0..rng.gen_range(1..10))
+                .map(|sample| {
+                    let min = start + motion * (sample as f32 / 10.);
+                    Aabb::new(min, min + size)
+                })
+                .collect::<Vec<_>>();
+            let expected = path
+                .iter()
+                .any(|aabb| voxels.iter().any(|voxel| aabb.intersects(voxel)));
+            assert_eq!(tree.continuous_overlaps(&path), expected);
+            overlapping_paths += expected as u32;
+        }
+        assert!(0 < overlapping_paths && overlapping_paths < 200);
+    }
+}
+
+#[cfg(test)]
+mod octree_broadphase_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::{Aabb, Cube, VoxelBroadPhase};
+    use crate::spatial::math::vector::V3c;
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// The min positions and sizes of the regions with voxels near the given entity
+    fn regions_near(broad_phase: &VoxelBroadPhase<char>, entity: char) -> Vec<(V3c<u32>, u32)> {
+        broad_phase
+            .voxel_regions_near(&entity)
+            .iter()
+            .map(|region| (region.min_position, region.size))
+            .collect()
+    }
+
+    #[test]
+    fn test_broadphase_cell_size() {
+        let tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        assert!(matches!(
+            VoxelBroadPhase::<u32>::new(&tree, 3),
+            Err(OctreeError::InvalidNodeSize(3))
+        ));
+        assert!(matches!(
+            VoxelBroadPhase::<u32>::new(&tree, 32),
+            Err(OctreeError::InvalidNodeSize(32))
+        ));
+        assert_eq!(
+            VoxelBroadPhase::<u32>::new(&tree, 4)
+                .ok()
+                .unwrap()
+                .cell_size(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_broadphase_follows_entities_and_edits() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap().with_voxel_size(0.5);
+        tree.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        tree.insert(&V3c::new(10, 10, 10), 5).ok().unwrap();
+
+        // Edits are only tracked for trees with a broad phase
+        assert!(tree.changed_regions.regions.is_empty());
+        let mut broad_phase = VoxelBroadPhase::new(&tree, 4).ok().unwrap();
+
+        // Boxes are in world units, each voxel being half a unit wide
+        broad_phase.insert('a', Aabb::new(V3c::unit(0.), V3c::unit(1.)));
+        broad_phase.insert('b', Aabb::new(V3c::unit(6.), V3c::unit(7.)));
+        assert_eq!(regions_near(&broad_phase, 'a'), vec![(V3c::unit(0), 4)]);
+        assert!(broad_phase.voxel_regions_near(&'b').is_empty());
+        assert_eq!(
+            broad_phase.entities_near(&Cube::new(V3c::unit(0), 4)),
+            vec!['a']
+        );
+        assert!(broad_phase
+            .entities_near(&Cube::new(V3c::unit(8), 4))
+            .is_empty());
+
+        // Moving an entity
+        broad_phase.insert('b', Aabb::new(V3c::unit(4.5), V3c::unit(5.5)));
+        assert_eq!(regions_near(&broad_phase, 'b'), vec![(V3c::unit(8), 4)]);
+        assert_eq!(
+            broad_phase.entities_near(&Cube::new(V3c::unit(8), 4)),
+            vec!['b']
+        );
+
+        // Edits of the tree are taken over by the update
+        tree.clear(&V3c::new(1, 1, 1)).ok().unwrap();
+        tree.insert(&V3c::new(2, 2, 5), 5).ok().unwrap();
+        broad_phase.insert('c', Aabb::new(V3c::new(0., 0., 1.5), V3c::new(2., 2., 3.)));
+        assert_eq!(regions_near(&broad_phase, 'c'), vec![(V3c::unit(0), 4)]);
+        broad_phase.update_voxels(&tree);
+        assert!(broad_phase.voxel_regions_near(&'a').is_empty());
+        assert_eq!(
+            regions_near(&broad_phase, 'c'),
+            vec![(V3c::new(0, 0, 4), 4)]
+        );
+
+        assert_eq!(
+            broad_phase.remove(&'a'),
+            Some(Aabb::new(V3c::unit(0.), V3c::unit(1.)))
+        );
+        assert!(broad_phase.remove(&'a').is_none());
+        assert!(broad_phase.get(&'a').is_none());
+        assert!(broad_phase.voxel_regions_near(&'a').is_empty());
+        assert!(broad_phase
+            .entities_near(&Cube::new(V3c::unit(0), 2))
+            .is_empty());
+
+        // A resized tree is taken over as a whole
+        tree.reset_to(32).ok().unwrap();
+        tree.insert(&V3c::new(20, 20, 20), 5).ok().unwrap();
+        broad_phase.update_voxels(&tree);
+        broad_phase.insert('d', Aabb::new(V3c::unit(9.5), V3c::unit(10.5)));
+        assert_eq!(regions_near(&broad_phase, 'd'), vec![(V3c::unit(20), 4)]);
+        assert!(broad_phase.voxel_regions_near(&'b').is_empty());
+        assert_eq!(
+            broad_phase.entities_near(&Cube::new(V3c::unit(8), 4)),
+            vec!['b']
+        );
+    }
+
+    #[test]
+    fn test_broadphase_matches_each_entity() {
+        let mut rng = StdRng::seed_from_u64(seed("test_broadphase_matches_each_entity"));
+        let mut tree = Octree::<u32, 4>::new(32).ok().unwrap();
+        let mut broad_phase = VoxelBroadPhase::new(&tree, 8).ok().unwrap();
+        let mut boxes = Vec::new();
+        for entity in 0..30 {
+            let min = V3c::new(
+                rng.gen_range(-4.0..34.0),
+                rng.gen_range(-4.0..34.0),
+                rng.gen_range(-4.0..34.0),
+            );
+            let aabb = Aabb::new(min, min + V3c::unit(rng.gen_range(0.5..6.0)));
+            broad_phase.insert(entity, aabb);
+            boxes.push(aabb);
+        }
+        for _ in 0..5 {
+            for _ in 0..40 {
+                let position = V3c::new(
+                    rng.gen_range(0..32),
+                    rng.gen_range(0..32),
+                    rng.gen_range(0..32),
+                );
+                if rng.gen_bool(0.7) {
+                    tree.insert(&position, 5).ok().unwrap();
+                } else {
+                    tree.clear_at_lod(&position, 4).ok().unwrap();
+                }
+            }
+            broad_phase.update_voxels(&tree);
+
+            for (entity, aabb) in boxes.iter().enumerate() {
+                let mut expected = Vec::new();
+                for x in 0..4 {
+                    for y in 0..4 {
+                        for z in 0..4 {
+                            let cell = Cube::new(V3c::new(x, y, z) * 8, 8);
+                            if aabb.intersects(&Aabb::from(cell)) && tree.contains_any_in(&cell) {
+                                expected.push(cell.min_position);
+                            }
+                        }
+                    }
+                }
+                let mut regions = broad_phase
+                    .voxel_regions_near(&entity)
+                    .iter()
+                    .map(|region| region.min_position)
+                    .collect::<Vec<_>>();
+                regions.sort_by_key(|position| (position.x, position.y, position.z));
+                expected.sort_by_key(|position| (position.x, position.y, position.z));
+                assert_eq!(regions, expected);
+            }
+            let region = Cube::new(
+                V3c::new(
+                    rng.gen_range(0..8),
+                    rng.gen_range(0..8),
+                    rng.gen_range(0..8),
+                ) * 4,
+                4,
+            );
+            let mut near = broad_phase.entities_near(&region);
+            near.sort();
+            let expected = (0..boxes.len())
+                .filter(|entity| boxes[*entity].intersects(&Aabb::from(region)))
+                .collect::<Vec<_>>();
+            assert_eq!(near, expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod octree_anim_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::VoxelAnimation;
+    use crate::spatial::math::vector::V3c;
+
+    /// True if both trees contain the same data at every position
+    fn same_voxels(a: &Octree<u32, 2>, b: &Octree<u32, 2>, size: u32) -> bool {
+        (0..size).all(|x| {
+            (0..size)
+                .all(|y| (0..size).all(|z| a.get(&V3c::new(x, y, z)) == b.get(&V3c::new(x, y, z))))
+        })
+    }
+
+    #[test]
+    fn test_delta_between_trees() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 5).ok().unwrap();
+        tree.insert(&V3c::new(6, 6, 6), 6).ok().unwrap();
+
+        let mut other = Octree::<u32, 2>::new(8).ok().unwrap();
+        other.insert_at_lod(&V3c::new(0, 0, 0), 4, 5).ok().unwrap();
+        other.clear(&V3c::new(1, 1, 1)).ok().unwrap();
+        other.insert(&V3c::new(7, 0, 7), 7).ok().unwrap();
+        other.insert_at_lod(&V3c::new(4, 4, 4), 4, 8).ok().unwrap();
+
+        // Matching trees need no edits
+        assert!(tree.delta_to(&tree).is_ok_and(|edits| edits.is_empty()));
+
+        let edits = tree.delta_to(&other).ok().unwrap();
+        for edit in edits {
+            tree.apply_edit(edit).ok().unwrap();
+        }
+        assert!(same_voxels(&tree, &other, 8));
+
+        assert!(matches!(
+            tree.delta_to(&Octree::new(4).ok().unwrap()),
+            Err(OctreeError::InvalidNodeSize(4))
+        ));
+    }
+
+    #[test]
+    fn test_animation_keyframes() {
+        let mut base = Octree::<u32, 2>::new(8).ok().unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                base.insert(&V3c::new(x, 0, z), 5).ok().unwrap();
+            }
+        }
+
+        // A voxel moving along the floor
+        let frames = (0..4)
+            .map(|i| {
+                let mut frame = Octree::<u32, 2>::new(8).ok().unwrap();
+                for x in 0..8 {
+                    for z in 0..8 {
+                        frame.insert(&V3c::new(x, 0, z), 5).ok().unwrap();
+                    }
+                }
+                frame.insert(&V3c::new(i, 1, 3), 6).ok().unwrap();
+                frame
+            })
+            .collect::<Vec<_>>();
+
+        let mut animation = VoxelAnimation::new(base);
+        for frame in frames.iter() {
+            animation.push_keyframe(frame).ok().unwrap();
+        }
+        assert!(animation.len() == 4);
+        assert!(animation
+            .keyframe_delta(2)
+            .is_some_and(|edits| edits.len() == 1));
+
+        // Any frame can be reconstructed in any order
+        for i in [3, 0, 2, 1] {
+            assert!(same_voxels(
+                &animation.frame(i).ok().unwrap(),
+                &frames[i],
+                8
+            ));
+        }
+        assert!(animation.base().get(&V3c::new(0, 1, 3)).is_none());
+        assert!(matches!(
+            animation.frame(4),
+            Err(OctreeError::InvalidKeyframe(4))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod octree_recorder_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::EditRecorder;
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_record_and_replay_edits() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 5 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        tree.start_recording();
+        tree.insert(&V3c::new(6, 6, 6), 6 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.clear(&V3c::new(1, 1, 1)).ok().unwrap();
+
+        // Failed calls are not recorded
+        let recorded_calls = tree.recording().unwrap().len();
+        assert!(tree.insert(&V3c::new(9, 0, 0), 7).is_err());
+        assert!(tree.clear(&V3c::new(9, 0, 0)).is_err());
+        tree.set_memory_budget(Some(tree.memory_usage() - 1));
+        assert!(matches!(
+            tree.insert(&V3c::new(7, 0, 7), 7),
+            Err(OctreeError::OutOfBudget { .. })
+        ));
+        tree.set_memory_budget(None);
+        assert!(tree.recording().unwrap().len() == recorded_calls);
+        tree.auto_simplify = false;
+        for x in 0..8 {
+            for z in 0..8 {
+                tree.insert(&V3c::new(x, 7, z), 8 | 0xFF000000)
+                    .ok()
+                    .unwrap();
+            }
+        }
+        tree.simplify_incremental(usize::MAX);
+        let recording = tree.stop_recording().unwrap();
+        assert!(tree.recording().is_none());
+
+        // Changes after the recording stopped are not captured
+        tree.insert(&V3c::new(7, 0, 0), 9).ok().unwrap();
+        tree.clear(&V3c::new(7, 0, 0)).ok().unwrap();
+
+        let recording = EditRecorder::<u32>::from_bytes(recording.to_bytes())
+            .ok()
+            .unwrap();
+        let (replayed, errors) = recording.replay::<2>();
+        assert!(errors.is_empty());
+        assert!(!replayed.auto_simplify);
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    let position = V3c::new(x, y, z);
+                    assert!(replayed.get(&position) == tree.get(&position));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod octree_stats_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    fn test_memory_report() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 5).ok().unwrap();
+        tree.insert(&V3c::new(6, 6, 6), 6).ok().unwrap();
+        tree.insert(&V3c::new(7, 0, 0), 7).ok().unwrap();
+        tree.clear(&V3c::new(7, 0, 0)).ok().unwrap();
+
+        let report = tree.memory_report();
+        assert!(report.by_depth.len() == 3);
+        assert!(0 < report.by_depth[0].internal);
+        assert!(0 == report.by_depth[0].leaf + report.by_depth[0].uniform_leaf);
+        assert!(0 < report.by_depth[1].uniform_leaf);
+        assert!(0 < report.by_depth[2].leaf);
+        assert!(report.total.total() - report.total.free_slots == tree.memory_usage());
+
+        let folded = report.to_folded();
+        assert!(folded
+            .lines()
+            .any(|line| line.starts_with("octree;depth_2;leaf ")));
+        assert!(!folded.contains("depth_0;leaf"));
+    }
+}
+
+#[cfg(test)]
+mod octree_minimap_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::{math::vector::V3c, Cube};
+
+    #[test]
+    fn test_project_topdown() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, 200 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(6, 7, 1), 100 << 8 | 0xFF000000)
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(6, 2, 1), 100 | 0xFF000000)
+            .ok()
+            .unwrap();
+
+        // One pixel for each column of the tree
+        let image = tree.project_topdown(&Cube::root_bounds(8), (8, 8));
+        assert!(image.len() == 8 * 8 * 4);
+        let pixel = |image: &[u8], x: usize, z: usize| -> [u8; 4] {
+            image[(z * 8 + x) * 4..(z * 8 + x + 1) * 4]
+                .try_into()
+                .unwrap()
+        };
+        assert!(pixel(&image, 1, 1) == [(200. * 0.7) as u8, 0, 0, 255]);
+        assert!(pixel(&image, 6, 1) == [0, 100, 0, 255]);
+        assert!(pixel(&image, 6, 6) == [0, 0, 0, 0]);
+
+        // Voxels above the bounds are not shown
+        let lower = Cube::new(V3c::new(4, 0, 0), 4);
+        let image = tree.project_topdown(&lower, (8, 8));
+        assert!(pixel(&image, 4, 2) == [(100. * 0.85) as u8, 0, 0, 255]);
+        assert!(pixel(&image, 0, 0) == [0, 0, 0, 0]);
+
+        // Bounds reaching outside the tree are empty there
+        let image = tree.project_topdown(&Cube::new(V3c::new(0, 0, 0), 16), (4, 4));
+        assert!(image[0..4] == [(200. * 0.55) as u8, 0, 0, 255]);
+        assert!(image[4..].iter().all(|channel| 0 == *channel));
+    }
+}
+
+#[cfg(test)]
+mod octree_column_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::math::vector::V3c;
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Reference implementation looking up every voxel of the column from the top
+    fn top_voxel_by_lookup<const DIM: usize>(
+        tree: &Octree<u32, DIM>,
+        x: u32,
+        z: u32,
+    ) -> Option<(u32, &u32)> {
+        (0..16)
+            .rev()
+            .find_map(|y| tree.get(&V3c::new(x, y, z)).map(|data| (y, data)))
+    }
+
+    fn compare_top_voxels<const DIM: usize>() {
+        let mut rng = StdRng::seed_from_u64(seed("test_top_voxel_at"));
+        let mut tree = Octree::<u32, DIM>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, 1).ok().unwrap();
+        for _ in 0..100 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, rng.gen_range(2..10)).ok().unwrap();
+        }
+        tree.clear(&V3c::new(3, 7, 3)).ok().unwrap();
+        for x in 0..16 {
+            for z in 0..16 {
+                assert!(tree.top_voxel_at(x, z) == top_voxel_by_lookup(&tree, x, z));
+            }
+        }
+    }
+
+    #[test]
+    fn test_top_voxel_at() {
+        compare_top_voxels::<1>();
+        compare_top_voxels::<2>();
+        compare_top_voxels::<4>();
+
+        let mut tree = Octree::<u32>::new(8).ok().unwrap();
+        assert!(tree.top_voxel_at(2, 2).is_none());
+        tree.insert(&V3c::new(2, 5, 2), 3).ok().unwrap();
+        tree.insert(&V3c::new(2, 1, 2), 4).ok().unwrap();
+        assert!(tree.top_voxel_at(2, 2) == Some((5, &3)));
+        assert!(tree.top_voxel_at(3, 2).is_none());
+        assert!(tree.top_voxel_at(8, 2).is_none());
+    }
+
+    /// Random columns with the runs in the form `to_rle_columns` gives them back in
+    fn random_rle_columns(rng: &mut StdRng, size: u32) -> Vec<Vec<(u32, u32)>> {
+        (0..(size * size))
+            .map(|_| {
+                let mut column = Vec::<(u32, u32)>::new();
+                let mut end = 0;
+                loop {
+                    let data = rng.gen_range(0..4);
+                    let run_length = rng.gen_range(1..7);
+                    if end + run_length > size {
+                        break;
+                    }
+                    match column.last_mut() {
+                        Some((last_data, last_length)) if *last_data == data => {
+                            *last_length += run_length
+                        }
+                        _ => column.push((data, run_length)),
+                    }
+                    end += run_length;
+                }
+                if column.last().is_some_and(|(data, _)| *data == 0) {
+                    column.pop();
+                }
+                column
+            })
+            .collect()
+    }
+
+    fn compare_rle_columns<const DIM: usize>() {
+        let mut rng = StdRng::seed_from_u64(seed("test_rle_columns"));
+        let columns = random_rle_columns(&mut rng, 16);
+        let tree = Octree::<u32, DIM>::from_rle_columns(16, &columns)
+            .ok()
+            .unwrap();
+        for x in 0..16 {
+            for z in 0..16 {
+                let mut expected = [0; 16];
+                let mut bottom = 0;
+                for (data, run_length) in columns[(z * 16 + x) as usize].iter() {
+                    expected[bottom..(bottom + *run_length as usize)].fill(*data);
+                    bottom += *run_length as usize;
+                }
+                for (y, data) in expected.iter().enumerate() {
+                    let position = V3c::new(x, y as u32, z);
+                    assert!(tree.get(&position).copied().unwrap_or(0) == *data);
+                }
+            }
+        }
+        assert!(tree.to_rle_columns() == columns);
+    }
+
+    #[test]
+    fn test_rle_columns() {
+        compare_rle_columns::<1>();
+        compare_rle_columns::<2>();
+        compare_rle_columns::<4>();
+
+        // Uniform columns are inserted as whole nodes
+        let tree = Octree::<u32>::from_rle_columns(8, &vec![vec![(0, 4), (5, 4)]; 64])
+            .ok()
+            .unwrap();
+        assert!(tree.get(&V3c::new(3, 5, 6)) == Some(&5));
+        assert!(tree.get(&V3c::new(3, 3, 6)).is_none());
+        assert!(tree.to_rle_columns() == vec![vec![(0, 4), (5, 4)]; 64]);
+
+        assert!(Octree::<u32>::from_rle_columns(8, &vec![vec![]; 63]).is_err());
+        assert!(Octree::<u32>::from_rle_columns(8, &vec![vec![(1, 9)]; 64]).is_err());
+    }
+}
+
+#[cfg(test)]
+mod octree_slice_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::{math::vector::V3c, primitives::Axis};
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn compare_slices<const DIM: usize>() {
+        let mut rng = StdRng::seed_from_u64(seed("test_slice"));
+        let mut tree = Octree::<u32, DIM>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(8, 0, 0), 8, 1).ok().unwrap();
+        for _ in 0..200 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, rng.gen_range(2..10)).ok().unwrap();
+        }
+        tree.clear(&V3c::new(9, 1, 1)).ok().unwrap();
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            for coord in 0..16 {
+                let slice = tree.slice(axis, coord).ok().unwrap();
+                assert!(slice.len() == 16 * 16);
+                for v in 0..16 {
+                    for u in 0..16 {
+                        let position = axis.vector_with(coord, (u, v));
+                        assert!(slice[(v * 16 + u) as usize].as_ref() == tree.get(&position));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        compare_slices::<1>();
+        compare_slices::<2>();
+        compare_slices::<4>();
+
+        let tree = Octree::<u32>::new(4).ok().unwrap();
+        assert!(tree.slice(Axis::Y, 4).is_err());
+        assert!(tree
+            .slice(Axis::Y, 3)
+            .is_ok_and(|slice| slice.iter().all(|voxel| voxel.is_none())));
+    }
+
+    #[test]
+    fn test_slice_image() {
+        let mut tree = Octree::<u32>::new(4).ok().unwrap();
+        tree.insert(&V3c::new(1, 2, 3), 0xFF0000FF).ok().unwrap();
+        let image = tree.slice_image(Axis::Z, 3).ok().unwrap();
+        assert!(image.len() == 4 * 4 * 4);
+        let pixel = (2 * 4 + 1) * 4;
+        assert!(image[pixel..pixel + 4] == [255, 0, 0, 255]);
+        assert!(image
+            .iter()
+            .enumerate()
+            .all(|(i, channel)| (pixel..pixel + 4).contains(&i) || 0 == *channel));
+        assert!(tree.slice_image(Axis::X, 4).is_err());
+    }
+}
+
+#[cfg(test)]
+mod octree_handle_tests {
+    use crate::octree::types::Octree;
+    use crate::octree::{NodeKind, V3c};
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    /// Rebuilds every voxel of the given tree by walking its nodes, and compares them to the tree
+    fn compare_walked_voxels<const DIM: usize>() {
+        let mut rng = StdRng::seed_from_u64(seed("test_node_handles"));
+        let mut tree = Octree::<u32, DIM>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(8, 8, 0), 8, 1).ok().unwrap();
+        for _ in 0..100 {
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            tree.insert(&position, rng.gen_range(2..10)).ok().unwrap();
+        }
+
+        let mut walked = vec![None; 16 * 16 * 16];
+        let mut keys = HashSet::new();
+        for node in tree.nodes() {
+            assert!(keys.insert(node.key()));
+            let bounds = node.bounds();
+            let fill = |walked: &mut Vec<Option<u32>>, min: V3c<u32>, size: u32, data: &u32| {
+                for x in min.x..min.x + size {
+                    for y in min.y..min.y + size {
+                        for z in min.z..min.z + size {
+                            walked[(x * 256 + y * 16 + z) as usize] = Some(*data);
+                        }
+                    }
+                }
+            };
+            match node.kind() {
+                NodeKind::Empty => assert!(node.children().iter().all(Option::is_none)),
+                NodeKind::Internal => {
+                    for child in node.children().iter().flatten() {
+                        assert!(child.bounds().size * 2 == bounds.size);
+                    }
+                }
+                NodeKind::UniformLeaf => {
+                    let data = node.uniform_data().unwrap();
+                    if 0 != *data {
+                        fill(&mut walked, bounds.min_position, bounds.size, data);
+                    }
+                }
+                NodeKind::Leaf => {
+                    assert!(node.uniform_data().is_none());
+                    let cell_size = (bounds.size / DIM as u32).max(1);
+                    for (x, plane) in node.leaf_brick().unwrap().iter().enumerate() {
+                        for (y, row) in plane.iter().enumerate() {
+                            for (z, data) in row.iter().enumerate() {
+                                let cell = V3c::new(x as u32, y as u32, z as u32) * cell_size;
+                                // Bricks of leaves smaller than DIM have cells outside the leaf
+                                let inside = cell.x < bounds.size
+                                    && cell.y < bounds.size
+                                    && cell.z < bounds.size;
+                                if 0 != *data && inside {
+                                    fill(&mut walked, bounds.min_position + cell, cell_size, data);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let walked = walked[(x * 256 + y * 16 + z) as usize];
+                    assert!(walked.as_ref() == tree.get(&V3c::new(x, y, z)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_handles() {
+        compare_walked_voxels::<1>();
+        compare_walked_voxels::<2>();
+        compare_walked_voxels::<4>();
+
+        let tree = Octree::<u32>::new(4).ok().unwrap();
+        let root = tree.root_node();
+        assert!(root.bounds().size == 4);
+        assert!(root.leaf_brick().is_none());
+        assert!(tree.nodes().count() == 1);
+    }
+}
+
+#[cfg(all(test, feature = "schematic"))]
+mod octree_schematic_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::math::vector::V3c;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    /// A named NBT tag of the given type with the given payload
+    fn tag(tag_type: u8, name: &str, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag_type];
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    fn compound(tags: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = tags.concat();
+        bytes.push(0);
+        bytes
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        [
+            (value.len() as u16).to_be_bytes().to_vec(),
+            value.as_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    fn palette(block_state: &str) -> Option<u32> {
+        match block_state {
+            "minecraft:stone" => Some(1),
+            "minecraft:oak_log[axis=y]" => Some(2),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_sponge_schematic() {
+        // 3 wide, 2 high, 2 long; stone on the bottom layer, a log on top of one corner
+        let mut block_data = vec![1; 6];
+        block_data.extend([0, 0, 2, 0, 0, 0]);
+        let schematic = tag(
+            10,
+            "Schematic",
+            &compound(&[
+                tag(3, "Version", &2_i32.to_be_bytes()),
+                tag(2, "Width", &3_i16.to_be_bytes()),
+                tag(2, "Height", &2_i16.to_be_bytes()),
+                tag(2, "Length", &2_i16.to_be_bytes()),
+                tag(
+                    10,
+                    "Palette",
+                    &compound(&[
+                        tag(3, "minecraft:air", &0_i32.to_be_bytes()),
+                        tag(3, "minecraft:stone", &1_i32.to_be_bytes()),
+                        tag(3, "minecraft:oak_log[axis=y]", &2_i32.to_be_bytes()),
+                    ]),
+                ),
+                tag(
+                    7,
+                    "BlockData",
+                    &[(block_data.len() as i32).to_be_bytes().to_vec(), block_data].concat(),
+                ),
+            ]),
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&schematic).ok().unwrap();
+        let bytes = encoder.finish().ok().unwrap();
+
+        let tree = Octree::<u32, 2>::from_schematic(&bytes, palette)
+            .ok()
+            .unwrap();
+        assert!(tree.octree_size == 4);
+        for x in 0..3 {
+            for z in 0..2 {
+                assert!(tree.get(&V3c::new(x, 0, z)) == Some(&1));
+            }
+        }
+        assert!(tree.get(&V3c::new(2, 1, 0)) == Some(&2));
+        assert!(tree.get(&V3c::new(1, 1, 0)).is_none());
+        assert!(tree.get(&V3c::new(3, 0, 0)).is_none());
+
+        // Schematics with missing blocks are rejected
+        assert!(
+            Octree::<u32, 2>::from_schematic(&schematic[..schematic.len() - 5], palette).is_err()
+        );
+    }
+
+    #[test]
+    fn test_litematic_schematic() {
+        // A single region of 2x1x3 blocks extending from its position towards -z
+        let vector = |x: i32, y: i32, z: i32| {
+            compound(&[
+                tag(3, "x", &x.to_be_bytes()),
+                tag(3, "y", &y.to_be_bytes()),
+                tag(3, "z", &z.to_be_bytes()),
+            ])
+        };
+        let block_names = [
+            compound(&[tag(8, "Name", &string("minecraft:air"))]),
+            compound(&[tag(8, "Name", &string("minecraft:stone"))]),
+            compound(&[
+                tag(8, "Name", &string("minecraft:oak_log")),
+                tag(10, "Properties", &compound(&[tag(8, "axis", &string("y"))])),
+            ]),
+        ];
+        // 2 bits for each of the 6 blocks: stone, air, log, stone, air, log
+        let states = 0b10_00_01_10_00_01_i64;
+        let region = compound(&[
+            tag(10, "Position", &vector(5, 0, 2)),
+            tag(10, "Size", &vector(2, 1, -3)),
+            tag(
+                9,
+                "BlockStatePalette",
+                &[vec![10], 3_i32.to_be_bytes().to_vec(), block_names.concat()].concat(),
+            ),
+            tag(
+                12,
+                "BlockStates",
+                &[1_i32.to_be_bytes().to_vec(), states.to_be_bytes().to_vec()].concat(),
+            ),
+        ]);
+        let bytes = tag(
+            10,
+            "",
+            &compound(&[tag(10, "Regions", &compound(&[tag(10, "house", &region)]))]),
+        );
+
+        let tree = Octree::<u32, 1>::from_schematic(&bytes, palette)
+            .ok()
+            .unwrap();
+        assert!(tree.octree_size == 4);
+        assert!(tree.get(&V3c::new(0, 0, 0)) == Some(&1));
+        assert!(tree.get(&V3c::new(1, 0, 0)).is_none());
+        assert!(tree.get(&V3c::new(0, 0, 1)) == Some(&2));
+        assert!(tree.get(&V3c::new(1, 0, 1)) == Some(&1));
+        assert!(tree.get(&V3c::new(0, 0, 2)).is_none());
+        assert!(tree.get(&V3c::new(1, 0, 2)) == Some(&2));
+    }
+}
+
+#[cfg(all(test, feature = "vox"))]
+mod octree_vox_tests {
+    use crate::octree::types::Octree;
+    use crate::spatial::math::vector::V3c;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        [
+            id.to_vec(),
+            (content.len() as u32).to_le_bytes().to_vec(),
+            (children.len() as u32).to_le_bytes().to_vec(),
+            content.to_vec(),
+            children.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// A .vox file of a single model with the given size, voxels and optional palette
+    fn vox_file(size: [u32; 3], voxels: &[[u8; 4]], rgba: Option<&[[u8; 4]]>) -> Vec<u8> {
+        let mut children = chunk(
+            b"SIZE",
+            &size
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect::<Vec<_>>(),
+            &[],
+        );
+        children.extend(chunk(
+            b"XYZI",
+            &[
+                (voxels.len() as u32).to_le_bytes().to_vec(),
+                voxels.concat(),
+            ]
+            .concat(),
+            &[],
+        ));
+        if let Some(colors) = rgba {
+            let mut content = vec![0; 256 * 4];
+            for (index, color) in colors.iter().enumerate() {
+                content[index * 4..index * 4 + 4].copy_from_slice(color);
+            }
+            children.extend(chunk(b"RGBA", &content, &[]));
+        }
+        [
+            b"VOX ".to_vec(),
+            150_u32.to_le_bytes().to_vec(),
+            chunk(b"MAIN", &[], &children),
+        ]
+        .concat()
+    }
+
+    fn rgba_to_u32(color: [u8; 4]) -> u32 {
+        u32::from_le_bytes(color)
+    }
+
+    #[test]
+    fn test_vox_model() {
+        // 3 wide, 2 deep, 5 high in MagicaVoxel, which is Z-up
+        let bytes = vox_file(
+            [3, 2, 5],
+            &[[0, 0, 0, 1], [2, 1, 4, 2], [1, 0, 3, 3]],
+            Some(&[[255, 0, 0, 255], [0, 255, 0, 255]]),
+        );
+        let tree = Octree::<u32, 2>::from_vox(&bytes, |index, color| {
+            (3 != index).then(|| rgba_to_u32(color))
+        })
+        .ok()
+        .unwrap();
+        assert!(tree.octree_size == 8);
+        assert!(tree.get(&V3c::new(0, 0, 0)) == Some(&rgba_to_u32([255, 0, 0, 255])));
+        assert!(tree.get(&V3c::new(2, 4, 1)) == Some(&rgba_to_u32([0, 255, 0, 255])));
+        assert!(tree.get(&V3c::new(2, 1, 4)).is_none());
+
+        // Voxels without a voxel in the palette are left empty
+        assert!(tree.get(&V3c::new(1, 3, 0)).is_none());
+    }
+
+    #[test]
+    fn test_vox_default_palette() {
+        let bytes = vox_file(
+            [2, 2, 2],
+            &[[0, 0, 0, 1], [1, 0, 0, 215], [0, 1, 0, 216]],
+            None,
+        );
+        let tree = Octree::<u32, 1>::from_vox(&bytes, |_, color| Some(rgba_to_u32(color)))
+            .ok()
+            .unwrap();
+        assert!(tree.get(&V3c::new(0, 0, 0)) == Some(&rgba_to_u32([255, 255, 255, 255])));
+        assert!(tree.get(&V3c::new(1, 0, 0)) == Some(&rgba_to_u32([0, 0, 0x33, 255])));
+        assert!(tree.get(&V3c::new(0, 0, 1)) == Some(&rgba_to_u32([0xEE, 0, 0, 255])));
+    }
+
+    #[test]
+    fn test_vox_invalid_files() {
+        let bytes = vox_file([2, 2, 2], &[[0, 0, 0, 1], [1, 1, 1, 1]], None);
+        let palette = |_: u8, color: [u8; 4]| Some(u32::from_le_bytes(color));
+        assert!(Octree::<u32, 1>::from_vox(&bytes, palette).is_ok());
+        assert!(Octree::<u32, 1>::from_vox(&bytes[4..], palette).is_err());
+        assert!(Octree::<u32, 1>::from_vox(&bytes[..bytes.len() - 2], palette).is_err());
+        assert!(
+            Octree::<u32, 1>::from_vox(&vox_file([2, 2, 2], &[], None)[..20], palette).is_err()
+        );
+    }
+}
+
+#[cfg(test)]
+mod octree_color_tests {
+    use crate::octree::color::quantize;
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_quantize() {
+        // Few colors are kept exactly
+        let palette = quantize([[255, 0, 0], [0, 0, 255], [255, 0, 0]].into_iter(), 4);
+        assert!(palette.colors() == [[0, 0, 255], [255, 0, 0]]);
+        assert!(palette.nearest(&[200, 10, 10]) == Some(1));
+        assert!(quantize([[1, 2, 3]].into_iter(), 0).is_empty());
+
+        // Clusters of many colors are represented by a color close to each of them
+        let mut rng = StdRng::seed_from_u64(seed("test_quantize"));
+        let centers = [[20, 20, 20], [230, 40, 40], [40, 200, 60], [50, 60, 220]];
+        let colors = (0..4000)
+            .map(|i| {
+                let center = centers[i % centers.len()];
+                center.map(|channel| channel + rng.gen_range(0..20) - 10)
+            })
+            .collect::<Vec<[u8; 3]>>();
+        let palette = quantize(colors.iter().copied(), 16);
+        assert!(palette.len() <= 16);
+        for color in colors.iter() {
+            let nearest = palette.colors()[palette.nearest(color).unwrap()];
+            for channel in 0..3 {
+                assert!((nearest[channel] as i32 - color[channel] as i32).abs() <= 20);
+            }
+        }
+
+        // The order of the colors doesn't matter
+        assert!(quantize(colors.iter().rev().copied(), 16) == palette);
+    }
+}
+
+#[cfg(test)]
+mod octree_progress_tests {
+    use crate::octree::{
+        types::{Octree, OctreeError},
+        CancellationToken, V3c,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_generate_with_progress() {
+        let generator = |position: &V3c<u32>| Some(position.x + position.y).filter(|d| 0 < *d);
+        let reports = AtomicUsize::new(0);
+        let tree = Octree::<u32, 2>::generate_with_progress(
+            16,
+            generator,
+            &|done: usize, total: usize| {
+                assert!(done <= total && total == 64);
+                reports.fetch_add(1, Ordering::Relaxed);
+            },
+            &CancellationToken::new(),
+        )
+        .ok()
+        .unwrap();
+        assert!(reports.load(Ordering::Relaxed) == 64);
+        assert!(tree.get(&V3c::new(3, 4, 5)) == Some(&7));
+
+        // Cancelling from the progress report aborts the generation
+        let cancellation = CancellationToken::new();
+        let result = Octree::<u32, 2>::generate_with_progress(
+            16,
+            generator,
+            &|done: usize, _| {
+                if 3 == done {
+                    cancellation.cancel();
+                }
+            },
+            &cancellation,
+        );
+        assert!(matches!(result, Err(OctreeError::Cancelled)));
+    }
+
+    #[test]
+    fn test_simplify_and_light_with_progress() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.auto_simplify = false;
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, 5).ok().unwrap();
+        for x in 0..8 {
+            tree.insert(&V3c::new(x, 0, 0), 5).ok().unwrap();
+        }
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        assert!(matches!(
+            tree.propagate_light_with_progress(&(), &cancelled),
+            Err(OctreeError::Cancelled)
+        ));
+        assert!(tree.light_at(&V3c::new(0, 0, 0)).is_none());
+
+        let last_report = AtomicUsize::new(0);
+        tree.simplify_with_progress(
+            &|done: usize, total: usize| {
+                assert!(done <= total);
+                last_report.store(done, Ordering::Relaxed);
+            },
+            &CancellationToken::new(),
+        )
+        .ok()
+        .unwrap();
+        assert!(tree.simplify_queue.is_empty());
+        assert!(0 < last_report.load(Ordering::Relaxed));
+        assert!(tree.get(&V3c::new(3, 3, 3)) == Some(&5));
+    }
+}
+
+#[cfg(test)]
+mod octree_aggregate_tests {
+    use crate::octree::{
+        Aggregate, Cube, DensityRange, DominantMaterial, LightLevel, LightSum, OccupancyCount,
+        Octree, V3c, VoxelData,
+    };
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// A voxel of the given material, its density is 10 times the material
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct Block(u32);
+
+    impl VoxelData for Block {
+        fn new(_r: u8, _g: u8, _b: u8, _a: u8, user_data: u32) -> Self {
+            Block(user_data)
+        }
+        fn albedo(&self) -> [u8; 4] {
+            [255, 255, 255, (self.0 * 10) as u8]
+        }
+        fn user_data(&self) -> u32 {
+            self.0
+        }
+        fn is_empty(&self) -> bool {
+            0 == self.0
+        }
+        fn clear(&mut self) {
+            self.0 = 0;
+        }
+    }
+
+    /// The summary of the voxels inside the given bounds, calculated voxel by voxel
+    fn summary_in<A: Aggregate<Block>>(tree: &Octree<Block, 2>, bounds: &Cube) -> A {
+        let mut summary = A::default();
+        for x in 0..bounds.size {
+            for y in 0..bounds.size {
+                for z in 0..bounds.size {
+                    if let Some(voxel) = tree.get(&(bounds.min_position + V3c::new(x, y, z))) {
+                        summary.merge(&A::of_voxels(voxel, 1));
+                    }
+                }
+            }
+        }
+        summary
+    }
+
+    fn assert_summaries_match(tree: &Octree<Block, 2>) {
+        for node in tree.nodes() {
+            let summary = node.aggregate::<DominantMaterial>().unwrap();
+            assert!(*summary == summary_in(tree, &node.bounds()));
+        }
+    }
+
+    #[test]
+    fn test_aggregate_follows_edits() {
+        let mut rng = StdRng::seed_from_u64(seed("test_aggregate_follows_edits"));
+        let mut tree = Octree::<Block, 2>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, Block(1))
+            .ok()
+            .unwrap();
+        assert!(tree.aggregate::<DominantMaterial>().is_none());
+        tree.set_aggregate::<DominantMaterial>();
+        assert!(tree.aggregate::<OccupancyCount>().is_none());
+        assert_summaries_match(&tree);
+
+        for step in 0..200 {
+            tree.auto_simplify = 0 != step % 50;
+            let position = V3c::new(
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+                rng.gen_range(0..16),
+            );
+            let size = [1, 2, 4][rng.gen_range(0..3)];
+            if rng.gen_bool(0.3) {
+                tree.clear_at_lod(&position, size).ok().unwrap();
+            } else {
+                tree.insert_at_lod(&position, size, Block(rng.gen_range(1..4)))
+                    .ok()
+                    .unwrap();
+            }
+            if 0 == step % 20 {
+                tree.simplify_incremental(usize::MAX);
+                assert_summaries_match(&tree);
+            }
+        }
+        assert_summaries_match(&tree);
+
+        // Changes through get_mut are only reflected after a refresh
+        let position = V3c::new(0, 0, 0);
+        tree.insert(&position, Block(1)).ok().unwrap();
+        *tree.get_mut(&position).unwrap() = Block(3);
+        tree.refresh_aggregate();
+        assert_summaries_match(&tree);
+
+        tree.clear_all();
+        assert!(*tree.aggregate::<DominantMaterial>().unwrap() == DominantMaterial::default());
+        assert!(tree
+            .aggregate::<DominantMaterial>()
+            .unwrap()
+            .material()
+            .is_none());
+    }
+
+    #[test]
+    fn test_aggregate_kinds() {
+        let mut tree = Octree::<Block, 2>::new(8).ok().unwrap();
+        tree.set_aggregate::<OccupancyCount>();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 4, Block(2))
+            .ok()
+            .unwrap();
+        tree.insert(&V3c::new(7, 7, 7), Block(5)).ok().unwrap();
+        tree.insert(&V3c::new(6, 7, 7), Block(5)).ok().unwrap();
+        assert!(*tree.aggregate::<OccupancyCount>().unwrap() == OccupancyCount(66));
+
+        tree.set_aggregate::<DominantMaterial>();
+        let materials = tree.aggregate::<DominantMaterial>().unwrap();
+        assert!(materials.material() == Some(2));
+        assert!(materials.count_of(5) == 2);
+
+        tree.set_aggregate::<DensityRange>();
+        assert!(*tree.aggregate::<DensityRange>().unwrap() == DensityRange(Some((20, 50))));
+        let octant_summaries = tree
+            .root_node()
+            .children()
+            .map(|child| child.map(|child| *child.aggregate::<DensityRange>().unwrap()));
+        assert!(octant_summaries[0] == Some(DensityRange(Some((20, 20)))));
+        assert!(octant_summaries[7] == Some(DensityRange(Some((50, 50)))));
+
+        // Grafted subtrees are summarized as well
+        let mut subtree = Octree::<Block, 2>::new(4).ok().unwrap();
+        subtree.insert(&V3c::new(1, 1, 1), Block(9)).ok().unwrap();
+        tree.graft(5, subtree).ok().unwrap();
+        assert!(*tree.aggregate::<DensityRange>().unwrap() == DensityRange(Some((20, 90))));
+
+        let mut light = Octree::<LightLevel, 2>::new(4).ok().unwrap();
+        light.set_aggregate::<LightSum>();
+        light
+            .insert_at_lod(&V3c::new(0, 0, 0), 4, LightLevel { sky: 2, block: 1 })
+            .ok()
+            .unwrap();
+        light.clear(&V3c::new(3, 3, 3)).ok().unwrap();
+        assert!(
+            *light.aggregate::<LightSum>().unwrap()
+                == LightSum {
+                    sky: 126,
+                    block: 63
+                }
+        );
+    }
+}
+
+#[cfg(test)]
+mod octree_auxiliary_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::{V3c, VoxelAux};
+
+    #[test]
+    fn test_auxiliary_data() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(1, 2, 3), 5).ok().unwrap();
+        assert!(tree.auxiliary_at(&V3c::new(1, 2, 3)).is_none());
+
+        let value = VoxelAux {
+            motion: V3c::new(0.5, 0., -1.),
+            id: 42,
+        };
+        tree.set_auxiliary(&V3c::new(1, 2, 3), value).ok().unwrap();
+        assert!(tree.auxiliary_at(&V3c::new(1, 2, 3)) == Some(&value));
+        assert!(tree.auxiliary_at(&V3c::new(1, 2, 2)).is_none());
+        assert!(tree.get(&V3c::new(1, 2, 3)) == Some(&5));
+        assert!(matches!(
+            tree.set_auxiliary(&V3c::new(8, 0, 0), value),
+            Err(OctreeError::InvalidPosition { x: 8, y: 0, z: 0 })
+        ));
+
+        // Setting the default value clears it
+        tree.set_auxiliary(&V3c::new(1, 2, 3), VoxelAux::default())
+            .ok()
+            .unwrap();
+        assert!(tree.auxiliary_at(&V3c::new(1, 2, 3)).is_none());
+
+        tree.set_auxiliary(&V3c::new(4, 4, 4), value).ok().unwrap();
+        tree.clear_auxiliary(&V3c::new(4, 4, 4)).ok().unwrap();
+        assert!(tree.auxiliary_at(&V3c::new(4, 4, 4)).is_none());
+        tree.set_auxiliary(&V3c::new(4, 4, 4), value).ok().unwrap();
+        tree.drop_auxiliary();
+        assert!(tree.auxiliary_at(&V3c::new(4, 4, 4)).is_none());
+    }
+}
+
+#[cfg(test)]
+mod octree_occupancy_tests {
+    use crate::octree::types::{Octree, OctreeError};
+    use crate::octree::BitOctree;
+    use crate::spatial::{math::vector::V3c, Cube};
+    use crate::testing::seed;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    /// Asserts that the occupancy tree has the same voxels occupied as the given tree
+    fn assert_matches<const DIM: usize>(tree: &Octree<u32, DIM>, occupancy: &BitOctree<DIM>) {
+        let size = tree.octree_size;
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let position = V3c::new(x, y, z);
+                    assert!(
+                        tree.get(&position).is_some() == occupancy.get(&position),
+                        "occupancy mismatch at {:?}",
+                        position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_occupancy() {
+        let mut rng = StdRng::seed_from_u64(seed("test_to_occupancy"));
+        let mut tree = Octree::<u32, 4>::new(32).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(16, 16, 16), 16, 3)
+            .ok()
+            .unwrap();
+        tree.clear(&V3c::new(20, 21, 22)).ok().unwrap();
+        for _ in 0..200 {
+            let position = V3c::new(
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+                rng.gen_range(0..32),
+            );
+            tree.insert(&position, rng.gen_range(1..5)).ok().unwrap();
+        }
+
+        let occupancy = tree.to_occupancy();
+        assert!(32 == occupancy.size());
+        assert_matches(&tree, &occupancy);
+        assert!(!occupancy.get(&V3c::new(32, 0, 0)));
+
+        // A uniform tree is stored in a single node
+        let mut full = Octree::<u32, 4>::new(32).ok().unwrap();
+        full.insert_at_lod(&V3c::new(0, 0, 0), 32, 1).ok().unwrap();
+        let occupancy = full.to_occupancy();
+        assert!(occupancy.get(&V3c::new(31, 31, 31)));
+        assert!(occupancy.memory_usage() < 64);
+    }
+
+    #[test]
+    fn test_update_occupancy() {
+        let mut rng = StdRng::seed_from_u64(seed("test_update_occupancy"));
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, 1).ok().unwrap();
+        let mut occupancy = tree.to_occupancy();
+        for _ in 0..20 {
+            for _ in 0..10 {
+                let position = V3c::new(
+                    rng.gen_range(0..16),
+                    rng.gen_range(0..16),
+                    rng.gen_range(0..16),
+                );
+                match rng.gen_range(0..4) {
+                    0 => tree.clear(&position).ok().unwrap(),
+                    1 => tree.clear_at_lod(&position, 4).ok().unwrap(),
+                    2 => tree.insert_at_lod(&position, 4, 2).ok().unwrap(),
+                    _ => tree.insert(&position, 3).ok().unwrap(),
+                }
+            }
+            tree.update_occupancy(&mut occupancy);
+            assert_matches(&tree, &occupancy);
+        }
+
+        // Freed bricks are reused by later updates
+        tree.clear_all();
+        tree.update_occupancy(&mut occupancy);
+        assert_matches(&tree, &occupancy);
+        assert!(occupancy.memory_usage() == tree.to_occupancy().memory_usage());
+
+        // Trees of a different size are derived again
+        tree.reset_to(8).ok().unwrap();
+        tree.insert(&V3c::new(7, 7, 7), 1).ok().unwrap();
+        tree.update_occupancy(&mut occupancy);
+        assert!(8 == occupancy.size());
+        assert_matches(&tree, &occupancy);
+    }
+
+    #[test]
+    fn test_changed_regions_are_bounded() {
+        // Regions are not recorded until an occupancy tree is derived
+        let mut tree = Octree::<u32, 2>::new(64).ok().unwrap();
+        for x in 0..64 {
+            tree.insert(&V3c::new(x, 0, 0), 1).ok().unwrap();
+        }
+        assert!(tree.changed_regions.regions.is_empty());
+
+        // The oldest regions are forgotten should too many of them accumulate,
+        // occupancy trees behind them are derived again
+        let mut occupancy = tree.to_occupancy();
+        for x in (0..64).step_by(2) {
+            for y in (0..64).step_by(2) {
+                for z in [32, 40] {
+                    tree.insert(&V3c::new(x, y, z), 2).ok().unwrap();
+                }
+            }
+        }
+        assert!(tree.changed_regions.regions.len() <= 1024);
+        assert!(0 < tree.changed_regions.forgotten_before);
+        tree.update_occupancy(&mut occupancy);
+        assert_matches(&tree, &occupancy);
+
+        // A change of the whole tree replaces every other region
+        tree.clear_all();
+        assert!(tree.changed_regions.regions.len() == 1);
+        tree.update_occupancy(&mut occupancy);
+        assert_matches(&tree, &occupancy);
+    }
+
+    #[test]
+    fn test_update_multiple_occupancy_trees() {
+        let mut tree = Octree::<u32, 2>::new(16).ok().unwrap();
+        tree.insert_at_lod(&V3c::new(0, 0, 0), 8, 1).ok().unwrap();
+        let mut first = tree.to_occupancy();
+        tree.insert(&V3c::new(9, 9, 9), 2).ok().unwrap();
+        let mut second = tree.to_occupancy();
+        tree.clear_at_lod(&V3c::new(0, 0, 0), 4).ok().unwrap();
+
+        // Each occupancy tree takes over the changes it has not seen yet
+        tree.update_occupancy(&mut first);
+        assert_matches(&tree, &first);
+        tree.insert(&V3c::new(15, 0, 15), 3).ok().unwrap();
+        tree.update_occupancy(&mut second);
+        assert_matches(&tree, &second);
+        tree.update_occupancy(&mut first);
+        assert_matches(&tree, &first);
+    }
+
+    /// A tree of randomly occupied voxels along with the set of them
+    fn random_bit_octree(rng: &mut StdRng, size: u32) -> (BitOctree, HashSet<V3c<u32>>) {
+        let mut tree = BitOctree::new(size).ok().unwrap();
+        let mut occupied = HashSet::new();
+        // A solid block, so uniform nodes are split and merged too
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    tree.set(&V3c::new(x, y, z), true).ok().unwrap();
+                    occupied.insert(V3c::new(x, y, z));
+                }
+            }
+        }
+        for _ in 0..300 {
+            let position = V3c::new(
+                rng.gen_range(0..size),
+                rng.gen_range(0..size),
+                rng.gen_range(0..size),
+            );
+            let value = rng.gen_bool(0.7);
+            tree.set(&position, value).ok().unwrap();
+            if value {
+                occupied.insert(position);
+            } else {
+                occupied.remove(&position);
+            }
+        }
+        (tree, occupied)
+    }
+
+    #[test]
+    fn test_bit_octree_set_and_count() {
+        let mut rng = StdRng::seed_from_u64(seed("test_bit_octree_set_and_count"));
+        assert!(matches!(
+            BitOctree::<4>::new(12),
+            Err(OctreeError::InvalidNodeSize(12))
+        ));
+        let (mut tree, occupied) = random_bit_octree(&mut rng, 16);
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let position = V3c::new(x, y, z);
+                    assert!(tree.get(&position) == occupied.contains(&position));
+                }
+            }
+        }
+        assert!(matches!(
+            tree.set(&V3c::new(16, 0, 0), true),
+            Err(OctreeError::InvalidPosition { .. })
+        ));
+
+        for _ in 0..50 {
+            let size = 1 << rng.gen_range(0..4);
+            let region = Cube::new(
+                V3c::new(
+                    rng.gen_range(0..=16 - size),
+                    rng.gen_range(0..=16 - size),
+                    rng.gen_range(0..=16 - size),
+                ),
+                size,
+            );
+            let expected = occupied
+                .iter()
+                .filter(|position| region.contains(position))
+                .count() as u64;
+            assert!(tree.count_occupied(&region) == expected);
+        }
+        let everything = Cube::new(V3c::unit(0), 16);
+        assert!(tree.count_occupied(&everything) == occupied.len() as u64);
+
+        // Clearing every voxel leaves a single empty node
+        for position in occupied.iter() {
+            tree.set(position, false).ok().unwrap();
+        }
+        assert!(0. == tree.density(&everything));
+        assert!(tree.memory_usage() == BitOctree::<4>::new(16).ok().unwrap().memory_usage());
+    }
+
+    #[test]
+    fn test_bit_octree_csg() {
+        let mut rng = StdRng::seed_from_u64(seed("test_bit_octree_csg"));
+        let (a, a_occupied) = random_bit_octree(&mut rng, 16);
+        let (b, b_occupied) = random_bit_octree(&mut rng, 16);
+        let union = a.union(&b).ok().unwrap();
+        let intersection = a.intersection(&b).ok().unwrap();
+        let difference = a.difference(&b).ok().unwrap();
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let position = V3c::new(x, y, z);
+                    let in_a = a_occupied.contains(&position);
+                    let in_b = b_occupied.contains(&position);
+                    assert!(union.get(&position) == (in_a || in_b));
+                    assert!(intersection.get(&position) == (in_a && in_b));
+                    assert!(difference.get(&position) == (in_a && !in_b));
+                }
+            }
+        }
+        let block = Cube::new(V3c::unit(0), 8);
+        let block_union = a_occupied
+            .union(&b_occupied)
+            .filter(|position| block.contains(position))
+            .count();
+        assert!(union.density(&block) == block_union as f32 / 512.);
+
+        assert!(matches!(
+            a.union(&BitOctree::new(32).ok().unwrap()),
+            Err(OctreeError::InvalidNodeSize(32))
+        ));
+    }
+}
+
+#[cfg(all(test, feature = "half"))]
+mod octree_half_color_tests {
+    use crate::octree::types::Octree;
+    use crate::octree::{AtlasTexelFormat, HalfColorCodec, HalfColorVoxel, VoxelData};
+    use crate::spatial::math::vector::V3c;
+    use half::f16;
+
+    #[test]
+    fn test_half_color_voxel() {
+        assert!(12 == std::mem::size_of::<HalfColorVoxel>());
+        assert!(HalfColorVoxel::default().is_empty());
+
+        let voxel = HalfColorVoxel::from_hdr([4., 0.5, 0., 1.], 7);
+        assert!([4., 0.5, 0., 1.] == voxel.albedo_hdr());
+        assert!([255, 128, 0, 255] == voxel.albedo());
+        assert!(7 == voxel.user_data());
+        assert!([255, 128, 0, 255] == HalfColorVoxel::new(255, 128, 0, 255, 0).albedo());
+    }
+
+    #[test]
+    fn test_half_color_codec() {
+        let mut tree = Octree::<HalfColorVoxel, 2>::new(4).ok().unwrap();
+        let bright = HalfColorVoxel::from_hdr([16., 8., 0.25, 1.], 3);
+        let dim = HalfColorVoxel::from_hdr([0.001, 0.002, 0.003, 0.5], 0);
+        tree.insert(&V3c::new(0, 0, 0), bright).ok().unwrap();
+        tree.insert(&V3c::new(3, 2, 1), dim).ok().unwrap();
+
+        let loaded = Octree::<HalfColorVoxel, 2>::from_bytes_with(
+            tree.to_bytes_with(&HalfColorCodec),
+            &HalfColorCodec,
+        );
+        assert!(loaded.get(&V3c::new(0, 0, 0)) == Some(&bright));
+        assert!(loaded.get(&V3c::new(3, 2, 1)) == Some(&dim));
+        assert!(loaded.get(&V3c::new(1, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_pack_bricks_into_half_float_atlas() {
+        let mut tree = Octree::<HalfColorVoxel, 2>::new(4).ok().unwrap();
+        let voxel = HalfColorVoxel::from_hdr([2.5, 0.5, 0., 1.], 0);
+        tree.insert(&V3c::new(1, 0, 0), voxel).ok().unwrap();
+
+        let (layout, atlas) = tree
+            .pack_bricks_into_atlas_with_format(V3c::new(1, 1, 1), AtlasTexelFormat::Rgba16Float)
+            .ok()
+            .unwrap();
+        assert!(AtlasTexelFormat::Rgba16Float == layout.texel_format);
+        assert!(atlas.len() == 2 * 2 * 2 * 8);
+
+        // The texel of the voxel is the second one of the only tile
+        let texel = atlas[8..16]
+            .chunks_exact(2)
+            .map(|bytes| f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+            .collect::<Vec<f32>>();
+        assert!(texel == vec![2.5, 0.5, 0., 1.]);
+        assert!(atlas[0..8].iter().all(|byte| 0 == *byte));
+    }
+}
+
+#[cfg(all(test, feature = "world_dir"))]
+mod octree_world_dir_tests {
+    use crate::octree::{CancellationToken, DistanceStreamingPolicy, V3c, VoxelWorld, WorldGet};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_world_directory() {
+        let path = std::env::temp_dir().join("shocovox_test_world_directory");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(path);
+
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        world.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        world.insert(&V3c::new(-3, 2, 9), 6).ok().unwrap();
+        world.insert_empty_chunk(V3c::new(1, 0, 0));
+        world.insert(&V3c::new(0, 20, 0), 8).ok().unwrap();
+        world.clear(&V3c::new(0, 20, 0)).ok().unwrap();
+        world.save_dir(path).ok().unwrap();
+
+        let loaded = VoxelWorld::<u32, 2>::load_dir(path).ok().unwrap();
+        assert!(loaded.query(&V3c::new(1, 21, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(loaded.query(&V3c::new(-3, 2, 9)) == WorldGet::Voxel(&6));
+        assert!(loaded.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(9, 1, 1)) == WorldGet::Unknown);
+
+        // Only the changed chunks are written again
+        world.insert(&V3c::new(2, 2, 2), 7).ok().unwrap();
+        world.save_dir(path).ok().unwrap();
+        let saved = VoxelWorld::<u32, 2>::saved_chunks_in_dir(path)
+            .ok()
+            .unwrap();
+        assert!(saved.len() == 2);
+        assert!(saved[&V3c::new(0, 0, 0)].version == 2);
+        assert!(saved[&V3c::new(-1, 0, 2)].version == 1);
+
+        // Chunks can be loaded one by one, the ones not loaded are kept by saves
+        let mut partial = VoxelWorld::<u32, 2>::open_dir(path).ok().unwrap();
+        assert!(partial.query(&V3c::new(1, 1, 1)) == WorldGet::Unknown);
+        assert!(partial
+            .load_chunk_from_dir(path, &V3c::new(0, 0, 0))
+            .is_ok_and(|loaded| loaded));
+        assert!(partial
+            .load_chunk_from_dir(path, &V3c::new(1, 0, 0))
+            .is_ok_and(|loaded| loaded));
+        assert!(partial
+            .load_chunk_from_dir(path, &V3c::new(5, 0, 0))
+            .is_ok_and(|loaded| !loaded));
+        assert!(partial.query(&V3c::new(2, 2, 2)) == WorldGet::Voxel(&7));
+        assert!(partial.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(partial.query(&V3c::new(-3, 2, 9)) == WorldGet::Unknown);
+        partial.insert_empty_chunk(V3c::new(0, 0, 0));
+        partial.save_dir(path).ok().unwrap();
+        let loaded = VoxelWorld::<u32, 2>::load_dir(path).ok().unwrap();
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(-3, 2, 9)) == WorldGet::Voxel(&6));
+        assert!(loaded.chunks().count() == 1);
+
+        // Corrupted chunk files and other chunk sizes are rejected
+        std::fs::write(
+            std::path::Path::new(path).join("chunk_-1_0_2.bin"),
+            [1, 2, 3],
+        )
+        .ok()
+        .unwrap();
+        assert!(VoxelWorld::<u32, 2>::load_dir(path).is_err());
+        assert!(VoxelWorld::<u32, 2>::new(8)
+            .ok()
+            .unwrap()
+            .save_dir(path)
+            .is_err());
+        std::fs::remove_dir_all(path).ok().unwrap();
+        assert!(VoxelWorld::<u32, 2>::open_dir(path).is_err());
+    }
+
+    #[test]
+    fn test_world_saved_into_other_directory() {
+        let first = std::env::temp_dir().join("shocovox_test_world_first_directory");
+        let first = first.to_str().unwrap();
+        let second = std::env::temp_dir().join("shocovox_test_world_second_directory");
+        let second = second.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(first);
+        let _ = std::fs::remove_dir_all(second);
+
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        world.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        world.insert(&V3c::new(9, 1, 1), 6).ok().unwrap();
+        world.save_dir(first).ok().unwrap();
+        let mut other = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        other.insert(&V3c::new(1, 1, 1), 7).ok().unwrap();
+        other.save_dir(second).ok().unwrap();
+
+        // Chunks unchanged since the save into the first directory are still written into the second one
+        world.save_dir(second).ok().unwrap();
+        let loaded = VoxelWorld::<u32, 2>::load_dir(second).ok().unwrap();
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(loaded.query(&V3c::new(9, 1, 1)) == WorldGet::Voxel(&6));
+
+        // Chunk files already missing are not an error when the chunk becomes empty
+        std::fs::remove_file(std::path::Path::new(second).join("chunk_0_0_0.bin"))
+            .ok()
+            .unwrap();
+        world.clear(&V3c::new(1, 1, 1)).ok().unwrap();
+        assert!(world.save_dir(second).is_ok());
+        assert!(VoxelWorld::<u32, 2>::load_dir(second)
+            .is_ok_and(|loaded| loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Empty));
+
+        std::fs::remove_dir_all(first).ok().unwrap();
+        std::fs::remove_dir_all(second).ok().unwrap();
+    }
+
+    #[test]
+    fn test_world_chunk_mut_marks_only_edited_chunks() {
+        let path = std::env::temp_dir().join("shocovox_test_world_chunk_mut");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(path);
+
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        world.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        world.insert(&V3c::new(9, 1, 1), 6).ok().unwrap();
+        world.save_dir(path).ok().unwrap();
+
+        // Reading through the mutable access doesn't make the chunk saved again
+        assert!(world
+            .chunk_mut(&V3c::new(0, 0, 0))
+            .is_some_and(|chunk| chunk.get(&V3c::new(1, 1, 1)).is_some_and(|v| *v == 5)));
+        world
+            .chunk_mut(&V3c::new(2, 0, 0))
+            .unwrap()
+            .insert(&V3c::new(2, 2, 2), 7)
+            .ok()
+            .unwrap();
+        *world
+            .chunk_mut(&V3c::new(0, 0, 0))
+            .unwrap()
+            .get_mut(&V3c::new(1, 1, 1))
+            .unwrap() = 8;
+        world.save_dir(path).ok().unwrap();
+        let saved = VoxelWorld::<u32, 2>::saved_chunks_in_dir(path)
+            .ok()
+            .unwrap();
+        assert!(saved[&V3c::new(0, 0, 0)].version == 2);
+        assert!(saved[&V3c::new(2, 0, 0)].version == 2);
+
+        world.chunk_mut(&V3c::new(2, 0, 0)).unwrap();
+        world.save_dir(path).ok().unwrap();
+        let saved = VoxelWorld::<u32, 2>::saved_chunks_in_dir(path)
+            .ok()
+            .unwrap();
+        assert!(saved[&V3c::new(0, 0, 0)].version == 2);
+        assert!(saved[&V3c::new(2, 0, 0)].version == 2);
+        let loaded = VoxelWorld::<u32, 2>::load_dir(path).ok().unwrap();
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&8));
+        assert!(loaded.query(&V3c::new(10, 2, 2)) == WorldGet::Voxel(&7));
+
+        std::fs::remove_dir_all(path).ok().unwrap();
+    }
+
+    #[test]
+    fn test_world_streaming() {
+        let policy = DistanceStreamingPolicy {
+            load_radius: 1,
+            keep_radius: 2,
+            loads_per_tick: 3,
+        };
+
+        let path = std::env::temp_dir().join("shocovox_test_world_streaming");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(path);
+        let mut saved = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        saved.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        saved.insert_empty_chunk(V3c::new(0, 1, 0));
+        saved.save_dir(path).ok().unwrap();
+
+        // Chunks the directory has no content for are left to the caller, e.g. to generate them
+        let mut world = VoxelWorld::<u32, 2>::open_dir(path).ok().unwrap();
+        let mut ticks = 0;
+        let mut generated = 0;
+        while !world
+            .plan_streaming(&V3c::new(1, 1, 1), &policy)
+            .load
+            .is_empty()
+        {
+            let missing = world
+                .stream_from_dir(path, &V3c::new(1, 1, 1), &policy)
+                .ok()
+                .unwrap();
+            generated += missing.len();
+            for chunk_coord in missing {
+                world.insert_empty_chunk(chunk_coord);
+            }
+            ticks += 1;
+        }
+        assert!(ticks == 3);
+        assert!(generated == 5);
+        assert!(world.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(world.query(&V3c::new(1, 5, 1)) == WorldGet::Empty);
+
+        // Changed chunks are saved before they are evicted, chunks within the keep radius are kept
+        world.insert(&V3c::new(2, 2, 2), 7).ok().unwrap();
+        world
+            .stream_from_dir(path, &V3c::new(4 * 3 + 1, 1, 1), &policy)
+            .ok()
+            .unwrap();
+        assert!(world.query(&V3c::new(2, 2, 2)) == WorldGet::Unknown);
+        assert!(world.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        world
+            .stream_from_dir(path, &V3c::new(1, 1, 1), &policy)
+            .ok()
+            .unwrap();
+        assert!(world.query(&V3c::new(2, 2, 2)) == WorldGet::Voxel(&7));
+        std::fs::remove_dir_all(path).ok().unwrap();
+    }
+
+    #[test]
+    fn test_save_dir_cancelled() {
+        let path = std::env::temp_dir().join("shocovox_test_save_dir_cancelled");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(path);
+
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        for x in 0..4 {
+            world.insert(&V3c::new(x * 4, 0, 0), 5).ok().unwrap();
+        }
+        let cancellation = CancellationToken::new();
+        let result = world.save_dir_with_progress(
+            path,
+            &|done: usize, _| {
+                if 2 == done {
+                    cancellation.cancel();
+                }
+            },
+            &cancellation,
+        );
+        assert!(result.is_err_and(|error| error.kind() == ErrorKind::Interrupted));
+
+        // The chunks written before the cancellation are listed, the rest are saved by the next call
+        let saved = VoxelWorld::<u32, 2>::saved_chunks_in_dir(path)
+            .ok()
+            .unwrap();
+        assert!(saved.len() == 2);
+        world.save_dir(path).ok().unwrap();
+        let loaded = VoxelWorld::<u32, 2>::load_dir(path).ok().unwrap();
+        for x in 0..4 {
+            assert!(loaded.query(&V3c::new(x * 4, 0, 0)) == WorldGet::Voxel(&5));
+        }
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
+
+#[cfg(all(test, feature = "archive"))]
+mod octree_world_archive_tests {
+    use crate::octree::{VoxelWorld, WorldArchive, WorldGet};
+    use crate::spatial::math::vector::V3c;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_world_archive() {
+        let mut world = VoxelWorld::<u32, 2>::new(4).ok().unwrap();
+        world.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        world.insert(&V3c::new(-3, 2, 9), 6).ok().unwrap();
+        world.insert_empty_chunk(V3c::new(1, 0, 0));
+        world.insert(&V3c::new(0, 20, 0), 8).ok().unwrap();
+        world.clear(&V3c::new(0, 20, 0)).ok().unwrap();
+        let mut bytes = Vec::new();
+        world.save_archive(&mut bytes).ok().unwrap();
+
+        let mut archive = WorldArchive::open(Cursor::new(bytes.clone())).ok().unwrap();
+        assert!(archive.chunk_size() == 4);
+        assert!(archive.saved_chunks().len() == 2);
+        let loaded = VoxelWorld::<u32, 2>::load_archive(&mut archive)
+            .ok()
+            .unwrap();
+        assert!(loaded.query(&V3c::new(1, 21, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+        assert!(loaded.query(&V3c::new(-3, 2, 9)) == WorldGet::Voxel(&6));
+        assert!(loaded.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(loaded.query(&V3c::new(9, 1, 1)) == WorldGet::Unknown);
+
+        // Chunks can be loaded one by one, in any order
+        let mut partial = VoxelWorld::<u32, 2>::open_archive(&archive).ok().unwrap();
+        assert!(partial.query(&V3c::new(1, 1, 1)) == WorldGet::Unknown);
+        assert!(partial
+            .load_chunk_from_archive(&mut archive, &V3c::new(-1, 0, 2))
+            .is_ok_and(|loaded| loaded));
+        assert!(partial
+            .load_chunk_from_archive(&mut archive, &V3c::new(1, 0, 0))
+            .is_ok_and(|loaded| loaded));
+        assert!(partial
+            .load_chunk_from_archive(&mut archive, &V3c::new(5, 0, 0))
+            .is_ok_and(|loaded| !loaded));
+        assert!(partial.query(&V3c::new(-3, 2, 9)) == WorldGet::Voxel(&6));
+        assert!(partial.query(&V3c::new(5, 1, 1)) == WorldGet::Empty);
+        assert!(partial.query(&V3c::new(1, 1, 1)) == WorldGet::Unknown);
+        assert!(partial
+            .load_chunk_from_archive(&mut archive, &V3c::new(0, 0, 0))
+            .is_ok_and(|loaded| loaded));
+        assert!(partial.query(&V3c::new(1, 1, 1)) == WorldGet::Voxel(&5));
+
+        // Other chunk sizes, archives without a manifest and corrupted chunks are rejected
+        assert!(VoxelWorld::<u32, 2>::new(8)
+            .ok()
+            .unwrap()
+            .load_chunk_from_archive(&mut archive, &V3c::new(0, 0, 0))
+            .is_err());
+        assert!(WorldArchive::open(Cursor::new(vec![0; 1024])).is_err());
+        let mut source = tar::Archive::new(Cursor::new(bytes));
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in source.entries().ok().unwrap() {
+            let mut entry = entry.ok().unwrap();
+            let mut header = entry.header().clone();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).ok().unwrap();
+            if header.path().ok().unwrap().to_str() == Some("chunk_0_0_0.bin") {
+                contents = vec![1, 2, 3];
+                header.set_size(contents.len() as u64);
+                header.set_cksum();
+            }
+            builder.append(&header, contents.as_slice()).ok().unwrap();
+        }
+        let mut corrupted = WorldArchive::open(Cursor::new(builder.into_inner().ok().unwrap()))
+            .ok()
+            .unwrap();
+        assert!(VoxelWorld::<u32, 2>::load_archive(&mut corrupted).is_err());
+    }
+
+    #[test]
+    fn test_world_archive_file() {
+        let path = std::env::temp_dir().join("shocovox_test_world_archive.tar");
+        let mut world = VoxelWorld::<u32>::new(4).ok().unwrap();
+        for x in -8..8 {
+            world.insert(&V3c::new(x, x, -x), 7).ok().unwrap();
+        }
+        world
+            .save_archive(std::fs::File::create(&path).ok().unwrap())
+            .ok()
+            .unwrap();
+
+        let mut archive = WorldArchive::open(std::fs::File::open(&path).ok().unwrap())
+            .ok()
+            .unwrap();
+        assert!(archive.saved_chunks().len() == world.chunks().count());
+        let loaded = VoxelWorld::<u32>::load_archive(&mut archive).ok().unwrap();
+        for x in -8..8 {
+            assert!(loaded.query(&V3c::new(x, x, -x)) == WorldGet::Voxel(&7));
+            assert!(loaded.get(&V3c::new(x, x + 1, -x)).is_none());
+        }
+        std::fs::remove_file(&path).ok().unwrap();
+    }
+}
+
+#[cfg(all(test, feature = "debug-verify"))]
+mod octree_verify_tests {
+    use crate::octree::types::{NodeContent, Octree};
+    use crate::spatial::math::vector::V3c;
+
+    #[test]
+    #[should_panic(expected = "Voxel count of node")]
+    fn test_count_drift_is_caught() {
+        let mut tree = Octree::<u32, 2>::new(8).ok().unwrap();
+        tree.insert(&V3c::new(1, 1, 1), 5).ok().unwrap();
+        tree.insert(&V3c::new(6, 6, 6), 6).ok().unwrap();
+
+        // The count of the root drifts away from the voxels inside it, caught by the next edit
+        *tree.nodes.get_mut(Octree::<u32, 2>::ROOT_NODE_KEY as usize) = NodeContent::Internal(5);
+        tree.insert(&V3c::new(1, 1, 0), 5).ok().unwrap();
+    }
 }
@@ -1,22 +1,42 @@
-use crate::object_pool::ObjectPool;
+use crate::object_pool::{ObjectPool, PoolKey};
+use crate::octree::{
+    aggregate::AggregateStore, fluid::FluidLayer, recorder::EditRecorder, LightLevel, V3c,
+    VoxelAux, VoxelShape,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::AtomicBool;
 
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Clone)]
 #[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
-pub(crate) enum NodeContent<T: Clone, const DIM: usize = 1> {
+pub(crate) enum NodeContent<T: Clone> {
     #[default]
     Nothing,
-    Internal(u32), // cache data to store the enclosed nodes
-    Leaf([[[T; DIM]; DIM]; DIM]),
+    Internal(u32),  // cache data to store the enclosed nodes
+    Leaf(PoolKey),  // key of the brick storing the voxels of the leaf
+    UniformLeaf(T), // a leaf with every voxel equal to the given data, without a brick
 }
 
+/// The voxels of a leaf node, stored in a pool separate from the nodes,
+/// so they can be managed independently of the structure of the tree
+#[derive(Clone)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub(crate) struct Brick<T: Clone, const DIM: usize>(pub(crate) [[[T; DIM]; DIM]; DIM]);
+
 /// error types during usage or creation of the octree
 #[derive(Debug)]
 pub enum OctreeError {
     InvalidNodeSize(u32),
+    InvalidOctant(u32),
     InvalidPosition { x: u32, y: u32, z: u32 },
+    OutOfBudget { used: usize, budget: usize },
+    AtlasFull { tiles: usize, required: usize },
+    InvalidDenseData { expected: usize, actual: usize },
+    InvalidKeyframe(usize),
+    Cancelled,
+    OutOfMemory,
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -32,12 +52,20 @@ pub(in crate::octree) enum NodeChildrenArray<T: Default> {
 pub(in crate::octree) struct NodeChildren<T: Default> {
     pub(in crate::octree) default_key: T,
     pub(in crate::octree) content: NodeChildrenArray<T>,
+    pub(in crate::octree) occupied_bits: u8, // a bit set for each octant with a non-empty child
 }
 
 pub trait VoxelData {
     fn new(r: u8, g: u8, b: u8, a: u8, user_data: u32) -> Self;
     /// The color to display during raytracing 0-255 RGBA
+    /// The RGB channels are treated as linear values by the renderers, and encoded to sRGB on output
     fn albedo(&self) -> [u8; 4];
+    /// The linear RGBA color to display during raytracing, with channels not limited to 0-1:
+    /// values above 1 are brighter than white, e.g. for emissive voxels or baked lighting.
+    /// By default it is the albedo mapped to 0-1
+    fn albedo_hdr(&self) -> [f32; 4] {
+        self.albedo().map(|channel| channel as f32 / 255.)
+    }
     /// User defined data
     fn user_data(&self) -> u32;
     /// determines if the voxel is to be hit by rays in the raytracing algorithms
@@ -46,6 +74,18 @@ pub trait VoxelData {
     }
     /// Implementation to clear the contained data, as well as albedo
     fn clear(&mut self);
+    /// The level of light the voxel emits, see `Octree::propagate_light`
+    fn light_emission(&self) -> u8 {
+        0
+    }
+    /// determines if the voxel blocks light in `Octree::propagate_light`
+    fn is_opaque(&self) -> bool {
+        !self.is_empty()
+    }
+    /// The shape of the voxel inside its cell, ray hits are refined against it on the CPU
+    fn shape(&self) -> VoxelShape {
+        VoxelShape::Cube
+    }
 }
 
 impl VoxelData for u32 {
@@ -71,10 +111,140 @@ impl VoxelData for u32 {
     }
 }
 
+/// Converts voxel data to and from bytes when saving and loading an octree, so voxel types
+/// with fields beyond what `VoxelData` exposes can be persisted as well.
+/// The encoded size of the voxels may be fixed or vary between voxels.
+pub trait VoxelDataCodec<T> {
+    /// The bytes representing the given voxel
+    fn encode(&self, data: &T) -> Vec<u8>;
+    /// The voxel represented by the given bytes, or None if they are malformed
+    fn decode(&self, bytes: &[u8]) -> Option<T>;
+    /// The version of the encoded layout, saved along with the voxels.
+    /// It is to be increased whenever the layout changes, so older saves can be migrated on load.
+    fn version(&self) -> u32 {
+        0
+    }
+}
+
+/// Converts the bytes of a voxel saved with an older version of a codec into the current voxel type
+pub type VoxelDataMigration<T> = Box<dyn Fn(&[u8]) -> T>;
+
+/// Loads octrees saved with the given codec, upgrading voxels saved with older versions of it
+/// through the registered migrations
+pub struct OctreeLoader<T, C: VoxelDataCodec<T>> {
+    pub(in crate::octree) codec: C,
+    pub(in crate::octree) migrations: HashMap<u32, VoxelDataMigration<T>>,
+}
+
+/// The codec used by default: stores the albedo and the user data of each voxel in 8 bytes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultVoxelDataCodec;
+
+/// An edit of an octree, stored to be applied later
+#[derive(Debug, Clone)]
+pub enum OctreeEdit<T> {
+    /// Sets the data at the given position in the given size, see `Octree::insert_at_lod`
+    Insert {
+        position: V3c<u32>,
+        size: u32,
+        data: T,
+    },
+    /// Clears the data at the given position in the given size, see `Octree::clear_at_lod`
+    Clear { position: V3c<u32>, size: u32 },
+}
+
+/// Collects edits without borrowing the octree, so they can be applied later in one go,
+/// or spread across multiple frames with `apply_for`
+#[derive(Debug, Clone, Default)]
+pub struct OctreeWriteQueue<T> {
+    pub(in crate::octree) edits: VecDeque<OctreeEdit<T>>,
+}
+
+/// The regions of a tree changed since the structures derived from it were last updated, e.g. its occupancy trees
+/// and broad phases. Every change is numbered, and each derived structure keeps the number of the first change
+/// it has not taken over yet, so any number of them are updated from the same regions independently.
+/// Regions are only recorded once such a structure is created, and the oldest ones are forgotten should
+/// too many of them accumulate, so trees without derived structures don't pay for the bookkeeping
+#[derive(Debug, Default)]
+pub(in crate::octree) struct ChangedRegions {
+    pub(in crate::octree) recording: AtomicBool,
+    /// The number of the latest change of each region, by the min position and size of the region
+    pub(in crate::octree) regions: HashMap<(V3c<u32>, u32), u64>,
+    /// The number of the next change
+    pub(in crate::octree) next_change: u64,
+    /// Changes numbered below this may be forgotten already
+    pub(in crate::octree) forgotten_before: u64,
+}
+
+/// Called when an insert would make the octree exceed its memory budget or its node capacity,
+/// expected to free up space e.g. by clearing or simplifying distant regions of the tree
+pub type EvictionCallback<T, const DIM: usize> = Box<dyn FnMut(&mut Octree<T, DIM>) + Send + Sync>;
+
+/// How the node pools of an octree grow as voxels are inserted, see `Octree::set_capacity_policy`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityPolicy {
+    /// The pools grow as needed; inserts fail with `OctreeError::OutOfMemory` should the allocation fail
+    #[default]
+    Grow,
+    /// Inserts fail with `OctreeError::OutOfMemory` once the tree has the given number of nodes
+    Fail { max_nodes: usize },
+    /// Once the tree has the given number of nodes, inserts call the eviction callback first,
+    /// and fail with `OctreeError::OutOfMemory` should the number of nodes remain as high
+    Evict { max_nodes: usize },
+}
+
 #[cfg_attr(feature = "serialization", derive(Serialize))]
 pub struct Octree<T: Default + Clone + VoxelData, const DIM: usize = 1> {
     pub auto_simplify: bool,
     pub(in crate::octree) octree_size: u32,
-    pub(in crate::octree) nodes: ObjectPool<NodeContent<T, DIM>>,
-    pub(in crate::octree) node_children: Vec<NodeChildren<u32>>, // Children index values of each Node
+    pub(in crate::octree) nodes: ObjectPool<NodeContent<T>>,
+    pub(in crate::octree) node_children: Vec<NodeChildren<PoolKey>>, // Children index values of each Node
+    pub(in crate::octree) bricks: ObjectPool<Brick<T, DIM>>,         // Voxel data of the leaf Nodes
+    pub(in crate::octree) voxel_size: f32, // The physical size of a voxel edge, e.g. in meters
+
+    // Runtime settings, not persisted with the data
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) memory_budget: Option<usize>, // in bytes
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) eviction_callback: Option<EvictionCallback<T, DIM>>,
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) capacity_policy: CapacityPolicy,
+
+    // Areas edited while auto_simplify was disabled, to be simplified by simplify_incremental
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) simplify_queue: VecDeque<V3c<u32>>,
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) simplify_queued: HashSet<(u32, u32, u32)>,
+
+    // Bricks allocated or modified since the texture atlas was last packed
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) dirty_bricks: HashSet<PoolKey>,
+
+    // The number of edits of the voxels of the tree, to tell whether it was edited between two points in time
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) edit_count: u64,
+
+    // Regions changed since the occupancy trees and broad phases of the tree were last updated
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) changed_regions: ChangedRegions,
+
+    // Light levels calculated by propagate_light, stored in a tree of the same size
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) light: Option<Box<Octree<LightLevel, DIM>>>,
+
+    // Data stored beside the voxels by set_auxiliary, in a tree of the same size; not persisted with the data
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) auxiliary: Option<Box<Octree<VoxelAux, DIM>>>,
+
+    // Fluid levels simulated by step_fluids, not persisted with the data
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) fluids: FluidLayer<DIM>,
+
+    // The calls changing the tree since start_recording, not persisted with the data
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) recorder: Option<EditRecorder<T>>,
+
+    // The summaries of the nodes selected by set_aggregate, not persisted with the data
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(in crate::octree) aggregates: Option<Box<dyn AggregateStore<T, DIM>>>,
 }
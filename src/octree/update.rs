@@ -1,13 +1,21 @@
-use crate::object_pool::key_none_value;
+use crate::object_pool::{key_might_be_valid, key_none_value, PoolKey};
 use crate::octree::{
     detail::{bound_contains, child_octant_for},
-    types::{NodeChildren, NodeContent, OctreeError},
+    progress::{CancellationToken, ProgressSink},
+    recorder::RecordedCall,
+    types::{
+        Brick, CapacityPolicy, NodeChildren, NodeContent, OctreeEdit, OctreeError, OctreeWriteQueue,
+    },
     Octree, VoxelData,
 };
 use crate::spatial::{
-    math::{offset_region, vector::V3c},
+    math::{vector::V3c, Octant},
     Cube,
 };
+use std::time::{Duration, Instant};
+
+/// The number of nodes simplified between progress reports in `Octree::simplify_with_progress`
+const SIMPLIFY_STEP_NODES: usize = 256;
 
 impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
     /// Inserts the given data into the octree into the intended voxel position
@@ -33,10 +41,25 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                 z: position.z,
             });
         }
+        // The limits of the tree only apply to inserts creating nodes or bricks, which takes a walk down the tree to tell
+        let limited = self.memory_budget.is_some() || CapacityPolicy::Grow != self.capacity_policy;
+        if !limited || self.insert_allocates(position, insert_size, &data) {
+            self.ensure_memory_budget()?;
+            self.ensure_capacity()?;
+        }
+        // Only inserts getting past the checks are recorded, as the rest of the insert can not fail
+        self.record(|| {
+            RecordedCall::Edit(OctreeEdit::Insert {
+                position: *position,
+                size: insert_size,
+                data: data.clone(),
+            })
+        });
+        self.mark_changed(position, insert_size);
 
         // A vector does not consume significant resources in this case, e.g. a 4096*4096*4096 chunk has depth of 12
         let mut node_stack = vec![(Octree::<T, DIM>::ROOT_NODE_KEY, root_bounds)];
-        let mut added_nodes_count = 0;
+        let mut added_voxels = 0_i64;
         loop {
             let (current_node_key, current_bounds) = *node_stack.last().unwrap();
             let current_node_key = current_node_key as usize;
@@ -49,14 +72,10 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                 ) {
                     node_stack.push((
                         self.node_children[current_node_key][target_child_octant],
-                        Cube {
-                            min_position: current_bounds.min_position
-                                + offset_region(target_child_octant) * current_bounds.size / 2,
-                            size: current_bounds.size / 2,
-                        },
+                        current_bounds.child_bounds_for(target_child_octant),
                     ));
                 } else {
-                    let is_full_match = self.nodes.get(current_node_key).is_all(&data);
+                    let is_full_match = self.is_all(current_node_key, &data);
                     // no children are available for the target octant
                     if self.nodes.get(current_node_key).is_leaf() && is_full_match {
                         // The current Node is a leaf, but the data stored equals the data to be set, so no need to go deeper as tha data already matches
@@ -66,21 +85,17 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                         // The current Node is a leaf, which essentially represents an area where all the contained space have the same data.
                         // The contained data does not match the given data to set the position to, so all of the Nodes' children need to be created
                         // as separate Nodes with the same data as their parent to keep integrity
-                        let content = self.nodes.get(current_node_key).clone();
-                        let new_children = self.make_uniform_children(content.leaf_data().clone());
+                        let new_children = self.make_uniform_children(current_node_key);
 
-                        // Set node type as internal, after the insertion the count will be updated for the whole structure
-                        // Since this node in this function will only have at most 1 child node( the currently inserted node),
-                        // Internal count will be updated correctly, as the other children of this node will contain no voxels
-                        *self.nodes.get_mut(current_node_key) = NodeContent::Internal(0);
+                        // Set node type as internal, containing the voxels of the leaf it was,
+                        // after the insertion the count will be updated for the whole structure
+                        let leaf_voxels =
+                            self.voxel_count(current_node_key as PoolKey, &current_bounds);
+                        self.set_node_content(current_node_key, NodeContent::Internal(leaf_voxels));
                         self.node_children[current_node_key].set(new_children);
                         node_stack.push((
                             self.node_children[current_node_key][target_child_octant],
-                            Cube {
-                                min_position: current_bounds.min_position
-                                    + offset_region(target_child_octant) * current_bounds.size / 2,
-                                size: current_bounds.size / 2,
-                            },
+                            current_bounds.child_bounds_for(target_child_octant),
                         ));
                     } else {
                         // current Node is a non-leaf Node, which doesn't have the child at the requested position, so it is inserted
@@ -89,17 +104,13 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                             // A special case during the first insertion, where the root Node was empty beforehand
                             *self.nodes.get_mut(current_node_key) = NodeContent::Internal(0);
                         };
-                        let child_key = self.nodes.push(NodeContent::Internal(0)) as u32;
+                        let child_key = self.nodes.push(NodeContent::Internal(0)) as PoolKey;
                         self.node_children
                             .resize(self.nodes.len(), NodeChildren::new(key_none_value()));
 
                         node_stack.push((
                             child_key,
-                            Cube {
-                                min_position: current_bounds.min_position
-                                    + offset_region(target_child_octant) * current_bounds.size / 2,
-                                size: current_bounds.size / 2,
-                            },
+                            current_bounds.child_bounds_for(target_child_octant),
                         ));
                         self.node_children[current_node_key][target_child_octant] =
                             node_stack.last().unwrap().0;
@@ -116,9 +127,9 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                         // simulate the Nodes layout and update accordingly
                         mat_index.cut_each_component(&(DIM - insert_size as usize));
                         for x in d.iter_mut().skip(mat_index.x).take(insert_size as usize) {
-                            for y in x.iter_mut().skip(mat_index.x).take(insert_size as usize) {
+                            for y in x.iter_mut().skip(mat_index.y).take(insert_size as usize) {
                                 for item in
-                                    y.iter_mut().skip(mat_index.x).take(insert_size as usize)
+                                    y.iter_mut().skip(mat_index.z).take(insert_size as usize)
                                 {
                                     *item = data.clone();
                                 }
@@ -126,26 +137,30 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                         }
                     }
                 };
-                match self.nodes.get_mut(current_node_key) {
-                    NodeContent::Leaf(d) => {
-                        added_nodes_count = insert_size.pow(3);
-                        matrix_update_fn(d);
+                // The voxels already set are not added again, so the count is based on the content before and after the update
+                let voxels_before = self.voxel_count(current_node_key as PoolKey, &current_bounds);
+                match self.nodes.get(current_node_key) {
+                    NodeContent::UniformLeaf(leaf_data) if *leaf_data == data => {}
+                    NodeContent::Leaf(_) | NodeContent::UniformLeaf(_)
+                        if insert_size < DIM as u32 =>
+                    {
+                        matrix_update_fn(self.mut_leaf_data(current_node_key));
                     }
-                    // should the current Node be anything other, than a leaf at this point, it is to be converted into one
+                    // should the current Node be anything other, than a leaf to be partially updated, it is to be converted into one
                     _ => {
-                        added_nodes_count = current_bounds.size.pow(3);
                         if insert_size == DIM as u32 || insert_size >= current_bounds.size {
                             // update size equals matrix size, update the whole matrix
-                            *self.nodes.get_mut(current_node_key) = NodeContent::leaf_from(data);
+                            self.set_node_content(current_node_key, NodeContent::UniformLeaf(data));
                             self.deallocate_children_of(node_stack.last().unwrap().0);
-                            break;
                         } else {
-                            *self.nodes.get_mut(current_node_key) =
-                                NodeContent::leaf_from(T::default());
-                            matrix_update_fn(self.nodes.get_mut(current_node_key).mut_leaf_data());
+                            self.make_leaf(current_node_key, Brick::default());
+                            matrix_update_fn(self.mut_leaf_data(current_node_key));
                         }
                     }
                 }
+                added_voxels = self.voxel_count(current_node_key as PoolKey, &current_bounds)
+                    as i64
+                    - voxels_before as i64;
                 break;
             }
         }
@@ -162,17 +177,109 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                     // As the current Node is either a parent or a data leaf, it can not be Empty or nothing
                     // To correct this, the children will determine the count
                     *self.nodes.get_mut(node_key as usize) =
-                        NodeContent::Internal(self.count_cached_children(node_key));
+                        NodeContent::Internal(self.count_cached_children(node_key, &node_bounds));
                 }
-                // Update the number of voxels the node contains; It can hold at maximum size*size*size voxels
+                // Update the number of voxels the node contains
                 NodeContent::Internal(contains_count) => {
-                    *self.nodes.get_mut(node_key as usize) = NodeContent::Internal(
-                        (contains_count + added_nodes_count).min(node_bounds.size.pow(3)),
-                    );
+                    *self.nodes.get_mut(node_key as usize) =
+                        NodeContent::Internal((*contains_count as i64 + added_voxels) as u32);
                 }
                 _ => {}
             }
+            self.update_occupied_bits(node_key);
+            self.update_aggregate(node_key, &node_bounds);
+        }
+        if !self.auto_simplify {
+            self.queue_simplification(position);
+        }
+        #[cfg(feature = "debug-verify")]
+        self.verify_counts_towards(position);
+        Ok(())
+    }
+
+    /// Stores the area around the given position to be simplified later by `simplify_incremental`
+    fn queue_simplification(&mut self, position: &V3c<u32>) {
+        // Edits inside the same parent of the smallest nodes share the same path to simplify
+        let cell = (2 * DIM as u32).min(self.octree_size);
+        let cell_position = (
+            position.x / cell * cell,
+            position.y / cell * cell,
+            position.z / cell * cell,
+        );
+        if self.simplify_queued.insert(cell_position) {
+            self.simplify_queue.push_back(V3c::new(
+                cell_position.0,
+                cell_position.1,
+                cell_position.2,
+            ));
+        }
+    }
+
+    /// Simplifies the areas edited while `auto_simplify` was disabled, a bounded number of nodes per call,
+    /// so the simplification after bulk edits can be spread across multiple frames.
+    /// The queue of areas to simplify is not persisted when saving the tree.
+    /// * `node_budget` - The maximum number of nodes to try to simplify in this call
+    ///
+    /// output: true if there is nothing left to simplify
+    pub fn simplify_incremental(&mut self, node_budget: usize) -> bool {
+        self.record(|| RecordedCall::SimplifyIncremental { node_budget });
+        let mut budget = node_budget;
+        while let Some(position) = self.simplify_queue.front().cloned() {
+            // Collect the path to the smallest node at the queued position
+            let mut node_stack = vec![Octree::<T, DIM>::ROOT_NODE_KEY];
+            let mut current_bounds = Cube::root_bounds(self.octree_size);
+            loop {
+                let current_node_key = *node_stack.last().unwrap();
+                let child_octant = child_octant_for(&current_bounds, &position);
+                let child_key = self.node_children[current_node_key as usize][child_octant];
+                if !crate::object_pool::key_might_be_valid(child_key) {
+                    break;
+                }
+                node_stack.push(child_key);
+                current_bounds = current_bounds.child_bounds_for(child_octant);
+            }
+
+            // Simplify the path bottom-up, until a node can not be simplified
+            for i in (0..node_stack.len()).rev() {
+                let node_key = node_stack[i];
+                if self.nodes.get(node_key as usize).is_leaf() {
+                    continue;
+                }
+                if 0 == budget {
+                    return false;
+                }
+                budget -= 1;
+                if !self.simplify(node_key) {
+                    break;
+                }
+                if 0 < i {
+                    self.update_occupied_bits(node_stack[i - 1]);
+                }
+            }
+            self.simplify_queue.pop_front();
+            self.simplify_queued
+                .remove(&(position.x, position.y, position.z));
+        }
+        true
+    }
+
+    /// Simplifies every area edited while `auto_simplify` was disabled, like calling `simplify_incremental`
+    /// until it is done, reporting the number of simplified areas to the given sink.
+    /// Fails with `OctreeError::Cancelled` if the given token is cancelled before it is done;
+    /// the tree stays valid then, with the areas not yet simplified still queued
+    pub fn simplify_with_progress(
+        &mut self,
+        progress: &impl ProgressSink,
+        cancellation: &CancellationToken,
+    ) -> Result<(), OctreeError> {
+        let total = self.simplify_queue.len();
+        while !self.simplify_incremental(SIMPLIFY_STEP_NODES) {
+            if cancellation.is_cancelled() {
+                return Err(OctreeError::Cancelled);
+            }
+            progress.report(total.saturating_sub(self.simplify_queue.len()), total);
         }
+        progress.report(total, total);
         Ok(())
     }
 
@@ -198,10 +305,18 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
             });
         }
 
+        self.record(|| {
+            RecordedCall::Edit(OctreeEdit::Clear {
+                position: *position,
+                size: clear_size,
+            })
+        });
+        self.mark_changed(position, clear_size);
+
         // A vector does not consume significant resources in this case, e.g. a 4096*4096*4096 chunk has depth of 12
         let mut node_stack = vec![(Octree::<T, DIM>::ROOT_NODE_KEY, root_bounds)];
-        let mut target_child_octant = 9; //This init value should not be used. In case there is only one node, there is parent of it;
-        let mut removed_nodes_count = 0;
+        let mut target_child_octant = Octant::Origin; //This init value is not used, only nodes with a parent are removed from their parent
+        let mut removed_voxels = 0;
         loop {
             let (current_node_key, current_bounds) = *node_stack.last().unwrap();
             let current_node_key = current_node_key as usize;
@@ -214,11 +329,7 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                     //Iteration can go deeper , as target child is valid
                     node_stack.push((
                         self.node_children[current_node_key][target_child_octant],
-                        Cube {
-                            min_position: current_bounds.min_position
-                                + offset_region(target_child_octant) * current_bounds.size / 2,
-                            size: current_bounds.size / 2,
-                        },
+                        current_bounds.child_bounds_for(target_child_octant),
                     ));
                 } else {
                     // no children are available for the target octant
@@ -227,18 +338,14 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                         // The contained data does not match the given data to set the position to, so all of the Nodes' children need to be created
                         // as separate Nodes with the same data as their parent to keep integrity, the node targeted for clean will update node count correctly
                         debug_assert!(self.nodes.get(current_node_key).is_leaf());
-                        let current_data = self.nodes.get(current_node_key).leaf_data().clone();
-                        let new_children = self.make_uniform_children(current_data);
-                        *self.nodes.get_mut(current_node_key) =
-                            NodeContent::Internal(current_bounds.size.pow(3));
+                        let new_children = self.make_uniform_children(current_node_key);
+                        let leaf_voxels =
+                            self.voxel_count(current_node_key as PoolKey, &current_bounds);
+                        self.set_node_content(current_node_key, NodeContent::Internal(leaf_voxels));
                         self.node_children[current_node_key].set(new_children);
                         node_stack.push((
                             self.node_children[current_node_key][target_child_octant],
-                            Cube {
-                                min_position: current_bounds.min_position
-                                    + offset_region(target_child_octant) * current_bounds.size / 2,
-                                size: current_bounds.size / 2,
-                            },
+                            current_bounds.child_bounds_for(target_child_octant),
                         ));
                     } else {
                         // current Node is a non-leaf Node, which doesn't have the child at the requested position.
@@ -250,74 +357,388 @@ impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM
                 // when clearing Nodes with size > DIM, Nodes are being cleared
                 // current_bounds.size == min_node_size, which is the desired depth
                 let mut mat_index = Self::mat_index(&current_bounds, position);
+                removed_voxels = self.voxel_count(current_node_key as PoolKey, &current_bounds);
                 if clear_size == 1 {
-                    self.nodes
-                        .get_mut(current_node_key)
-                        .as_mut_leaf_ref()
-                        .unwrap()[mat_index.x][mat_index.y][mat_index.z]
+                    self.mut_leaf_data(current_node_key)[mat_index.x][mat_index.y][mat_index.z]
                         .clear();
-                    removed_nodes_count = 1;
+                    removed_voxels -=
+                        self.voxel_count(current_node_key as PoolKey, &current_bounds);
                 } else if clear_size < DIM as u32 {
                     // update size is smaller, than the matrix, but > 1
                     mat_index.cut_each_component(&(DIM - clear_size as usize));
                     for x in mat_index.x..(mat_index.x + clear_size as usize) {
                         for y in mat_index.y..(mat_index.y + clear_size as usize) {
                             for z in mat_index.z..(mat_index.z + clear_size as usize) {
-                                self.nodes
-                                    .get_mut(current_node_key)
-                                    .as_mut_leaf_ref()
-                                    .unwrap()[x][y][z]
-                                    .clear();
+                                self.mut_leaf_data(current_node_key)[x][y][z].clear();
                             }
                         }
                     }
-                    removed_nodes_count = clear_size.pow(3);
+                    removed_voxels -=
+                        self.voxel_count(current_node_key as PoolKey, &current_bounds);
                 } else {
                     // The size to clear equals, or is greater than DIM, the whole node is to be erased
                     // unset the current node and its children
-                    self.deallocate_children_of(current_node_key as u32);
+                    self.deallocate_children_of(current_node_key as PoolKey);
 
                     // Set the parents child to None
-                    if node_stack.len() >= 2 && target_child_octant < 9 {
-                        self.nodes.free(current_node_key);
+                    if node_stack.len() >= 2 {
+                        self.free_node(current_node_key);
                         let parent_key = node_stack[node_stack.len() - 2].0 as usize;
                         self.node_children[parent_key][target_child_octant] = key_none_value();
                     } else {
                         // If the node doesn't have parents, then it's a root node and should not be deleted
-                        *self.nodes.get_mut(current_node_key) = NodeContent::Nothing;
+                        self.set_node_content(current_node_key, NodeContent::Nothing);
                     }
-                    removed_nodes_count = current_bounds.size.pow(3);
                 }
                 break;
             }
         }
 
         // post-processing operations
-        node_stack.pop(); // Except for the last removed element
-        for (node_key, _node_bounds) in node_stack.into_iter().rev() {
+        // Except for the last removed element, which only needs its summary updated should it remain
+        let (cleared_key, cleared_bounds) = node_stack.pop().unwrap();
+        if self.nodes.key_is_valid(cleared_key as usize) {
+            self.update_aggregate(cleared_key, &cleared_bounds);
+        }
+        for (node_key, node_bounds) in node_stack.into_iter().rev() {
             match self.nodes.get(node_key as usize) {
                 NodeContent::Nothing => {
-                    *self.nodes.get_mut(node_key as usize) =
-                        if !self.node_children[node_key as usize].is_empty() {
-                            // This is incorrect information which needs to be corrected
-                            // As the current Node is either a parent or a data leaf, it can not be Empty or nothing
-                            // To correct this, the children will determine the count
-                            NodeContent::Internal(self.count_cached_children(node_key))
-                        } else {
-                            NodeContent::Nothing
-                        };
+                    *self.nodes.get_mut(node_key as usize) = if !self.node_children
+                        [node_key as usize]
+                        .is_empty()
+                    {
+                        // This is incorrect information which needs to be corrected
+                        // As the current Node is either a parent or a data leaf, it can not be Empty or nothing
+                        // To correct this, the children will determine the count
+                        NodeContent::Internal(self.count_cached_children(node_key, &node_bounds))
+                    } else {
+                        // Children left without voxels are not reachable from an empty node
+                        self.deallocate_children_of(node_key);
+                        NodeContent::Nothing
+                    };
                 }
                 NodeContent::Internal(contains_count) => {
-                    *self.nodes.get_mut(node_key as usize) =
-                        if removed_nodes_count < *contains_count {
-                            NodeContent::Internal(contains_count - removed_nodes_count)
-                        } else {
-                            NodeContent::Nothing
-                        };
+                    let contains_count = *contains_count;
+                    *self.nodes.get_mut(node_key as usize) = if removed_voxels < contains_count {
+                        NodeContent::Internal(contains_count - removed_voxels)
+                    } else if self.has_occupied_child(node_key) {
+                        // Trees saved by earlier versions may store counts lower than the voxels inside,
+                        // so other children may still have content
+                        NodeContent::Internal(self.count_cached_children(node_key, &node_bounds))
+                    } else {
+                        // Children left without voxels are not reachable from an empty node
+                        self.deallocate_children_of(node_key);
+                        NodeContent::Nothing
+                    };
                 }
                 _ => {}
             }
+            self.update_occupied_bits(node_key);
+            self.update_aggregate(node_key, &node_bounds);
+        }
+        #[cfg(feature = "debug-verify")]
+        self.verify_counts_towards(position);
+        Ok(())
+    }
+
+    /// Removes every voxel from the tree, leaving only an empty root node.
+    /// The space allocated for nodes and bricks is kept, so refilling the tree, e.g. when
+    /// loading the next level, does not need to allocate it again. Calculated light and fluid levels are dropped.
+    pub fn clear_all(&mut self) {
+        let octree_size = self.octree_size;
+        self.record(|| {
+            RecordedCall::Edit(OctreeEdit::Clear {
+                position: V3c::unit(0),
+                size: octree_size,
+            })
+        });
+        self.nodes.clear();
+        self.node_children.clear();
+        self.bricks.clear();
+        let root_node_key = self.nodes.push(NodeContent::Nothing);
+        debug_assert!(Octree::<T, DIM>::ROOT_NODE_KEY as usize == root_node_key);
+        self.node_children.push(NodeChildren::new(key_none_value()));
+        self.simplify_queue.clear();
+        self.simplify_queued.clear();
+        self.dirty_bricks.clear();
+        self.mark_changed(&V3c::unit(0), octree_size);
+        self.light = None;
+        self.auxiliary = None;
+        self.fluids = Default::default();
+        self.update_aggregate(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            &Cube::root_bounds(self.octree_size),
+        );
+    }
+
+    /// Empties the tree like `clear_all` does, and changes its size to the given one,
+    /// keeping the space allocated for nodes and bricks. Settings of the tree, e.g. the voxel size
+    /// or the memory budget are kept as well. A recording of the tree is restarted with the new size.
+    /// * `size` - must be `DIM * (2^x)`, see `Octree::new`
+    pub fn reset_to(&mut self, size: u32) -> Result<(), OctreeError> {
+        if Self::is_size_inadequate(size) {
+            return Err(OctreeError::InvalidNodeSize(size));
+        }
+        let recording = self.recorder.take().is_some();
+        self.clear_all();
+        self.octree_size = size;
+        // The regions noted at the previous size don't fit the new one
+        self.mark_changed(&V3c::unit(0), size);
+        if recording {
+            self.start_recording();
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of the given octant of the tree with the given tree, moving its nodes
+    /// and bricks into this tree instead of inserting its voxels one by one, so it costs
+    /// in proportion to the number of nodes of the given tree.
+    /// * `octant` - The octant of the root to replace, x is the least significant bit, then z, then y
+    /// * `subtree` - The tree to place into the octant, its size must be half the size of this tree
+    pub fn graft(&mut self, octant: u32, subtree: Octree<T, DIM>) -> Result<(), OctreeError> {
+        let octant = Octant::from_index(octant).ok_or(OctreeError::InvalidOctant(octant))?;
+        if subtree.octree_size * 2 != self.octree_size {
+            return Err(OctreeError::InvalidNodeSize(subtree.octree_size));
+        }
+        self.ensure_memory_budget()?;
+
+        let root_bounds = Cube::root_bounds(self.octree_size);
+        self.graft_subtree(
+            Octree::<T, DIM>::ROOT_NODE_KEY,
+            &root_bounds,
+            octant,
+            subtree,
+        );
+        if self.auto_simplify {
+            self.simplify(Octree::<T, DIM>::ROOT_NODE_KEY);
+        }
+        let grafted_bounds = root_bounds.child_bounds_for(octant);
+        self.mark_changed(&grafted_bounds.min_position, grafted_bounds.size);
+        let grafted = self.node_children[Octree::<T, DIM>::ROOT_NODE_KEY as usize][octant];
+        if key_might_be_valid(grafted) {
+            self.update_aggregates_below(grafted, grafted_bounds);
+        }
+        self.update_aggregate(Octree::<T, DIM>::ROOT_NODE_KEY, &root_bounds);
+        #[cfg(feature = "debug-verify")]
+        self.verify_counts_towards(&grafted_bounds.min_position);
+        Ok(())
+    }
+
+    /// Splits the tree into the subtrees under the octants of its root, the counterpart of `graft`.
+    /// Octants without any content are None. Fails with `OctreeError::InvalidNodeSize`
+    /// if the tree is too small for its octants to be trees on their own.
+    pub fn split_root(mut self) -> Result<[Option<Octree<T, DIM>>; 8], OctreeError> {
+        let subtree_size = self.octree_size / 2;
+        if Self::is_size_inadequate(subtree_size) {
+            return Err(OctreeError::InvalidNodeSize(subtree_size));
+        }
+        let root_key = Octree::<T, DIM>::ROOT_NODE_KEY as usize;
+        if self.nodes.get(root_key).is_leaf() {
+            // Every octant of a leaf holds the same content
+            let children = self.make_uniform_children(root_key);
+            self.set_node_content(root_key, NodeContent::Internal(0));
+            self.node_children[root_key].set(children);
+        }
+
+        let mut subtrees = [None, None, None, None, None, None, None, None];
+        for (octant, subtree) in Octant::iter().zip(subtrees.iter_mut()) {
+            let child_key = self.node_children[root_key][octant];
+            if crate::object_pool::key_might_be_valid(child_key)
+                && self.nodes.get(child_key as usize).is_occupied()
+            {
+                *subtree = Some(self.extract_subtree(child_key, subtree_size)?);
+            }
+        }
+        Ok(subtrees)
+    }
+
+    /// Moves the given node and its descendants into a new tree of the given size
+    fn extract_subtree(&mut self, node: PoolKey, size: u32) -> Result<Octree<T, DIM>, OctreeError> {
+        let mut subtree = Octree::<T, DIM>::new(size)?;
+        subtree.auto_simplify = self.auto_simplify;
+        subtree.voxel_size = self.voxel_size;
+        let mut node_stack = vec![(node, Octree::<T, DIM>::ROOT_NODE_KEY)];
+        while let Some((source_key, target_key)) = node_stack.pop() {
+            let content = match self.nodes.get(source_key as usize).clone() {
+                NodeContent::Leaf(brick) => {
+                    let brick = self.bricks.pop(brick as usize).unwrap();
+                    NodeContent::Leaf(subtree.bricks.push(brick) as PoolKey)
+                }
+                content => content,
+            };
+            *subtree.nodes.get_mut(target_key as usize) = content;
+
+            let mut children = self.node_children[source_key as usize];
+            for octant in Octant::iter() {
+                let child_key = children[octant];
+                if crate::object_pool::key_might_be_valid(child_key) {
+                    let target_child_key = subtree.nodes.push(NodeContent::Nothing) as PoolKey;
+                    node_stack.push((child_key, target_child_key));
+                    children[octant] = target_child_key;
+                }
+            }
+            subtree
+                .node_children
+                .resize(subtree.nodes.len(), NodeChildren::new(key_none_value()));
+            subtree.node_children[target_key as usize] = children;
+        }
+        Ok(subtree)
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData> OctreeWriteQueue<T> {
+    /// Creates an empty queue
+    pub fn new() -> Self {
+        Self {
+            edits: Default::default(),
+        }
+    }
+
+    /// The number of edits waiting to be applied
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    /// True if there are no edits waiting to be applied
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Queues the given edit to be applied after the already queued ones
+    pub fn push(&mut self, edit: OctreeEdit<T>) {
+        self.edits.push_back(edit);
+    }
+
+    /// Queues setting the given data at the given position, see `Octree::insert`
+    pub fn insert(&mut self, position: &V3c<u32>, data: T) {
+        self.insert_at_lod(position, 1, data);
+    }
+
+    /// Queues setting the given data at the given position and lod size, see `Octree::insert_at_lod`
+    pub fn insert_at_lod(&mut self, position: &V3c<u32>, insert_size: u32, data: T) {
+        self.push(OctreeEdit::Insert {
+            position: *position,
+            size: insert_size,
+            data,
+        });
+    }
+
+    /// Queues clearing the data at the given position, see `Octree::clear`
+    pub fn clear(&mut self, position: &V3c<u32>) {
+        self.clear_at_lod(position, 1);
+    }
+
+    /// Queues clearing the data at the given position and lod size, see `Octree::clear_at_lod`
+    pub fn clear_at_lod(&mut self, position: &V3c<u32>, clear_size: u32) {
+        self.push(OctreeEdit::Clear {
+            position: *position,
+            size: clear_size,
+        });
+    }
+
+    /// The data the given position would have once the queued edits are applied to the given tree, without applying them:
+    /// None if no queued edit touches the position, otherwise what the last such edit leaves there
+    pub fn pending_at<const DIM: usize>(
+        &self,
+        tree: &Octree<T, DIM>,
+        position: &V3c<u32>,
+    ) -> Option<Option<&T>> {
+        self.edits
+            .iter()
+            .rev()
+            .find(|edit| {
+                edit.region::<DIM>(tree.octree_size)
+                    .is_some_and(|region| bound_contains(&region, position))
+            })
+            .map(|edit| match edit {
+                OctreeEdit::Insert { data, .. } if !data.is_empty() => Some(data),
+                _ => None,
+            })
+    }
+
+    /// Applies every queued edit to the given tree, in the order they were queued
+    /// Should an edit fail, it is removed from the queue and the error is returned, the edits after it stay queued
+    pub fn apply<const DIM: usize>(
+        &mut self,
+        tree: &mut Octree<T, DIM>,
+    ) -> Result<(), OctreeError> {
+        while let Some(edit) = self.edits.pop_front() {
+            tree.apply_edit(edit)?;
         }
         Ok(())
     }
+
+    /// Applies queued edits to the given tree until the given time budget expires.
+    /// At least one edit is applied in every call, so the queue is always progressing.
+    /// Should an edit fail, it is removed from the queue and the error is returned, the edits after it stay queued
+    /// * `tree` - The tree to apply the edits to
+    /// * `budget` - The time after which no more edits are started
+    ///
+    /// output: the number of edits still waiting to be applied
+    pub fn apply_for<const DIM: usize>(
+        &mut self,
+        tree: &mut Octree<T, DIM>,
+        budget: Duration,
+    ) -> Result<usize, OctreeError> {
+        let start = Instant::now();
+        while let Some(edit) = self.edits.pop_front() {
+            tree.apply_edit(edit)?;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        Ok(self.edits.len())
+    }
+}
+
+impl<T> OctreeEdit<T> {
+    /// The region affected by the edit in a tree of the given size, following `Octree::insert_at_lod`:
+    /// the node the edit reaches, or the part of its brick for edits smaller, than DIM.
+    /// None for edits changing nothing, e.g. because their position is outside the tree
+    pub(in crate::octree) fn region<const DIM: usize>(&self, octree_size: u32) -> Option<Cube> {
+        let (position, size) = match self {
+            OctreeEdit::Insert { position, size, .. } | OctreeEdit::Clear { position, size } => {
+                (position, *size)
+            }
+        };
+        let mut node_bounds = Cube::root_bounds(octree_size);
+        if 0 == size || !bound_contains(&node_bounds, position) {
+            return None;
+        }
+
+        // The edit reaches the largest node on the path not larger, than its size or DIM
+        while node_bounds.size > size.max(DIM as u32) {
+            node_bounds = node_bounds.child_bounds_for(child_octant_for(&node_bounds, position));
+        }
+        if size >= DIM as u32 {
+            return Some(node_bounds);
+        }
+
+        // Edits smaller, than DIM start at the position, moved back inside the brick should they reach over its edge
+        let offset = *position - node_bounds.min_position;
+        let max_offset = DIM as u32 - size;
+        Some(Cube::new(
+            node_bounds.min_position
+                + V3c::new(
+                    offset.x.min(max_offset),
+                    offset.y.min(max_offset),
+                    offset.z.min(max_offset),
+                ),
+            size,
+        ))
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Applies the given edit to the tree
+    pub fn apply_edit(&mut self, edit: OctreeEdit<T>) -> Result<(), OctreeError> {
+        match edit {
+            OctreeEdit::Insert {
+                position,
+                size,
+                data,
+            } => self.insert_at_lod(&position, size, data),
+            OctreeEdit::Clear { position, size } => self.clear_at_lod(&position, size),
+        }
+    }
 }
@@ -0,0 +1,36 @@
+use crate::object_pool::key_might_be_valid;
+use crate::octree::{detail::child_octant_for, types::NodeContent, Octree, VoxelData};
+use crate::spatial::{math::vector::V3c, Cube};
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Checks the voxel count of every internal node on the path from the root to the given position
+    /// against the voxels in its children: the stored count of internal children, and the voxels
+    /// of leaf children, which are counted one by one. Only enabled with the `debug-verify` feature,
+    /// called after mutations with the position they touched, so the cost is bound by the depth of the tree.
+    /// Voxels changed through the references given by `get_mut` are not tracked in the counts.
+    ///
+    /// # Panics
+    /// On the first node where the stored count doesn't match the voxels in its children
+    pub(in crate::octree) fn verify_counts_towards(&self, position: &V3c<u32>) {
+        let mut node_key = Octree::<T, DIM>::ROOT_NODE_KEY;
+        let mut bounds = Cube::root_bounds(self.octree_size);
+        while let NodeContent::Internal(count) = self.nodes.get(node_key as usize) {
+            let children_count = self.count_cached_children(node_key, &bounds);
+            assert!(
+                *count == children_count,
+                "Voxel count of node {} at {:?} is {}, while its children contain {} voxels",
+                node_key,
+                bounds,
+                count,
+                children_count
+            );
+            let octant = child_octant_for(&bounds, position);
+            let child_key = self.node_children[node_key as usize][octant];
+            if !key_might_be_valid(child_key) {
+                break;
+            }
+            node_key = child_key;
+            bounds = bounds.child_bounds_for(octant);
+        }
+    }
+}
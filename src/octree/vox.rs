@@ -0,0 +1,158 @@
+use crate::octree::{Octree, V3c, VoxelData};
+use std::io::{Error, ErrorKind};
+
+fn invalid_data(message: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn unexpected_end() -> Error {
+    invalid_data("Unexpected end of .vox file")
+}
+
+/// The palette of models without an RGBA chunk, as RGBA colors by color index:
+/// a 6*6*6 color cube without black, followed by ramps of red, green, blue and gray
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE_STEPS: [u8; 6] = [0xFF, 0xCC, 0x99, 0x66, 0x33, 0x00];
+    const RAMP_STEPS: [u8; 10] = [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+    for r in CUBE_STEPS {
+        for g in CUBE_STEPS {
+            for b in CUBE_STEPS {
+                if 0 != r || 0 != g || 0 != b {
+                    palette[index] = [r, g, b, 0xFF];
+                    index += 1;
+                }
+            }
+        }
+    }
+    for ramp in [[1, 0, 0], [0, 1, 0], [0, 0, 1], [1, 1, 1]] {
+        for step in RAMP_STEPS {
+            palette[index] = [ramp[0] * step, ramp[1] * step, ramp[2] * step, 0xFF];
+            index += 1;
+        }
+    }
+    palette
+}
+
+/// The voxels of the first model of a .vox file, see `Octree::from_vox`
+struct VoxModel {
+    size: V3c<u32>,
+    /// The position and color index of each voxel
+    voxels: Vec<(V3c<u32>, u8)>,
+    /// The RGBA color of each color index
+    palette: [[u8; 4]; 256],
+}
+
+/// Reads the first model of a .vox file
+/// The format is described at https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+fn read_vox(bytes: &[u8]) -> Result<VoxModel, Error> {
+    let read_u32 = |at: usize| -> Result<u32, Error> {
+        bytes
+            .get(at..at.saturating_add(4))
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(unexpected_end)
+    };
+    if bytes.get(0..4) != Some(b"VOX ".as_slice()) {
+        return Err(invalid_data("Not a .vox file"));
+    }
+    if bytes.get(8..12) != Some(b"MAIN".as_slice()) {
+        return Err(invalid_data("Missing MAIN chunk in .vox file"));
+    }
+
+    let mut model = VoxModel {
+        size: V3c::unit(0),
+        voxels: Vec::new(),
+        palette: default_palette(),
+    };
+    let mut has_model = false;
+    // Skip the header and the MAIN chunk header, every other chunk is a child of MAIN
+    let mut at = 8 + 12;
+    while at.saturating_add(12) <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let content_size = read_u32(at + 4)? as usize;
+        let children_size = read_u32(at + 8)? as usize;
+        let content = at + 12;
+        match id {
+            b"SIZE" if !has_model => {
+                model.size = V3c::new(
+                    read_u32(content)?,
+                    read_u32(content + 4)?,
+                    read_u32(content + 8)?,
+                );
+            }
+            b"XYZI" if !has_model => {
+                let count = read_u32(content)? as usize;
+                let data = bytes
+                    .get(content + 4..(content + 4).saturating_add(count.saturating_mul(4)))
+                    .ok_or_else(unexpected_end)?;
+                model.voxels = data
+                    .chunks_exact(4)
+                    .map(|v| (V3c::new(v[0] as u32, v[1] as u32, v[2] as u32), v[3]))
+                    .collect();
+                has_model = true;
+            }
+            b"RGBA" => {
+                let colors = bytes
+                    .get(content..content + 255 * 4)
+                    .ok_or_else(unexpected_end)?;
+                // Color index 0 is empty, so the palette is shifted by one
+                for (index, color) in colors.chunks_exact(4).enumerate() {
+                    model.palette[index + 1] = [color[0], color[1], color[2], color[3]];
+                }
+            }
+            _ => {}
+        }
+        at = content
+            .saturating_add(content_size)
+            .saturating_add(children_size);
+    }
+    if !has_model {
+        return Err(invalid_data("No model in .vox file"));
+    }
+    Ok(model)
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> Octree<T, DIM> {
+    /// Creates a tree from the first model of a MagicaVoxel file (.vox).
+    /// The tree is the smallest possible to contain the model, with its lowest corner at the origin.
+    /// MagicaVoxel is Z-up, so the Y and Z axes of the model are swapped.
+    /// * `bytes` - The content of the .vox file
+    /// * `palette` - Provides the voxel of each color index, given with its RGBA color;
+    ///   models without an RGBA chunk have the colors of the default palette of MagicaVoxel
+    pub fn from_vox(
+        bytes: &[u8],
+        mut palette: impl FnMut(u8, [u8; 4]) -> Option<T>,
+    ) -> Result<Self, Error> {
+        let model = read_vox(bytes)?;
+        let extent = model.size.x.max(model.size.y).max(model.size.z);
+        let mut size = DIM as u32;
+        while size < extent {
+            size *= 2;
+        }
+
+        let voxels = (0..=u8::MAX)
+            .map(|index| palette(index, model.palette[index as usize]))
+            .collect::<Vec<_>>();
+        let mut tree = Self::new(size).map_err(|error| invalid_data(format!("{:?}", error)))?;
+        tree.auto_simplify = false;
+        for (position, color_index) in model.voxels.iter() {
+            let Some(data) = &voxels[*color_index as usize] else {
+                continue;
+            };
+            tree.insert(&V3c::new(position.x, position.z, position.y), data.clone())
+                .map_err(|error| invalid_data(format!("{:?}", error)))?;
+        }
+        while !tree.simplify_incremental(usize::MAX) {}
+        tree.auto_simplify = true;
+        Ok(tree)
+    }
+
+    /// Loads a tree from the MagicaVoxel file at the given file path, see `from_vox`
+    pub fn load_vox(
+        path: &str,
+        palette: impl FnMut(u8, [u8; 4]) -> Option<T>,
+    ) -> Result<Self, Error> {
+        Self::from_vox(&std::fs::read(path)?, palette)
+    }
+}
@@ -0,0 +1,362 @@
+use crate::octree::{types::OctreeError, Octree, SeamHandling, V3c, VoxelData};
+use bendy::{decoding::FromBencode, encoding::ToBencode};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "world_dir")]
+use std::path::PathBuf;
+
+/// The content of a world position, telling apart positions known to be empty
+/// from the ones in chunks which are not loaded, see `VoxelWorld::query`
+#[derive(Debug, PartialEq)]
+pub enum WorldGet<'a, T> {
+    /// There is no data at the position
+    Empty,
+    /// The chunk of the position is not loaded, so its content is unknown
+    Unknown,
+    /// The data at the position
+    Voxel(&'a T),
+}
+
+/// Mutable access to a chunk of a `VoxelWorld`, see `VoxelWorld::chunk_mut`.
+/// Once dropped, the chunk is marked to be saved again, should its voxels have been edited through it
+pub struct ChunkMut<'a, T: Default + Clone + VoxelData, const DIM: usize> {
+    chunk: &'a mut Octree<T, DIM>,
+    chunk_coord: V3c<i32>,
+    dirty_chunks: &'a mut HashSet<V3c<i32>>,
+    // The edit count of the chunk when the access was given
+    edit_count: u64,
+}
+
+impl<T: Default + Clone + VoxelData, const DIM: usize> Deref for ChunkMut<'_, T, DIM> {
+    type Target = Octree<T, DIM>;
+
+    fn deref(&self) -> &Self::Target {
+        self.chunk
+    }
+}
+
+impl<T: Default + Clone + VoxelData, const DIM: usize> DerefMut for ChunkMut<'_, T, DIM> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.chunk
+    }
+}
+
+impl<T: Default + Clone + VoxelData, const DIM: usize> Drop for ChunkMut<'_, T, DIM> {
+    fn drop(&mut self) {
+        if self.chunk.edit_count != self.edit_count {
+            self.dirty_chunks.insert(self.chunk_coord);
+        }
+    }
+}
+
+/// A world made up of octrees of the same size placed on a grid, each called a chunk.
+/// Voxels are addressed with signed world positions, so the world can extend in every direction;
+/// only the chunks with content in them, or which were added explicitly, are stored.
+/// Chunks known to be empty can be marked as loaded without storing a tree for them,
+/// so streamed worlds can tell them apart from the chunks not loaded yet.
+pub struct VoxelWorld<T: Default + Clone + VoxelData, const DIM: usize = 1> {
+    pub(in crate::octree) chunk_size: u32,
+    pub(in crate::octree) chunks: HashMap<V3c<i32>, Octree<T, DIM>>,
+    pub(in crate::octree) empty_chunks: HashSet<V3c<i32>>,
+    // Chunks changed since they were last loaded from or saved to the directory below, see `save_dir`
+    pub(in crate::octree) dirty_chunks: HashSet<V3c<i32>>,
+    // The directory the chunks not in dirty_chunks match the files of, if any
+    #[cfg(feature = "world_dir")]
+    pub(in crate::octree) clean_dir: Option<PathBuf>,
+    // Lower resolution versions of chunks, see `insert_coarse_chunk`
+    pub(in crate::octree) coarse_chunks: HashMap<V3c<i32>, Octree<T, DIM>>,
+    pub(in crate::octree) seam_handling: SeamHandling,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelWorld<T, DIM> {
+    /// Creates an empty world
+    /// * `chunk_size` - The size of each chunk, see `Octree::new`
+    pub fn new(chunk_size: u32) -> Result<Self, OctreeError> {
+        if Octree::<T, DIM>::is_size_inadequate(chunk_size) {
+            return Err(OctreeError::InvalidNodeSize(chunk_size));
+        }
+        Ok(Self {
+            chunk_size,
+            chunks: HashMap::new(),
+            empty_chunks: HashSet::new(),
+            dirty_chunks: HashSet::new(),
+            #[cfg(feature = "world_dir")]
+            clean_dir: None,
+            coarse_chunks: HashMap::new(),
+            seam_handling: SeamHandling::default(),
+        })
+    }
+
+    /// The size of each chunk of the world
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// The coordinates of the chunk containing the given world position,
+    /// and the position inside the chunk
+    pub fn chunk_coord_of(&self, position: &V3c<i32>) -> (V3c<i32>, V3c<u32>) {
+        let size = self.chunk_size as i32;
+        (
+            V3c::new(
+                position.x.div_euclid(size),
+                position.y.div_euclid(size),
+                position.z.div_euclid(size),
+            ),
+            V3c::new(
+                position.x.rem_euclid(size) as u32,
+                position.y.rem_euclid(size) as u32,
+                position.z.rem_euclid(size) as u32,
+            ),
+        )
+    }
+
+    /// The world position of the given position inside the given chunk
+    pub fn world_position_of(&self, chunk_coord: &V3c<i32>, position: &V3c<u32>) -> V3c<i32> {
+        *chunk_coord * self.chunk_size as i32 + V3c::<i32>::from(*position)
+    }
+
+    /// The chunk at the given coordinates, if it is present
+    pub fn chunk(&self, chunk_coord: &V3c<i32>) -> Option<&Octree<T, DIM>> {
+        self.chunks.get(chunk_coord)
+    }
+
+    /// The chunk at the given coordinates, if it is present; should it be edited through the returned access,
+    /// it is saved again by the next `save_dir`
+    pub fn chunk_mut(&mut self, chunk_coord: &V3c<i32>) -> Option<ChunkMut<'_, T, DIM>> {
+        let chunk = self.chunks.get_mut(chunk_coord)?;
+        Some(ChunkMut {
+            edit_count: chunk.edit_count,
+            chunk,
+            chunk_coord: *chunk_coord,
+            dirty_chunks: &mut self.dirty_chunks,
+        })
+    }
+
+    /// Places the given tree into the world as the chunk at the given coordinates,
+    /// returns the chunk it replaced, if any
+    pub fn insert_chunk(
+        &mut self,
+        chunk_coord: V3c<i32>,
+        chunk: Octree<T, DIM>,
+    ) -> Result<Option<Octree<T, DIM>>, OctreeError> {
+        if chunk.octree_size != self.chunk_size {
+            return Err(OctreeError::InvalidNodeSize(chunk.octree_size));
+        }
+        self.empty_chunks.remove(&chunk_coord);
+        self.dirty_chunks.insert(chunk_coord);
+        Ok(self.chunks.insert(chunk_coord, chunk))
+    }
+
+    /// Marks the chunk at the given coordinates as loaded without any content,
+    /// returns the chunk it replaced, if any
+    pub fn insert_empty_chunk(&mut self, chunk_coord: V3c<i32>) -> Option<Octree<T, DIM>> {
+        self.empty_chunks.insert(chunk_coord);
+        self.dirty_chunks.insert(chunk_coord);
+        self.chunks.remove(&chunk_coord)
+    }
+
+    /// Takes the chunk at the given coordinates out of the world, after which its content is unknown.
+    /// Its changes since it was last saved by `save_dir` are not saved.
+    pub fn remove_chunk(&mut self, chunk_coord: &V3c<i32>) -> Option<Octree<T, DIM>> {
+        self.empty_chunks.remove(chunk_coord);
+        self.dirty_chunks.remove(chunk_coord);
+        self.chunks.remove(chunk_coord)
+    }
+
+    /// Places the given tree into the world as a lower resolution version of the chunk at the given coordinates,
+    /// e.g. to be shown in the distance, see `get_by_ray_lod`. Each of its voxels covers `chunk_size / octree_size`
+    /// voxels of the chunk along every axis. Coarse versions are kept apart from the chunks:
+    /// they are not updated by edits and not saved with the world.
+    /// Returns the coarse version it replaced, if any
+    pub fn insert_coarse_chunk(
+        &mut self,
+        chunk_coord: V3c<i32>,
+        chunk: Octree<T, DIM>,
+    ) -> Result<Option<Octree<T, DIM>>, OctreeError> {
+        if chunk.octree_size >= self.chunk_size || 0 != self.chunk_size % chunk.octree_size {
+            return Err(OctreeError::InvalidNodeSize(chunk.octree_size));
+        }
+        Ok(self.coarse_chunks.insert(chunk_coord, chunk))
+    }
+
+    /// The lower resolution version of the chunk at the given coordinates, if it is present
+    pub fn coarse_chunk(&self, chunk_coord: &V3c<i32>) -> Option<&Octree<T, DIM>> {
+        self.coarse_chunks.get(chunk_coord)
+    }
+
+    /// Takes the lower resolution version of the chunk at the given coordinates out of the world
+    pub fn remove_coarse_chunk(&mut self, chunk_coord: &V3c<i32>) -> Option<Octree<T, DIM>> {
+        self.coarse_chunks.remove(chunk_coord)
+    }
+
+    /// How the faces between chunks shown in different resolutions are handled by `visible_faces_of_chunk_lod`
+    pub fn seam_handling(&self) -> SeamHandling {
+        self.seam_handling
+    }
+
+    /// Sets how the faces between chunks shown in different resolutions are handled by `visible_faces_of_chunk_lod`
+    pub fn set_seam_handling(&mut self, seam_handling: SeamHandling) {
+        self.seam_handling = seam_handling;
+    }
+
+    /// True if the chunk at the given coordinates is present, or marked as empty by `insert_empty_chunk`
+    pub fn is_chunk_loaded(&self, chunk_coord: &V3c<i32>) -> bool {
+        self.chunks.contains_key(chunk_coord) || self.empty_chunks.contains(chunk_coord)
+    }
+
+    /// The coordinates and contents of every chunk in the world, in no particular order
+    pub fn chunks(&self) -> impl Iterator<Item = (&V3c<i32>, &Octree<T, DIM>)> {
+        self.chunks.iter()
+    }
+
+    /// Provides immutable reference to the data, if there is any at the given world position
+    pub fn get(&self, position: &V3c<i32>) -> Option<&T> {
+        let (chunk_coord, local) = self.chunk_coord_of(position);
+        self.chunks.get(&chunk_coord)?.get(&local)
+    }
+
+    /// The content of the given world position, with positions in chunks which are not loaded
+    /// reported as unknown instead of empty, see `is_chunk_loaded`
+    pub fn query(&self, position: &V3c<i32>) -> WorldGet<'_, T> {
+        let (chunk_coord, local) = self.chunk_coord_of(position);
+        match self.chunks.get(&chunk_coord) {
+            Some(chunk) => chunk.get(&local).map_or(WorldGet::Empty, WorldGet::Voxel),
+            None if self.empty_chunks.contains(&chunk_coord) => WorldGet::Empty,
+            None => WorldGet::Unknown,
+        }
+    }
+
+    /// Sets the given data at the given world position, creating its chunk if needed
+    pub fn insert(&mut self, position: &V3c<i32>, data: T) -> Result<(), OctreeError> {
+        let (chunk_coord, local) = self.chunk_coord_of(position);
+        let chunk = match self.chunks.entry(chunk_coord) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.empty_chunks.remove(&chunk_coord);
+                entry.insert(Octree::new(self.chunk_size)?)
+            }
+        };
+        self.dirty_chunks.insert(chunk_coord);
+        chunk.insert(&local, data)
+    }
+
+    /// Clears the data at the given world position, if its chunk is present
+    pub fn clear(&mut self, position: &V3c<i32>) -> Result<(), OctreeError> {
+        let (chunk_coord, local) = self.chunk_coord_of(position);
+        match self.chunks.get_mut(&chunk_coord) {
+            Some(chunk) => {
+                self.dirty_chunks.insert(chunk_coord);
+                chunk.clear(&local)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// converts the world with every chunk in it to a byte representation
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bencode().ok().unwrap()
+    }
+
+    /// parses the world from a byte string
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::from_bencode(&bytes).ok().unwrap()
+    }
+}
+
+/// A world with multiple named layers of content, e.g. terrain, props or editor markup, each
+/// stored as a separate `VoxelWorld` so voxels of different layers don't prevent the
+/// simplification of each other. Layers are composited in order, later layers on top.
+pub struct LayeredWorld<T: Default + Clone + VoxelData, const DIM: usize = 1> {
+    pub(in crate::octree) chunk_size: u32,
+    pub(in crate::octree) layers: Vec<(String, VoxelWorld<T, DIM>)>,
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> LayeredWorld<T, DIM> {
+    /// Creates a world without any layers
+    /// * `chunk_size` - The size of each chunk in every layer, see `VoxelWorld::new`
+    pub fn new(chunk_size: u32) -> Result<Self, OctreeError> {
+        VoxelWorld::<T, DIM>::new(chunk_size)?;
+        Ok(Self {
+            chunk_size,
+            layers: Vec::new(),
+        })
+    }
+
+    /// The layer with the given name, added on top of the others if it is not present yet
+    pub fn layer(&mut self, name: &str) -> &mut VoxelWorld<T, DIM> {
+        let index = match self.layer_index(name) {
+            Some(index) => index,
+            None => {
+                self.layers.push((
+                    name.to_string(),
+                    VoxelWorld::new(self.chunk_size).ok().unwrap(),
+                ));
+                self.layers.len() - 1
+            }
+        };
+        &mut self.layers[index].1
+    }
+
+    /// The layer with the given name, if it is present
+    pub fn get_layer(&self, name: &str) -> Option<&VoxelWorld<T, DIM>> {
+        self.layer_index(name).map(|index| &self.layers[index].1)
+    }
+
+    /// Places the given world into this one as the layer with the given name,
+    /// replacing the layer with the same name in its place, or on top of the others.
+    /// Returns the layer it replaced, if any
+    pub fn insert_layer(
+        &mut self,
+        name: &str,
+        layer: VoxelWorld<T, DIM>,
+    ) -> Result<Option<VoxelWorld<T, DIM>>, OctreeError> {
+        if layer.chunk_size != self.chunk_size {
+            return Err(OctreeError::InvalidNodeSize(layer.chunk_size));
+        }
+        match self.layer_index(name) {
+            Some(index) => Ok(Some(std::mem::replace(&mut self.layers[index].1, layer))),
+            None => {
+                self.layers.push((name.to_string(), layer));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Takes the layer with the given name out of the world
+    pub fn remove_layer(&mut self, name: &str) -> Option<VoxelWorld<T, DIM>> {
+        self.layer_index(name)
+            .map(|index| self.layers.remove(index).1)
+    }
+
+    /// Moves the layer with the given name to the given place in the compositing order,
+    /// 0 being the bottom layer. Returns false if there is no such layer
+    pub fn move_layer(&mut self, name: &str, index: usize) -> bool {
+        let Some(current) = self.layer_index(name) else {
+            return false;
+        };
+        let layer = self.layers.remove(current);
+        self.layers.insert(index.min(self.layers.len()), layer);
+        true
+    }
+
+    /// The names of the layers in compositing order, from the bottom layer to the top one
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Provides immutable reference to the data at the given world position
+    /// in the topmost layer with any data there
+    pub fn get(&self, position: &V3c<i32>) -> Option<&T> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|(_, layer)| layer.get(position))
+    }
+
+    /// The place of the layer with the given name in the compositing order
+    fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers
+            .iter()
+            .position(|(layer_name, _)| layer_name == name)
+    }
+}
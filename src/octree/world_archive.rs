@@ -0,0 +1,174 @@
+use crate::octree::{
+    world_dir::{
+        chunk_file_name, chunk_size_mismatch, compress_chunk, content_hash, invalid_data,
+        SavedChunk, WorldManifest, MANIFEST_FILE_NAME,
+    },
+    V3c, VoxelData, VoxelWorld,
+};
+use bendy::{decoding::FromBencode, encoding::ToBencode};
+use std::collections::HashMap;
+use std::io::{Error, Read, Seek, SeekFrom, Write};
+
+/// A world saved into a tar archive by `VoxelWorld::save_archive`, read lazily:
+/// opening it only reads the manifest and the positions of the chunks inside the archive,
+/// the chunks are read once they are loaded, e.g. one by one with `VoxelWorld::load_chunk_from_archive`
+pub struct WorldArchive<R: Read + Seek> {
+    reader: R,
+    manifest: WorldManifest,
+    /// The position and size of the compressed bytes of each saved chunk inside the archive
+    entries: HashMap<V3c<i32>, (u64, u64)>,
+}
+
+impl<R: Read + Seek> WorldArchive<R> {
+    /// Reads the index of the archive from the given source, e.g. an opened file.
+    /// Fails if the archive has no manifest, or any of the chunks listed in it are missing
+    pub fn open(reader: R) -> Result<Self, Error> {
+        let mut archive = tar::Archive::new(reader);
+        let mut manifest = None;
+        let mut entries_by_name = HashMap::new();
+        for entry in archive.entries_with_seek()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if MANIFEST_FILE_NAME == name {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                manifest = Some(WorldManifest::from_bencode(&bytes).map_err(invalid_data)?);
+            } else {
+                entries_by_name.insert(name, (entry.raw_file_position(), entry.size()));
+            }
+        }
+        let manifest = manifest.ok_or_else(|| invalid_data("No world manifest in the archive"))?;
+        let mut entries = HashMap::new();
+        for chunk_coord in manifest.chunks.keys() {
+            let entry = entries_by_name
+                .get(&chunk_file_name(chunk_coord))
+                .ok_or_else(|| {
+                    invalid_data(format!(
+                        "The chunk {:?} is missing from the archive",
+                        chunk_coord
+                    ))
+                })?;
+            entries.insert(*chunk_coord, *entry);
+        }
+        Ok(Self {
+            reader: archive.into_inner(),
+            manifest,
+            entries,
+        })
+    }
+
+    /// The size of the chunks of the world in the archive
+    pub fn chunk_size(&self) -> u32 {
+        self.manifest.chunk_size
+    }
+
+    /// The chunks saved into the archive, chunks known to be empty not included
+    pub fn saved_chunks(&self) -> &HashMap<V3c<i32>, SavedChunk> {
+        &self.manifest.chunks
+    }
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelWorld<T, DIM> {
+    /// Writes the world into the given destination as a tar archive, so it can be distributed as a single file:
+    /// every chunk is stored in its own compressed entry, named like its file by `save_dir`, followed by the manifest.
+    /// Chunks marked empty by `insert_empty_chunk`, or without any voxels in them, are only listed in the manifest
+    pub fn save_archive(&self, writer: impl Write) -> Result<(), Error> {
+        let mut builder = tar::Builder::new(writer);
+        let mut manifest = WorldManifest {
+            chunk_size: self.chunk_size,
+            empty_chunks: self.empty_chunks.clone(),
+            ..Default::default()
+        };
+        for (chunk_coord, chunk) in self.chunks.iter() {
+            if chunk.is_empty() {
+                manifest.empty_chunks.insert(*chunk_coord);
+                continue;
+            }
+            let bytes = chunk.to_bytes();
+            manifest.chunks.insert(
+                *chunk_coord,
+                SavedChunk {
+                    hash: content_hash(&bytes),
+                    version: 1,
+                },
+            );
+            append_entry(
+                &mut builder,
+                &chunk_file_name(chunk_coord),
+                &compress_chunk(&bytes)?,
+            )?;
+        }
+        append_entry(
+            &mut builder,
+            MANIFEST_FILE_NAME,
+            &manifest.to_bencode().map_err(invalid_data)?,
+        )?;
+        builder.into_inner()?.flush()
+    }
+
+    /// Loads the world saved into the given archive by `save_archive`, with every chunk in it
+    pub fn load_archive<R: Read + Seek>(archive: &mut WorldArchive<R>) -> Result<Self, Error> {
+        let mut world = Self::open_archive(archive)?;
+        let chunk_coords = archive.entries.keys().copied().collect::<Vec<_>>();
+        for chunk_coord in chunk_coords.iter() {
+            world.load_archived_chunk(archive, chunk_coord)?;
+        }
+        world.empty_chunks = archive.manifest.empty_chunks.clone();
+        Ok(world)
+    }
+
+    /// Creates a world for the given archive without loading any of its chunks,
+    /// so they can be loaded one by one with `load_chunk_from_archive`, see `open_dir`
+    pub fn open_archive<R: Read + Seek>(archive: &WorldArchive<R>) -> Result<Self, Error> {
+        Self::new(archive.chunk_size()).map_err(|error| invalid_data(format!("{:?}", error)))
+    }
+
+    /// Loads the chunk at the given coordinates from the given archive saved by `save_archive`,
+    /// replacing the chunk in the world if it is present.
+    /// Returns false if the archive has no chunk at the given coordinates, the world is not changed then
+    pub fn load_chunk_from_archive<R: Read + Seek>(
+        &mut self,
+        archive: &mut WorldArchive<R>,
+        chunk_coord: &V3c<i32>,
+    ) -> Result<bool, Error> {
+        if archive.chunk_size() != self.chunk_size {
+            return Err(chunk_size_mismatch(archive.chunk_size()));
+        }
+        if archive.entries.contains_key(chunk_coord) {
+            self.load_archived_chunk(archive, chunk_coord)?;
+        } else if archive.manifest.empty_chunks.contains(chunk_coord) {
+            self.insert_empty_chunk(*chunk_coord);
+            self.dirty_chunks.remove(chunk_coord);
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Reads the given chunk from its entry in the archive, and places it into the world
+    fn load_archived_chunk<R: Read + Seek>(
+        &mut self,
+        archive: &mut WorldArchive<R>,
+        chunk_coord: &V3c<i32>,
+    ) -> Result<(), Error> {
+        let (position, size) = archive.entries[chunk_coord];
+        archive.reader.seek(SeekFrom::Start(position))?;
+        self.place_saved_chunk(
+            chunk_coord,
+            &archive.manifest.chunks[chunk_coord],
+            (&mut archive.reader).take(size),
+        )
+    }
+}
+
+/// Appends a file with the given name and contents to the archive under construction
+fn append_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, name, bytes)
+}
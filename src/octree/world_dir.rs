@@ -0,0 +1,307 @@
+use crate::octree::{
+    progress::{CancellationToken, ProgressSink},
+    Octree, V3c, VoxelData, VoxelWorld,
+};
+use bendy::{decoding::FromBencode, encoding::ToBencode};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The version of the format of the manifest of world directories
+pub(in crate::octree) const WORLD_MANIFEST_VERSION: u32 = 1;
+
+/// The name of the manifest file inside a world directory
+pub(in crate::octree) const MANIFEST_FILE_NAME: &str = "manifest.bin";
+
+/// A chunk saved into a world directory, as listed in its manifest
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SavedChunk {
+    /// The hash of the uncompressed bytes of the chunk, checked when the chunk is loaded
+    pub hash: u64,
+    /// The number of times the chunk was written into the directory
+    pub version: u32,
+}
+
+/// The index of a world directory: the size of its chunks, the chunks saved in it,
+/// and the chunks known to be empty, which have no files
+#[derive(Default)]
+pub(in crate::octree) struct WorldManifest {
+    pub(in crate::octree) chunk_size: u32,
+    pub(in crate::octree) chunks: HashMap<V3c<i32>, SavedChunk>,
+    pub(in crate::octree) empty_chunks: HashSet<V3c<i32>>,
+}
+
+/// FNV-1a hash of the given bytes, to detect changed or corrupted chunk files
+pub(in crate::octree) fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub(in crate::octree) fn invalid_data(message: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// The error of using a world directory with chunks of the given size for a world with other chunk sizes
+pub(in crate::octree) fn chunk_size_mismatch(chunk_size: u32) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "The directory contains a world with chunks of size {}",
+            chunk_size
+        ),
+    )
+}
+
+/// The name of the file of the chunk at the given coordinates
+pub(in crate::octree) fn chunk_file_name(chunk_coord: &V3c<i32>) -> String {
+    format!(
+        "chunk_{}_{}_{}.bin",
+        chunk_coord.x, chunk_coord.y, chunk_coord.z
+    )
+}
+
+/// The path of the file of the chunk at the given coordinates inside the given world directory
+fn chunk_file_path(path: &str, chunk_coord: &V3c<i32>) -> PathBuf {
+    Path::new(path).join(chunk_file_name(chunk_coord))
+}
+
+/// Removes the file of the chunk at the given coordinates from the given world directory, should it be there
+fn remove_chunk_file(path: &str, chunk_coord: &V3c<i32>) -> Result<(), Error> {
+    match std::fs::remove_file(chunk_file_path(path, chunk_coord)) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// The given directory in a form telling whether two paths point to the same directory
+fn directory_identity(path: &str) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// The given bytes of a chunk, compressed as stored in its file
+pub(in crate::octree) fn compress_chunk(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Reads the manifest of the given world directory, None if there is no manifest in it
+fn read_manifest(path: &str) -> Result<Option<WorldManifest>, Error> {
+    let bytes = match std::fs::read(Path::new(path).join(MANIFEST_FILE_NAME)) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    WorldManifest::from_bencode(&bytes)
+        .map(Some)
+        .map_err(invalid_data)
+}
+
+/// Reads the manifest of the given world directory, failing if there is none
+fn require_manifest(path: &str) -> Result<WorldManifest, Error> {
+    read_manifest(path)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("No world manifest in directory {}", path),
+        )
+    })
+}
+
+impl<T: Default + PartialEq + Clone + VoxelData, const DIM: usize> VoxelWorld<T, DIM> {
+    /// Saves the world into the given directory: every chunk into its own compressed file,
+    /// and a manifest listing the saved chunks with the hash and version of each.
+    /// Saving into the directory the world was last saved into or loaded from only writes the chunks changed since,
+    /// saving into any other directory writes every chunk; chunks saved in the directory which are not loaded
+    /// in the world are kept.
+    /// Chunks marked empty by `insert_empty_chunk`, or without any voxels in them, have their files removed.
+    pub fn save_dir(&mut self, path: &str) -> Result<(), Error> {
+        self.save_dir_with_progress(path, &(), &CancellationToken::new())
+    }
+
+    /// Saves the world like `save_dir`, reporting the number of chunks saved to the given sink.
+    /// If the given token is cancelled, no more chunks are written and it fails with `ErrorKind::Interrupted`;
+    /// the manifest is still updated with the chunks written before, so the directory stays consistent,
+    /// and the chunks not written yet are saved by the next call
+    pub fn save_dir_with_progress(
+        &mut self,
+        path: &str,
+        progress: &impl ProgressSink,
+        cancellation: &CancellationToken,
+    ) -> Result<(), Error> {
+        std::fs::create_dir_all(path)?;
+        let mut manifest = match read_manifest(path)? {
+            Some(manifest) if manifest.chunk_size != self.chunk_size => {
+                return Err(chunk_size_mismatch(manifest.chunk_size));
+            }
+            Some(manifest) => manifest,
+            None => WorldManifest {
+                chunk_size: self.chunk_size,
+                ..Default::default()
+            },
+        };
+
+        // Chunks are only known to match the files of the directory the world was last saved into or loaded from
+        let directory = directory_identity(path);
+        if self.clean_dir.as_ref() != Some(&directory) {
+            self.dirty_chunks.extend(self.chunks.keys());
+            self.clean_dir = Some(directory);
+        }
+
+        let total = self.chunks.len();
+        let mut saved_chunks = Vec::new();
+        let mut cancelled = false;
+        for (done, (chunk_coord, chunk)) in self.chunks.iter().enumerate() {
+            progress.report(done, total);
+            if cancellation.is_cancelled() {
+                cancelled = true;
+                break;
+            }
+            saved_chunks.push(*chunk_coord);
+            if !self.dirty_chunks.contains(chunk_coord) && manifest.chunks.contains_key(chunk_coord)
+            {
+                continue;
+            }
+            if chunk.is_empty() {
+                // Chunks without any voxels are listed as empty, without a file
+                if manifest.chunks.remove(chunk_coord).is_some() {
+                    remove_chunk_file(path, chunk_coord)?;
+                }
+                manifest.empty_chunks.insert(*chunk_coord);
+                continue;
+            }
+            let bytes = chunk.to_bytes();
+            let hash = content_hash(&bytes);
+            let saved = manifest.chunks.entry(*chunk_coord).or_default();
+            if saved.hash == hash && chunk_file_path(path, chunk_coord).exists() {
+                continue;
+            }
+            std::fs::write(chunk_file_path(path, chunk_coord), compress_chunk(&bytes)?)?;
+            saved.hash = hash;
+            saved.version += 1;
+            manifest.empty_chunks.remove(chunk_coord);
+        }
+        for chunk_coord in self.empty_chunks.iter() {
+            if manifest.chunks.remove(chunk_coord).is_some() {
+                remove_chunk_file(path, chunk_coord)?;
+            }
+            manifest.empty_chunks.insert(*chunk_coord);
+        }
+
+        // The manifest is replaced at once, so it is never left half written
+        let manifest_path = Path::new(path).join(MANIFEST_FILE_NAME);
+        let temporary_path = manifest_path.with_extension("tmp");
+        std::fs::write(
+            &temporary_path,
+            manifest.to_bencode().map_err(invalid_data)?,
+        )?;
+        std::fs::rename(temporary_path, manifest_path)?;
+        if cancelled {
+            for chunk_coord in saved_chunks.iter() {
+                self.dirty_chunks.remove(chunk_coord);
+            }
+            return Err(Error::new(
+                ErrorKind::Interrupted,
+                "Saving the world was cancelled",
+            ));
+        }
+        self.dirty_chunks.clear();
+        progress.report(total, total);
+        Ok(())
+    }
+
+    /// Loads the world saved into the given directory by `save_dir`, with every chunk in it
+    pub fn load_dir(path: &str) -> Result<Self, Error> {
+        let manifest = require_manifest(path)?;
+        let mut world = Self::open_dir(path)?;
+        for (chunk_coord, saved) in manifest.chunks.iter() {
+            world.load_saved_chunk(path, chunk_coord, saved)?;
+        }
+        world.empty_chunks = manifest.empty_chunks;
+        Ok(world)
+    }
+
+    /// Creates a world for the given directory saved by `save_dir` without loading any of its chunks,
+    /// so they can be loaded one by one with `load_chunk_from_dir`, e.g. as they come into view
+    pub fn open_dir(path: &str) -> Result<Self, Error> {
+        let mut world = Self::new(require_manifest(path)?.chunk_size)
+            .map_err(|error| invalid_data(format!("{:?}", error)))?;
+        world.clean_dir = Some(directory_identity(path));
+        Ok(world)
+    }
+
+    /// Loads the chunk at the given coordinates from the given directory saved by `save_dir`,
+    /// replacing the chunk in the world if it is present.
+    /// Returns false if the directory has no chunk at the given coordinates, the world is not changed then
+    pub fn load_chunk_from_dir(
+        &mut self,
+        path: &str,
+        chunk_coord: &V3c<i32>,
+    ) -> Result<bool, Error> {
+        let manifest = require_manifest(path)?;
+        if manifest.chunk_size != self.chunk_size {
+            return Err(chunk_size_mismatch(manifest.chunk_size));
+        }
+        if let Some(saved) = manifest.chunks.get(chunk_coord) {
+            self.load_saved_chunk(path, chunk_coord, saved)?;
+        } else if manifest.empty_chunks.contains(chunk_coord) {
+            self.insert_empty_chunk(*chunk_coord);
+            self.dirty_chunks.remove(chunk_coord);
+        } else {
+            return Ok(false);
+        }
+        // Chunks loaded from another directory don't match the files of the one the world is saved into
+        if self.clean_dir != Some(directory_identity(path)) {
+            self.dirty_chunks.insert(*chunk_coord);
+        }
+        Ok(true)
+    }
+
+    /// The chunks saved into the given directory by `save_dir`, chunks known to be empty not included
+    pub fn saved_chunks_in_dir(path: &str) -> Result<HashMap<V3c<i32>, SavedChunk>, Error> {
+        Ok(require_manifest(path)?.chunks)
+    }
+
+    /// Reads the given saved chunk from its file in the given directory, and places it into the world
+    fn load_saved_chunk(
+        &mut self,
+        path: &str,
+        chunk_coord: &V3c<i32>,
+        saved: &SavedChunk,
+    ) -> Result<(), Error> {
+        self.place_saved_chunk(
+            chunk_coord,
+            saved,
+            std::fs::File::open(chunk_file_path(path, chunk_coord))?,
+        )
+    }
+
+    /// Decompresses the given saved chunk read from the given source, and places it into the world
+    pub(in crate::octree) fn place_saved_chunk(
+        &mut self,
+        chunk_coord: &V3c<i32>,
+        saved: &SavedChunk,
+        compressed: impl Read,
+    ) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        DeflateDecoder::new(compressed).read_to_end(&mut bytes)?;
+        if content_hash(&bytes) != saved.hash {
+            return Err(invalid_data(format!(
+                "The file of chunk {:?} doesn't match the manifest",
+                chunk_coord
+            )));
+        }
+        let chunk = Octree::from_bencode(&bytes).map_err(invalid_data)?;
+        if chunk.octree_size != self.chunk_size {
+            return Err(invalid_data(format!(
+                "The chunk {:?} has a size of {}",
+                chunk_coord, chunk.octree_size
+            )));
+        }
+        self.empty_chunks.remove(chunk_coord);
+        self.dirty_chunks.remove(chunk_coord);
+        self.chunks.insert(*chunk_coord, chunk);
+        Ok(())
+    }
+}
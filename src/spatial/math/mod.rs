@@ -1,60 +1,122 @@
 pub mod vector;
 
-use crate::spatial::math::vector::V3c;
+use crate::spatial::{math::vector::V3c, primitives::Axis};
 
 ///####################################################################################
 /// Octant
 ///####################################################################################
-pub(crate) fn offset_region(octant: u32) -> V3c<u32> {
-    match octant {
-        0 => V3c::new(0, 0, 0),
-        1 => V3c::new(1, 0, 0),
-        2 => V3c::new(0, 0, 1),
-        3 => V3c::new(1, 0, 1),
-        4 => V3c::new(0, 1, 0),
-        5 => V3c::new(1, 1, 0),
-        6 => V3c::new(0, 1, 1),
-        7 => V3c::new(1, 1, 1),
-        _ => panic!("Invalid region hash provided for spatial reference!"),
-    }
+/// One of the 8 equal parts of a cube, named after the axes along which it is in the upper half of the cube.
+/// Its index is the key of the child node in the octant: x is the least significant bit, then z, then y
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Octant {
+    Origin = 0,
+    X = 1,
+    Z = 2,
+    XZ = 3,
+    Y = 4,
+    XY = 5,
+    YZ = 6,
+    XYZ = 7,
 }
 
-/// Each Node is separated to 8 Octants based on their relative position inside the Nodes occupying space.
-/// The hash function assigns an index for each octant, so every child Node can be indexed in a well defined manner
-/// * `offset` - From range 0..size in each dimensions
-/// * `size` - Size of the region to check for child octants
-pub fn hash_region(offset: &V3c<f32>, size: f32) -> u32 {
-    let midpoint = V3c::unit(size / 2.);
-    // The below is rewritten to be branchless
-    // (if offset.x < midpoint.x { 0 } else { 1 })
-    //     + if offset.z < midpoint.z { 0 } else { 2 }
-    //     + if offset.y < midpoint.y { 0 } else { 4 }
-    (offset.x >= midpoint.x) as u32
-        + (offset.z >= midpoint.z) as u32 * 2
-        + (offset.y >= midpoint.y) as u32 * 4
+impl Octant {
+    /// Every octant, in the order of their indices
+    pub const ALL: [Octant; 8] = [
+        Octant::Origin,
+        Octant::X,
+        Octant::Z,
+        Octant::XZ,
+        Octant::Y,
+        Octant::XY,
+        Octant::YZ,
+        Octant::XYZ,
+    ];
+
+    /// Iterates over every octant, in the order of their indices
+    pub fn iter() -> impl Iterator<Item = Octant> {
+        Self::ALL.into_iter()
+    }
+
+    /// The octant with the given index, None if the index is not below 8
+    pub fn from_index(index: u32) -> Option<Octant> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// The index of the octant, the key of the child node in it
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// The octant of a cube of the given size containing the given offset from the min position of the cube
+    /// * `offset` - From range 0..size in each dimensions, the upper boundary belonging to the next cube
+    /// * `size` - Size of the cube to check for the octant
+    pub fn from_offset(offset: &V3c<f32>, size: f32) -> Octant {
+        debug_assert!(
+            (0. ..size).contains(&offset.x)
+                && (0. ..size).contains(&offset.y)
+                && (0. ..size).contains(&offset.z),
+            "Offset {offset:?} is outside of the region of size {size}"
+        );
+        let midpoint = V3c::unit(size / 2.);
+        // The below is rewritten to be branchless
+        // (if offset.x < midpoint.x { 0 } else { 1 })
+        //     + if offset.z < midpoint.z { 0 } else { 2 }
+        //     + if offset.y < midpoint.y { 0 } else { 4 }
+        Self::ALL[(offset.x >= midpoint.x) as usize
+            + (offset.z >= midpoint.z) as usize * 2
+            + (offset.y >= midpoint.y) as usize * 4]
+    }
+
+    /// The octant of a cube of the given size containing the voxel at the given offset from the min position of the cube;
+    /// same as `from_offset`, but exact for every size: converting the offset to floats rounds it above 2^24,
+    /// placing voxels next to the midpoint of large cubes in the wrong half
+    /// * `offset` - From range 0..size in each dimensions
+    /// * `size` - Size of the cube to check for the octant
+    pub fn from_voxel_offset(offset: &V3c<u32>, size: u32) -> Octant {
+        debug_assert!(
+            offset.x < size && offset.y < size && offset.z < size,
+            "Offset {offset:?} is outside of the region of size {size}"
+        );
+        // The offset is in the upper half if `2 * offset >= size`, i.e. `size - 1 - 2 * offset` is negative:
+        // the sign bit of the difference is the bit of the octant along the axis, without any branches
+        let upper_half = |offset: u32| ((size as i64 - 1 - 2 * offset as i64) >> 63) as usize & 1;
+        Self::ALL[upper_half(offset.x) + upper_half(offset.z) * 2 + upper_half(offset.y) * 4]
+    }
+
+    /// The position of the octant inside the cube in units of half its size, 0 or 1 along each axis
+    pub fn offset(self) -> V3c<u32> {
+        let index = self.index();
+        V3c::new(index & 1, (index >> 2) & 1, (index >> 1) & 1)
+    }
+
+    /// The octant on the other side of the cube along the given axis
+    pub fn mirror(self, axis: Axis) -> Octant {
+        self.mirror_by_mask(match axis {
+            Axis::X => 1,
+            Axis::Z => 2,
+            Axis::Y => 4,
+        })
+    }
+
+    /// The octant mirrored along every axis set in the given mask, in the layout of the octant indices,
+    /// e.g. by the sign bits of a ray direction ( see `Ray::direction_sign_mask` ).
+    /// Mirroring the octants by the sign bits makes a ray only ever step into octants with greater index,
+    /// so the order is always front-to-back.
+    pub(crate) fn mirror_by_mask(self, mask: usize) -> Octant {
+        Self::ALL[self as usize ^ mask]
+    }
 }
 
-#[allow(dead_code)] // Could be useful either for debugging or new implementations
+/// The mirrored octant a ray steps into after leaving the given mirrored octant through the exit plane
+/// on the given axis ( x: 0, y: 1, z: 2 ). 8 means the ray leaves the parent node.
 #[cfg(feature = "raytracing")]
-/// calculates the distance between the line, and the plane both described by a ray
-/// plane: normal, and a point on plane, line: origin and direction
-/// return the distance from the line origin to the direction of it, if they have an intersection
-pub fn plane_line_intersection(
-    plane_point: &V3c<f32>,
-    plane_normal: &V3c<f32>,
-    line_origin: &V3c<f32>,
-    line_direction: &V3c<f32>,
-) -> Option<f32> {
-    let origins_diff = *plane_point - *line_origin;
-    let plane_line_dot_to_plane = origins_diff.dot(plane_normal);
-    let directions_dot = line_direction.dot(plane_normal);
-    if 0. == directions_dot {
-        // line and plane is paralell
-        if 0. == origins_diff.dot(plane_normal) {
-            // The distance is zero because the origin is already on the plane
-            return Some(0.);
-        }
-        return None;
-    }
-    Some(plane_line_dot_to_plane / directions_dot)
-}
+pub(crate) const RAY_NEXT_MIRRORED_OCTANT: [[u8; 3]; 8] = [
+    [1, 4, 2],
+    [8, 5, 3],
+    [3, 6, 8],
+    [8, 7, 8],
+    [5, 8, 6],
+    [8, 8, 7],
+    [7, 8, 8],
+    [8, 8, 8],
+];
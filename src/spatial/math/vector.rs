@@ -158,6 +158,14 @@ where
 }
 impl<T> Eq for V3c<T> where T: Default + Add<Output = T> + Mul<Output = T> + Copy + PartialEq {}
 
+impl<T: std::hash::Hash> std::hash::Hash for V3c<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.x.hash(state);
+        self.y.hash(state);
+        self.z.hash(state);
+    }
+}
+
 impl From<V3c<usize>> for V3c<f32> {
     fn from(vec: V3c<usize>) -> V3c<f32> {
         {
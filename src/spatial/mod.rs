@@ -1,22 +1,38 @@
 pub mod math;
+pub mod primitives;
 pub mod raytracing;
 pub mod tests;
 
-use crate::spatial::math::{offset_region, vector::V3c};
+use crate::spatial::math::{vector::V3c, Octant};
 
+#[cfg(feature = "raytracing")]
 pub(crate) const FLOAT_ERROR_TOLERANCE: f32 = 0.00001;
 
+/// An axis aligned cube on the grid of the octree, covering the half-open range
+/// `[min_position, min_position + size)` on each axis: positions on its upper boundary
+/// belong to the neighbouring cube, so every position is inside exactly one node of a level
 #[derive(Default, Clone, Copy, Debug)]
 #[cfg_attr(
     feature = "serialization",
     derive(serde::Serialize, serde::Deserialize)
 )]
-pub(crate) struct Cube {
-    pub(crate) min_position: V3c<u32>,
-    pub(crate) size: u32,
+pub struct Cube {
+    pub min_position: V3c<u32>,
+    pub size: u32,
+}
+
+/// An axis aligned bounding box, not restricted to the grid of the octree
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: V3c<f32>,
+    pub max: V3c<f32>,
 }
 
 impl Cube {
+    pub fn new(min_position: V3c<u32>, size: u32) -> Self {
+        Self { min_position, size }
+    }
+
     pub(crate) fn root_bounds(size: u32) -> Self {
         Self {
             min_position: V3c::unit(0),
@@ -24,23 +40,204 @@ impl Cube {
         }
     }
 
+    /// The level of cubes with the given size, counted from the smallest, sized 1, the size doubling on each level.
+    /// Sizes between two levels are rounded down, so for nodes of the same tree, sized `DIM * 2^x`,
+    /// the difference of their levels is the number of levels between them
+    pub fn level_of(size: u32) -> u32 {
+        debug_assert!(0 < size);
+        size.ilog2()
+    }
+
     /// Creates a bounding box within an area described by the min_position and size, for the given octant
-    pub(crate) fn child_bounds_for(&self, octant: u32) -> Cube {
+    pub(crate) fn child_bounds_for(&self, octant: Octant) -> Cube {
         let child_size = self.size / 2;
         Cube {
-            min_position: (self.min_position + (offset_region(octant) * child_size)),
+            min_position: (self.min_position + (octant.offset() * child_size)),
             size: child_size,
         }
     }
 
-    /// True if the given point is inside the cube, with coordinates in inclusive, exclusive range
-    /// Edges included
-    pub(crate) fn contains_point(&self, point: &V3c<f32>) -> bool {
-        (point.x >= self.min_position.x as f32 - FLOAT_ERROR_TOLERANCE)
-            && (point.x < (self.min_position.x + self.size) as f32 + FLOAT_ERROR_TOLERANCE)
-            && (point.y >= self.min_position.y as f32 - FLOAT_ERROR_TOLERANCE)
-            && (point.y < (self.min_position.y + self.size) as f32 + FLOAT_ERROR_TOLERANCE)
-            && (point.z >= self.min_position.z as f32 - FLOAT_ERROR_TOLERANCE)
-            && (point.z < (self.min_position.z + self.size) as f32 + FLOAT_ERROR_TOLERANCE)
+    /// The corner of the cube opposite to its min position, outside of the cube
+    pub fn max_position(&self) -> V3c<u32> {
+        self.min_position + V3c::unit(self.size)
+    }
+
+    /// True if the given position is inside the cube
+    pub fn contains(&self, position: &V3c<u32>) -> bool {
+        let max_position = self.max_position();
+        position.x >= self.min_position.x
+            && position.y >= self.min_position.y
+            && position.z >= self.min_position.z
+            && position.x < max_position.x
+            && position.y < max_position.y
+            && position.z < max_position.z
+    }
+
+    /// True if the given cube is completely inside this one
+    pub fn contains_cube(&self, other: &Cube) -> bool {
+        let max_position = self.max_position();
+        let other_max_position = other.max_position();
+        other.min_position.x >= self.min_position.x
+            && other.min_position.y >= self.min_position.y
+            && other.min_position.z >= self.min_position.z
+            && other_max_position.x <= max_position.x
+            && other_max_position.y <= max_position.y
+            && other_max_position.z <= max_position.z
+    }
+
+    /// True if the two cubes share a volume; cubes only touching each other do not intersect
+    pub fn intersects(&self, other: &Cube) -> bool {
+        let max_position = self.max_position();
+        let other_max_position = other.max_position();
+        self.min_position.x < other_max_position.x
+            && self.min_position.y < other_max_position.y
+            && self.min_position.z < other_max_position.z
+            && other.min_position.x < max_position.x
+            && other.min_position.y < max_position.y
+            && other.min_position.z < max_position.z
+    }
+
+    /// The cube grown by the given margin in every direction; the min position can not go below zero
+    pub fn expanded(&self, margin: u32) -> Cube {
+        let min_position = V3c::new(
+            self.min_position.x.saturating_sub(margin),
+            self.min_position.y.saturating_sub(margin),
+            self.min_position.z.saturating_sub(margin),
+        );
+        let max_position = self.max_position() + V3c::unit(margin);
+        Cube {
+            min_position,
+            size: (max_position.x - min_position.x)
+                .max(max_position.y - min_position.y)
+                .max(max_position.z - min_position.z),
+        }
+    }
+
+    /// The smallest cube containing both cubes
+    pub fn union(&self, other: &Cube) -> Cube {
+        let max_position = self.max_position();
+        let other_max_position = other.max_position();
+        let min_position = V3c::new(
+            self.min_position.x.min(other.min_position.x),
+            self.min_position.y.min(other.min_position.y),
+            self.min_position.z.min(other.min_position.z),
+        );
+        Cube {
+            min_position,
+            size: (max_position.x.max(other_max_position.x) - min_position.x)
+                .max(max_position.y.max(other_max_position.y) - min_position.y)
+                .max(max_position.z.max(other_max_position.z) - min_position.z),
+        }
+    }
+
+    pub fn surface_area(&self) -> u64 {
+        6 * self.size as u64 * self.size as u64
+    }
+
+    /// The corners of the cube, indexed by octant
+    pub fn corners(&self) -> [V3c<u32>; 8] {
+        Octant::ALL.map(|octant| self.min_position + octant.offset() * self.size)
+    }
+}
+
+impl Aabb {
+    pub fn new(min: V3c<f32>, max: V3c<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn size(&self) -> V3c<f32> {
+        self.max - self.min
+    }
+
+    /// True if the given point is inside the box, including its boundaries
+    pub fn contains(&self, point: &V3c<f32>) -> bool {
+        point.x >= self.min.x
+            && point.y >= self.min.y
+            && point.z >= self.min.z
+            && point.x <= self.max.x
+            && point.y <= self.max.y
+            && point.z <= self.max.z
+    }
+
+    /// True if the given box is completely inside this one
+    pub fn contains_aabb(&self, other: &Aabb) -> bool {
+        self.contains(&other.min) && self.contains(&other.max)
+    }
+
+    /// True if the two boxes share a volume; boxes only touching each other do not intersect
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && self.min.y < other.max.y
+            && self.min.z < other.max.z
+            && other.min.x < self.max.x
+            && other.min.y < self.max.y
+            && other.min.z < self.max.z
+    }
+
+    /// The box grown by the given margin in every direction
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        Aabb {
+            min: self.min - V3c::unit(margin),
+            max: self.max + V3c::unit(margin),
+        }
+    }
+
+    /// The smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: V3c::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: V3c::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2. * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// The corners of the box, indexed by octant
+    pub fn corners(&self) -> [V3c<f32>; 8] {
+        let size = self.size();
+        Octant::ALL.map(|octant| self.min + V3c::<f32>::from(octant.offset()) * size)
+    }
+}
+
+impl From<Cube> for Aabb {
+    fn from(cube: Cube) -> Aabb {
+        Aabb {
+            min: cube.min_position.into(),
+            max: cube.max_position().into(),
+        }
+    }
+}
+
+impl From<Aabb> for Cube {
+    /// The smallest cube on the integer grid containing the given box;
+    /// parts of the box below zero are left out
+    fn from(aabb: Aabb) -> Cube {
+        let min_position = V3c::new(
+            aabb.min.x.max(0.).floor() as u32,
+            aabb.min.y.max(0.).floor() as u32,
+            aabb.min.z.max(0.).floor() as u32,
+        );
+        let max_position = V3c::new(
+            aabb.max.x.max(0.).ceil() as u32,
+            aabb.max.y.max(0.).ceil() as u32,
+            aabb.max.z.max(0.).ceil() as u32,
+        );
+        Cube {
+            min_position,
+            size: (max_position.x.saturating_sub(min_position.x))
+                .max(max_position.y.saturating_sub(min_position.y))
+                .max(max_position.z.saturating_sub(min_position.z)),
+        }
     }
 }
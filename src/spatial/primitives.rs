@@ -0,0 +1,364 @@
+use crate::spatial::{math::vector::V3c, Cube};
+
+#[cfg(feature = "raytracing")]
+use crate::spatial::raytracing::Ray;
+
+/// An infinite plane described by a point on it and its normal
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub point: V3c<f32>,
+    /// The direction the plane is facing, expected to be normalized
+    pub normal: V3c<f32>,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: V3c<f32>,
+    pub radius: f32,
+}
+
+/// A cylinder with hemispheres on both ends: every point closer to the segment between `start` and `end`,
+/// than the radius of the capsule
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    pub start: V3c<f32>,
+    pub end: V3c<f32>,
+    pub radius: f32,
+}
+
+/// The horizontal directions a voxel shape can face
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facing {
+    #[default]
+    PositiveX,
+    NegativeX,
+    PositiveZ,
+    NegativeZ,
+}
+
+/// The axes of the space
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    #[default]
+    X,
+    Y,
+    Z,
+}
+
+/// The shape of the solid part of a voxel inside its cell, see `VoxelData::shape`
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoxelShape {
+    /// The whole cell is solid
+    #[default]
+    Cube,
+    /// A wedge rising from the bottom of the cell to its top in the given direction
+    Slope(Facing),
+    /// The bottom half of the cell, with a step on top of it on the side of the given direction
+    Stairs(Facing),
+}
+
+/// The point inside the given cube closest to the given point
+fn closest_point_in(cube: &Cube, point: &V3c<f32>) -> V3c<f32> {
+    let min_position = V3c::<f32>::from(cube.min_position);
+    let max_position = V3c::<f32>::from(cube.max_position());
+    V3c::new(
+        point.x.clamp(min_position.x, max_position.x),
+        point.y.clamp(min_position.y, max_position.y),
+        point.z.clamp(min_position.z, max_position.z),
+    )
+}
+
+impl Plane {
+    pub fn new(point: V3c<f32>, normal: V3c<f32>) -> Self {
+        Self { point, normal }
+    }
+
+    /// The distance of the given point from the plane, negative behind the plane
+    pub fn signed_distance(&self, point: &V3c<f32>) -> f32 {
+        (*point - self.point).dot(&self.normal)
+    }
+
+    /// calculates the distance between the line and the plane
+    /// line: origin and direction
+    /// return the distance from the line origin to the direction of it, if they have an intersection
+    pub fn intersect_line(&self, line_origin: &V3c<f32>, line_direction: &V3c<f32>) -> Option<f32> {
+        let origins_diff = self.point - *line_origin;
+        let plane_line_dot_to_plane = origins_diff.dot(&self.normal);
+        let directions_dot = line_direction.dot(&self.normal);
+        if 0. == directions_dot {
+            // line and plane is paralell
+            if 0. == plane_line_dot_to_plane {
+                // The distance is zero because the origin is already on the plane
+                return Some(0.);
+            }
+            return None;
+        }
+        Some(plane_line_dot_to_plane / directions_dot)
+    }
+
+    /// The distance along the given ray where it hits the plane, should it hit the plane in front of its origin
+    #[cfg(feature = "raytracing")]
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        self.intersect_line(&ray.origin, &ray.direction)
+            .filter(|distance| *distance >= 0.)
+    }
+
+    /// True if the plane goes through the given cube
+    pub fn intersects_cube(&self, cube: &Cube) -> bool {
+        let mut in_front = false;
+        let mut behind = false;
+        for corner in cube.corners() {
+            let distance = self.signed_distance(&corner.into());
+            in_front |= distance >= 0.;
+            behind |= distance <= 0.;
+        }
+        in_front && behind
+    }
+}
+
+impl Sphere {
+    pub fn new(center: V3c<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains(&self, point: &V3c<f32>) -> bool {
+        (*point - self.center).length() <= self.radius
+    }
+
+    /// True if the sphere and the given cube share a volume
+    pub fn intersects_cube(&self, cube: &Cube) -> bool {
+        self.contains(&closest_point_in(cube, &self.center))
+    }
+
+    /// The distance along the given ray where it enters the sphere, 0 if the ray starts inside it
+    #[cfg(feature = "raytracing")]
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let to_origin = ray.origin - self.center;
+        let b = to_origin.dot(&ray.direction);
+        let c = to_origin.dot(&to_origin) - self.radius * self.radius;
+        if c <= 0. {
+            return Some(0.);
+        }
+        let discriminant = b * b - c;
+        if discriminant < 0. || b > 0. {
+            // The ray misses the sphere, or it is pointing away from it
+            return None;
+        }
+        Some(-b - discriminant.sqrt())
+    }
+}
+
+impl Capsule {
+    pub fn new(start: V3c<f32>, end: V3c<f32>, radius: f32) -> Self {
+        Self { start, end, radius }
+    }
+
+    /// The point of the segment of the capsule closest to the given point
+    pub fn closest_point_on_segment(&self, point: &V3c<f32>) -> V3c<f32> {
+        let segment = self.end - self.start;
+        let segment_length_squared = segment.dot(&segment);
+        if 0. == segment_length_squared {
+            return self.start;
+        }
+        let t = ((*point - self.start).dot(&segment) / segment_length_squared).clamp(0., 1.);
+        self.start + segment * t
+    }
+
+    pub fn contains(&self, point: &V3c<f32>) -> bool {
+        (*point - self.closest_point_on_segment(point)).length() <= self.radius
+    }
+
+    /// True if the capsule and the given cube share a volume
+    pub fn intersects_cube(&self, cube: &Cube) -> bool {
+        // The distance of the points of the segment from the cube is a convex function,
+        // so its minimum can be found with a ternary search along the segment
+        let distance_at = |t: f32| {
+            let point = self.start + (self.end - self.start) * t;
+            (point - closest_point_in(cube, &point)).length()
+        };
+        let (mut low, mut high) = (0., 1.);
+        for _ in 0..32 {
+            let third = (high - low) / 3.;
+            if distance_at(low + third) <= distance_at(high - third) {
+                high -= third;
+            } else {
+                low += third;
+            }
+        }
+        distance_at((low + high) / 2.) <= self.radius
+    }
+
+    /// The distance along the given ray where it enters the capsule, 0 if the ray starts inside it
+    #[cfg(feature = "raytracing")]
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        if self.contains(&ray.origin) {
+            return Some(0.);
+        }
+
+        // The ray either enters through one of the caps, or the cylinder between them
+        let mut entry = [self.start, self.end]
+            .iter()
+            .filter_map(|center| Sphere::new(*center, self.radius).intersect_ray(ray))
+            .reduce(f32::min);
+        let axis = self.end - self.start;
+        let to_origin = ray.origin - self.start;
+        let axis_length_squared = axis.dot(&axis);
+        let axis_dot_direction = axis.dot(&ray.direction);
+        let axis_dot_origin = axis.dot(&to_origin);
+        let a = axis_length_squared - axis_dot_direction * axis_dot_direction;
+        let b = axis_length_squared * ray.direction.dot(&to_origin)
+            - axis_dot_origin * axis_dot_direction;
+        let c = axis_length_squared * to_origin.dot(&to_origin)
+            - axis_dot_origin * axis_dot_origin
+            - self.radius * self.radius * axis_length_squared;
+        let discriminant = b * b - a * c;
+        if 0. < a && 0. <= discriminant {
+            let distance = (-b - discriminant.sqrt()) / a;
+            let along_axis = axis_dot_origin + distance * axis_dot_direction;
+            if 0. <= distance && 0. < along_axis && along_axis < axis_length_squared {
+                entry = Some(entry.map_or(distance, |d| d.min(distance)));
+            }
+        }
+        entry
+    }
+}
+
+impl Axis {
+    /// The component of the given vector along the axis
+    pub(crate) fn component_of<T: Copy>(&self, vector: &V3c<T>) -> T {
+        match self {
+            Axis::X => vector.x,
+            Axis::Y => vector.y,
+            Axis::Z => vector.z,
+        }
+    }
+
+    /// The components of the given vector along the other two axes, in x, y, z order
+    pub(crate) fn plane_of<T: Copy>(&self, vector: &V3c<T>) -> (T, T) {
+        match self {
+            Axis::X => (vector.y, vector.z),
+            Axis::Y => (vector.x, vector.z),
+            Axis::Z => (vector.x, vector.y),
+        }
+    }
+
+    /// The vector with the given component along the axis, and the given components
+    /// along the other two axes in x, y, z order
+    pub(crate) fn vector_with<T: Copy>(&self, component: T, plane: (T, T)) -> V3c<T> {
+        match self {
+            Axis::X => V3c::new(component, plane.0, plane.1),
+            Axis::Y => V3c::new(plane.0, component, plane.1),
+            Axis::Z => V3c::new(plane.0, plane.1, component),
+        }
+    }
+}
+
+#[cfg(feature = "raytracing")]
+impl Facing {
+    /// The horizontal unit vector pointing in the direction
+    fn direction(&self) -> V3c<f32> {
+        match self {
+            Facing::PositiveX => V3c::new(1., 0., 0.),
+            Facing::NegativeX => V3c::new(-1., 0., 0.),
+            Facing::PositiveZ => V3c::new(0., 0., 1.),
+            Facing::NegativeZ => V3c::new(0., 0., -1.),
+        }
+    }
+
+    /// The distance of the side of the unit cell opposite to the direction, along the direction
+    fn start(&self) -> f32 {
+        match self {
+            Facing::PositiveX | Facing::PositiveZ => 0.,
+            Facing::NegativeX | Facing::NegativeZ => -1.,
+        }
+    }
+}
+
+/// A convex volume as the intersection of half-spaces, each given as `normal . point <= offset`
+#[cfg(feature = "raytracing")]
+type HalfSpaces = Vec<(V3c<f32>, f32)>;
+
+/// The half-spaces of the box between the given corners
+#[cfg(feature = "raytracing")]
+fn box_half_spaces(min: V3c<f32>, max: V3c<f32>) -> HalfSpaces {
+    vec![
+        (V3c::new(-1., 0., 0.), -min.x),
+        (V3c::new(1., 0., 0.), max.x),
+        (V3c::new(0., -1., 0.), -min.y),
+        (V3c::new(0., 1., 0.), max.y),
+        (V3c::new(0., 0., -1.), -min.z),
+        (V3c::new(0., 0., 1.), max.z),
+    ]
+}
+
+/// The distance along the given line where it enters the convex volume, with the normal of the
+/// surface it enters through; 0 if the origin is inside the volume
+#[cfg(feature = "raytracing")]
+fn intersect_half_spaces(
+    origin: &V3c<f32>,
+    direction: &V3c<f32>,
+    half_spaces: &HalfSpaces,
+) -> Option<(f32, V3c<f32>)> {
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = V3c::unit(0.);
+    for (plane_normal, offset) in half_spaces {
+        let approach = plane_normal.dot(direction);
+        let distance = offset - plane_normal.dot(origin);
+        if 0. == approach {
+            // Parallel to the plane: either always inside the half-space, or never
+            if distance < 0. {
+                return None;
+            }
+            continue;
+        }
+        let t = distance / approach;
+        if approach < 0. {
+            if t > enter {
+                enter = t;
+                normal = *plane_normal;
+            }
+        } else {
+            exit = exit.min(t);
+        }
+    }
+    if enter > exit || exit < 0. {
+        return None;
+    }
+    Some((enter.max(0.), normal.normalized()))
+}
+
+#[cfg(feature = "raytracing")]
+impl VoxelShape {
+    /// The convex parts of the shape inside the unit cell
+    fn convex_parts(&self) -> Vec<HalfSpaces> {
+        let unit_cell = box_half_spaces(V3c::unit(0.), V3c::unit(1.));
+        match self {
+            VoxelShape::Cube => vec![unit_cell],
+            VoxelShape::Slope(facing) => {
+                // Below the plane rising along the facing direction: y <= direction . p - start
+                let mut wedge = unit_cell;
+                wedge.push((V3c::new(0., 1., 0.) - facing.direction(), -facing.start()));
+                vec![wedge]
+            }
+            VoxelShape::Stairs(facing) => {
+                let mut step = box_half_spaces(V3c::new(0., 0.5, 0.), V3c::unit(1.));
+                step.push((facing.direction() * -1., -0.5 - facing.start()));
+                vec![box_half_spaces(V3c::unit(0.), V3c::new(1., 0.5, 1.)), step]
+            }
+        }
+    }
+
+    /// The distance along the given ray where it hits the shape placed inside the given cell,
+    /// with the normal of the surface it hits; 0 if the ray starts inside the shape
+    pub fn intersect_ray(&self, ray: &Ray, cell: &Cube) -> Option<(f32, V3c<f32>)> {
+        // In the coordinates of the unit cell the distances along the ray are unchanged
+        let scale = cell.size as f32;
+        let origin = (ray.origin - V3c::<f32>::from(cell.min_position)) / scale;
+        let direction = ray.direction / scale;
+        self.convex_parts()
+            .iter()
+            .filter_map(|part| intersect_half_spaces(&origin, &direction, part))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+}
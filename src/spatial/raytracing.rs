@@ -1,5 +1,7 @@
+use crate::spatial::math::vector::V3c;
+
 #[cfg(feature = "raytracing")]
-use crate::spatial::{math::vector::V3c, Cube, FLOAT_ERROR_TOLERANCE};
+use crate::spatial::{Aabb, Cube};
 
 #[cfg(feature = "raytracing")]
 #[derive(Debug)]
@@ -17,70 +19,161 @@ impl Ray {
     pub fn point_at(&self, d: f32) -> V3c<f32> {
         self.origin + self.direction * d
     }
+
+    /// The octant bits of the components where the direction of the ray is negative,
+    /// in the same layout as the octant indices: x: 1, z: 2, y: 4, see `Octant::mirror_by_mask`
+    pub(crate) fn direction_sign_mask(&self) -> usize {
+        (self.direction.x < 0.) as usize
+            + (self.direction.z < 0.) as usize * 2
+            + (self.direction.y < 0.) as usize * 4
+    }
 }
 
+/// The direction of a ray reflected by a surface with the given normal, e.g. for mirror like voxels.
+/// The normal is expected to be of unit length; the side it is facing doesn't matter
 #[cfg(feature = "raytracing")]
-#[derive(Debug, Copy, Clone, Default)]
-pub struct CubeRayIntersection {
-    pub(crate) impact_distance: Option<f32>,
-    pub(crate) exit_distance: f32,
-    pub(crate) impact_normal: V3c<f32>,
+pub fn reflect(direction: V3c<f32>, normal: V3c<f32>) -> V3c<f32> {
+    direction - normal * (2. * direction.dot(&normal))
 }
 
+/// The direction of a ray refracted by a surface with the given normal, based on Snell's law.
+/// * `ior` - the index of refraction of the material the normal points out of, relative to the one it points into
+///   e.g. 1.33 for water surrounded by air, with the normal provided by `Octree::get_by_ray`
+///
+/// The ray enters the material should it point against the normal, and leaves it otherwise.
+/// returns None on total internal reflection, in which case the ray is reflected instead, see `reflect`
 #[cfg(feature = "raytracing")]
-impl Cube {
-    /// Tells the intersection with the cube of the given ray.
-    /// returns the distance from the origin to the direction of the ray until the hit point and the normal of the hit
-    /// https://gamedev.stackexchange.com/questions/18436/most-efficient-aabb-vs-ray-collision-algorithms
-    #[cfg(feature = "raytracing")]
-    pub fn intersect_ray(&self, ray: &Ray) -> Option<CubeRayIntersection> {
-        debug_assert!(ray.is_valid());
+pub fn refract(direction: V3c<f32>, normal: V3c<f32>, ior: f32) -> Option<V3c<f32>> {
+    let cos_incident = direction.dot(&normal);
+    let (normal, cos_incident, ratio) = if 0. < cos_incident {
+        (normal * -1., -cos_incident, ior)
+    } else {
+        (normal, cos_incident, 1. / ior)
+    };
+    let cos_transmitted_squared = 1. - ratio * ratio * (1. - cos_incident * cos_incident);
+    if cos_transmitted_squared < 0. {
+        return None;
+    }
+    Some(
+        (direction * ratio - normal * (ratio * cos_incident + cos_transmitted_squared.sqrt()))
+            .normalized(),
+    )
+}
+
+/// The faces of an axis aligned box
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum BoxFace {
+    #[default]
+    NegativeX,
+    PositiveX,
+    NegativeY,
+    PositiveY,
+    NegativeZ,
+    PositiveZ,
+}
+
+impl BoxFace {
+    /// Every face of a box
+    pub const ALL: [BoxFace; 6] = [
+        BoxFace::NegativeX,
+        BoxFace::PositiveX,
+        BoxFace::NegativeY,
+        BoxFace::PositiveY,
+        BoxFace::NegativeZ,
+        BoxFace::PositiveZ,
+    ];
 
-        let max_position = V3c::<f32>::from(self.min_position) + V3c::unit(self.size as f32);
-        let t1 = (self.min_position.x as f32 - ray.origin.x) / ray.direction.x;
-        let t2 = (max_position.x - ray.origin.x) / ray.direction.x;
-        let t3 = (self.min_position.y as f32 - ray.origin.y) / ray.direction.y;
-        let t4 = (max_position.y - ray.origin.y) / ray.direction.y;
-        let t5 = (self.min_position.z as f32 - ray.origin.z) / ray.direction.z;
-        let t6 = (max_position.z - ray.origin.z) / ray.direction.z;
-
-        let tmin = t1.min(t2).max(t3.min(t4)).max(t5.min(t6));
-        let tmax = t1.max(t2).min(t3.max(t4)).min(t5.max(t6));
-
-        if tmax < 0. || tmin > tmax {
-            // ray is intersecting the cube, but it is behind it
-            // OR ray doesn't intersect cube
-            return None;
+    /// The face on the given axis (x: 0, y: 1, z: 2) a ray with the given direction component enters through
+    #[cfg(feature = "raytracing")]
+    fn entered_on_axis(axis: usize, direction: f32) -> Self {
+        match (axis, 0. <= direction) {
+            (0, true) => BoxFace::NegativeX,
+            (0, false) => BoxFace::PositiveX,
+            (1, true) => BoxFace::NegativeY,
+            (1, false) => BoxFace::PositiveY,
+            (_, true) => BoxFace::NegativeZ,
+            (_, false) => BoxFace::PositiveZ,
         }
+    }
 
-        let p = ray.point_at(tmin);
-        let mut impact_normal = V3c::unit(0.);
-        if (p.x - self.min_position.x as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.x = -1.;
-        } else if (p.x - (self.min_position.x + self.size) as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.x = 1.;
-        } else if (p.y - self.min_position.y as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.y = -1.;
-        } else if (p.y - (self.min_position.y + self.size) as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.y = 1.;
-        } else if (p.z - self.min_position.z as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.z = -1.;
-        } else if (p.z - (self.min_position.z + self.size) as f32).abs() < FLOAT_ERROR_TOLERANCE {
-            impact_normal.z = 1.;
+    /// The outward facing normal of the face
+    pub fn normal(&self) -> V3c<f32> {
+        match self {
+            BoxFace::NegativeX => V3c::new(-1., 0., 0.),
+            BoxFace::PositiveX => V3c::new(1., 0., 0.),
+            BoxFace::NegativeY => V3c::new(0., -1., 0.),
+            BoxFace::PositiveY => V3c::new(0., 1., 0.),
+            BoxFace::NegativeZ => V3c::new(0., 0., -1.),
+            BoxFace::PositiveZ => V3c::new(0., 0., 1.),
         }
+    }
+}
+
+#[cfg(feature = "raytracing")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CubeRayIntersection {
+    /// The distance along the ray where it enters the box, None if the origin of the ray is inside it
+    pub impact_distance: Option<f32>,
+    /// The distance along the ray where it leaves the box
+    pub exit_distance: f32,
+    pub impact_normal: V3c<f32>,
+    /// The face the ray enters the box through; it is behind the origin should the ray start inside the box
+    pub entry_face: BoxFace,
+}
 
-        if tmin < 0.0 {
-            return Some(CubeRayIntersection {
-                impact_distance: None,
-                exit_distance: tmax,
-                impact_normal,
-            });
+/// Tells the intersection of the given ray with the given axis aligned box, using the slab method.
+/// Rays parallel to any of the axes are supported: they hit the box only if their origin is
+/// between the planes of the box on that axis. At edges and corners the entry face is chosen in x, y, z order.
+/// returns the distance from the origin to the direction of the ray until the hit point and the normal of the hit
+#[cfg(feature = "raytracing")]
+pub fn intersect_aabb(aabb: &Aabb, ray: &Ray) -> Option<CubeRayIntersection> {
+    // The distances along the ray where it enters and leaves the slab of an axis,
+    // infinite in both directions should the ray be parallel to the slab but inside of it
+    let slab = |origin: f32, direction: f32, min: f32, max: f32| {
+        if 0. == direction {
+            if origin < min || origin > max {
+                None
+            } else {
+                Some((f32::NEG_INFINITY, f32::INFINITY))
+            }
+        } else {
+            let near = (min - origin) / direction;
+            let far = (max - origin) / direction;
+            Some((near.min(far), near.max(far)))
         }
+    };
+    let x = slab(ray.origin.x, ray.direction.x, aabb.min.x, aabb.max.x)?;
+    let y = slab(ray.origin.y, ray.direction.y, aabb.min.y, aabb.max.y)?;
+    let z = slab(ray.origin.z, ray.direction.z, aabb.min.z, aabb.max.z)?;
+    let entry_distance = x.0.max(y.0).max(z.0);
+    let exit_distance = x.1.min(y.1).min(z.1);
 
-        Some(CubeRayIntersection {
-            impact_distance: Some(tmin),
-            exit_distance: tmax,
-            impact_normal,
-        })
+    if exit_distance < 0. || entry_distance > exit_distance {
+        // ray is intersecting the box, but it is behind it
+        // OR ray doesn't intersect box
+        return None;
+    }
+
+    let entry_face = if entry_distance == x.0 {
+        BoxFace::entered_on_axis(0, ray.direction.x)
+    } else if entry_distance == y.0 {
+        BoxFace::entered_on_axis(1, ray.direction.y)
+    } else {
+        BoxFace::entered_on_axis(2, ray.direction.z)
+    };
+    Some(CubeRayIntersection {
+        impact_distance: (0. <= entry_distance).then_some(entry_distance),
+        exit_distance,
+        impact_normal: entry_face.normal(),
+        entry_face,
+    })
+}
+
+#[cfg(feature = "raytracing")]
+impl Cube {
+    /// Tells the intersection with the cube of the given ray, see `intersect_aabb`
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<CubeRayIntersection> {
+        debug_assert!(ray.is_valid());
+        intersect_aabb(&Aabb::from(*self), ray)
     }
 }
@@ -14,35 +14,308 @@ mod vector_tests {
     }
 }
 
+#[cfg(test)]
+mod cube_tests {
+
+    use crate::spatial::{math::Octant, Aabb, Cube, V3c};
+
+    #[test]
+    fn test_cube_intersection_and_containment() {
+        let cube = Cube::new(V3c::new(2, 2, 2), 4);
+        assert!(cube.intersects(&Cube::new(V3c::new(5, 5, 5), 2)));
+        assert!(!cube.intersects(&Cube::new(V3c::new(6, 2, 2), 2))); // only touching
+        assert!(cube.contains_cube(&Cube::new(V3c::new(4, 4, 4), 2)));
+        assert!(!cube.contains_cube(&Cube::new(V3c::new(5, 5, 5), 2)));
+        assert!(cube.contains(&V3c::new(5, 5, 5)));
+        assert!(!cube.contains(&V3c::new(6, 5, 5)));
+    }
+
+    #[test]
+    fn test_cube_level_of() {
+        assert_eq!(Cube::level_of(1), 0);
+        assert_eq!(Cube::level_of(2), 1);
+        assert_eq!(Cube::level_of(64), 6);
+        // Sizes between levels are rounded down, keeping the distance between node sizes of the same tree
+        assert_eq!(Cube::level_of(3), 1);
+        assert_eq!(Cube::level_of(48), 5);
+        assert_eq!(Cube::level_of(48) - Cube::level_of(3), 4);
+    }
+
+    #[test]
+    fn test_cube_children_partition_bounds() {
+        // Every position of the cube is inside exactly one of its children, none on the upper boundary
+        let cube = Cube::new(V3c::new(4, 0, 8), 4);
+        for x in 3..9 {
+            for y in 0..5 {
+                for z in 8..13 {
+                    let position = V3c::new(x, y, z);
+                    let containing_children = Octant::iter()
+                        .filter(|octant| cube.child_bounds_for(*octant).contains(&position))
+                        .count();
+                    assert!(containing_children == cube.contains(&position) as usize);
+                }
+            }
+        }
+        assert!(!cube.contains(&cube.max_position()));
+        assert!(cube.contains(&(cube.max_position() - V3c::unit(1))));
+    }
+
+    #[test]
+    fn test_cube_expanded_and_union() {
+        let cube = Cube::new(V3c::new(1, 4, 4), 2);
+        let expanded = cube.expanded(2);
+        assert!(expanded.min_position == V3c::new(0, 2, 2));
+        assert!(expanded.size == 6);
+
+        let union = cube.union(&Cube::new(V3c::new(4, 4, 5), 1));
+        assert!(union.min_position == V3c::new(1, 4, 4));
+        assert!(union.size == 4);
+        assert!(cube.surface_area() == 24);
+        assert!(cube.corners()[7] == V3c::new(3, 6, 6));
+    }
+
+    #[test]
+    fn test_aabb_conversions() {
+        let cube = Cube::new(V3c::new(1, 2, 3), 2);
+        let aabb = Aabb::from(cube);
+        assert!(aabb == Aabb::new(V3c::new(1., 2., 3.), V3c::new(3., 4., 5.)));
+        assert!(aabb.surface_area() == 24.);
+
+        let enclosing = Cube::from(Aabb::new(V3c::new(1.5, 2., 3.), V3c::new(2.5, 4.5, 4.)));
+        assert!(enclosing.min_position == V3c::new(1, 2, 3));
+        assert!(enclosing.size == 3);
+        assert!(Aabb::from(enclosing).contains_aabb(&aabb.expanded(-0.5)));
+        assert!(aabb.intersects(&Aabb::new(V3c::unit(2.5), V3c::unit(10.))));
+    }
+}
+
+#[cfg(test)]
+mod primitives_tests {
+
+    use crate::spatial::{
+        primitives::{Capsule, Plane, Sphere},
+        Cube, V3c,
+    };
+
+    #[test]
+    fn test_primitives_intersect_cube() {
+        let cube = Cube::new(V3c::new(2, 2, 2), 2);
+        assert!(Plane::new(V3c::unit(3.), V3c::new(0., 1., 0.)).intersects_cube(&cube));
+        assert!(!Plane::new(V3c::unit(5.), V3c::new(0., 1., 0.)).intersects_cube(&cube));
+
+        assert!(Sphere::new(V3c::new(5., 3., 3.), 1.).intersects_cube(&cube));
+        assert!(!Sphere::new(V3c::new(5., 5., 5.), 1.).intersects_cube(&cube));
+
+        // The segment passes by the corner of the cube at a distance of sqrt(2)
+        let capsule = Capsule::new(V3c::new(0., 0., 6.), V3c::new(6., 6., 6.), 2.);
+        assert!(capsule.intersects_cube(&cube));
+        assert!(!Capsule {
+            radius: 1.,
+            ..capsule
+        }
+        .intersects_cube(&cube));
+    }
+}
+
+#[cfg(feature = "raytracing")]
+#[cfg(test)]
+mod primitives_raytracing_tests {
+
+    use crate::spatial::{
+        primitives::{Capsule, Facing, Plane, Sphere, VoxelShape},
+        raytracing::Ray,
+        Cube, V3c,
+    };
+
+    #[test]
+    fn test_primitives_intersect_ray() {
+        let ray = Ray {
+            origin: V3c::new(0., 0., 10.),
+            direction: V3c::new(0., 0., -1.),
+        };
+        assert!(Plane::new(V3c::unit(0.), V3c::new(0., 0., 1.)).intersect_ray(&ray) == Some(10.));
+        assert!(Plane::new(V3c::unit(20.), V3c::new(0., 0., 1.))
+            .intersect_ray(&ray)
+            .is_none());
+        assert!(Sphere::new(V3c::unit(0.), 2.).intersect_ray(&ray) == Some(8.));
+        assert!(Sphere::new(V3c::new(3., 0., 0.), 2.)
+            .intersect_ray(&ray)
+            .is_none());
+        assert!(Sphere::new(V3c::new(0., 0., 10.), 2.).intersect_ray(&ray) == Some(0.));
+
+        // Hitting the side of the capsule, then one of its caps
+        let capsule = Capsule::new(V3c::new(-5., 0., 0.), V3c::new(5., 0., 0.), 1.);
+        assert!(capsule
+            .intersect_ray(&ray)
+            .is_some_and(|d| (d - 9.).abs() < 0.0001));
+        let ray = Ray {
+            origin: V3c::new(10., 0., 0.),
+            direction: V3c::new(-1., 0., 0.),
+        };
+        assert!(capsule
+            .intersect_ray(&ray)
+            .is_some_and(|d| (d - 4.).abs() < 0.0001));
+    }
+
+    #[test]
+    fn test_voxel_shape_intersect_ray() {
+        let cell = Cube::new(V3c::new(2, 0, 0), 2);
+        // Going down along the middle of the cell
+        let ray = Ray {
+            origin: V3c::new(2.5, 10., 1.),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let (distance, normal) = VoxelShape::Cube.intersect_ray(&ray, &cell).unwrap();
+        assert!((distance - 8.).abs() < 0.0001 && normal == V3c::new(0., 1., 0.));
+
+        // The slope rising towards +x is at height 0.5 a quarter into the cell
+        let (distance, normal) = VoxelShape::Slope(Facing::PositiveX)
+            .intersect_ray(&ray, &cell)
+            .unwrap();
+        assert!((distance - 9.5).abs() < 0.0001);
+        assert!((normal - V3c::new(-1., 1., 0.).normalized()).length() < 0.0001);
+        let (distance, _) = VoxelShape::Slope(Facing::NegativeX)
+            .intersect_ray(&ray, &cell)
+            .unwrap();
+        assert!((distance - 8.5).abs() < 0.0001);
+
+        // The stairs are lower on the side opposite to their facing
+        let (distance, _) = VoxelShape::Stairs(Facing::PositiveX)
+            .intersect_ray(&ray, &cell)
+            .unwrap();
+        assert!((distance - 9.).abs() < 0.0001);
+        let (distance, _) = VoxelShape::Stairs(Facing::NegativeX)
+            .intersect_ray(&ray, &cell)
+            .unwrap();
+        assert!((distance - 8.).abs() < 0.0001);
+
+        // Passing above the slope through the empty corner of the cell
+        let ray = Ray {
+            origin: V3c::new(0., 1.5, 1.),
+            direction: V3c::new(1., 0., 0.),
+        };
+        assert!(VoxelShape::Slope(Facing::NegativeZ)
+            .intersect_ray(&ray, &Cube::new(V3c::new(2, 0, 0), 2))
+            .is_none());
+        assert!(VoxelShape::Slope(Facing::NegativeX)
+            .intersect_ray(&ray, &Cube::new(V3c::new(2, 0, 0), 2))
+            .is_some_and(|(distance, _)| (distance - 2.).abs() < 0.0001));
+        assert!(VoxelShape::Slope(Facing::PositiveX)
+            .intersect_ray(&ray, &Cube::new(V3c::new(2, 0, 0), 2))
+            .is_some_and(|(distance, _)| (distance - 3.5).abs() < 0.0001));
+        let ray = Ray {
+            origin: V3c::new(2.5, 1.5, 0.),
+            direction: V3c::new(0., 0., 1.),
+        };
+        assert!(VoxelShape::Slope(Facing::PositiveX)
+            .intersect_ray(&ray, &Cube::new(V3c::new(2, 0, 0), 2))
+            .is_none());
+    }
+}
+
 #[cfg(test)]
 mod octant_tests {
 
-    use crate::spatial::math::hash_region;
-    use crate::spatial::math::offset_region;
-    use crate::spatial::V3c;
+    use crate::spatial::math::Octant;
+    use crate::spatial::{primitives::Axis, V3c};
+
+    #[test]
+    fn test_octant_from_offset() {
+        assert!(Octant::from_offset(&V3c::new(0.0, 0.0, 0.0), 10.0) == Octant::Origin);
+        assert!(Octant::from_offset(&V3c::new(6.0, 0.0, 0.0), 10.0) == Octant::X);
+        assert!(Octant::from_offset(&V3c::new(0.0, 0.0, 6.0), 10.0) == Octant::Z);
+        assert!(Octant::from_offset(&V3c::new(6.0, 0.0, 6.0), 10.0) == Octant::XZ);
+        assert!(Octant::from_offset(&V3c::new(0.0, 6.0, 0.0), 10.0) == Octant::Y);
+        assert!(Octant::from_offset(&V3c::new(6.0, 6.0, 0.0), 10.0) == Octant::XY);
+        assert!(Octant::from_offset(&V3c::new(0.0, 6.0, 6.0), 10.0) == Octant::YZ);
+        assert!(Octant::from_offset(&V3c::new(6.0, 6.0, 6.0), 10.0) == Octant::XYZ);
+    }
+
+    #[test]
+    fn test_octant_from_offset_boundaries() {
+        // The midpoint belongs to the upper half, the last position before the end still to the region
+        assert!(Octant::from_offset(&V3c::new(1.0, 1.0, 1.0), 4.0) == Octant::Origin);
+        assert!(Octant::from_offset(&V3c::new(2.0, 0.0, 0.0), 4.0) == Octant::X);
+        assert!(Octant::from_offset(&V3c::new(2.0, 2.0, 2.0), 4.0) == Octant::XYZ);
+        assert!(Octant::from_offset(&V3c::new(3.0, 3.0, 3.0), 4.0) == Octant::XYZ);
+    }
 
     #[test]
-    fn test_hash_region() {
-        assert!(hash_region(&V3c::new(0.0, 0.0, 0.0), 10.0) == 0);
-        assert!(hash_region(&V3c::new(6.0, 0.0, 0.0), 10.0) == 1);
-        assert!(hash_region(&V3c::new(0.0, 0.0, 6.0), 10.0) == 2);
-        assert!(hash_region(&V3c::new(6.0, 0.0, 6.0), 10.0) == 3);
-        assert!(hash_region(&V3c::new(0.0, 6.0, 0.0), 10.0) == 4);
-        assert!(hash_region(&V3c::new(6.0, 6.0, 0.0), 10.0) == 5);
-        assert!(hash_region(&V3c::new(0.0, 6.0, 6.0), 10.0) == 6);
-        assert!(hash_region(&V3c::new(6.0, 6.0, 6.0), 10.0) == 7);
+    fn test_octant_from_voxel_offset() {
+        // Every offset of small cubes is in the same octant as with float offsets
+        for size in 1..=32 {
+            for x in 0..size {
+                for y in 0..size {
+                    for z in 0..size {
+                        let offset = V3c::new(x, y, z);
+                        assert!(
+                            Octant::from_voxel_offset(&offset, size)
+                                == Octant::from_offset(&offset.into(), size as f32)
+                        );
+                    }
+                }
+            }
+        }
+
+        // Offsets around the midpoint of large cubes, where floats can no longer represent every offset
+        for size in [1 << 25, 3 << 24, (1 << 30) + 2, u32::MAX] {
+            let half = size.div_ceil(2);
+            for x in [0, half - 2, half - 1, half, half + 1, size - 1] {
+                let expected = if 2 * x as u64 >= size as u64 {
+                    Octant::XYZ
+                } else {
+                    Octant::Origin
+                };
+                assert!(Octant::from_voxel_offset(&V3c::unit(x), size) == expected);
+            }
+        }
+        let size = 1 << 26;
+        let below_midpoint = V3c::unit((1 << 25) - 1);
+        assert!(Octant::from_voxel_offset(&below_midpoint, size) == Octant::Origin);
+        assert!(Octant::from_offset(&below_midpoint.into(), size as f32) == Octant::XYZ);
+    }
+
+    #[test]
+    fn test_octant_offset() {
+        assert!(V3c::new(0, 0, 0) == Octant::Origin.offset());
+        assert!(V3c::new(1, 0, 0) == Octant::X.offset());
+        assert!(V3c::new(0, 0, 1) == Octant::Z.offset());
+        assert!(V3c::new(1, 0, 1) == Octant::XZ.offset());
+        assert!(V3c::new(0, 1, 0) == Octant::Y.offset());
+        assert!(V3c::new(1, 1, 0) == Octant::XY.offset());
+        assert!(V3c::new(0, 1, 1) == Octant::YZ.offset());
+        assert!(V3c::new(1, 1, 1) == Octant::XYZ.offset());
     }
 
     #[test]
-    fn test_offset_region() {
-        assert!(V3c::new(0, 0, 0) == offset_region(0));
-        assert!(V3c::new(1, 0, 0) == offset_region(1));
-        assert!(V3c::new(0, 0, 1) == offset_region(2));
-        assert!(V3c::new(1, 0, 1) == offset_region(3));
-        assert!(V3c::new(0, 1, 0) == offset_region(4));
-        assert!(V3c::new(1, 1, 0) == offset_region(5));
-        assert!(V3c::new(0, 1, 1) == offset_region(6));
-        assert!(V3c::new(1, 1, 1) == offset_region(7));
+    fn test_octant_index_and_iteration() {
+        assert!(Octant::iter().count() == 8);
+        for (index, octant) in Octant::iter().enumerate() {
+            assert!(octant.index() == index as u32);
+            assert!(Octant::from_index(index as u32) == Some(octant));
+        }
+        assert!(Octant::from_index(8).is_none());
+    }
+
+    #[test]
+    fn test_octant_mirror() {
+        assert!(Octant::Origin.mirror(Axis::X) == Octant::X);
+        assert!(Octant::XY.mirror(Axis::X) == Octant::Y);
+        assert!(Octant::XY.mirror(Axis::Y) == Octant::X);
+        assert!(Octant::Z.mirror(Axis::Z) == Octant::Origin);
+        for octant in Octant::iter() {
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                let mirrored = octant.mirror(axis);
+                assert!(mirrored != octant);
+                assert!(mirrored.mirror(axis) == octant);
+            }
+            // Mirroring by the sign bits of a ray direction flips the same axes as mirroring one by one
+            assert!(
+                octant.mirror_by_mask(0b111)
+                    == octant.mirror(Axis::X).mirror(Axis::Y).mirror(Axis::Z)
+            );
+            assert!(octant.mirror_by_mask(0b010) == octant.mirror(Axis::Z));
+        }
     }
 }
 
@@ -50,7 +323,7 @@ mod octant_tests {
 #[cfg(test)]
 mod intersection_tests {
 
-    use crate::spatial::{math::plane_line_intersection, raytracing::Ray, Cube, V3c};
+    use crate::spatial::{primitives::Plane, raytracing::Ray, Cube, V3c};
 
     #[test]
     fn test_negative_intersection() {
@@ -58,13 +331,9 @@ mod intersection_tests {
         let plane_normal = V3c::new(0., 1., 0.);
         let line_origin = V3c::new(0., 1., 0.);
         let line_direction = V3c::new(0., 1., 0.);
-        assert!(plane_line_intersection(
-            &plane_point,
-            &plane_normal,
-            &line_origin,
-            &line_direction
-        )
-        .is_some_and(|v| v == -1.));
+        assert!(Plane::new(plane_point, plane_normal)
+            .intersect_line(&line_origin, &line_direction)
+            .is_some_and(|v| v == -1.));
     }
 
     #[test]
@@ -107,44 +376,118 @@ mod intersection_tests {
 #[cfg(feature = "raytracing")]
 #[cfg(test)]
 mod raytracing_tests {
-    use crate::spatial::{math::plane_line_intersection, raytracing::Ray, Cube, V3c};
+    use crate::spatial::{
+        math::Octant,
+        primitives::Plane,
+        raytracing::{intersect_aabb, reflect, refract, BoxFace, Ray},
+        Aabb, Cube, V3c,
+    };
 
     #[test]
     fn test_plane_line_intersection() {
         assert!(
-            plane_line_intersection(
-                // plane
-                &V3c::new(0., 0., 0.),
-                &V3c::new(0., 1., 0.),
-                // line
-                &V3c::new(0., 1., 0.),
-                &V3c::new(1., 0., 0.),
-            ) == None
+            Plane::new(V3c::new(0., 0., 0.), V3c::new(0., 1., 0.))
+                .intersect_line(&V3c::new(0., 1., 0.), &V3c::new(1., 0., 0.))
+                == None
         );
 
         assert!(
-            plane_line_intersection(
-                // plane
-                &V3c::new(0., 0., 0.),
-                &V3c::new(0., 1., 0.),
-                // line
-                &V3c::new(0., 1., 0.),
-                &V3c::new(0., -1., 0.),
-            ) == Some(1.)
+            Plane::new(V3c::new(0., 0., 0.), V3c::new(0., 1., 0.))
+                .intersect_line(&V3c::new(0., 1., 0.), &V3c::new(0., -1., 0.))
+                == Some(1.)
         );
 
         assert!(
-            plane_line_intersection(
-                // plane
-                &V3c::new(0., 0., 0.),
-                &V3c::new(0., 1., 0.),
-                // line
-                &V3c::new(0., 0., 0.),
-                &V3c::new(1., 0., 0.),
-            ) == Some(0.)
+            Plane::new(V3c::new(0., 0., 0.), V3c::new(0., 1., 0.))
+                .intersect_line(&V3c::new(0., 0., 0.), &V3c::new(1., 0., 0.))
+                == Some(0.)
         );
     }
 
+    #[test]
+    fn test_intersect_aabb_with_axis_parallel_rays() {
+        let aabb = Aabb::new(V3c::unit(0.), V3c::unit(4.));
+
+        // The origin is on the boundary of the slabs the ray is parallel to
+        let ray = Ray {
+            origin: V3c::new(0., 6., 4.),
+            direction: V3c::new(0., -1., 0.),
+        };
+        let hit = intersect_aabb(&aabb, &ray).unwrap();
+        assert!(hit.impact_distance == Some(2.));
+        assert!(hit.exit_distance == 6.);
+        assert!(hit.entry_face == BoxFace::PositiveY);
+        assert!(hit.impact_normal == V3c::new(0., 1., 0.));
+
+        let ray = Ray {
+            origin: V3c::new(4.5, 6., 2.),
+            direction: V3c::new(0., -1., 0.),
+        };
+        assert!(intersect_aabb(&aabb, &ray).is_none());
+
+        // Starting inside the box the ray has no impact, only an exit
+        let ray = Ray {
+            origin: V3c::new(2., 2., 2.),
+            direction: V3c::new(0., 0., -1.),
+        };
+        let hit = intersect_aabb(&aabb, &ray).unwrap();
+        assert!(hit.impact_distance.is_none());
+        assert!(hit.exit_distance == 2.);
+    }
+
+    #[test]
+    fn test_intersect_aabb_entry_face_on_edge() {
+        let aabb = Aabb::new(V3c::unit(0.), V3c::unit(4.));
+        let ray = Ray {
+            origin: V3c::new(-1., 2., -1.),
+            direction: V3c::new(1., 0., 1.).normalized(),
+        };
+        let hit = intersect_aabb(&aabb, &ray).unwrap();
+        assert!(hit.entry_face == BoxFace::NegativeX);
+        assert!(hit
+            .impact_distance
+            .is_some_and(|d| (d - 2_f32.sqrt()).abs() < 0.0001));
+    }
+
+    #[test]
+    fn test_reflect_and_refract() {
+        let close = |a: V3c<f32>, b: V3c<f32>| (a - b).length() < 0.0001;
+        let up = V3c::new(0., 1., 0.);
+        let direction = V3c::new(1., -1., 0.).normalized();
+
+        // Reflection mirrors the component along the normal, regardless of the side the normal faces
+        assert!(close(
+            reflect(direction, up),
+            V3c::new(1., 1., 0.).normalized()
+        ));
+        assert!(close(
+            reflect(direction, up * -1.),
+            V3c::new(1., 1., 0.).normalized()
+        ));
+
+        // Rays perpendicular to the surface, or crossing materials of the same density pass straight through
+        assert!(close(refract(up * -1., up, 1.33).unwrap(), up * -1.));
+        assert!(close(refract(direction, up, 1.).unwrap(), direction));
+
+        // Entering a denser material, the ray bends towards the normal following Snell's law
+        let entering = refract(direction, up, 1.5).unwrap();
+        let sin_incident = direction.cross(up).length();
+        let sin_transmitted = entering.cross(up).length();
+        assert!((1.5 * sin_transmitted - sin_incident).abs() < 0.0001);
+        assert!(entering.y < 0. && entering.x < direction.x);
+
+        // Leaving it through the same surface along the reversed path restores the original direction
+        assert!(close(
+            refract(entering * -1., up, 1.5).unwrap(),
+            direction * -1.
+        ));
+
+        // Beyond the critical angle the ray is reflected inside the material
+        let grazing = V3c::new(1., 0.3, 0.).normalized();
+        assert!(refract(grazing, up, 1.5).is_none());
+        assert!(refract(grazing, up, 1.02).is_some());
+    }
+
     #[test]
     fn test_cube_bounds() {
         let cube = Cube {
@@ -153,42 +496,42 @@ mod raytracing_tests {
         };
 
         // Test front bottom left
-        let bound_fbl = Cube::child_bounds_for(&cube, 0);
+        let bound_fbl = cube.child_bounds_for(Octant::Origin);
         assert!(bound_fbl.min_position == V3c::unit(0));
         assert!(bound_fbl.size == 5);
 
         // Test front bottom right
-        let bound_fbl = Cube::child_bounds_for(&cube, 1);
+        let bound_fbl = cube.child_bounds_for(Octant::X);
         assert!(bound_fbl.min_position == V3c::new(5, 0, 0));
         assert!(bound_fbl.size == 5);
 
         // Test back bottom left
-        let bound_fbl = Cube::child_bounds_for(&cube, 2);
+        let bound_fbl = cube.child_bounds_for(Octant::Z);
         assert!(bound_fbl.min_position == V3c::new(0, 0, 5));
         assert!(bound_fbl.size == 5);
 
         // Test back bottom right
-        let bound_fbl = Cube::child_bounds_for(&cube, 3);
+        let bound_fbl = cube.child_bounds_for(Octant::XZ);
         assert!(bound_fbl.min_position == V3c::new(5, 0, 5));
         assert!(bound_fbl.size == 5);
 
         // Test front top left
-        let bound_fbl = Cube::child_bounds_for(&cube, 4);
+        let bound_fbl = cube.child_bounds_for(Octant::Y);
         assert!(bound_fbl.min_position == V3c::new(0, 5, 0));
         assert!(bound_fbl.size == 5);
 
         // Test front top right
-        let bound_fbl = Cube::child_bounds_for(&cube, 5);
+        let bound_fbl = cube.child_bounds_for(Octant::XY);
         assert!(bound_fbl.min_position == V3c::new(5, 5, 0));
         assert!(bound_fbl.size == 5);
 
         // Test back top left
-        let bound_fbl = Cube::child_bounds_for(&cube, 6);
+        let bound_fbl = cube.child_bounds_for(Octant::YZ);
         assert!(bound_fbl.min_position == V3c::new(0, 5, 5));
         assert!(bound_fbl.size == 5);
 
         // Test back top right
-        let bound_fbl = Cube::child_bounds_for(&cube, 7);
+        let bound_fbl = cube.child_bounds_for(Octant::XYZ);
         assert!(bound_fbl.min_position == V3c::new(5, 5, 5));
         assert!(bound_fbl.size == 5);
     }
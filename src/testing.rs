@@ -0,0 +1,28 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The environment variable to set the seed of randomized tests, examples and benchmarks with
+pub const SEED_ENV_VAR: &str = "SHOCOVOX_SEED";
+
+/// Provides the seed for the randomized scene with the given name, so failures can be reproduced:
+/// it is the value of the `SHOCOVOX_SEED` environment variable should it be set, or one based on the current time.
+/// The seed is printed along with the name, which the test harness shows for failing tests.
+/// * `name` - The name of the test or scene the seed is used in
+pub fn seed(name: &str) -> u64 {
+    let seed = match std::env::var(SEED_ENV_VAR) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .unwrap_or_else(|_| panic!("{SEED_ENV_VAR} is not a valid u64: {value}")),
+        Err(_) => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+            // Mix in the name, so scenes started at the same time still differ
+            name.bytes().fold(nanos, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+        }
+    };
+    println!("{name} is using seed {seed}; reproduce with {SEED_ENV_VAR}={seed}");
+    seed
+}